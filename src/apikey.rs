@@ -0,0 +1,454 @@
+//! API keys for the web service
+//!
+//! A key is a random token of which only the SHA-256 hash is kept, either in
+//! the config (`[[web.api_key]]`) or in the token file managed with
+//! `flux-ssl-mgr web token`. Each key carries scopes: `read` for looking
+//! certificates up, `issue` for signing and generating them, and `revoke`.
+//! Scopes don't imply each other.
+//!
+//! Enrollment tokens (`web token new`, or the ones in `enroll-script` output)
+//! live in the same file. Each lets a new machine upload one CSR for a name
+//! matching its pattern before it expires, and is removed once used.
+
+use crate::config::Config;
+use crate::crypto::{SanEntry, SanSet};
+use crate::error::{FluxError, Result};
+use crate::fsutil::FileLock;
+use chrono::{DateTime, Duration, Utc};
+use openssl::hash::{hash, MessageDigest};
+use openssl::rand::rand_bytes;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+
+/// Prefix of generated tokens, so they are easy to spot in logs and configs
+const TOKEN_PREFIX: &str = "flux_";
+
+/// Prefix of enrollment tokens
+const ENROLLMENT_PREFIX: &str = "flux_enroll_";
+
+/// Random bytes per token
+const TOKEN_BYTES: usize = 32;
+
+/// Serializes redeeming within this process, so a token can't be used by two
+/// requests at once; a [`FileLock`] on the token file does so across processes
+static REDEEM_LOCK: Mutex<()> = Mutex::new(());
+
+/// What a key may do
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    Read,
+    Issue,
+    Revoke,
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Scope::Read => "read",
+            Scope::Issue => "issue",
+            Scope::Revoke => "revoke",
+        })
+    }
+}
+
+impl FromStr for Scope {
+    type Err = FluxError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "read" => Ok(Scope::Read),
+            "issue" => Ok(Scope::Issue),
+            "revoke" => Ok(Scope::Revoke),
+            _ => Err(FluxError::InvalidConfigValue("scope".to_string(), format!("'{}' (expected read, issue or revoke)", s))),
+        }
+    }
+}
+
+/// A named, hashed API key
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApiKey {
+    pub name: String,
+    /// `sha256:<hex>` of the token
+    pub hash: String,
+    #[serde(default)]
+    pub scopes: BTreeSet<Scope>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+impl ApiKey {
+    /// Whether `token` is this key (constant-time comparison of the hashes)
+    pub fn matches(&self, token: &str) -> bool {
+        hash_matches(&self.hash, token)
+    }
+
+    pub fn allows(&self, scope: Scope) -> bool {
+        self.scopes.contains(&scope)
+    }
+}
+
+/// A single-use credential for a machine's first certificate
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Enrollment {
+    /// Common name the CSR must carry; `*` stands for part of a label (`nas*.lab`)
+    pub name: String,
+    /// SANs allowed besides `DNS:<name>`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sans: Vec<String>,
+    /// `sha256:<hex>` of the token
+    pub hash: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl Enrollment {
+    pub fn matches(&self, token: &str) -> bool {
+        hash_matches(&self.hash, token)
+    }
+
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at <= now
+    }
+
+    /// Whether a certificate for `common_name` with `sans` stays within this enrollment
+    ///
+    /// Besides the enrolled SANs, only `DNS:<common_name>` is allowed.
+    pub fn permits(&self, common_name: &str, sans: &[SanEntry]) -> bool {
+        let common_name = common_name.trim_end_matches('.');
+        if !name_matches(&self.name, common_name) {
+            return false;
+        }
+        let allowed: SanSet = std::iter::once(SanEntry::Dns(common_name.to_string()))
+            .chain(self.sans.iter().filter_map(|s| SanEntry::parse(s).ok()))
+            .collect();
+        sans.iter().all(|san| allowed.contains(san))
+    }
+}
+
+/// A new random token
+pub fn generate_token() -> Result<String> {
+    random_token(TOKEN_PREFIX)
+}
+
+fn random_token(prefix: &str) -> Result<String> {
+    let mut bytes = [0u8; TOKEN_BYTES];
+    rand_bytes(&mut bytes)?;
+    Ok(format!("{}{}", prefix, hex(&bytes)))
+}
+
+/// Whether `name` matches an enrollment pattern (case-insensitive)
+///
+/// `*` matches one or more hostname label characters (`[a-z0-9-]`), so it
+/// never covers a dot, a literal `*` or DN syntax such as `,` and `=`.
+pub fn name_matches(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.to_ascii_lowercase();
+    let name = name.to_ascii_lowercase();
+    match pattern.split_once('*') {
+        None => pattern == name,
+        Some((prefix, rest)) => {
+            let Some(tail) = name.strip_prefix(prefix) else { return false };
+            // Try every split of the label characters `*` may cover
+            let run_end = tail.find(|c: char| !c.is_ascii_alphanumeric() && c != '-').unwrap_or(tail.len());
+            (1..=run_end).any(|i| name_matches(rest, &tail[i..]))
+        }
+    }
+}
+
+/// Check an enrollment pattern: a certificate name, possibly with `*`
+pub fn validate_name_pattern(pattern: &str) -> Result<()> {
+    let invalid = |msg: &str| FluxError::InvalidCertName(format!("{} ({})", pattern, msg));
+    if pattern.trim_matches(|c| c == '*' || c == '.').is_empty() {
+        return Err(invalid("the pattern needs at least one fixed character"));
+    }
+    if pattern.contains(|c: char| !c.is_ascii_alphanumeric() && !matches!(c, '-' | '_' | '.' | '*')) {
+        return Err(invalid("only alphanumeric characters, hyphens, underscores, dots and * are allowed"));
+    }
+    if pattern.starts_with('.') {
+        return Err(invalid("cannot start with a dot"));
+    }
+    Ok(())
+}
+
+/// Whether `token` is an enrollment token rather than an API key
+pub fn is_enrollment_token(token: &str) -> bool {
+    token.starts_with(ENROLLMENT_PREFIX)
+}
+
+/// Constant-time comparison of `token`'s hash with a stored one
+fn hash_matches(stored: &str, token: &str) -> bool {
+    let Ok(presented) = hash_token(token) else { return false };
+    presented.len() == stored.len()
+        && openssl::memcmp::eq(presented.as_bytes(), stored.to_ascii_lowercase().as_bytes())
+}
+
+/// The `sha256:<hex>` form a token is stored as
+pub fn hash_token(token: &str) -> Result<String> {
+    Ok(format!("sha256:{}", hex(&hash(MessageDigest::sha256(), token.as_bytes())?)))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// The key `token` belongs to
+pub fn authenticate<'a>(keys: &'a [ApiKey], token: &str) -> Option<&'a ApiKey> {
+    keys.iter().find(|key| key.matches(token))
+}
+
+/// Keys from the config followed by the token file's
+pub fn all_keys(config: &Config) -> Result<Vec<ApiKey>> {
+    let mut keys = config.web.api_keys.clone();
+    keys.extend(TokenFile::open(config.web_tokens_path())?.keys);
+    Ok(keys)
+}
+
+/// Keys managed with `flux-ssl-mgr web token`
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct TokenFile {
+    #[serde(skip)]
+    path: PathBuf,
+
+    #[serde(default)]
+    keys: Vec<ApiKey>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    enrollments: Vec<Enrollment>,
+}
+
+impl TokenFile {
+    /// Open the token file, starting empty if it doesn't exist yet
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        if !path.exists() {
+            return Ok(Self { path, ..Self::default() });
+        }
+
+        let data = std::fs::read_to_string(&path)
+            .map_err(|e| FluxError::FileReadFailed(path.clone(), e.to_string()))?;
+        let mut file: TokenFile = serde_json::from_str(&data)
+            .map_err(|e| FluxError::InvalidConfigValue(path.display().to_string(), e.to_string()))?;
+        file.path = path;
+        Ok(file)
+    }
+
+    /// Write the file back (owner-only, replaced atomically)
+    pub fn save(&self) -> Result<()> {
+        let data = serde_json::to_string_pretty(self)
+            .map_err(|e| FluxError::InvalidConfigValue(self.path.display().to_string(), e.to_string()))?;
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let tmp_path = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, data)
+            .map_err(|e| FluxError::FileWriteFailed(tmp_path.clone(), e.to_string()))?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&tmp_path, std::fs::Permissions::from_mode(0o600))?;
+        }
+        std::fs::rename(&tmp_path, &self.path)
+            .map_err(|e| FluxError::FileWriteFailed(self.path.clone(), e.to_string()))?;
+        Ok(())
+    }
+
+    pub fn keys(&self) -> &[ApiKey] {
+        &self.keys
+    }
+
+    /// Add a key, returning its token (shown once, never stored)
+    pub fn create(&mut self, name: &str, scopes: BTreeSet<Scope>) -> Result<String> {
+        if self.keys.iter().any(|k| k.name == name) {
+            return Err(FluxError::InvalidConfigValue("web token".to_string(), format!("a key named {} already exists", name)));
+        }
+
+        let token = generate_token()?;
+        self.keys.push(ApiKey {
+            name: name.to_string(),
+            hash: hash_token(&token)?,
+            scopes,
+            created_at: Some(Utc::now()),
+        });
+        Ok(token)
+    }
+
+    /// Remove a key, returning whether it existed
+    pub fn remove(&mut self, name: &str) -> bool {
+        let before = self.keys.len();
+        self.keys.retain(|k| k.name != name);
+        self.keys.len() != before
+    }
+
+    pub fn enrollments(&self) -> &[Enrollment] {
+        &self.enrollments
+    }
+
+    /// Add an enrollment for `name` valid for `ttl`, returning its token
+    ///
+    /// Expired enrollments are dropped on the way.
+    pub fn create_enrollment(&mut self, name: &str, sans: &[SanEntry], ttl: Duration) -> Result<(String, Enrollment)> {
+        let now = Utc::now();
+        self.enrollments.retain(|e| !e.is_expired(now));
+
+        let token = random_token(ENROLLMENT_PREFIX)?;
+        let enrollment = Enrollment {
+            name: name.to_string(),
+            sans: sans.iter().map(ToString::to_string).collect(),
+            hash: hash_token(&token)?,
+            created_at: now,
+            expires_at: now + ttl,
+        };
+        self.enrollments.push(enrollment.clone());
+        Ok((token, enrollment))
+    }
+
+    /// The unexpired enrollment `token` belongs to
+    pub fn enrollment(&self, token: &str, now: DateTime<Utc>) -> Option<&Enrollment> {
+        self.enrollments.iter().find(|e| !e.is_expired(now) && e.matches(token))
+    }
+
+    /// Remove the enrollments for `name`, returning how many there were
+    pub fn remove_enrollments(&mut self, name: &str) -> usize {
+        let before = self.enrollments.len();
+        self.enrollments.retain(|e| e.name != name);
+        before - self.enrollments.len()
+    }
+
+    /// Remove and return the unexpired enrollment `token` belongs to
+    pub fn take_enrollment(&mut self, token: &str, now: DateTime<Utc>) -> Option<Enrollment> {
+        let index = self.enrollments.iter().position(|e| !e.is_expired(now) && e.matches(token))?;
+        Some(self.enrollments.remove(index))
+    }
+}
+
+/// Issue for the enrollment `token` belongs to, then use the token up
+///
+/// `issue` runs while the token file is locked, and the token is only used
+/// up once it succeeds, so a CSR that fails to sign leaves the token for
+/// another try. `None` when the token is no longer valid.
+pub fn redeem_enrollment<T, E: From<FluxError>>(
+    config: &Config,
+    token: &str,
+    issue: impl FnOnce(&Enrollment) -> std::result::Result<T, E>,
+) -> std::result::Result<Option<T>, E> {
+    let _guard = REDEEM_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let path = config.web_tokens_path();
+    if !path.exists() {
+        return Ok(None);
+    }
+    let _lock = FileLock::acquire(&path)?;
+
+    let mut tokens = TokenFile::open(&path)?;
+    let now = Utc::now();
+    let Some(enrollment) = tokens.enrollment(token, now).cloned() else { return Ok(None) };
+    let issued = issue(&enrollment)?;
+    tokens.take_enrollment(token, now);
+    tokens.save()?;
+    Ok(Some(issued))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_file() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("tokens.json");
+
+        let mut file = TokenFile::open(&path).unwrap();
+        let token = file.create("ci", BTreeSet::from([Scope::Issue])).unwrap();
+        assert!(token.starts_with(TOKEN_PREFIX));
+        assert!(file.create("ci", BTreeSet::new()).is_err());
+        file.save().unwrap();
+        assert!(!std::fs::read_to_string(&path).unwrap().contains(&token));
+
+        let file = TokenFile::open(&path).unwrap();
+        let key = authenticate(file.keys(), &token).unwrap();
+        assert_eq!(key.name, "ci");
+        assert!(key.allows(Scope::Issue) && !key.allows(Scope::Read));
+        assert!(authenticate(file.keys(), "flux_guess").is_none());
+    }
+
+    #[test]
+    fn test_enrollment_single_use() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let config = Config {
+            working_dir: temp_dir.path().to_path_buf(),
+            ..Config::default()
+        };
+        let mut file = TokenFile::open(config.web_tokens_path()).unwrap();
+        let (token, _) = file.create_enrollment("nas01.lab", &[SanEntry::Ip("10.0.0.5".to_string())], Duration::hours(1)).unwrap();
+        let (stale, _) = file.create_enrollment("old.lab", &[], Duration::seconds(-1)).unwrap();
+        file.save().unwrap();
+        assert!(is_enrollment_token(&token) && !is_enrollment_token(&generate_token().unwrap()));
+
+        let file = TokenFile::open(config.web_tokens_path()).unwrap();
+        let enrollment = file.enrollment(&token, Utc::now()).unwrap();
+        assert!(file.enrollment(&stale, Utc::now()).is_none());
+        assert!(authenticate(file.keys(), &token).is_none());
+
+        assert!(enrollment.permits("NAS01.lab", &[SanEntry::Dns("nas01.lab.".to_string()), SanEntry::Ip("10.0.0.5".to_string())]));
+        assert!(!enrollment.permits("nas01.lab", &[SanEntry::Dns("router.lab".to_string())]));
+        assert!(!enrollment.permits("router.lab", &[]));
+
+        // A failed issuance leaves the token; a successful one uses it up
+        let failed = redeem_enrollment(&config, &token, |_| Err::<(), _>(FluxError::CertSigningFailed("refused".to_string())));
+        assert!(failed.is_err());
+        let name = redeem_enrollment(&config, &token, |e| Ok::<_, FluxError>(e.name.clone())).unwrap();
+        assert_eq!(name.as_deref(), Some("nas01.lab"));
+        assert!(redeem_enrollment(&config, &token, |_| Ok::<_, FluxError>(())).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_name_patterns() {
+        assert!(name_matches("nas01.lab", "NAS01.lab"));
+        assert!(name_matches("nas*.lab", "nas01.lab"));
+        assert!(!name_matches("nas*.lab", "nas.lab"));
+        assert!(name_matches("*-cam.*.lab", "door-cam.iot.lab"));
+        assert!(!name_matches("nas*.lab", "nas01.evil.lab"));
+        assert!(!name_matches("nas*.lab", "nas01.lab.evil"));
+        assert!(!name_matches("nas*.lab", "router.lab"));
+        assert!(!name_matches("*.lab", "*.lab"));
+        assert!(!name_matches("*.lab", "a,b.lab"));
+        assert!(!name_matches("*.lab", "x,CN=Administrator.lab"));
+        assert!(!name_matches("*.lab", ".lab"));
+        assert!(name_matches("*.lab", "cam-01.lab"));
+
+        let enrollment = Enrollment {
+            name: "nas*.lab".to_string(),
+            sans: Vec::new(),
+            hash: hash_token("flux_enroll_x").unwrap(),
+            created_at: Utc::now(),
+            expires_at: Utc::now() + Duration::hours(1),
+        };
+        assert!(enrollment.permits("nas02.lab", &[SanEntry::Dns("nas02.lab".to_string())]));
+        assert!(!enrollment.permits("nas02.lab", &[SanEntry::Dns("nas03.lab".to_string())]));
+
+        assert!(validate_name_pattern("nas*.lab").is_ok());
+        assert!(validate_name_pattern("*.*").is_err());
+        assert!(validate_name_pattern("nas/*.lab").is_err());
+    }
+
+    #[test]
+    fn test_config_keys() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut config = Config {
+            working_dir: temp_dir.path().to_path_buf(),
+            ..Config::default()
+        };
+        config.web = toml::from_str(&format!(
+            "[[api_key]]\nname = \"grafana\"\nhash = \"{}\"\nscopes = [\"read\"]",
+            hash_token("flux_static").unwrap().to_uppercase().replace("SHA256", "sha256")
+        )).unwrap();
+
+        let keys = all_keys(&config).unwrap();
+        assert_eq!(authenticate(&keys, "flux_static").map(|k| k.name.as_str()), Some("grafana"));
+        assert!(toml::from_str::<crate::config::WebConfig>("[[api_key]]\nname = \"x\"\nhash = \"h\"\nscopes = [\"admin\"]").is_err());
+    }
+}
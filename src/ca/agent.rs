@@ -0,0 +1,451 @@
+//! CA signing agent
+//!
+//! The agent unlocks the intermediate CA key once and keeps it in memory,
+//! serving signing requests over a local unix socket. The decrypted key is
+//! never written to disk.
+//!
+//! The socket lives in a directory that must belong to the agent's user
+//! with mode 0700, is created with mode 0600, and connections from other
+//! users (by their peer credentials) are dropped. Clients hold the agent to
+//! the same checks, and to serving the configured CA certificate.
+//!
+//! The protocol is one JSON request line per connection, answered by one
+//! JSON response line:
+//!
+//! ```text
+//! -> {"op":"ca_cert"}
+//! <- {"ok":true,"cert_pem":"-----BEGIN CERTIFICATE-----..."}
+//! -> {"op":"sign","csr_pem":"-----BEGIN CERTIFICATE REQUEST-----...","days":375}
+//! <- {"ok":true,"cert_pem":"-----BEGIN CERTIFICATE-----..."}
+//! ```
+//!
+//! A sign request may add `"not_before":"2025-01-01T00:00:00Z"` to override
+//! the agent's own `defaults.backdate_minutes`, and `"validity":"12h"` for
+//! validity that isn't a whole number of days (it takes precedence over
+//! `days`).
+
+use crate::crypto::{cert_from_pem, cert_to_pem, csr_from_pem_bytes, NotBefore};
+use crate::duration::Validity;
+use crate::error::{FluxError, Result};
+use chrono::{DateTime, Utc};
+use openssl::x509::{X509, X509Req};
+use serde::{Deserialize, Serialize};
+use nix::sys::stat::{umask, Mode};
+use nix::unistd::{geteuid, Uid};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use super::{CertSigner, IntermediateCA};
+
+/// Maximum size of a single request or response line
+const MAX_MESSAGE_SIZE: u64 = 1024 * 1024;
+
+/// Request sent to the agent
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum AgentRequest {
+    /// Check that the agent is alive
+    Ping,
+    /// Fetch the CA certificate
+    CaCert,
+    /// Sign a CSR
+    Sign {
+        csr_pem: String,
+        days: u32,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        validity: Option<Validity>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        not_before: Option<DateTime<Utc>>,
+    },
+}
+
+/// Response returned by the agent
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct AgentResponse {
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cert_pem: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl AgentResponse {
+    fn cert(cert: &X509) -> Result<Self> {
+        Ok(Self {
+            ok: true,
+            cert_pem: Some(String::from_utf8_lossy(&cert_to_pem(cert)?).to_string()),
+            error: None,
+        })
+    }
+
+    fn error(msg: impl Into<String>) -> Self {
+        Self {
+            ok: false,
+            cert_pem: None,
+            error: Some(msg.into()),
+        }
+    }
+}
+
+/// Signing agent bound to a unix socket
+pub struct SigningAgent {
+    listener: UnixListener,
+    socket_path: PathBuf,
+    ca: Arc<IntermediateCA>,
+}
+
+impl SigningAgent {
+    /// Bind the agent socket, replacing a stale socket file if present
+    ///
+    /// A missing socket directory is created with mode 0700; an existing one
+    /// must belong to the current user and have mode 0700.
+    pub fn bind<P: AsRef<Path>>(socket_path: P, ca: IntermediateCA) -> Result<Self> {
+        let socket_path = socket_path.as_ref().to_path_buf();
+
+        let parent = socket_path.parent()
+            .filter(|p| !p.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        if !parent.exists() {
+            std::fs::create_dir_all(parent)?;
+            set_mode(parent, 0o700)?;
+        }
+        check_socket_dir(parent)?;
+
+        if socket_path.exists() {
+            if UnixStream::connect(&socket_path).is_ok() {
+                return Err(FluxError::AgentError(format!(
+                    "an agent is already listening on {}",
+                    socket_path.display()
+                )));
+            }
+            std::fs::remove_file(&socket_path)?;
+        }
+
+        // Create the socket 0600 from the start rather than narrowing it after
+        let previous = umask(Mode::from_bits_truncate(0o177));
+        let bound = UnixListener::bind(&socket_path);
+        umask(previous);
+        let listener = bound
+            .map_err(|e| FluxError::AgentError(format!("failed to bind {}: {}", socket_path.display(), e)))?;
+        set_mode(&socket_path, 0o600)?;
+
+        Ok(Self {
+            listener,
+            socket_path,
+            ca: Arc::new(ca),
+        })
+    }
+
+    /// Path of the bound socket
+    pub fn socket_path(&self) -> &Path {
+        &self.socket_path
+    }
+
+    /// Serve requests until the process is terminated
+    pub fn run(&self) -> Result<()> {
+        for stream in self.listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    match peer_uid(&stream) {
+                        Ok(uid) if uid == geteuid() => {}
+                        Ok(uid) => {
+                            tracing::warn!("Agent refused a connection from uid {}", uid);
+                            continue;
+                        }
+                        Err(e) => {
+                            tracing::warn!("Agent refused a connection: {}", e);
+                            continue;
+                        }
+                    }
+                    let ca = Arc::clone(&self.ca);
+                    std::thread::spawn(move || {
+                        if let Err(e) = handle_connection(stream, &ca) {
+                            tracing::warn!("Agent connection failed: {}", e);
+                        }
+                    });
+                }
+                Err(e) => tracing::warn!("Agent accept failed: {}", e),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for SigningAgent {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.socket_path);
+    }
+}
+
+fn handle_connection(stream: UnixStream, ca: &IntermediateCA) -> Result<()> {
+    let request: AgentRequest = match read_message(&stream) {
+        Ok(req) => req,
+        Err(e) => return write_message(&stream, &AgentResponse::error(e.to_string())),
+    };
+
+    let response = match request {
+        AgentRequest::Ping => AgentResponse { ok: true, ..Default::default() },
+        AgentRequest::CaCert => AgentResponse::cert(ca.cert())?,
+        AgentRequest::Sign { csr_pem, days, validity, not_before } => {
+            let validity = validity.unwrap_or(Validity::days(days));
+            let not_before = not_before.map_or(ca.not_before(), NotBefore::At);
+            match csr_from_pem_bytes(csr_pem.as_bytes()).and_then(|csr| ca.sign_starting(&csr, validity, not_before)) {
+                Ok(cert) => {
+                    tracing::info!("Agent signed certificate for {:?}", cert.subject_name());
+                    AgentResponse::cert(&cert)?
+                }
+                Err(e) => AgentResponse::error(e.to_string()),
+            }
+        }
+    };
+
+    write_message(&stream, &response)
+}
+
+fn read_message<T: for<'de> Deserialize<'de>>(stream: &UnixStream) -> Result<T> {
+    let mut line = String::new();
+    BufReader::new(stream.take(MAX_MESSAGE_SIZE)).read_line(&mut line)?;
+
+    serde_json::from_str(&line)
+        .map_err(|e| FluxError::AgentError(format!("malformed message: {}", e)))
+}
+
+fn write_message<T: Serialize>(mut stream: &UnixStream, message: &T) -> Result<()> {
+    let mut line = serde_json::to_string(message)
+        .map_err(|e| FluxError::AgentError(e.to_string()))?;
+    line.push('\n');
+    stream.write_all(line.as_bytes())?;
+    stream.flush()?;
+    Ok(())
+}
+
+/// Fail unless `dir` belongs to the current user and only they can use it
+fn check_socket_dir(dir: &Path) -> Result<()> {
+    let meta = std::fs::metadata(dir)?;
+    let uid = geteuid();
+    if meta.uid() != uid.as_raw() || meta.mode() & 0o777 != 0o700 {
+        return Err(FluxError::AgentError(format!(
+            "{} must belong to uid {} and have mode 0700 (it is uid {}, mode {:o}); \
+             fix it with `chmod 700`, or choose another socket path",
+            dir.display(), uid, meta.uid(), meta.mode() & 0o777
+        )));
+    }
+    Ok(())
+}
+
+/// User of the process at the other end of `stream`
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn peer_uid(stream: &UnixStream) -> Result<Uid> {
+    use nix::sys::socket::{getsockopt, sockopt::PeerCredentials};
+
+    let credentials = getsockopt(stream, PeerCredentials)
+        .map_err(|e| FluxError::AgentError(format!("cannot read peer credentials: {}", e)))?;
+    Ok(Uid::from_raw(credentials.uid()))
+}
+
+/// User of the process at the other end of `stream`
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn peer_uid(stream: &UnixStream) -> Result<Uid> {
+    let (uid, _) = nix::unistd::getpeereid(stream)
+        .map_err(|e| FluxError::AgentError(format!("cannot read peer credentials: {}", e)))?;
+    Ok(uid)
+}
+
+fn set_mode(path: &Path, mode: u32) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+    Ok(())
+}
+
+/// Client for a running signing agent
+pub struct AgentClient {
+    socket_path: PathBuf,
+    ca_cert: X509,
+    not_before: Option<DateTime<Utc>>,
+}
+
+impl AgentClient {
+    /// Connect to the agent and fetch its CA certificate
+    ///
+    /// The agent must be serving `expected_ca` (the configured CA
+    /// certificate), so a socket planted by someone else can't hand out
+    /// certificates from another CA.
+    pub fn connect<P: AsRef<Path>>(socket_path: P, expected_ca: &X509) -> Result<Self> {
+        let socket_path = socket_path.as_ref().to_path_buf();
+        let response = request(&socket_path, &AgentRequest::CaCert)?;
+        let ca_cert = cert_from_pem(response_cert(response)?.as_bytes())?;
+        if ca_cert.to_der()? != expected_ca.to_der()? {
+            return Err(FluxError::AgentError(format!(
+                "the agent at {} serves a different CA certificate than ca_cert_path",
+                socket_path.display()
+            )));
+        }
+
+        Ok(Self { socket_path, ca_cert, not_before: None })
+    }
+
+    /// Ask the agent to start validity at `not_before` instead of its default
+    pub fn with_not_before(mut self, not_before: Option<DateTime<Utc>>) -> Self {
+        self.not_before = not_before;
+        self
+    }
+
+    /// Check that the agent is responding
+    pub fn ping(&self) -> Result<()> {
+        request(&self.socket_path, &AgentRequest::Ping).map(|_| ())
+    }
+}
+
+impl CertSigner for AgentClient {
+    fn ca_cert(&self) -> &X509 {
+        &self.ca_cert
+    }
+
+    fn sign_for(&self, csr: &X509Req, validity: Validity) -> Result<X509> {
+        let csr_pem = csr.to_pem()
+            .map_err(|e| FluxError::CsrGenerationFailed(e.to_string()))?;
+
+        let response = request(&self.socket_path, &AgentRequest::Sign {
+            csr_pem: String::from_utf8_lossy(&csr_pem).to_string(),
+            days: validity.whole_days(),
+            validity: (!validity.is_whole_days()).then_some(validity),
+            not_before: self.not_before,
+        })?;
+
+        let cert_pem = response_cert(response)
+            .map_err(|e| FluxError::CertSigningFailed(e.to_string()))?;
+        cert_from_pem(cert_pem.as_bytes())
+    }
+}
+
+/// Connect to the agent socket, checking that its directory and the process
+/// listening on it belong to the current user
+fn connect_stream(socket_path: &Path) -> Result<UnixStream> {
+    let parent = socket_path.parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    check_socket_dir(parent)?;
+
+    let stream = UnixStream::connect(socket_path)
+        .map_err(|e| FluxError::AgentError(format!("cannot connect to {}: {}", socket_path.display(), e)))?;
+    let uid = peer_uid(&stream)?;
+    if uid != geteuid() {
+        return Err(FluxError::AgentError(format!(
+            "{} is served by uid {}, not the current user",
+            socket_path.display(), uid
+        )));
+    }
+    Ok(stream)
+}
+
+fn request(socket_path: &Path, req: &AgentRequest) -> Result<AgentResponse> {
+    let stream = connect_stream(socket_path)?;
+
+    write_message(&stream, req)?;
+    let response: AgentResponse = read_message(&stream)?;
+
+    if response.ok {
+        Ok(response)
+    } else {
+        Err(FluxError::AgentError(response.error.unwrap_or_else(|| "unknown error".to_string())))
+    }
+}
+
+fn response_cert(response: AgentResponse) -> Result<String> {
+    response.cert_pem
+        .ok_or_else(|| FluxError::AgentError("response did not contain a certificate".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::{create_csr, generate_rsa_key, SanEntry};
+    use crate::fixtures::intermediate_ca as test_ca;
+
+    #[test]
+    fn test_agent_sign_roundtrip() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let socket_path = temp_dir.path().join("run/agent.sock");
+
+        let ca = test_ca();
+        let ca_key = ca.key().clone();
+        let agent = SigningAgent::bind(&socket_path, ca).unwrap();
+        std::thread::spawn(move || agent.run());
+
+        let client = AgentClient::connect(&socket_path, test_ca().cert()).unwrap();
+        client.ping().unwrap();
+
+        let key = generate_rsa_key(2048, None).unwrap();
+        let csr = create_csr("agent-test", &key, &[SanEntry::Dns("agent.lab".to_string())], None).unwrap();
+        let cert = client.sign(&csr, 30).unwrap();
+
+        assert!(cert.verify(&ca_key).unwrap());
+        assert_eq!(
+            format!("{:?}", cert.issuer_name()),
+            format!("{:?}", client.ca_cert().subject_name())
+        );
+    }
+
+    #[test]
+    fn test_agent_refuses_shared_socket_dir() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let shared = temp_dir.path().join("shared");
+        std::fs::create_dir(&shared).unwrap();
+        set_mode(&shared, 0o755).unwrap();
+
+        let err = SigningAgent::bind(shared.join("agent.sock"), test_ca()).err().unwrap();
+        assert!(err.to_string().contains("mode 0700"), "{}", err);
+        assert!(!shared.join("agent.sock").exists());
+
+        // A directory it creates itself is fine, and the socket is private
+        let socket_path = temp_dir.path().join("run/agent.sock");
+        let _agent = SigningAgent::bind(&socket_path, test_ca()).unwrap();
+        assert_eq!(std::fs::metadata(temp_dir.path().join("run")).unwrap().mode() & 0o777, 0o700);
+        assert_eq!(std::fs::metadata(&socket_path).unwrap().mode() & 0o777, 0o600);
+    }
+
+    #[test]
+    fn test_client_checks_the_agent() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let socket_path = temp_dir.path().join("run/agent.sock");
+        let agent = SigningAgent::bind(&socket_path, test_ca()).unwrap();
+        std::thread::spawn(move || agent.run());
+
+        // An agent serving another CA is refused
+        let other_ca = crate::fixtures::root_ca_cert();
+        let err = AgentClient::connect(&socket_path, &other_ca).err().unwrap();
+        assert!(err.to_string().contains("different CA certificate"), "{}", err);
+
+        // So is a socket in a directory other users can get into
+        set_mode(&temp_dir.path().join("run"), 0o755).unwrap();
+        let err = AgentClient::connect(&socket_path, test_ca().cert()).err().unwrap();
+        assert!(err.to_string().contains("mode 0700"), "{}", err);
+    }
+
+    #[test]
+    fn test_peer_uid() {
+        let (client, _server) = UnixStream::pair().unwrap();
+        assert_eq!(peer_uid(&client).unwrap(), geteuid());
+    }
+
+    #[test]
+    fn test_agent_rejects_invalid_csr() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let socket_path = temp_dir.path().join("run/agent.sock");
+
+        let agent = SigningAgent::bind(&socket_path, test_ca()).unwrap();
+        std::thread::spawn(move || agent.run());
+
+        let err = request(&socket_path, &AgentRequest::Sign {
+            csr_pem: "not a csr".to_string(),
+            days: 30,
+            validity: None,
+            not_before: None,
+        });
+        assert!(matches!(err, Err(FluxError::AgentError(_))));
+    }
+}
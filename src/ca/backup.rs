@@ -0,0 +1,589 @@
+//! Encrypted CA backups (`flux-ssl-mgr ca backup` and `ca restore`)
+//!
+//! A backup is a tar archive of the working directory (CA keys and
+//! certificates, the OpenSSL database, the certificate store), the CA key and
+//! certificate if they live elsewhere, and the config file. `MANIFEST.json`
+//! at the front of the archive records where each file came from, its mode
+//! and its SHA-256.
+//!
+//! The archive is encrypted with AES-256-GCM under a key derived from a
+//! passphrase with scrypt:
+//!
+//! ```text
+//! "FLUXBAK1" | log2(N) (1) | r (4, BE) | p (4, BE) | salt (16) | nonce (12) | ciphertext | tag (16)
+//! ```
+//!
+//! The header is authenticated along with the ciphertext, so a wrong
+//! passphrase and a damaged file are both caught before anything is read.
+//! `ca restore` then checks each file against the manifest and the CA key
+//! against its certificate before writing anything back.
+
+use crate::config::Config;
+use crate::crypto::{format_name, load_cert};
+use crate::error::{FluxError, Result};
+use crate::fsutil::{write_atomic, PRIVATE_FILE_MODE};
+use chrono::{DateTime, Utc};
+use openssl::pkey::PKey;
+use openssl::symm::{decrypt_aead, encrypt_aead, Cipher};
+use openssl::x509::X509;
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use zeroize::Zeroizing;
+
+const MAGIC: &[u8; 8] = b"FLUXBAK1";
+const HEADER_LEN: usize = MAGIC.len() + 1 + 4 + 4 + SALT_LEN + NONCE_LEN;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+
+/// scrypt cost for new backups (N = 2^15, r = 8, p = 1: 32 MiB)
+const SCRYPT_LOG_N: u8 = 15;
+const SCRYPT_R: u32 = 8;
+const SCRYPT_P: u32 = 1;
+/// Highest cost accepted when reading a backup (N = 2^20: 1 GiB)
+const MAX_SCRYPT_LOG_N: u8 = 20;
+
+/// Name of the manifest inside the archive
+pub const MANIFEST_NAME: &str = "MANIFEST.json";
+
+/// What a backup holds
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupManifest {
+    /// Format version
+    pub version: u32,
+    /// When the backup was made
+    pub created_at: DateTime<Utc>,
+    /// Subject of the intermediate CA certificate
+    pub ca_subject: String,
+    /// Working directory the `ca/` files came from
+    pub working_dir: PathBuf,
+    /// CA certificate path at the time of the backup
+    pub ca_cert_path: PathBuf,
+    /// CA key path at the time of the backup
+    pub ca_key_path: PathBuf,
+    /// Files in the archive, in archive order
+    pub files: Vec<BackupEntry>,
+}
+
+/// A file in a backup
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupEntry {
+    /// Path inside the archive
+    pub path: String,
+    /// Where the file was backed up from
+    pub source: PathBuf,
+    /// Unix permission bits
+    pub mode: u32,
+    /// Size in bytes
+    pub size: u64,
+    /// SHA-256 of the contents (hex)
+    pub sha256: String,
+}
+
+/// A decrypted backup whose checksums have been verified
+pub struct Backup {
+    pub manifest: BackupManifest,
+    files: BTreeMap<String, Zeroizing<Vec<u8>>>,
+}
+
+impl Backup {
+    /// Decrypt a backup and check every file against the manifest
+    pub fn open(data: &[u8], passphrase: &str) -> Result<Self> {
+        let archive = decrypt(data, passphrase)?;
+        let mut entries = tar::read(&archive)?;
+
+        let manifest = entries.remove(MANIFEST_NAME)
+            .ok_or_else(|| FluxError::BackupError(format!("{} missing from the archive", MANIFEST_NAME)))?;
+        let manifest: BackupManifest = serde_json::from_slice(&manifest.1)
+            .map_err(|e| FluxError::BackupError(format!("invalid {}: {}", MANIFEST_NAME, e)))?;
+
+        let mut files = BTreeMap::new();
+        for entry in &manifest.files {
+            let (_, data) = entries.remove(&entry.path)
+                .ok_or_else(|| FluxError::BackupError(format!("{} missing from the archive", entry.path)))?;
+            if data.len() as u64 != entry.size || sha256_hex(&data) != entry.sha256 {
+                return Err(FluxError::BackupError(format!("{} does not match its checksum", entry.path)));
+            }
+            files.insert(entry.path.clone(), data);
+        }
+        if let Some(extra) = entries.keys().next() {
+            return Err(FluxError::BackupError(format!("{} is not in the manifest", extra)));
+        }
+
+        Ok(Self { manifest, files })
+    }
+
+    /// Contents of a file in the backup
+    pub fn file(&self, path: &str) -> Option<&[u8]> {
+        self.files.get(path).map(|data| data.as_slice())
+    }
+}
+
+/// Write the files of a backup back to where they came from
+///
+/// Before anything is written, the CA key is checked against the CA
+/// certificate (`key_password` is asked for an encrypted key). Existing files
+/// are only replaced with `force`. Returns the files written.
+pub fn restore(
+    backup: &Backup,
+    force: bool,
+    key_password: impl FnOnce() -> Result<Secret<String>>,
+) -> Result<Vec<PathBuf>> {
+    check_ca_pair(backup, key_password)?;
+
+    let existing: Vec<&Path> = backup.manifest.files.iter()
+        .map(|f| f.source.as_path())
+        .filter(|p| p.exists())
+        .collect();
+    if !existing.is_empty() && !force {
+        return Err(FluxError::BackupError(format!(
+            "{} and {} other file(s) already exist; a live CA is only replaced with --force",
+            existing[0].display(),
+            existing.len() - 1
+        )));
+    }
+
+    let mut restored = Vec::new();
+    for entry in &backup.manifest.files {
+        let data = backup.file(&entry.path).expect("checked by Backup::open");
+        if let Some(parent) = entry.source.parent().filter(|p| !p.as_os_str().is_empty()) {
+            create_dirs(parent)?;
+        }
+        write_atomic(&entry.source, data, entry.mode)?;
+        restored.push(entry.source.clone());
+    }
+    Ok(restored)
+}
+
+/// Fail unless the backup's CA key belongs to its CA certificate
+fn check_ca_pair(backup: &Backup, key_password: impl FnOnce() -> Result<Secret<String>>) -> Result<()> {
+    let file = |path: &Path| {
+        backup.manifest.files.iter()
+            .find(|f| f.source == path)
+            .and_then(|f| backup.file(&f.path))
+            .ok_or_else(|| FluxError::BackupError(format!("{} is not in the backup", path.display())))
+    };
+    let cert = X509::from_pem(file(&backup.manifest.ca_cert_path)?)
+        .map_err(|e| FluxError::BackupError(format!("CA certificate: {}", e)))?;
+    let key_pem = file(&backup.manifest.ca_key_path)?;
+
+    let key = if key_pem.windows(9).any(|w| w == b"ENCRYPTED") {
+        let password = key_password()?;
+        PKey::private_key_from_pem_passphrase(key_pem, password.expose_secret().as_bytes())
+            .map_err(|_| FluxError::CaKeyUnlockFailed)?
+    } else {
+        PKey::private_key_from_pem(key_pem)
+            .map_err(|e| FluxError::BackupError(format!("CA key: {}", e)))?
+    };
+
+    if !cert.public_key()?.public_eq(&key) {
+        return Err(FluxError::BackupError("the CA key in the backup does not match the CA certificate".to_string()));
+    }
+    Ok(())
+}
+
+/// Create missing directories, keeping `private/` directories owner-only
+fn create_dirs(dir: &Path) -> Result<()> {
+    if dir.is_dir() {
+        return Ok(());
+    }
+    std::fs::create_dir_all(dir)
+        .map_err(|e| FluxError::FileWriteFailed(dir.to_path_buf(), e.to_string()))?;
+    #[cfg(unix)]
+    if dir.file_name().is_some_and(|n| n == "private") {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(dir, std::fs::Permissions::from_mode(0o700))
+            .map_err(|e| FluxError::FileWriteFailed(dir.to_path_buf(), e.to_string()))?;
+    }
+    Ok(())
+}
+
+/// Back up the CA to `out`, encrypted with `passphrase`
+///
+/// `config_file` is included when given. Returns the manifest.
+pub fn backup(config: &Config, config_file: Option<&Path>, out: &Path, passphrase: &str) -> Result<BackupManifest> {
+    if passphrase.is_empty() {
+        return Err(FluxError::BackupError("the passphrase is empty".to_string()));
+    }
+    let (archive, manifest) = archive(config, config_file, Some(out))?;
+    write_atomic(out, &encrypt(&archive, passphrase)?, PRIVATE_FILE_MODE)?;
+    Ok(manifest)
+}
+
+/// Plain tar archive of the CA, leaving out `exclude` (the backup itself)
+pub fn archive(config: &Config, config_file: Option<&Path>, exclude: Option<&Path>) -> Result<(Zeroizing<Vec<u8>>, BackupManifest)> {
+    let ca_cert = load_cert(&config.ca_cert_path)?;
+    let exclude = exclude.and_then(|p| std::path::absolute(p).ok());
+    let mut sources: Vec<(String, PathBuf)> = Vec::new();
+
+    if !config.working_dir.is_dir() {
+        return Err(FluxError::WorkingDirNotFound(config.working_dir.clone()));
+    }
+    for entry in walkdir::WalkDir::new(&config.working_dir).sort_by_file_name() {
+        let entry = entry.map_err(|e| FluxError::BackupError(e.to_string()))?;
+        let path = entry.path();
+        let is_temp = entry.file_name().to_str().is_some_and(|n| n.starts_with(".flux-"));
+        if !entry.file_type().is_file() || is_temp || exclude.as_deref() == std::path::absolute(path).ok().as_deref() {
+            continue;
+        }
+        let relative = path.strip_prefix(&config.working_dir).unwrap_or(path);
+        sources.push((format!("ca/{}", archive_path(relative)?), path.to_path_buf()));
+    }
+
+    // The CA files and config may live outside the working directory
+    let mut outside = vec![("key", config.ca_key_path.as_path()), ("cert", config.ca_cert_path.as_path())];
+    outside.extend(config_file.map(|p| ("config", p)));
+    for (kind, path) in outside {
+        if path.starts_with(&config.working_dir) {
+            continue;
+        }
+        let name = path.file_name().and_then(|n| n.to_str())
+            .ok_or_else(|| FluxError::BackupError(format!("{}: not a file", path.display())))?;
+        sources.push((format!("{}/{}", kind, name), path.to_path_buf()));
+    }
+
+    let mut manifest = BackupManifest {
+        version: 1,
+        created_at: Utc::now(),
+        ca_subject: format_name(ca_cert.subject_name()),
+        working_dir: config.working_dir.clone(),
+        ca_cert_path: config.ca_cert_path.clone(),
+        ca_key_path: config.ca_key_path.clone(),
+        files: Vec::new(),
+    };
+    let mut contents = Vec::new();
+    for (archive_path, source) in sources {
+        // Not bounded by limits.max_file_size: everything has to be in the backup
+        let data = Zeroizing::new(std::fs::read(&source)
+            .map_err(|e| FluxError::FileReadFailed(source.clone(), e.to_string()))?);
+        manifest.files.push(BackupEntry {
+            path: archive_path,
+            mode: file_mode(&source)?,
+            size: data.len() as u64,
+            sha256: sha256_hex(&data),
+            source,
+        });
+        contents.push(data);
+    }
+
+    let mut out = Zeroizing::new(Vec::new());
+    let manifest_json = serde_json::to_vec_pretty(&manifest)
+        .map_err(|e| FluxError::BackupError(e.to_string()))?;
+    let mtime = manifest.created_at.timestamp().max(0) as u64;
+    tar::append(&mut out, MANIFEST_NAME, 0o600, mtime, &manifest_json)?;
+    for (entry, data) in manifest.files.iter().zip(&contents) {
+        tar::append(&mut out, &entry.path, entry.mode, mtime, data)?;
+    }
+    tar::finish(&mut out);
+
+    Ok((out, manifest))
+}
+
+/// Encrypt `plain` with a key derived from `passphrase`
+pub fn encrypt(plain: &[u8], passphrase: &str) -> Result<Vec<u8>> {
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    openssl::rand::rand_bytes(&mut salt)?;
+    openssl::rand::rand_bytes(&mut nonce)?;
+
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    header.extend_from_slice(MAGIC);
+    header.push(SCRYPT_LOG_N);
+    header.extend_from_slice(&SCRYPT_R.to_be_bytes());
+    header.extend_from_slice(&SCRYPT_P.to_be_bytes());
+    header.extend_from_slice(&salt);
+    header.extend_from_slice(&nonce);
+
+    let key = derive_key(passphrase, &salt, SCRYPT_LOG_N, SCRYPT_R, SCRYPT_P)?;
+    let mut tag = [0u8; TAG_LEN];
+    let ciphertext = encrypt_aead(Cipher::aes_256_gcm(), key.as_slice(), Some(&nonce), &header, plain, &mut tag)?;
+
+    let mut out = header;
+    out.extend_from_slice(&ciphertext);
+    out.extend_from_slice(&tag);
+    Ok(out)
+}
+
+/// Decrypt a backup made by [`encrypt`]
+pub fn decrypt(data: &[u8], passphrase: &str) -> Result<Zeroizing<Vec<u8>>> {
+    if data.len() < HEADER_LEN + TAG_LEN || !data.starts_with(MAGIC) {
+        return Err(FluxError::BackupError("not a flux-ssl-mgr backup".to_string()));
+    }
+    let (header, rest) = data.split_at(HEADER_LEN);
+    let (ciphertext, tag) = rest.split_at(rest.len() - TAG_LEN);
+
+    let log_n = header[8];
+    let r = u32::from_be_bytes(header[9..13].try_into().expect("4 bytes"));
+    let p = u32::from_be_bytes(header[13..17].try_into().expect("4 bytes"));
+    let salt = &header[17..17 + SALT_LEN];
+    let nonce = &header[17 + SALT_LEN..];
+    if log_n > MAX_SCRYPT_LOG_N || r == 0 || r > 32 || p == 0 || p > 16 {
+        return Err(FluxError::BackupError("unsupported key derivation parameters".to_string()));
+    }
+
+    let key = derive_key(passphrase, salt, log_n, r, p)?;
+    decrypt_aead(Cipher::aes_256_gcm(), key.as_slice(), Some(nonce), header, ciphertext, tag)
+        .map(Zeroizing::new)
+        .map_err(|_| FluxError::BackupError("wrong passphrase, or the backup is damaged".to_string()))
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], log_n: u8, r: u32, p: u32) -> Result<Zeroizing<[u8; 32]>> {
+    let n = 1u64 << log_n;
+    let maxmem = 128 * u64::from(r) * (n + u64::from(p) + 2);
+    let mut key = Zeroizing::new([0u8; 32]);
+    openssl::pkcs5::scrypt(passphrase.as_bytes(), salt, n, u64::from(r), u64::from(p), maxmem, key.as_mut_slice())?;
+    Ok(key)
+}
+
+pub(crate) fn sha256_hex(data: &[u8]) -> String {
+    openssl::sha::sha256(data).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// `/`-separated archive path of a relative path
+fn archive_path(relative: &Path) -> Result<String> {
+    let parts = relative.components()
+        .map(|c| match c {
+            std::path::Component::Normal(part) => part.to_str()
+                .ok_or_else(|| FluxError::BackupError(format!("{}: not valid UTF-8", relative.display()))),
+            _ => Err(FluxError::BackupError(format!("{}: unexpected path", relative.display()))),
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(parts.join("/"))
+}
+
+#[cfg(unix)]
+fn file_mode(path: &Path) -> Result<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    let metadata = std::fs::metadata(path)
+        .map_err(|e| FluxError::FileReadFailed(path.to_path_buf(), e.to_string()))?;
+    Ok(metadata.permissions().mode() & 0o7777)
+}
+
+#[cfg(not(unix))]
+fn file_mode(_path: &Path) -> Result<u32> {
+    Ok(0o600)
+}
+
+/// Just enough of the ustar format for backups: regular files only
+mod tar {
+    use crate::error::{FluxError, Result};
+    use std::collections::BTreeMap;
+    use zeroize::Zeroizing;
+
+    const BLOCK: usize = 512;
+
+    /// Files of an archive by path, with their modes
+    pub type Files = BTreeMap<String, (u32, Zeroizing<Vec<u8>>)>;
+
+    /// Append a regular file
+    pub fn append(out: &mut Vec<u8>, path: &str, mode: u32, mtime: u64, data: &[u8]) -> Result<()> {
+        let mut header = [0u8; BLOCK];
+        let (prefix, name) = split_path(path)?;
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        octal(&mut header[100..108], u64::from(mode));
+        octal(&mut header[108..116], 0);
+        octal(&mut header[116..124], 0);
+        octal(&mut header[124..136], data.len() as u64);
+        octal(&mut header[136..148], mtime);
+        header[156] = b'0';
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[263..265].copy_from_slice(b"00");
+        header[345..345 + prefix.len()].copy_from_slice(prefix.as_bytes());
+
+        header[148..156].fill(b' ');
+        let checksum: u64 = header.iter().map(|&b| u64::from(b)).sum();
+        header[148..156].copy_from_slice(format!("{:06o}\0 ", checksum).as_bytes());
+
+        out.extend_from_slice(&header);
+        out.extend_from_slice(data);
+        out.resize(out.len().div_ceil(BLOCK) * BLOCK, 0);
+        Ok(())
+    }
+
+    /// End the archive
+    pub fn finish(out: &mut Vec<u8>) {
+        out.resize(out.len() + 2 * BLOCK, 0);
+    }
+
+    /// Every file in an archive, by path, with its mode
+    pub fn read(archive: &[u8]) -> Result<Files> {
+        let invalid = |what: &str| FluxError::BackupError(format!("invalid archive: {}", what));
+        let mut files = BTreeMap::new();
+        let mut offset = 0;
+
+        while offset + BLOCK <= archive.len() {
+            let header = &archive[offset..offset + BLOCK];
+            if header.iter().all(|&b| b == 0) {
+                return Ok(files);
+            }
+
+            let mut unsigned = header.to_vec();
+            unsigned[148..156].fill(b' ');
+            let checksum: u64 = unsigned.iter().map(|&b| u64::from(b)).sum();
+            if parse_octal(&header[148..156]) != Some(checksum) {
+                return Err(invalid("header checksum mismatch"));
+            }
+            if !matches!(header[156], b'0' | 0) {
+                return Err(invalid("only regular files are supported"));
+            }
+
+            let name = field(&header[..100]);
+            let prefix = field(&header[345..500]);
+            let path = if prefix.is_empty() { name } else { format!("{}/{}", prefix, name) };
+            let mode = parse_octal(&header[100..108]).ok_or_else(|| invalid("bad mode"))? as u32;
+            let size = parse_octal(&header[124..136]).ok_or_else(|| invalid("bad size"))? as usize;
+
+            let start = offset + BLOCK;
+            let data = archive.get(start..start.saturating_add(size)).ok_or_else(|| invalid("truncated"))?;
+            files.insert(path, (mode, Zeroizing::new(data.to_vec())));
+            offset = start + size.div_ceil(BLOCK) * BLOCK;
+        }
+
+        Err(invalid("missing end of archive"))
+    }
+
+    fn split_path(path: &str) -> Result<(&str, &str)> {
+        if path.len() <= 100 {
+            return Ok(("", path));
+        }
+        path.char_indices()
+            .filter(|&(i, c)| c == '/' && i <= 155 && path.len() - i - 1 <= 100)
+            .map(|(i, _)| (&path[..i], &path[i + 1..]))
+            .next()
+            .ok_or_else(|| FluxError::BackupError(format!("{}: path too long for the archive", path)))
+    }
+
+    fn octal(field: &mut [u8], value: u64) {
+        let width = field.len() - 1;
+        field[..width].copy_from_slice(format!("{:0width$o}", value, width = width).as_bytes());
+        field[width] = 0;
+    }
+
+    fn parse_octal(field: &[u8]) -> Option<u64> {
+        let text = std::str::from_utf8(field).ok()?;
+        let text = text.trim_matches(|c: char| c == '\0' || c == ' ');
+        u64::from_str_radix(text, 8).ok()
+    }
+
+    fn field(bytes: &[u8]) -> String {
+        let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+        String::from_utf8_lossy(&bytes[..end]).into_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    /// A working directory with the fixture CA in the usual layout
+    fn ca_layout(dir: &Path) -> Config {
+        let working_dir = dir.join("ca");
+        let intermediate = working_dir.join("intermediate");
+        std::fs::create_dir_all(intermediate.join("private")).unwrap();
+        std::fs::create_dir_all(intermediate.join("certs")).unwrap();
+
+        let ca = fixtures::intermediate_ca();
+        let config = Config {
+            ca_key_path: intermediate.join("private").join("intermediate.key.pem"),
+            ca_cert_path: intermediate.join("certs").join("intermediate.cert.pem"),
+            working_dir,
+            ..Config::default()
+        };
+        std::fs::write(&config.ca_key_path, ca.key().private_key_to_pem_pkcs8().unwrap()).unwrap();
+        std::fs::write(&config.ca_cert_path, ca.cert().to_pem().unwrap()).unwrap();
+        std::fs::write(intermediate.join("index.txt"), "V\t270101000000Z\t\t1000\tunknown\t/CN=web\n").unwrap();
+        config
+    }
+
+    #[test]
+    fn test_backup_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = ca_layout(dir.path());
+        let config_file = dir.path().join("config.toml");
+        std::fs::write(&config_file, "working_dir = \"/root/ca\"\n").unwrap();
+        // A long path needs the ustar prefix field
+        let deep = config.working_dir.join("intermediate").join("a".repeat(60)).join("b".repeat(60));
+        std::fs::create_dir_all(&deep).unwrap();
+        std::fs::write(deep.join("deep.pem"), "deep").unwrap();
+
+        // The backup is written into the working directory but not into itself
+        let out = config.working_dir.join("backup.tar.enc");
+        let manifest = backup(&config, Some(&config_file), &out, "correct horse").unwrap();
+        let paths: Vec<&str> = manifest.files.iter().map(|f| f.path.as_str()).collect();
+        assert!(paths.contains(&"ca/intermediate/private/intermediate.key.pem"), "{:?}", paths);
+        assert!(paths.contains(&"ca/intermediate/index.txt"), "{:?}", paths);
+        assert!(paths.contains(&"config/config.toml"), "{:?}", paths);
+        assert!(!paths.iter().any(|p| p.contains("backup.tar.enc")), "{:?}", paths);
+
+        let data = std::fs::read(&out).unwrap();
+        assert!(data.starts_with(MAGIC));
+        assert!(!data.windows(11).any(|w| w == b"PRIVATE KEY"));
+
+        let restored = Backup::open(&data, "correct horse").unwrap();
+        assert_eq!(restored.manifest.files.len(), manifest.files.len());
+        assert_eq!(restored.file("ca/intermediate/index.txt").unwrap(), std::fs::read(config.working_dir.join("intermediate/index.txt")).unwrap().as_slice());
+        let deep_path = format!("ca/intermediate/{}/{}/deep.pem", "a".repeat(60), "b".repeat(60));
+        assert_eq!(restored.file(&deep_path).unwrap(), b"deep");
+        #[cfg(unix)]
+        assert!(manifest.files.iter().all(|f| f.mode != 0));
+
+        assert!(matches!(Backup::open(&data, "wrong horse"), Err(FluxError::BackupError(_))));
+        let mut damaged = data.clone();
+        let last = damaged.len() - 40;
+        damaged[last] ^= 1;
+        assert!(Backup::open(&damaged, "correct horse").is_err());
+        assert!(backup(&config, None, &out, "").is_err());
+    }
+
+    #[test]
+    fn test_restore() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = ca_layout(dir.path());
+        let out = dir.path().join("backup.tar.enc");
+        backup(&config, None, &out, "pass").unwrap();
+        let saved = Backup::open(&std::fs::read(&out).unwrap(), "pass").unwrap();
+        let no_password = || -> Result<Secret<String>> { panic!("the fixture key isn't encrypted") };
+
+        // A live CA is left alone without force
+        let err = restore(&saved, false, no_password).unwrap_err();
+        assert!(err.to_string().contains("--force"), "{}", err);
+
+        std::fs::remove_dir_all(&config.working_dir).unwrap();
+        let restored = restore(&saved, false, no_password).unwrap();
+        assert!(restored.contains(&config.ca_key_path));
+        assert_eq!(std::fs::read_to_string(config.working_dir.join("intermediate/index.txt")).unwrap(), "V\t270101000000Z\t\t1000\tunknown\t/CN=web\n");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = |p: &Path| std::fs::metadata(p).unwrap().permissions().mode() & 0o777;
+            assert_eq!(mode(config.ca_key_path.parent().unwrap()), 0o700);
+        }
+        assert_eq!(restore(&saved, true, no_password).unwrap().len(), restored.len());
+
+        // A key that doesn't match the certificate is refused
+        std::fs::write(&config.ca_key_path, fixtures::leaf_key().private_key_to_pem_pkcs8().unwrap()).unwrap();
+        backup(&config, None, &out, "pass").unwrap();
+        let mismatched = Backup::open(&std::fs::read(&out).unwrap(), "pass").unwrap();
+        let err = restore(&mismatched, true, no_password).unwrap_err();
+        assert!(err.to_string().contains("does not match"), "{}", err);
+    }
+
+    #[test]
+    fn test_tar_matches_ustar() {
+        let mut archive = Vec::new();
+        tar::append(&mut archive, "ca/index.txt", 0o644, 0, b"hello").unwrap();
+        tar::finish(&mut archive);
+        assert_eq!(archive.len(), 4 * 512);
+        assert_eq!(&archive[257..263], b"ustar\0");
+
+        let files = tar::read(&archive).unwrap();
+        let (mode, data) = &files["ca/index.txt"];
+        assert_eq!((*mode, data.as_slice()), (0o644, b"hello".as_slice()));
+
+        archive[0] ^= 1;
+        assert!(tar::read(&archive).is_err());
+    }
+}
@@ -1,12 +1,20 @@
 //! Intermediate CA management
 
-use crate::config::Config;
-use crate::crypto::{load_private_key, load_cert, is_key_encrypted, unlock_ca_key};
+use crate::config::{CaExpiryPolicy, Config, PolicyConfig};
+use crate::crypto::{load_private_key, load_cert, is_key_encrypted, unlock_ca_key, format_name, NotBefore};
+use crate::crypto::cert::asn1_time_to_chrono;
+use crate::crypto::backend::{backend, CryptoBackend, OpenSslBackend, SubCaExtensions};
+use crate::crypto::NameConstraints;
+use crate::duration::Validity;
+use super::openssl_db::OpensslDb;
+use super::serial::{serial_source, RandomSerial, SerialSource};
+use crate::crypto::key::prompt_password;
 use crate::error::{FluxError, Result};
+use crate::fsutil::{read_bounded, read_to_string_bounded};
 use openssl::pkey::{PKey, Private};
 use openssl::x509::X509;
-use secrecy::{Secret, ExposeSecret};
-use std::path::PathBuf;
+use secrecy::{ExposeSecret, Secret};
+use zeroize::Zeroizing;
 
 /// Represents an intermediate Certificate Authority
 pub struct IntermediateCA {
@@ -16,6 +24,20 @@ pub struct IntermediateCA {
     cert: X509,
     /// Temporary file handle (if CA key was unlocked)
     _temp_file: Option<tempfile::NamedTempFile>,
+    /// Backend used to sign CSRs
+    backend: &'static dyn CryptoBackend,
+    /// Handling of validity past the CA's own expiry
+    expiry_policy: CaExpiryPolicy,
+    /// Restrictions on what is signed
+    policy: PolicyConfig,
+    /// Start of validity of signed certificates
+    not_before: NotBefore,
+    /// Serial numbers of signed certificates
+    serials: Box<dyn SerialSource>,
+    /// OpenSSL `ca` database that signed certificates are added to
+    openssl_db: Option<OpensslDb>,
+    /// Extensions for subordinate CA certificates
+    sub_ca: SubCaExtensions,
 }
 
 impl IntermediateCA {
@@ -28,15 +50,11 @@ impl IntermediateCA {
         let is_encrypted = is_key_encrypted(&config.ca_key_path)?;
 
         let (key, temp_file) = if is_encrypted {
-            // Prompt for password
-            use dialoguer::Password;
-            let password = Password::new()
-                .with_prompt("Enter intermediate CA private key password")
-                .interact()
-                .map_err(|e| FluxError::InteractiveError(e.to_string()))?;
+            // Read the password from the configured file, or prompt for it
+            let password = ca_key_password(config)?;
 
             // Unlock the CA key
-            let (key, temp) = unlock_ca_key(&config.ca_key_path, &password)?;
+            let (key, temp) = unlock_ca_key(&config.ca_key_path, password.expose_secret())?;
             (key, Some(temp))
         } else {
             // Load unencrypted key
@@ -48,6 +66,13 @@ impl IntermediateCA {
             key,
             cert,
             _temp_file: temp_file,
+            backend: backend(config.crypto_backend)?,
+            expiry_policy: config.ca_expiry_policy,
+            policy: config.policy.clone(),
+            not_before: config.defaults.validity_start(),
+            serials: serial_source(config),
+            openssl_db: OpensslDb::from_config(config),
+            sub_ca: sub_ca_extensions(config)?,
         })
     }
 
@@ -69,9 +94,135 @@ impl IntermediateCA {
             key,
             cert,
             _temp_file: temp_file,
+            backend: backend(config.crypto_backend)?,
+            expiry_policy: config.ca_expiry_policy,
+            policy: config.policy.clone(),
+            not_before: config.defaults.validity_start(),
+            serials: serial_source(config),
+            openssl_db: OpensslDb::from_config(config),
+            sub_ca: sub_ca_extensions(config)?,
         })
     }
 
+    /// Load the CA keeping the unlocked key only in memory
+    ///
+    /// Unlike [`IntermediateCA::load`], no decrypted copy of the key is ever
+    /// written to a temporary file. Used by the signing agent.
+    pub fn load_in_memory(config: &Config) -> Result<Self> {
+        let cert = load_cert(&config.ca_cert_path)?;
+
+        let key = if is_key_encrypted(&config.ca_key_path)? {
+            let password = ca_key_password(config)?;
+
+            load_private_key(&config.ca_key_path, Some(password.expose_secret()))
+                .map_err(|_| FluxError::CaKeyUnlockFailed)?
+        } else {
+            load_private_key(&config.ca_key_path, None)?
+        };
+
+        Ok(Self::from_parts(cert, key)
+            .with_backend(backend(config.crypto_backend)?)
+            .with_expiry_policy(config.ca_expiry_policy)
+            .with_policy(config.policy.clone())
+            .with_not_before(config.defaults.validity_start())
+            .with_serial_source(serial_source(config))
+            .with_openssl_db(OpensslDb::from_config(config))
+            .with_sub_ca(sub_ca_extensions(config)?))
+    }
+
+    /// Build a CA from an already loaded certificate and key
+    pub fn from_parts(cert: X509, key: PKey<Private>) -> Self {
+        Self {
+            key,
+            cert,
+            _temp_file: None,
+            backend: &OpenSslBackend,
+            expiry_policy: CaExpiryPolicy::default(),
+            policy: PolicyConfig::default(),
+            not_before: NotBefore::default(),
+            serials: Box::new(RandomSerial),
+            openssl_db: None,
+            sub_ca: SubCaExtensions::default(),
+        }
+    }
+
+    /// Use a different backend for signing
+    pub fn with_backend(mut self, backend: &'static dyn CryptoBackend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// Backend used to sign CSRs
+    pub fn backend(&self) -> &'static dyn CryptoBackend {
+        self.backend
+    }
+
+    /// Handle validity past the CA's expiry according to `policy`
+    pub fn with_expiry_policy(mut self, policy: CaExpiryPolicy) -> Self {
+        self.expiry_policy = policy;
+        self
+    }
+
+    /// Handling of validity past the CA's expiry
+    pub fn expiry_policy(&self) -> CaExpiryPolicy {
+        self.expiry_policy
+    }
+
+    /// Apply `policy` when signing
+    pub fn with_policy(mut self, policy: PolicyConfig) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Restrictions on what is signed
+    pub fn policy(&self) -> &PolicyConfig {
+        &self.policy
+    }
+
+    /// Start the validity of signed certificates at `not_before`
+    pub fn with_not_before(mut self, not_before: NotBefore) -> Self {
+        self.not_before = not_before;
+        self
+    }
+
+    /// Start of validity of signed certificates
+    pub fn not_before(&self) -> NotBefore {
+        self.not_before
+    }
+
+    /// Take serial numbers of signed certificates from `serials`
+    pub fn with_serial_source(mut self, serials: Box<dyn SerialSource>) -> Self {
+        self.serials = serials;
+        self
+    }
+
+    /// Source of serial numbers of signed certificates
+    pub fn serial_source(&self) -> &dyn SerialSource {
+        self.serials.as_ref()
+    }
+
+    /// Add signed certificates to `db`, if given
+    pub fn with_openssl_db(mut self, db: Option<OpensslDb>) -> Self {
+        self.openssl_db = db;
+        self
+    }
+
+    /// OpenSSL `ca` database that signed certificates are added to
+    pub fn openssl_db(&self) -> Option<&OpensslDb> {
+        self.openssl_db.as_ref()
+    }
+
+    /// Set the extensions for subordinate CA certificates
+    pub fn with_sub_ca(mut self, sub_ca: SubCaExtensions) -> Self {
+        self.sub_ca = sub_ca;
+        self
+    }
+
+    /// Extensions for subordinate CA certificates
+    pub fn sub_ca(&self) -> &SubCaExtensions {
+        &self.sub_ca
+    }
+
     /// Get reference to CA private key
     pub fn key(&self) -> &PKey<Private> {
         &self.key
@@ -94,6 +245,99 @@ impl IntermediateCA {
     }
 }
 
+/// Fail with [`FluxError::CaExpired`] once the CA certificate has expired
+///
+/// Certificates signed by an expired intermediate don't validate anywhere,
+/// so signing is refused rather than handing them out.
+pub fn ensure_not_expired(cert: &X509) -> Result<()> {
+    let not_after = asn1_time_to_chrono(cert.not_after())?;
+    if not_after <= chrono::Utc::now() {
+        return Err(FluxError::CaExpired(
+            format_name(cert.subject_name()),
+            not_after.format("%Y-%m-%d %H:%M UTC").to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// `validity`, ending no later than the CA certificate
+///
+/// A longer request is shortened to the CA's whole remaining days with a
+/// warning, or refused with [`FluxError::ValidityExceedsCa`] under
+/// [`CaExpiryPolicy::Fail`] or when less than a day remains.
+pub fn clamp_validity(cert: &X509, validity: Validity, policy: CaExpiryPolicy) -> Result<Validity> {
+    let not_after = asn1_time_to_chrono(cert.not_after())?;
+    let remaining = not_after - chrono::Utc::now();
+    if validity.as_duration() <= remaining {
+        return Ok(validity);
+    }
+
+    let until = not_after.format("%Y-%m-%d %H:%M UTC").to_string();
+    let remaining = remaining.num_days();
+    if policy == CaExpiryPolicy::Fail || remaining < 1 {
+        return Err(FluxError::ValidityExceedsCa(validity, until));
+    }
+    tracing::warn!(
+        "{} of validity would outlast the CA certificate (expires {}); issuing for {} days",
+        validity, until, remaining
+    );
+    Ok(Validity::days(remaining as u32))
+}
+
+/// Extensions for subordinate CAs from the `[sub_ca]` section
+fn sub_ca_extensions(config: &Config) -> Result<SubCaExtensions> {
+    Ok(SubCaExtensions {
+        pathlen: config.sub_ca.pathlen,
+        name_constraints: NameConstraints::from_config(&config.sub_ca)?,
+    })
+}
+
+/// CA key password from `ca_key_password_file`, or prompted for
+pub(crate) fn ca_key_password(config: &Config) -> Result<Secret<String>> {
+    match &config.ca_key_password_file {
+        Some(path) => {
+            let content = Zeroizing::new(read_to_string_bounded(path)?);
+            Ok(Secret::new(content.trim_end_matches(['\r', '\n']).to_string()))
+        }
+        None => prompt_password("Enter intermediate CA private key password"),
+    }
+}
+
+/// Intermediate (and root, if present) CA certificates as one PEM bundle
+pub fn chain_pem(config: &Config) -> Result<Vec<u8>> {
+    let mut chain = read_bounded(&config.ca_cert_path)?;
+    let root = config.root_ca_cert_path();
+    if root.exists() {
+        chain.extend(read_bounded(&root)?);
+    }
+    Ok(chain)
+}
+
+/// Which CA certificates [`ca_chain`] returns
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChainPart {
+    Intermediate,
+    Root,
+    /// The intermediate, then the root if present
+    Full,
+}
+
+/// The selected CA certificates, intermediate first
+pub fn ca_chain(config: &Config, part: ChainPart) -> Result<Vec<X509>> {
+    let root = config.root_ca_cert_path();
+    let mut chain = Vec::new();
+    if part != ChainPart::Root {
+        chain.push(load_cert(&config.ca_cert_path)?);
+    }
+    match part {
+        ChainPart::Root if !root.exists() => return Err(FluxError::CaCertNotFound(root)),
+        ChainPart::Root => chain.push(load_cert(&root)?),
+        ChainPart::Full if root.exists() => chain.push(load_cert(&root)?),
+        _ => {}
+    }
+    Ok(chain)
+}
+
 impl Drop for IntermediateCA {
     fn drop(&mut self) {
         // Temp file will be automatically cleaned up
@@ -104,7 +348,209 @@ impl Drop for IntermediateCA {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::crypto::{cert_to_pem, key_to_encrypted_pem};
+    use crate::fixtures;
+
+    #[test]
+    fn test_load_with_password_file() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let ca = fixtures::intermediate_ca();
+        let config = Config {
+            ca_key_path: temp_dir.path().join("ca.key.pem"),
+            ca_cert_path: temp_dir.path().join("ca.cert.pem"),
+            ca_key_password_file: Some(temp_dir.path().join("ca.pass")),
+            ..Config::default()
+        };
+
+        let password = Secret::new("correct horse".to_string());
+        std::fs::write(&config.ca_key_path, key_to_encrypted_pem(ca.key(), &password).unwrap()).unwrap();
+        std::fs::write(&config.ca_cert_path, cert_to_pem(ca.cert()).unwrap()).unwrap();
+        std::fs::write(config.ca_key_password_file.as_ref().unwrap(), "correct horse\n").unwrap();
+
+        let loaded = IntermediateCA::load_in_memory(&config).unwrap();
+        assert!(loaded.key().public_eq(ca.key()));
+
+        std::fs::write(config.ca_key_password_file.as_ref().unwrap(), "wrong").unwrap();
+        assert!(IntermediateCA::load_in_memory(&config).is_err());
+    }
+
+    #[test]
+    fn test_ca_chain() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let config = Config {
+            working_dir: temp_dir.path().to_path_buf(),
+            ca_cert_path: temp_dir.path().join("intermediate.cert.pem"),
+            ..Config::default()
+        };
+        let intermediate = fixtures::intermediate_ca().cert().to_owned();
+        crate::crypto::save_cert_pem(&intermediate, &config.ca_cert_path).unwrap();
+
+        // No root yet
+        assert_eq!(ca_chain(&config, ChainPart::Full).unwrap().len(), 1);
+        assert!(matches!(ca_chain(&config, ChainPart::Root), Err(FluxError::CaCertNotFound(_))));
+
+        std::fs::create_dir(temp_dir.path().join("certs")).unwrap();
+        crate::crypto::save_cert_pem(&fixtures::root_ca_cert(), config.root_ca_cert_path()).unwrap();
+        let full = ca_chain(&config, ChainPart::Full).unwrap();
+        assert_eq!(full.len(), 2);
+        assert_eq!(full[0].to_der().unwrap(), intermediate.to_der().unwrap());
+        assert_eq!(full[1].to_der().unwrap(), fixtures::root_ca_cert().to_der().unwrap());
+        assert_eq!(ca_chain(&config, ChainPart::Root).unwrap()[0].to_der().unwrap(), full[1].to_der().unwrap());
+        assert_eq!(ca_chain(&config, ChainPart::Intermediate).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_expired_ca_refuses_to_sign() {
+        use crate::ca::CertSigner;
+
+        assert!(ensure_not_expired(fixtures::intermediate_ca().cert()).is_ok());
+
+        let expired = fixtures::expired_intermediate_ca();
+        let err = expired.sign(&fixtures::leaf_csr("web", &[]), 30).unwrap_err();
+        assert!(matches!(err, FluxError::CaExpired(_, ref date) if date.starts_with("2025-07-01")), "{}", err);
+        assert!(err.to_string().contains("ca renew-intermediate"));
+    }
+
+    #[test]
+    fn test_validity_never_outlasts_ca() {
+        use crate::ca::CertSigner;
+
+        let ca = fixtures::intermediate_ca();
+        let ca_not_after = asn1_time_to_chrono(ca.cert().not_after()).unwrap();
+        let days = u32::try_from((ca_not_after - chrono::Utc::now()).num_days()).unwrap() + 30;
+
+        let cert = ca.sign(&fixtures::leaf_csr("web", &[]), days).unwrap();
+        assert!(asn1_time_to_chrono(cert.not_after()).unwrap() <= ca_not_after);
+        assert_eq!(clamp_validity(ca.cert(), Validity::days(30), CaExpiryPolicy::Fail).unwrap(), Validity::days(30));
+
+        let strict = ca.with_expiry_policy(CaExpiryPolicy::Fail);
+        let err = strict.sign(&fixtures::leaf_csr("web", &[]), days).unwrap_err();
+        assert!(matches!(err, FluxError::ValidityExceedsCa(v, _) if v == Validity::days(days)), "{}", err);
+    }
+
+    #[test]
+    fn test_signing_policy() {
+        use crate::ca::CertSigner;
+        use crate::crypto::SanEntry;
 
-    // Note: These tests would require a real CA setup
-    // For now, we'll skip them in the test environment
+        let ca = fixtures::intermediate_ca().with_policy(PolicyConfig {
+            allowed_domains: vec!["lab.internal".to_string()],
+            max_validity: Some(Validity::days(90)),
+            ..PolicyConfig::default()
+        });
+        let inside = [SanEntry::Dns("nas.lab.internal".to_string())];
+        ca.sign(&fixtures::leaf_csr("nas.lab.internal", &inside), 90).unwrap();
+
+        // Replacement SANs are checked, not the CSR's, and both rules are reported
+        let outside = [SanEntry::Dns("nas.example.com".to_string())];
+        let err = ca.sign_with_sans(&fixtures::leaf_csr("nas", &inside), &outside, Validity::days(365)).unwrap_err();
+        let FluxError::PolicyViolation(violations) = err else { panic!("{}", err) };
+        let rules: Vec<&str> = violations.iter().map(|v| v.rule.as_str()).collect();
+        assert_eq!(rules, ["allowed_domains", "allowed_domains", "max_validity"]);
+    }
+
+    #[test]
+    fn test_serial_source() {
+        use crate::ca::CertSigner;
+        use crate::ca::serial::SequentialSerial;
+
+        let dir = tempfile::tempdir().unwrap();
+        let ca = fixtures::intermediate_ca().with_serial_source(Box::new(SequentialSerial::new(dir.path().join("flux-serial"))));
+        let serial = |cert: X509| cert.serial_number().to_bn().unwrap().to_hex_str().unwrap().to_string();
+
+        assert_eq!(ca.serial_source().name(), "sequential");
+        assert_eq!(serial(ca.sign(&fixtures::leaf_csr("a", &[]), 30).unwrap()), "01");
+        assert_eq!(serial(ca.sign(&fixtures::leaf_csr("b", &[]), 30).unwrap()), "02");
+    }
+
+    #[test]
+    fn test_sub_ca_name_constraints() {
+        use crate::ca::CertSigner;
+        use crate::config::SubCaConfig;
+        use crate::crypto::{verify_chain, SanEntry};
+
+        let sub_ca = SubCaConfig {
+            permitted_dns: vec!["guest.lab".to_string()],
+            permitted_ips: vec!["10.20.0.0/16".to_string()],
+            ..Default::default()
+        };
+        let root = IntermediateCA::from_parts(fixtures::root_ca_cert(), fixtures::root_key())
+            .with_sub_ca(SubCaExtensions { pathlen: 0, name_constraints: NameConstraints::from_config(&sub_ca).unwrap() });
+
+        let guest_cert = root.sign_sub_ca(&fixtures::ca_csr("Guest CA"), Validity::days(365), root.sub_ca()).unwrap();
+        let text = String::from_utf8(guest_cert.to_text().unwrap()).unwrap();
+        assert!(text.contains("X509v3 Name Constraints: critical"), "{}", text);
+        let guest = IntermediateCA::from_parts(guest_cert.clone(), fixtures::leaf_key());
+
+        let verify = |name: &str, san: SanEntry| {
+            let leaf = guest.sign(&fixtures::leaf_csr(name, &[san]), 30).unwrap();
+            verify_chain(&leaf, &[fixtures::root_ca_cert()], std::slice::from_ref(&guest_cert)).unwrap()
+        };
+        assert!(verify("wifi.guest.lab", SanEntry::Dns("wifi.guest.lab".to_string())).trusted);
+        assert!(verify("printer", SanEntry::Ip("10.20.1.5".to_string())).trusted);
+        let outside = verify("nas.lab", SanEntry::Dns("nas.lab".to_string()));
+        assert!(!outside.trusted);
+        assert!(outside.error.unwrap().contains("subtree"));
+
+        // Leaf signing never makes a CA, whatever the CSR asks for
+        let err = root.sign(&fixtures::ca_csr("Guest CA"), 30).unwrap_err();
+        assert!(matches!(err, FluxError::PolicyViolation(_)), "{}", err);
+        let leaf = root.sign(&fixtures::leaf_csr("web", &[]), 30).unwrap();
+        assert!(!String::from_utf8(leaf.to_text().unwrap()).unwrap().contains("Name Constraints"));
+    }
+
+    #[test]
+    fn test_openssl_ca_database() {
+        use crate::ca::CertSigner;
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("index.txt"), "").unwrap();
+        std::fs::write(dir.path().join("serial"), "1000\n").unwrap();
+        let mut config = Config::default();
+        config.openssl_ca.enabled = true;
+        config.openssl_ca.dir = Some(dir.path().to_path_buf());
+
+        let ca = fixtures::intermediate_ca()
+            .with_serial_source(serial_source(&config))
+            .with_openssl_db(OpensslDb::from_config(&config));
+        let cert = ca.sign(&fixtures::leaf_csr("web", &[]), 30).unwrap();
+
+        assert_eq!(cert.serial_number().to_bn().unwrap().to_hex_str().unwrap().to_string(), "1000");
+        assert_eq!(std::fs::read_to_string(dir.path().join("serial")).unwrap(), "1001\n");
+        let index = std::fs::read_to_string(dir.path().join("index.txt")).unwrap();
+        assert!(index.ends_with("\t\t1000\tunknown\t/CN=web\n"), "{}", index);
+    }
+
+    #[test]
+    fn test_sub_day_validity() {
+        use crate::ca::CertSigner;
+
+        let cert = fixtures::intermediate_ca().sign_for(&fixtures::leaf_csr("ci", &[]), "12h".parse().unwrap()).unwrap();
+        let not_before = asn1_time_to_chrono(cert.not_before()).unwrap();
+        let not_after = asn1_time_to_chrono(cert.not_after()).unwrap();
+        assert!(((not_after - not_before).num_seconds() - 12 * 3_600).abs() <= 1);
+    }
+
+    #[test]
+    fn test_not_before() {
+        use crate::ca::CertSigner;
+        use chrono::{Duration, Utc};
+
+        let csr = fixtures::leaf_csr("web", &[]);
+        let ca = fixtures::intermediate_ca().with_not_before(NotBefore::Backdate(60));
+        let cert = ca.sign(&csr, 30).unwrap();
+        let not_before = asn1_time_to_chrono(cert.not_before()).unwrap();
+        let not_after = asn1_time_to_chrono(cert.not_after()).unwrap();
+        assert!((not_before - (Utc::now() - Duration::minutes(60))).num_seconds().abs() < 60);
+        assert!((not_after - (Utc::now() + Duration::days(30))).num_seconds().abs() < 60);
+
+        // A fixed start only moves the start
+        let at = chrono::DateTime::from_timestamp(Utc::now().timestamp(), 0).unwrap() - Duration::days(3);
+        let cert = ca.sign_starting(&csr, Validity::days(30), NotBefore::At(at)).unwrap();
+        assert_eq!(asn1_time_to_chrono(cert.not_before()).unwrap(), at);
+        assert_eq!(asn1_time_to_chrono(cert.not_after()).unwrap().date_naive(), not_after.date_naive());
+
+        let late = NotBefore::At(Utc::now() + Duration::days(31));
+        assert!(matches!(ca.sign_starting(&csr, Validity::days(30), late), Err(FluxError::CertSigningFailed(_))));
+    }
 }
@@ -1,5 +1,15 @@
 //! Certificate Authority module
 
 pub mod intermediate;
+pub mod signer;
+pub mod renew;
+pub mod backup;
+pub mod serial;
+pub mod openssl_db;
+#[cfg(unix)]
+pub mod agent;
 
-pub use intermediate::IntermediateCA;
+pub use intermediate::{IntermediateCA, ca_chain, chain_pem, ensure_not_expired, ChainPart};
+pub use signer::{CertSigner, load_signer};
+pub use serial::{serial_source, Serial, SerialSource};
+pub use openssl_db::OpensslDb;
@@ -0,0 +1,252 @@
+//! The database kept by `openssl ca`
+//!
+//! With `[openssl_ca]` enabled, certificates signed here are appended to
+//! `index.txt` and revocations are marked there, in the format `openssl ca`
+//! reads, so both tools can work on the same CA directory. Serials come from
+//! the shared `serial` file (see [`super::serial::serial_source`]).
+//! `crlnumber` belongs to `openssl ca -gencrl` and is left alone.
+
+use crate::config::Config;
+use crate::crypto::cert::asn1_time_to_chrono;
+use crate::crypto::revocation::normalize_serial;
+use crate::error::{FluxError, Result};
+use crate::fsutil::{read_to_string_bounded, write_atomic, FileLock, PUBLIC_FILE_MODE};
+use chrono::{DateTime, Datelike, Utc};
+use openssl::x509::{X509, X509NameRef};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Serializes read-modify-write cycles on `index.txt` within this process;
+/// `index.txt.lock` does so across processes
+static INDEX_LOCK: Mutex<()> = Mutex::new(());
+
+/// An OpenSSL CA database directory
+#[derive(Debug, Clone)]
+pub struct OpensslDb {
+    dir: PathBuf,
+}
+
+impl OpensslDb {
+    /// Database in `dir`
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// The configured database, if `[openssl_ca]` is enabled
+    pub fn from_config(config: &Config) -> Option<Self> {
+        config.openssl_ca.enabled.then(|| Self::new(config.openssl_ca_dir()))
+    }
+
+    /// Path of `index.txt`
+    pub fn index_path(&self) -> PathBuf {
+        self.dir.join("index.txt")
+    }
+
+    /// Path of the `serial` file
+    pub fn serial_path(&self) -> PathBuf {
+        self.dir.join("serial")
+    }
+
+    /// Append a signed certificate to `index.txt` as valid
+    pub fn record_issued(&self, cert: &X509) -> Result<()> {
+        let serial = cert.serial_number().to_bn()?.to_hex_str()?.to_string();
+        let subject = oneline(cert.subject_name());
+        let line = [
+            "V".to_string(),
+            index_time(asn1_time_to_chrono(cert.not_after())?),
+            String::new(),
+            serial,
+            "unknown".to_string(),
+            subject.clone(),
+        ].join("\t");
+
+        let _guard = INDEX_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let _lock = FileLock::acquire(&self.index_path())?;
+        let mut index = self.read_index()?;
+
+        if self.unique_subject()? && index.lines().any(|l| is_valid_for(l, &subject)) {
+            tracing::warn!(
+                "{} already has a valid certificate for {}; `openssl ca` will refuse this database \
+                 unless index.txt.attr sets unique_subject = no",
+                self.index_path().display(), subject
+            );
+        }
+
+        if !index.is_empty() && !index.ends_with('\n') {
+            index.push('\n');
+        }
+        index.push_str(&line);
+        index.push('\n');
+        write_atomic(&self.index_path(), index.as_bytes(), PUBLIC_FILE_MODE)
+    }
+
+    /// Mark certificates revoked in `index.txt`, at `when` for `reason`
+    ///
+    /// Returns how many entries changed. Serials not in the database, or
+    /// already revoked there, are left alone.
+    pub fn revoke(&self, serials: &[String], reason: &str, when: DateTime<Utc>) -> Result<usize> {
+        let serials: Vec<String> = serials.iter().map(|s| normalize_serial(s)).collect();
+        let revocation = match reason {
+            "" | "unspecified" => index_time(when),
+            reason => format!("{},{}", index_time(when), reason),
+        };
+
+        let _guard = INDEX_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let _lock = FileLock::acquire(&self.index_path())?;
+        let index = self.read_index()?;
+        let mut changed = 0;
+
+        let lines: Vec<String> = index.lines()
+            .map(|line| {
+                let mut fields: Vec<&str> = line.split('\t').collect();
+                if fields.len() >= 6 && fields[0] == "V" && serials.contains(&normalize_serial(fields[3])) {
+                    fields[0] = "R";
+                    fields[2] = &revocation;
+                    changed += 1;
+                }
+                fields.join("\t")
+            })
+            .collect();
+
+        if changed > 0 {
+            let mut data = lines.join("\n");
+            data.push('\n');
+            write_atomic(&self.index_path(), data.as_bytes(), PUBLIC_FILE_MODE)?;
+        }
+        Ok(changed)
+    }
+
+    fn read_index(&self) -> Result<String> {
+        let path = self.index_path();
+        if !path.exists() {
+            return Err(FluxError::FileReadFailed(path, "OpenSSL CA database not found".to_string()));
+        }
+        read_to_string_bounded(&path)
+    }
+
+    /// Whether `index.txt.attr` asks for unique subjects (OpenSSL's default)
+    fn unique_subject(&self) -> Result<bool> {
+        let attr = attr_path(&self.index_path());
+        if !attr.exists() {
+            return Ok(true);
+        }
+        Ok(read_to_string_bounded(&attr)?
+            .lines()
+            .filter_map(|l| l.split_once('='))
+            .find(|(key, _)| key.trim() == "unique_subject")
+            .is_none_or(|(_, value)| !matches!(value.trim(), "no" | "n" | "false" | "0")))
+    }
+}
+
+fn attr_path(index: &Path) -> PathBuf {
+    let mut path = index.as_os_str().to_owned();
+    path.push(".attr");
+    PathBuf::from(path)
+}
+
+fn is_valid_for(line: &str, subject: &str) -> bool {
+    let fields: Vec<&str> = line.split('\t').collect();
+    fields.len() >= 6 && fields[0] == "V" && fields[5] == subject
+}
+
+/// A time as `index.txt` stores it: UTCTime before 2050, GeneralizedTime after
+fn index_time(time: DateTime<Utc>) -> String {
+    if time.year() < 2050 {
+        time.format("%y%m%d%H%M%SZ").to_string()
+    } else {
+        time.format("%Y%m%d%H%M%SZ").to_string()
+    }
+}
+
+/// A name in OpenSSL's one-line form (`/C=US/O=Lab/CN=web`)
+fn oneline(name: &X509NameRef) -> String {
+    name.entries()
+        .map(|entry| {
+            let key = entry.object().nid().short_name().unwrap_or("UNKNOWN");
+            let value = entry.data().as_utf8().map(|s| s.to_string()).unwrap_or_default();
+            format!("/{}={}", key, value)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ca::CertSigner;
+    use crate::crypto::revocation::{check_index, RevocationState};
+    use crate::fixtures;
+
+    #[test]
+    fn test_openssl_db() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = OpensslDb::new(dir.path());
+        // A line left by `openssl ca`
+        let legacy = "V\t270101000000Z\t\t1000\tunknown\t/CN=legacy\n";
+        std::fs::write(db.index_path(), legacy).unwrap();
+
+        let ca = fixtures::intermediate_ca();
+        let cert = ca.sign(&fixtures::leaf_csr("web", &[]), 30).unwrap();
+        db.record_issued(&cert).unwrap();
+
+        let index = std::fs::read_to_string(db.index_path()).unwrap();
+        let line = index.lines().nth(1).unwrap();
+        let serial = cert.serial_number().to_bn().unwrap().to_hex_str().unwrap().to_string();
+        assert!(index.starts_with(legacy));
+        assert!(line.starts_with("V\t"), "{}", line);
+        assert!(line.ends_with(&format!("\t\t{}\tunknown\t/CN=web", serial)), "{}", line);
+        assert_eq!(check_index(&cert, &index, Some(ca.cert())).unwrap().state, RevocationState::Good);
+
+        let when = DateTime::parse_from_rfc3339("2026-10-17T12:00:00Z").unwrap().with_timezone(&Utc);
+        assert_eq!(db.revoke(&[serial.to_lowercase()], "keyCompromise", when).unwrap(), 1);
+        assert_eq!(db.revoke(&[serial], "keyCompromise", when).unwrap(), 0);
+
+        let index = std::fs::read_to_string(db.index_path()).unwrap();
+        let status = check_index(&cert, &index, Some(ca.cert())).unwrap();
+        assert_eq!(status.state, RevocationState::Revoked);
+        assert_eq!(status.reason.as_deref(), Some("keyCompromise"));
+        assert_eq!(status.revoked_at, Some(when));
+        assert!(index.starts_with(legacy));
+    }
+
+    #[test]
+    fn test_index_waits_for_other_processes() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = OpensslDb::new(dir.path());
+        std::fs::write(db.index_path(), "").unwrap();
+
+        // Another process in the middle of an update
+        let lock = FileLock::acquire(&db.index_path()).unwrap();
+        let cert = fixtures::intermediate_ca().sign(&fixtures::leaf_csr("web", &[]), 30).unwrap();
+        let waiting = {
+            let db = db.clone();
+            std::thread::spawn(move || db.record_issued(&cert).unwrap())
+        };
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        assert!(!waiting.is_finished());
+        let legacy = "V\t270101000000Z\t\t1000\tunknown\t/CN=legacy\n";
+        std::fs::write(db.index_path(), legacy).unwrap();
+        drop(lock);
+
+        waiting.join().unwrap();
+        let index = std::fs::read_to_string(db.index_path()).unwrap();
+        assert!(index.starts_with(legacy), "{}", index);
+        assert_eq!(index.lines().count(), 2);
+    }
+
+    #[test]
+    fn test_index_time() {
+        let time = |s: &str| DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc);
+        assert_eq!(index_time(time("2027-03-01T08:30:00Z")), "270301083000Z");
+        assert_eq!(index_time(time("2051-03-01T08:30:00Z")), "20510301083000Z");
+    }
+
+    #[test]
+    fn test_unique_subject() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = OpensslDb::new(dir.path());
+        assert!(db.unique_subject().unwrap());
+
+        std::fs::write(dir.path().join("index.txt.attr"), "unique_subject = no\n").unwrap();
+        assert!(!db.unique_subject().unwrap());
+    }
+}
@@ -0,0 +1,283 @@
+//! Recovery for an expired intermediate CA
+//!
+//! Signing refuses an expired intermediate (see [`super::ensure_not_expired`]), while
+//! everything that only reads the inventory (`status`, `check`, `info`,
+//! `metrics`) keeps working. `ca renew-intermediate` re-certifies the existing
+//! intermediate key with the root CA: same subject, key and key identifier,
+//! new serial and validity. Certificates issued before the expiry chain to the
+//! new certificate as well, so only the CA chain has to be redeployed.
+//!
+//! Basic constraints (with the old path length, unless `--pathlen` sets a
+//! new one), key usage and the key identifiers are written anew; other extensions of the old certificate,
+//! such as CRL distribution points, are not carried over.
+
+use crate::config::Config;
+use crate::crypto::cert::asn1_time_to_chrono;
+use crate::crypto::{cert_to_pem, check_pathlen, format_name, is_key_encrypted, load_cert, load_private_key};
+use crate::crypto::key::prompt_password;
+use crate::error::{FluxError, Result};
+use crate::fsutil::{write_atomic, PUBLIC_FILE_MODE};
+use chrono::{DateTime, Duration, Utc};
+use openssl::asn1::{Asn1Object, Asn1OctetString, Asn1Time};
+use openssl::bn::{BigNum, MsbOption};
+use openssl::hash::MessageDigest;
+use openssl::pkey::{PKey, Private};
+use openssl::x509::extension::{AuthorityKeyIdentifier, BasicConstraints, KeyUsage, SubjectKeyIdentifier};
+use openssl::x509::{X509, X509Builder, X509Extension};
+use secrecy::ExposeSecret;
+use std::path::{Path, PathBuf};
+
+/// OID of the subject key identifier extension
+const SUBJECT_KEY_IDENTIFIER_OID: &str = "2.5.29.14";
+
+/// Validity of one CA certificate
+#[derive(Debug, Clone)]
+pub struct CaCertStatus {
+    /// "intermediate" or "root"
+    pub role: &'static str,
+    pub subject: String,
+    pub not_after: DateTime<Utc>,
+}
+
+impl CaCertStatus {
+    fn new(role: &'static str, cert: &X509) -> Result<Self> {
+        Ok(Self {
+            role,
+            subject: format_name(cert.subject_name()),
+            not_after: asn1_time_to_chrono(cert.not_after())?,
+        })
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.not_after <= Utc::now()
+    }
+
+    pub fn days_remaining(&self) -> i64 {
+        (self.not_after - Utc::now()).num_days()
+    }
+}
+
+/// The intermediate CA certificate, followed by the root's if it is present
+pub fn ca_status(config: &Config) -> Result<Vec<CaCertStatus>> {
+    let mut status = vec![CaCertStatus::new("intermediate", &load_cert(&config.ca_cert_path)?)?];
+    let root = config.root_ca_cert_path();
+    if root.exists() {
+        status.push(CaCertStatus::new("root", &load_cert(&root)?)?);
+    }
+    Ok(status)
+}
+
+/// Load the root CA key, prompting for its password if it is encrypted
+pub fn load_root_key(path: &Path) -> Result<PKey<Private>> {
+    if !path.exists() {
+        return Err(FluxError::CaKeyNotFound(path.to_path_buf()));
+    }
+    if is_key_encrypted(path)? {
+        let password = prompt_password("Enter root CA private key password")?;
+        load_private_key(path, Some(password.expose_secret())).map_err(|_| FluxError::CaKeyUnlockFailed)
+    } else {
+        load_private_key(path, None)
+    }
+}
+
+/// A new certificate for the intermediate's key, signed by the root
+///
+/// Valid for `days` from now, but never past the root's own expiry. The path
+/// length is `pathlen` if given, otherwise the current certificate's; either
+/// way it has to fit under the root's.
+pub fn recertify(current: &X509, root_cert: &X509, root_key: &PKey<Private>, days: u32, pathlen: Option<u32>) -> Result<X509> {
+    let failed = |msg: String| FluxError::CaRenewalFailed(msg);
+
+    let root_public = root_cert.public_key()?;
+    if !root_public.public_eq(root_key) {
+        return Err(failed("the root CA key does not belong to the root CA certificate".to_string()));
+    }
+    if !current.verify(&root_public)? {
+        return Err(failed(format!(
+            "{} was not issued by {}",
+            format_name(current.subject_name()),
+            format_name(root_cert.subject_name())
+        )));
+    }
+    let root_status = CaCertStatus::new("root", root_cert)?;
+    if root_status.is_expired() {
+        return Err(failed(format!(
+            "the root CA {} expired on {} as well; a new root is needed",
+            root_status.subject,
+            root_status.not_after.format("%Y-%m-%d")
+        )));
+    }
+
+    let pathlen = pathlen.or_else(|| current.pathlen());
+    check_pathlen(root_cert, pathlen).map_err(|e| failed(e.to_string()))?;
+
+    let not_after = (Utc::now() + Duration::days(i64::from(days))).min(root_status.not_after);
+
+    let mut builder = X509Builder::new()?;
+    builder.set_version(2)?;
+    let mut serial = BigNum::new()?;
+    serial.rand(159, MsbOption::MAYBE_ZERO, false)?;
+    let serial = serial.to_asn1_integer()?;
+    builder.set_serial_number(&serial)?;
+    builder.set_subject_name(current.subject_name())?;
+    builder.set_issuer_name(root_cert.subject_name())?;
+    builder.set_pubkey(current.public_key()?.as_ref())?;
+    let (start, end) = (Asn1Time::days_from_now(0)?, Asn1Time::from_unix(not_after.timestamp())?);
+    builder.set_not_before(&start)?;
+    builder.set_not_after(&end)?;
+
+    let mut constraints = BasicConstraints::new();
+    constraints.critical().ca();
+    if let Some(pathlen) = pathlen {
+        constraints.pathlen(pathlen);
+    }
+    builder.append_extension(constraints.build()?)?;
+    builder.append_extension(KeyUsage::new().critical().digital_signature().key_cert_sign().crl_sign().build()?)?;
+
+    // Keep the old key identifier, which issued certificates refer to
+    let ski = match current.subject_key_id() {
+        Some(id) => subject_key_id_extension(id.as_slice())?,
+        None => SubjectKeyIdentifier::new().build(&builder.x509v3_context(Some(root_cert), None))?,
+    };
+    builder.append_extension(ski)?;
+    let aki = AuthorityKeyIdentifier::new().keyid(false).build(&builder.x509v3_context(Some(root_cert), None))?;
+    builder.append_extension(aki)?;
+
+    builder.sign(root_key, MessageDigest::sha256())?;
+    Ok(builder.build())
+}
+
+/// Subject key identifier extension with a given key ID
+fn subject_key_id_extension(id: &[u8]) -> Result<X509Extension> {
+    let len = u8::try_from(id.len())
+        .ok()
+        .filter(|len| *len < 0x80)
+        .ok_or_else(|| FluxError::CaRenewalFailed("subject key identifier is too long".to_string()))?;
+
+    // The extension value is the DER of an OCTET STRING holding the ID
+    let mut der = vec![0x04, len];
+    der.extend_from_slice(id);
+    let oid = Asn1Object::from_str(SUBJECT_KEY_IDENTIFIER_OID)?;
+    let value = Asn1OctetString::new_from_bytes(&der)?;
+    Ok(X509Extension::new_from_der(&oid, false, &value)?)
+}
+
+/// Combined intermediate and root certificate in the standard layout
+fn chain_path(config: &Config) -> PathBuf {
+    config.working_dir.join("intermediate").join("certs").join("ca-chain.cert.pem")
+}
+
+/// Put `cert` in place of the intermediate CA certificate
+///
+/// The old certificate is kept as `<ca_cert_path>.<timestamp>.bak`, which is
+/// returned. An existing `intermediate/certs/ca-chain.cert.pem` is rewritten
+/// with the new certificate followed by the root.
+pub fn install(config: &Config, cert: &X509) -> Result<PathBuf> {
+    let cert_path = &config.ca_cert_path;
+    let backup = PathBuf::from(format!("{}.{}.bak", cert_path.display(), Utc::now().format("%Y%m%dT%H%M%SZ")));
+    std::fs::copy(cert_path, &backup)
+        .map_err(|e| FluxError::FileWriteFailed(backup.clone(), e.to_string()))?;
+
+    let pem = cert_to_pem(cert)?;
+    replace_file(cert_path, &pem)?;
+
+    let chain = chain_path(config);
+    let root = config.root_ca_cert_path();
+    if chain.exists() && root.exists() {
+        let mut contents = pem;
+        contents.extend(cert_to_pem(&load_cert(&root)?)?);
+        replace_file(&chain, &contents)?;
+    }
+
+    Ok(backup)
+}
+
+/// Atomically replace `path` with `contents`, keeping its permissions
+fn replace_file(path: &Path, contents: &[u8]) -> Result<()> {
+    #[cfg(unix)]
+    let mode = {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::metadata(path).map_or(PUBLIC_FILE_MODE, |meta| meta.permissions().mode() & 0o7777)
+    };
+    #[cfg(not(unix))]
+    let mode = PUBLIC_FILE_MODE;
+    write_atomic(path, contents, mode)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ca::{ensure_not_expired, CertSigner, IntermediateCA};
+    use crate::crypto::{save_cert_pem, verify_chain};
+    use crate::fixtures;
+
+    #[test]
+    fn test_recertify_keeps_issued_certificates_valid() {
+        let expired = fixtures::expired_intermediate_ca();
+        let root = fixtures::root_ca_cert();
+        let leaf = fixtures::intermediate_ca().sign(&fixtures::leaf_csr("web", &[]), 30).unwrap();
+        assert!(ensure_not_expired(expired.cert()).is_err());
+
+        let renewed = recertify(expired.cert(), &root, &fixtures::root_key(), 365, None).unwrap();
+        ensure_not_expired(&renewed).unwrap();
+        assert_eq!(format_name(renewed.subject_name()), format_name(expired.cert().subject_name()));
+        assert_eq!(renewed.subject_key_id().unwrap().as_slice(), expired.cert().subject_key_id().unwrap().as_slice());
+        assert_eq!(renewed.pathlen(), Some(0));
+
+        // A leaf signed with the same key validates against the new certificate
+        assert!(verify_chain(&leaf, std::slice::from_ref(&root), std::slice::from_ref(&renewed)).unwrap().trusted);
+        let signer = IntermediateCA::from_parts(renewed, fixtures::intermediate_key());
+        assert!(signer.sign(&fixtures::leaf_csr("web", &[]), 30).is_ok());
+
+        // Only the root that issued it can re-certify it
+        let err = recertify(expired.cert(), &root, &fixtures::intermediate_key(), 365, None).unwrap_err();
+        assert!(matches!(err, FluxError::CaRenewalFailed(_)));
+    }
+
+    #[test]
+    fn test_recertify_pathlen() {
+        use crate::crypto::SubCaExtensions;
+        use crate::duration::Validity;
+
+        let current = fixtures::intermediate_ca();
+        let root = fixtures::root_ca_cert();
+
+        // The fixture intermediate (pathlen:0) can't sign sub-CAs
+        let err = current.sign_sub_ca(&fixtures::ca_csr("Guest CA"), Validity::days(30), current.sub_ca()).unwrap_err();
+        assert!(err.to_string().contains("pathlen:0"), "{}", err);
+
+        let renewed = recertify(current.cert(), &root, &fixtures::root_key(), 365, Some(1)).unwrap();
+        assert_eq!(renewed.pathlen(), Some(1));
+        let signer = IntermediateCA::from_parts(renewed, fixtures::intermediate_key());
+        let sub_ca = signer.sign_sub_ca(&fixtures::ca_csr("Guest CA"), Validity::days(30), signer.sub_ca()).unwrap();
+        assert_eq!(sub_ca.pathlen(), Some(0));
+
+        // A sub-CA may not reach as far as its issuer
+        let sub_ca = SubCaExtensions { pathlen: 1, ..Default::default() };
+        let err = signer.sign_sub_ca(&fixtures::ca_csr("Guest CA"), Validity::days(30), &sub_ca).unwrap_err();
+        assert!(err.to_string().contains("use at most 0"), "{}", err);
+    }
+
+    #[test]
+    fn test_install_keeps_backup() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let config = Config {
+            working_dir: temp_dir.path().to_path_buf(),
+            ca_cert_path: temp_dir.path().join("intermediate.cert.pem"),
+            ..Config::default()
+        };
+        let expired = fixtures::expired_intermediate_ca();
+        save_cert_pem(expired.cert(), &config.ca_cert_path).unwrap();
+        std::fs::create_dir_all(temp_dir.path().join("certs")).unwrap();
+        save_cert_pem(&fixtures::root_ca_cert(), config.root_ca_cert_path()).unwrap();
+        assert!(ca_status(&config).unwrap()[0].is_expired());
+
+        let renewed = recertify(expired.cert(), &fixtures::root_ca_cert(), &fixtures::root_key(), 30, None).unwrap();
+        let backup = install(&config, &renewed).unwrap();
+
+        assert_eq!(load_cert(&backup).unwrap().to_der().unwrap(), expired.cert().to_der().unwrap());
+        let status = ca_status(&config).unwrap();
+        assert_eq!(status.iter().map(|s| s.role).collect::<Vec<_>>(), ["intermediate", "root"]);
+        assert!(!status[0].is_expired() && status[0].days_remaining() >= 29);
+    }
+}
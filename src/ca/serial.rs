@@ -0,0 +1,303 @@
+//! Serial numbers for signed certificates
+//!
+//! `serial_source` in the configuration picks where serials come from:
+//! random values with no record kept (the default), a counter in the working
+//! directory, or random values checked against and recorded in a ledger.
+
+use crate::config::{Config, SerialSourceKind};
+use crate::error::{FluxError, Result};
+use crate::fsutil::{read_to_string_bounded, write_atomic, FileLock, PUBLIC_FILE_MODE};
+use crate::store::CertStore;
+use chrono::{DateTime, Utc};
+use openssl::bn::{BigNum, MsbOption};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// Serializes allocations from the counter and ledger files within this
+/// process; a [`FileLock`] does so across processes
+static SERIAL_LOCK: Mutex<()> = Mutex::new(());
+
+/// Attempts at drawing a random serial the ledger hasn't seen
+const MAX_DRAWS: usize = 16;
+
+/// Certificate serial number (a positive integer, big-endian)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Serial(Vec<u8>);
+
+impl Serial {
+    /// 159 random bits
+    ///
+    /// The top bit of the 20-byte maximum (RFC 5280) stays clear so the
+    /// serial never encodes as a negative number or longer than 20 bytes.
+    pub fn random() -> Result<Self> {
+        let mut bn = BigNum::new().map_err(failed)?;
+        bn.rand(159, MsbOption::MAYBE_ZERO, false).map_err(failed)?;
+        Self::from_bn(&bn)
+    }
+
+    /// Parse a hexadecimal serial, as printed by `info` and OpenSSL
+    pub fn from_hex(hex: &str) -> Result<Self> {
+        let invalid = || FluxError::CertSigningFailed(format!("invalid serial number '{}'", hex));
+        let hex = hex.trim();
+        if hex.is_empty() || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+        Self::from_bn(&BigNum::from_hex_str(hex).map_err(|_| invalid())?)
+    }
+
+    fn from_bn(bn: &BigNum) -> Result<Self> {
+        if bn.is_negative() || bn.num_bytes() > 20 {
+            return Err(FluxError::CertSigningFailed(format!("serial number {} is out of range", bn)));
+        }
+        Ok(Self(bn.to_vec()))
+    }
+
+    fn to_bn(&self) -> Result<BigNum> {
+        BigNum::from_slice(&self.0).map_err(failed)
+    }
+
+    /// Big-endian bytes, without leading zeros
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// The next serial in sequence
+    pub fn next(&self) -> Result<Self> {
+        let mut bn = self.to_bn()?;
+        bn.add_word(1).map_err(failed)?;
+        Self::from_bn(&bn)
+    }
+}
+
+impl fmt::Display for Serial {
+    /// Uppercase hex, the form recorded in the certificate store
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hex = self.to_bn().and_then(|bn| bn.to_hex_str().map(|s| s.to_string()).map_err(failed));
+        match hex {
+            Ok(hex) => f.write_str(&hex),
+            Err(_) => Err(fmt::Error),
+        }
+    }
+}
+
+fn failed(e: openssl::error::ErrorStack) -> FluxError {
+    FluxError::CertSigningFailed(e.to_string())
+}
+
+/// Where the serial numbers of signed certificates come from
+pub trait SerialSource: Send + Sync {
+    /// Name for diagnostics
+    fn name(&self) -> &'static str;
+
+    /// Serial for the next certificate
+    ///
+    /// A serial handed out is used up even if signing then fails.
+    fn next_serial(&self) -> Result<Serial>;
+}
+
+/// Serial source for a configuration
+///
+/// With `[openssl_ca]` enabled, serials always come from its `serial` file.
+pub fn serial_source(config: &Config) -> Box<dyn SerialSource> {
+    if config.openssl_ca.enabled {
+        return Box::new(SequentialSerial::new(config.openssl_ca_dir().join("serial")));
+    }
+    match config.serial_source {
+        SerialSourceKind::Random => Box::new(RandomSerial),
+        SerialSourceKind::Sequential => Box::new(SequentialSerial::new(config.serial_counter_path())),
+        SerialSourceKind::Database => Box::new(DatabaseSerial::new(config.serial_ledger_path(), config.store_path())),
+    }
+}
+
+/// Random 159-bit serials, with no record kept (the default)
+pub struct RandomSerial;
+
+impl SerialSource for RandomSerial {
+    fn name(&self) -> &'static str {
+        "random"
+    }
+
+    fn next_serial(&self) -> Result<Serial> {
+        Serial::random()
+    }
+}
+
+/// Serials counting up from 1, kept in a counter file
+///
+/// The file holds the next serial in hex, like OpenSSL's `serial` file.
+pub struct SequentialSerial {
+    path: PathBuf,
+}
+
+impl SequentialSerial {
+    /// Counter kept at `path`
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl SerialSource for SequentialSerial {
+    fn name(&self) -> &'static str {
+        "sequential"
+    }
+
+    fn next_serial(&self) -> Result<Serial> {
+        let _guard = SERIAL_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let _lock = FileLock::acquire(&self.path)?;
+
+        let serial = if self.path.exists() {
+            Serial::from_hex(&read_to_string_bounded(&self.path)?)
+                .map_err(|_| FluxError::StoreError(format!("{}: not a hex serial number", self.path.display())))?
+        } else {
+            Serial::from_hex("01")?
+        };
+
+        // Move the counter on before handing the serial out, so a crash
+        // can skip a serial but never reuse one
+        write_atomic(&self.path, format!("{}\n", serial.next()?).as_bytes(), PUBLIC_FILE_MODE)?;
+        Ok(serial)
+    }
+}
+
+/// Every serial handed out by [`DatabaseSerial`], with when
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct SerialLedger {
+    #[serde(default)]
+    serials: BTreeMap<String, DateTime<Utc>>,
+}
+
+/// Random serials checked against, and recorded in, a ledger
+///
+/// A serial is never handed out twice, nor one already recorded in the
+/// certificate store from before the ledger existed.
+pub struct DatabaseSerial {
+    path: PathBuf,
+    store_path: PathBuf,
+}
+
+impl DatabaseSerial {
+    /// Ledger kept at `path`, also checking the store at `store_path`
+    pub fn new(path: impl Into<PathBuf>, store_path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into(), store_path: store_path.into() }
+    }
+
+    fn open(&self) -> Result<SerialLedger> {
+        if !self.path.exists() {
+            return Ok(SerialLedger::default());
+        }
+        serde_json::from_str(&read_to_string_bounded(&self.path)?)
+            .map_err(|e| FluxError::StoreError(format!("{}: {}", self.path.display(), e)))
+    }
+}
+
+impl SerialSource for DatabaseSerial {
+    fn name(&self) -> &'static str {
+        "database"
+    }
+
+    fn next_serial(&self) -> Result<Serial> {
+        let _guard = SERIAL_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let _lock = FileLock::acquire(&self.path)?;
+
+        let mut ledger = self.open()?;
+        let store = CertStore::open(&self.store_path)?;
+        let taken = |hex: &str| {
+            ledger.serials.contains_key(hex) || store.certificates().iter().any(|c| c.serial.eq_ignore_ascii_case(hex))
+        };
+
+        for _ in 0..MAX_DRAWS {
+            let serial = Serial::random()?;
+            let hex = serial.to_string();
+            if taken(&hex) {
+                continue;
+            }
+
+            ledger.serials.insert(hex, Utc::now());
+            let data = serde_json::to_string_pretty(&ledger)
+                .map_err(|e| FluxError::StoreError(e.to_string()))?;
+            write_atomic(&self.path, data.as_bytes(), PUBLIC_FILE_MODE)?;
+            return Ok(serial);
+        }
+
+        Err(FluxError::CertSigningFailed(format!(
+            "no unused serial number after {} attempts",
+            MAX_DRAWS
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serial_hex() {
+        let serial = Serial::from_hex("0fff").unwrap();
+        assert_eq!(serial.as_bytes(), &[0x0f, 0xff]);
+        assert_eq!(serial.next().unwrap().to_string(), "1000");
+        assert!(Serial::from_hex("").is_err());
+        assert!(Serial::from_hex("-1").is_err());
+        assert!(Serial::from_hex(&"ff".repeat(21)).is_err());
+
+        let random = Serial::random().unwrap();
+        assert!(random.as_bytes().len() <= 20);
+        assert_ne!(random, Serial::random().unwrap());
+    }
+
+    #[test]
+    fn test_sequential_serial() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("flux-serial");
+        let source = SequentialSerial::new(&path);
+
+        assert_eq!(source.next_serial().unwrap().to_string(), "01");
+        assert_eq!(source.next_serial().unwrap().to_string(), "02");
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "03\n");
+
+        // Continues from an existing counter, e.g. OpenSSL's
+        std::fs::write(&path, "10FF\n").unwrap();
+        assert_eq!(source.next_serial().unwrap().to_string(), "10FF");
+        assert_eq!(source.next_serial().unwrap().to_string(), "1100");
+
+        std::fs::write(&path, "not a serial").unwrap();
+        assert!(source.next_serial().is_err());
+    }
+
+    #[test]
+    fn test_serial_waits_for_other_processes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("flux-serial");
+        std::fs::write(&path, "05\n").unwrap();
+
+        // Another process in the middle of an allocation
+        let lock = FileLock::acquire(&path).unwrap();
+        let source = SequentialSerial::new(&path);
+        let waiting = std::thread::spawn(move || source.next_serial().unwrap());
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        assert!(!waiting.is_finished());
+        std::fs::write(&path, "06\n").unwrap();
+        drop(lock);
+
+        assert_eq!(waiting.join().unwrap().to_string(), "06");
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "07\n");
+    }
+
+    #[test]
+    fn test_database_serial() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("flux-serials.json");
+        let source = DatabaseSerial::new(&path, dir.path().join("flux-store.json"));
+
+        let first = source.next_serial().unwrap();
+        let second = source.next_serial().unwrap();
+        assert_ne!(first, second);
+
+        let ledger = source.open().unwrap();
+        assert_eq!(ledger.serials.len(), 2);
+        assert!(ledger.serials.contains_key(&first.to_string()));
+        assert!(ledger.serials.contains_key(&second.to_string()));
+    }
+}
@@ -0,0 +1,138 @@
+//! Certificate signing abstraction over a local CA key or a signing agent
+
+use crate::config::Config;
+use crate::crypto::{cert_from_pem, cert_to_pem, check_wildcards, csr_sans, get_csr_subject, key_to_pem, load_cert, IssueExtensions, NotBefore, SanEntry, SubCaExtensions, ValidityWindow};
+use crate::crypto::check_pathlen;
+use crate::crypto::details::csr_requests_ca;
+use crate::error::{FluxError, Result};
+use crate::policy::{self, Violation};
+use crate::duration::Validity;
+use chrono::Utc;
+use openssl::x509::{X509, X509Req};
+use zeroize::Zeroizing;
+
+use super::intermediate::{clamp_validity, ensure_not_expired};
+use super::IntermediateCA;
+
+/// Something that can sign CSRs on behalf of the intermediate CA
+pub trait CertSigner: Send + Sync {
+    /// CA certificate used as the issuer of signed certificates
+    fn ca_cert(&self) -> &X509;
+
+    /// Sign a CSR, producing a certificate valid for `validity`
+    fn sign_for(&self, csr: &X509Req, validity: Validity) -> Result<X509>;
+
+    /// Sign a CSR, producing a certificate valid for `days` days
+    fn sign(&self, csr: &X509Req, days: u32) -> Result<X509> {
+        self.sign_for(csr, Validity::days(days))
+    }
+}
+
+impl CertSigner for IntermediateCA {
+    fn ca_cert(&self) -> &X509 {
+        self.cert()
+    }
+
+    fn sign_for(&self, csr: &X509Req, validity: Validity) -> Result<X509> {
+        self.sign_inner(csr, validity, self.not_before(), IssueExtensions::default())
+    }
+}
+
+impl IntermediateCA {
+    /// Sign a CSR with `sans` in place of the SANs it requests
+    pub fn sign_with_sans(&self, csr: &X509Req, sans: &[SanEntry], validity: Validity) -> Result<X509> {
+        self.sign_inner(csr, validity, self.not_before(), IssueExtensions { sans: Some(sans), ..Default::default() })
+    }
+
+    /// Sign a CSR whose validity starts at `not_before` rather than the
+    /// CA's default
+    pub fn sign_starting(&self, csr: &X509Req, validity: Validity, not_before: NotBefore) -> Result<X509> {
+        self.sign_inner(csr, validity, not_before, IssueExtensions::default())
+    }
+
+    /// Sign a CSR as a subordinate CA: CA:TRUE with `sub_ca`'s path length
+    /// and name constraints, and keyCertSign/cRLSign key usage
+    ///
+    /// Start from [`IntermediateCA::sub_ca`] for the configured constraints.
+    pub fn sign_sub_ca(&self, csr: &X509Req, validity: Validity, sub_ca: &SubCaExtensions) -> Result<X509> {
+        check_pathlen(self.cert(), Some(sub_ca.pathlen))?;
+        policy::check_sub_ca(self.policy(), &sub_ca.name_constraints)?;
+        if sub_ca.name_constraints.is_empty() {
+            tracing::warn!("Signing a subordinate CA without name constraints; it can issue for any name");
+        } else {
+            tracing::info!("Signing a subordinate CA with name constraints: {}", sub_ca.name_constraints);
+        }
+        self.sign_inner(csr, validity, self.not_before(), IssueExtensions { sub_ca: Some(sub_ca), ..Default::default() })
+    }
+
+    fn sign_inner(&self, csr: &X509Req, validity: Validity, not_before: NotBefore, extensions: IssueExtensions) -> Result<X509> {
+        ensure_not_expired(self.cert())?;
+        // CA certificates only come from sign_sub_ca, never from a CSR's say-so
+        if extensions.sub_ca.is_none() && csr_requests_ca(csr)? {
+            return Err(FluxError::PolicyViolation(Violation::new(
+                "sub_ca",
+                "the CSR asks for a CA certificate; sign it as a subordinate CA (`csr sign --ca`)",
+            ).into()));
+        }
+        let requested = match extensions.sans {
+            Some(sans) => sans.to_vec(),
+            None => csr_sans(csr)?,
+        };
+        check_wildcards(get_csr_subject(csr).ok().as_deref(), &requested, self.policy().allow_wildcards)?;
+        policy::evaluate(self.policy(), csr, &requested, validity)?;
+
+        let length = clamp_validity(self.cert(), validity, self.expiry_policy())?;
+
+        // The end is counted from now, however far back the start is moved
+        let now = Utc::now();
+        let validity = ValidityWindow {
+            not_before: not_before.resolve(now),
+            not_after: now + length.as_duration(),
+        };
+        if validity.not_before >= validity.not_after {
+            return Err(FluxError::CertSigningFailed(format!(
+                "not_before {} is not before the certificate's expiry {}",
+                validity.not_before, validity.not_after
+            )));
+        }
+
+        let csr_pem = csr.to_pem()
+            .map_err(|e| FluxError::CertSigningFailed(e.to_string()))?;
+        let key_pem = Zeroizing::new(key_to_pem(self.key())?);
+
+        let serial = self.serial_source().next_serial()?;
+        let cert_pem = self.backend().sign_csr(&csr_pem, &cert_to_pem(self.cert())?, &key_pem, serial.as_bytes(), validity, extensions)?;
+        let cert = cert_from_pem(&cert_pem)?;
+
+        if let Some(db) = self.openssl_db() {
+            db.record_issued(&cert)?;
+        }
+        Ok(cert)
+    }
+}
+
+/// Load the signer for this configuration
+///
+/// If a signing agent is listening on the configured socket it is used,
+/// otherwise the CA key is loaded (and unlocked) locally. An expired CA
+/// certificate is reported before anything asks for the key password.
+pub fn load_signer(config: &Config) -> Result<Box<dyn CertSigner>> {
+    let ca_cert = load_cert(&config.ca_cert_path)?;
+    ensure_not_expired(&ca_cert)?;
+
+    #[cfg(unix)]
+    if config.agent.enabled && config.agent.socket_path.exists() {
+        match super::agent::AgentClient::connect(&config.agent.socket_path, &ca_cert) {
+            Ok(client) => {
+                let client = client.with_not_before(config.defaults.not_before);
+                tracing::debug!("Using CA signing agent at {}", config.agent.socket_path.display());
+                return Ok(Box::new(client));
+            }
+            Err(e) => {
+                tracing::warn!("Signing agent unavailable, loading CA locally: {}", e);
+            }
+        }
+    }
+
+    Ok(Box::new(IntermediateCA::load(config)?))
+}
@@ -0,0 +1,197 @@
+//! Monitoring checks with Nagios/Icinga plugin semantics
+//!
+//! A check prints one status line and exits 0 (OK), 1 (WARNING),
+//! 2 (CRITICAL) or 3 (UNKNOWN):
+//!
+//! ```text
+//! CERT WARNING - web expires in 20 days (2026-11-06) | 'web'=20;30;7
+//! ```
+
+use crate::crypto::cert::asn1_time_to_chrono;
+use crate::duration::Days;
+use crate::error::{FluxError, Result};
+use crate::store::CertStore;
+use chrono::{DateTime, Utc};
+use openssl::x509::X509;
+use std::fmt;
+
+/// Plugin status, ordered from best to worst
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CheckStatus {
+    Ok,
+    Warning,
+    Critical,
+    Unknown,
+}
+
+impl CheckStatus {
+    /// Process exit code for this status
+    pub fn exit_code(self) -> i32 {
+        match self {
+            CheckStatus::Ok => 0,
+            CheckStatus::Warning => 1,
+            CheckStatus::Critical => 2,
+            CheckStatus::Unknown => 3,
+        }
+    }
+}
+
+impl fmt::Display for CheckStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            CheckStatus::Ok => "OK",
+            CheckStatus::Warning => "WARNING",
+            CheckStatus::Critical => "CRITICAL",
+            CheckStatus::Unknown => "UNKNOWN",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Day thresholds below which a certificate is WARNING or CRITICAL
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Thresholds {
+    pub warning: Days,
+    pub critical: Days,
+}
+
+impl Thresholds {
+    /// Thresholds with the warning window at least as long as the critical one
+    pub fn new(warning: Days, critical: Days) -> Result<Self> {
+        if warning < critical {
+            return Err(FluxError::InvalidConfigValue(
+                "check.warning_days".to_string(),
+                format!("warning threshold ({}) is below the critical threshold ({})", warning, critical),
+            ));
+        }
+
+        Ok(Self { warning, critical })
+    }
+}
+
+/// Expiry check of one certificate
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CertCheck {
+    pub name: String,
+    pub status: CheckStatus,
+    pub not_after: DateTime<Utc>,
+    /// Whole days until expiry (negative once expired)
+    pub days_remaining: i64,
+}
+
+impl CertCheck {
+    /// Classify a certificate by its expiry time
+    pub fn new(name: &str, not_after: DateTime<Utc>, thresholds: Thresholds, now: DateTime<Utc>) -> Self {
+        let days_remaining = (not_after - now).num_days();
+        let status = if not_after <= now || days_remaining < i64::from(thresholds.critical.get()) {
+            CheckStatus::Critical
+        } else if days_remaining < i64::from(thresholds.warning.get()) {
+            CheckStatus::Warning
+        } else {
+            CheckStatus::Ok
+        };
+
+        Self { name: name.to_string(), status, not_after, days_remaining }
+    }
+
+    /// Check a parsed certificate
+    pub fn from_cert(name: &str, cert: &X509, thresholds: Thresholds, now: DateTime<Utc>) -> Result<Self> {
+        Ok(Self::new(name, asn1_time_to_chrono(cert.not_after())?, thresholds, now))
+    }
+
+    fn describe(&self, now: DateTime<Utc>) -> String {
+        if self.not_after <= now {
+            format!("{} expired {} days ago", self.name, -self.days_remaining)
+        } else {
+            format!("{} expires in {} days ({})", self.name, self.days_remaining, self.not_after.format("%Y-%m-%d"))
+        }
+    }
+}
+
+/// Check the latest unrevoked certificate of every name in the store
+pub fn check_store(store: &CertStore, thresholds: Thresholds, now: DateTime<Utc>) -> Vec<CertCheck> {
+    store.current()
+        .into_iter()
+        .map(|cert| CertCheck::new(&cert.name, cert.not_after, thresholds, now))
+        .collect()
+}
+
+/// Overall status and the single plugin output line for a set of checks
+pub fn summarize(checks: &[CertCheck], thresholds: Thresholds, now: DateTime<Utc>) -> (CheckStatus, String) {
+    let Some(status) = checks.iter().map(|c| c.status).max() else {
+        return (CheckStatus::Unknown, "CERT UNKNOWN - no certificates to check".to_string());
+    };
+
+    let mut problems: Vec<&CertCheck> = checks.iter().filter(|c| c.status != CheckStatus::Ok).collect();
+    problems.sort_by_key(|c| c.not_after);
+    let ok = checks.len() - problems.len();
+
+    let detail = match checks {
+        [single] => single.describe(now),
+        _ if problems.is_empty() => {
+            let next = checks.iter().min_by_key(|c| c.not_after).map(|c| c.describe(now)).unwrap_or_default();
+            format!("{} certificates OK, next: {}", checks.len(), next)
+        }
+        _ => {
+            let listed: Vec<String> = problems.iter().map(|c| c.describe(now)).collect();
+            format!("{}; {} OK", listed.join(", "), ok)
+        }
+    };
+
+    let perfdata: Vec<String> = checks.iter()
+        .map(|c| format!("'{}'={};{};{}", c.name, c.days_remaining, thresholds.warning, thresholds.critical))
+        .collect();
+
+    (status, format!("CERT {} - {} | {}", status, detail, perfdata.join(" ")))
+}
+
+/// Plugin output for a check that could not be run
+pub fn unknown(error: &FluxError) -> (CheckStatus, String) {
+    (CheckStatus::Unknown, format!("CERT UNKNOWN - {}", error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+
+    fn now() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 10, 17, 12, 0, 0).unwrap()
+    }
+
+    fn thresholds() -> Thresholds {
+        Thresholds::new(Days::new(30), Days::new(7)).unwrap()
+    }
+
+    fn check(name: &str, days: i64) -> CertCheck {
+        CertCheck::new(name, now() + Duration::days(days) + Duration::hours(1), thresholds(), now())
+    }
+
+    #[test]
+    fn test_status_thresholds() {
+        assert_eq!(check("a", 30).status, CheckStatus::Ok);
+        assert_eq!(check("a", 29).status, CheckStatus::Warning);
+        assert_eq!(check("a", 7).status, CheckStatus::Warning);
+        assert_eq!(check("a", 6).status, CheckStatus::Critical);
+        assert_eq!(check("a", -2).status, CheckStatus::Critical);
+        assert!(Thresholds::new(Days::new(7), Days::new(30)).is_err());
+    }
+
+    #[test]
+    fn test_summarize() {
+        let (status, line) = summarize(&[check("web", 20)], thresholds(), now());
+        assert_eq!(status, CheckStatus::Warning);
+        assert_eq!(line, "CERT WARNING - web expires in 20 days (2026-11-06) | 'web'=20;30;7");
+
+        let checks = [check("nas", 200), check("old", -3), check("web", 20)];
+        let (status, line) = summarize(&checks, thresholds(), now());
+        assert_eq!(status.exit_code(), 2);
+        assert!(line.starts_with("CERT CRITICAL - old expired 2 days ago, web expires in 20 days (2026-11-06); 1 OK |"), "{}", line);
+
+        let (status, line) = summarize(&[check("nas", 200), check("web", 90)], thresholds(), now());
+        assert_eq!(status, CheckStatus::Ok);
+        assert!(line.starts_with("CERT OK - 2 certificates OK, next: web expires in 90 days"), "{}", line);
+
+        assert_eq!(summarize(&[], thresholds(), now()).0, CheckStatus::Unknown);
+    }
+}
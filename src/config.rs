@@ -1,9 +1,27 @@
 //! Configuration management for flux-ssl-mgr
 
+use crate::apikey::ApiKey;
+use crate::crypto::{NotBefore, SanEntry};
+use crate::duration::{Days, Interval, Validity};
+use crate::policy::KeyType;
+use crate::notify::schedule::Schedule;
+use crate::notify::webhook::EventKind;
+use crate::health::HealthCheck;
+use crate::inventory::InventoryConfig;
+use crate::scan::ScanConfig;
+use crate::window::MaintenanceWindow;
 use crate::error::{FluxError, Result};
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
 
+/// Prefix of environment variables that override config keys
+pub const ENV_PREFIX: &str = "FLUX_SSL_";
+
+/// `FLUX_SSL_*` variables read by the CLI itself rather than mapped to config keys
+pub const ENV_RESERVED: &[&str] = &["FLUX_SSL_MANIFEST"];
+
 /// Main configuration structure
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
@@ -22,8 +40,29 @@ pub struct Config {
     /// Path to CA certificate
     pub ca_cert_path: PathBuf,
 
-    /// Path to OpenSSL configuration file
-    pub openssl_config: PathBuf,
+    /// File holding the CA key password (instead of prompting)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ca_key_password_file: Option<PathBuf>,
+
+    /// Path to the CA's OpenSSL configuration file (checked if set, never required)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub openssl_config: Option<PathBuf>,
+
+    /// What to do when a certificate's output files already exist
+    #[serde(default)]
+    pub overwrite_policy: OverwritePolicy,
+
+    /// Library used for key generation, CSRs and signing
+    #[serde(default)]
+    pub crypto_backend: CryptoBackendKind,
+
+    /// What to do when a certificate would outlast the CA certificate
+    #[serde(default)]
+    pub ca_expiry_policy: CaExpiryPolicy,
+
+    /// Where serial numbers of signed certificates come from
+    #[serde(default)]
+    pub serial_source: SerialSourceKind,
 
     /// Default certificate settings
     #[serde(default)]
@@ -40,6 +79,160 @@ pub struct Config {
     /// Output formatting settings
     #[serde(default)]
     pub output: OutputConfig,
+
+    /// CA signing agent settings
+    #[serde(default)]
+    pub agent: AgentConfig,
+
+    /// Input size limits
+    #[serde(default)]
+    pub limits: Limits,
+
+    /// Local revocation data (CRL / CA database)
+    #[serde(default)]
+    pub revocation: RevocationConfig,
+
+    /// Shared OpenSSL `ca` database (`index.txt`, `serial`)
+    #[serde(default)]
+    pub openssl_ca: OpensslCaConfig,
+
+    /// Restrictions on what the CA signs
+    #[serde(default)]
+    pub policy: PolicyConfig,
+
+    /// Constraints written into subordinate CA certificates
+    #[serde(default)]
+    pub sub_ca: SubCaConfig,
+
+    /// Publishing certificates to an LDAP / Active Directory directory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ldap: Option<LdapConfig>,
+
+    /// Named certificate profiles overriding the defaults
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+
+    /// Expiry thresholds for `check`
+    #[serde(default)]
+    pub check: CheckConfig,
+
+    /// Where `scan` looks for served certificates
+    #[serde(default)]
+    pub scan: ScanConfig,
+
+    /// Automatic renewal service (`flux-ssl-mgr daemon`)
+    #[serde(default)]
+    pub daemon: DaemonConfig,
+
+    /// Web service (`flux-ssl-mgr serve`)
+    #[serde(default)]
+    pub web: WebConfig,
+
+    /// CSR drop directory (`batch --watch`)
+    #[serde(default)]
+    pub watch: WatchConfig,
+
+    /// Expiry notifications
+    #[serde(default)]
+    pub notify: NotifyConfig,
+
+    /// Git history of the certificate store
+    #[serde(default)]
+    pub git: GitConfig,
+
+    /// Kubernetes TLS Secret naming
+    #[serde(default)]
+    pub kubernetes: KubernetesConfig,
+
+    /// Named deploy targets (SSH hosts or Kubernetes clusters)
+    #[serde(default)]
+    pub deploy: BTreeMap<String, DeployTarget>,
+
+    /// Sources of known hosts suggested when entering SANs
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub inventory: Vec<InventoryConfig>,
+}
+
+/// Certificate profile
+///
+/// Any setting left unset falls back to `[defaults]`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Profile {
+    /// RSA key size in bits
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_size: Option<u32>,
+
+    /// Certificate validity period (days, or e.g. `13w`, `1y`, `12h`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cert_days: Option<Validity>,
+
+    /// Also write `<name>.combined.pem`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub combined_pem: Option<bool>,
+}
+
+/// Handling of existing certificate and key files in the output directory
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OverwritePolicy {
+    /// Refuse to replace existing files
+    Fail,
+    /// Move existing files to a timestamped `.bak` directory first
+    #[default]
+    Backup,
+    /// Replace existing files
+    Overwrite,
+}
+
+/// Handling of a requested validity that outlasts the CA certificate
+///
+/// Certificates valid past their issuer's notAfter fail validation once the
+/// CA expires, however long they claim to last.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CaExpiryPolicy {
+    /// Shorten the validity to end with the CA, and warn
+    #[default]
+    Clamp,
+    /// Refuse to sign
+    Fail,
+}
+
+/// Crypto library for key generation, CSRs and signing
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CryptoBackendKind {
+    /// OpenSSL
+    #[default]
+    Openssl,
+    /// rcgen on aws-lc-rs (requires the `backend-rustls` feature)
+    Rustls,
+}
+
+/// Source of serial numbers for signed certificates
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SerialSourceKind {
+    /// 159 random bits, with no record kept
+    #[default]
+    Random,
+    /// A counter in `<working_dir>/intermediate/flux-serial`
+    Sequential,
+    /// Random, checked against and recorded in `<working_dir>/intermediate/flux-serials.json`
+    Database,
+}
+
+/// Order of SANs in generated CSRs (duplicates are always dropped)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SanOrder {
+    /// The common name first, then DNS names, IPs and emails, each sorted
+    #[default]
+    CnFirst,
+    /// DNS names, IPs and emails, each sorted
+    Sorted,
+    /// As given
+    Preserve,
 }
 
 /// Default certificate settings
@@ -49,9 +242,18 @@ pub struct Defaults {
     #[serde(default = "default_key_size")]
     pub key_size: u32,
 
-    /// Certificate validity period in days
+    /// Certificate validity period (days, or e.g. `13w`, `1y`, `12h`)
     #[serde(default = "default_cert_days")]
-    pub cert_days: u32,
+    pub cert_days: Validity,
+
+    /// Start validity this many minutes before signing, for clients with
+    /// clocks running behind
+    #[serde(default)]
+    pub backdate_minutes: u32,
+
+    /// Fixed start of validity (`--not-before`; not read from the file)
+    #[serde(skip)]
+    pub not_before: Option<DateTime<Utc>>,
 
     /// Signature hash algorithm
     #[serde(default = "default_hash_algorithm")]
@@ -64,6 +266,77 @@ pub struct Defaults {
     /// Default file group
     #[serde(default = "default_group")]
     pub group: String,
+
+    /// SAN ordering in generated CSRs
+    #[serde(default)]
+    pub san_order: SanOrder,
+
+    /// Also write `<name>.combined.pem` (key, certificate and intermediate) for HAProxy
+    #[serde(default)]
+    pub combined_pem: bool,
+
+    /// Overrides for `single` (`[defaults.single]`)
+    #[serde(default, skip_serializing_if = "CommandDefaults::is_empty")]
+    pub single: CommandDefaults,
+
+    /// Overrides for `batch` (`[defaults.batch]`)
+    #[serde(default, skip_serializing_if = "CommandDefaults::is_empty")]
+    pub batch: CommandDefaults,
+
+    /// Overrides for the web service (`[defaults.web]`)
+    #[serde(default, skip_serializing_if = "CommandDefaults::is_empty")]
+    pub web: CommandDefaults,
+}
+
+/// Subcommand whose `[defaults.<command>]` apply
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandKind {
+    Single,
+    Batch,
+    Web,
+}
+
+/// Per-subcommand defaults
+///
+/// Unset fields fall back to `[defaults]`; command-line flags and request
+/// fields still win.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CommandDefaults {
+    /// RSA key size in bits
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_size: Option<u32>,
+
+    /// Certificate validity period (days, or e.g. `13w`, `1y`, `12h`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cert_days: Option<Validity>,
+
+    /// Password-protect private keys without asking
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password_protect: Option<bool>,
+
+    /// SANs added to every certificate (`DNS:...`, `IP:...`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sans: Vec<String>,
+}
+
+impl Defaults {
+    /// Start of validity for certificates signed with these defaults
+    pub fn validity_start(&self) -> NotBefore {
+        self.not_before.map_or(NotBefore::Backdate(self.backdate_minutes), NotBefore::At)
+    }
+}
+
+impl CommandDefaults {
+    fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Parsed SAN entries
+    pub fn san_entries(&self) -> Result<Vec<SanEntry>> {
+        self.sans.iter()
+            .map(|s| SanEntry::parse(s.trim()))
+            .collect()
+    }
 }
 
 impl Default for Defaults {
@@ -71,9 +344,16 @@ impl Default for Defaults {
         Self {
             key_size: default_key_size(),
             cert_days: default_cert_days(),
+            backdate_minutes: 0,
+            not_before: None,
             hash_algorithm: default_hash_algorithm(),
             owner: default_owner(),
             group: default_group(),
+            san_order: SanOrder::default(),
+            combined_pem: false,
+            single: CommandDefaults::default(),
+            batch: CommandDefaults::default(),
+            web: CommandDefaults::default(),
         }
     }
 }
@@ -144,6 +424,10 @@ pub struct OutputConfig {
     /// Quiet mode (suppress non-error output)
     #[serde(default)]
     pub quiet: bool,
+
+    /// End every command with a JSON summary line on stderr
+    #[serde(default)]
+    pub summary_json: bool,
 }
 
 impl Default for OutputConfig {
@@ -152,13 +436,761 @@ impl Default for OutputConfig {
             colored: default_colored(),
             verbose: false,
             quiet: false,
+            summary_json: false,
+        }
+    }
+}
+
+/// Input size limits
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Limits {
+    /// Largest certificate, CSR, key, or CRL file read from disk (bytes)
+    #[serde(default = "default_max_file_size")]
+    pub max_file_size: u64,
+
+    /// Largest file accepted by the web service per upload (bytes);
+    /// `[web] max_upload_size` takes precedence
+    #[serde(default = "default_max_upload_size")]
+    pub max_upload_size: u64,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_file_size: default_max_file_size(),
+            max_upload_size: default_max_upload_size(),
+        }
+    }
+}
+
+/// LDAP / Active Directory publishing (`flux-ssl-mgr ldap`)
+///
+/// Changes go through the OpenLDAP `ldapmodify` tool, with a simple bind
+/// (`bind_dn` and `password_file`) or SASL (`sasl_mech`, e.g. GSSAPI with a
+/// Kerberos ticket).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LdapConfig {
+    /// Directory server, e.g. `ldaps://dc1.lab.example`
+    pub uri: String,
+
+    /// DN for a simple bind
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bind_dn: Option<String>,
+
+    /// File holding the bind password (passed to `ldapmodify -y`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password_file: Option<PathBuf>,
+
+    /// SASL mechanism instead of a simple bind
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sasl_mech: Option<String>,
+
+    /// Entry a certificate is published to; `{name}` is the certificate name
+    /// and `{host}` its first label (e.g. `CN={host},CN=Computers,DC=lab,DC=example`)
+    pub entry_dn: String,
+
+    /// Entry for `cACertificate` and `certificateRevocationList`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ca_dn: Option<String>,
+
+    /// Append `;binary` to attribute names (OpenLDAP; not for Active Directory)
+    #[serde(default)]
+    pub binary: bool,
+
+    /// Replace all `userCertificate` values of an entry instead of adding one
+    #[serde(default)]
+    pub replace: bool,
+
+    /// Publish certificates as they are issued and remove them when revoked
+    #[serde(default)]
+    pub auto_publish: bool,
+}
+
+/// Revocation data sources
+///
+/// Revocation status is only checked when at least one source is set.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RevocationConfig {
+    /// CRL file (PEM or DER)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub crl_path: Option<PathBuf>,
+
+    /// OpenSSL CA database (`index.txt`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub index_path: Option<PathBuf>,
+}
+
+impl RevocationConfig {
+    /// Whether any revocation source is configured
+    pub fn is_configured(&self) -> bool {
+        self.crl_path.is_some() || self.index_path.is_some()
+    }
+}
+
+/// Compatibility with the database kept by `openssl ca`
+///
+/// When enabled, every certificate signed takes its serial from the `serial`
+/// file and is appended to `index.txt`, and revocations are marked there, so
+/// `openssl ca` commands can keep working on the same CA directory.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OpensslCaConfig {
+    /// Keep `index.txt` and `serial` up to date
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Directory holding `index.txt` and `serial` (default `<working_dir>/intermediate`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dir: Option<PathBuf>,
+}
+
+/// Restrictions on what the CA signs
+///
+/// Checked when a CSR is signed, so they apply to the CLI, the web service and
+/// the signing agent alike (see [`crate::policy`]).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PolicyConfig {
+    /// Sign wildcard DNS names such as `*.lab.example`
+    #[serde(default = "default_allow_wildcards")]
+    pub allow_wildcards: bool,
+
+    /// Sign subordinate CAs through `POST /api/csr/sign-ca` (the CLI always can)
+    #[serde(default)]
+    pub allow_web_sub_ca: bool,
+
+    /// Key algorithms a CSR may use (empty: any)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_key_types: Vec<KeyType>,
+
+    /// Smallest RSA key signed
+    #[serde(default = "default_min_rsa_bits")]
+    pub min_rsa_bits: u32,
+
+    /// Smallest EC key signed
+    #[serde(default = "default_min_ec_bits")]
+    pub min_ec_bits: u32,
+
+    /// Domains (`*.lab.internal`, `lab.internal`) and CIDR ranges the CN and
+    /// DNS and IP SANs must fall within (empty: any)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_domains: Vec<String>,
+
+    /// Longest validity a certificate may be signed for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_validity: Option<Validity>,
+
+    /// Extensions (by name or OID) a CSR may not ask for
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub forbidden_extensions: Vec<String>,
+
+    /// Subject fields a CSR must have, e.g. `["O", "OU"]`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub required_subject: Vec<String>,
+}
+
+impl Default for PolicyConfig {
+    fn default() -> Self {
+        Self {
+            allow_wildcards: default_allow_wildcards(),
+            allow_web_sub_ca: false,
+            allowed_key_types: Vec::new(),
+            min_rsa_bits: default_min_rsa_bits(),
+            min_ec_bits: default_min_ec_bits(),
+            allowed_domains: Vec::new(),
+            max_validity: None,
+            forbidden_extensions: Vec::new(),
+            required_subject: Vec::new(),
+        }
+    }
+}
+
+/// Subordinate CAs signed by this CA
+///
+/// `csr sign --ca` writes the path length and name constraints here into the
+/// certificate (unless given on the command line), so the sub-CA can only
+/// issue for the permitted names.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SubCaConfig {
+    /// basicConstraints path length (0: the sub-CA signs only leaf certificates)
+    #[serde(default)]
+    pub pathlen: u32,
+
+    /// DNS subtrees the sub-CA may issue for (`guest.lab` covers its subdomains)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub permitted_dns: Vec<String>,
+
+    /// IP ranges the sub-CA may issue for (`10.20.0.0/16`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub permitted_ips: Vec<String>,
+
+    /// DNS subtrees the sub-CA may not issue for, even within a permitted one
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub excluded_dns: Vec<String>,
+
+    /// IP ranges the sub-CA may not issue for
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub excluded_ips: Vec<String>,
+}
+
+/// Expiry thresholds for monitoring checks
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CheckConfig {
+    /// WARNING when fewer days than this remain
+    #[serde(default = "default_check_warning_days")]
+    pub warning_days: Days,
+
+    /// CRITICAL when fewer days than this remain (or the certificate has expired)
+    #[serde(default = "default_check_critical_days")]
+    pub critical_days: Days,
+}
+
+impl Default for CheckConfig {
+    fn default() -> Self {
+        Self {
+            warning_days: default_check_warning_days(),
+            critical_days: default_check_critical_days(),
+        }
+    }
+}
+
+fn default_check_warning_days() -> Days { Days::new(30) }
+fn default_check_critical_days() -> Days { Days::new(7) }
+
+/// Renewal daemon settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DaemonConfig {
+    /// Time between renewal passes
+    #[serde(default = "default_daemon_interval")]
+    pub interval: Interval,
+
+    /// Renew certificates with fewer days than this left
+    #[serde(default = "default_daemon_renew_before")]
+    pub renew_before: Days,
+
+    /// Spread renewals over this many days before `renew_before` (stable per certificate)
+    #[serde(default)]
+    pub jitter: Days,
+
+    /// Renew at most this many certificates per pass, soonest-expiring first
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_renewals_per_run: Option<usize>,
+
+    /// Manifest supplying SANs, profiles and deploy targets of its entries
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub manifest: Option<PathBuf>,
+
+    /// Deploy targets for renewed certificates that aren't in the manifest, by name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub deploy: BTreeMap<String, Vec<String>>,
+}
+
+impl Default for DaemonConfig {
+    fn default() -> Self {
+        Self {
+            interval: default_daemon_interval(),
+            renew_before: default_daemon_renew_before(),
+            jitter: Days::default(),
+            max_renewals_per_run: None,
+            manifest: None,
+            deploy: BTreeMap::new(),
+        }
+    }
+}
+
+fn default_daemon_interval() -> Interval { Interval::from_secs(3_600) }
+fn default_daemon_renew_before() -> Days { Days::new(30) }
+
+/// Web service settings
+///
+/// `/api/*` requires an API key once any key exists, in the config or in the
+/// token file managed with `flux-ssl-mgr web token`. Until then only the
+/// read routes are open, unless `allow_unauthenticated` opens them all.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WebConfig {
+    /// API keys, by hash
+    #[serde(default, rename = "api_key", skip_serializing_if = "Vec::is_empty")]
+    pub api_keys: Vec<ApiKey>,
+
+    /// Accept issue and revoke requests without a key while no key exists
+    #[serde(default)]
+    pub allow_unauthenticated: bool,
+
+    /// Keys managed with `web token` (default: `intermediate/flux-web-tokens.json`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tokens_file: Option<PathBuf>,
+
+    /// Append-only log of API requests (default: `intermediate/flux-audit.jsonl`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audit_log: Option<PathBuf>,
+
+    /// Largest file accepted per upload field, in bytes (default: `[limits] max_upload_size`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_upload_size: Option<u64>,
+
+    /// Largest request body, in bytes (default: the upload limit plus 256 KiB
+    /// for multipart framing and form fields)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_body_size: Option<u64>,
+
+    /// Request limits on the signing endpoints
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
+
+    /// Cross-origin access for browser dashboards hosted elsewhere
+    #[serde(default)]
+    pub cors: CorsConfig,
+}
+
+/// CORS policy of the web API
+///
+/// Without origins no CORS headers are sent, so only pages served by
+/// `flux-ssl-mgr serve` itself can call the API from a browser.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CorsConfig {
+    /// Origins such as `https://dash.lab`, or `"*"` for any
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+
+    /// Methods allowed cross-origin
+    #[serde(default = "default_cors_methods")]
+    pub allowed_methods: Vec<String>,
+
+    /// Request headers allowed cross-origin
+    #[serde(default = "default_cors_headers")]
+    pub allowed_headers: Vec<String>,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        Self {
+            allowed_origins: Vec::new(),
+            allowed_methods: default_cors_methods(),
+            allowed_headers: default_cors_headers(),
+        }
+    }
+}
+
+fn default_cors_methods() -> Vec<String> {
+    vec!["GET".to_string(), "POST".to_string()]
+}
+
+fn default_cors_headers() -> Vec<String> {
+    ["authorization", "content-type", "x-api-key"].map(String::from).to_vec()
+}
+
+/// Rate limits on `/api/csr/upload` and `/api/cert/generate`
+///
+/// Each client address and each API key gets `burst` requests at once, refilled
+/// at the per-minute rate. 0 turns a limit off.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RateLimitConfig {
+    /// Requests per minute from one client address
+    #[serde(default = "default_rate_limit_per_ip")]
+    pub per_ip: u32,
+
+    /// Requests per minute with one API key
+    #[serde(default = "default_rate_limit_per_token")]
+    pub per_token: u32,
+
+    /// Requests allowed back to back before the rate applies
+    #[serde(default = "default_rate_limit_burst")]
+    pub burst: u32,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            per_ip: default_rate_limit_per_ip(),
+            per_token: default_rate_limit_per_token(),
+            burst: default_rate_limit_burst(),
+        }
+    }
+}
+
+fn default_rate_limit_per_ip() -> u32 { 10 }
+fn default_rate_limit_per_token() -> u32 { 30 }
+fn default_rate_limit_burst() -> u32 { 5 }
+
+/// `batch --watch` settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WatchConfig {
+    /// Time between directory scans
+    #[serde(default = "default_watch_interval")]
+    pub interval: Interval,
+
+    /// Where signed CSRs are moved (default: `<dir>/processed`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub archive_dir: Option<PathBuf>,
+
+    /// Where rejected CSRs are moved (default: `<dir>/failed`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failed_dir: Option<PathBuf>,
+}
+
+impl Default for WatchConfig {
+    fn default() -> Self {
+        Self {
+            interval: default_watch_interval(),
+            archive_dir: None,
+            failed_dir: None,
+        }
+    }
+}
+
+fn default_watch_interval() -> Interval { Interval::from_secs(10) }
+
+/// Expiry notification settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotifyConfig {
+    /// Notify once as a certificate drops below each of these day counts
+    #[serde(default = "default_notify_thresholds")]
+    pub thresholds: Vec<Days>,
+
+    /// When to notify: `immediate`, `daily HH:MM` or `weekly <day> HH:MM` (local time)
+    #[serde(default)]
+    pub schedule: Schedule,
+
+    /// Digest emails over SMTP
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<EmailConfig>,
+
+    /// Digests published to an MQTT topic
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mqtt: Option<MqttConfig>,
+
+    /// HTTP endpoints receiving certificate events (`[[notify.webhook]]`)
+    #[serde(default, rename = "webhook", skip_serializing_if = "Vec::is_empty")]
+    pub webhooks: Vec<WebhookConfig>,
+}
+
+impl Default for NotifyConfig {
+    fn default() -> Self {
+        Self {
+            thresholds: default_notify_thresholds(),
+            schedule: Schedule::default(),
+            email: None,
+            mqtt: None,
+            webhooks: Vec::new(),
+        }
+    }
+}
+
+/// Per-sink overrides of the `[notify]` thresholds and schedule
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SinkOptions {
+    /// Thresholds for this sink only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thresholds: Option<Vec<Days>>,
+
+    /// Schedule for this sink only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<Schedule>,
+}
+
+fn default_notify_thresholds() -> Vec<Days> {
+    vec![Days::new(30), Days::new(14), Days::new(7)]
+}
+
+/// Git-backed history of the certificate store and applied manifests
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GitConfig {
+    /// Commit every store change to a git repository in the store's directory
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Sign commits with git's configured signing key
+    #[serde(default)]
+    pub sign: bool,
+
+    /// Remote to push to after each commit
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub push: Option<String>,
+}
+
+/// SMTP server and recipients for notification emails
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmailConfig {
+    /// SMTP server host name
+    pub server: String,
+
+    /// SMTP port (defaults to 587 for STARTTLS, 465 for TLS, 25 otherwise)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+
+    /// Connection security
+    #[serde(default)]
+    pub security: SmtpSecurity,
+
+    /// SMTP user name (no authentication if unset)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+
+    /// File holding the SMTP password
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password_file: Option<PathBuf>,
+
+    /// Sender address
+    pub from: String,
+
+    /// Recipient addresses
+    pub to: Vec<String>,
+
+    #[serde(flatten)]
+    pub options: SinkOptions,
+}
+
+/// MQTT broker and topic for notification digests
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MqttConfig {
+    /// Broker address (`host:port`, plain TCP)
+    pub broker: String,
+
+    /// Topic digests are published to (as JSON)
+    #[serde(default = "default_mqtt_topic")]
+    pub topic: String,
+
+    /// MQTT client identifier
+    #[serde(default = "default_mqtt_client_id")]
+    pub client_id: String,
+
+    /// User name (no authentication if unset)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+
+    /// File holding the password
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password_file: Option<PathBuf>,
+
+    /// Publish with the retain flag, so new subscribers see the last digest
+    #[serde(default)]
+    pub retain: bool,
+
+    #[serde(flatten)]
+    pub options: SinkOptions,
+}
+
+/// HTTP endpoint receiving certificate events as JSON
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookConfig {
+    /// Name used in logs and the notification state
+    #[serde(default = "default_webhook_name")]
+    pub name: String,
+
+    /// URL events are POSTed to (http or https)
+    pub url: String,
+
+    /// Extra request headers, e.g. `Authorization`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+
+    /// File holding the HMAC-SHA256 secret used to sign request bodies
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret_file: Option<PathBuf>,
+
+    /// Events to send: issued, renewed, revoked, expiring (all if empty)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub events: Vec<EventKind>,
+
+    #[serde(flatten)]
+    pub options: SinkOptions,
+}
+
+fn default_webhook_name() -> String {
+    "webhook".to_string()
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        Self {
+            broker: "localhost:1883".to_string(),
+            topic: default_mqtt_topic(),
+            client_id: default_mqtt_client_id(),
+            username: None,
+            password_file: None,
+            retain: false,
+            options: SinkOptions::default(),
+        }
+    }
+}
+
+fn default_mqtt_topic() -> String {
+    "flux-ssl-mgr/expiry".to_string()
+}
+
+fn default_mqtt_client_id() -> String {
+    "flux-ssl-mgr".to_string()
+}
+
+/// How to secure the SMTP connection
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpSecurity {
+    /// Upgrade a plain connection with STARTTLS
+    #[default]
+    Starttls,
+    /// Implicit TLS
+    Tls,
+    /// Unencrypted (local relays only)
+    None,
+}
+
+/// Namespace and name templates for Kubernetes TLS Secrets
+///
+/// Both may contain `{name}`, replaced by the certificate name.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KubernetesConfig {
+    /// Secret namespace
+    #[serde(default = "default_k8s_namespace")]
+    pub namespace: String,
+
+    /// Secret name
+    #[serde(default = "default_k8s_secret_name")]
+    pub secret_name: String,
+}
+
+impl Default for KubernetesConfig {
+    fn default() -> Self {
+        Self {
+            namespace: default_k8s_namespace(),
+            secret_name: default_k8s_secret_name(),
+        }
+    }
+}
+
+fn default_k8s_namespace() -> String {
+    "default".to_string()
+}
+
+fn default_k8s_secret_name() -> String {
+    "{name}-tls".to_string()
+}
+
+/// How a deploy target receives certificates
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeployKind {
+    /// Copy files with scp and restart over ssh
+    #[default]
+    Ssh,
+    /// Apply a TLS Secret with kubectl
+    Kubernetes,
+    /// Write into a bind mount or Docker volume and signal the container
+    Docker,
+    /// Import into the Windows certificate store, locally or over ssh
+    Windows,
+}
+
+/// A host, cluster or container certificates are pushed to
+///
+/// Remote paths, namespaces and secret names may contain `{name}`, replaced
+/// by the certificate name.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DeployTarget {
+    /// Target type (`ssh`, `kubernetes`, `docker` or `windows`)
+    #[serde(default, rename = "type")]
+    pub kind: DeployKind,
+
+    /// Host name or address (ssh; windows: empty for this machine)
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub host: String,
+
+    /// SSH user (defaults to the SSH client's choice)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+
+    /// SSH port
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+
+    /// SSH private key to authenticate with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identity_file: Option<PathBuf>,
+
+    /// Certificate path (ssh: remote; docker: host path or relative to `volume`)
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub cert_path: String,
+
+    /// Private key path (ssh, docker)
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub key_path: String,
+
+    /// CA chain path (ssh, docker)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chain_path: Option<String>,
+
+    /// Only deploy inside this maintenance window (cron: minute hour day month weekday)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub window: Option<MaintenanceWindow>,
+
+    /// Command run on the host after copying (e.g. `systemctl reload nginx`;
+    /// PowerShell for windows targets, e.g. `Restart-Service W3SVC`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub restart_command: Option<String>,
+
+    /// Probe after reloading; the previous files are restored if it fails (ssh, docker)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub health: Option<HealthCheck>,
+
+    /// Secret namespace (kubernetes; defaults to `[kubernetes]`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+
+    /// Secret name (kubernetes; defaults to `[kubernetes]`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret_name: Option<String>,
+
+    /// kubectl context (kubernetes; defaults to the current context)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context: Option<String>,
+
+    /// kubeconfig file (kubernetes; defaults to kubectl's lookup)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kubeconfig: Option<PathBuf>,
+
+    /// Named volume the paths are relative to (docker; default: bind-mount host paths)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub volume: Option<String>,
+
+    /// Container to signal after writing (docker)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub container: Option<String>,
+
+    /// Signal sent to `container` (docker; default `HUP`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signal: Option<String>,
+
+    /// Compose service to restart after writing (docker)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compose_service: Option<String>,
+
+    /// Compose file for `compose_service` (docker; default: compose's lookup)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compose_file: Option<PathBuf>,
+}
+
+/// CA signing agent configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentConfig {
+    /// Use a running signing agent when one is available
+    #[serde(default = "default_agent_enabled")]
+    pub enabled: bool,
+
+    /// Unix socket the agent listens on
+    #[serde(default = "default_agent_socket_path")]
+    pub socket_path: PathBuf,
+}
+
+impl Default for AgentConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_agent_enabled(),
+            socket_path: default_agent_socket_path(),
         }
     }
 }
 
 // Default value functions
 fn default_key_size() -> u32 { 4096 }
-fn default_cert_days() -> u32 { 375 }
+fn default_cert_days() -> Validity { Validity::days(375) }
 fn default_hash_algorithm() -> String { "sha256".to_string() }
 fn default_owner() -> String { "fluxadmin".to_string() }
 fn default_group() -> String { "root".to_string() }
@@ -169,44 +1201,144 @@ fn default_parallel() -> bool { true }
 fn default_max_workers() -> usize { 4 }
 fn default_progress_bar() -> bool { true }
 fn default_colored() -> bool { true }
+fn default_agent_enabled() -> bool { true }
+fn default_allow_wildcards() -> bool { true }
+fn default_min_rsa_bits() -> u32 { 2048 }
+fn default_min_ec_bits() -> u32 { 256 }
+fn default_max_file_size() -> u64 { 5 * 1024 * 1024 }
+fn default_max_upload_size() -> u64 { 5 * 1024 * 1024 }
+
+/// Allowance on top of the upload limit for multipart framing and form fields
+const BODY_LIMIT_OVERHEAD: usize = 256 * 1024;
+
+/// `$XDG_RUNTIME_DIR/flux-ssl-mgr/agent.sock`, or else a socket in a
+/// per-user directory under the temporary directory
+fn default_agent_socket_path() -> PathBuf {
+    if let Some(dir) = std::env::var_os("XDG_RUNTIME_DIR").filter(|d| !d.is_empty()) {
+        return PathBuf::from(dir).join("flux-ssl-mgr/agent.sock");
+    }
+    #[cfg(unix)]
+    let dir = format!("flux-ssl-mgr-{}", nix::unistd::geteuid());
+    #[cfg(not(unix))]
+    let dir = "flux-ssl-mgr".to_string();
+    std::env::temp_dir().join(dir).join("agent.sock")
+}
 
 impl Config {
-    /// Load configuration from file or use defaults
+    /// Load configuration from the first config file found (or the defaults),
+    /// with `FLUX_SSL_*` environment overrides applied
     pub fn load() -> Result<Self> {
-        // Try to find config file in standard locations
-        let config_paths = [
+        Self::load_with_env(Self::find_file().as_deref(), std::env::vars())
+    }
+
+    /// The config file [`Config::load`] would use, if any
+    pub fn find_file() -> Option<PathBuf> {
+        // Standard locations, most specific first
+        [
             PathBuf::from("./flux-ssl-mgr.toml"),
-            dirs::config_dir()
-                .map(|d| d.join("flux-ssl-mgr/config.toml"))
-                .unwrap_or_default(),
-            PathBuf::from("/etc/flux-ssl-mgr/config.toml"),
-        ];
-
-        for path in &config_paths {
-            if path.exists() {
-                return Self::from_file(path);
-            }
+            Self::user_file().unwrap_or_default(),
+            dirs::system_dir().join("config.toml"),
+        ]
+        .into_iter()
+        .find(|path| path.exists())
+    }
+
+    /// The per-user config file (`~/.config/flux-ssl-mgr/config.toml`, or
+    /// `%APPDATA%\flux-ssl-mgr\config.toml` on Windows)
+    pub fn user_file() -> Option<PathBuf> {
+        dirs::config_dir().map(|d| d.join("flux-ssl-mgr").join("config.toml"))
+    }
+
+    /// Load configuration from `path` (or the defaults), then apply
+    /// `FLUX_SSL_*` overrides from `vars`
+    ///
+    /// Precedence is CLI flags (applied by the caller) > environment > file >
+    /// defaults. A loaded file is validated after the overrides, so the
+    /// environment can point at CA files the file gets wrong.
+    pub fn load_with_env<I>(path: Option<&Path>, vars: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        let config = Self::resolve(path, vars)?;
+        if path.is_some() {
+            config.validate()?;
         }
+        Ok(config)
+    }
 
-        // Return default config if no file found
-        Ok(Self::default())
+    /// [`Config::load_with_env`] without validation, for `config --doctor`
+    /// to report on
+    pub fn resolve<I>(path: Option<&Path>, vars: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        let base = match path {
+            Some(path) => Self::parse_file(path)?,
+            None => Self::default(),
+        };
+        base.with_env_overrides(vars)
     }
 
     /// Load configuration from a specific file
     pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let config_str = std::fs::read_to_string(path.as_ref())
+        let config = Self::parse_file(path.as_ref())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn parse_file(path: &Path) -> Result<Self> {
+        let config_str = std::fs::read_to_string(path)
             .map_err(|e| FluxError::FileReadFailed(
-                path.as_ref().to_path_buf(),
+                path.to_path_buf(),
                 e.to_string()
             ))?;
 
-        let config: Config = toml::from_str(&config_str)
+        toml::from_str(&config_str)
             .map_err(|e| FluxError::InvalidConfigValue(
                 "config file".to_string(),
                 e.to_string()
-            ))?;
+            ))
+    }
+
+    /// Copy of this configuration with `FLUX_SSL_*` overrides from `vars` applied
+    ///
+    /// The rest of the name is the config key, lowercased, with `__` between
+    /// nesting levels: `FLUX_SSL_OUTPUT_DIR`, `FLUX_SSL_DEFAULTS__KEY_SIZE`.
+    /// Values are read as TOML values where possible (`4096`, `true`, `0o600`)
+    /// and as strings otherwise. Unknown keys are an error.
+    pub fn with_env_overrides<I>(&self, vars: I) -> Result<Config>
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        let invalid = |key: &str, msg: String| FluxError::InvalidConfigValue(key.to_string(), msg);
+
+        let mut value = toml::Value::try_from(self)
+            .map_err(|e| invalid("config", e.to_string()))?;
+        let mut keys = Vec::new();
+
+        for (name, raw) in vars {
+            let Some(key) = name.strip_prefix(ENV_PREFIX) else { continue };
+            if ENV_RESERVED.contains(&name.as_str()) || key.is_empty() {
+                continue;
+            }
+
+            let path: Vec<String> = key.split("__").map(str::to_ascii_lowercase).collect();
+            set_value(&mut value, &path, env_value(&raw)).map_err(|msg| invalid(&name, msg))?;
+            keys.push((name, path));
+        }
+
+        let config: Config = value.try_into()
+            .map_err(|e: toml::de::Error| invalid("environment", e.message().to_string()))?;
+
+        // Keys serde doesn't know are silently dropped; catch them here
+        let applied = toml::Value::try_from(&config)
+            .map_err(|e| invalid("config", e.to_string()))?;
+        for (name, path) in keys {
+            if lookup_value(&applied, &path).is_none() {
+                return Err(invalid(&name, "no such config key".to_string()));
+            }
+        }
 
-        config.validate()?;
         Ok(config)
     }
 
@@ -227,14 +1359,146 @@ impl Config {
             return Err(FluxError::CaCertNotFound(self.ca_cert_path.clone()));
         }
 
-        // Check if OpenSSL config exists
-        if !self.openssl_config.exists() {
-            return Err(FluxError::OpenSslConfigNotFound(self.openssl_config.clone()));
+        // Check the OpenSSL config only if one is configured
+        if let Some(openssl_config) = self.openssl_config.as_ref().filter(|p| !p.exists()) {
+            return Err(FluxError::OpenSslConfigNotFound(openssl_config.clone()));
+        }
+
+        for (key, command) in [
+            ("defaults.single.sans", CommandKind::Single),
+            ("defaults.batch.sans", CommandKind::Batch),
+            ("defaults.web.sans", CommandKind::Web),
+        ] {
+            self.command_defaults(command).san_entries()
+                .map_err(|e| FluxError::InvalidConfigValue(key.to_string(), e.to_string()))?;
+        }
+
+        crate::crypto::NameConstraints::from_config(&self.sub_ca)
+            .map_err(|e| FluxError::InvalidConfigValue("sub_ca".to_string(), e.to_string()))?;
+
+        crate::policy::validate(&self.policy)?;
+        if let Some(max) = self.policy.max_validity.filter(|max| self.defaults.cert_days > *max) {
+            return Err(FluxError::InvalidConfigValue(
+                "defaults.cert_days".to_string(),
+                format!("{} is longer than policy.max_validity ({})", self.defaults.cert_days, max),
+            ));
         }
 
         Ok(())
     }
 
+    /// Path of the issued certificate store
+    pub fn store_path(&self) -> PathBuf {
+        self.working_dir.join("intermediate").join("flux-store.json")
+    }
+
+    /// Path of the counter used by `serial_source = "sequential"`
+    pub fn serial_counter_path(&self) -> PathBuf {
+        self.working_dir.join("intermediate").join("flux-serial")
+    }
+
+    /// Path of the ledger used by `serial_source = "database"`
+    pub fn serial_ledger_path(&self) -> PathBuf {
+        self.working_dir.join("intermediate").join("flux-serials.json")
+    }
+
+    /// Directory of the OpenSSL `ca` database
+    pub fn openssl_ca_dir(&self) -> PathBuf {
+        self.openssl_ca.dir.clone()
+            .unwrap_or_else(|| self.working_dir.join("intermediate"))
+    }
+
+    /// Path of the web API token file
+    pub fn web_tokens_path(&self) -> PathBuf {
+        self.web.tokens_file.clone()
+            .unwrap_or_else(|| self.working_dir.join("intermediate").join("flux-web-tokens.json"))
+    }
+
+    /// Path of the web API audit log
+    pub fn web_audit_path(&self) -> PathBuf {
+        self.web.audit_log.clone()
+            .unwrap_or_else(|| self.working_dir.join("intermediate").join("flux-audit.jsonl"))
+    }
+
+    /// Largest file the web service accepts per upload field
+    pub fn web_upload_limit(&self) -> usize {
+        self.web.max_upload_size.unwrap_or(self.limits.max_upload_size) as usize
+    }
+
+    /// Largest request body the web service reads
+    pub fn web_body_limit(&self) -> usize {
+        self.web.max_body_size
+            .map_or_else(|| self.web_upload_limit().saturating_add(BODY_LIMIT_OVERHEAD), |size| size as usize)
+    }
+
+    /// Directory of certificates issued through the web service (by serial)
+    pub fn web_certs_dir(&self) -> PathBuf {
+        self.working_dir.join("intermediate").join("flux-web-certs")
+    }
+
+    /// Path of the notification state (what was sent, and when)
+    pub fn notify_state_path(&self) -> PathBuf {
+        self.working_dir.join("intermediate").join("flux-notify.json")
+    }
+
+    /// Copy of this configuration with a named profile applied to the defaults
+    pub fn with_profile(&self, name: &str) -> Result<Config> {
+        let profile = self.profiles.get(name)
+            .ok_or_else(|| FluxError::InvalidConfigValue(
+                "profile".to_string(),
+                format!("unknown profile '{}'", name)
+            ))?;
+
+        let mut config = self.clone();
+        if let Some(key_size) = profile.key_size {
+            config.defaults.key_size = key_size;
+        }
+        if let Some(cert_days) = profile.cert_days {
+            config.defaults.cert_days = cert_days;
+        }
+        if let Some(combined_pem) = profile.combined_pem {
+            config.defaults.combined_pem = combined_pem;
+        }
+
+        Ok(config)
+    }
+
+    /// `[defaults.<command>]` of `command`
+    pub fn command_defaults(&self, command: CommandKind) -> &CommandDefaults {
+        match command {
+            CommandKind::Single => &self.defaults.single,
+            CommandKind::Batch => &self.defaults.batch,
+            CommandKind::Web => &self.defaults.web,
+        }
+    }
+
+    /// Copy of this configuration with the key size and validity of
+    /// `[defaults.<command>]` applied
+    ///
+    /// `password_protect` and `sans` are read by the command itself, through
+    /// [`Config::command_defaults`].
+    pub fn for_command(&self, command: CommandKind) -> Config {
+        let overrides = self.command_defaults(command);
+        let mut config = self.clone();
+        if let Some(key_size) = overrides.key_size {
+            config.defaults.key_size = key_size;
+        }
+        if let Some(cert_days) = overrides.cert_days {
+            config.defaults.cert_days = cert_days;
+        }
+        config
+    }
+
+    /// Path of the root CA certificate in the standard PKI layout
+    pub fn root_ca_cert_path(&self) -> PathBuf {
+        self.working_dir.join("certs").join("ca.cert.pem")
+    }
+
+    /// Path of the root CA key in the standard PKI layout
+    pub fn root_ca_key_path(&self) -> PathBuf {
+        self.working_dir.join("private").join("ca.key.pem")
+    }
+
     /// Create default config file template
     pub fn create_default_template() -> String {
         toml::to_string_pretty(&Self::default()).unwrap_or_default()
@@ -260,25 +1524,80 @@ impl Config {
 
 impl Default for Config {
     fn default() -> Self {
+        let (working_dir, csr_input_dir) = dirs::default_layout();
+        let intermediate = working_dir.join("intermediate");
+
         Self {
-            working_dir: PathBuf::from("/root/ca"),
-            output_dir: PathBuf::from("/home/fluxadmin/ssl/pem-out"),
-            csr_input_dir: PathBuf::from("/home/fluxadmin/ssl"),
-            ca_key_path: PathBuf::from("/root/ca/intermediate/private/intermediate.key.pem"),
-            ca_cert_path: PathBuf::from("/root/ca/intermediate/certs/intermediate.cert.pem"),
-            openssl_config: PathBuf::from("/root/ca/intermediate/openssl.cnf"),
+            ca_key_path: intermediate.join("private").join("intermediate.key.pem"),
+            ca_cert_path: intermediate.join("certs").join("intermediate.cert.pem"),
+            output_dir: csr_input_dir.join("pem-out"),
+            working_dir,
+            csr_input_dir,
+            ca_key_password_file: None,
+            openssl_config: None,
+            overwrite_policy: OverwritePolicy::default(),
+            crypto_backend: CryptoBackendKind::default(),
+            ca_expiry_policy: CaExpiryPolicy::default(),
+            serial_source: SerialSourceKind::default(),
             defaults: Defaults::default(),
             permissions: Permissions::default(),
             batch: BatchConfig::default(),
             output: OutputConfig::default(),
+            agent: AgentConfig::default(),
+            limits: Limits::default(),
+            revocation: RevocationConfig::default(),
+            openssl_ca: OpensslCaConfig::default(),
+            policy: PolicyConfig::default(),
+            sub_ca: SubCaConfig::default(),
+            ldap: None,
+            profiles: BTreeMap::new(),
+            check: CheckConfig::default(),
+            scan: ScanConfig::default(),
+            daemon: DaemonConfig::default(),
+            web: WebConfig::default(),
+            watch: WatchConfig::default(),
+            notify: NotifyConfig::default(),
+            git: GitConfig::default(),
+            kubernetes: KubernetesConfig::default(),
+            deploy: BTreeMap::new(),
+            inventory: Vec::new(),
         }
     }
 }
 
-// Helper module for dirs crate functionality
+/// An environment value as TOML (number, bool, array, ...) or a plain string
+fn env_value(raw: &str) -> toml::Value {
+    format!("v = {}", raw)
+        .parse::<toml::Table>()
+        .ok()
+        .and_then(|mut table| table.remove("v"))
+        .unwrap_or_else(|| toml::Value::String(raw.to_string()))
+}
+
+/// Set `path` in a TOML tree, creating tables as needed
+fn set_value(root: &mut toml::Value, path: &[String], new: toml::Value) -> std::result::Result<(), String> {
+    let (last, parents) = path.split_last().ok_or("empty key")?;
+    let mut node = root;
+    for key in parents {
+        let table = node.as_table_mut().ok_or_else(|| format!("{} is not a table", key))?;
+        node = table.entry(key.clone()).or_insert_with(|| toml::Value::Table(toml::Table::new()));
+    }
+    node.as_table_mut()
+        .ok_or_else(|| format!("cannot set {} here", last))?
+        .insert(last.clone(), new);
+    Ok(())
+}
+
+fn lookup_value<'a>(root: &'a toml::Value, path: &[String]) -> Option<&'a toml::Value> {
+    path.iter().try_fold(root, |node, key| node.get(key))
+}
+
+// Platform directories (what the dirs crate would provide)
 mod dirs {
     use std::path::PathBuf;
 
+    /// Per-user configuration directory
+    #[cfg(not(windows))]
     pub fn config_dir() -> Option<PathBuf> {
         if let Ok(home) = std::env::var("HOME") {
             Some(PathBuf::from(home).join(".config"))
@@ -286,4 +1605,139 @@ mod dirs {
             None
         }
     }
+
+    /// Per-user configuration directory (roaming AppData)
+    #[cfg(windows)]
+    pub fn config_dir() -> Option<PathBuf> {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    }
+
+    /// Machine-wide flux-ssl-mgr directory
+    #[cfg(not(windows))]
+    pub fn system_dir() -> PathBuf {
+        PathBuf::from("/etc/flux-ssl-mgr")
+    }
+
+    /// Machine-wide flux-ssl-mgr directory (under ProgramData)
+    #[cfg(windows)]
+    pub fn system_dir() -> PathBuf {
+        program_data().join("flux-ssl-mgr")
+    }
+
+    /// Default CA working directory and CSR input directory
+    #[cfg(not(windows))]
+    pub fn default_layout() -> (PathBuf, PathBuf) {
+        (PathBuf::from("/root/ca"), PathBuf::from("/home/fluxadmin/ssl"))
+    }
+
+    /// Default CA working directory and CSR input directory, both under
+    /// ProgramData, which only administrators can write by default
+    #[cfg(windows)]
+    pub fn default_layout() -> (PathBuf, PathBuf) {
+        let base = program_data().join("flux-ssl-mgr");
+        (base.join("ca"), base.join("ssl"))
+    }
+
+    #[cfg(windows)]
+    fn program_data() -> PathBuf {
+        std::env::var_os("ProgramData")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(r"C:\ProgramData"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn test_env_overrides() {
+        let config = Config::default().with_env_overrides(vars(&[
+            ("FLUX_SSL_OUTPUT_DIR", "/certs"),
+            ("FLUX_SSL_DEFAULTS__KEY_SIZE", "2048"),
+            ("FLUX_SSL_DEFAULTS__CERT_DAYS", "13w"),
+            ("FLUX_SSL_PERMISSIONS__PRIVATE_KEY", "0o640"),
+            ("FLUX_SSL_OVERWRITE_POLICY", "fail"),
+            ("FLUX_SSL_CA_KEY_PASSWORD_FILE", "/run/secrets/ca-pass"),
+            ("FLUX_SSL_MANIFEST", "/manifest.yaml"),
+            ("HOME", "/root"),
+        ])).unwrap();
+
+        assert_eq!(config.output_dir, PathBuf::from("/certs"));
+        assert_eq!(config.defaults.key_size, 2048);
+        assert_eq!(config.defaults.cert_days, Validity::days(91));
+        assert_eq!(config.permissions.private_key, 0o640);
+        assert_eq!(config.overwrite_policy, OverwritePolicy::Fail);
+        assert_eq!(config.ca_key_password_file, Some(PathBuf::from("/run/secrets/ca-pass")));
+    }
+
+    #[test]
+    fn test_load_with_env() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir = temp_dir.path();
+        std::fs::write(dir.join("ca.key.pem"), "").unwrap();
+        std::fs::write(dir.join("ca.cert.pem"), "").unwrap();
+        let file = dir.join("config.toml");
+        let example = include_str!("../config.toml.example")
+            .replace("working_dir = \"/root/ca\"", &format!("working_dir = {:?}", dir))
+            .replace("ca_key_path = \"/root/ca/", "ca_key_path = \"/missing/")
+            .replace("ca_cert_path = \"/root/ca/intermediate/certs/intermediate.cert.pem\"", &format!("ca_cert_path = {:?}", dir.join("ca.cert.pem")))
+            .replace("openssl_config =", "#openssl_config =");
+        std::fs::write(&file, example).unwrap();
+
+        // The file's CA key is missing until the environment fixes it
+        assert!(matches!(Config::load_with_env(Some(&file), vars(&[])), Err(FluxError::CaKeyNotFound(_))));
+        let config = Config::load_with_env(Some(&file), vars(&[
+            ("FLUX_SSL_CA_KEY_PATH", dir.join("ca.key.pem").to_str().unwrap()),
+            ("FLUX_SSL_DEFAULTS__KEY_SIZE", "2048"),
+        ])).unwrap();
+        assert_eq!(config.ca_key_path, dir.join("ca.key.pem"));
+        assert_eq!(config.defaults.key_size, 2048);
+        // Everything else still comes from the file
+        assert_eq!(config.ca_cert_path, dir.join("ca.cert.pem"));
+    }
+
+    #[test]
+    fn test_command_defaults() {
+        let config: Config = toml::from_str(r#"
+            working_dir = "/root/ca"
+            output_dir = "/out"
+            csr_input_dir = "/csr"
+            ca_key_path = "/root/ca/ca.key.pem"
+            ca_cert_path = "/root/ca/ca.cert.pem"
+
+            [defaults]
+            cert_days = 375
+
+            [defaults.batch]
+            cert_days = "90d"
+            password_protect = false
+            sans = ["DNS:fleet.lab"]
+        "#).unwrap();
+
+        assert_eq!(config.for_command(CommandKind::Batch).defaults.cert_days, Validity::days(90));
+        assert_eq!(config.for_command(CommandKind::Single).defaults.cert_days, Validity::days(375));
+        let batch = config.command_defaults(CommandKind::Batch);
+        assert_eq!(batch.password_protect, Some(false));
+        assert_eq!(batch.san_entries().unwrap(), [SanEntry::Dns("fleet.lab".to_string())]);
+
+        // Survives a save and the environment can reach it
+        let saved: Config = toml::from_str(&toml::to_string_pretty(&config).unwrap()).unwrap();
+        assert_eq!(saved.defaults.batch, config.defaults.batch);
+        let config = config.with_env_overrides(vars(&[("FLUX_SSL_DEFAULTS__WEB__KEY_SIZE", "2048")])).unwrap();
+        assert_eq!(config.for_command(CommandKind::Web).defaults.key_size, 2048);
+    }
+
+    #[test]
+    fn test_env_overrides_reject_unknown_and_invalid() {
+        let err = Config::default().with_env_overrides(vars(&[("FLUX_SSL_OUTPUT_DRI", "/certs")])).unwrap_err();
+        assert!(err.to_string().contains("FLUX_SSL_OUTPUT_DRI"));
+
+        assert!(Config::default().with_env_overrides(vars(&[("FLUX_SSL_DEFAULTS__KEY_SIZE", "big")])).is_err());
+        assert!(Config::default().with_env_overrides(vars(&[("FLUX_SSL_OUTPUT_DIR__X", "1")])).is_err());
+    }
 }
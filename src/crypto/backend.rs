@@ -0,0 +1,315 @@
+//! Crypto backends for key generation, CSR creation and signing
+//!
+//! Backends exchange PEM, so issuance does not depend on one library's types.
+//! OpenSSL is the default. With the `backend-rustls` feature,
+//! `crypto_backend = "rustls"` selects an implementation on rcgen and aws-lc-rs.
+//! Parsing and inspecting certificates (`info`, chain checks, the store) still
+//! uses OpenSSL either way, so the feature doesn't remove the OpenSSL build
+//! dependency.
+
+use crate::config::CryptoBackendKind;
+use crate::error::{FluxError, Result};
+use super::{NameConstraints, SanEntry};
+use chrono::{DateTime, Utc};
+
+/// Start and end of an issued certificate's validity
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ValidityWindow {
+    pub not_before: DateTime<Utc>,
+    pub not_after: DateTime<Utc>,
+}
+
+/// Extensions of a subordinate CA certificate, set by the signer rather
+/// than taken from the CSR
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SubCaExtensions {
+    /// Path length in basicConstraints (0: the sub-CA signs only leaf certificates)
+    pub pathlen: u32,
+    /// Names the sub-CA may issue for (left out when empty)
+    pub name_constraints: NameConstraints,
+}
+
+/// What the signer puts in a certificate in place of the CSR's requests
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IssueExtensions<'a> {
+    /// SANs replacing the CSR's
+    pub sans: Option<&'a [SanEntry]>,
+    /// Issue a subordinate CA certificate rather than a leaf
+    pub sub_ca: Option<&'a SubCaExtensions>,
+}
+
+/// Key generation, CSR creation and CSR signing
+pub trait CryptoBackend: Send + Sync {
+    /// Backend name as used in the config
+    fn name(&self) -> &'static str;
+
+    /// Generate an RSA key of `bits` bits (unencrypted PKCS#8 PEM)
+    fn generate_key(&self, bits: u32) -> Result<Vec<u8>>;
+
+    /// CSR with subject `CN=common_name` and the given SANs, signed with `key_pem`
+    fn create_csr(&self, key_pem: &[u8], common_name: &str, sans: &[SanEntry]) -> Result<Vec<u8>>;
+
+    /// Sign a CSR with the CA certificate and key, valid for `validity`
+    ///
+    /// The certificate keeps the CSR's subject and extensions and gets
+    /// `serial` (big-endian) as its serial number. `extensions` replace what
+    /// the CSR asks for.
+    fn sign_csr(&self, csr_pem: &[u8], ca_cert_pem: &[u8], ca_key_pem: &[u8], serial: &[u8], validity: ValidityWindow, extensions: IssueExtensions) -> Result<Vec<u8>>;
+}
+
+/// Backend for a configured kind
+pub fn backend(kind: CryptoBackendKind) -> Result<&'static dyn CryptoBackend> {
+    match kind {
+        CryptoBackendKind::Openssl => Ok(&OpenSslBackend),
+        #[cfg(feature = "backend-rustls")]
+        CryptoBackendKind::Rustls => Ok(&RustlsBackend),
+        #[cfg(not(feature = "backend-rustls"))]
+        CryptoBackendKind::Rustls => Err(FluxError::InvalidConfigValue(
+            "crypto_backend".to_string(),
+            "built without the backend-rustls feature".to_string(),
+        )),
+    }
+}
+
+/// OpenSSL (the default)
+pub struct OpenSslBackend;
+
+impl CryptoBackend for OpenSslBackend {
+    fn name(&self) -> &'static str {
+        "openssl"
+    }
+
+    fn generate_key(&self, bits: u32) -> Result<Vec<u8>> {
+        super::key::to_pem(&super::generate_rsa_key(bits, None)?)
+    }
+
+    fn create_csr(&self, key_pem: &[u8], common_name: &str, sans: &[SanEntry]) -> Result<Vec<u8>> {
+        let key = openssl::pkey::PKey::private_key_from_pem(key_pem)
+            .map_err(|e| FluxError::CsrGenerationFailed(e.to_string()))?;
+        super::create_csr(common_name, &key, sans, None)?
+            .to_pem()
+            .map_err(|e| FluxError::CsrGenerationFailed(e.to_string()))
+    }
+
+    fn sign_csr(&self, csr_pem: &[u8], ca_cert_pem: &[u8], ca_key_pem: &[u8], serial: &[u8], validity: ValidityWindow, extensions: IssueExtensions) -> Result<Vec<u8>> {
+        let csr = super::csr_from_pem_bytes(csr_pem)?;
+        let ca_cert = super::cert_from_pem(ca_cert_pem)?;
+        let ca_key = openssl::pkey::PKey::private_key_from_pem(ca_key_pem)
+            .map_err(|e| FluxError::CertSigningFailed(e.to_string()))?;
+        super::cert_to_pem(&super::cert::sign_csr_with_extensions(&csr, &ca_cert, &ca_key, serial, validity, extensions)?)
+    }
+}
+
+/// rcgen on aws-lc-rs
+#[cfg(feature = "backend-rustls")]
+pub struct RustlsBackend;
+
+#[cfg(feature = "backend-rustls")]
+impl CryptoBackend for RustlsBackend {
+    fn name(&self) -> &'static str {
+        "rustls"
+    }
+
+    fn generate_key(&self, bits: u32) -> Result<Vec<u8>> {
+        use rcgen::{KeyPair, RsaKeySize, PKCS_RSA_SHA256};
+
+        let size = match bits {
+            2048 => RsaKeySize::_2048,
+            3072 => RsaKeySize::_3072,
+            4096 => RsaKeySize::_4096,
+            _ => {
+                return Err(FluxError::KeyGenerationFailed(format!(
+                    "the rustls backend supports 2048, 3072 or 4096-bit RSA keys, not {}",
+                    bits
+                )))
+            }
+        };
+
+        let key = KeyPair::generate_rsa_for(&PKCS_RSA_SHA256, size)
+            .map_err(|e| FluxError::KeyGenerationFailed(e.to_string()))?;
+        Ok(key.serialize_pem().into_bytes())
+    }
+
+    fn create_csr(&self, key_pem: &[u8], common_name: &str, sans: &[SanEntry]) -> Result<Vec<u8>> {
+        use rcgen::{CertificateParams, DistinguishedName, DnType};
+
+        let failed = |e: rcgen::Error| FluxError::CsrGenerationFailed(e.to_string());
+        let key = rustls_key(key_pem).map_err(failed)?;
+
+        let mut params = CertificateParams::default();
+        params.distinguished_name = DistinguishedName::new();
+        params.distinguished_name.push(DnType::CommonName, common_name);
+        params.subject_alt_names = sans.iter()
+            .map(rustls_san)
+            .collect::<Result<_>>()?;
+
+        let csr = params.serialize_request(&key).map_err(failed)?;
+        Ok(csr.pem().map_err(failed)?.into_bytes())
+    }
+
+    fn sign_csr(&self, csr_pem: &[u8], ca_cert_pem: &[u8], ca_key_pem: &[u8], serial: &[u8], validity: ValidityWindow, extensions: IssueExtensions) -> Result<Vec<u8>> {
+        use rcgen::{CertificateSigningRequestParams, Issuer, SerialNumber};
+        use time::OffsetDateTime;
+
+        let failed = |e: rcgen::Error| FluxError::CertSigningFailed(e.to_string());
+        let pem_str = |pem: &[u8]| {
+            String::from_utf8(pem.to_vec()).map_err(|e| FluxError::CertSigningFailed(e.to_string()))
+        };
+
+        let ca_key = rustls_key(ca_key_pem).map_err(failed)?;
+        let issuer = Issuer::from_ca_cert_pem(&pem_str(ca_cert_pem)?, ca_key).map_err(failed)?;
+        let mut csr = CertificateSigningRequestParams::from_pem(&pem_str(csr_pem)?).map_err(failed)?;
+
+        let time = |t: DateTime<Utc>| OffsetDateTime::from_unix_timestamp(t.timestamp())
+            .map_err(|e| FluxError::CertSigningFailed(e.to_string()));
+        csr.params.serial_number = Some(SerialNumber::from_slice(serial));
+        csr.params.not_before = time(validity.not_before)?;
+        csr.params.not_after = time(validity.not_after)?;
+        if let Some(sans) = extensions.sans {
+            csr.params.subject_alt_names = sans.iter().map(rustls_san).collect::<Result<_>>()?;
+        }
+        if let Some(sub_ca) = extensions.sub_ca {
+            let pathlen = u8::try_from(sub_ca.pathlen)
+                .map_err(|_| FluxError::CertSigningFailed(format!("pathlen {} is too large", sub_ca.pathlen)))?;
+            csr.params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Constrained(pathlen));
+            csr.params.key_usages = vec![
+                rcgen::KeyUsagePurpose::DigitalSignature,
+                rcgen::KeyUsagePurpose::KeyCertSign,
+                rcgen::KeyUsagePurpose::CrlSign,
+            ];
+            csr.params.name_constraints = (!sub_ca.name_constraints.is_empty())
+                .then(|| sub_ca.name_constraints.to_rcgen());
+        }
+
+        Ok(csr.signed_by(&issuer).map_err(failed)?.pem().into_bytes())
+    }
+}
+
+#[cfg(feature = "backend-rustls")]
+fn rustls_key(pem: &[u8]) -> std::result::Result<rcgen::KeyPair, rcgen::Error> {
+    let pem = std::str::from_utf8(pem).map_err(|_| rcgen::Error::CouldNotParseKeyPair)?;
+    rcgen::KeyPair::from_pem(pem)
+}
+
+#[cfg(feature = "backend-rustls")]
+fn rustls_san(san: &SanEntry) -> Result<rcgen::SanType> {
+    use rcgen::SanType;
+
+    let invalid = || FluxError::InvalidSanFormat(san.to_string());
+    Ok(match san {
+        SanEntry::Dns(dns) => SanType::DnsName(dns.as_str().try_into().map_err(|_| invalid())?),
+        SanEntry::Ip(ip) => SanType::IpAddress(ip.parse().map_err(|_| invalid())?),
+        SanEntry::Email(email) => SanType::Rfc822Name(email.as_str().try_into().map_err(|_| invalid())?),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::cert::asn1_time_to_chrono;
+    use crate::crypto::{certs_from_pem, csr_from_pem_bytes, key_to_pem};
+    use crate::fixtures;
+
+    fn sans() -> Vec<SanEntry> {
+        vec![
+            SanEntry::Dns("web.lab".to_string()),
+            SanEntry::Ip("10.0.0.5".to_string()),
+        ]
+    }
+
+    /// 90 days starting an hour ago (on a whole second, as certificates store it)
+    fn window() -> ValidityWindow {
+        let not_before = DateTime::from_timestamp(Utc::now().timestamp() - 3600, 0).unwrap();
+        ValidityWindow { not_before, not_after: not_before + chrono::Duration::days(90) }
+    }
+
+    /// Issue a leaf with `backend` using the fixture intermediate CA and check it chains
+    fn check_issuance(backend: &dyn CryptoBackend) {
+        let ca = fixtures::intermediate_ca();
+        let key_pem = key_to_pem(&fixtures::leaf_key()).unwrap();
+        let window = window();
+
+        let csr_pem = backend.create_csr(&key_pem, "web", &sans()).unwrap();
+        let csr = csr_from_pem_bytes(&csr_pem).unwrap();
+        assert!(csr.verify(&csr.public_key().unwrap()).unwrap());
+
+        let cert_pem = backend.sign_csr(
+            &csr_pem,
+            &ca.cert().to_pem().unwrap(),
+            &key_to_pem(ca.key()).unwrap(),
+            &[0x80, 0x01],
+            window,
+            IssueExtensions::default(),
+        ).unwrap();
+        let cert = certs_from_pem(&cert_pem).unwrap().remove(0);
+
+        assert!(cert.verify(&ca.cert().public_key().unwrap()).unwrap());
+        assert!(cert.public_key().unwrap().public_eq(&fixtures::leaf_key()));
+        assert_eq!(cert.not_before().diff(cert.not_after()).unwrap().days, 90);
+        assert_eq!(asn1_time_to_chrono(cert.not_before()).unwrap(), window.not_before);
+        // A leading set bit doesn't make the serial negative
+        assert_eq!(cert.serial_number().to_bn().unwrap().to_hex_str().unwrap().to_string(), "8001");
+
+        let description = fixtures::describe(&cert);
+        assert!(description.contains("subject: CN=web"), "{}", description);
+        assert!(description.contains("sans: DNS:web.lab, IP:10.0.0.5"), "{}", description);
+
+        // SANs given to the signer replace the CSR's
+        let replaced = [SanEntry::Dns("web.lab".to_string()), SanEntry::Dns("www.web.lab".to_string())];
+        let cert_pem = backend.sign_csr(
+            &csr_pem,
+            &ca.cert().to_pem().unwrap(),
+            &key_to_pem(ca.key()).unwrap(),
+            &[0x01],
+            window,
+            IssueExtensions { sans: Some(&replaced), ..Default::default() },
+        ).unwrap();
+        let description = fixtures::describe(&certs_from_pem(&cert_pem).unwrap().remove(0));
+        assert!(description.contains("sans: DNS:web.lab, DNS:www.web.lab\n"), "{}", description);
+
+        // A sub-CA gets CA extensions and name constraints in place of any it asks for
+        let sub_ca = SubCaExtensions {
+            pathlen: 1,
+            name_constraints: NameConstraints { permitted: vec!["guest.lab".parse().unwrap()], excluded: Vec::new() },
+        };
+        let cert_pem = backend.sign_csr(
+            &fixtures::ca_csr("Guest CA").to_pem().unwrap(),
+            &ca.cert().to_pem().unwrap(),
+            &key_to_pem(ca.key()).unwrap(),
+            &[0x02],
+            window,
+            IssueExtensions { sub_ca: Some(&sub_ca), ..Default::default() },
+        ).unwrap();
+        let text = String::from_utf8(certs_from_pem(&cert_pem).unwrap()[0].to_text().unwrap()).unwrap();
+        assert!(text.contains("CA:TRUE, pathlen:1"), "{}", text);
+        assert!(text.contains("Digital Signature, Certificate Sign, CRL Sign"), "{}", text);
+        assert!(text.contains("Name Constraints: critical"), "{}", text);
+        assert!(text.contains("DNS:guest.lab"), "{}", text);
+    }
+
+    #[test]
+    fn test_openssl_backend() {
+        let backend = backend(CryptoBackendKind::Openssl).unwrap();
+        assert_eq!(backend.name(), "openssl");
+        check_issuance(backend);
+    }
+
+    #[cfg(feature = "backend-rustls")]
+    #[test]
+    fn test_rustls_backend() {
+        let backend = backend(CryptoBackendKind::Rustls).unwrap();
+        assert_eq!(backend.name(), "rustls");
+        check_issuance(backend);
+
+        let key = backend.generate_key(2048).unwrap();
+        let key = openssl::pkey::PKey::private_key_from_pem(&key).unwrap();
+        assert_eq!(key.bits(), 2048);
+        assert!(backend.generate_key(1024).is_err());
+    }
+
+    #[cfg(not(feature = "backend-rustls"))]
+    #[test]
+    fn test_rustls_backend_requires_feature() {
+        assert!(backend(CryptoBackendKind::Rustls).is_err());
+    }
+}
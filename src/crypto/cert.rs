@@ -1,13 +1,48 @@
 //! Certificate signing and management
 
 use crate::error::{FluxError, Result};
-use openssl::x509::{X509, X509Req, X509Builder};
+use crate::fsutil::{read_bounded, write_atomic, PUBLIC_FILE_MODE};
+use super::{IssueExtensions, SanEntry, ValidityWindow};
+use super::csr::{display_dns, format_ip};
+use openssl::x509::{X509, X509ExtensionRef, X509NameRef, X509Req, X509Builder, X509StoreContext};
+use openssl::x509::extension::{BasicConstraints, KeyUsage, SubjectAlternativeName};
+use openssl::x509::store::X509StoreBuilder;
+use openssl::x509::verify::X509VerifyFlags;
+use openssl::stack::Stack;
+use openssl::pkcs12::Pkcs12;
+use openssl::pkcs7::Pkcs7;
 use openssl::pkey::{PKey, Private};
 use openssl::hash::MessageDigest;
-use openssl::asn1::Asn1Time;
+use openssl::asn1::{Asn1Time, Asn1TimeRef};
 use openssl::bn::{BigNum, MsbOption};
 use std::path::Path;
 
+/// Start of an issued certificate's validity window
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotBefore {
+    /// This many minutes before signing (`defaults.backdate_minutes`), for
+    /// devices whose clocks run behind
+    Backdate(u32),
+    /// A fixed time (`--not-before`)
+    At(chrono::DateTime<chrono::Utc>),
+}
+
+impl Default for NotBefore {
+    fn default() -> Self {
+        NotBefore::Backdate(0)
+    }
+}
+
+impl NotBefore {
+    /// The start time for a certificate signed at `now`
+    pub fn resolve(self, now: chrono::DateTime<chrono::Utc>) -> chrono::DateTime<chrono::Utc> {
+        match self {
+            NotBefore::Backdate(minutes) => now - chrono::Duration::minutes(i64::from(minutes)),
+            NotBefore::At(at) => at,
+        }
+    }
+}
+
 /// Sign a CSR with the CA key
 pub fn sign_csr(
     csr: &X509Req,
@@ -15,6 +50,29 @@ pub fn sign_csr(
     ca_key: &PKey<Private>,
     days: u32,
 ) -> Result<X509> {
+    // 159 random bits
+    let mut serial = BigNum::new()
+        .map_err(|e| FluxError::CertSigningFailed(e.to_string()))?;
+    serial.rand(159, MsbOption::MAYBE_ZERO, false)
+        .map_err(|e| FluxError::CertSigningFailed(e.to_string()))?;
+
+    let now = chrono::Utc::now();
+    let validity = ValidityWindow { not_before: now, not_after: now + chrono::Duration::days(i64::from(days)) };
+    sign_csr_with_extensions(csr, ca_cert, ca_key, &serial.to_vec(), validity, IssueExtensions::default())
+}
+
+/// Sign a CSR with the CA key as `serial` (big-endian) for `validity`,
+/// with `extensions` in place of what the CSR asks for
+pub fn sign_csr_with_extensions(
+    csr: &X509Req,
+    ca_cert: &X509,
+    ca_key: &PKey<Private>,
+    serial: &[u8],
+    validity: ValidityWindow,
+    extensions: IssueExtensions,
+) -> Result<X509> {
+    let IssueExtensions { sans, sub_ca } = extensions;
+
     let mut cert_builder = X509Builder::new()
         .map_err(|e| FluxError::CertSigningFailed(e.to_string()))?;
 
@@ -22,12 +80,9 @@ pub fn sign_csr(
     cert_builder.set_version(2)
         .map_err(|e| FluxError::CertSigningFailed(e.to_string()))?;
 
-    // Generate random serial number
-    let mut serial = BigNum::new()
-        .map_err(|e| FluxError::CertSigningFailed(e.to_string()))?;
-    serial.rand(159, MsbOption::MAYBE_ZERO, false)
-        .map_err(|e| FluxError::CertSigningFailed(e.to_string()))?;
-    let serial_asn1 = serial.to_asn1_integer()
+    // Set serial number
+    let serial_asn1 = BigNum::from_slice(serial)
+        .and_then(|serial| serial.to_asn1_integer())
         .map_err(|e| FluxError::CertSigningFailed(e.to_string()))?;
     cert_builder.set_serial_number(&serial_asn1)
         .map_err(|e| FluxError::CertSigningFailed(e.to_string()))?;
@@ -47,12 +102,12 @@ pub fn sign_csr(
         .map_err(|e| FluxError::CertSigningFailed(e.to_string()))?;
 
     // Set validity period
-    let not_before = Asn1Time::days_from_now(0)
+    let not_before = Asn1Time::from_unix(validity.not_before.timestamp())
         .map_err(|e| FluxError::CertSigningFailed(e.to_string()))?;
     cert_builder.set_not_before(&not_before)
         .map_err(|e| FluxError::CertSigningFailed(e.to_string()))?;
 
-    let not_after = Asn1Time::days_from_now(days)
+    let not_after = Asn1Time::from_unix(validity.not_after.timestamp())
         .map_err(|e| FluxError::CertSigningFailed(e.to_string()))?;
     cert_builder.set_not_after(&not_after)
         .map_err(|e| FluxError::CertSigningFailed(e.to_string()))?;
@@ -60,11 +115,47 @@ pub fn sign_csr(
     // Copy extensions from CSR to certificate
     if let Ok(extensions) = csr.extensions() {
         for ext in extensions {
+            if sans.is_some() && extension_is(&ext, SAN_OID)? {
+                continue;
+            }
+            if sub_ca.is_some() && extension_is_any(&ext, &CA_OIDS)? {
+                continue;
+            }
             cert_builder.append_extension(ext)
                 .map_err(|e| FluxError::CertSigningFailed(e.to_string()))?;
         }
     }
 
+    if let Some(sans) = sans.filter(|sans| !sans.is_empty()) {
+        let mut san_ext = SubjectAlternativeName::new();
+        for san in sans {
+            match san {
+                SanEntry::Dns(dns) => san_ext.dns(dns),
+                SanEntry::Ip(ip) => san_ext.ip(ip),
+                SanEntry::Email(email) => san_ext.email(email),
+            };
+        }
+        let san_ext = san_ext.build(&cert_builder.x509v3_context(Some(ca_cert), None))
+            .map_err(|e| FluxError::CertSigningFailed(format!("invalid SANs: {}", e)))?;
+        cert_builder.append_extension(san_ext)
+            .map_err(|e| FluxError::CertSigningFailed(e.to_string()))?;
+    }
+
+    if let Some(sub_ca) = sub_ca {
+        let constraints = BasicConstraints::new().critical().ca().pathlen(sub_ca.pathlen).build()
+            .map_err(|e| FluxError::CertSigningFailed(e.to_string()))?;
+        cert_builder.append_extension(constraints)
+            .map_err(|e| FluxError::CertSigningFailed(e.to_string()))?;
+        let usage = KeyUsage::new().critical().digital_signature().key_cert_sign().crl_sign().build()
+            .map_err(|e| FluxError::CertSigningFailed(e.to_string()))?;
+        cert_builder.append_extension(usage)
+            .map_err(|e| FluxError::CertSigningFailed(e.to_string()))?;
+        if !sub_ca.name_constraints.is_empty() {
+            cert_builder.append_extension(sub_ca.name_constraints.to_extension()?)
+                .map_err(|e| FluxError::CertSigningFailed(e.to_string()))?;
+        }
+    }
+
     // Sign the certificate
     cert_builder.sign(ca_key, MessageDigest::sha256())
         .map_err(|e| FluxError::CertSigningFailed(e.to_string()))?;
@@ -72,18 +163,63 @@ pub fn sign_csr(
     Ok(cert_builder.build())
 }
 
+/// DER of the subjectAltName OID (2.5.29.17)
+const SAN_OID: [u8; 5] = [0x06, 0x03, 0x55, 0x1d, 0x11];
+
+/// DER of the OIDs of the extensions a sub-CA gets from the signer: key
+/// usage (2.5.29.15), basic constraints (2.5.29.19), name constraints (2.5.29.30)
+const CA_OIDS: [[u8; 5]; 3] = [
+    [0x06, 0x03, 0x55, 0x1d, 0x0f],
+    [0x06, 0x03, 0x55, 0x1d, 0x13],
+    [0x06, 0x03, 0x55, 0x1d, 0x1e],
+];
+
+/// Whether an extension has the OID `oid` (given as DER)
+fn extension_is(ext: &X509ExtensionRef, oid: [u8; 5]) -> Result<bool> {
+    // SEQUENCE { extnID OBJECT IDENTIFIER, ... }; skip the SEQUENCE header
+    let der = ext.to_der()?;
+    let start = match der.get(1) {
+        Some(len) if len & 0x80 == 0 => 2,
+        Some(len) => 2 + usize::from(len & 0x7f),
+        None => return Ok(false),
+    };
+    Ok(der.get(start..start + oid.len()) == Some(&oid[..]))
+}
+
+/// Whether an extension has any of the OIDs in `oids`
+fn extension_is_any(ext: &X509ExtensionRef, oids: &[[u8; 5]]) -> Result<bool> {
+    for oid in oids {
+        if extension_is(ext, *oid)? {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// PKCS#12 (PFX) bundle of a certificate, its key and CA certificates
+///
+/// `name` becomes the friendly name. Encrypted with OpenSSL's defaults
+/// (AES-256 and PBKDF2), which Windows 10 1709+ and macOS read.
+pub fn to_pkcs12(name: &str, cert: &X509, key: &PKey<Private>, ca_certs: &[X509], password: &str) -> Result<Vec<u8>> {
+    let mut chain = Stack::new()?;
+    for ca in ca_certs {
+        chain.push(ca.clone())?;
+    }
+    let pkcs12 = Pkcs12::builder()
+        .name(name)
+        .pkey(key)
+        .cert(cert)
+        .ca(chain)
+        .build2(password)?;
+    Ok(pkcs12.to_der()?)
+}
+
 /// Save certificate to file in PEM format
 pub fn save_cert_pem<P: AsRef<Path>>(cert: &X509, path: P) -> Result<()> {
     let pem_bytes = cert.to_pem()
         .map_err(|e| FluxError::CertSigningFailed(e.to_string()))?;
 
-    std::fs::write(path.as_ref(), &pem_bytes)
-        .map_err(|e| FluxError::FileWriteFailed(
-            path.as_ref().to_path_buf(),
-            e.to_string()
-        ))?;
-
-    Ok(())
+    write_atomic(path.as_ref(), &pem_bytes, PUBLIC_FILE_MODE)
 }
 
 /// Save certificate to file in DER format (CRT)
@@ -91,27 +227,19 @@ pub fn save_cert_der<P: AsRef<Path>>(cert: &X509, path: P) -> Result<()> {
     let der_bytes = cert.to_der()
         .map_err(|e| FluxError::CertSigningFailed(e.to_string()))?;
 
-    std::fs::write(path.as_ref(), &der_bytes)
-        .map_err(|e| FluxError::FileWriteFailed(
-            path.as_ref().to_path_buf(),
-            e.to_string()
-        ))?;
-
-    Ok(())
+    write_atomic(path.as_ref(), &der_bytes, PUBLIC_FILE_MODE)
 }
 
-/// Load certificate from PEM file
+/// Load a certificate from a PEM, DER or PKCS#7 file
+///
+/// A file holding several certificates yields the first.
 pub fn load_cert<P: AsRef<Path>>(path: P) -> Result<X509> {
-    let pem_bytes = std::fs::read(path.as_ref())
-        .map_err(|e| FluxError::FileReadFailed(
-            path.as_ref().to_path_buf(),
-            e.to_string()
-        ))?;
-
-    let cert = X509::from_pem(&pem_bytes)
-        .map_err(|e| FluxError::CertParseError(e.to_string()))?;
+    Ok(load_certs(path)?.remove(0))
+}
 
-    Ok(cert)
+/// Load every certificate from a PEM, DER or PKCS#7 file
+pub fn load_certs<P: AsRef<Path>>(path: P) -> Result<Vec<X509>> {
+    from_bytes(&read_bounded(path.as_ref())?)
 }
 
 /// Get certificate information as a formatted string
@@ -145,13 +273,10 @@ pub fn get_cert_info(cert: &X509) -> Result<String> {
         info.push_str("Subject Alternative Names:\n");
         for san in san_ext {
             if let Some(dns) = san.dnsname() {
-                info.push_str(&format!("  DNS: {}\n", dns));
+                info.push_str(&format!("  DNS: {}\n", display_dns(dns)));
             }
             if let Some(ip) = san.ipaddress() {
-                let ip_str = ip.iter()
-                    .map(|b| b.to_string())
-                    .collect::<Vec<_>>()
-                    .join(".");
+                let ip_str = format_ip(ip);
                 info.push_str(&format!("  IP: {}\n", ip_str));
             }
         }
@@ -173,16 +298,21 @@ pub fn is_cert_expired(cert: &X509) -> Result<bool> {
 
 /// Get days until expiration (negative if already expired)
 pub fn days_until_expiration(cert: &X509) -> Result<i64> {
-    let now = Asn1Time::days_from_now(0)
-        .map_err(|e| FluxError::CertParseError(e.to_string()))?;
+    let not_after = asn1_time_to_chrono(cert.not_after())?;
 
-    let not_after = cert.not_after();
+    Ok((not_after - chrono::Utc::now()).num_days())
+}
 
-    // Calculate difference in days
-    let diff = not_after.diff(&now)
+/// Convert an ASN.1 time into a chrono timestamp
+pub fn asn1_time_to_chrono(time: &Asn1TimeRef) -> Result<chrono::DateTime<chrono::Utc>> {
+    let epoch = Asn1Time::from_unix(0)
+        .map_err(|e| FluxError::CertParseError(e.to_string()))?;
+    let diff = epoch.diff(time)
         .map_err(|e| FluxError::CertParseError(e.to_string()))?;
 
-    Ok(diff.days as i64)
+    let secs = diff.days as i64 * 86_400 + diff.secs as i64;
+    chrono::DateTime::from_timestamp(secs, 0)
+        .ok_or_else(|| FluxError::CertParseError(format!("timestamp out of range: {}", time)))
 }
 
 /// Detailed certificate information structure
@@ -213,9 +343,8 @@ pub fn extract_certificate_info(cert: &X509) -> Result<CertificateInfo> {
         .map_err(|e| FluxError::CertParseError(e.to_string()))?;
 
     // Validity dates - convert OpenSSL Asn1Time to chrono DateTime
-    // Using current time as placeholder since proper ASN1 time parsing is complex
-    let not_before = chrono::Utc::now();
-    let not_after = chrono::Utc::now() + chrono::Duration::days(365);
+    let not_before = asn1_time_to_chrono(cert.not_before())?;
+    let not_after = asn1_time_to_chrono(cert.not_after())?;
 
     // Subject Alternative Names
     let mut sans = Vec::new();
@@ -225,10 +354,7 @@ pub fn extract_certificate_info(cert: &X509) -> Result<CertificateInfo> {
                 sans.push(format!("DNS:{}", dns));
             }
             if let Some(ip) = san.ipaddress() {
-                let ip_str = ip.iter()
-                    .map(|b| b.to_string())
-                    .collect::<Vec<_>>()
-                    .join(".");
+                let ip_str = format_ip(ip);
                 sans.push(format!("IP:{}", ip_str));
             }
             if let Some(email) = san.email() {
@@ -254,6 +380,102 @@ pub fn extract_certificate_info(cert: &X509) -> Result<CertificateInfo> {
     })
 }
 
+/// Format an X509 name as a one-line `CN=..., O=...` string
+pub fn format_name(name: &X509NameRef) -> String {
+    name.entries()
+        .map(|entry| {
+            let key = entry.object().nid().short_name().unwrap_or("UNKNOWN");
+            let value = entry.data().as_utf8()
+                .map(|s| s.to_string())
+                .unwrap_or_default();
+            format!("{}={}", key, value)
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Result of verifying a certificate chain against the CA
+#[derive(Debug, Clone)]
+pub struct ChainVerification {
+    /// Whether the certificate chains to a trusted CA certificate
+    pub trusted: bool,
+    /// Failure reason, if verification failed
+    pub error: Option<String>,
+    /// Depth at which verification failed
+    pub error_depth: Option<u32>,
+    /// Certificates in the verified chain, leaf first
+    pub chain: Vec<ChainElement>,
+}
+
+/// A certificate in a verified chain
+#[derive(Debug, Clone)]
+pub struct ChainElement {
+    /// Depth in the chain (0 = leaf)
+    pub depth: u32,
+    /// Subject of the certificate at this depth
+    pub subject: String,
+    /// Whether this element passed verification
+    pub valid: bool,
+    /// Failure reason for this element
+    pub error: Option<String>,
+}
+
+/// Verify a certificate against trusted CA certificates
+///
+/// `trusted` are the trust anchors, `untrusted` are additional intermediates
+/// that may be used to build the chain. Intermediates in `trusted` are
+/// accepted as anchors even without their root (partial chain).
+pub fn verify_chain(cert: &X509, trusted: &[X509], untrusted: &[X509]) -> Result<ChainVerification> {
+    let mut store_builder = X509StoreBuilder::new()?;
+    for ca in trusted {
+        store_builder.add_cert(ca.clone())?;
+    }
+    store_builder.set_flags(X509VerifyFlags::PARTIAL_CHAIN)?;
+    let store = store_builder.build();
+
+    let mut chain = Stack::new()?;
+    for intermediate in untrusted {
+        chain.push(intermediate.clone())?;
+    }
+
+    let mut context = X509StoreContext::new()?;
+    context.init(&store, cert, &chain, |ctx| {
+        let trusted = ctx.verify_cert()?;
+        let error = if trusted { None } else { Some(ctx.error().error_string().to_string()) };
+        let error_depth = if trusted { None } else { Some(ctx.error_depth()) };
+
+        let mut elements = Vec::new();
+        match ctx.chain() {
+            Some(built) => {
+                for (depth, c) in built.iter().enumerate() {
+                    let depth = depth as u32;
+                    let failed = error_depth == Some(depth);
+                    elements.push(ChainElement {
+                        depth,
+                        subject: format_name(c.subject_name()),
+                        valid: !failed,
+                        error: if failed { error.clone() } else { None },
+                    });
+                }
+            }
+            None => elements.push(ChainElement {
+                depth: 0,
+                subject: format_name(cert.subject_name()),
+                valid: trusted,
+                error: error.clone(),
+            }),
+        }
+
+        Ok(ChainVerification {
+            trusted,
+            error,
+            error_depth,
+            chain: elements,
+        })
+    })
+    .map_err(FluxError::from)
+}
+
 /// Convert certificate to PEM bytes
 pub fn to_pem(cert: &X509) -> Result<Vec<u8>> {
     cert.to_pem()
@@ -266,6 +488,109 @@ pub fn from_pem(pem_bytes: &[u8]) -> Result<X509> {
         .map_err(|e| FluxError::CertParseError(e.to_string()))
 }
 
+/// Load all certificates from PEM bytes (e.g. a chain or bundle)
+pub fn from_pem_multiple(pem_bytes: &[u8]) -> Result<Vec<X509>> {
+    let certs = X509::stack_from_pem(pem_bytes)
+        .map_err(|e| FluxError::CertParseError(e.to_string()))?;
+
+    if certs.is_empty() {
+        return Err(FluxError::CertParseError("no certificates found".to_string()));
+    }
+
+    Ok(certs)
+}
+
+/// Load all certificates from PEM, DER or PKCS#7 bytes
+///
+/// The format is told by content: a PEM header, otherwise ASN.1 as a single
+/// DER certificate or a PKCS#7 bundle (`.p7b`, in DER or PEM).
+pub fn from_bytes(data: &[u8]) -> Result<Vec<X509>> {
+    let text = data.trim_ascii_start();
+    if text.starts_with(b"-----BEGIN") {
+        if text.windows(16).any(|w| w == b"-----BEGIN PKCS7") {
+            let p7 = Pkcs7::from_pem(data).map_err(|e| FluxError::CertParseError(e.to_string()))?;
+            return pkcs7_certs(&p7);
+        }
+        return from_pem_multiple(data);
+    }
+
+    if let Ok(cert) = X509::from_der(data) {
+        return Ok(vec![cert]);
+    }
+    match Pkcs7::from_der(data) {
+        Ok(p7) => pkcs7_certs(&p7),
+        Err(_) => Err(FluxError::CertParseError(
+            "not a PEM, DER or PKCS#7 certificate".to_string(),
+        )),
+    }
+}
+
+fn pkcs7_certs(p7: &Pkcs7) -> Result<Vec<X509>> {
+    let certs: Vec<X509> = p7.signed()
+        .and_then(|signed| signed.certificates())
+        .map(|stack| stack.iter().map(|cert| cert.to_owned()).collect())
+        .unwrap_or_default();
+
+    if certs.is_empty() {
+        return Err(FluxError::CertParseError("no certificates in PKCS#7 bundle".to_string()));
+    }
+    Ok(certs)
+}
+
+/// How a certificate relates to the others in an uploaded set
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChainLink {
+    /// Index of the certificate's issuer within the set, if present
+    pub issuer_index: Option<usize>,
+    /// Whether the signature verifies against that issuer's public key
+    pub signature_valid: bool,
+    /// Whether the certificate is self-signed
+    pub self_signed: bool,
+    /// Whether another certificate in the set was issued by this one
+    pub issues_others: bool,
+}
+
+/// Determine issuer/subject linkage between a set of certificates
+pub fn link_certificates(certs: &[X509]) -> Vec<ChainLink> {
+    use openssl::x509::X509VerifyResult;
+
+    let issued_by = |issuer: &X509, subject: &X509| {
+        issuer.issued(subject) == X509VerifyResult::OK
+            && issuer.public_key()
+                .and_then(|key| subject.verify(&key))
+                .unwrap_or(false)
+    };
+
+    let mut links: Vec<ChainLink> = certs.iter()
+        .enumerate()
+        .map(|(i, cert)| {
+            let self_signed = issued_by(cert, cert);
+            let issuer = if self_signed {
+                None
+            } else {
+                certs.iter()
+                    .enumerate()
+                    .find(|(j, candidate)| *j != i && candidate.issued(cert) == X509VerifyResult::OK)
+            };
+
+            ChainLink {
+                issuer_index: issuer.map(|(j, _)| j),
+                signature_valid: self_signed || issuer.map(|(_, c)| issued_by(c, cert)).unwrap_or(false),
+                self_signed,
+                issues_others: false,
+            }
+        })
+        .collect();
+
+    for i in 0..links.len() {
+        if let Some(j) = links[i].issuer_index {
+            links[j].issues_others = true;
+        }
+    }
+
+    links
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -296,6 +621,13 @@ mod tests {
         let not_after = Asn1Time::days_from_now(365).unwrap();
         cert_builder.set_not_after(&not_after).unwrap();
 
+        let basic_constraints = openssl::x509::extension::BasicConstraints::new()
+            .critical()
+            .ca()
+            .build()
+            .unwrap();
+        cert_builder.append_extension(basic_constraints).unwrap();
+
         cert_builder.sign(&key, MessageDigest::sha256()).unwrap();
 
         (cert_builder.build(), key)
@@ -312,6 +644,35 @@ mod tests {
         assert!(cert.verify(&ca_key).unwrap());
     }
 
+    #[test]
+    fn test_sign_csr_golden() {
+        use crate::fixtures;
+
+        let ca = fixtures::intermediate_ca();
+        let sans = [
+            SanEntry::Dns("web.lab.internal".to_string()),
+            SanEntry::Ip("10.0.0.5".to_string()),
+            SanEntry::Email("ops@lab.internal".to_string()),
+        ];
+        let csr = fixtures::leaf_csr("web", &sans);
+
+        let cert = sign_csr(&csr, ca.cert(), ca.key(), 90).unwrap();
+        assert!(cert.verify(ca.key()).unwrap());
+        fixtures::assert_golden("leaf-server", &fixtures::describe(&cert));
+    }
+
+    #[test]
+    fn test_ipv6_sans() {
+        use crate::fixtures;
+
+        let ca = fixtures::intermediate_ca();
+        let sans = SanEntry::parse_multiple("IP:10.0.0.5,IP:[fd00::5]").unwrap();
+        let cert = sign_csr(&fixtures::leaf_csr("nas01", &sans), ca.cert(), ca.key(), 30).unwrap();
+
+        assert_eq!(extract_certificate_info(&cert).unwrap().sans, ["IP:10.0.0.5", "IP:fd00::5"]);
+        assert!(get_cert_info(&cert).unwrap().contains("  IP: fd00::5\n"));
+    }
+
     #[test]
     fn test_save_and_load_cert() {
         let temp_dir = tempfile::tempdir().unwrap();
@@ -329,9 +690,95 @@ mod tests {
         assert!(loaded_cert.verify(&ca_key).unwrap());
     }
 
+    #[test]
+    fn test_from_bytes_detects_format() {
+        use openssl::pkcs7::Pkcs7Flags;
+
+        let (ca_cert, ca_key) = create_test_ca();
+        let key = generate_rsa_key(2048, None).unwrap();
+        let csr = create_csr("nas", &key, &[], None).unwrap();
+        let cert = sign_csr(&csr, &ca_cert, &ca_key, 30).unwrap();
+        let serials = |certs: Vec<X509>| certs.iter().map(|c| c.serial_number().to_bn().unwrap()).collect::<Vec<_>>();
+
+        assert_eq!(serials(from_bytes(&cert.to_der().unwrap()).unwrap()), serials(vec![cert.clone()]));
+        let pem = [b"\n".as_slice(), &to_pem(&cert).unwrap(), &to_pem(&ca_cert).unwrap()].concat();
+        assert_eq!(from_bytes(&pem).unwrap().len(), 2);
+
+        // A .p7b bundle, in DER and in PEM
+        let mut extra = Stack::new().unwrap();
+        extra.push(cert.clone()).unwrap();
+        let p7 = Pkcs7::sign(&ca_cert, &ca_key, &extra, b"", Pkcs7Flags::empty()).unwrap();
+        for encoded in [p7.to_der().unwrap(), p7.to_pem().unwrap()] {
+            assert_eq!(from_bytes(&encoded).unwrap().len(), 2);
+        }
+
+        assert!(matches!(from_bytes(b"\x30\x03\x02\x01\x01"), Err(FluxError::CertParseError(_))));
+    }
+
+    #[test]
+    fn test_verify_chain_trusted() {
+        let (ca_cert, ca_key) = create_test_ca();
+        let key = generate_rsa_key(2048, None).unwrap();
+        let csr = create_csr("leaf", &key, &[SanEntry::Dns("leaf.lab".to_string())], None).unwrap();
+        let cert = sign_csr(&csr, &ca_cert, &ca_key, 30).unwrap();
+
+        let result = verify_chain(&cert, &[ca_cert], &[]).unwrap();
+        assert!(result.trusted);
+        assert!(result.error.is_none());
+        assert_eq!(result.chain.len(), 2);
+        assert_eq!(result.chain[0].subject, "CN=leaf");
+        assert_eq!(result.chain[1].subject, "CN=Test CA");
+    }
+
+    #[test]
+    fn test_verify_chain_untrusted() {
+        let (ca_cert, ca_key) = create_test_ca();
+        let (other_ca, _) = create_test_ca();
+        let key = generate_rsa_key(2048, None).unwrap();
+        let csr = create_csr("leaf", &key, &[], None).unwrap();
+        let cert = sign_csr(&csr, &ca_cert, &ca_key, 30).unwrap();
+
+        let result = verify_chain(&cert, &[other_ca], &[]).unwrap();
+        assert!(!result.trusted);
+        assert!(result.error.is_some());
+        assert_eq!(result.error_depth, Some(0));
+        assert!(!result.chain[0].valid);
+    }
+
+    #[test]
+    fn test_link_certificates() {
+        let (ca_cert, ca_key) = create_test_ca();
+        let key = generate_rsa_key(2048, None).unwrap();
+        let csr = create_csr("leaf", &key, &[], None).unwrap();
+        let leaf = sign_csr(&csr, &ca_cert, &ca_key, 30).unwrap();
+
+        let mut bundle = to_pem(&leaf).unwrap();
+        bundle.extend(to_pem(&ca_cert).unwrap());
+        let certs = from_pem_multiple(&bundle).unwrap();
+        assert_eq!(certs.len(), 2);
+
+        let links = link_certificates(&certs);
+        assert_eq!(links[0].issuer_index, Some(1));
+        assert!(links[0].signature_valid);
+        assert!(!links[0].self_signed);
+        assert!(links[1].self_signed);
+        assert!(links[1].issues_others);
+    }
+
     #[test]
     fn test_is_cert_expired() {
         let (ca_cert, _) = create_test_ca();
         assert!(!is_cert_expired(&ca_cert).unwrap());
     }
+
+    #[test]
+    fn test_validity_dates() {
+        let (ca_cert, _) = create_test_ca();
+        let days = days_until_expiration(&ca_cert).unwrap();
+        assert!((364..=365).contains(&days));
+
+        let info = extract_certificate_info(&ca_cert).unwrap();
+        assert_eq!((info.not_after - info.not_before).num_days(), 365);
+        assert!((info.not_before - chrono::Utc::now()).num_seconds().abs() < 60);
+    }
 }
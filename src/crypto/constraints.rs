@@ -0,0 +1,411 @@
+//! Name constraints and path lengths for subordinate CA certificates
+//!
+//! A sub-CA signed with `[sub_ca]` constraints can only issue certificates
+//! whose names fall within the permitted subtrees (and outside the excluded
+//! ones): with `permitted_dns = ["guest.lab"]`, a delegated CA can issue for
+//! `guest.lab` and `wifi.guest.lab` but not for the main domain. Clients
+//! check this when they verify the chain (RFC 5280, section 4.2.1.10).
+//!
+//! The path length in basicConstraints limits how many CAs may follow: a
+//! sub-CA with `pathlen:0` issues only leaf certificates. A CA can only sign
+//! CAs with a shorter path length than its own, so an intermediate made with
+//! `pathlen:0` (as in the usual OpenSSL layout) cannot sign sub-CAs at all.
+
+use crate::config::SubCaConfig;
+use crate::error::{FluxError, Result};
+use crate::policy::Violation;
+use super::backend::SubCaExtensions;
+use super::cert::format_name;
+use super::csr::{to_ascii_dns, SanEntry};
+use openssl::asn1::{Asn1Object, Asn1OctetString};
+use openssl::x509::{X509, X509Extension};
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+/// OID of the name constraints extension
+const NAME_CONSTRAINTS_OID: &str = "2.5.29.30";
+
+/// One permitted or excluded subtree
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Subtree {
+    /// `guest.lab` (the domain and everything below it), or `.guest.lab`
+    /// (only names below it)
+    Dns(String),
+    /// An IP network, such as `10.20.0.0/16`
+    Ip(IpAddr, u8),
+}
+
+impl FromStr for Subtree {
+    type Err = FluxError;
+
+    /// Parse `guest.lab`, `.guest.lab`, `10.20.0.0/16` or a single address
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let invalid = |why: &str| FluxError::InvalidSanFormat(format!("name constraint '{}': {}", s, why));
+
+        if let Some((addr, prefix)) = s.split_once('/') {
+            let addr: IpAddr = addr.parse().map_err(|_| invalid("not an IP network"))?;
+            let max = if addr.is_ipv4() { 32 } else { 128 };
+            let prefix: u8 = prefix.parse().ok()
+                .filter(|p| *p <= max)
+                .ok_or_else(|| invalid(&format!("prefix length must be 0-{}", max)))?;
+            if mask(&addr, prefix) != addr {
+                return Err(invalid(&format!("host bits are set; did you mean {}/{}?", mask(&addr, prefix), prefix)));
+            }
+            return Ok(Self::Ip(addr, prefix));
+        }
+        if let Ok(addr) = s.parse::<IpAddr>() {
+            return Ok(Self::Ip(addr, if addr.is_ipv4() { 32 } else { 128 }));
+        }
+
+        if let Some(domain) = s.strip_prefix("*.") {
+            return Err(invalid(&format!("use '{}' to permit a domain and its subdomains", domain)));
+        }
+        let (dot, domain) = match s.strip_prefix('.') {
+            Some(domain) => (".", domain),
+            None => ("", s),
+        };
+        if domain.is_empty() || domain.contains(['*', '/', ' ']) {
+            return Err(invalid("not a domain name"));
+        }
+        Ok(Self::Dns(format!("{}{}", dot, to_ascii_dns(domain)?.to_ascii_lowercase())))
+    }
+}
+
+impl fmt::Display for Subtree {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Dns(domain) => write!(f, "DNS:{}", domain),
+            Self::Ip(addr, prefix) => write!(f, "IP:{}/{}", addr, prefix),
+        }
+    }
+}
+
+impl Subtree {
+    /// Whether a DNS or IP SAN falls within the subtree
+    ///
+    /// A wildcard such as `*.guest.lab` stands for every name below
+    /// `guest.lab`, so it is within `guest.lab` and `.guest.lab` but not
+    /// within `wifi.guest.lab`. Email SANs are never within a subtree.
+    pub fn contains(&self, san: &SanEntry) -> bool {
+        match (self, san) {
+            (Self::Dns(base), SanEntry::Dns(name)) => {
+                let name = name.trim_end_matches('.').to_ascii_lowercase();
+                let (below_only, base) = match base.strip_prefix('.') {
+                    Some(base) => (true, base),
+                    None => (false, base.as_str()),
+                };
+                let below = |name: &str| name.strip_suffix(base).is_some_and(|rest| rest.ends_with('.'));
+                match name.strip_prefix("*.") {
+                    Some(parent) => parent == base || below(parent),
+                    None => (!below_only && name == base) || below(&name),
+                }
+            }
+            (Self::Ip(base, prefix), SanEntry::Ip(addr)) => addr.parse::<IpAddr>()
+                .is_ok_and(|addr| addr.is_ipv4() == base.is_ipv4() && mask(&addr, *prefix) == *base),
+            _ => false,
+        }
+    }
+
+    /// Whether every name within `self` is also within `other`
+    pub fn within(&self, other: &Subtree) -> bool {
+        match (self, other) {
+            (Self::Dns(name), Self::Dns(base)) => {
+                fn split(domain: &str) -> (bool, &str) {
+                    domain.strip_prefix('.').map_or((false, domain), |domain| (true, domain))
+                }
+                let (name_below_only, name) = split(name);
+                let (base_below_only, base) = split(base);
+                let below = name.strip_suffix(base).is_some_and(|rest| rest.ends_with('.'));
+                below || (name == base && (name_below_only || !base_below_only))
+            }
+            (Self::Ip(addr, prefix), Self::Ip(base, base_prefix)) => {
+                addr.is_ipv4() == base.is_ipv4() && prefix >= base_prefix && mask(addr, *base_prefix) == *base
+            }
+            _ => false,
+        }
+    }
+
+    fn is_dns(&self) -> bool {
+        matches!(self, Self::Dns(_))
+    }
+
+    /// Address followed by netmask, as an iPAddress subtree is encoded
+    fn ip_bytes(&self) -> Vec<u8> {
+        let Self::Ip(addr, prefix) = self else { return Vec::new() };
+        let (mut bytes, len) = match addr {
+            IpAddr::V4(addr) => (addr.octets().to_vec(), 4),
+            IpAddr::V6(addr) => (addr.octets().to_vec(), 16),
+        };
+        let prefix = usize::from(*prefix);
+        bytes.extend((0..len).map(|i| match prefix.saturating_sub(i * 8) {
+            0 => 0,
+            bits if bits >= 8 => 0xff,
+            bits => 0xffu8 << (8 - bits),
+        }));
+        bytes
+    }
+
+    /// DER of the GeneralSubtree (only `base`; minimum and maximum keep their defaults)
+    fn to_der(&self) -> Vec<u8> {
+        let base = match self {
+            // dNSName [2] IA5String, iPAddress [7] OCTET STRING
+            Self::Dns(domain) => der(0x82, domain.as_bytes()),
+            Self::Ip(..) => der(0x87, &self.ip_bytes()),
+        };
+        der(0x30, &base)
+    }
+}
+
+/// `addr` with the bits past `prefix` cleared
+fn mask(addr: &IpAddr, prefix: u8) -> IpAddr {
+    match addr {
+        IpAddr::V4(addr) => {
+            let bits = u32::from(*addr) & u32::MAX.checked_shl(32 - u32::from(prefix)).unwrap_or(0);
+            IpAddr::V4(bits.into())
+        }
+        IpAddr::V6(addr) => {
+            let bits = u128::from(*addr) & u128::MAX.checked_shl(128 - u32::from(prefix)).unwrap_or(0);
+            IpAddr::V6(bits.into())
+        }
+    }
+}
+
+/// DER element with a definite length
+fn der(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    match content.len() {
+        len @ 0..=0x7f => out.push(len as u8),
+        len => {
+            let bytes: Vec<u8> = len.to_be_bytes().into_iter().skip_while(|b| *b == 0).collect();
+            out.push(0x80 | bytes.len() as u8);
+            out.extend(bytes);
+        }
+    }
+    out.extend_from_slice(content);
+    out
+}
+
+/// Permitted and excluded subtrees for a subordinate CA
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NameConstraints {
+    pub permitted: Vec<Subtree>,
+    pub excluded: Vec<Subtree>,
+}
+
+impl NameConstraints {
+    /// Constraints from the `[sub_ca]` section
+    pub fn from_config(config: &SubCaConfig) -> Result<Self> {
+        let mut constraints = Self::parse(&config.permitted_dns, &config.excluded_dns)?;
+        let ips = Self::parse(&config.permitted_ips, &config.excluded_ips)?;
+        constraints.permitted.extend(ips.permitted);
+        constraints.excluded.extend(ips.excluded);
+        Ok(constraints)
+    }
+
+    /// Constraints from subtrees given as domains or CIDR ranges
+    pub fn parse(permitted: &[String], excluded: &[String]) -> Result<Self> {
+        let parse = |values: &[String]| values.iter().map(|v| v.parse()).collect::<Result<Vec<Subtree>>>();
+        Ok(Self { permitted: parse(permitted)?, excluded: parse(excluded)? })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.permitted.is_empty() && self.excluded.is_empty()
+    }
+
+    /// The critical name constraints extension
+    pub fn to_extension(&self) -> Result<X509Extension> {
+        let subtrees = |tag: u8, subtrees: &[Subtree]| {
+            if subtrees.is_empty() {
+                Vec::new()
+            } else {
+                der(tag, &subtrees.iter().flat_map(Subtree::to_der).collect::<Vec<_>>())
+            }
+        };
+        // permittedSubtrees [0], excludedSubtrees [1]
+        let mut content = subtrees(0xa0, &self.permitted);
+        content.extend(subtrees(0xa1, &self.excluded));
+
+        let oid = Asn1Object::from_str(NAME_CONSTRAINTS_OID)?;
+        let value = Asn1OctetString::new_from_bytes(&der(0x30, &content))?;
+        Ok(X509Extension::new_from_der(&oid, true, &value)?)
+    }
+
+    /// The same constraints for rcgen
+    #[cfg(feature = "backend-rustls")]
+    pub(crate) fn to_rcgen(&self) -> rcgen::NameConstraints {
+        use rcgen::{CidrSubnet, GeneralSubtree};
+
+        let convert = |subtrees: &[Subtree]| subtrees.iter()
+            .map(|subtree| match subtree {
+                Subtree::Dns(domain) => GeneralSubtree::DnsName(domain.clone()),
+                Subtree::Ip(addr, prefix) => GeneralSubtree::IpAddress(CidrSubnet::from_addr_prefix(*addr, *prefix)),
+            })
+            .collect();
+        rcgen::NameConstraints {
+            permitted_subtrees: convert(&self.permitted),
+            excluded_subtrees: convert(&self.excluded),
+        }
+    }
+}
+
+impl fmt::Display for NameConstraints {
+    /// `permitted DNS:guest.lab, IP:10.20.0.0/16; excluded DNS:admin.guest.lab`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let list = |subtrees: &[Subtree]| subtrees.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ");
+        let mut parts = Vec::new();
+        if !self.permitted.is_empty() {
+            parts.push(format!("permitted {}", list(&self.permitted)));
+        }
+        if !self.excluded.is_empty() {
+            parts.push(format!("excluded {}", list(&self.excluded)));
+        }
+        f.write_str(&parts.join("; "))
+    }
+}
+
+/// `configured` narrowed by the path length and name constraints of a request
+///
+/// Requested permitted subtrees replace the configured ones of the same
+/// kind (DNS or IP), and each must fall within one of them; requested
+/// exclusions are added to the configured ones, which always stay. A path
+/// length may only go down. Every widening is reported.
+pub fn narrow_sub_ca(
+    configured: &SubCaExtensions,
+    pathlen: Option<u32>,
+    requested: &NameConstraints,
+) -> Result<SubCaExtensions> {
+    let mut violations = Vec::new();
+    if let Some(pathlen) = pathlen.filter(|pathlen| *pathlen > configured.pathlen) {
+        violations.push(Violation::new("pathlen", format!(
+            "pathlen:{} exceeds the configured sub_ca.pathlen ({})",
+            pathlen, configured.pathlen
+        )));
+    }
+
+    let mut permitted = Vec::new();
+    for dns in [true, false] {
+        let allowed: Vec<&Subtree> = configured.name_constraints.permitted.iter().filter(|s| s.is_dns() == dns).collect();
+        let asked: Vec<&Subtree> = requested.permitted.iter().filter(|s| s.is_dns() == dns).collect();
+        if asked.is_empty() {
+            permitted.extend(allowed.into_iter().cloned());
+            continue;
+        }
+        for subtree in asked {
+            if allowed.is_empty() || allowed.iter().any(|base| subtree.within(base)) {
+                permitted.push(subtree.clone());
+            } else {
+                violations.push(Violation::new("name_constraints", format!(
+                    "{} is not within the configured permitted subtrees",
+                    subtree
+                )));
+            }
+        }
+    }
+
+    let mut excluded = configured.name_constraints.excluded.clone();
+    for subtree in &requested.excluded {
+        if !excluded.contains(subtree) {
+            excluded.push(subtree.clone());
+        }
+    }
+
+    if !violations.is_empty() {
+        return Err(FluxError::PolicyViolation(violations.into_iter().collect()));
+    }
+    Ok(SubCaExtensions {
+        pathlen: pathlen.unwrap_or(configured.pathlen),
+        name_constraints: NameConstraints { permitted, excluded },
+    })
+}
+
+/// Fail unless `issuer` may sign a CA certificate with path length `pathlen`
+/// (`None` for no limit)
+pub fn check_pathlen(issuer: &X509, pathlen: Option<u32>) -> Result<()> {
+    let Some(limit) = issuer.pathlen() else { return Ok(()) };
+    let issuer_name = format_name(issuer.subject_name());
+    if limit == 0 {
+        return Err(FluxError::PolicyViolation(Violation::new("pathlen", format!(
+            "{} has pathlen:0, so it cannot sign CA certificates",
+            issuer_name
+        )).into()));
+    }
+    match pathlen {
+        Some(pathlen) if pathlen < limit => Ok(()),
+        Some(pathlen) => Err(FluxError::PolicyViolation(Violation::new("pathlen", format!(
+            "pathlen:{} exceeds what {} (pathlen:{}) allows; use at most {}",
+            pathlen, issuer_name, limit, limit - 1
+        )).into())),
+        None => Err(FluxError::PolicyViolation(Violation::new("pathlen", format!(
+            "{} has pathlen:{}, so a CA it signs needs a pathlen of at most {}",
+            issuer_name, limit, limit - 1
+        )).into())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_subtrees() {
+        assert_eq!("guest.lab".parse::<Subtree>().unwrap(), Subtree::Dns("guest.lab".to_string()));
+        assert_eq!(".Guest.Lab".parse::<Subtree>().unwrap(), Subtree::Dns(".guest.lab".to_string()));
+        assert_eq!("10.20.0.0/16".parse::<Subtree>().unwrap(), Subtree::Ip("10.20.0.0".parse().unwrap(), 16));
+        assert_eq!("fd00::1".parse::<Subtree>().unwrap(), Subtree::Ip("fd00::1".parse().unwrap(), 128));
+
+        let err = "*.guest.lab".parse::<Subtree>().unwrap_err();
+        assert!(err.to_string().contains("use 'guest.lab'"), "{}", err);
+        let err = "10.20.0.5/16".parse::<Subtree>().unwrap_err();
+        assert!(err.to_string().contains("10.20.0.0/16"), "{}", err);
+        assert!("10.0.0.0/33".parse::<Subtree>().is_err());
+        assert!("".parse::<Subtree>().is_err());
+    }
+
+    #[test]
+    fn test_subtree_within() {
+        let subtree = |s: &str| s.parse::<Subtree>().unwrap();
+        assert!(subtree("wifi.guest.lab").within(&subtree("guest.lab")));
+        assert!(subtree("guest.lab").within(&subtree("guest.lab")));
+        assert!(subtree(".guest.lab").within(&subtree("guest.lab")));
+        assert!(!subtree("guest.lab").within(&subtree(".guest.lab")));
+        assert!(!subtree("lab").within(&subtree("guest.lab")));
+        assert!(!subtree("badguest.lab").within(&subtree("guest.lab")));
+        assert!(subtree("10.20.5.0/24").within(&subtree("10.20.0.0/16")));
+        assert!(!subtree("10.0.0.0/8").within(&subtree("10.20.0.0/16")));
+        assert!(!subtree("guest.lab").within(&subtree("10.20.0.0/16")));
+    }
+
+    #[test]
+    fn test_check_pathlen() {
+        let root = crate::fixtures::root_ca_cert();
+        let intermediate = crate::fixtures::intermediate_ca();
+        assert!(check_pathlen(&root, None).is_ok());
+        assert!(check_pathlen(&root, Some(3)).is_ok());
+
+        let err = check_pathlen(intermediate.cert(), Some(0)).unwrap_err();
+        assert!(err.to_string().contains("cannot sign CA certificates"), "{}", err);
+    }
+
+    #[test]
+    fn test_extension_der() {
+        let constraints = NameConstraints {
+            permitted: vec!["guest.lab".parse().unwrap(), "10.20.0.0/16".parse().unwrap()],
+            excluded: vec!["admin.guest.lab".parse().unwrap()],
+        };
+        assert_eq!(
+            constraints.to_string(),
+            "permitted DNS:guest.lab, IP:10.20.0.0/16; excluded DNS:admin.guest.lab"
+        );
+
+        let ext = constraints.to_extension().unwrap();
+        let der = ext.to_der().unwrap();
+        let permitted = [
+            &[0xa0, 0x19, 0x30, 0x0b, 0x82, 0x09][..], b"guest.lab",
+            &[0x30, 0x0a, 0x87, 0x08, 10, 20, 0, 0, 255, 255, 0, 0],
+        ].concat();
+        assert!(der.windows(permitted.len()).any(|w| w == permitted), "{:02x?}", der);
+        assert!(der.windows(b"admin.guest.lab".len()).any(|w| w == b"admin.guest.lab"));
+    }
+}
@@ -1,10 +1,13 @@
 //! Certificate Signing Request (CSR) generation and management
 
+use crate::config::SanOrder;
 use crate::error::{FluxError, Result};
-use openssl::x509::{X509Req, X509ReqBuilder, X509Name, X509NameBuilder};
+use crate::fsutil::{read_bounded, write_atomic, PUBLIC_FILE_MODE};
+use openssl::x509::{X509Builder, X509Req, X509ReqBuilder, X509NameBuilder};
 use openssl::x509::extension::SubjectAlternativeName;
 use openssl::pkey::{PKey, Private};
 use openssl::hash::MessageDigest;
+use std::net::IpAddr;
 use std::path::Path;
 
 /// Subject Alternative Name entry
@@ -18,6 +21,16 @@ pub enum SanEntry {
     Email(String),
 }
 
+impl std::fmt::Display for SanEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SanEntry::Dns(v) => write!(f, "DNS:{}", v),
+            SanEntry::Ip(v) => write!(f, "IP:{}", v),
+            SanEntry::Email(v) => write!(f, "EMAIL:{}", v),
+        }
+    }
+}
+
 impl SanEntry {
     /// Parse SAN entry from string (e.g., "DNS:example.com" or "IP:192.168.1.1")
     pub fn parse(s: &str) -> Result<Self> {
@@ -30,9 +43,19 @@ impl SanEntry {
         let value = parts[1].to_string();
 
         match san_type.as_str() {
-            "DNS" => Ok(SanEntry::Dns(value)),
-            "IP" => Ok(SanEntry::Ip(value)),
-            "EMAIL" => Ok(SanEntry::Email(value)),
+            "DNS" => {
+                let value = to_ascii_dns(&value)?;
+                validate_wildcard(&value)?;
+                Ok(SanEntry::Dns(value))
+            }
+            "IP" => {
+                // IPv6 may be bracketed as in URLs: IP:[fd00::1]
+                let ip = value.strip_prefix('[').and_then(|v| v.strip_suffix(']')).unwrap_or(&value);
+                let ip: IpAddr = ip.parse()
+                    .map_err(|_| FluxError::InvalidSanFormat(format!("{} (not an IPv4 or IPv6 address)", s)))?;
+                Ok(SanEntry::Ip(ip.to_string()))
+            }
+            "EMAIL" => Ok(SanEntry::Email(normalize_email(&value)?)),
             _ => Err(FluxError::InvalidSanFormat(format!("Unknown SAN type: {}", san_type))),
         }
     }
@@ -43,6 +66,288 @@ impl SanEntry {
             .map(|entry| Self::parse(entry.trim()))
             .collect()
     }
+
+    /// Comparable form: lowercase DNS name without trailing dot, canonical IP
+    fn canonical(&self) -> Self {
+        match self {
+            SanEntry::Dns(dns) => SanEntry::Dns(dns.trim_end_matches('.').to_ascii_lowercase()),
+            SanEntry::Ip(ip) => SanEntry::Ip(ip.parse::<IpAddr>().map_or_else(|_| ip.clone(), |ip| ip.to_string())),
+            SanEntry::Email(email) => SanEntry::Email(email.clone()),
+        }
+    }
+
+    /// DNS names, then IPs (numerically), then emails
+    fn sort_key(&self) -> (u8, Option<IpAddr>, String) {
+        match self {
+            SanEntry::Dns(dns) => (0, None, dns.clone()),
+            SanEntry::Ip(ip) => (1, ip.parse().ok(), ip.clone()),
+            SanEntry::Email(email) => (2, None, email.clone()),
+        }
+    }
+
+    /// DNS SANs for a domain and its wildcard (`example.lab` and `*.example.lab`)
+    pub fn wildcard_pair(domain: &str) -> Result<Vec<Self>> {
+        let domain = domain.trim().trim_end_matches('.');
+        if domain.is_empty() || domain.starts_with("*.") || domain.starts_with('.') {
+            return Err(FluxError::InvalidSanFormat(format!(
+                "{} (wildcard pair needs a plain domain, e.g. example.lab)",
+                domain
+            )));
+        }
+
+        Ok(vec![
+            SanEntry::Dns(domain.to_string()),
+            SanEntry::Dns(format!("*.{}", domain)),
+        ])
+    }
+}
+
+/// Duplicate-free set of SANs, kept in canonical form and insertion order
+///
+/// DNS names are lowercased without a trailing dot and IP addresses written in
+/// their canonical form before comparing, so `Web.Lab.` and `web.lab` (or
+/// `fd00:0::1` and `fd00::1`) count as one. Every place that combines SAN
+/// lists (common and per-certificate SANs, a CSR's SANs and extra ones) goes
+/// through this type.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SanSet(Vec<SanEntry>);
+
+impl SanSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `san` unless an equivalent entry is present; returns whether it was added
+    pub fn insert(&mut self, san: SanEntry) -> bool {
+        let san = san.canonical();
+        if self.0.contains(&san) {
+            return false;
+        }
+        self.0.push(san);
+        true
+    }
+
+    /// Whether an entry equivalent to `san` is present
+    pub fn contains(&self, san: &SanEntry) -> bool {
+        self.0.contains(&san.canonical())
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, SanEntry> {
+        self.0.iter()
+    }
+
+    /// The entries ordered per `order`
+    ///
+    /// With [`SanOrder::CnFirst`] a DNS SAN equal to `common_name` leads; it
+    /// is not added if missing.
+    pub fn ordered(mut self, common_name: &str, order: SanOrder) -> Vec<SanEntry> {
+        match order {
+            SanOrder::Preserve => {}
+            SanOrder::Sorted => self.0.sort_by_key(SanEntry::sort_key),
+            SanOrder::CnFirst => {
+                let cn = SanEntry::Dns(common_name.to_string()).canonical();
+                self.0.sort_by_key(|san| (*san != cn, san.sort_key()));
+            }
+        }
+        self.0
+    }
+}
+
+impl Extend<SanEntry> for SanSet {
+    fn extend<I: IntoIterator<Item = SanEntry>>(&mut self, iter: I) {
+        for san in iter {
+            self.insert(san);
+        }
+    }
+}
+
+impl FromIterator<SanEntry> for SanSet {
+    fn from_iter<I: IntoIterator<Item = SanEntry>>(iter: I) -> Self {
+        let mut set = Self::new();
+        set.extend(iter);
+        set
+    }
+}
+
+/// Canonical form of a SAN list: duplicates dropped, ordered per `order`
+///
+/// Shorthand for collecting into a [`SanSet`].
+pub fn normalize_sans(common_name: &str, sans: &[SanEntry], order: SanOrder) -> Vec<SanEntry> {
+    sans.iter().cloned().collect::<SanSet>().ordered(common_name, order)
+}
+
+/// SANs of a CSR followed by operator-supplied ones, normalized
+pub fn merge_sans(common_name: &str, csr_sans: &[SanEntry], extra: &[SanEntry], order: SanOrder) -> Vec<SanEntry> {
+    csr_sans.iter().chain(extra).cloned().collect::<SanSet>().ordered(common_name, order)
+}
+
+/// SANs requested in a CSR
+pub fn csr_sans(csr: &X509Req) -> Result<Vec<SanEntry>> {
+    // Only certificates expose parsed SANs, so read them through a scratch one
+    let mut builder = X509Builder::new()?;
+    if let Ok(extensions) = csr.extensions() {
+        for ext in extensions {
+            builder.append_extension(ext)?;
+        }
+    }
+    let scratch = builder.build();
+
+    let mut sans = Vec::new();
+    for name in scratch.subject_alt_names().into_iter().flatten() {
+        if let Some(dns) = name.dnsname() {
+            sans.push(SanEntry::Dns(dns.to_string()));
+        } else if let Some(ip) = name.ipaddress() {
+            sans.extend(ip_from_bytes(ip).map(|ip| SanEntry::Ip(ip.to_string())));
+        } else if let Some(email) = name.email() {
+            sans.push(SanEntry::Email(email.to_string()));
+        }
+    }
+    Ok(sans)
+}
+
+/// A-label (punycode) form of a DNS name: `bücher.lab` becomes `xn--bcher-kva.lab`
+///
+/// dNSName SANs are IA5Strings, so clients reject raw UTF-8. A leading
+/// wildcard label is kept as-is.
+pub fn to_ascii_dns(name: &str) -> Result<String> {
+    if name.is_ascii() {
+        return Ok(name.to_string());
+    }
+    let (wildcard, domain) = split_wildcard(name);
+    let ascii = idna::domain_to_ascii_strict(domain).map_err(|_| {
+        FluxError::InvalidSanFormat(format!("'{}' is not a valid internationalized domain name", name))
+    })?;
+    Ok(format!("{}{}", wildcard, ascii))
+}
+
+/// DNS name for display, with the Unicode form after any A-labels
+///
+/// `xn--bcher-kva.lab` is shown as `xn--bcher-kva.lab (bücher.lab)`.
+pub fn display_dns(name: &str) -> String {
+    let (wildcard, domain) = split_wildcard(name);
+    if !domain.split('.').any(|label| label.get(..4).is_some_and(|p| p.eq_ignore_ascii_case("xn--"))) {
+        return name.to_string();
+    }
+    match idna::domain_to_unicode(domain) {
+        (unicode, Ok(())) if unicode != domain => format!("{} ({}{})", name, wildcard, unicode),
+        _ => name.to_string(),
+    }
+}
+
+fn split_wildcard(name: &str) -> (&str, &str) {
+    match name.strip_prefix("*.") {
+        Some(domain) => ("*.", domain),
+        None => ("", name),
+    }
+}
+
+/// Check the form of a wildcard DNS name
+///
+/// `*` may only be the whole leftmost label, followed by ordinary labels:
+/// `*.lab.example` is accepted, `*.*.lab`, `w*.lab` and `lab.*.example` are not.
+pub fn validate_wildcard(name: &str) -> Result<()> {
+    if !name.contains('*') {
+        return Ok(());
+    }
+    let invalid = |reason: &str| {
+        FluxError::InvalidSanFormat(format!("Invalid wildcard name '{}': {}", name, reason))
+    };
+
+    let rest = name.strip_prefix("*.")
+        .ok_or_else(|| invalid("'*' must be the whole leftmost label"))?;
+    if rest.contains('*') {
+        return Err(invalid("only the leftmost label may be a wildcard"));
+    }
+    if rest.trim_end_matches('.').split('.').any(str::is_empty) {
+        return Err(invalid("labels cannot be empty"));
+    }
+    Ok(())
+}
+
+/// Check wildcard DNS SANs, and a wildcard CN, before signing
+///
+/// Each wildcard must be well-formed, and with `allow` false none are accepted.
+pub fn check_wildcards(common_name: Option<&str>, sans: &[SanEntry], allow: bool) -> Result<()> {
+    let dns_names = sans.iter().filter_map(|san| match san {
+        SanEntry::Dns(dns) => Some(dns.as_str()),
+        _ => None,
+    });
+    for name in common_name.into_iter().chain(dns_names).filter(|name| name.contains('*')) {
+        validate_wildcard(name)?;
+        if !allow {
+            return Err(FluxError::WildcardNotAllowed(name.to_string()));
+        }
+    }
+    Ok(())
+}
+
+/// Address in an iPAddress SAN: 4 bytes for IPv4, 16 for IPv6
+pub fn ip_from_bytes(bytes: &[u8]) -> Option<IpAddr> {
+    match bytes.len() {
+        4 => <[u8; 4]>::try_from(bytes).ok().map(IpAddr::from),
+        16 => <[u8; 16]>::try_from(bytes).ok().map(IpAddr::from),
+        _ => None,
+    }
+}
+
+/// Text form of an iPAddress SAN, e.g. `10.0.0.5` or `fd00::1`
+pub fn format_ip(bytes: &[u8]) -> String {
+    ip_from_bytes(bytes).map_or_else(|| format!("<invalid {}-byte address>", bytes.len()), |ip| ip.to_string())
+}
+
+/// Validate an email address for use as an rfc822Name SAN and lowercase its domain.
+///
+/// The local part is kept as-is (it is case-sensitive per RFC 5322); the domain
+/// must be a valid hostname. Only ASCII is accepted since rfc822Name is an IA5String.
+pub fn normalize_email(value: &str) -> Result<String> {
+    let invalid = |reason: &str| {
+        FluxError::InvalidSanFormat(format!("Invalid email address '{}': {}", value, reason))
+    };
+
+    if !value.is_ascii() {
+        return Err(invalid("only ASCII characters are allowed"));
+    }
+
+    let (local, domain) = value.rsplit_once('@')
+        .ok_or_else(|| invalid("missing '@'"))?;
+
+    // Local part: dot-atom per RFC 5322
+    if local.is_empty() || local.len() > 64 {
+        return Err(invalid("local part must be 1-64 characters"));
+    }
+    if local.starts_with('.') || local.ends_with('.') || local.contains("..") {
+        return Err(invalid("local part has misplaced dots"));
+    }
+    let atext = |c: char| c.is_ascii_alphanumeric() || "!#$%&'*+-/=?^_`{|}~.".contains(c);
+    if !local.chars().all(atext) {
+        return Err(invalid("local part contains invalid characters"));
+    }
+
+    // Domain part: hostname labels
+    if domain.is_empty() || domain.len() > 253 {
+        return Err(invalid("domain must be 1-253 characters"));
+    }
+    for label in domain.split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(invalid("domain labels must be 1-63 characters"));
+        }
+        if label.starts_with('-') || label.ends_with('-') {
+            return Err(invalid("domain labels cannot start or end with '-'"));
+        }
+        if !label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+            return Err(invalid("domain contains invalid characters"));
+        }
+    }
+
+    Ok(format!("{}@{}", local, domain.to_ascii_lowercase()))
 }
 
 /// Create a Certificate Signing Request
@@ -115,22 +420,12 @@ pub fn save_csr<P: AsRef<Path>>(csr: &X509Req, path: P) -> Result<()> {
     let pem_bytes = csr.to_pem()
         .map_err(|e| FluxError::CsrGenerationFailed(e.to_string()))?;
 
-    std::fs::write(path.as_ref(), &pem_bytes)
-        .map_err(|e| FluxError::FileWriteFailed(
-            path.as_ref().to_path_buf(),
-            e.to_string()
-        ))?;
-
-    Ok(())
+    write_atomic(path.as_ref(), &pem_bytes, PUBLIC_FILE_MODE)
 }
 
 /// Load CSR from file
 pub fn load_csr<P: AsRef<Path>>(path: P) -> Result<X509Req> {
-    let pem_bytes = std::fs::read(path.as_ref())
-        .map_err(|e| FluxError::FileReadFailed(
-            path.as_ref().to_path_buf(),
-            e.to_string()
-        ))?;
+    let pem_bytes = read_bounded(path.as_ref())?;
 
     from_pem_bytes(&pem_bytes)
 }
@@ -138,8 +433,7 @@ pub fn load_csr<P: AsRef<Path>>(path: P) -> Result<X509Req> {
 /// Load CSR from PEM bytes
 pub fn from_pem_bytes(pem_bytes: &[u8]) -> Result<X509Req> {
     X509Req::from_pem(pem_bytes)
-        .map_err(|e| FluxError::CsrReadFailed(std::path::PathBuf::from("<bytes>"))
-            .into()) // Convert to FluxError
+        .map_err(|_| FluxError::CsrReadFailed(std::path::PathBuf::from("<bytes>")))
 }
 
 /// Get subject from CSR
@@ -171,6 +465,38 @@ mod tests {
 
         let email = SanEntry::parse("EMAIL:test@example.com").unwrap();
         assert_eq!(email, SanEntry::Email("test@example.com".to_string()));
+
+        assert_eq!(SanEntry::parse("IP:fd00::1").unwrap(), SanEntry::Ip("fd00::1".to_string()));
+        assert_eq!(SanEntry::parse("ip:[FD00:0:0::1]").unwrap(), SanEntry::Ip("fd00::1".to_string()));
+        assert!(SanEntry::parse("IP:nas01.lab").is_err());
+        assert!(SanEntry::parse("IP:10.0.0.256").is_err());
+    }
+
+    #[test]
+    fn test_email_san_normalization() {
+        let email = SanEntry::parse("EMAIL:John.Doe@Example.COM").unwrap();
+        assert_eq!(email, SanEntry::Email("John.Doe@example.com".to_string()));
+
+        let email = SanEntry::parse("email:ops+certs@mail.lab.internal").unwrap();
+        assert_eq!(email, SanEntry::Email("ops+certs@mail.lab.internal".to_string()));
+    }
+
+    #[test]
+    fn test_email_san_rejects_invalid() {
+        for bad in [
+            "EMAIL:no-at-sign",
+            "EMAIL:@example.com",
+            "EMAIL:user@",
+            "EMAIL:.user@example.com",
+            "EMAIL:us..er@example.com",
+            "EMAIL:user name@example.com",
+            "EMAIL:user@-example.com",
+            "EMAIL:user@example..com",
+            "EMAIL:user@exa_mple.com",
+            "EMAIL:üser@example.com",
+        ] {
+            assert!(SanEntry::parse(bad).is_err(), "expected {} to be rejected", bad);
+        }
     }
 
     #[test]
@@ -181,6 +507,95 @@ mod tests {
         assert_eq!(sans[1], SanEntry::Ip("192.168.1.1".to_string()));
     }
 
+    #[test]
+    fn test_wildcard_validation() {
+        assert!(SanEntry::parse("DNS:*.lab.example").is_ok());
+        for bad in ["DNS:*.*.lab", "DNS:w*.lab", "DNS:lab.*.example", "DNS:*.", "DNS:*", "DNS:*..lab"] {
+            assert!(matches!(SanEntry::parse(bad), Err(FluxError::InvalidSanFormat(_))), "expected {} to be rejected", bad);
+        }
+
+        let sans = [SanEntry::Dns("nas01.lab".to_string()), SanEntry::Dns("*.nas01.lab".to_string())];
+        assert!(check_wildcards(Some("nas01.lab"), &sans, true).is_ok());
+        assert!(matches!(check_wildcards(Some("nas01.lab"), &sans, false), Err(FluxError::WildcardNotAllowed(n)) if n == "*.nas01.lab"));
+        assert!(matches!(check_wildcards(Some("*.lab"), &[], false), Err(FluxError::WildcardNotAllowed(_))));
+        // SANs read from a CSR haven't been through SanEntry::parse
+        assert!(check_wildcards(None, &[SanEntry::Dns("*.*.lab".to_string())], true).is_err());
+    }
+
+    #[test]
+    fn test_idn_sans() {
+        assert_eq!(SanEntry::parse("DNS:bücher.lab").unwrap(), SanEntry::Dns("xn--bcher-kva.lab".to_string()));
+        assert_eq!(SanEntry::parse("DNS:*.Bücher.lab").unwrap(), SanEntry::Dns("*.xn--bcher-kva.lab".to_string()));
+        assert!(SanEntry::parse("DNS:bü cher.lab").is_err());
+
+        assert_eq!(display_dns("*.xn--bcher-kva.lab"), "*.xn--bcher-kva.lab (*.bücher.lab)");
+        assert_eq!(display_dns("nas01.lab"), "nas01.lab");
+    }
+
+    #[test]
+    fn test_wildcard_pair() {
+        let pair = SanEntry::wildcard_pair("example.lab").unwrap();
+        assert_eq!(pair, vec![
+            SanEntry::Dns("example.lab".to_string()),
+            SanEntry::Dns("*.example.lab".to_string()),
+        ]);
+
+        assert!(SanEntry::wildcard_pair("*.example.lab").is_err());
+        assert!(SanEntry::wildcard_pair("").is_err());
+    }
+
+    #[test]
+    fn test_normalize_sans() {
+        let sans = SanEntry::parse_multiple(
+            "IP:10.0.0.10,DNS:www.web.lab,IP:10.0.0.9,DNS:Web.Lab.,EMAIL:ops@web.lab,DNS:web.lab,IP:fd00:0::1"
+        ).unwrap();
+        let render = |sans: Vec<SanEntry>| sans.iter().map(|s| s.to_string()).collect::<Vec<_>>().join(",");
+
+        assert_eq!(
+            render(normalize_sans("web.lab", &sans, SanOrder::CnFirst)),
+            "DNS:web.lab,DNS:www.web.lab,IP:10.0.0.9,IP:10.0.0.10,IP:fd00::1,EMAIL:ops@web.lab"
+        );
+        assert_eq!(
+            render(normalize_sans("www.web.lab", &sans, SanOrder::CnFirst)),
+            "DNS:www.web.lab,DNS:web.lab,IP:10.0.0.9,IP:10.0.0.10,IP:fd00::1,EMAIL:ops@web.lab"
+        );
+        assert_eq!(
+            render(normalize_sans("web.lab", &sans, SanOrder::Preserve)),
+            "IP:10.0.0.10,DNS:www.web.lab,IP:10.0.0.9,DNS:web.lab,EMAIL:ops@web.lab,IP:fd00::1"
+        );
+
+        // Whatever order they come in, the result is the same
+        let mut reversed = sans.clone();
+        reversed.reverse();
+        assert_eq!(normalize_sans("web.lab", &reversed, SanOrder::Sorted), normalize_sans("web.lab", &sans, SanOrder::Sorted));
+    }
+
+    #[test]
+    fn test_san_set() {
+        let mut set: SanSet = SanEntry::parse_multiple("DNS:nas01.lab,IP:fd00::5").unwrap().into_iter().collect();
+        assert!(!set.insert(SanEntry::Dns("NAS01.lab.".to_string())));
+        assert!(set.contains(&SanEntry::Ip("fd00:0:0::5".to_string())));
+
+        // Common SANs combined with per-certificate ones
+        set.extend(SanEntry::parse_multiple("DNS:Nas01.Lab,DNS:files.lab,IP:FD00::5").unwrap());
+        assert_eq!(set.len(), 3);
+        assert_eq!(set.ordered("nas01.lab", SanOrder::Preserve), SanEntry::parse_multiple("DNS:nas01.lab,IP:fd00::5,DNS:files.lab").unwrap());
+    }
+
+    #[test]
+    fn test_merge_csr_sans() {
+        let key = generate_rsa_key(2048, None).unwrap();
+        let csr = create_csr("nas.lab", &key, &SanEntry::parse_multiple("IP:10.0.0.5,DNS:nas.lab").unwrap(), None).unwrap();
+        let csr_sans = csr_sans(&csr).unwrap();
+        assert_eq!(csr_sans, SanEntry::parse_multiple("IP:10.0.0.5,DNS:nas.lab").unwrap());
+
+        let extra = SanEntry::parse_multiple("DNS:NAS.lab,DNS:files.lab").unwrap();
+        assert_eq!(
+            merge_sans("nas.lab", &csr_sans, &extra, SanOrder::CnFirst),
+            SanEntry::parse_multiple("DNS:nas.lab,DNS:files.lab,IP:10.0.0.5").unwrap()
+        );
+    }
+
     #[test]
     fn test_create_csr() {
         let key = generate_rsa_key(2048, None).unwrap();
@@ -0,0 +1,705 @@
+//! Structured certificate and CSR details
+//!
+//! The shape returned by the web API's `/api/cert/info` and printed by
+//! `info --json`, and what `csr info` shows about a certificate request.
+
+use super::cert::{extract_certificate_info, link_certificates, ChainLink, ChainVerification};
+use super::csr::{csr_sans, display_dns, format_ip};
+use super::revocation::{check_revocation, RevocationStatus};
+use crate::config::RevocationConfig;
+use crate::error::{FluxError, Result};
+use chrono::{DateTime, Utc};
+use openssl::asn1::Asn1Object;
+use openssl::hash::MessageDigest;
+use openssl::pkey::{PKey, Public};
+use openssl::x509::{X509, X509NameRef, X509Req};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Detailed certificate information
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "web", derive(utoipa::ToSchema))]
+pub struct DetailedCertificateInfo {
+    /// X.509 version
+    pub version: i32,
+
+    /// Serial number (hex)
+    pub serial_number: String,
+
+    /// Signature algorithm
+    pub signature_algorithm: String,
+
+    /// Issuer DN components
+    pub issuer: HashMap<String, String>,
+
+    /// Validity information
+    pub validity: ValidityInfo,
+
+    /// Subject DN components
+    pub subject: HashMap<String, String>,
+
+    /// Subject Alternative Names
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub subject_alternative_names: Vec<String>,
+
+    /// Public key information
+    pub public_key: PublicKeyInfo,
+
+    /// Certificate extensions
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub extensions: Vec<ExtensionInfo>,
+
+    /// Fingerprints
+    pub fingerprints: FingerprintInfo,
+
+    /// Certificate in PEM format
+    pub pem: String,
+
+    /// Chain verification against the configured CA (when requested)
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub chain_verification: Option<ChainVerificationInfo>,
+
+    /// Relationship to the other uploaded certificates (multi-certificate uploads)
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub chain_relation: Option<ChainRelationInfo>,
+
+    /// Revocation status from local CRL / CA database (when configured)
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub revocation: Option<RevocationInfo>,
+}
+
+/// Revocation status
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "web", derive(utoipa::ToSchema))]
+pub struct RevocationInfo {
+    /// "good", "revoked", or "unknown"
+    pub status: String,
+    /// When the certificate was revoked
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revoked_at: Option<DateTime<Utc>>,
+    /// Revocation reason (e.g. "keyCompromise")
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// Source of the status ("index" or "crl"), or "unavailable" when the
+    /// revocation data couldn't be loaded
+    pub source: String,    /// Why the status is unknown, or a caveat such as a stale CRL
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+/// Position of a certificate within an uploaded chain
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "web", derive(utoipa::ToSchema))]
+pub struct ChainRelationInfo {
+    /// Index of this certificate in the upload
+    pub position: usize,
+    /// "leaf", "intermediate", or "root"
+    pub role: String,
+    /// Index of the issuing certificate in the upload, if present
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub issuer_index: Option<usize>,
+    /// Whether the signature verifies against the issuer in the upload
+    pub signature_valid: bool,
+    /// Whether the certificate is self-signed
+    pub self_signed: bool,
+}
+
+/// Chain verification result
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "web", derive(utoipa::ToSchema))]
+pub struct ChainVerificationInfo {
+    /// Whether the certificate is trusted by this CA
+    pub trusted: bool,
+    /// Failure reason, if verification failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Depth at which verification failed (0 = the certificate itself)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_depth: Option<u32>,
+    /// Number of certificates in the chain that was built, the certificate included
+    pub depth: usize,
+    /// Per-depth verification results, leaf first
+    pub chain: Vec<ChainElementInfo>,
+}
+
+/// Verification result for one certificate in the chain
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "web", derive(utoipa::ToSchema))]
+pub struct ChainElementInfo {
+    pub depth: u32,
+    pub subject: String,
+    pub valid: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Validity period information
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "web", derive(utoipa::ToSchema))]
+pub struct ValidityInfo {
+    pub not_before: DateTime<Utc>,
+    pub not_after: DateTime<Utc>,
+    pub days_remaining: i64,
+    pub is_expired: bool,
+    pub is_expiring_soon: bool,
+}
+
+/// Public key information
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "web", derive(utoipa::ToSchema))]
+pub struct PublicKeyInfo {
+    pub algorithm: String,
+    pub size: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exponent: Option<u64>,
+}
+
+/// Extension information
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "web", derive(utoipa::ToSchema))]
+pub struct ExtensionInfo {
+    pub oid: String,
+    pub name: String,
+    pub critical: bool,
+    pub value: String,
+}
+
+/// Fingerprint information
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "web", derive(utoipa::ToSchema))]
+pub struct FingerprintInfo {
+    pub sha1: String,
+    pub sha256: String,
+}
+
+
+impl From<ChainVerification> for ChainVerificationInfo {
+    fn from(result: ChainVerification) -> Self {
+        Self {
+            trusted: result.trusted,
+            error: result.error,
+            error_depth: result.error_depth,
+            depth: result.chain.len(),
+            chain: result.chain
+                .into_iter()
+                .map(|e| ChainElementInfo {
+                    depth: e.depth,
+                    subject: e.subject,
+                    valid: e.valid,
+                    error: e.error,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<RevocationStatus> for RevocationInfo {
+    fn from(status: RevocationStatus) -> Self {
+        Self {
+            status: status.state.to_string().to_lowercase(),
+            revoked_at: status.revoked_at,
+            reason: status.reason,
+            source: status.source,
+            note: status.note,
+        }
+    }
+}
+
+impl RevocationInfo {
+    /// Status reported when the configured revocation data can't be loaded
+    fn unavailable() -> Self {
+        Self {
+            status: "unknown".to_string(),
+            revoked_at: None,
+            reason: None,
+            source: "unavailable".to_string(),
+            note: None,
+        }
+    }
+}
+
+impl ChainRelationInfo {
+    fn new(position: usize, link: &ChainLink) -> Self {
+        Self {
+            position,
+            role: if link.self_signed {
+                "root"
+            } else if link.issues_others {
+                "intermediate"
+            } else {
+                "leaf"
+            }
+            .to_string(),
+            issuer_index: link.issuer_index,
+            signature_valid: link.signature_valid,
+            self_signed: link.self_signed,
+        }
+    }
+}
+
+/// Details of each certificate in a set, with revocation status and, for
+/// several certificates, how they relate to each other
+///
+/// `issuer` verifies CRL signatures; it is only needed when revocation data
+/// is configured. Revocation data that can't be loaded is reported as an
+/// "unknown" status rather than failing the whole description.
+pub fn describe_all(certs: &[X509], revocation: &RevocationConfig, issuer: Option<&X509>) -> Result<Vec<DetailedCertificateInfo>> {
+    let links = if certs.len() > 1 { link_certificates(certs) } else { Vec::new() };
+
+    let mut details = Vec::with_capacity(certs.len());
+    for (index, cert) in certs.iter().enumerate() {
+        let mut detail = describe(cert)?;
+        detail.revocation = match check_revocation(cert, revocation, issuer) {
+            Ok(status) => status.map(RevocationInfo::from),
+            Err(e) => {
+                tracing::warn!("Failed to load revocation data: {}", e);
+                Some(RevocationInfo::unavailable())
+            }
+        };
+        detail.chain_relation = links.get(index).map(|link| ChainRelationInfo::new(index, link));
+        details.push(detail);
+    }
+    Ok(details)
+}
+
+/// Details of a single certificate
+pub fn describe(cert: &X509) -> Result<DetailedCertificateInfo> {
+    let cert_info = extract_certificate_info(cert)?;
+
+    // Calculate fingerprints
+    let sha1 = hex_colons(&cert.digest(MessageDigest::sha1())?);
+    let sha256 = hex_colons(&cert.digest(MessageDigest::sha256())?);
+
+    // Extract issuer and subject into HashMaps
+    let issuer = parse_x509_name(cert.issuer_name());
+    let subject = parse_x509_name(cert.subject_name());
+
+    // Calculate validity info
+    let now = Utc::now();
+    let not_before = cert_info.not_before;
+    let not_after = cert_info.not_after;
+    let days_remaining = (not_after - now).num_days();
+    let is_expired = now > not_after;
+    let is_expiring_soon = days_remaining < 30 && !is_expired;
+
+    let pem = cert.to_pem()?;
+
+    Ok(DetailedCertificateInfo {
+        version: cert.version() + 1, // OpenSSL uses 0-based versioning
+        serial_number: cert_info.serial_number,
+        signature_algorithm: cert_info.signature_algorithm,
+        issuer,
+        validity: ValidityInfo {
+            not_before,
+            not_after,
+            days_remaining,
+            is_expired,
+            is_expiring_soon,
+        },
+        subject,
+        subject_alternative_names: cert_info.sans,
+        public_key: extract_public_key_info(cert)?,
+        extensions: extract_extensions(cert),
+        fingerprints: FingerprintInfo { sha1, sha256 },
+        pem: String::from_utf8_lossy(&pem).to_string(),
+        chain_verification: None,
+        chain_relation: None,
+        revocation: None,
+    })
+}
+
+fn hex_colons(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect::<Vec<_>>().join(":")
+}
+
+/// Parse X509Name into HashMap
+fn parse_x509_name(name: &X509NameRef) -> HashMap<String, String> {
+    let mut map = HashMap::new();
+
+    for entry in name.entries() {
+        if let Ok(data) = entry.data().as_utf8() {
+            let key = entry.object().nid().short_name().unwrap_or("UNKNOWN");
+            map.insert(key.to_string(), data.to_string());
+        }
+    }
+
+    map
+}
+
+/// Extract public key information
+fn extract_public_key_info(cert: &X509) -> Result<PublicKeyInfo> {
+    let public_key = cert
+        .public_key()
+        .map_err(|e| FluxError::CertParseError(format!("Failed to get public key: {}", e)))?;
+    Ok(public_key_info(&public_key))
+}
+
+fn public_key_info(public_key: &PKey<Public>) -> PublicKeyInfo {
+    let algorithm = if public_key.rsa().is_ok() {
+        "RSA"
+    } else if public_key.ec_key().is_ok() {
+        "ECDSA"
+    } else {
+        "UNKNOWN"
+    };
+
+    let size = public_key.bits();
+
+    let exponent = if let Ok(rsa) = public_key.rsa() {
+        rsa.e().to_dec_str().ok().and_then(|s| s.parse().ok())
+    } else {
+        None
+    };
+
+    PublicKeyInfo {
+        algorithm: algorithm.to_string(),
+        size,
+        exponent,
+    }
+}
+
+/// Extract certificate extensions
+fn extract_extensions(cert: &X509) -> Vec<ExtensionInfo> {
+    let mut extensions = Vec::new();
+
+    // Extract Subject Alternative Names
+    if let Some(san_ext) = cert.subject_alt_names() {
+        let mut sans = Vec::new();
+        for san in san_ext {
+            if let Some(dns) = san.dnsname() {
+                sans.push(format!("DNS:{}", display_dns(dns)));
+            }
+            if let Some(ip) = san.ipaddress() {
+                let ip_str = format_ip(ip);
+                sans.push(format!("IP:{}", ip_str));
+            }
+            if let Some(email) = san.email() {
+                sans.push(format!("EMAIL:{}", email));
+            }
+        }
+
+        if !sans.is_empty() {
+            extensions.push(ExtensionInfo {
+                oid: "2.5.29.17".to_string(),
+                name: "Subject Alternative Name".to_string(),
+                critical: false,
+                value: sans.join(", "),
+            });
+        }
+    }
+
+    // Extract Authority Key Identifier
+    if let Some(aki) = cert.authority_key_id() {
+        let keyid = aki.as_slice()
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect::<Vec<_>>()
+            .join(":");
+
+        extensions.push(ExtensionInfo {
+            oid: "2.5.29.35".to_string(),
+            name: "Authority Key Identifier".to_string(),
+            critical: false,
+            value: format!("keyid:{}", keyid),
+        });
+    }
+
+    // Extract Subject Key Identifier
+    if let Some(ski) = cert.subject_key_id() {
+        let keyid = ski.as_slice()
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect::<Vec<_>>()
+            .join(":");
+
+        extensions.push(ExtensionInfo {
+            oid: "2.5.29.14".to_string(),
+            name: "Subject Key Identifier".to_string(),
+            critical: false,
+            value: keyid,
+        });
+    }
+
+    // Note: OpenSSL version in use doesn't expose direct methods for
+    // Basic Constraints, Key Usage, Extended Key Usage extraction.
+    // These would require parsing the extension stack directly which is
+    // version-dependent. The above extensions cover the most critical
+    // certificate information for web service use.
+
+    extensions
+}
+
+/// What a certificate signing request asks for
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "web", derive(utoipa::ToSchema))]
+pub struct CsrDetails {
+    /// Subject DN components
+    pub subject: HashMap<String, String>,
+
+    /// Requested Subject Alternative Names
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub subject_alternative_names: Vec<String>,
+
+    /// Public key information
+    pub public_key: PublicKeyInfo,
+
+    /// Algorithm the request is signed with
+    pub signature_algorithm: String,
+
+    /// Whether the request is signed by the key it carries
+    pub signature_valid: bool,
+
+    /// Requested extensions
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub extensions: Vec<ExtensionInfo>,
+}
+
+/// Details of a certificate signing request
+pub fn describe_csr(csr: &X509Req) -> Result<CsrDetails> {
+    let public_key = csr.public_key()
+        .map_err(|e| FluxError::CertParseError(format!("Failed to get public key: {}", e)))?;
+    // A broken signature is reported, not an error
+    let signature_valid = csr.verify(&public_key).unwrap_or(false);
+
+    let der = csr.to_der()?;
+    let signature_algorithm = csr_signature_oid(&der)
+        .map_or_else(|| "UNKNOWN".to_string(), |oid| oid_name(&oid, false));
+
+    let sans = csr_sans(csr)?;
+    let mut extensions = Vec::new();
+    for ext in csr.extensions().into_iter().flatten() {
+        let der = ext.to_der()?;
+        let Some((oid, critical, value)) = extension_parts(&der) else { continue };
+        let value = match oid.as_str() {
+            "2.5.29.17" => sans.iter().map(ToString::to_string).collect::<Vec<_>>().join(", "),
+            "2.5.29.15" => key_usage(value),
+            "2.5.29.37" => extended_key_usage(value),
+            "2.5.29.19" => basic_constraints(value),
+            _ => hex_colons(value),
+        };
+        extensions.push(ExtensionInfo { name: oid_name(&oid, true), oid, critical, value });
+    }
+
+    Ok(CsrDetails {
+        subject: parse_x509_name(csr.subject_name()),
+        subject_alternative_names: sans.iter().map(ToString::to_string).collect(),
+        public_key: public_key_info(&public_key),
+        signature_algorithm,
+        signature_valid,
+        extensions,
+    })
+}
+
+/// Name OpenSSL knows an OID by, or the OID itself
+///
+/// Extension names lose OpenSSL's `X509v3 ` prefix.
+fn oid_name(oid: &str, extension: bool) -> String {
+    let name = Asn1Object::from_str(oid).ok()
+        .and_then(|object| object.nid().long_name().ok().map(str::to_string))
+        .unwrap_or_else(|| oid.to_string());
+    if extension {
+        name.trim_start_matches("X509v3 ").to_string()
+    } else {
+        name
+    }
+}
+
+/// One DER element: tag, contents, and what follows it
+fn der_element(data: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = data.split_first()?;
+    let (&first, rest) = rest.split_first()?;
+    let (len, rest) = if first & 0x80 == 0 {
+        (usize::from(first), rest)
+    } else {
+        let count = usize::from(first & 0x7f);
+        if count == 0 || count > 4 || rest.len() < count {
+            return None;
+        }
+        let len = rest[..count].iter().fold(0usize, |len, b| (len << 8) | usize::from(*b));
+        (len, &rest[count..])
+    };
+    (rest.len() >= len).then(|| (tag, &rest[..len], &rest[len..]))
+}
+
+/// Dotted form of DER OBJECT IDENTIFIER contents
+fn decode_oid(content: &[u8]) -> Option<String> {
+    let mut ids = Vec::new();
+    let mut value = 0u64;
+    for byte in content {
+        value = (value << 7) | u64::from(byte & 0x7f);
+        if byte & 0x80 == 0 {
+            ids.push(value);
+            value = 0;
+        }
+    }
+    // The first sub-identifier packs two arcs: 40 * first + second
+    let (&first, rest) = ids.split_first()?;
+    let top = (first / 40).min(2);
+    let arcs = [top, first - 40 * top].into_iter().chain(rest.iter().copied());
+    Some(arcs.map(|arc| arc.to_string()).collect::<Vec<_>>().join("."))
+}
+
+/// OID of the signature algorithm of a DER CertificationRequest
+fn csr_signature_oid(der: &[u8]) -> Option<String> {
+    let (_, request, _) = der_element(der)?;
+    let (_, _, after_info) = der_element(request)?;
+    let (_, algorithm, _) = der_element(after_info)?;
+    let (tag, oid, _) = der_element(algorithm)?;
+    (tag == 0x06).then(|| decode_oid(oid)).flatten()
+}
+
+/// OID, criticality and value of a DER Extension
+fn extension_parts(der: &[u8]) -> Option<(String, bool, &[u8])> {
+    let (_, extension, _) = der_element(der)?;
+    let (0x06, oid, rest) = der_element(extension)? else { return None };
+    let (critical, rest) = match der_element(rest)? {
+        (0x01, flag, rest) => (flag.first().is_some_and(|b| *b != 0), rest),
+        _ => (false, rest),
+    };
+    let (0x04, value, _) = der_element(rest)? else { return None };
+    Some((decode_oid(oid)?, critical, value))
+}
+
+fn key_usage(value: &[u8]) -> String {
+    const NAMES: [&str; 9] = [
+        "Digital Signature", "Non Repudiation", "Key Encipherment", "Data Encipherment",
+        "Key Agreement", "Certificate Sign", "CRL Sign", "Encipher Only", "Decipher Only",
+    ];
+    let Some((0x03, bits, _)) = der_element(value) else { return hex_colons(value) };
+    let bits = bits.get(1..).unwrap_or_default();
+    NAMES.iter()
+        .enumerate()
+        .filter(|(i, _)| bits.get(i / 8).is_some_and(|byte| byte & (0x80 >> (i % 8)) != 0))
+        .map(|(_, name)| *name)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn extended_key_usage(value: &[u8]) -> String {
+    let Some((0x30, mut usages, _)) = der_element(value) else { return hex_colons(value) };
+    let mut names = Vec::new();
+    while let Some((tag, oid, rest)) = der_element(usages) {
+        if tag == 0x06 {
+            names.extend(decode_oid(oid).map(|oid| oid_name(&oid, false)));
+        }
+        usages = rest;
+    }
+    names.join(", ")
+}
+
+fn basic_constraints(value: &[u8]) -> String {
+    let Some((ca, path_len)) = parse_basic_constraints(value) else { return hex_colons(value) };
+    match path_len {
+        Some(path_len) => format!("CA:{}, pathlen:{}", if ca { "TRUE" } else { "FALSE" }, path_len),
+        None => format!("CA:{}", if ca { "TRUE" } else { "FALSE" }),
+    }
+}
+
+/// The cA flag and path length of a DER BasicConstraints
+fn parse_basic_constraints(value: &[u8]) -> Option<(bool, Option<u64>)> {
+    let (0x30, mut fields, _) = der_element(value)? else { return None };
+    let mut ca = false;
+    let mut path_len = None;
+    while let Some((tag, content, rest)) = der_element(fields) {
+        match tag {
+            0x01 => ca = content.first().is_some_and(|b| *b != 0),
+            0x02 => path_len = Some(content.iter().fold(0u64, |n, b| (n << 8) | u64::from(*b))),
+            _ => {}
+        }
+        fields = rest;
+    }
+    Some((ca, path_len))
+}
+
+/// Whether a CSR asks for a CA certificate (basicConstraints with cA set)
+pub(crate) fn csr_requests_ca(csr: &X509Req) -> Result<bool> {
+    for ext in csr.extensions().into_iter().flatten() {
+        let der = ext.to_der()?;
+        let Some((oid, _, value)) = extension_parts(&der) else { continue };
+        if oid == "2.5.29.19" {
+            return Ok(parse_basic_constraints(value).is_some_and(|(ca, _)| ca));
+        }
+    }
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ca::CertSigner;
+    use crate::crypto::SanEntry;
+    use crate::fixtures;
+
+    #[test]
+    fn test_describe_all() {
+        let ca = fixtures::intermediate_ca();
+        let leaf = ca.sign(&fixtures::leaf_csr("nas01.lab", &[SanEntry::Dns("nas01.lab".to_string())]), 30).unwrap();
+
+        let details = describe_all(&[leaf, ca.cert().clone()], &RevocationConfig::default(), None).unwrap();
+        assert_eq!(details[0].subject["CN"], "nas01.lab");
+        assert_eq!(details[0].subject_alternative_names, ["DNS:nas01.lab"]);
+        assert!(details[0].validity.days_remaining >= 29);
+        assert_eq!(details[0].fingerprints.sha256.len(), 32 * 3 - 1);
+
+        let relations: Vec<_> = details.iter().map(|d| d.chain_relation.as_ref().unwrap()).collect();
+        assert_eq!((relations[0].role.as_str(), relations[0].issuer_index), ("leaf", Some(1)));
+        assert_eq!(relations[1].role, "intermediate");
+
+        // A lone certificate has no relation, and optional fields stay out of the JSON
+        let json = serde_json::to_value(describe(ca.cert()).unwrap()).unwrap();
+        assert!(json.get("chain_relation").is_none() && json.get("revocation").is_none());
+    }
+
+    #[test]
+    fn test_describe_all_with_unreadable_revocation_data() {
+        let dir = tempfile::tempdir().unwrap();
+        let crl_path = dir.path().join("ca.crl");
+        std::fs::write(&crl_path, "not a CRL").unwrap();
+        let revocation = RevocationConfig { crl_path: Some(crl_path), index_path: None };
+
+        let ca = fixtures::intermediate_ca();
+        let details = describe_all(&[ca.cert().clone()], &revocation, Some(ca.cert())).unwrap();
+        let status = details[0].revocation.as_ref().unwrap();
+        assert_eq!((status.status.as_str(), status.source.as_str()), ("unknown", "unavailable"));
+        assert!(!details[0].fingerprints.sha256.is_empty());
+    }
+
+    #[test]
+    fn test_describe_csr() {
+        use openssl::stack::Stack;
+        use openssl::x509::extension::{BasicConstraints, ExtendedKeyUsage, KeyUsage, SubjectAlternativeName};
+        use openssl::x509::X509ReqBuilder;
+
+        let key = fixtures::leaf_key();
+        let mut builder = X509ReqBuilder::new().unwrap();
+        builder.set_pubkey(&key).unwrap();
+        builder.set_subject_name(fixtures::leaf_csr("nas01.lab", &[]).subject_name()).unwrap();
+        let context = builder.x509v3_context(None);
+        let mut extensions = Stack::new().unwrap();
+        extensions.push(SubjectAlternativeName::new().dns("nas01.lab").ip("fd00::5").build(&context).unwrap()).unwrap();
+        extensions.push(KeyUsage::new().critical().digital_signature().key_encipherment().build().unwrap()).unwrap();
+        extensions.push(ExtendedKeyUsage::new().server_auth().build().unwrap()).unwrap();
+        extensions.push(BasicConstraints::new().ca().pathlen(0).build().unwrap()).unwrap();
+        builder.add_extensions(&extensions).unwrap();
+        builder.sign(&key, MessageDigest::sha256()).unwrap();
+        let csr = builder.build();
+
+        let details = describe_csr(&csr).unwrap();
+        assert_eq!(details.subject["CN"], "nas01.lab");
+        assert_eq!(details.subject_alternative_names, ["DNS:nas01.lab", "IP:fd00::5"]);
+        assert_eq!(details.signature_algorithm, "sha256WithRSAEncryption");
+        assert!(details.signature_valid);
+        let extensions: Vec<_> = details.extensions.iter()
+            .map(|e| (e.name.as_str(), e.critical, e.value.as_str()))
+            .collect();
+        assert_eq!(extensions, [
+            ("Subject Alternative Name", false, "DNS:nas01.lab, IP:fd00::5"),
+            ("Key Usage", true, "Digital Signature, Key Encipherment"),
+            ("Extended Key Usage", false, "TLS Web Server Authentication"),
+            ("Basic Constraints", false, "CA:TRUE, pathlen:0"),
+        ]);
+
+        // The first arc pair of 2.999.1 takes two bytes
+        assert_eq!(decode_oid(&[0x88, 0x37, 0x01]).as_deref(), Some("2.999.1"));
+    }
+}
@@ -1,12 +1,18 @@
 //! Private key generation and management
 
 use crate::error::{FluxError, Result};
+use crate::fsutil::{read_bounded, read_to_string_bounded, write_atomic, PRIVATE_FILE_MODE};
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
 use openssl::rsa::Rsa;
-use openssl::pkey::PKey;
+use openssl::pkey::{HasPublic, Id, PKey, PKeyRef, Private};
+use openssl::x509::X509;
 use openssl::symm::Cipher;
 use secrecy::{Secret, ExposeSecret};
+use serde::Serialize;
+use std::fmt;
 use std::path::Path;
-use zeroize::Zeroize;
+use zeroize::Zeroizing;
 
 /// Generate an RSA private key
 pub fn generate_rsa_key(key_size: u32, _password: Option<&str>) -> Result<PKey<openssl::pkey::Private>> {
@@ -21,27 +27,21 @@ pub fn generate_rsa_key(key_size: u32, _password: Option<&str>) -> Result<PKey<o
     Ok(pkey)
 }
 
-/// Save private key to file
+/// Save private key to file (atomically, mode 0600)
 pub fn save_private_key<P: AsRef<Path>>(
     key: &PKey<openssl::pkey::Private>,
     path: P,
     password: Option<&str>,
 ) -> Result<()> {
-    let pem_bytes = if let Some(pwd) = password {
+    let pem_bytes = Zeroizing::new(if let Some(pwd) = password {
         // Encrypt with AES-256
         key.private_key_to_pem_pkcs8_passphrase(Cipher::aes_256_cbc(), pwd.as_bytes())?
     } else {
         // No encryption
         key.private_key_to_pem_pkcs8()?
-    };
-
-    std::fs::write(path.as_ref(), &pem_bytes)
-        .map_err(|e| FluxError::FileWriteFailed(
-            path.as_ref().to_path_buf(),
-            e.to_string()
-        ))?;
+    });
 
-    Ok(())
+    write_atomic(path.as_ref(), &pem_bytes, PRIVATE_FILE_MODE)
 }
 
 /// Convert private key to PEM bytes (unencrypted)
@@ -61,11 +61,7 @@ pub fn load_private_key<P: AsRef<Path>>(
     path: P,
     password: Option<&str>,
 ) -> Result<PKey<openssl::pkey::Private>> {
-    let pem_bytes = std::fs::read(path.as_ref())
-        .map_err(|e| FluxError::FileReadFailed(
-            path.as_ref().to_path_buf(),
-            e.to_string()
-        ))?;
+    let pem_bytes = read_bounded(path.as_ref())?;
 
     let key = if let Some(pwd) = password {
         PKey::private_key_from_pem_passphrase(&pem_bytes, pwd.as_bytes())?
@@ -85,17 +81,130 @@ pub fn load_private_key<P: AsRef<Path>>(
 
 /// Check if a private key is password protected
 pub fn is_key_encrypted<P: AsRef<Path>>(path: P) -> Result<bool> {
-    let content = std::fs::read_to_string(path.as_ref())
-        .map_err(|e| FluxError::FileReadFailed(
-            path.as_ref().to_path_buf(),
-            e.to_string()
-        ))?;
+    let content = read_to_string_bounded(path.as_ref())?;
 
     // Check for encryption headers in PEM format
     Ok(content.contains("ENCRYPTED"))
 }
 
+/// Encoding of a PEM private key
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum KeyFormat {
+    /// `BEGIN RSA PRIVATE KEY`
+    #[serde(rename = "PKCS#1")]
+    Pkcs1,
+    /// `BEGIN EC PRIVATE KEY`
+    #[serde(rename = "SEC1")]
+    Sec1,
+    /// `BEGIN PRIVATE KEY` or `BEGIN ENCRYPTED PRIVATE KEY`
+    #[serde(rename = "PKCS#8")]
+    Pkcs8,
+}
+
+impl fmt::Display for KeyFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            KeyFormat::Pkcs1 => "PKCS#1",
+            KeyFormat::Sec1 => "SEC1",
+            KeyFormat::Pkcs8 => "PKCS#8",
+        })
+    }
+}
+
+/// What a private key file holds
+///
+/// The algorithm, size and fingerprint of an encrypted key are only known
+/// once it is unlocked; a PKCS#1 or SEC1 label still gives the algorithm.
+#[derive(Debug, Clone, Serialize)]
+pub struct KeyInfo {
+    pub format: KeyFormat,
+    pub encrypted: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub algorithm: Option<String>,
+    /// Key size in bits
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<u32>,
+    /// SHA-256 of the DER SubjectPublicKeyInfo of the public key
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spki_sha256: Option<String>,
+}
+
+/// Inspect a PEM private key, unlocking it with `password` if encrypted
+pub fn inspect_key(pem: &[u8], password: Option<&str>) -> Result<KeyInfo> {
+    let text = String::from_utf8_lossy(pem);
+    let label = text.lines()
+        .find_map(|line| line.trim().strip_prefix("-----BEGIN ")?.strip_suffix("-----"))
+        .ok_or_else(|| FluxError::KeyParseError("no PEM private key found".to_string()))?;
+    let (format, algorithm, encrypted) = match label {
+        "RSA PRIVATE KEY" => (KeyFormat::Pkcs1, Some("RSA"), text.contains("Proc-Type: 4,ENCRYPTED")),
+        "EC PRIVATE KEY" => (KeyFormat::Sec1, Some("EC"), text.contains("Proc-Type: 4,ENCRYPTED")),
+        "PRIVATE KEY" => (KeyFormat::Pkcs8, None, false),
+        "ENCRYPTED PRIVATE KEY" => (KeyFormat::Pkcs8, None, true),
+        other => return Err(FluxError::KeyParseError(format!("'{}' is not a private key", other))),
+    };
+
+    let key = match (encrypted, password) {
+        (false, _) => Some(parse_private_key(pem, None)?),
+        (true, Some(password)) => Some(parse_private_key(pem, Some(password))?),
+        (true, None) => None,
+    };
+
+    let mut info = KeyInfo {
+        format,
+        encrypted,
+        algorithm: algorithm.map(str::to_string),
+        size: None,
+        spki_sha256: None,
+    };
+    if let Some(key) = key {
+        info.algorithm = Some(key_algorithm(&key));
+        info.size = Some(key.bits());
+        info.spki_sha256 = Some(spki_sha256(&key)?);
+    }
+    Ok(info)
+}
+
+/// Parse a PEM private key, unlocking it with `password` if given
+pub fn parse_private_key(pem: &[u8], password: Option<&str>) -> Result<PKey<Private>> {
+    match password {
+        Some(password) => PKey::private_key_from_pem_passphrase(pem, password.as_bytes())
+            .map_err(|_| FluxError::KeyParseError("wrong passphrase".to_string())),
+        None => PKey::private_key_from_pem(pem)
+            .map_err(|e| FluxError::KeyParseError(e.to_string())),
+    }
+}
+
+/// Whether `key` is the private half of the public key in `cert`
+pub fn matches_cert(key: &PKey<Private>, cert: &X509) -> Result<bool> {
+    Ok(cert.public_key()?.public_eq(key))
+}
+
+/// Algorithm name, with the curve for EC keys
+fn key_algorithm(key: &PKey<Private>) -> String {
+    match key.id() {
+        Id::RSA => "RSA".to_string(),
+        Id::EC => {
+            let curve = key.ec_key().ok()
+                .and_then(|ec| ec.group().curve_name())
+                .and_then(|nid: Nid| nid.short_name().ok().map(str::to_string));
+            curve.map_or_else(|| "EC".to_string(), |curve| format!("EC ({})", curve))
+        }
+        Id::ED25519 => "Ed25519".to_string(),
+        Id::ED448 => "Ed448".to_string(),
+        _ => "UNKNOWN".to_string(),
+    }
+}
+
+/// SHA-256 of the key's DER SubjectPublicKeyInfo, as colon-separated hex
+///
+/// The same for a certificate or CSR carrying this key's public half.
+pub fn spki_sha256<T: HasPublic>(key: &PKeyRef<T>) -> Result<String> {
+    let digest = openssl::hash::hash(MessageDigest::sha256(), &key.public_key_to_der()?)?;
+    Ok(digest.iter().map(|b| format!("{:02X}", b)).collect::<Vec<_>>().join(":"))
+}
+
 /// Securely prompt for password
+#[cfg(feature = "interactive")]
 pub fn prompt_password(prompt: &str) -> Result<Secret<String>> {
     use dialoguer::Password;
 
@@ -108,6 +217,7 @@ pub fn prompt_password(prompt: &str) -> Result<Secret<String>> {
 }
 
 /// Prompt for password with confirmation
+#[cfg(feature = "interactive")]
 pub fn prompt_password_with_confirmation(prompt: &str) -> Result<Secret<String>> {
     use dialoguer::Password;
 
@@ -120,6 +230,18 @@ pub fn prompt_password_with_confirmation(prompt: &str) -> Result<Secret<String>>
     Ok(Secret::new(password))
 }
 
+/// Password prompts need the `interactive` feature
+#[cfg(not(feature = "interactive"))]
+pub fn prompt_password(prompt: &str) -> Result<Secret<String>> {
+    Err(FluxError::InteractiveError(format!("{}: built without the interactive feature", prompt)))
+}
+
+/// Password prompts need the `interactive` feature
+#[cfg(not(feature = "interactive"))]
+pub fn prompt_password_with_confirmation(prompt: &str) -> Result<Secret<String>> {
+    prompt_password(prompt)
+}
+
 /// Create a temporary unlocked copy of a CA key
 pub fn unlock_ca_key<P: AsRef<Path>>(
     key_path: P,
@@ -130,7 +252,7 @@ pub fn unlock_ca_key<P: AsRef<Path>>(
 
     // Create a temporary file
     let temp_file = tempfile::NamedTempFile::new()
-        .map_err(|e| FluxError::IoError(e))?;
+        .map_err(FluxError::IoError)?;
 
     // Write unencrypted key to temp file
     let pem_bytes = key.private_key_to_pem_pkcs8()?;
@@ -187,4 +309,39 @@ mod tests {
         let loaded_key = load_private_key(&key_path, Some("testpass")).unwrap();
         assert!(loaded_key.rsa().is_ok());
     }
+
+    #[test]
+    fn test_inspect_key() {
+        use openssl::ec::{EcGroup, EcKey};
+
+        let key = generate_rsa_key(2048, None).unwrap();
+        let pkcs8 = inspect_key(&to_pem(&key).unwrap(), None).unwrap();
+        assert_eq!((pkcs8.format, pkcs8.encrypted, pkcs8.size), (KeyFormat::Pkcs8, false, Some(2048)));
+        assert_eq!(pkcs8.algorithm.as_deref(), Some("RSA"));
+        assert_eq!(pkcs8.spki_sha256.unwrap(), spki_sha256(&key).unwrap());
+
+        let pkcs1 = key.rsa().unwrap().private_key_to_pem().unwrap();
+        assert_eq!(inspect_key(&pkcs1, None).unwrap().format, KeyFormat::Pkcs1);
+
+        // Encrypted PKCS#8 says nothing about the key until it is unlocked
+        let encrypted = to_encrypted_pem(&key, &Secret::new("testpass".to_string())).unwrap();
+        let locked = inspect_key(&encrypted, None).unwrap();
+        assert!(locked.encrypted && locked.algorithm.is_none() && locked.spki_sha256.is_none());
+        assert_eq!(inspect_key(&encrypted, Some("testpass")).unwrap().size, Some(2048));
+        assert!(matches!(inspect_key(&encrypted, Some("wrong")), Err(FluxError::KeyParseError(_))));
+
+        let ec = EcKey::generate(&EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap()).unwrap();
+        let sec1 = inspect_key(&ec.private_key_to_pem().unwrap(), None).unwrap();
+        assert_eq!((sec1.format, sec1.algorithm.as_deref()), (KeyFormat::Sec1, Some("EC (prime256v1)")));
+    }
+    #[test]
+    fn test_matches_cert() {
+        use crate::ca::CertSigner;
+        use crate::fixtures;
+
+        let cert = fixtures::intermediate_ca().sign(&fixtures::leaf_csr("nas01.lab", &[]), 30).unwrap();
+        assert!(matches_cert(&fixtures::leaf_key(), &cert).unwrap());
+        assert!(!matches_cert(&generate_rsa_key(2048, None).unwrap(), &cert).unwrap());
+        assert_eq!(spki_sha256(&cert.public_key().unwrap()).unwrap(), spki_sha256(&fixtures::leaf_key()).unwrap());
+    }
 }
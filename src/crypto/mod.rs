@@ -3,7 +3,14 @@
 pub mod key;
 pub mod csr;
 pub mod cert;
+pub mod revocation;
+pub mod backend;
+pub mod details;
+pub mod constraints;
 
 pub use key::{generate_rsa_key, save_private_key, load_private_key, is_key_encrypted, unlock_ca_key, to_pem as key_to_pem, to_encrypted_pem as key_to_encrypted_pem};
-pub use csr::{SanEntry, create_csr, save_csr, load_csr, from_pem_bytes as csr_from_pem_bytes, get_csr_subject};
-pub use cert::{sign_csr, save_cert_pem, save_cert_der, load_cert, get_cert_info, is_cert_expired, days_until_expiration, extract_certificate_info, to_pem as cert_to_pem, from_pem as cert_from_pem, CertificateInfo};
+pub use csr::{SanEntry, SanSet, check_wildcards, validate_wildcard, to_ascii_dns, display_dns, create_csr, normalize_sans, merge_sans, csr_sans, save_csr, load_csr, from_pem_bytes as csr_from_pem_bytes, get_csr_subject};
+pub use cert::{sign_csr, NotBefore, save_cert_pem, save_cert_der, load_cert, load_certs, from_bytes as certs_from_bytes, get_cert_info, is_cert_expired, days_until_expiration, extract_certificate_info, to_pem as cert_to_pem, from_pem as cert_from_pem, CertificateInfo, verify_chain, format_name, ChainVerification, ChainElement, from_pem_multiple as certs_from_pem, link_certificates, ChainLink, to_pkcs12};
+pub use backend::{backend, CryptoBackend, IssueExtensions, SubCaExtensions, ValidityWindow};
+pub use constraints::{check_pathlen, narrow_sub_ca, NameConstraints, Subtree};
+pub use revocation::{check_revocation, RevocationState, RevocationStatus};
@@ -0,0 +1,368 @@
+//! Certificate revocation status checks
+//!
+//! Status is looked up in local revocation data: the OpenSSL CA database
+//! (`index.txt`, the same data an `openssl ocsp` responder serves) and/or a
+//! CRL file published by the CA.
+//!
+//! Both belong to the configured CA, so a status is only reported for
+//! certificates that CA signed. A CRL counts only when its signature checks
+//! out against the CA certificate and it isn't past its `nextUpdate`;
+//! otherwise the status is Unknown, with a note saying why.
+
+use crate::config::RevocationConfig;
+use crate::crypto::cert::asn1_time_to_chrono;
+use crate::error::{FluxError, Result};
+use crate::fsutil::{read_bounded, read_to_string_bounded};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use openssl::x509::{CrlStatus, ReasonCode, X509, X509Crl, X509RevokedRef, X509VerifyResult};
+use serde::Serialize;
+use std::path::Path;
+
+/// Revocation state of a certificate
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RevocationState {
+    Good,
+    Revoked,
+    Unknown,
+}
+
+impl std::fmt::Display for RevocationState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            RevocationState::Good => "Good",
+            RevocationState::Revoked => "Revoked",
+            RevocationState::Unknown => "Unknown",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Result of a revocation check
+#[derive(Debug, Clone, Serialize)]
+pub struct RevocationStatus {
+    pub state: RevocationState,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub reason: Option<String>,
+    /// Where the status came from (`index` or `crl`)
+    pub source: String,
+    /// Why the source couldn't give a definite answer, or a caveat about it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+impl RevocationStatus {
+    fn new(state: RevocationState, source: &str) -> Self {
+        Self {
+            state,
+            revoked_at: None,
+            reason: None,
+            source: source.to_string(),
+            note: None,
+        }
+    }
+
+    fn unknown(source: &str, note: impl Into<String>) -> Self {
+        Self {
+            note: Some(note.into()),
+            ..Self::new(RevocationState::Unknown, source)
+        }
+    }
+
+    /// Source, followed by the note if there is one (e.g. `crl: CRL is stale ...`)
+    pub fn detail(&self) -> String {
+        match &self.note {
+            Some(note) => format!("{}: {}", self.source, note),
+            None => self.source.clone(),
+        }
+    }
+}
+
+/// Check a certificate against the configured revocation sources
+///
+/// Returns `None` when no revocation data is configured. A revoked status
+/// from any source wins; otherwise Good from any source, else Unknown.
+/// `issuer` is the CA certificate the data belongs to; without it every
+/// source reports Unknown.
+pub fn check_revocation(cert: &X509, config: &RevocationConfig, issuer: Option<&X509>) -> Result<Option<RevocationStatus>> {
+    let mut results = Vec::new();
+
+    if let Some(index_path) = &config.index_path {
+        let data = read_to_string_bounded(index_path)?;
+        results.push(check_index(cert, &data, issuer)?);
+    }
+
+    if let Some(crl_path) = &config.crl_path {
+        let crl = load_crl(crl_path)?;
+        results.push(check_crl(cert, &crl, issuer)?);
+    }
+
+    let pick = |state| results.iter().position(|r: &RevocationStatus| r.state == state);
+    let index = pick(RevocationState::Revoked)
+        .or_else(|| pick(RevocationState::Good))
+        .or_else(|| pick(RevocationState::Unknown));
+
+    Ok(index.map(|i| results.swap_remove(i)))
+}
+
+/// Load a CRL from a PEM or DER file
+pub fn load_crl<P: AsRef<Path>>(path: P) -> Result<X509Crl> {
+    let data = read_bounded(path.as_ref())?;
+
+    X509Crl::from_pem(&data)
+        .or_else(|_| X509Crl::from_der(&data))
+        .map_err(|e| FluxError::RevocationError(format!("Failed to parse CRL {}: {}", path.as_ref().display(), e)))
+}
+
+/// Look a certificate up in a CRL issued by `issuer`
+///
+/// A stale CRL still reports revocations, but not Good.
+pub fn check_crl(cert: &X509, crl: &X509Crl, issuer: Option<&X509>) -> Result<RevocationStatus> {
+    // A CRL only covers certificates from its own issuer
+    if crl.issuer_name().to_der()? != cert.issuer_name().to_der()? {
+        return Ok(RevocationStatus::new(RevocationState::Unknown, "crl"));
+    }
+
+    let Some(issuer) = issuer else {
+        return Ok(RevocationStatus::unknown("crl", "CRL signature not verified (CA certificate unavailable)"));
+    };
+    let key = issuer.public_key()?;
+    if !crl.verify(&key)? {
+        return Err(FluxError::RevocationError("CRL signature does not match the CA certificate".to_string()));
+    }
+    if !issued_by(cert, issuer)? {
+        return Ok(RevocationStatus::unknown("crl", "not issued by this CA"));
+    }
+
+    let status = match crl.get_by_cert(cert) {
+        CrlStatus::NotRevoked => RevocationStatus::new(RevocationState::Good, "crl"),
+        CrlStatus::Revoked(entry) | CrlStatus::RemoveFromCrl(entry) => revoked_entry_status(entry)?,
+    };
+
+    let next_update = crl.next_update().map(asn1_time_to_chrono).transpose()?;
+    match next_update {
+        Some(next_update) if next_update < Utc::now() => {
+            let note = format!("CRL is stale (next update was due {})", next_update.format("%Y-%m-%d %H:%M:%S UTC"));
+            Ok(match status.state {
+                RevocationState::Revoked => RevocationStatus { note: Some(note), ..status },
+                _ => RevocationStatus::unknown("crl", note),
+            })
+        }
+        _ => Ok(status),
+    }
+}
+
+/// Whether `issuer` signed `cert`
+fn issued_by(cert: &X509, issuer: &X509) -> Result<bool> {
+    if issuer.issued(cert) != X509VerifyResult::OK {
+        return Ok(false);
+    }
+    let key = issuer.public_key()?;
+    Ok(cert.verify(&key).unwrap_or(false))
+}
+
+fn revoked_entry_status(entry: &X509RevokedRef) -> Result<RevocationStatus> {
+    let reason = entry.extension::<ReasonCode>()?
+        .and_then(|(_, code)| code.get_i64().ok())
+        .and_then(reason_name)
+        .map(str::to_string);
+
+    let state = if reason.as_deref() == Some("removeFromCRL") {
+        RevocationState::Good
+    } else {
+        RevocationState::Revoked
+    };
+
+    Ok(RevocationStatus {
+        state,
+        revoked_at: Some(asn1_time_to_chrono(entry.revocation_date())?),
+        reason,
+        source: "crl".to_string(),
+        note: None,
+    })
+}
+
+/// Look a certificate up in the OpenSSL CA database (`index.txt`) of `issuer`
+///
+/// The database only lists serials, so the certificate must have been
+/// signed by `issuer` for a match to mean anything.
+pub fn check_index(cert: &X509, index: &str, issuer: Option<&X509>) -> Result<RevocationStatus> {
+    let Some(issuer) = issuer else {
+        return Ok(RevocationStatus::unknown("index", "CA certificate unavailable"));
+    };
+    if !issued_by(cert, issuer)? {
+        return Ok(RevocationStatus::unknown("index", "not issued by this CA"));
+    }
+
+    let serial = normalize_serial(&cert.serial_number().to_bn()?.to_hex_str()?);
+
+    for line in index.lines() {
+        // status, expiry, revocation[,reason], serial, filename, subject
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() < 4 || normalize_serial(fields[3]) != serial {
+            continue;
+        }
+
+        return Ok(match fields[0] {
+            "R" => {
+                let (date, reason) = match fields[2].split_once(',') {
+                    Some((date, reason)) => (date, Some(reason.to_string())),
+                    None => (fields[2], None),
+                };
+                RevocationStatus {
+                    state: RevocationState::Revoked,
+                    revoked_at: parse_index_time(date),
+                    reason,
+                    source: "index".to_string(),
+                    note: None,
+                }
+            }
+            "V" | "E" => RevocationStatus::new(RevocationState::Good, "index"),
+            _ => RevocationStatus::new(RevocationState::Unknown, "index"),
+        });
+    }
+
+    Ok(RevocationStatus::new(RevocationState::Unknown, "index"))
+}
+
+pub(crate) fn normalize_serial(serial: &str) -> String {
+    let trimmed = serial.trim().trim_start_matches('0').to_ascii_uppercase();
+    if trimmed.is_empty() { "0".to_string() } else { trimmed }
+}
+
+/// Parse an index.txt time (`YYMMDDHHMMSSZ` or `YYYYMMDDHHMMSSZ`)
+fn parse_index_time(value: &str) -> Option<DateTime<Utc>> {
+    let format = if value.len() == 15 { "%Y%m%d%H%M%SZ" } else { "%y%m%d%H%M%SZ" };
+    NaiveDateTime::parse_from_str(value, format)
+        .ok()
+        .map(|t| t.and_utc())
+}
+
+/// RFC 5280 reason code names, as used by OpenSSL
+fn reason_name(code: i64) -> Option<&'static str> {
+    Some(match code {
+        0 => "unspecified",
+        1 => "keyCompromise",
+        2 => "CACompromise",
+        3 => "affiliationChanged",
+        4 => "superseded",
+        5 => "cessationOfOperation",
+        6 => "certificateHold",
+        8 => "removeFromCRL",
+        9 => "privilegeWithdrawn",
+        10 => "AACompromise",
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openssl::asn1::{Asn1Integer, Asn1Time};
+    use openssl::bn::BigNum;
+    use openssl::hash::MessageDigest;
+    use openssl::pkey::PKey;
+    use openssl::rsa::Rsa;
+    use openssl::x509::{X509Builder, X509NameBuilder};
+
+    fn cert_with_serial(serial: u32) -> X509 {
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_text("CN", "revocation.test").unwrap();
+        let name = name.build();
+
+        let mut builder = X509Builder::new().unwrap();
+        builder.set_version(2).unwrap();
+        let serial = Asn1Integer::from_bn(&BigNum::from_u32(serial).unwrap()).unwrap();
+        builder.set_serial_number(&serial).unwrap();
+        builder.set_subject_name(&name).unwrap();
+        builder.set_issuer_name(&name).unwrap();
+        builder.set_pubkey(&key).unwrap();
+        builder.set_not_before(&Asn1Time::days_from_now(0).unwrap()).unwrap();
+        builder.set_not_after(&Asn1Time::days_from_now(1).unwrap()).unwrap();
+        builder.sign(&key, MessageDigest::sha256()).unwrap();
+        builder.build()
+    }
+
+    const INDEX: &str = "V\t270101000000Z\t\t1000\tunknown\t/CN=good\n\
+R\t270101000000Z\t250301120000Z,keyCompromise\t1001\tunknown\t/CN=revoked\n";
+
+    /// A self-signed certificate is its own issuer
+    fn check_own_index(cert: &X509) -> RevocationStatus {
+        check_index(cert, INDEX, Some(cert)).unwrap()
+    }
+
+    #[test]
+    fn test_check_index() {
+        let good = check_own_index(&cert_with_serial(0x1000));
+        assert_eq!(good.state, RevocationState::Good);
+
+        let revoked = check_own_index(&cert_with_serial(0x1001));
+        assert_eq!(revoked.state, RevocationState::Revoked);
+        assert_eq!(revoked.reason.as_deref(), Some("keyCompromise"));
+        assert_eq!(revoked.revoked_at.unwrap().to_rfc3339(), "2025-03-01T12:00:00+00:00");
+
+        let unknown = check_own_index(&cert_with_serial(0x2000));
+        assert_eq!(unknown.state, RevocationState::Unknown);
+    }
+
+    #[test]
+    fn test_check_index_foreign_issuer() {
+        // Same serial and issuer name as a revoked entry, but another CA's key
+        let cert = cert_with_serial(0x1001);
+        let other_ca = cert_with_serial(0x1001);
+        let status = check_index(&cert, INDEX, Some(&other_ca)).unwrap();
+        assert_eq!(status.state, RevocationState::Unknown);
+        assert_eq!(status.detail(), "index: not issued by this CA");
+
+        let status = check_index(&cert, INDEX, None).unwrap();
+        assert_eq!(status.state, RevocationState::Unknown);
+    }
+
+    #[test]
+    fn test_check_crl() {
+        use crate::ca::CertSigner;
+        use crate::crypto::SanEntry;
+        use crate::fixtures;
+
+        let fixture = |name: &str| load_crl(format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name)).unwrap();
+        let (crl, stale) = (fixture("intermediate.crl.pem"), fixture("intermediate-stale.crl.pem"));
+        let ca = fixtures::intermediate_ca();
+        let leaf = ca.sign(&fixtures::leaf_csr("nas01.lab", &[SanEntry::Dns("nas01.lab".to_string())]), 30).unwrap();
+
+        let status = check_crl(&leaf, &crl, Some(ca.cert())).unwrap();
+        assert_eq!((status.state, status.note), (RevocationState::Good, None));
+
+        // Past its nextUpdate, the CRL can't vouch for the certificate
+        let status = check_crl(&leaf, &stale, Some(ca.cert())).unwrap();
+        assert_eq!(status.state, RevocationState::Unknown);
+        assert_eq!(status.detail(), "crl: CRL is stale (next update was due 2026-01-08 00:00:00 UTC)");
+
+        // Nor can one whose signature wasn't checked
+        let status = check_crl(&leaf, &crl, None).unwrap();
+        assert_eq!(status.state, RevocationState::Unknown);
+        assert!(status.note.unwrap().contains("not verified"));
+
+        // A certificate from another CA that copied the issuer name
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let mut builder = X509Builder::new().unwrap();
+        builder.set_version(2).unwrap();
+        builder.set_serial_number(leaf.serial_number()).unwrap();
+        builder.set_subject_name(leaf.subject_name()).unwrap();
+        builder.set_issuer_name(leaf.issuer_name()).unwrap();
+        builder.set_pubkey(&key).unwrap();
+        builder.set_not_before(leaf.not_before()).unwrap();
+        builder.set_not_after(leaf.not_after()).unwrap();
+        builder.sign(&key, MessageDigest::sha256()).unwrap();
+        let forged = builder.build();
+        let status = check_crl(&forged, &crl, Some(ca.cert())).unwrap();
+        assert_eq!(status.state, RevocationState::Unknown);
+        assert_eq!(status.detail(), "crl: not issued by this CA");
+    }
+
+    #[test]
+    fn test_check_revocation_unconfigured() {
+        let cert = cert_with_serial(1);
+        assert!(check_revocation(&cert, &RevocationConfig::default(), None).unwrap().is_none());
+    }
+}
@@ -0,0 +1,286 @@
+//! Renewal daemon
+//!
+//! `flux-ssl-mgr daemon` wakes up every `[daemon] interval`, renews each
+//! certificate in the store with fewer than `renew_before` days left and
+//! pushes it to its deploy targets. Certificates listed in `[daemon] manifest`
+//! are renewed with that entry's SANs, profile and deploy targets; the rest
+//! keep the SANs they were issued with and go to their `[daemon.deploy]` targets.
+//! Deployments to targets outside their maintenance window are queued, and
+//! each pass first runs the queued ones whose window has opened.
+//!
+//! Certificates issued together would all renew in the same pass. `jitter`
+//! moves each certificate's threshold up to that many days earlier (by a
+//! fixed amount derived from its serial, so it doesn't move between passes),
+//! and `max_renewals_per_run` leaves the rest for later passes.
+//!
+//! Renewal can't prompt for anything, so the CA key has to be reachable
+//! through the signing agent (or be unencrypted), and certificates whose
+//! private keys are password-protected are skipped.
+
+use crate::batch::process_certificate;
+use crate::ca::{load_signer, CertSigner};
+use crate::config::Config;
+use crate::crypto::SanEntry;
+use crate::deploy::{self, PendingReport};
+use crate::duration::Days;
+use crate::error::Result;
+use crate::manifest::{Manifest, ManifestEntry};
+use crate::output::OutputFormatter;
+use crate::store::{CertStore, IssuedCert};
+use chrono::{DateTime, Duration, Utc};
+use std::sync::Arc;
+use tokio::time::MissedTickBehavior;
+
+/// Current certificates with fewer than `renew_before` days (less their
+/// jitter offset) left, soonest first
+pub fn due(store: &CertStore, renew_before: Days, jitter: Days, now: DateTime<Utc>) -> Vec<&IssuedCert> {
+    let mut due: Vec<&IssuedCert> = store.current()
+        .into_iter()
+        .filter(|cert| cert.not_after - now < renew_before.as_duration() - jitter_offset(&cert.serial, jitter, renew_before))
+        .collect();
+
+    due.sort_by_key(|cert| cert.not_after);
+    due
+}
+
+/// Stable offset in `[0, jitter]` for a serial, kept below `renew_before`
+fn jitter_offset(serial: &str, jitter: Days, renew_before: Days) -> Duration {
+    let max_secs = jitter.as_duration()
+        .min(renew_before.as_duration() - Duration::days(1))
+        .num_seconds();
+    if max_secs <= 0 {
+        return Duration::zero();
+    }
+
+    // FNV-1a, so the offset survives restarts and toolchain updates
+    let hash = serial.bytes().fold(0xcbf2_9ce4_8422_2325u64, |h, b| (h ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3));
+    Duration::seconds((hash % (max_secs as u64 + 1)) as i64)
+}
+
+/// Outcome of one renewal pass
+#[derive(Debug, Default)]
+pub struct PassReport {
+    pub renewed: Vec<String>,
+    /// Certificates that can't be renewed unattended, with the reason
+    pub skipped: Vec<(String, String)>,
+    /// Due but over `max_renewals_per_run`, left for the next pass
+    pub deferred: Vec<String>,
+    pub failed: Vec<(String, String)>,
+    /// Queued deployments run (or still waiting) this pass
+    pub pending: PendingReport,
+}
+
+impl PassReport {
+    /// Log a one-line summary plus every skip and failure
+    pub fn log(&self) {
+        for (queued, error) in &self.pending.failed {
+            tracing::error!("Queued deployment of {} to {} failed: {}", queued.name, queued.target, error);
+        }
+        if !self.pending.deployed.is_empty() || !self.pending.failed.is_empty() || self.pending.waiting > 0 {
+            tracing::info!(
+                "Queued deployments: {} deployed, {} failed, {} waiting for their window",
+                self.pending.deployed.len(), self.pending.failed.len(), self.pending.waiting
+            );
+        }
+        for (name, reason) in &self.skipped {
+            tracing::warn!("Skipped {}: {}", name, reason);
+        }
+        for (name, error) in &self.failed {
+            tracing::error!("Failed to renew {}: {}", name, error);
+        }
+        if !self.deferred.is_empty() {
+            tracing::info!("Deferred to the next pass: {}", self.deferred.join(", "));
+        }
+        tracing::info!(
+            "Renewal pass done: {} renewed, {} skipped, {} deferred, {} failed",
+            self.renewed.len(), self.skipped.len(), self.deferred.len(), self.failed.len()
+        );
+    }
+}
+
+/// Run queued deployments whose window is open, then renew and deploy
+/// everything that is due
+pub fn renew_due(config: &Config, output: &OutputFormatter) -> Result<PassReport> {
+    let mut report = PassReport {
+        pending: deploy::run_pending(config, output)?,
+        ..PassReport::default()
+    };
+
+    let store = CertStore::open_default(config)?;
+    let settings = &config.daemon;
+    let (locked, mut due): (Vec<IssuedCert>, Vec<IssuedCert>) = due(&store, settings.renew_before, settings.jitter, Utc::now())
+        .into_iter()
+        .cloned()
+        .partition(|cert| cert.key_encrypted);
+    for cert in locked {
+        report.skipped.push((cert.name, "private key is password-protected".to_string()));
+    }
+    if let Some(max) = settings.max_renewals_per_run {
+        report.deferred = due.split_off(max.min(due.len())).into_iter().map(|c| c.name).collect();
+    }
+    if due.is_empty() {
+        return Ok(report);
+    }
+
+    let manifest = config.daemon.manifest.as_ref().map(Manifest::from_file).transpose()?;
+    let signer = load_signer(config)?;
+
+    for cert in &due {
+        let entry = manifest.as_ref()
+            .and_then(|m| m.certificates.iter().find(|e| e.name == cert.name));
+        match renew(cert, entry, config, signer.as_ref(), output) {
+            Ok(()) => report.renewed.push(cert.name.clone()),
+            Err(e) => report.failed.push((cert.name.clone(), e.to_string())),
+        }
+    }
+
+    Ok(report)
+}
+
+/// Deploy targets of `name`: its `[daemon] manifest` entry's, or its
+/// `[daemon.deploy]` list
+pub fn deploy_targets(config: &Config, name: &str) -> Result<Vec<String>> {
+    let manifest = config.daemon.manifest.as_ref().map(Manifest::from_file).transpose()?;
+    let entry = manifest.as_ref().and_then(|m| m.certificates.iter().find(|e| e.name == name));
+
+    Ok(match entry {
+        Some(entry) => entry.deploy.clone(),
+        None => config.daemon.deploy.get(name).cloned().unwrap_or_default(),
+    })
+}
+
+/// Reissue one certificate and push it to its deploy targets
+fn renew(cert: &IssuedCert, entry: Option<&ManifestEntry>, config: &Config, signer: &dyn CertSigner, output: &OutputFormatter) -> Result<()> {
+    let (config, sans, targets) = match entry {
+        Some(entry) => (entry.effective_config(config)?, entry.san_entries()?, entry.deploy.clone()),
+        None => (
+            config.clone(),
+            cert.sans.iter().map(|s| SanEntry::parse(s)).collect::<Result<Vec<_>>>()?,
+            config.daemon.deploy.get(&cert.name).cloned().unwrap_or_default(),
+        ),
+    };
+
+    process_certificate(&cert.name, &sans, None, &config, signer, output)?;
+    targets.iter().try_for_each(|target| deploy::deploy_in_window(&cert.name, target, &config, output).map(|_| ()))
+}
+
+/// Run renewal passes every `[daemon] interval` until SIGINT/SIGTERM
+///
+/// The first pass runs immediately. A signal during a pass stops the daemon
+/// once that pass has finished.
+pub async fn run(config: Arc<Config>, output: OutputFormatter) -> Result<()> {
+    let mut ticker = tokio::time::interval(config.daemon.interval.get());
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
+
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            result = &mut shutdown => {
+                tracing::info!("Shutting down renewal daemon");
+                return result;
+            }
+        }
+
+        let (pass_config, pass_output) = (Arc::clone(&config), output.clone());
+        match tokio::task::spawn_blocking(move || renew_due(&pass_config, &pass_output)).await {
+            Ok(Ok(report)) => report.log(),
+            Ok(Err(e)) => tracing::error!("Renewal pass failed: {}", e),
+            Err(e) => tracing::error!("Renewal pass aborted: {}", e),
+        }
+    }
+}
+
+/// Resolves on Ctrl+C or (on unix) SIGTERM
+async fn shutdown_signal() -> Result<()> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut terminate = signal(SignalKind::terminate())?;
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => result?,
+            _ = terminate.recv() => {}
+        }
+    }
+
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn issued(name: &str, days_left: i64, now: DateTime<Utc>) -> IssuedCert {
+        IssuedCert {
+            name: name.to_string(),
+            serial: name.to_uppercase(),
+            subject: format!("CN={}", name),
+            sans: vec![format!("DNS:{}.lab", name)],
+            not_before: now - Duration::days(300),
+            not_after: now + Duration::days(days_left),
+            issued_at: now - Duration::days(300),
+            cert_path: PathBuf::from(format!("/tmp/{}.cert.pem", name)),
+            key_path: None,
+            key_encrypted: false,
+            revoked_at: None,
+            revocation_reason: None,
+            endpoints: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_due() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let now = Utc::now();
+        let mut store = CertStore::open(temp_dir.path().join("store.json")).unwrap();
+        store.record(issued("nas", 40, now));
+        store.record(issued("grafana", 10, now));
+        store.record(issued("router", -2, now));
+        store.record(issued("old", 5, now));
+        store.revoke("old", "superseded");
+
+        let names: Vec<&str> = due(&store, Days::new(30), Days::new(0), now).iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, vec!["router", "grafana"]);
+        assert!(due(&store, Days::new(1), Days::new(0), now).iter().all(|c| c.name == "router"));
+    }
+
+    #[test]
+    fn test_jitter_offset() {
+        let (jitter, renew_before) = (Days::new(7), Days::new(30));
+        let offsets: Vec<Duration> = (0..50).map(|i| jitter_offset(&format!("{:X}", i), jitter, renew_before)).collect();
+
+        assert!(offsets.iter().all(|o| *o >= Duration::zero() && *o <= jitter.as_duration()));
+        assert!(offsets.iter().any(|o| *o > Duration::days(3)), "offsets should spread out");
+        assert_eq!(jitter_offset("1A", jitter, renew_before), jitter_offset("1A", jitter, renew_before));
+        assert_eq!(jitter_offset("1A", Days::new(0), renew_before), Duration::zero());
+        // Never pushes the threshold to zero
+        assert!(jitter_offset("1A", Days::new(90), renew_before) < Duration::days(30));
+    }
+
+    #[test]
+    fn test_renew_due_skips_encrypted_keys() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let config = Config {
+            working_dir: temp_dir.path().to_path_buf(),
+            ..Config::default()
+        };
+        crate::store::update_store(&config, "Record", |store| {
+            let mut cert = issued("vault", 3, Utc::now());
+            cert.key_encrypted = true;
+            store.record(cert);
+        }).unwrap();
+
+        // Nothing renewable, so no CA is needed
+        let output = OutputFormatter::with_writer(&config.output, std::io::sink());
+        let report = renew_due(&config, &output).unwrap();
+        assert!(report.renewed.is_empty());
+        assert_eq!(report.skipped, vec![("vault".to_string(), "private key is password-protected".to_string())]);
+    }
+}
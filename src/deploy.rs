@@ -0,0 +1,833 @@
+//! Certificate deployment over SSH, to Kubernetes or to Docker containers
+//!
+//! Targets are defined under `[deploy.<target>]` in the config. For SSH
+//! targets, files are copied with the system `scp` and the optional restart
+//! command runs through `ssh`, so `~/.ssh/config`, the SSH agent and
+//! `known_hosts` apply as usual. Both run in batch mode: a host that would
+//! prompt for a password or an unknown host key fails instead of hanging.
+//!
+//! Kubernetes targets (`type = "kubernetes"`) pipe a TLS Secret to
+//! `kubectl apply`, using the target's context and kubeconfig if set.
+//!
+//! Docker targets (`type = "docker"`) write the files into a bind-mounted
+//! host directory or a named volume, then signal the container
+//! (`docker kill -s HUP`) and/or restart a compose service.
+//!
+//! Windows targets (`type = "windows"`) pipe a PowerShell script to
+//! `powershell -Command -`, on this machine or over ssh (Windows OpenSSH).
+//! It adds the certificate and key to `LocalMachine\My` and the CA
+//! certificates to `LocalMachine\CA` / `LocalMachine\Root` with .NET's
+//! `X509Store`, so no files are left on the host.
+//!
+//! A target with a `window` (see [`crate::window`]) only takes automatic
+//! deployments inside it: renewals outside the window queue the deployment in
+//! the store, and the renewal daemon runs it once the window opens.
+//!
+//! SSH and Docker targets with a `health` check (see [`crate::health`]) keep
+//! the files they replace until the probe after the reload has passed. If it
+//! never does, the previous files are put back and the reload runs again.
+
+use crate::batch::IssuancePaths;
+use crate::ca::chain_pem;
+use crate::config::{Config, DeployKind, DeployTarget};
+use crate::crypto::{certs_from_pem, is_key_encrypted, load_cert, load_private_key, to_pkcs12};
+use crate::error::{FluxError, Result};
+use crate::fsutil::{read_bounded, write_atomic};
+use crate::health::HealthCheck;
+use crate::k8s::{self, SecretRef};
+use crate::output::OutputFormatter;
+use crate::store::{update_store, CertStore, PendingDeploy};
+use chrono::Local;
+use openssl::base64;
+use openssl::pkey::{PKey, Private};
+use openssl::x509::X509;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Suffix of the copies kept on SSH targets until the health check passes
+const PREVIOUS_SUFFIX: &str = ".flux-prev";
+
+/// Look up a deploy target by name
+pub fn target<'a>(config: &'a Config, name: &str) -> Result<&'a DeployTarget> {
+    let target = config.deploy.get(name)
+        .ok_or_else(|| FluxError::DeployError(format!("unknown deploy target: {}", name)))?;
+
+    let missing_paths = target.cert_path.is_empty() || target.key_path.is_empty();
+    match target.kind {
+        DeployKind::Ssh if target.host.is_empty() || missing_paths => Err(FluxError::DeployError(format!(
+            "deploy target {} needs host, cert_path and key_path",
+            name
+        ))),
+        DeployKind::Docker if missing_paths => Err(FluxError::DeployError(format!(
+            "deploy target {} needs cert_path and key_path",
+            name
+        ))),
+        DeployKind::Kubernetes | DeployKind::Windows if target.health.is_some() => Err(FluxError::DeployError(format!(
+            "deploy target {}: health checks need an ssh or docker target",
+            name
+        ))),
+        DeployKind::Windows if target.host.is_empty() && !cfg!(windows) => Err(FluxError::DeployError(format!(
+            "deploy target {} needs a host, or flux-ssl-mgr running on Windows",
+            name
+        ))),
+        _ => Ok(target),
+    }
+}
+
+/// Push a certificate, its key and the CA chain to a target
+///
+/// SSH targets then run their restart command, Docker targets signal or
+/// restart their container; Kubernetes targets get the certificate as a TLS
+/// Secret.
+pub fn deploy(cert_name: &str, target_name: &str, config: &Config, output: &OutputFormatter) -> Result<()> {
+    push(cert_name, target_name, config, output)?;
+
+    // A deployment done by hand settles one queued for the window
+    let queued = CertStore::open_default(config)
+        .is_ok_and(|store| store.pending_deploys().iter().any(|p| p.name == cert_name && p.target == target_name));
+    if queued {
+        let message = format!("Deploy {} to {}", cert_name, target_name);
+        if let Err(e) = update_store(config, &message, |store| store.remove_deploy(cert_name, target_name)) {
+            output.warning(&format!("Failed to clear queued deployment of {} to {}: {}", cert_name, target_name, e));
+        }
+    }
+    Ok(())
+}
+
+/// Deploy now if the target's maintenance window is open, otherwise queue it
+///
+/// Returns whether the deployment ran.
+pub fn deploy_in_window(cert_name: &str, target_name: &str, config: &Config, output: &OutputFormatter) -> Result<bool> {
+    let now = Local::now().naive_local();
+    let window = match &target(config, target_name)?.window {
+        Some(window) if !window.contains(now) => window,
+        _ => return deploy(cert_name, target_name, config, output).map(|()| true),
+    };
+
+    let message = format!("Queue deploy of {} to {}", cert_name, target_name);
+    update_store(config, &message, |store| store.queue_deploy(cert_name, target_name))?;
+
+    let next = window.next_open(now)
+        .map_or_else(|| "never".to_string(), |t| t.format("%Y-%m-%d %H:%M").to_string());
+    output.info(&format!(
+        "Deferred deploying {} to {} to its maintenance window (next: {})",
+        cert_name, target_name, next
+    ));
+    Ok(false)
+}
+
+/// Outcome of running queued deployments
+#[derive(Debug, Default)]
+pub struct PendingReport {
+    pub deployed: Vec<PendingDeploy>,
+    /// Failed deployments (still queued) with the error
+    pub failed: Vec<(PendingDeploy, String)>,
+    /// Queued deployments whose window isn't open
+    pub waiting: usize,
+}
+
+/// Run queued deployments whose maintenance window is open
+///
+/// Failed deployments stay queued; ones whose target was removed from the
+/// config are dropped.
+pub fn run_pending(config: &Config, output: &OutputFormatter) -> Result<PendingReport> {
+    let mut report = PendingReport::default();
+    let now = Local::now().naive_local();
+
+    let pending = CertStore::open_default(config)?.pending_deploys().to_vec();
+    for queued in pending {
+        if !config.deploy.contains_key(&queued.target) {
+            output.warning(&format!("Dropping queued deployment of {} to unknown target {}", queued.name, queued.target));
+            let message = format!("Drop queued deploy of {} to {}", queued.name, queued.target);
+            update_store(config, &message, |store| store.remove_deploy(&queued.name, &queued.target))?;
+            continue;
+        }
+
+        let open = target(config, &queued.target)
+            .map(|t| t.window.as_ref().is_none_or(|w| w.contains(now)))
+            .unwrap_or(true);
+        if !open {
+            report.waiting += 1;
+            continue;
+        }
+
+        match deploy(&queued.name, &queued.target, config, output) {
+            Ok(()) => report.deployed.push(queued),
+            Err(e) => report.failed.push((queued, e.to_string())),
+        }
+    }
+
+    Ok(report)
+}
+
+/// Push without looking at maintenance windows or the queue
+fn push(cert_name: &str, target_name: &str, config: &Config, output: &OutputFormatter) -> Result<()> {
+    let target = target(config, target_name)?;
+    let (cert_path, key_path) = local_files(cert_name, config)?;
+
+    match target.kind {
+        DeployKind::Kubernetes => {
+            return deploy_kubernetes(cert_name, target_name, target, &cert_path, &key_path, config, output);
+        }
+        DeployKind::Docker => {
+            return deploy_docker(cert_name, target_name, target, &cert_path, &key_path, config, output);
+        }
+        DeployKind::Windows => {
+            return deploy_windows(cert_name, target_name, target, &cert_path, &key_path, config, output);
+        }
+        DeployKind::Ssh => {}
+    }
+
+    output.step(&format!("Deploying {} to {} ({})", cert_name, target_name, target.host));
+
+    let mut remote_files = vec![remote_path(&target.cert_path, cert_name), remote_path(&target.key_path, cert_name)];
+    remote_files.extend(target.chain_path.as_ref().map(|p| remote_path(p, cert_name)));
+    if target.health.is_some() {
+        output.verbose(&format!("Keeping the current files on {}", target.host));
+        run(ssh_command(target, &backup_script(&remote_files)), "ssh")?;
+    }
+
+    copy_file(target, &cert_path, &remote_files[0])?;
+    copy_file(target, &key_path, &remote_files[1])?;
+
+    if let Some(remote_chain) = remote_files.get(2) {
+        let chain = chain_file(config)?;
+        copy_file(target, chain.path(), remote_chain)?;
+    }
+
+    let restart = || match &target.restart_command {
+        Some(restart) => {
+            output.verbose(&format!("Running on {}: {}", target.host, restart));
+            run(ssh_command(target, restart), "ssh").map(|_| ())
+        }
+        None => Ok(()),
+    };
+    restart()?;
+
+    if let Some(health) = &target.health {
+        check_health(target_name, health, &cert_path, output, || {
+            run(ssh_command(target, &restore_script(&remote_files)), "ssh")?;
+            restart()
+        })?;
+        if let Err(e) = run(ssh_command(target, &cleanup_script(&remote_files)), "ssh") {
+            output.warning(&format!("Failed to remove the previous files from {}: {}", target.host, e));
+        }
+    }
+
+    output.success(&format!("Deployed {} to {}", cert_name, target_name));
+    Ok(())
+}
+
+/// Probe the target, running `rollback` if every attempt fails
+fn check_health(
+    target_name: &str,
+    health: &HealthCheck,
+    cert_path: &Path,
+    output: &OutputFormatter,
+    rollback: impl FnOnce() -> Result<()>,
+) -> Result<()> {
+    output.step(&format!("Checking {} ({})", target_name, health.probe));
+    let Err(e) = health.run(&load_cert(cert_path)?) else {
+        return Ok(());
+    };
+
+    output.warning(&format!("{}; restoring the previous certificate on {}", e, target_name));
+    match rollback() {
+        Ok(()) => Err(FluxError::DeployError(format!(
+            "{} was rolled back to the previous certificate: {}",
+            target_name, e
+        ))),
+        Err(rollback_err) => Err(FluxError::DeployError(format!(
+            "{} failed its health check ({}) and the rollback failed: {}",
+            target_name, e, rollback_err
+        ))),
+    }
+}
+
+/// Certificate and key to deploy: the latest issuance, or the output directory
+fn local_files(cert_name: &str, config: &Config) -> Result<(PathBuf, PathBuf)> {
+    let (cert, key) = IssuancePaths::current(config, cert_name);
+
+    for file in [&cert, &key] {
+        if !file.exists() {
+            return Err(FluxError::DeployError(format!(
+                "{} not found; issue {} before deploying it",
+                file.display(),
+                cert_name
+            )));
+        }
+    }
+
+    Ok((cert, key))
+}
+
+/// Intermediate (and root, if present) CA certificates in one temporary file
+fn chain_file(config: &Config) -> Result<tempfile::NamedTempFile> {
+    let mut file = tempfile::NamedTempFile::new()?;
+    file.write_all(&chain_pem(config)?)?;
+    file.flush()?;
+    Ok(file)
+}
+
+/// Apply the certificate as a TLS Secret with kubectl
+fn deploy_kubernetes(
+    cert_name: &str,
+    target_name: &str,
+    target: &DeployTarget,
+    cert_path: &Path,
+    key_path: &Path,
+    config: &Config,
+    output: &OutputFormatter,
+) -> Result<()> {
+    let secret = SecretRef::new(
+        cert_name,
+        target.namespace.as_deref().unwrap_or(&config.kubernetes.namespace),
+        target.secret_name.as_deref().unwrap_or(&config.kubernetes.secret_name),
+    )?;
+    let manifest = k8s::secret_from_files(&secret, cert_path, key_path, config)?;
+
+    output.step(&format!(
+        "Deploying {} to {} (secret {}/{})",
+        cert_name, target_name, secret.namespace, secret.name
+    ));
+
+    let mut child = kubectl_command(target)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| FluxError::DeployError(format!("failed to run kubectl: {}", e)))?;
+
+    // Report kubectl's own error rather than a broken pipe if it exits early
+    let written = child.stdin.take().map_or(Ok(()), |mut stdin| stdin.write_all(manifest.as_bytes()));
+    check_output(child.wait_with_output(), "kubectl")?;
+    written?;
+
+    output.success(&format!("Deployed {} to {}", cert_name, target_name));
+    Ok(())
+}
+
+/// `kubectl apply` reading a manifest from stdin
+fn kubectl_command(target: &DeployTarget) -> Command {
+    let mut cmd = Command::new("kubectl");
+    if let Some(kubeconfig) = &target.kubeconfig {
+        cmd.arg("--kubeconfig").arg(kubeconfig);
+    }
+    if let Some(context) = &target.context {
+        cmd.arg("--context").arg(context);
+    }
+    cmd.args(["apply", "-f", "-"]);
+    cmd
+}
+
+/// Import into the Windows certificate store with a piped PowerShell script
+fn deploy_windows(
+    cert_name: &str,
+    target_name: &str,
+    target: &DeployTarget,
+    cert_path: &Path,
+    key_path: &Path,
+    config: &Config,
+    output: &OutputFormatter,
+) -> Result<()> {
+    if is_key_encrypted(key_path)? {
+        return Err(FluxError::DeployError(format!(
+            "{} is password-protected; windows targets need an unencrypted key",
+            key_path.display()
+        )));
+    }
+    let cert = load_cert(cert_path)?;
+    let key = load_private_key(key_path, None)?;
+    let ca_certs = certs_from_pem(&chain_pem(config)?)?;
+    let script = windows_import_script(cert_name, &cert, &key, &ca_certs, target.restart_command.as_deref())?;
+
+    let (mut cmd, place) = if target.host.is_empty() {
+        let mut cmd = Command::new("powershell.exe");
+        cmd.args(POWERSHELL_ARGS);
+        (cmd, "this machine".to_string())
+    } else {
+        (ssh_command(target, &format!("powershell {}", POWERSHELL_ARGS.join(" "))), target.host.clone())
+    };
+    output.step(&format!("Deploying {} to {} ({})", cert_name, target_name, place));
+
+    let mut child = cmd
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| FluxError::DeployError(format!("failed to run powershell: {}", e)))?;
+    let written = child.stdin.take().map_or(Ok(()), |mut stdin| stdin.write_all(script.as_bytes()));
+    let thumbprint = check_output(child.wait_with_output(), "powershell")?;
+    written?;
+
+    output.verbose(&format!("Thumbprint {}", thumbprint.trim()));
+    output.success(&format!("Deployed {} to {}", cert_name, target_name));
+    Ok(())
+}
+
+/// PowerShell reading its script from stdin
+const POWERSHELL_ARGS: [&str; 4] = ["-NoProfile", "-NonInteractive", "-Command", "-"];
+
+/// Script adding the certificate and key (as an in-memory PFX) to
+/// `LocalMachine\My`, self-signed CA certificates to `Root` and the others
+/// to `CA`, then running `restart`
+///
+/// `-Command -` runs stdin line by line, so every statement is one line.
+fn windows_import_script(
+    cert_name: &str,
+    cert: &X509,
+    key: &PKey<Private>,
+    ca_certs: &[X509],
+    restart: Option<&str>,
+) -> Result<String> {
+    let pfx = to_pkcs12(cert_name, cert, key, ca_certs, "")?;
+
+    let mut script = String::from(
+        "$ErrorActionPreference = 'Stop'\n\
+         function Add-FluxCertificate($Cert, $Name) { $Store = [Security.Cryptography.X509Certificates.X509Store]::new($Name, 'LocalMachine'); $Store.Open('ReadWrite'); $Store.Add($Cert); $Store.Close() }\n",
+    );
+    script.push_str(&format!(
+        "$Cert = [Security.Cryptography.X509Certificates.X509Certificate2]::new([Convert]::FromBase64String('{}'), '', 'MachineKeySet, PersistKeySet')\n",
+        base64::encode_block(&pfx)
+    ));
+    script.push_str("Add-FluxCertificate $Cert 'My'\n");
+    for ca in ca_certs {
+        let store = if ca.subject_name().to_der()? == ca.issuer_name().to_der()? { "Root" } else { "CA" };
+        script.push_str(&format!(
+            "Add-FluxCertificate ([Security.Cryptography.X509Certificates.X509Certificate2]::new([Convert]::FromBase64String('{}'))) '{}'\n",
+            base64::encode_block(&ca.to_der()?),
+            store
+        ));
+    }
+    if let Some(restart) = restart {
+        script.push_str(restart);
+        script.push('\n');
+    }
+    script.push_str("Write-Output $Cert.Thumbprint\n");
+    Ok(script)
+}
+
+/// Expand `{name}` in a remote path template
+pub fn remote_path(template: &str, cert_name: &str) -> String {
+    template.replace("{name}", cert_name)
+}
+
+/// Copy into a bind mount or volume, then signal/restart the container
+fn deploy_docker(
+    cert_name: &str,
+    target_name: &str,
+    target: &DeployTarget,
+    cert_path: &Path,
+    key_path: &Path,
+    config: &Config,
+    output: &OutputFormatter,
+) -> Result<()> {
+    // Paths are relative to the volume's mountpoint, or host paths for bind mounts
+    let base = match &target.volume {
+        Some(volume) => {
+            let mountpoint = run(volume_command(volume), "docker volume inspect")?;
+            Some(PathBuf::from(mountpoint.trim()))
+        }
+        None => None,
+    };
+    let local_path = |template: &str| {
+        let path = remote_path(template, cert_name);
+        match &base {
+            Some(base) => base.join(path.trim_start_matches('/')),
+            None => PathBuf::from(path),
+        }
+    };
+
+    output.step(&format!(
+        "Deploying {} to {} ({})",
+        cert_name,
+        target_name,
+        target.volume.as_deref().map_or("bind mount".to_string(), |v| format!("volume {}", v))
+    ));
+
+    let mut files = vec![
+        (local_path(&target.cert_path), read_bounded(cert_path)?, config.permissions.certificate),
+        (local_path(&target.key_path), read_bounded(key_path)?, config.permissions.private_key),
+    ];
+    if let Some(chain_path) = &target.chain_path {
+        files.push((local_path(chain_path), chain_pem(config)?, config.permissions.certificate));
+    }
+
+    // Contents being replaced, kept in memory until the health check passes
+    let previous: Vec<(&Path, Vec<u8>, u32)> = match target.health {
+        Some(_) => files.iter()
+            .filter_map(|(path, _, mode)| std::fs::read(path).ok().map(|old| (path.as_path(), old, *mode)))
+            .collect(),
+        None => Vec::new(),
+    };
+
+    for (path, contents, mode) in &files {
+        install_file(contents, path, *mode)?;
+    }
+    reload_container(target, output)?;
+
+    if let Some(health) = &target.health {
+        check_health(target_name, health, cert_path, output, || {
+            previous.iter().try_for_each(|(path, old, mode)| install_file(old, path, *mode))?;
+            reload_container(target, output)
+        })?;
+    }
+
+    output.success(&format!("Deployed {} to {}", cert_name, target_name));
+    Ok(())
+}
+
+/// Signal the container and/or restart the compose service
+fn reload_container(target: &DeployTarget, output: &OutputFormatter) -> Result<()> {
+    if let Some(container) = &target.container {
+        output.verbose(&format!("Signalling container {}", container));
+        run(signal_command(target, container), "docker kill")?;
+    }
+    if let Some(service) = &target.compose_service {
+        output.verbose(&format!("Restarting compose service {}", service));
+        run(compose_restart_command(target, service), "docker compose")?;
+    }
+    Ok(())
+}
+
+/// Atomically write `contents` to `path` (creating parent directories) with `mode`
+fn install_file(contents: &[u8], path: &Path, mode: u32) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    write_atomic(path, contents, mode)
+}
+
+/// `docker volume inspect` printing the volume's host mountpoint
+fn volume_command(volume: &str) -> Command {
+    let mut cmd = Command::new("docker");
+    cmd.args(["volume", "inspect", "--format", "{{ .Mountpoint }}", volume]);
+    cmd
+}
+
+/// `docker kill -s <signal>` (HUP unless configured)
+fn signal_command(target: &DeployTarget, container: &str) -> Command {
+    let mut cmd = Command::new("docker");
+    cmd.args(["kill", "-s", target.signal.as_deref().unwrap_or("HUP"), container]);
+    cmd
+}
+
+/// `docker compose [-f file] restart <service>`
+fn compose_restart_command(target: &DeployTarget, service: &str) -> Command {
+    let mut cmd = Command::new("docker");
+    cmd.arg("compose");
+    if let Some(file) = &target.compose_file {
+        cmd.arg("-f").arg(file);
+    }
+    cmd.args(["restart", service]);
+    cmd
+}
+
+/// `user@host`, or just `host`
+fn destination(target: &DeployTarget) -> String {
+    match &target.user {
+        Some(user) => format!("{}@{}", user, target.host),
+        None => target.host.clone(),
+    }
+}
+
+/// Options shared by ssh and scp (`port_flag` is `-p` for ssh, `-P` for scp)
+fn common_args(target: &DeployTarget, port_flag: &str) -> Vec<String> {
+    let mut args = vec!["-o".to_string(), "BatchMode=yes".to_string()];
+    if let Some(port) = target.port {
+        args.push(port_flag.to_string());
+        args.push(port.to_string());
+    }
+    if let Some(identity) = &target.identity_file {
+        args.push("-i".to_string());
+        args.push(identity.display().to_string());
+    }
+    args
+}
+
+/// scp invocation copying `local` to `remote` (file modes are preserved)
+fn scp_command(target: &DeployTarget, local: &Path, remote: &str) -> Command {
+    let mut cmd = Command::new("scp");
+    cmd.args(common_args(target, "-P"))
+        .arg("-p")
+        .arg("-q")
+        .arg(local)
+        .arg(format!("{}:{}", destination(target), remote));
+    cmd
+}
+
+/// ssh invocation running `remote_command` on the target
+fn ssh_command(target: &DeployTarget, remote_command: &str) -> Command {
+    let mut cmd = Command::new("ssh");
+    cmd.args(common_args(target, "-p"))
+        .arg(destination(target))
+        .arg("--")
+        .arg(remote_command);
+    cmd
+}
+
+/// Quote for a POSIX shell
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+/// Remote script copying each existing file to `<file>.flux-prev`
+fn backup_script(files: &[String]) -> String {
+    format!(
+        "for f in {}; do if [ -e \"$f\" ]; then cp -p \"$f\" \"$f{suffix}\"; else rm -f \"$f{suffix}\"; fi; done",
+        files.iter().map(|f| shell_quote(f)).collect::<Vec<_>>().join(" "),
+        suffix = PREVIOUS_SUFFIX
+    )
+}
+
+/// Remote script moving the `.flux-prev` copies back into place
+fn restore_script(files: &[String]) -> String {
+    format!(
+        "for f in {}; do if [ -e \"$f{suffix}\" ]; then mv -f \"$f{suffix}\" \"$f\"; fi; done",
+        files.iter().map(|f| shell_quote(f)).collect::<Vec<_>>().join(" "),
+        suffix = PREVIOUS_SUFFIX
+    )
+}
+
+/// Remote command removing the `.flux-prev` copies
+fn cleanup_script(files: &[String]) -> String {
+    let copies: Vec<String> = files.iter().map(|f| shell_quote(&format!("{}{}", f, PREVIOUS_SUFFIX))).collect();
+    format!("rm -f {}", copies.join(" "))
+}
+
+fn copy_file(target: &DeployTarget, local: &Path, remote: &str) -> Result<()> {
+    run(scp_command(target, local, remote), "scp").map(|_| ())
+}
+
+/// Run a command and return its stdout, turning a non-zero exit into a deploy error with its stderr
+fn run(mut cmd: Command, what: &str) -> Result<String> {
+    check_output(cmd.output(), what)
+}
+
+fn check_output(out: std::io::Result<std::process::Output>, what: &str) -> Result<String> {
+    let out = out
+        .map_err(|e| FluxError::DeployError(format!("failed to run {}: {}", what, e)))?;
+
+    if !out.status.success() {
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        return Err(FluxError::DeployError(format!(
+            "{} exited with {}: {}",
+            what,
+            out.status,
+            stderr.trim()
+        )));
+    }
+
+    Ok(String::from_utf8_lossy(&out.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::duration::Interval;
+    use crate::fixtures;
+    use crate::health::Probe;
+
+    fn router() -> DeployTarget {
+        DeployTarget {
+            host: "10.0.0.1".to_string(),
+            user: Some("admin".to_string()),
+            port: Some(2222),
+            identity_file: Some(PathBuf::from("/keys/deploy")),
+            cert_path: "/etc/ssl/{name}.crt".to_string(),
+            key_path: "/etc/ssl/private/{name}.key".to_string(),
+            chain_path: None,
+            restart_command: Some("systemctl reload nginx".to_string()),
+            ..DeployTarget::default()
+        }
+    }
+
+    fn args(cmd: &Command) -> Vec<String> {
+        cmd.get_args().map(|a| a.to_string_lossy().into_owned()).collect()
+    }
+
+    #[test]
+    fn test_commands() {
+        let target = router();
+
+        let scp = scp_command(&target, Path::new("/out/web.crt"), &remote_path(&target.cert_path, "web"));
+        assert_eq!(scp.get_program(), "scp");
+        assert_eq!(args(&scp), [
+            "-o", "BatchMode=yes", "-P", "2222", "-i", "/keys/deploy",
+            "-p", "-q", "/out/web.crt", "admin@10.0.0.1:/etc/ssl/web.crt",
+        ]);
+
+        let ssh = ssh_command(&target, "systemctl reload nginx");
+        assert_eq!(args(&ssh), [
+            "-o", "BatchMode=yes", "-p", "2222", "-i", "/keys/deploy",
+            "admin@10.0.0.1", "--", "systemctl reload nginx",
+        ]);
+
+        let files = vec!["/etc/ssl/web.crt".to_string(), "/etc/ssl/it's.key".to_string()];
+        assert_eq!(
+            restore_script(&files),
+            "for f in '/etc/ssl/web.crt' '/etc/ssl/it'\\''s.key'; do if [ -e \"$f.flux-prev\" ]; then mv -f \"$f.flux-prev\" \"$f\"; fi; done"
+        );
+        assert_eq!(cleanup_script(&files[..1]), "rm -f '/etc/ssl/web.crt.flux-prev'");
+    }
+
+    #[test]
+    fn test_deploy_checks_target_and_files() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut config = Config {
+            working_dir: temp_dir.path().join("pki"),
+            output_dir: temp_dir.path().join("out"),
+            ..Config::default()
+        };
+        config.deploy.insert("router".to_string(), router());
+        let output = OutputFormatter::with_writer(&config.output, std::io::sink());
+
+        let err = deploy("web", "nas", &config, &output).unwrap_err();
+        assert!(err.to_string().contains("unknown deploy target"));
+
+        let err = deploy("web", "router", &config, &output).unwrap_err();
+        assert!(err.to_string().contains("issue web before deploying"));
+
+        config.deploy.insert("broken".to_string(), DeployTarget { host: String::new(), ..router() });
+        let err = target(&config, "broken").unwrap_err();
+        assert!(err.to_string().contains("needs host"));
+    }
+
+    #[test]
+    fn test_kubectl_command() {
+        let cluster = DeployTarget {
+            kind: DeployKind::Kubernetes,
+            context: Some("homelab".to_string()),
+            kubeconfig: Some(PathBuf::from("/etc/kube/config")),
+            ..DeployTarget::default()
+        };
+
+        let cmd = kubectl_command(&cluster);
+        assert_eq!(cmd.get_program(), "kubectl");
+        assert_eq!(args(&cmd), [
+            "--kubeconfig", "/etc/kube/config", "--context", "homelab", "apply", "-f", "-",
+        ]);
+    }
+
+    #[test]
+    fn test_docker_bind_mount() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut config = Config {
+            working_dir: temp_dir.path().join("pki"),
+            output_dir: temp_dir.path().join("out"),
+            ca_cert_path: temp_dir.path().join("pki/ca.cert.pem"),
+            ..Config::default()
+        };
+        std::fs::create_dir_all(&config.output_dir).unwrap();
+        let paths = IssuancePaths::new(&config, "web");
+        std::fs::write(&paths.output_cert_pem, "CERT").unwrap();
+        std::fs::write(&paths.output_key, "KEY").unwrap();
+
+        let mount = temp_dir.path().join("mount");
+        let target = DeployTarget {
+            kind: DeployKind::Docker,
+            cert_path: mount.join("{name}/tls.crt").display().to_string(),
+            key_path: mount.join("{name}/tls.key").display().to_string(),
+            ..DeployTarget::default()
+        };
+        config.deploy.insert("proxy".to_string(), target.clone());
+        let output = OutputFormatter::with_writer(&config.output, std::io::sink());
+
+        deploy("web", "proxy", &config, &output).unwrap();
+        assert_eq!(std::fs::read_to_string(mount.join("web/tls.crt")).unwrap(), "CERT");
+        assert_eq!(std::fs::read_to_string(mount.join("web/tls.key")).unwrap(), "KEY");
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(mount.join("web/tls.key")).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, config.permissions.private_key);
+        }
+
+        let target = DeployTarget {
+            container: Some("nginx".to_string()),
+            compose_service: Some("proxy".to_string()),
+            compose_file: Some(PathBuf::from("/srv/compose.yaml")),
+            ..target
+        };
+        assert_eq!(args(&signal_command(&target, "nginx")), ["kill", "-s", "HUP", "nginx"]);
+        assert_eq!(
+            args(&compose_restart_command(&target, "proxy")),
+            ["compose", "-f", "/srv/compose.yaml", "restart", "proxy"]
+        );
+    }
+
+    #[test]
+    fn test_docker_health_rollback() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut config = Config {
+            working_dir: temp_dir.path().join("pki"),
+            output_dir: temp_dir.path().join("out"),
+            ca_cert_path: temp_dir.path().join("pki/ca.cert.pem"),
+            ..Config::default()
+        };
+        std::fs::create_dir_all(&config.output_dir).unwrap();
+        let paths = IssuancePaths::new(&config, "web");
+        std::fs::write(&paths.output_cert_pem, fixtures::root_ca_cert().to_pem().unwrap()).unwrap();
+        std::fs::write(&paths.output_key, "NEW KEY").unwrap();
+
+        let mount = temp_dir.path().join("mount");
+        std::fs::create_dir_all(&mount).unwrap();
+        std::fs::write(mount.join("tls.crt"), "OLD CERT").unwrap();
+        std::fs::write(mount.join("tls.key"), "OLD KEY").unwrap();
+
+        // A port nothing listens on
+        let address = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string();
+        config.deploy.insert("proxy".to_string(), DeployTarget {
+            kind: DeployKind::Docker,
+            cert_path: mount.join("tls.crt").display().to_string(),
+            key_path: mount.join("tls.key").display().to_string(),
+            health: Some(HealthCheck {
+                probe: Probe::Tcp { address },
+                attempts: 1,
+                delay: Interval::from_secs(0),
+                timeout: Interval::from_secs(1),
+            }),
+            ..DeployTarget::default()
+        });
+        let output = OutputFormatter::with_writer(&config.output, std::io::sink());
+
+        let err = deploy("web", "proxy", &config, &output).unwrap_err();
+        assert!(err.to_string().contains("rolled back"), "{}", err);
+        assert_eq!(std::fs::read_to_string(mount.join("tls.crt")).unwrap(), "OLD CERT");
+        assert_eq!(std::fs::read_to_string(mount.join("tls.key")).unwrap(), "OLD KEY");
+    }
+
+    #[test]
+    fn test_windows_import_script() {
+        use crate::ca::CertSigner;
+
+        let ca = fixtures::intermediate_ca();
+        let cert = ca.sign(&fixtures::leaf_csr("iis.lab", &[]), 30).unwrap();
+        let key = fixtures::leaf_key();
+        let chain = [ca.cert().clone(), fixtures::root_ca_cert()];
+        let script = windows_import_script("iis.lab", &cert, &key, &chain, Some("Restart-Service W3SVC")).unwrap();
+
+        let lines: Vec<&str> = script.lines().collect();
+        assert_eq!(lines.len(), 8);
+        assert_eq!(lines[3], "Add-FluxCertificate $Cert 'My'");
+        assert!(lines[4].ends_with("'CA'") && lines[5].ends_with("'Root'"));
+        assert_eq!(lines[6], "Restart-Service W3SVC");
+        assert_eq!(lines[7], "Write-Output $Cert.Thumbprint");
+
+        // The PFX embedded in the script opens with an empty password
+        let encoded = lines[2].split('\'').nth(1).unwrap();
+        let parsed = openssl::pkcs12::Pkcs12::from_der(&base64::decode_block(encoded).unwrap()).unwrap().parse2("").unwrap();
+        assert_eq!(parsed.cert.unwrap().to_der().unwrap(), cert.to_der().unwrap());
+        assert_eq!(parsed.ca.unwrap().len(), 2);
+        assert!(parsed.pkey.unwrap().public_eq(&key));
+
+        let host = DeployTarget { kind: DeployKind::Windows, health: None, ..router() };
+        let config = Config {
+            deploy: [("win".to_string(), host)].into(),
+            ..Config::default()
+        };
+        assert!(target(&config, "win").is_ok());
+    }
+}
@@ -0,0 +1,309 @@
+//! Configuration diagnostics (`flux-ssl-mgr config --doctor`)
+//!
+//! Checks everything issuance depends on up front: paths, whether the CA key
+//! and certificate belong together, CA expiry, directory permissions and
+//! whether the output directory can be written. Unlike `Config::validate`,
+//! every problem is reported, each with a hint on how to fix it.
+
+use crate::ca::intermediate::ca_key_password;
+use crate::config::Config;
+use crate::crypto::cert::asn1_time_to_chrono;
+use crate::crypto::key::{inspect_key, matches_cert, parse_private_key};
+use crate::crypto::{format_name, load_cert};
+use crate::fsutil::{managed_dirs, read_bounded};
+use openssl::x509::X509;
+use secrecy::ExposeSecret;
+use std::path::Path;
+use zeroize::Zeroizing;
+
+/// How bad a finding is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Ok,
+    /// Issuance works, but something should be fixed
+    Warning,
+    /// Issuance will fail
+    Error,
+}
+
+/// One finding of [`diagnose`]
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    /// What to do about it
+    pub hint: Option<String>,
+}
+
+impl Diagnostic {
+    fn ok(message: impl Into<String>) -> Self {
+        Self { severity: Severity::Ok, message: message.into(), hint: None }
+    }
+
+    fn warning(message: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self { severity: Severity::Warning, message: message.into(), hint: Some(hint.into()) }
+    }
+
+    fn error(message: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self { severity: Severity::Error, message: message.into(), hint: Some(hint.into()) }
+    }
+}
+
+/// Check `config` against the filesystem and the CA
+pub fn diagnose(config: &Config) -> Vec<Diagnostic> {
+    let mut findings = Vec::new();
+
+    if config.working_dir.is_dir() {
+        findings.push(Diagnostic::ok(format!("Working directory {}", config.working_dir.display())));
+    } else {
+        findings.push(Diagnostic::error(
+            format!("Working directory {} does not exist", config.working_dir.display()),
+            "set working_dir to the root of the PKI directory",
+        ));
+    }
+
+    let ca_cert = check_ca_cert(config, &mut findings);
+    check_ca_key(config, ca_cert.as_ref(), &mut findings);
+
+    if let Some(openssl_config) = &config.openssl_config {
+        if !openssl_config.exists() {
+            findings.push(Diagnostic::error(
+                format!("OpenSSL config {} does not exist", openssl_config.display()),
+                "fix openssl_config or remove it; it is optional",
+            ));
+        }
+    }
+
+    check_dir_modes(config, &mut findings);
+    check_output_dir(&config.output_dir, &mut findings);
+
+    if let Err(e) = crate::policy::validate(&config.policy) {
+        findings.push(Diagnostic::error(e.to_string(), "fix or remove the rule in [policy]"));
+    }
+
+    if !config.csr_input_dir.is_dir() {
+        findings.push(Diagnostic::warning(
+            format!("CSR input directory {} does not exist", config.csr_input_dir.display()),
+            "create it or set csr_input_dir; batch mode reads CSRs from there",
+        ));
+    }
+
+    findings
+}
+
+fn check_ca_cert(config: &Config, findings: &mut Vec<Diagnostic>) -> Option<X509> {
+    let path = &config.ca_cert_path;
+    if !path.exists() {
+        findings.push(Diagnostic::error(
+            format!("CA certificate {} does not exist", path.display()),
+            "set ca_cert_path to the intermediate CA certificate",
+        ));
+        return None;
+    }
+    let cert = match load_cert(path) {
+        Ok(cert) => cert,
+        Err(e) => {
+            findings.push(Diagnostic::error(
+                format!("CA certificate {} can't be read: {}", path.display(), e),
+                "ca_cert_path must be a PEM or DER certificate",
+            ));
+            return None;
+        }
+    };
+
+    let subject = format_name(cert.subject_name());
+    match asn1_time_to_chrono(cert.not_after()) {
+        Ok(not_after) => {
+            let days = (not_after - chrono::Utc::now()).num_days();
+            let until = not_after.format("%Y-%m-%d");
+            if days < 0 {
+                findings.push(Diagnostic::error(
+                    format!("CA certificate {} expired on {}", subject, until),
+                    "run `flux-ssl-mgr ca renew-intermediate`",
+                ));
+            } else if days < i64::from(config.check.warning_days.get()) {
+                findings.push(Diagnostic::warning(
+                    format!("CA certificate {} expires in {} days ({})", subject, days, until),
+                    "run `flux-ssl-mgr ca renew-intermediate` before issued certificates get shortened",
+                ));
+            } else {
+                findings.push(Diagnostic::ok(format!("CA certificate {}, valid until {}", subject, until)));
+            }
+        }
+        Err(e) => findings.push(Diagnostic::error(
+            format!("CA certificate {} has an unreadable expiry date: {}", subject, e),
+            "replace the CA certificate",
+        )),
+    }
+    Some(cert)
+}
+
+fn check_ca_key(config: &Config, ca_cert: Option<&X509>, findings: &mut Vec<Diagnostic>) {
+    let path = &config.ca_key_path;
+    if !path.exists() {
+        findings.push(Diagnostic::error(
+            format!("CA key {} does not exist", path.display()),
+            "set ca_key_path to the intermediate CA private key",
+        ));
+        return;
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if let Ok(meta) = std::fs::metadata(path) {
+            let mode = meta.permissions().mode() & 0o777;
+            if mode & 0o077 != 0 {
+                findings.push(Diagnostic::warning(
+                    format!("CA key {} is accessible to group or others (mode {:o})", path.display(), mode),
+                    format!("chmod 600 {}", path.display()),
+                ));
+            }
+        }
+    }
+
+    let pem = match read_bounded(path) {
+        Ok(pem) => Zeroizing::new(pem),
+        Err(e) => {
+            findings.push(Diagnostic::error(
+                format!("CA key {} can't be read: {}", path.display(), e),
+                "run as a user that can read the CA key",
+            ));
+            return;
+        }
+    };
+    let encrypted = match inspect_key(&pem, None) {
+        Ok(info) => info.encrypted,
+        Err(e) => {
+            findings.push(Diagnostic::error(
+                format!("CA key {} is not a private key: {}", path.display(), e),
+                "ca_key_path must be a PEM private key",
+            ));
+            return;
+        }
+    };
+
+    let key = if !encrypted {
+        parse_private_key(&pem, None)
+    } else if config.ca_key_password_file.is_some() {
+        ca_key_password(config).and_then(|password| parse_private_key(&pem, Some(password.expose_secret())))
+    } else {
+        findings.push(Diagnostic::ok(format!(
+            "CA key {} is encrypted (set ca_key_password_file to check it against the certificate)",
+            path.display()
+        )));
+        return;
+    };
+    let key = match key {
+        Ok(key) => key,
+        Err(e) => {
+            let hint = if encrypted { "check the password in ca_key_password_file" } else { "replace the CA key" };
+            findings.push(Diagnostic::error(format!("CA key {} can't be loaded: {}", path.display(), e), hint));
+            return;
+        }
+    };
+
+    let Some(cert) = ca_cert else { return };
+    match matches_cert(&key, cert) {
+        Ok(true) => findings.push(Diagnostic::ok("CA key matches the CA certificate")),
+        Ok(false) => findings.push(Diagnostic::error(
+            "CA key does not match the CA certificate",
+            "ca_key_path and ca_cert_path must belong to the same CA; compare them with `flux-ssl-mgr match`",
+        )),
+        Err(e) => findings.push(Diagnostic::error(
+            format!("CA key can't be compared with the certificate: {}", e),
+            "replace the CA key or certificate",
+        )),
+    }
+}
+
+/// Existing managed directories whose mode differs from the configuration
+fn check_dir_modes(config: &Config, findings: &mut Vec<Diagnostic>) {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        for (dir, expected) in managed_dirs(config) {
+            let Ok(meta) = std::fs::metadata(&dir) else { continue };
+            let mode = meta.permissions().mode() & 0o777;
+            if mode != expected {
+                findings.push(Diagnostic::warning(
+                    format!("{} has mode {:o}, expected {:o}", dir.display(), mode, expected),
+                    "run `flux-ssl-mgr config --fix-perms`",
+                ));
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = config;
+        findings.push(Diagnostic::ok(
+            "File modes are not applied on this platform; keys inherit the ACL of the output directory",
+        ));
+    }
+}
+
+/// The output directory, or the directory it will be created in, must be writable
+fn check_output_dir(dir: &Path, findings: &mut Vec<Diagnostic>) {
+    let Some(existing) = dir.ancestors().find(|p| p.is_dir()) else {
+        findings.push(Diagnostic::error(
+            format!("Output directory {} can't be created", dir.display()),
+            "set output_dir to a writable directory",
+        ));
+        return;
+    };
+
+    match tempfile::tempfile_in(existing) {
+        Ok(_) if existing == dir => findings.push(Diagnostic::ok(format!("Output directory {} is writable", dir.display()))),
+        Ok(_) => findings.push(Diagnostic::ok(format!(
+            "Output directory {} will be created on first issuance",
+            dir.display()
+        ))),
+        Err(e) => findings.push(Diagnostic::error(
+            format!("Output directory {} is not writable: {}", existing.display(), e),
+            "fix the directory's owner or permissions, or set output_dir elsewhere",
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::{key_to_pem, save_cert_pem};
+    use crate::fixtures;
+
+    fn errors(config: &Config) -> Vec<String> {
+        diagnose(config).into_iter().filter(|d| d.severity == Severity::Error).map(|d| d.message).collect()
+    }
+
+    #[test]
+    fn test_diagnose() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let config = Config {
+            working_dir: temp_dir.path().to_path_buf(),
+            ca_cert_path: temp_dir.path().join("ca.cert.pem"),
+            ca_key_path: temp_dir.path().join("ca.key.pem"),
+            output_dir: temp_dir.path().join("out"),
+            csr_input_dir: temp_dir.path().to_path_buf(),
+            openssl_config: None,
+            ..Config::default()
+        };
+        assert_eq!(errors(&config).len(), 2);
+
+        let ca = fixtures::intermediate_ca();
+        save_cert_pem(ca.cert(), &config.ca_cert_path).unwrap();
+        std::fs::write(&config.ca_key_path, key_to_pem(ca.key()).unwrap()).unwrap();
+        assert_eq!(errors(&config), Vec::<String>::new());
+
+        // Key from another CA
+        std::fs::write(&config.ca_key_path, key_to_pem(&fixtures::root_key()).unwrap()).unwrap();
+        assert_eq!(errors(&config), ["CA key does not match the CA certificate"]);
+
+        // Expired CA
+        let expired = fixtures::expired_intermediate_ca();
+        save_cert_pem(expired.cert(), &config.ca_cert_path).unwrap();
+        std::fs::write(&config.ca_key_path, key_to_pem(expired.key()).unwrap()).unwrap();
+        let errors = errors(&config);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("expired on 2025-07-01"), "{}", errors[0]);
+    }
+}
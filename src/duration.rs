@@ -0,0 +1,351 @@
+//! Humane day-count durations
+//!
+//! Anywhere a number of days is taken (CLI flags, config, manifests) it can be
+//! written as a plain count (`90`) or with a unit: `90d`, `13w`, `6m`, `1y`.
+//! Unit words work too (`2 weeks`, `1 year`). Months count as 30 days and
+//! years as 365 days.
+//!
+//! Certificate validity ([`Validity`]) also takes hours and minutes (`12h`,
+//! `90min`). Run intervals ([`Interval`]) are shorter: `90s`, `30m`, `6h`, `1d`.
+
+use crate::error::{FluxError, Result};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// A number of days, parsed from a humane duration string
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(transparent)]
+pub struct Days(u32);
+
+impl Days {
+    pub const fn new(days: u32) -> Self {
+        Self(days)
+    }
+
+    /// Number of days
+    pub const fn get(self) -> u32 {
+        self.0
+    }
+
+    /// As a chrono duration
+    pub fn as_duration(self) -> chrono::Duration {
+        chrono::Duration::days(self.0 as i64)
+    }
+}
+
+impl From<u32> for Days {
+    fn from(days: u32) -> Self {
+        Self(days)
+    }
+}
+
+impl fmt::Display for Days {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl FromStr for Days {
+    type Err = FluxError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        parse_days(s).map(Days)
+    }
+}
+
+impl<'de> Deserialize<'de> for Days {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        deserialize_days(deserializer).map(Days)
+    }
+}
+
+/// Parse a duration string into a number of days
+pub fn parse_days(s: &str) -> Result<u32> {
+    let invalid = || FluxError::InvalidDuration(s.to_string());
+
+    let trimmed = s.trim();
+    let split = trimmed.find(|c: char| !c.is_ascii_digit()).unwrap_or(trimmed.len());
+    let (count, unit) = trimmed.split_at(split);
+
+    let count: u32 = count.parse().map_err(|_| invalid())?;
+    let multiplier = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "d" | "day" | "days" => 1,
+        "w" | "wk" | "week" | "weeks" => 7,
+        "m" | "mo" | "month" | "months" => 30,
+        "y" | "yr" | "year" | "years" => 365,
+        _ => return Err(invalid()),
+    };
+
+    count.checked_mul(multiplier).ok_or_else(invalid)
+}
+
+/// Serde helper for plain `u32` day fields that should accept humane durations
+pub fn deserialize_days<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<u32, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Raw {
+        Count(u32),
+        Text(String),
+    }
+
+    match Raw::deserialize(deserializer)? {
+        Raw::Count(days) => Ok(days),
+        Raw::Text(text) => parse_days(&text).map_err(serde::de::Error::custom),
+    }
+}
+
+/// [`deserialize_days`] for optional fields (pair with `#[serde(default)]`)
+pub fn deserialize_optional_days<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Option<u32>, D::Error> {
+    deserialize_days(deserializer).map(Some)
+}
+
+/// Certificate validity: anything [`Days`] accepts, plus hours and minutes
+/// (`12h`, `90min`) for short-lived certificates
+///
+/// Plain numbers are days and `m` is still months, so minutes need `min`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Validity(u64);
+
+impl Validity {
+    /// Whole days
+    pub const fn days(days: u32) -> Self {
+        Self(days as u64 * 86_400)
+    }
+
+    /// Length in seconds
+    pub const fn as_secs(self) -> u64 {
+        self.0
+    }
+
+    /// As a chrono duration
+    pub fn as_duration(self) -> chrono::Duration {
+        chrono::Duration::seconds(self.0 as i64)
+    }
+
+    /// Whole days, rounded down
+    pub const fn whole_days(self) -> u32 {
+        (self.0 / 86_400) as u32
+    }
+
+    /// Whether this is a whole number of days
+    pub const fn is_whole_days(self) -> bool {
+        self.0.is_multiple_of(86_400)
+    }
+}
+
+impl Default for Validity {
+    fn default() -> Self {
+        Self::days(0)
+    }
+}
+
+impl From<Days> for Validity {
+    fn from(days: Days) -> Self {
+        Self::days(days.get())
+    }
+}
+
+impl fmt::Display for Validity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            s if s % 86_400 == 0 => write!(f, "{}d", s / 86_400),
+            s if s % 3_600 == 0 => write!(f, "{}h", s / 3_600),
+            s => write!(f, "{}min", s / 60),
+        }
+    }
+}
+
+impl FromStr for Validity {
+    type Err = FluxError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || FluxError::InvalidDuration(s.to_string());
+
+        let trimmed = s.trim();
+        let split = trimmed.find(|c: char| !c.is_ascii_digit()).unwrap_or(trimmed.len());
+        let (count, unit) = trimmed.split_at(split);
+
+        let minutes = match unit.trim().to_ascii_lowercase().as_str() {
+            "min" | "mins" | "minute" | "minutes" => 1,
+            "h" | "hr" | "hour" | "hours" => 60,
+            _ => return parse_days(s).map(Self::days),
+        };
+        let count: u32 = count.parse().map_err(|_| invalid())?;
+        Ok(Self(u64::from(count) * minutes * 60))
+    }
+}
+
+impl Serialize for Validity {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        // Whole days stay plain numbers, as `cert_days` always was
+        if self.is_whole_days() {
+            serializer.serialize_u32(self.whole_days())
+        } else {
+            serializer.collect_str(self)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Validity {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Days(u32),
+            Text(String),
+        }
+
+        match Raw::deserialize(deserializer)? {
+            Raw::Days(days) => Ok(Self::days(days)),
+            Raw::Text(text) => text.parse().map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+/// A run interval, parsed from `90s`, `30m`, `6h` or `1d` (plain numbers are minutes)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Interval(std::time::Duration);
+
+impl Interval {
+    pub const fn from_secs(secs: u64) -> Self {
+        Self(std::time::Duration::from_secs(secs))
+    }
+
+    /// As a std duration
+    pub const fn get(self) -> std::time::Duration {
+        self.0
+    }
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let secs = self.0.as_secs();
+        match secs {
+            s if s > 0 && s % 86_400 == 0 => write!(f, "{}d", s / 86_400),
+            s if s > 0 && s % 3_600 == 0 => write!(f, "{}h", s / 3_600),
+            s if s > 0 && s % 60 == 0 => write!(f, "{}m", s / 60),
+            s => write!(f, "{}s", s),
+        }
+    }
+}
+
+impl FromStr for Interval {
+    type Err = FluxError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || FluxError::InvalidDuration(s.to_string());
+
+        let trimmed = s.trim();
+        let split = trimmed.find(|c: char| !c.is_ascii_digit()).unwrap_or(trimmed.len());
+        let (count, unit) = trimmed.split_at(split);
+
+        let count: u64 = count.parse().map_err(|_| invalid())?;
+        let multiplier = match unit.trim().to_ascii_lowercase().as_str() {
+            "s" | "sec" | "secs" | "second" | "seconds" => 1,
+            "" | "m" | "min" | "mins" | "minute" | "minutes" => 60,
+            "h" | "hr" | "hour" | "hours" => 3_600,
+            "d" | "day" | "days" => 86_400,
+            _ => return Err(invalid()),
+        };
+
+        match count.checked_mul(multiplier) {
+            Some(secs) if secs > 0 => Ok(Self::from_secs(secs)),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl Serialize for Interval {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Interval {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Minutes(u64),
+            Text(String),
+        }
+
+        match Raw::deserialize(deserializer)? {
+            Raw::Minutes(minutes) => minutes.to_string().parse().map_err(serde::de::Error::custom),
+            Raw::Text(text) => text.parse().map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_days() {
+        assert_eq!(parse_days("90").unwrap(), 90);
+        assert_eq!(parse_days("90d").unwrap(), 90);
+        assert_eq!(parse_days("13w").unwrap(), 91);
+        assert_eq!(parse_days("6m").unwrap(), 180);
+        assert_eq!(parse_days("1y").unwrap(), 365);
+        assert_eq!(parse_days(" 2 weeks ").unwrap(), 14);
+        assert_eq!(parse_days("1 Year").unwrap(), 365);
+
+        assert!(parse_days("").is_err());
+        assert!(parse_days("y").is_err());
+        assert!(parse_days("-5d").is_err());
+        assert!(parse_days("3 fortnights").is_err());
+        assert!(parse_days("4294967295y").is_err());
+    }
+
+    #[test]
+    fn test_deserialize_days() {
+        #[derive(Deserialize)]
+        struct Settings {
+            a: Days,
+            b: Days,
+        }
+
+        let settings: Settings = serde_yaml::from_str("a: 30\nb: 13w\n").unwrap();
+        assert_eq!(settings.a, Days::new(30));
+        assert_eq!(settings.b.get(), 91);
+
+        assert!(serde_yaml::from_str::<Settings>("a: 30\nb: soon\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_validity() {
+        assert_eq!("90".parse::<Validity>().unwrap(), Validity::days(90));
+        assert_eq!("13w".parse::<Validity>().unwrap(), Validity::days(91));
+        assert_eq!("12h".parse::<Validity>().unwrap().as_secs(), 12 * 3_600);
+        assert_eq!("90min".parse::<Validity>().unwrap().to_string(), "90min");
+        assert_eq!("48 hours".parse::<Validity>().unwrap().to_string(), "2d");
+        assert_eq!("36h".parse::<Validity>().unwrap().whole_days(), 1);
+
+        assert!("12x".parse::<Validity>().is_err());
+        assert!("h".parse::<Validity>().is_err());
+
+        #[derive(Deserialize, Serialize)]
+        struct Settings {
+            a: Validity,
+            b: Validity,
+        }
+        let settings: Settings = toml::from_str("a = 375\nb = \"12h\"\n").unwrap();
+        assert_eq!(settings.a, Validity::days(375));
+        assert_eq!(toml::to_string(&settings).unwrap(), "a = 375\nb = \"12h\"\n");
+    }
+
+    #[test]
+    fn test_parse_interval() {
+        assert_eq!("90s".parse::<Interval>().unwrap().get().as_secs(), 90);
+        assert_eq!("30".parse::<Interval>().unwrap().to_string(), "30m");
+        assert_eq!("6 hours".parse::<Interval>().unwrap().to_string(), "6h");
+        assert_eq!("1d".parse::<Interval>().unwrap().to_string(), "1d");
+        assert_eq!("120m".parse::<Interval>().unwrap().to_string(), "2h");
+
+        assert!("0h".parse::<Interval>().is_err());
+        assert!("2w".parse::<Interval>().is_err());
+        assert!("soon".parse::<Interval>().is_err());
+    }
+}
@@ -0,0 +1,255 @@
+//! Bootstrap scripts for new machines (`flux-ssl-mgr enroll-script`)
+//!
+//! The script generates a key on the machine itself, builds a CSR for the
+//! enrolled name and SANs, uploads it to `/api/csr/upload` with a one-time
+//! enrollment token (see [`crate::apikey::Enrollment`]) and installs the
+//! certificate it gets back. The private key never leaves the machine.
+//!
+//! `sh` needs `openssl` (1.1.1 or later) and `curl` and writes PEM files;
+//! `powershell` needs PowerShell 7.3 or later and imports into the Windows
+//! machine store.
+
+use crate::crypto::SanEntry;
+use crate::error::{FluxError, Result};
+use chrono::{DateTime, Utc};
+use std::fmt;
+use std::str::FromStr;
+
+/// Script flavour
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ScriptShell {
+    #[default]
+    Sh,
+    PowerShell,
+}
+
+impl fmt::Display for ScriptShell {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ScriptShell::Sh => "sh",
+            ScriptShell::PowerShell => "powershell",
+        })
+    }
+}
+
+impl FromStr for ScriptShell {
+    type Err = FluxError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "sh" | "bash" => Ok(ScriptShell::Sh),
+            "powershell" | "pwsh" | "ps1" => Ok(ScriptShell::PowerShell),
+            _ => Err(FluxError::InvalidConfigValue("shell".to_string(), format!("'{}' (expected sh or powershell)", s))),
+        }
+    }
+}
+
+/// Everything a script needs to enroll one machine
+#[derive(Debug, Clone)]
+pub struct EnrollScript {
+    /// Certificate name, used as the CSR's common name
+    pub name: String,
+    /// SANs for the CSR, normally led by `DNS:<name>`
+    pub sans: Vec<SanEntry>,
+    /// Base URL of `flux-ssl-mgr serve`, e.g. `https://ca.lab:8443`
+    pub url: String,
+    /// One-time enrollment token
+    pub token: String,
+    pub key_size: u32,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl EnrollScript {
+    pub fn render(&self, shell: ScriptShell) -> String {
+        match shell {
+            ScriptShell::Sh => self.render_sh(),
+            ScriptShell::PowerShell => self.render_powershell(),
+        }
+    }
+
+    fn header(&self, comment: &str) -> String {
+        format!(
+            "{c} Enrolls {name} with flux-ssl-mgr at {url}\n\
+             {c} The enrollment token works once and expires {expires}.\n",
+            c = comment,
+            name = self.name,
+            url = self.url,
+            expires = self.expires_at.format("%Y-%m-%d %H:%M UTC"),
+        )
+    }
+
+    fn render_sh(&self) -> String {
+        let sans: Vec<String> = self.sans.iter()
+            .map(|san| match san {
+                SanEntry::Dns(v) => format!("DNS:{}", v),
+                SanEntry::Ip(v) => format!("IP:{}", v),
+                SanEntry::Email(v) => format!("email:{}", v),
+            })
+            .collect();
+
+        format!(
+            r#"#!/bin/sh
+{header}# Needs openssl and curl. Files go to $FLUX_ENROLL_DIR (default /etc/ssl/flux).
+set -eu
+
+FLUX_URL={url}
+FLUX_TOKEN={token}
+NAME={name}
+SANS={sans}
+DIR="${{FLUX_ENROLL_DIR:-/etc/ssl/flux}}"
+
+KEY="$DIR/$NAME.key.pem"
+CSR="$DIR/$NAME.csr"
+CERT="$DIR/$NAME.cert.pem"
+
+umask 077
+mkdir -p "$DIR"
+
+# The private key is generated here and never leaves this machine
+openssl req -new -newkey rsa:{key_size} -nodes -sha256 \
+    -keyout "$KEY" -out "$CSR" -subj "/CN=$NAME" -addext "subjectAltName=$SANS"
+
+if ! RESPONSE=$(curl -sS --fail-with-body -H "Authorization: Bearer $FLUX_TOKEN" \
+        -F "csr_file=@$CSR" "$FLUX_URL/api/csr/upload"); then
+    echo "Enrollment failed: $RESPONSE" >&2
+    exit 1
+fi
+
+# The certificate is the "pem" string of the JSON response
+printf '%s' "$RESPONSE" | sed -n 's/.*"pem":"\([^"]*\)".*/\1/p' \
+    | awk '{{ gsub(/\\n/, "\n"); printf "%s", $0 }}' > "$CERT"
+if ! grep -q 'BEGIN CERTIFICATE' "$CERT"; then
+    echo "Enrollment failed: no certificate in the response" >&2
+    exit 1
+fi
+chmod 644 "$CERT"
+rm -f "$CSR"
+
+echo "Installed $CERT (key: $KEY)"
+"#,
+            header = self.header("#"),
+            url = sh_quote(self.url.trim_end_matches('/')),
+            token = sh_quote(&self.token),
+            name = sh_quote(&self.name),
+            sans = sh_quote(&sans.join(",")),
+            key_size = self.key_size,
+        )
+    }
+
+    fn render_powershell(&self) -> String {
+        let list = |values: Vec<&str>| values.into_iter().map(ps_quote).collect::<Vec<_>>().join(", ");
+        let pick = |f: fn(&SanEntry) -> Option<&str>| list(self.sans.iter().filter_map(f).collect());
+        let dns = pick(|san| if let SanEntry::Dns(v) = san { Some(v) } else { None });
+        let ips = pick(|san| if let SanEntry::Ip(v) = san { Some(v) } else { None });
+        let emails = pick(|san| if let SanEntry::Email(v) = san { Some(v) } else { None });
+
+        format!(
+            r#"{header}# Needs PowerShell 7.3 or later; run elevated to import into Cert:\LocalMachine\My.
+using namespace System.Security.Cryptography
+using namespace System.Security.Cryptography.X509Certificates
+
+$ErrorActionPreference = 'Stop'
+
+$FluxUrl = {url}
+$FluxToken = {token}
+$Name = {name}
+$DnsNames = @({dns})
+$IpAddresses = @({ips})
+$Emails = @({emails})
+$Dir = if ($env:FLUX_ENROLL_DIR) {{ $env:FLUX_ENROLL_DIR }} else {{ Join-Path $env:ProgramData 'flux-ssl' }}
+New-Item -ItemType Directory -Force -Path $Dir | Out-Null
+
+# The private key is generated here and never leaves this machine
+$Key = [RSA]::Create({key_size})
+$Request = [CertificateRequest]::new("CN=$Name", $Key, [HashAlgorithmName]::SHA256, [RSASignaturePadding]::Pkcs1)
+$San = [SubjectAlternativeNameBuilder]::new()
+foreach ($Dns in $DnsNames) {{ $San.AddDnsName($Dns) }}
+foreach ($Ip in $IpAddresses) {{ $San.AddIpAddress([System.Net.IPAddress]::Parse($Ip)) }}
+foreach ($Email in $Emails) {{ $San.AddEmailAddress($Email) }}
+$Request.CertificateExtensions.Add($San.Build())
+
+$CsrPath = Join-Path $Dir "$Name.csr"
+Set-Content -Path $CsrPath -Value $Request.CreateSigningRequestPem() -NoNewline
+$Response = Invoke-RestMethod -Method Post -Uri "$FluxUrl/api/csr/upload" `
+    -Headers @{{ Authorization = "Bearer $FluxToken" }} -Form @{{ csr_file = Get-Item $CsrPath }}
+Remove-Item $CsrPath
+
+$CertPem = $Response.certificate.pem
+Set-Content -Path (Join-Path $Dir "$Name.cert.pem") -Value $CertPem -NoNewline
+
+# Keys of ephemeral certificates can't be stored, so go through a PFX
+$Cert = [X509Certificate2]::CreateFromPem($CertPem).CopyWithPrivateKey($Key)
+$Flags = [X509KeyStorageFlags]::MachineKeySet -bor [X509KeyStorageFlags]::PersistKeySet
+$Installed = [X509Certificate2]::new($Cert.Export([X509ContentType]::Pfx), [string]$null, $Flags)
+$Store = [X509Store]::new([StoreName]::My, [StoreLocation]::LocalMachine)
+$Store.Open([OpenFlags]::ReadWrite)
+$Store.Add($Installed)
+$Store.Close()
+
+Write-Host "Installed $Name ($($Installed.Thumbprint)) into Cert:\LocalMachine\My"
+"#,
+            header = self.header("#"),
+            url = ps_quote(self.url.trim_end_matches('/')),
+            token = ps_quote(&self.token),
+            name = ps_quote(&self.name),
+            key_size = self.key_size,
+        )
+    }
+}
+
+/// Single-quoted for POSIX sh
+fn sh_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// Single-quoted for PowerShell
+fn ps_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn script() -> EnrollScript {
+        EnrollScript {
+            name: "nas01.lab".to_string(),
+            sans: vec![SanEntry::Dns("nas01.lab".to_string()), SanEntry::Ip("10.0.0.5".to_string())],
+            url: "https://ca.lab:8443/".to_string(),
+            token: "flux_enroll_abc".to_string(),
+            key_size: 2048,
+            expires_at: DateTime::from_timestamp(1_790_000_000, 0).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_render_sh() {
+        let sh = script().render(ScriptShell::Sh);
+        assert!(sh.starts_with("#!/bin/sh\n# Enrolls nas01.lab"));
+        assert!(sh.contains("FLUX_URL='https://ca.lab:8443'\n"));
+        assert!(sh.contains("FLUX_TOKEN='flux_enroll_abc'\n"));
+        assert!(sh.contains("SANS='DNS:nas01.lab,IP:10.0.0.5'\n"));
+        assert!(sh.contains("rsa:2048"));
+        assert!(sh.contains(r#"gsub(/\\n/, "\n")"#));
+        assert!(sh.contains("DIR=\"${FLUX_ENROLL_DIR:-/etc/ssl/flux}\""));
+    }
+
+    #[test]
+    fn test_render_powershell() {
+        let ps = script().render(ScriptShell::PowerShell);
+        assert!(ps.starts_with("# Enrolls nas01.lab"));
+        assert!(ps.contains("$DnsNames = @('nas01.lab')\n"));
+        assert!(ps.contains("$IpAddresses = @('10.0.0.5')\n"));
+        assert!(ps.contains("$Emails = @()\n"));
+        assert!(ps.contains("[RSA]::Create(2048)"));
+        assert!(ps.contains("foreach ($Dns in $DnsNames) { $San.AddDnsName($Dns) }"));
+    }
+
+    #[test]
+    fn test_quoting() {
+        assert_eq!(sh_quote("it's"), r"'it'\''s'");
+        assert_eq!(ps_quote("it's"), "'it''s'");
+        assert_eq!("PWSH".parse::<ScriptShell>().unwrap(), ScriptShell::PowerShell);
+        assert!("zsh".parse::<ScriptShell>().is_err());
+    }
+}
@@ -49,6 +49,14 @@ pub enum FluxError {
     #[error("Failed to read CSR file: {0}")]
     CsrReadFailed(PathBuf),
 
+    /// CSR not signed by the key it carries
+    #[error("CSR signature does not verify against its public key: {0}")]
+    CsrSignatureInvalid(PathBuf),
+
+    /// Private key is not the one a certificate was issued for
+    #[error("Private key {0} does not match certificate {1}")]
+    KeyCertMismatch(PathBuf, PathBuf),
+
     /// OpenSSL error
     #[error("OpenSSL error: {0}")]
     OpenSslError(#[from] openssl::error::ErrorStack),
@@ -77,6 +85,10 @@ pub enum FluxError {
     #[error("Key generation failed: {0}")]
     KeyGenerationFailed(String),
 
+    /// Private key file not understood
+    #[error("Failed to parse private key: {0}")]
+    KeyParseError(String),
+
     /// CSR generation failed
     #[error("CSR generation failed: {0}")]
     CsrGenerationFailed(String),
@@ -97,6 +109,14 @@ pub enum FluxError {
     #[error("Invalid configuration value for {0}: {1}")]
     InvalidConfigValue(String, String),
 
+    /// Output file exists and overwrite_policy is "fail"
+    #[error("Refusing to overwrite existing file {0} (overwrite_policy is \"fail\")")]
+    OutputExists(PathBuf),
+
+    /// Invalid duration string
+    #[error("Invalid duration: {0} (expected e.g. 90, 90d, 13w, 6m or 1y)")]
+    InvalidDuration(String),
+
     /// Missing required configuration
     #[error("Missing required configuration: {0}")]
     MissingConfig(String),
@@ -105,6 +125,14 @@ pub enum FluxError {
     #[error("Failed to parse certificate: {0}")]
     CertParseError(String),
 
+    /// Wildcard SAN refused by `policy.allow_wildcards`
+    #[error("Wildcard name {0} is not allowed (policy.allow_wildcards = false)")]
+    WildcardNotAllowed(String),
+
+    /// Certificate did not verify against the CA
+    #[error("Certificate verification failed: {0}")]
+    VerificationFailed(String),
+
     /// User cancelled operation
     #[error("Operation cancelled by user")]
     UserCancelled,
@@ -113,6 +141,105 @@ pub enum FluxError {
     #[error("Interactive mode error: {0}")]
     InteractiveError(String),
 
+    /// Issued certificate store error
+    #[error("Certificate store error: {0}")]
+    StoreError(String),
+
+    /// Manifest error
+    #[error("Invalid manifest: {0}")]
+    ManifestError(String),
+
+    /// File exceeds the configured size limit
+    #[error("File too large: {0} (limit is {1} bytes)")]
+    FileTooLarge(PathBuf, u64),
+
+    /// Password file error
+    #[error("Password file error: {0}")]
+    PasswordFileError(String),
+
+    /// Revocation check error
+    #[error("Revocation check failed: {0}")]
+    RevocationError(String),
+
+    /// Signing agent error
+    #[error("Signing agent error: {0}")]
+    AgentError(String),
+
+    /// Deployment error
+    #[error("Deployment failed: {0}")]
+    DeployError(String),
+
+    /// LDAP/AD publishing error
+    #[error("Directory publishing failed: {0}")]
+    LdapError(String),
+
+    /// Installing into a local certificate store failed
+    #[error("Installation failed: {0}")]
+    InstallError(String),
+
+    /// `config --doctor` found problems that will stop issuance
+    #[error("Configuration check found {0} problem(s)")]
+    ConfigProblems(usize),
+
+    /// Building a Java truststore failed
+    #[error("Truststore export failed: {0}")]
+    TruststoreError(String),
+
+    /// Post-deploy health check failed
+    #[error("Health check failed: {0}")]
+    HealthCheckFailed(String),
+
+    /// Endpoints still present an older certificate
+    #[error("Deployment drift: {0}")]
+    DeploymentDrift(String),
+
+    #[error("Failed to inspect remote endpoint: {0}")]
+    RemoteInspectFailed(String),
+
+    /// A step of `smoke` failed
+    #[error("Smoke test failed at {0}: {1}")]
+    SmokeTestFailed(String, String),
+
+    /// Git history error
+    #[error("Git history error: {0}")]
+    HistoryError(String),
+
+    /// Notification delivery error
+    #[error("Notification failed: {0}")]
+    NotifyError(String),
+
+    /// Querying a host inventory source failed
+    #[error("Inventory lookup failed: {0}")]
+    InventoryError(String),
+
+    /// Automatic renewal error
+    #[error("Renewal failed: {0}")]
+    RenewalError(String),
+
+    /// CSR rejected by the signing policy, with every rule it broke
+    #[error("Policy violation: {0}")]
+    PolicyViolation(crate::policy::Violations),
+
+    /// No generation to roll back to, or restoring it failed
+    #[error("Rollback failed: {0}")]
+    RollbackError(String),
+
+    /// The intermediate CA certificate has expired, so nothing it signs validates
+    #[error("Intermediate CA certificate {0} expired on {1}; run `flux-ssl-mgr ca renew-intermediate` to re-certify it")]
+    CaExpired(String, String),
+
+    /// The requested validity runs past the CA certificate's expiry
+    #[error("{0} of validity would outlast the CA certificate, which expires {1}")]
+    ValidityExceedsCa(crate::duration::Validity, String),
+
+    /// Re-certifying the intermediate CA failed
+    #[error("Intermediate CA renewal failed: {0}")]
+    CaRenewalFailed(String),
+
+    /// Creating or reading a CA backup failed
+    #[error("CA backup error: {0}")]
+    BackupError(String),
+
     /// Batch processing error
     #[error("Batch processing failed: {0} successful, {1} failed")]
     BatchProcessingError(usize, usize),
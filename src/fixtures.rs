@@ -0,0 +1,219 @@
+//! Deterministic test fixtures and golden-file helpers
+//!
+//! Keys come from `tests/fixtures/keys`, and CA certificates are built with
+//! fixed serials and dates, so everything except freshly signed leaf
+//! certificates is identical between runs. [`describe`] renders the parts of
+//! a certificate that should stay stable (no serials, timestamps or key IDs),
+//! and [`assert_golden`] compares it against `tests/fixtures/golden/<name>.txt`.
+//! Set `UPDATE_GOLDEN=1` to rewrite the golden files after an intended change.
+
+use crate::ca::IntermediateCA;
+use crate::crypto::{create_csr, format_name, SanEntry};
+use openssl::asn1::Asn1Time;
+use openssl::bn::BigNum;
+use openssl::hash::MessageDigest;
+use openssl::pkey::{Id, PKey, Private};
+use openssl::x509::extension::{BasicConstraints, KeyUsage, SubjectKeyIdentifier};
+use openssl::stack::Stack;
+use openssl::x509::{GeneralNameRef, X509, X509Builder, X509NameBuilder, X509Req, X509ReqBuilder};
+use std::path::PathBuf;
+
+/// 2025-01-01T00:00:00Z, the notBefore of the fixture CAs
+const FIXTURE_NOT_BEFORE: i64 = 1_735_689_600;
+
+/// 2045-01-01T00:00:00Z, the notAfter of the fixture CAs
+const FIXTURE_NOT_AFTER: i64 = 2_366_841_600;
+
+/// 2025-07-01T00:00:00Z, the notAfter of [`expired_intermediate_ca`]
+const FIXTURE_EXPIRED_NOT_AFTER: i64 = 1_751_328_000;
+
+fn load_key(pem: &str) -> PKey<Private> {
+    PKey::private_key_from_pem(pem.as_bytes()).expect("fixture key")
+}
+
+/// Root CA key
+pub fn root_key() -> PKey<Private> {
+    load_key(include_str!("../tests/fixtures/keys/root-ca.key.pem"))
+}
+
+/// Intermediate CA key
+pub fn intermediate_key() -> PKey<Private> {
+    load_key(include_str!("../tests/fixtures/keys/intermediate-ca.key.pem"))
+}
+
+/// Key for leaf certificates
+pub fn leaf_key() -> PKey<Private> {
+    load_key(include_str!("../tests/fixtures/keys/leaf.key.pem"))
+}
+
+fn ca_cert(cn: &str, serial: u32, key: &PKey<Private>, issuer: Option<(&X509, &PKey<Private>)>, not_after: i64) -> X509 {
+    let mut name = X509NameBuilder::new().unwrap();
+    name.append_entry_by_text("O", "Flux Test").unwrap();
+    name.append_entry_by_text("CN", cn).unwrap();
+    let name = name.build();
+
+    let mut builder = X509Builder::new().unwrap();
+    builder.set_version(2).unwrap();
+    builder.set_serial_number(&BigNum::from_u32(serial).unwrap().to_asn1_integer().unwrap()).unwrap();
+    builder.set_subject_name(&name).unwrap();
+    builder.set_issuer_name(issuer.map_or(&name, |(cert, _)| cert.subject_name())).unwrap();
+    builder.set_pubkey(key).unwrap();
+    builder.set_not_before(&Asn1Time::from_unix(FIXTURE_NOT_BEFORE).unwrap()).unwrap();
+    builder.set_not_after(&Asn1Time::from_unix(not_after).unwrap()).unwrap();
+
+    let mut constraints = BasicConstraints::new();
+    constraints.critical().ca();
+    if issuer.is_some() {
+        constraints.pathlen(0);
+    }
+    builder.append_extension(constraints.build().unwrap()).unwrap();
+    builder.append_extension(KeyUsage::new().critical().key_cert_sign().crl_sign().build().unwrap()).unwrap();
+    let ski = SubjectKeyIdentifier::new().build(&builder.x509v3_context(None, None)).unwrap();
+    builder.append_extension(ski).unwrap();
+
+    let signing_key = issuer.map_or(key, |(_, key)| key);
+    builder.sign(signing_key, MessageDigest::sha256()).unwrap();
+    builder.build()
+}
+
+/// Self-signed root CA certificate
+pub fn root_ca_cert() -> X509 {
+    ca_cert("Flux Test Root CA", 1, &root_key(), None, FIXTURE_NOT_AFTER)
+}
+
+/// Intermediate CA (signed by [`root_ca_cert`]) with its key
+pub fn intermediate_ca() -> IntermediateCA {
+    let root = root_ca_cert();
+    let key = intermediate_key();
+    let cert = ca_cert("Flux Test Intermediate CA", 2, &key, Some((&root, &root_key())), FIXTURE_NOT_AFTER);
+    IntermediateCA::from_parts(cert, key)
+}
+
+/// [`intermediate_ca`] with a certificate that expired on 2025-07-01
+pub fn expired_intermediate_ca() -> IntermediateCA {
+    let root = root_ca_cert();
+    let key = intermediate_key();
+    let cert = ca_cert("Flux Test Intermediate CA", 3, &key, Some((&root, &root_key())), FIXTURE_EXPIRED_NOT_AFTER);
+    IntermediateCA::from_parts(cert, key)
+}
+
+/// CSR for `name` using the fixed leaf key
+pub fn leaf_csr(name: &str, sans: &[SanEntry]) -> X509Req {
+    create_csr(name, &leaf_key(), sans, None).expect("fixture CSR")
+}
+
+/// CSR for a subordinate CA named `name` (basicConstraints CA:TRUE), using the fixed leaf key
+pub fn ca_csr(name: &str) -> X509Req {
+    let mut subject = X509NameBuilder::new().unwrap();
+    subject.append_entry_by_text("CN", name).unwrap();
+    let mut builder = X509ReqBuilder::new().unwrap();
+    builder.set_subject_name(&subject.build()).unwrap();
+    builder.set_pubkey(&leaf_key()).unwrap();
+    let mut extensions = Stack::new().unwrap();
+    extensions.push(BasicConstraints::new().critical().ca().build().unwrap()).unwrap();
+    builder.add_extensions(&extensions).unwrap();
+    builder.sign(&leaf_key(), MessageDigest::sha256()).unwrap();
+    builder.build()
+}
+
+/// Stable, human-readable summary of a certificate for golden comparisons
+pub fn describe(cert: &X509) -> String {
+    let mut lines = vec![
+        format!("version: {}", cert.version() + 1),
+        format!("subject: {}", format_name(cert.subject_name())),
+        format!("issuer: {}", format_name(cert.issuer_name())),
+    ];
+
+    let key = cert.public_key().unwrap();
+    let key_type = match key.id() {
+        Id::RSA => "RSA",
+        Id::EC => "EC",
+        Id::ED25519 => "Ed25519",
+        _ => "other",
+    };
+    lines.push(format!("public key: {} {}", key_type, key.bits()));
+    lines.push(format!("signature: {}", cert.signature_algorithm().object()));
+
+    let validity = cert.not_before().diff(cert.not_after()).unwrap();
+    lines.push(format!("validity: {} days", validity.days));
+
+    let sans: Vec<String> = cert.subject_alt_names()
+        .map(|names| names.iter().map(general_name).collect())
+        .unwrap_or_default();
+    lines.push(format!("sans: {}", if sans.is_empty() { "(none)".to_string() } else { sans.join(", ") }));
+
+    lines.push("extensions:".to_string());
+    lines.extend(extension_lines(cert).into_iter().map(|l| format!("  {}", l)));
+
+    lines.join("\n") + "\n"
+}
+
+fn general_name(name: &GeneralNameRef) -> String {
+    if let Some(dns) = name.dnsname() {
+        format!("DNS:{}", dns)
+    } else if let Some(ip) = name.ipaddress() {
+format!("IP:{}", crate::crypto::csr::format_ip(ip))
+    } else if let Some(email) = name.email() {
+        format!("email:{}", email)
+    } else if let Some(uri) = name.uri() {
+        format!("URI:{}", uri)
+    } else {
+        "other".to_string()
+    }
+}
+
+/// The X509v3 extension section of OpenSSL's text dump, minus key identifiers
+fn extension_lines(cert: &X509) -> Vec<String> {
+    let text = String::from_utf8(cert.to_text().unwrap()).unwrap();
+    let is_hex = |l: &str| l.contains(':') && l.chars().all(|c| c.is_ascii_hexdigit() || c == ':');
+
+    text.lines()
+        .skip_while(|l| !l.trim_start().starts_with("X509v3 extensions:"))
+        .skip(1)
+        .take_while(|l| !l.trim_start().starts_with("Signature Algorithm:"))
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !is_hex(l) && !l.starts_with("keyid:"))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Compare `actual` with `tests/fixtures/golden/<name>.txt`
+///
+/// With `UPDATE_GOLDEN=1` the file is (re)written instead.
+pub fn assert_golden(name: &str, actual: &str) {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/golden")
+        .join(format!("{}.txt", name));
+
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::write(&path, actual).unwrap();
+        return;
+    }
+
+    let expected = std::fs::read_to_string(&path).unwrap_or_else(|_| {
+        panic!("missing golden file {}; run with UPDATE_GOLDEN=1 to create it", path.display())
+    });
+    assert_eq!(
+        actual, expected,
+        "certificate differs from {} (run with UPDATE_GOLDEN=1 if the change is intended)",
+        path.display()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixture_cas_are_deterministic() {
+        assert_eq!(root_ca_cert().to_der().unwrap(), root_ca_cert().to_der().unwrap());
+
+        let ca = intermediate_ca();
+        let root = root_ca_cert();
+        assert!(ca.cert().verify(&root.public_key().unwrap()).unwrap());
+        assert_eq!(ca.cert().to_der().unwrap(), intermediate_ca().cert().to_der().unwrap());
+
+        assert_golden("root-ca", &describe(&root));
+        assert_golden("intermediate-ca", &describe(ca.cert()));
+    }
+}
@@ -0,0 +1,384 @@
+//! Filesystem helpers
+
+use crate::config::Config;
+use crate::error::{FluxError, Result};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Largest file read by [`read_bounded`] (set from `limits.max_file_size`)
+static MAX_READ_SIZE: AtomicU64 = AtomicU64::new(5 * 1024 * 1024);
+
+/// Set the size limit for files read by the crypto helpers
+pub fn set_max_read_size(bytes: u64) {
+    MAX_READ_SIZE.store(bytes, Ordering::Relaxed);
+}
+
+/// Current size limit for files read by the crypto helpers
+pub fn max_read_size() -> u64 {
+    MAX_READ_SIZE.load(Ordering::Relaxed)
+}
+
+/// Read a file, refusing anything larger than the configured limit
+///
+/// The read itself is bounded, so files that grow (or special files such as
+/// `/dev/zero`) cannot exhaust memory.
+pub fn read_bounded<P: AsRef<Path>>(path: P) -> Result<Vec<u8>> {
+    let path = path.as_ref();
+    let limit = max_read_size();
+    let read_err = |e: std::io::Error| FluxError::FileReadFailed(path.to_path_buf(), e.to_string());
+
+    let file = std::fs::File::open(path).map_err(read_err)?;
+    let len = file.metadata().map_err(read_err)?.len();
+    if len > limit {
+        return Err(FluxError::FileTooLarge(path.to_path_buf(), limit));
+    }
+
+    let mut data = Vec::with_capacity(len as usize);
+    file.take(limit + 1).read_to_end(&mut data).map_err(read_err)?;
+    if data.len() as u64 > limit {
+        return Err(FluxError::FileTooLarge(path.to_path_buf(), limit));
+    }
+
+    Ok(data)
+}
+
+/// [`read_bounded`] for UTF-8 text files
+pub fn read_to_string_bounded<P: AsRef<Path>>(path: P) -> Result<String> {
+    let data = read_bounded(path.as_ref())?;
+    String::from_utf8(data)
+        .map_err(|e| FluxError::FileReadFailed(path.as_ref().to_path_buf(), e.to_string()))
+}
+
+/// Mode for certificates and CSRs written outside the configured layout
+pub const PUBLIC_FILE_MODE: u32 = 0o644;
+
+/// Mode for private keys written outside the configured layout
+pub const PRIVATE_FILE_MODE: u32 = 0o600;
+
+/// Replace `path` with `contents` so that a crash leaves either the old or
+/// the new file, never a truncated one
+///
+/// The contents go to a temporary file in the same directory, which gets
+/// `mode` before anything is written, is synced to disk and then renamed
+/// over `path`.
+pub fn write_atomic(path: &Path, contents: &[u8], mode: u32) -> Result<()> {
+    let write_err = |e: std::io::Error| FluxError::FileWriteFailed(path.to_path_buf(), e.to_string());
+    let dir = path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+
+    let mut tmp = tempfile::Builder::new().prefix(".flux-").tempfile_in(dir).map_err(write_err)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        tmp.as_file().set_permissions(std::fs::Permissions::from_mode(mode)).map_err(write_err)?;
+    }
+    #[cfg(not(unix))]
+    let _ = mode;
+
+    tmp.write_all(contents).map_err(write_err)?;
+    tmp.as_file().sync_all().map_err(write_err)?;
+    tmp.persist(path).map_err(|e| write_err(e.error))?;
+    sync_dir(dir);
+    Ok(())
+}
+
+/// Exclusive lock on the `<file>.lock` next to a shared file, across processes
+///
+/// Held around a read-modify-write of the file, so that two processes
+/// can't both read the old contents. Released when dropped (or when the
+/// process exits).
+pub struct FileLock(#[allow(dead_code)] std::fs::File);
+
+impl FileLock {
+    /// Wait for and take the lock of `path`
+    pub fn acquire(path: &Path) -> Result<Self> {
+        let mut lock_path = path.as_os_str().to_owned();
+        lock_path.push(".lock");
+        let lock_path = PathBuf::from(lock_path);
+        let file = std::fs::File::options().create(true).truncate(false).write(true).open(&lock_path)
+            .map_err(|e| FluxError::FileWriteFailed(lock_path.clone(), e.to_string()))?;
+        file.lock().map_err(|e| FluxError::FileWriteFailed(lock_path, e.to_string()))?;
+        Ok(Self(file))
+    }
+}
+
+/// Flush a directory entry change (a rename) to disk, where that is possible
+pub(crate) fn sync_dir(dir: &Path) {
+    #[cfg(unix)]
+    if let Ok(dir) = std::fs::File::open(dir) {
+        let _ = dir.sync_all();
+    }
+    #[cfg(not(unix))]
+    let _ = dir;
+}
+
+/// Give `files` the configured `defaults.owner` and `defaults.group`
+///
+/// Only root can hand files to another user, so anyone else gets a
+/// [`FluxError::PermissionError`] unless the files are already theirs.
+/// Without the `ownership` feature this does nothing.
+#[cfg(all(unix, feature = "ownership"))]
+pub fn set_owner(files: &[&Path], config: &Config) -> Result<()> {
+    use nix::unistd::{chown, Group, Uid, User};
+
+    let owner = &config.defaults.owner;
+    let group = &config.defaults.group;
+    let lookup_err = |e: nix::Error| FluxError::PermissionError(format!("looking up {}:{}: {}", owner, group, e));
+    let user = User::from_name(owner).map_err(lookup_err)?
+        .ok_or_else(|| FluxError::PermissionError(format!("no such user: {}", owner)))?;
+    let gid = Group::from_name(group).map_err(lookup_err)?
+        .ok_or_else(|| FluxError::PermissionError(format!("no such group: {}", group)))?
+        .gid;
+
+    if !Uid::effective().is_root() && user.uid != Uid::effective() {
+        return Err(FluxError::PermissionError(format!(
+            "giving files to {}:{} needs root (set defaults.owner to the current user or run as root)",
+            owner, group
+        )));
+    }
+
+    for file in files {
+        chown(*file, Some(user.uid), Some(gid)).map_err(|e| FluxError::PermissionError(format!(
+            "chown {}:{} {}: {}", owner, group, file.display(), e
+        )))?;
+    }
+
+    Ok(())
+}
+
+/// Give `files` the configured `defaults.owner` and `defaults.group`
+///
+/// Without the `ownership` feature this does nothing.
+#[cfg(not(all(unix, feature = "ownership")))]
+pub fn set_owner(files: &[&Path], config: &Config) -> Result<()> {
+    let _ = (files, config);
+    Ok(())
+}
+
+/// Mode for directories holding private keys
+pub const PRIVATE_DIR_MODE: u32 = 0o700;
+
+/// Create a directory (and any missing parents) with the given mode
+///
+/// The mode is set explicitly so the umask does not widen or narrow it.
+/// Directories that already exist are left untouched.
+pub fn create_dir_with_mode(path: &Path, mode: u32) -> Result<()> {
+    let missing: Vec<&Path> = path.ancestors()
+        .take_while(|p| !p.as_os_str().is_empty() && !p.exists())
+        .collect();
+
+    std::fs::create_dir_all(path)?;
+
+    for dir in missing {
+        set_dir_mode(dir, mode)?;
+    }
+
+    Ok(())
+}
+
+/// Set a directory's mode, returning whether it changed
+pub fn set_dir_mode(path: &Path, mode: u32) -> Result<bool> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        let current = std::fs::metadata(path)?.permissions().mode() & 0o7777;
+        if current == mode {
+            return Ok(false);
+        }
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+        Ok(true)
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = (path, mode);
+        Ok(false)
+    }
+}
+
+/// Move existing files into `backup_dir`, returning their new locations
+///
+/// The backup directory is only created (mode 0700, since it may hold
+/// private keys) when there is something to move.
+pub fn backup_files(files: &[&Path], backup_dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut moved = Vec::new();
+
+    for file in files.iter().filter(|f| f.exists()) {
+        if moved.is_empty() {
+            create_dir_with_mode(backup_dir, PRIVATE_DIR_MODE)?;
+        }
+
+        let name = file.file_name()
+            .ok_or_else(|| FluxError::FileWriteFailed(file.to_path_buf(), "not a file".to_string()))?;
+        let target = backup_dir.join(name);
+        std::fs::rename(file, &target)
+            .map_err(|e| FluxError::FileWriteFailed(target.clone(), e.to_string()))?;
+        moved.push(target);
+    }
+
+    Ok(moved)
+}
+
+/// Directories managed by flux-ssl-mgr and the mode each should have
+pub fn managed_dirs(config: &Config) -> Vec<(PathBuf, u32)> {
+    let intermediate = config.working_dir.join("intermediate");
+    let dir_mode = config.permissions.output_dir;
+
+    vec![
+        (intermediate.join("private"), PRIVATE_DIR_MODE),
+        (intermediate.join("csr"), dir_mode),
+        (intermediate.join("certs"), dir_mode),
+        (config.output_dir.clone(), dir_mode),
+    ]
+}
+
+/// Reset the mode of existing managed directories
+///
+/// Returns the directories that were changed.
+pub fn fix_permissions(config: &Config) -> Result<Vec<(PathBuf, u32)>> {
+    let mut changed = Vec::new();
+
+    for (dir, mode) in managed_dirs(config) {
+        if dir.is_dir() && set_dir_mode(&dir, mode)? {
+            changed.push((dir, mode));
+        }
+    }
+
+    Ok(changed)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn test_backup_files() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let cert = temp_dir.path().join("web.cert.pem");
+        let key = temp_dir.path().join("web.key.pem");
+        let missing = temp_dir.path().join("web.crt");
+        std::fs::write(&cert, "cert").unwrap();
+        std::fs::write(&key, "key").unwrap();
+
+        let backup_dir = temp_dir.path().join(".bak").join("1");
+        let moved = backup_files(&[&cert, &key, &missing], &backup_dir).unwrap();
+
+        assert_eq!(moved, vec![backup_dir.join("web.cert.pem"), backup_dir.join("web.key.pem")]);
+        assert!(!cert.exists() && !key.exists());
+        assert_eq!(std::fs::read_to_string(&moved[1]).unwrap(), "key");
+        assert_eq!(std::fs::metadata(&backup_dir).unwrap().permissions().mode() & 0o777, PRIVATE_DIR_MODE);
+
+        // Nothing to move, nothing created
+        let empty_dir = temp_dir.path().join(".bak").join("2");
+        assert!(backup_files(&[&missing], &empty_dir).unwrap().is_empty());
+        assert!(!empty_dir.exists());
+    }
+
+    #[test]
+    fn test_read_bounded() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("input.pem");
+        std::fs::write(&path, vec![b'a'; 1024]).unwrap();
+
+        assert_eq!(read_bounded(&path).unwrap().len(), 1024);
+
+        // Special files report a zero length but never end
+        let limit = max_read_size();
+        assert!(matches!(read_bounded("/dev/zero"), Err(FluxError::FileTooLarge(_, l)) if l == limit));
+    }
+
+    #[test]
+    fn test_write_atomic() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("web.key.pem");
+        std::fs::write(&path, "old key").unwrap();
+
+        write_atomic(&path, b"new key", 0o400).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "new key");
+        assert_eq!(mode_of(&path), 0o400);
+
+        // Read-only targets are replaced too, and no temporary files remain
+        write_atomic(&path, b"newer key", 0o400).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "newer key");
+        assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_file_lock_is_exclusive() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("serial");
+
+        let lock = FileLock::acquire(&path).unwrap();
+        let other = std::fs::File::options().write(true).open(temp_dir.path().join("serial.lock")).unwrap();
+        assert!(other.try_lock().is_err());
+
+        drop(lock);
+        other.try_lock().unwrap();
+    }
+
+    #[cfg(feature = "ownership")]
+    #[test]
+    fn test_set_owner() {
+        use std::os::unix::fs::MetadataExt;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("web.key.pem");
+        std::fs::write(&path, "key").unwrap();
+
+        let mut config = Config::default();
+        let me = nix::unistd::User::from_uid(nix::unistd::Uid::effective()).unwrap().unwrap();
+        let group = nix::unistd::Group::from_gid(me.gid).unwrap().unwrap();
+        config.defaults.owner = me.name;
+        config.defaults.group = group.name;
+        set_owner(&[&path], &config).unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().uid(), me.uid.as_raw());
+
+        config.defaults.owner = "no-such-flux-user".to_string();
+        assert!(matches!(set_owner(&[&path], &config), Err(FluxError::PermissionError(_))));
+    }
+
+    fn mode_of(path: &Path) -> u32 {
+        std::fs::metadata(path).unwrap().permissions().mode() & 0o7777
+    }
+
+    #[test]
+    fn test_create_dir_with_mode() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        std::fs::set_permissions(temp_dir.path(), std::fs::Permissions::from_mode(0o711)).unwrap();
+
+        let nested = temp_dir.path().join("a").join("b");
+        create_dir_with_mode(&nested, 0o750).unwrap();
+        assert_eq!(mode_of(&nested), 0o750);
+        assert_eq!(mode_of(&temp_dir.path().join("a")), 0o750);
+
+        // Existing directories keep their mode
+        assert_eq!(mode_of(temp_dir.path()), 0o711);
+        create_dir_with_mode(&nested, 0o700).unwrap();
+        assert_eq!(mode_of(&nested), 0o750);
+    }
+
+    #[test]
+    fn test_fix_permissions() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let config = Config {
+            working_dir: temp_dir.path().join("ca"),
+            output_dir: temp_dir.path().join("out"),
+            ..Config::default()
+        };
+
+        let private_dir = config.working_dir.join("intermediate").join("private");
+        std::fs::create_dir_all(&private_dir).unwrap();
+        std::fs::create_dir_all(&config.output_dir).unwrap();
+        std::fs::set_permissions(&private_dir, std::fs::Permissions::from_mode(0o777)).unwrap();
+        std::fs::set_permissions(&config.output_dir, std::fs::Permissions::from_mode(0o700)).unwrap();
+
+        let changed = fix_permissions(&config).unwrap();
+        assert_eq!(changed.len(), 2);
+        assert_eq!(mode_of(&private_dir), PRIVATE_DIR_MODE);
+        assert_eq!(mode_of(&config.output_dir), config.permissions.output_dir);
+
+        assert!(fix_permissions(&config).unwrap().is_empty());
+    }
+}
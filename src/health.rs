@@ -0,0 +1,262 @@
+//! Post-deploy health checks
+//!
+//! A deploy target with a `[deploy.<target>.health]` table is probed after its
+//! reload hook has run: an HTTP request that must return the expected status,
+//! a plain TCP connect, or a TLS handshake that must present the certificate
+//! just deployed. If every attempt fails, the deployment rolls the previous
+//! certificate files back (see [`crate::deploy`]).
+
+use crate::duration::Interval;
+use crate::error::{FluxError, Result};
+use crate::notify::webhook::{exchange, parse_url};
+use openssl::ssl::{SslConnector, SslMethod, SslVerifyMode};
+use openssl::x509::X509;
+use serde::{Deserialize, Serialize};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+/// Health probe run after deploying to a target
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HealthCheck {
+    #[serde(flatten)]
+    pub probe: Probe,
+
+    /// Probes before giving up and rolling back
+    #[serde(default = "default_attempts")]
+    pub attempts: u32,
+
+    /// Wait before each probe (gives the service time to reload)
+    #[serde(default = "default_delay")]
+    pub delay: Interval,
+
+    /// Connect and read timeout of each probe
+    #[serde(default = "default_timeout")]
+    pub timeout: Interval,
+}
+
+/// What a health check probes
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Probe {
+    /// GET `url`; HTTPS certificates are not verified
+    Http {
+        url: String,
+        /// Expected status (default: any 2xx or 3xx)
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        status: Option<u16>,
+    },
+    /// Connect to `host:port`
+    Tcp { address: String },
+    /// Handshake with `host:port`, which must present the deployed certificate
+    Tls {
+        address: String,
+        /// SNI name (default: the host part of `address`)
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        server_name: Option<String>,
+    },
+}
+
+fn default_attempts() -> u32 { 3 }
+fn default_delay() -> Interval { Interval::from_secs(5) }
+fn default_timeout() -> Interval { Interval::from_secs(10) }
+
+impl HealthCheck {
+    /// Probe until one attempt succeeds, returning the last failure otherwise
+    ///
+    /// `cert` is the certificate just deployed, checked by TLS probes.
+    pub fn run(&self, cert: &X509) -> Result<()> {
+        let mut result = Ok(());
+        for _ in 0..self.attempts.max(1) {
+            std::thread::sleep(self.delay.get());
+            result = probe(&self.probe, cert, self.timeout.get());
+            if result.is_ok() {
+                break;
+            }
+        }
+        result
+    }
+}
+
+impl std::fmt::Display for Probe {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Probe::Http { url, .. } => write!(f, "GET {}", url),
+            Probe::Tcp { address } => write!(f, "TCP {}", address),
+            Probe::Tls { address, .. } => write!(f, "TLS {}", address),
+        }
+    }
+}
+
+fn probe(probe: &Probe, cert: &X509, timeout: Duration) -> Result<()> {
+    match probe {
+        Probe::Http { url, status } => probe_http(url, *status, timeout),
+        Probe::Tcp { address } => connect(address, timeout).map(|_| ()),
+        Probe::Tls { address, server_name } => {
+            let server_name = server_name.as_deref().unwrap_or_else(|| host_part(address));
+            probe_tls(address, server_name, cert, timeout)
+        }
+    }
+}
+
+/// `host` from `host:port` or `[v6]:port`
+fn host_part(address: &str) -> &str {
+    let host = address.rsplit_once(':').map_or(address, |(host, _)| host);
+    host.trim_start_matches('[').trim_end_matches(']')
+}
+
+pub(crate) fn connect(address: &str, timeout: Duration) -> Result<TcpStream> {
+    let failed = |why: String| FluxError::HealthCheckFailed(format!("{}: {}", address, why));
+
+    let addrs = address.to_socket_addrs().map_err(|e| failed(e.to_string()))?;
+    let mut last = failed("no addresses".to_string());
+    for addr in addrs {
+        match TcpStream::connect_timeout(&addr, timeout) {
+            Ok(stream) => {
+                stream.set_read_timeout(Some(timeout)).map_err(|e| failed(e.to_string()))?;
+                stream.set_write_timeout(Some(timeout)).map_err(|e| failed(e.to_string()))?;
+                return Ok(stream);
+            }
+            Err(e) => last = failed(e.to_string()),
+        }
+    }
+    Err(last)
+}
+
+/// Connector that accepts any certificate; the probes check it themselves
+pub(crate) fn connector() -> Result<SslConnector> {
+    let mut builder = SslConnector::builder(SslMethod::tls_client())?;
+    builder.set_verify(SslVerifyMode::NONE);
+    Ok(builder.build())
+}
+
+fn probe_tls(address: &str, server_name: &str, cert: &X509, timeout: Duration) -> Result<()> {
+    let stream = connect(address, timeout)?;
+    let tls = connector()?.configure()?
+        .verify_hostname(false)
+        .connect(server_name, stream)
+        .map_err(|e| FluxError::HealthCheckFailed(format!("{}: TLS: {}", address, e)))?;
+
+    let presented = tls.ssl().peer_certificate()
+        .ok_or_else(|| FluxError::HealthCheckFailed(format!("{}: no certificate presented", address)))?;
+    if presented.to_der()? != cert.to_der()? {
+        let serial = |c: &X509| c.serial_number().to_bn().and_then(|bn| bn.to_hex_str()).map(|s| s.to_string());
+        return Err(FluxError::HealthCheckFailed(format!(
+            "{} presents serial {}, expected {}",
+            address, serial(&presented)?, serial(cert)?
+        )));
+    }
+    Ok(())
+}
+
+fn probe_http(url: &str, expected: Option<u16>, timeout: Duration) -> Result<()> {
+    let target = parse_url(url, "health.url")?;
+    let io = |e: std::io::Error| FluxError::HealthCheckFailed(format!("{}: {}", url, e));
+
+    let request = format!(
+        "GET {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: flux-ssl-mgr/{}\r\nConnection: close\r\n\r\n",
+        if target.path.starts_with('/') { target.path.to_string() } else { format!("/{}", target.path) },
+        target.authority,
+        env!("CARGO_PKG_VERSION")
+    );
+    let stream = connect(&format!("{}:{}", bracketed(target.host), target.port), timeout)?;
+    let response = if target.tls {
+        let mut stream = connector()?.connect(target.host, stream)
+            .map_err(|e| FluxError::HealthCheckFailed(format!("{}: TLS: {}", url, e)))?;
+        exchange(&mut stream, request.as_bytes(), &[]).map_err(io)?
+    } else {
+        let mut stream = stream;
+        exchange(&mut stream, request.as_bytes(), &[]).map_err(io)?
+    };
+
+    let status_line = response.lines().next().unwrap_or_default();
+    let status = status_line.split_whitespace().nth(1).and_then(|s| s.parse::<u16>().ok());
+    let healthy = match (status, expected) {
+        (Some(status), Some(expected)) => status == expected,
+        (Some(status), None) => (200..400).contains(&status),
+        (None, _) => false,
+    };
+    if !healthy {
+        return Err(FluxError::HealthCheckFailed(format!("{} answered '{}'", url, status_line)));
+    }
+    Ok(())
+}
+
+/// IPv6 literals need brackets before `:port`
+pub(crate) fn bracketed(host: &str) -> String {
+    if host.contains(':') { format!("[{}]", host) } else { host.to_string() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ca::CertSigner;
+    use crate::fixtures;
+    use openssl::ssl::SslAcceptor;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    fn check(probe: Probe) -> HealthCheck {
+        HealthCheck { probe, attempts: 2, delay: Interval::from_secs(0), timeout: Interval::from_secs(2) }
+    }
+
+    #[test]
+    fn test_parse() {
+        let health: HealthCheck = toml::from_str("type = \"http\"\nurl = \"https://nas.lab/\"\nstatus = 204\ndelay = \"2s\"").unwrap();
+        assert_eq!(health.probe, Probe::Http { url: "https://nas.lab/".to_string(), status: Some(204) });
+        assert_eq!((health.attempts, health.delay.get().as_secs()), (3, 2));
+        assert!(toml::from_str::<HealthCheck>("type = \"icmp\"\naddress = \"nas.lab\"").is_err());
+        assert_eq!(host_part("[fd00::2]:443"), "fd00::2");
+    }
+
+    #[test]
+    fn test_tcp_and_http() {
+        let cert = fixtures::root_ca_cert();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        let server = std::thread::spawn(move || {
+            let unavailable = "HTTP/1.1 503 Service Unavailable\r\n\r\n";
+            for reply in ["HTTP/1.1 200 OK\r\n\r\n", unavailable, unavailable] {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut buf = [0u8; 1024];
+                let _ = stream.read(&mut buf).unwrap();
+                stream.write_all(reply.as_bytes()).unwrap();
+            }
+        });
+        check(Probe::Http { url: format!("http://{}/health", address), status: None }).run(&cert).unwrap();
+        let err = check(Probe::Http { url: format!("http://{}/", address), status: Some(200) }).run(&cert).unwrap_err();
+        assert!(err.to_string().contains("503"), "{}", err);
+        server.join().unwrap();
+
+        // Nothing listens there any more
+        assert!(matches!(check(Probe::Tcp { address }).run(&cert), Err(FluxError::HealthCheckFailed(_))));
+    }
+
+    #[test]
+    fn test_tls_checks_presented_certificate() {
+        let ca = fixtures::intermediate_ca();
+        let deployed = ca.sign(&fixtures::leaf_csr("web", &[]), 30).unwrap();
+        let stale = ca.sign(&fixtures::leaf_csr("web", &[]), 30).unwrap();
+
+        let mut acceptor = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls()).unwrap();
+        acceptor.set_private_key(&fixtures::leaf_key()).unwrap();
+        acceptor.set_certificate(&deployed).unwrap();
+        let acceptor = acceptor.build();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let server = std::thread::spawn(move || {
+            for _ in 0..2 {
+                let (stream, _) = listener.accept().unwrap();
+                let _ = acceptor.accept(stream);
+            }
+        });
+
+        let mut health = check(Probe::Tls { address, server_name: Some("web.lab".to_string()) });
+        health.attempts = 1;
+        health.run(&deployed).unwrap();
+        let err = health.run(&stale).unwrap_err();
+        assert!(err.to_string().contains("presents serial"), "{}", err);
+        server.join().unwrap();
+    }
+}
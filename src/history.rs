@@ -0,0 +1,171 @@
+//! Git history of PKI state
+//!
+//! With `[git] enabled = true`, the directory holding the certificate store
+//! becomes a git repository and every change to the store (plus a copy of
+//! the last applied manifest) is committed. Cloning or pulling that repository
+//! replicates the inventory to a second machine.
+//!
+//! The repository's `.gitignore` only admits the state files, so private keys
+//! and CA material next to the store are never committed.
+
+use crate::config::Config;
+use crate::error::{FluxError, Result};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Copy of the last applied manifest, kept next to the store
+pub const MANIFEST_FILE: &str = "manifest.yaml";
+
+/// Ignore everything except the state files
+const GITIGNORE: &str = "\
+# Managed by flux-ssl-mgr: only state files are tracked, never keys
+*
+!.gitignore
+!flux-store.json
+!manifest.yaml
+";
+
+/// Directory of the state repository (the store's directory)
+pub fn repo_dir(config: &Config) -> PathBuf {
+    let store = config.store_path();
+    store.parent().map(Path::to_path_buf).unwrap_or_default()
+}
+
+/// Commit all state changes with `message`, if git history is enabled
+///
+/// Does nothing when nothing changed.
+pub fn commit(config: &Config, message: &str) -> Result<()> {
+    if !config.git.enabled {
+        return Ok(());
+    }
+
+    let dir = repo_dir(config);
+    ensure_repo(&dir)?;
+
+    git(&dir, &["add", "--all"])?;
+    if git_status(&dir, &["diff", "--cached", "--quiet"])? {
+        return Ok(());
+    }
+
+    let mut args = identity_args(&dir);
+    args.extend(["commit", "--quiet", "--message", message].map(String::from));
+    if config.git.sign {
+        args.push("--gpg-sign".to_string());
+    }
+    git(&dir, &args)?;
+
+    if let Some(remote) = &config.git.push {
+        git(&dir, &["push", "--quiet", remote, "HEAD"])?;
+    }
+
+    Ok(())
+}
+
+/// Copy an applied manifest into the state repository
+pub fn save_manifest(config: &Config, manifest: &Path) -> Result<()> {
+    if !config.git.enabled {
+        return Ok(());
+    }
+
+    let dest = repo_dir(config).join(MANIFEST_FILE);
+    std::fs::copy(manifest, &dest)
+        .map_err(|e| FluxError::FileWriteFailed(dest.clone(), e.to_string()))?;
+    Ok(())
+}
+
+/// Initialize the repository and its `.gitignore` on first use
+fn ensure_repo(dir: &Path) -> Result<()> {
+    if dir.join(".git").exists() {
+        return Ok(());
+    }
+
+    std::fs::create_dir_all(dir)?;
+    git(dir, &["init", "--quiet"])?;
+
+    let gitignore = dir.join(".gitignore");
+    if !gitignore.exists() {
+        std::fs::write(&gitignore, GITIGNORE)
+            .map_err(|e| FluxError::FileWriteFailed(gitignore.clone(), e.to_string()))?;
+    }
+
+    Ok(())
+}
+
+/// Fallback committer identity when git has none configured
+fn identity_args(dir: &Path) -> Vec<String> {
+    if git(dir, &["config", "user.email"]).is_ok_and(|email| !email.trim().is_empty()) {
+        return Vec::new();
+    }
+
+    ["-c", "user.name=flux-ssl-mgr", "-c", "user.email=flux-ssl-mgr@localhost"]
+        .map(String::from)
+        .to_vec()
+}
+
+fn git_command<S: AsRef<str>>(dir: &Path, args: &[S]) -> Command {
+    let mut cmd = Command::new("git");
+    cmd.arg("-C").arg(dir);
+    cmd.args(args.iter().map(AsRef::as_ref));
+    cmd
+}
+
+/// Run git, returning stdout
+fn git<S: AsRef<str>>(dir: &Path, args: &[S]) -> Result<String> {
+    let out = git_command(dir, args)
+        .output()
+        .map_err(|e| FluxError::HistoryError(format!("failed to run git: {}", e)))?;
+
+    if !out.status.success() {
+        let what = args.iter().map(AsRef::as_ref).collect::<Vec<_>>().join(" ");
+        return Err(FluxError::HistoryError(format!(
+            "git {} exited with {}: {}",
+            what,
+            out.status,
+            String::from_utf8_lossy(&out.stderr).trim()
+        )));
+    }
+
+    Ok(String::from_utf8_lossy(&out.stdout).into_owned())
+}
+
+/// Run git for its exit status (true on success)
+fn git_status(dir: &Path, args: &[&str]) -> Result<bool> {
+    git_command(dir, args)
+        .status()
+        .map(|status| status.success())
+        .map_err(|e| FluxError::HistoryError(format!("failed to run git: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::update_store;
+
+    #[test]
+    fn test_commit_tracks_only_state_files() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut config = Config {
+            working_dir: temp_dir.path().to_path_buf(),
+            ..Config::default()
+        };
+        config.git.enabled = true;
+
+        let dir = repo_dir(&config);
+        std::fs::create_dir_all(dir.join("private")).unwrap();
+        std::fs::write(dir.join("private/web.key.pem"), "secret").unwrap();
+
+        update_store(&config, "Record web", |_| ()).unwrap();
+        let manifest = temp_dir.path().join("certs.yaml");
+        std::fs::write(&manifest, "certificates: []\n").unwrap();
+        save_manifest(&config, &manifest).unwrap();
+        commit(&config, "Apply certs.yaml").unwrap();
+        // Nothing changed, so no new commit
+        commit(&config, "Empty").unwrap();
+
+        let log = git(&dir, &["log", "--format=%s"]).unwrap();
+        assert_eq!(log.lines().collect::<Vec<_>>(), vec!["Apply certs.yaml", "Record web"]);
+
+        let files = git(&dir, &["ls-files"]).unwrap();
+        assert_eq!(files.lines().collect::<Vec<_>>(), vec![".gitignore", "flux-store.json", "manifest.yaml"]);
+    }
+}
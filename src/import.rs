@@ -0,0 +1,281 @@
+//! Import of certificates issued with `openssl ca` (`flux-ssl-mgr import`)
+//!
+//! Reads an OpenSSL CA directory (`index.txt`, `newcerts/` and `certs/`, in
+//! the directory itself and in `intermediate/`) and adds every certificate
+//! issued by the configured CA to the certificate store, with its revocation
+//! status from `index.txt`. Certificates already in the store are skipped,
+//! so importing again only picks up what is new.
+
+use crate::config::Config;
+use crate::crypto::revocation::{check_index, normalize_serial, RevocationState};
+use crate::crypto::{is_key_encrypted, load_cert, load_certs};
+use crate::error::{FluxError, Result};
+use crate::fsutil::read_to_string_bounded;
+use crate::store::{update_store, CertStore, IssuedCert};
+use openssl::nid::Nid;
+use openssl::x509::X509;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+/// What an import found
+#[derive(Debug, Default)]
+pub struct ImportSummary {
+    /// Records added to the store (or that would be, on a dry run)
+    pub imported: Vec<IssuedCert>,
+    /// Certificates already in the store
+    pub known: usize,
+    /// Certificates from another CA (such as the intermediate itself)
+    pub other_issuer: usize,
+    /// Serials in `index.txt` with no certificate file
+    pub missing: Vec<String>,
+}
+
+/// A certificate file found in the CA directory
+struct Found {
+    cert: X509,
+    serial: String,
+    path: PathBuf,
+    /// Database directory it was found in
+    db_dir: PathBuf,
+    /// Whether it was found in `certs/`, under a name of its own
+    named: bool,
+}
+
+/// Import the certificates in the OpenSSL CA directory `dir`
+///
+/// With `dry_run`, nothing is written and the summary lists what would be
+/// imported.
+pub fn import_openssl_ca(config: &Config, dir: &Path, dry_run: bool) -> Result<ImportSummary> {
+    let ca_cert = load_cert(&config.ca_cert_path)?;
+    let mut summary = scan(dir, &ca_cert)?;
+
+    let store = CertStore::open_default(config)?;
+    let is_known = |serial: &str| {
+        store.certificates().iter().any(|c| normalize_serial(&c.serial) == normalize_serial(serial))
+    };
+    let before = summary.imported.len();
+    summary.imported.retain(|record| !is_known(&record.serial));
+    summary.known = before - summary.imported.len();
+
+    if !dry_run && !summary.imported.is_empty() {
+        let message = format!("Import {} certificate(s) from {}", summary.imported.len(), dir.display());
+        update_store(config, &message, |store| {
+            for record in &summary.imported {
+                store.record(record.clone());
+            }
+        })?;
+    }
+
+    Ok(summary)
+}
+
+/// Records for every certificate in `dir` issued by `ca_cert`
+fn scan(dir: &Path, ca_cert: &X509) -> Result<ImportSummary> {
+    let db_dirs: Vec<PathBuf> = [dir.to_path_buf(), dir.join("intermediate")]
+        .into_iter()
+        .filter(|d| d.join("index.txt").is_file() || d.join("newcerts").is_dir() || d.join("certs").is_dir())
+        .collect();
+    if db_dirs.is_empty() {
+        return Err(FluxError::FileReadFailed(
+            dir.to_path_buf(),
+            "no index.txt, newcerts/ or certs/ found (not an OpenSSL CA directory)".to_string(),
+        ));
+    }
+
+    let issuer = ca_cert.subject_name().to_der()?;
+    let mut summary = ImportSummary::default();
+    let mut found: BTreeMap<String, Found> = BTreeMap::new();
+    let mut seen = BTreeSet::new();
+
+    for db_dir in &db_dirs {
+        // newcerts/ first, so a copy in certs/ (with a real name) wins
+        for (sub, named) in [("newcerts", false), ("certs", true)] {
+            for path in cert_files(&db_dir.join(sub))? {
+                let certs = match load_certs(&path) {
+                    Ok(certs) => certs,
+                    Err(e) => {
+                        tracing::debug!("Skipping {}: {}", path.display(), e);
+                        continue;
+                    }
+                };
+                // A chain file's first certificate is the one it is about
+                let Some(cert) = certs.into_iter().next() else { continue };
+                let serial = normalize_serial(&cert.serial_number().to_bn()?.to_hex_str()?);
+                seen.insert(serial.clone());
+                if cert.issuer_name().to_der()? != issuer {
+                    summary.other_issuer += 1;
+                    continue;
+                }
+
+                if !named && found.contains_key(&serial) {
+                    continue;
+                }
+                found.insert(serial.clone(), Found { cert, serial, path, db_dir: db_dir.clone(), named });
+            }
+        }
+    }
+
+    let mut indexes = BTreeMap::new();
+    for db_dir in &db_dirs {
+        let path = db_dir.join("index.txt");
+        if path.is_file() {
+            indexes.insert(db_dir.clone(), read_to_string_bounded(&path)?);
+        }
+    }
+
+    for (db_dir, index) in &indexes {
+        for line in index.lines() {
+            let fields: Vec<&str> = line.split('\t').collect();
+            if fields.len() >= 4 && !seen.contains(&normalize_serial(fields[3])) {
+                tracing::debug!("{}: no certificate file for serial {}", db_dir.display(), fields[3]);
+                summary.missing.push(normalize_serial(fields[3]));
+            }
+        }
+    }
+
+    for found in found.into_values() {
+        summary.imported.push(record(&found, indexes.get(&found.db_dir).map(String::as_str), ca_cert)?);
+    }
+    summary.imported.sort_by_key(|r| r.issued_at);
+
+    Ok(summary)
+}
+
+/// Store record for a found certificate, with its status from `index`
+fn record(found: &Found, index: Option<&str>, ca_cert: &X509) -> Result<IssuedCert> {
+    let name = if found.named { file_name_stem(&found.path) } else { None }
+        .or_else(|| common_name(&found.cert))
+        .unwrap_or_else(|| format!("serial-{}", found.serial));
+
+    let key_path = found.db_dir.join("private").join(format!("{}.key.pem", name));
+    let key_path = key_path.is_file().then_some(key_path);
+
+    let mut record = IssuedCert::from_cert(&name, &found.cert, &found.path, key_path.as_deref())?;
+    record.issued_at = record.not_before;
+    record.key_encrypted = key_path.is_some_and(|p| is_key_encrypted(p).unwrap_or(false));
+
+    if let Some(index) = index {
+        let status = check_index(&found.cert, index, Some(ca_cert))?;
+        if status.state == RevocationState::Revoked {
+            record.revoked_at = Some(status.revoked_at.unwrap_or(record.issued_at));
+            record.revocation_reason = Some(status.reason.unwrap_or_else(|| "unspecified".to_string()));
+        }
+    }
+
+    Ok(record)
+}
+
+/// Certificate files directly in `dir` (none if it doesn't exist)
+fn cert_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let is_cert = path.extension().and_then(|e| e.to_str())
+            .is_some_and(|e| matches!(e, "pem" | "crt" | "cer" | "der"));
+        if path.is_file() && is_cert {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// `web` for `web.cert.pem`, `web.crt` or `web.pem`
+fn file_name_stem(path: &Path) -> Option<String> {
+    let file_name = path.file_name()?.to_str()?;
+    [".cert.pem", ".crt", ".cer", ".der", ".pem"].iter()
+        .find_map(|suffix| file_name.strip_suffix(suffix))
+        .filter(|stem| !stem.is_empty())
+        .map(str::to_string)
+}
+
+/// The certificate's CN, made usable as a certificate name (`*.lab` becomes `wildcard.lab`)
+fn common_name(cert: &X509) -> Option<String> {
+    let cn = cert.subject_name().entries_by_nid(Nid::COMMONNAME).next()?.data().as_utf8().ok()?.to_string();
+    let name: String = cn.replacen('*', "wildcard", 1)
+        .chars()
+        .map(|c| if c.is_alphanumeric() || matches!(c, '-' | '_' | '.') { c } else { '_' })
+        .collect();
+    let name = name.trim_start_matches('.').to_string();
+    (!name.is_empty()).then_some(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ca::{CertSigner, OpensslDb};
+    use crate::fixtures;
+
+    #[test]
+    fn test_import_openssl_ca() {
+        let dir = tempfile::tempdir().unwrap();
+        let ca_dir = dir.path().join("ca");
+        let db_dir = ca_dir.join("intermediate");
+        for sub in ["newcerts", "certs", "private"] {
+            std::fs::create_dir_all(db_dir.join(sub)).unwrap();
+        }
+        std::fs::write(db_dir.join("index.txt"), "").unwrap();
+
+        let ca = fixtures::intermediate_ca();
+        let db = OpensslDb::new(&db_dir);
+        let mut serials = Vec::new();
+        for name in ["web", "*.lab", "old"] {
+            let cert = ca.sign(&fixtures::leaf_csr(name, &[]), 30).unwrap();
+            db.record_issued(&cert).unwrap();
+            let serial = cert.serial_number().to_bn().unwrap().to_hex_str().unwrap().to_string();
+            std::fs::write(db_dir.join("newcerts").join(format!("{}.pem", serial)), cert.to_pem().unwrap()).unwrap();
+            if name == "web" {
+                std::fs::write(db_dir.join("certs").join("web.cert.pem"), cert.to_pem().unwrap()).unwrap();
+                std::fs::write(db_dir.join("private").join("web.key.pem"), "key").unwrap();
+            }
+            serials.push(serial);
+        }
+        db.revoke(&serials[2..], "superseded", chrono::Utc::now()).unwrap();
+        // The intermediate itself and an entry whose file is gone
+        std::fs::write(db_dir.join("certs").join("intermediate.cert.pem"), ca.cert().to_pem().unwrap()).unwrap();
+        let mut index = std::fs::read_to_string(db.index_path()).unwrap();
+        index.push_str("V\t270101000000Z\t\t0FFF\tunknown\t/CN=gone\n");
+        std::fs::write(db.index_path(), index).unwrap();
+
+        let config = crate::config::Config {
+            working_dir: dir.path().join("flux"),
+            ca_cert_path: dir.path().join("intermediate.cert.pem"),
+            ..Default::default()
+        };
+        std::fs::write(&config.ca_cert_path, ca.cert().to_pem().unwrap()).unwrap();
+
+        let preview = import_openssl_ca(&config, &ca_dir, true).unwrap();
+        assert_eq!(preview.imported.len(), 3);
+        assert!(CertStore::open_default(&config).unwrap().certificates().is_empty());
+
+        let summary = import_openssl_ca(&config, &ca_dir, false).unwrap();
+        assert_eq!(summary.imported.len(), 3);
+        assert_eq!(summary.other_issuer, 1);
+        assert_eq!(summary.missing, vec!["FFF".to_string()]);
+
+        let store = CertStore::open_default(&config).unwrap();
+        let web = store.latest("web").unwrap();
+        assert_eq!(web.cert_path, db_dir.join("certs").join("web.cert.pem"));
+        assert_eq!(web.key_path.as_deref(), Some(db_dir.join("private").join("web.key.pem").as_path()));
+        assert!(!web.is_revoked());
+        assert!(store.latest("wildcard.lab").is_some());
+        let old = store.latest("old").unwrap();
+        assert_eq!(old.revocation_reason.as_deref(), Some("superseded"));
+        assert_eq!(old.cert_path, db_dir.join("newcerts").join(format!("{}.pem", serials[2])));
+
+        // Importing again finds nothing new
+        let again = import_openssl_ca(&config, &ca_dir, false).unwrap();
+        assert!(again.imported.is_empty());
+        assert_eq!(again.known, 3);
+    }
+
+    #[test]
+    fn test_names() {
+        assert_eq!(file_name_stem(Path::new("certs/web.cert.pem")).as_deref(), Some("web"));
+        assert_eq!(file_name_stem(Path::new("certs/nas.lab.crt")).as_deref(), Some("nas.lab"));
+        assert_eq!(file_name_stem(Path::new("certs/.pem")), None);
+    }
+}
@@ -0,0 +1,263 @@
+//! Installing issued certificates on this machine (`flux-ssl-mgr install`)
+//!
+//! `--keychain` (macOS) imports a certificate and its key as an identity into
+//! the login keychain with the system `security` tool, going through a
+//! temporary PKCS#12 file with a one-time password. `--trust-root` also adds
+//! the root CA to the user's trust settings; macOS asks for the login password
+//! before changing them.
+//!
+//! `--nss` trusts the root CA in the NSS databases Firefox and Chromium use on
+//! Linux (`~/.pki/nssdb` and every Firefox profile, including snap and
+//! Flatpak installs), with NSS's `certutil`.
+
+use crate::batch::IssuancePaths;
+use crate::ca::chain_pem;
+use crate::config::Config;
+use crate::crypto::{certs_from_pem, load_cert, load_private_key, to_pkcs12};
+use crate::error::{FluxError, Result};
+use crate::output::OutputFormatter;
+use crate::passwords::{generate_password, GENERATED_PASSWORD_LEN};
+use openssl::nid::Nid;
+use openssl::x509::X509;
+use secrecy::ExposeSecret;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The user's login keychain
+pub fn login_keychain() -> Result<PathBuf> {
+    let home = std::env::var_os("HOME")
+        .ok_or_else(|| FluxError::InstallError("HOME is not set".to_string()))?;
+    Ok(PathBuf::from(home).join("Library/Keychains/login.keychain-db"))
+}
+
+/// Import `cert_name` into a macOS keychain (the login keychain by default)
+///
+/// `key_password` unlocks a password-protected key.
+pub fn install_keychain(
+    config: &Config,
+    cert_name: &str,
+    keychain: Option<&Path>,
+    trust_root: bool,
+    key_password: Option<&str>,
+    output: &OutputFormatter,
+) -> Result<()> {
+    if !cfg!(target_os = "macos") {
+        return Err(FluxError::InstallError("--keychain is only available on macOS".to_string()));
+    }
+    let keychain = match keychain {
+        Some(keychain) => keychain.to_path_buf(),
+        None => login_keychain()?,
+    };
+
+    let (cert_path, key_path) = IssuancePaths::current(config, cert_name);
+    for file in [&cert_path, &key_path] {
+        if !file.exists() {
+            return Err(FluxError::InstallError(format!(
+                "{} not found; issue {} before installing it",
+                file.display(),
+                cert_name
+            )));
+        }
+    }
+    let cert = load_cert(&cert_path)?;
+    let key = load_private_key(&key_path, key_password)?;
+    let ca_certs = certs_from_pem(&chain_pem(config)?)?;
+
+    // `security import` only takes identities as PKCS#12
+    let password = generate_password(GENERATED_PASSWORD_LEN)?;
+    let mut p12 = tempfile::Builder::new().suffix(".p12").tempfile()?;
+    p12.write_all(&to_pkcs12(cert_name, &cert, &key, &ca_certs, password.expose_secret())?)?;
+    p12.flush()?;
+
+    output.step(&format!("Importing {} into {}", cert_name, keychain.display()));
+    run(import_command(&keychain, p12.path(), password.expose_secret()), "security import")?;
+
+    if trust_root {
+        output.info("Trusting the CA; macOS asks for your password to change trust settings");
+        run(trust_command(&keychain, &trust_anchor(config)), "security add-trusted-cert")?;
+    }
+
+    output.success(&format!("Installed {} into {}", cert_name, keychain.display()));
+    Ok(())
+}
+
+/// Trust the root CA in NSS databases: `databases`, or every one found under
+/// `$HOME`
+///
+/// Returns how many databases were updated. A database that can't be updated
+/// is a warning as long as another one succeeds.
+pub fn install_nss(config: &Config, databases: &[PathBuf], output: &OutputFormatter) -> Result<usize> {
+    let databases = if databases.is_empty() {
+        let home = std::env::var_os("HOME")
+            .ok_or_else(|| FluxError::InstallError("HOME is not set".to_string()))?;
+        find_nss_databases(Path::new(&home))
+    } else {
+        databases.iter().map(|dir| nss_database(dir).unwrap_or_else(|| format!("sql:{}", dir.display()))).collect()
+    };
+    if databases.is_empty() {
+        return Err(FluxError::InstallError(
+            "no NSS databases found; start Firefox or Chromium once, or pass --nss-db".to_string(),
+        ));
+    }
+
+    let anchor = trust_anchor(config);
+    let nickname = nickname(&load_cert(&anchor)?);
+    let mut installed = 0;
+    for database in &databases {
+        output.step(&format!("Trusting {} in {}", nickname, database));
+        match run(certutil_command(database, &nickname, &anchor), "certutil") {
+            Ok(()) => installed += 1,
+            Err(e) => output.warning(&format!("{}: {}", database, e)),
+        }
+    }
+
+    if installed == 0 {
+        return Err(FluxError::InstallError(
+            "certutil failed for every database (it comes with libnss3-tools on Debian/Ubuntu, nss-tools on Fedora)"
+                .to_string(),
+        ));
+    }
+    Ok(installed)
+}
+
+/// NSS databases of the user's browsers, as `sql:` / `dbm:` directory specs
+pub fn find_nss_databases(home: &Path) -> Vec<String> {
+    let mut dirs = vec![home.join(".pki/nssdb"), home.join("snap/chromium/current/.pki/nssdb")];
+    for profiles in [
+        ".mozilla/firefox",
+        "snap/firefox/common/.mozilla/firefox",
+        ".var/app/org.mozilla.firefox/.mozilla/firefox",
+    ] {
+        if let Ok(entries) = std::fs::read_dir(home.join(profiles)) {
+            let mut profile_dirs: Vec<PathBuf> = entries.filter_map(|e| e.ok()).map(|e| e.path()).collect();
+            profile_dirs.sort();
+            dirs.extend(profile_dirs);
+        }
+    }
+    dirs.iter().filter_map(|dir| nss_database(dir)).collect()
+}
+
+/// `sql:<dir>` for a cert9.db database, `dbm:<dir>` for a legacy cert8.db one
+fn nss_database(dir: &Path) -> Option<String> {
+    if dir.join("cert9.db").is_file() {
+        Some(format!("sql:{}", dir.display()))
+    } else if dir.join("cert8.db").is_file() {
+        Some(format!("dbm:{}", dir.display()))
+    } else {
+        None
+    }
+}
+
+/// Nickname of the CA in NSS: its common name
+fn nickname(cert: &X509) -> String {
+    cert.subject_name().entries_by_nid(Nid::COMMONNAME).next()
+        .and_then(|entry| entry.data().as_utf8().ok())
+        .map_or_else(|| "flux-ssl-mgr CA".to_string(), |cn| cn.to_string())
+}
+
+/// The CA certificate to trust: the root, or the intermediate without one
+fn trust_anchor(config: &Config) -> PathBuf {
+    let root = config.root_ca_cert_path();
+    if root.exists() { root } else { config.ca_cert_path.clone() }
+}
+
+/// `certutil -A` adding `cert` as a trusted CA for TLS servers
+fn certutil_command(database: &str, nickname: &str, cert: &Path) -> Command {
+    let mut cmd = Command::new("certutil");
+    cmd.args(["-A", "-d", database, "-t", "C,,", "-n", nickname, "-i"]).arg(cert);
+    cmd
+}
+
+/// `security import` of a PKCS#12 identity
+fn import_command(keychain: &Path, p12: &Path, password: &str) -> Command {
+    let mut cmd = Command::new("security");
+    cmd.arg("import")
+        .arg(p12)
+        .arg("-k")
+        .arg(keychain)
+        .args(["-f", "pkcs12", "-P", password]);
+    cmd
+}
+
+/// `security add-trusted-cert` trusting `cert` as a root for this user
+fn trust_command(keychain: &Path, cert: &Path) -> Command {
+    let mut cmd = Command::new("security");
+    cmd.args(["add-trusted-cert", "-r", "trustRoot", "-k"])
+        .arg(keychain)
+        .arg(cert);
+    cmd
+}
+
+fn run(mut cmd: Command, what: &str) -> Result<()> {
+    let out = cmd.output()
+        .map_err(|e| FluxError::InstallError(format!("failed to run {}: {}", what, e)))?;
+    if !out.status.success() {
+        return Err(FluxError::InstallError(format!(
+            "{} exited with {}: {}",
+            what,
+            out.status,
+            String::from_utf8_lossy(&out.stderr).trim()
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(cmd: &Command) -> Vec<String> {
+        cmd.get_args().map(|a| a.to_string_lossy().into_owned()).collect()
+    }
+
+    #[test]
+    fn test_security_commands() {
+        let keychain = Path::new("/Users/me/Library/Keychains/login.keychain-db");
+        assert_eq!(
+            args(&import_command(keychain, Path::new("/tmp/laptop.p12"), "pw")),
+            ["import", "/tmp/laptop.p12", "-k", "/Users/me/Library/Keychains/login.keychain-db", "-f", "pkcs12", "-P", "pw"]
+        );
+        assert_eq!(
+            args(&trust_command(keychain, Path::new("/root/ca/certs/ca.cert.pem"))),
+            ["add-trusted-cert", "-r", "trustRoot", "-k", "/Users/me/Library/Keychains/login.keychain-db", "/root/ca/certs/ca.cert.pem"]
+        );
+    }
+
+    #[test]
+    fn test_find_nss_databases() {
+        let home = tempfile::tempdir().unwrap();
+        let touch = |dir: &str, file: &str| {
+            std::fs::create_dir_all(home.path().join(dir)).unwrap();
+            std::fs::write(home.path().join(dir).join(file), "").unwrap();
+        };
+        touch(".pki/nssdb", "cert9.db");
+        touch(".mozilla/firefox/abcd.default-release", "cert9.db");
+        touch(".mozilla/firefox/old.default", "cert8.db");
+        touch("snap/firefox/common/.mozilla/firefox/snap.default", "cert9.db");
+        std::fs::create_dir_all(home.path().join(".mozilla/firefox/Crash Reports")).unwrap();
+
+        let found = find_nss_databases(home.path());
+        let base = home.path().display();
+        assert_eq!(found, [
+            format!("sql:{}/.pki/nssdb", base),
+            format!("sql:{}/.mozilla/firefox/abcd.default-release", base),
+            format!("dbm:{}/.mozilla/firefox/old.default", base),
+            format!("sql:{}/snap/firefox/common/.mozilla/firefox/snap.default", base),
+        ]);
+
+        assert_eq!(
+            args(&certutil_command("sql:/h/.pki/nssdb", "Flux Test Root CA", Path::new("/ca/root.pem"))),
+            ["-A", "-d", "sql:/h/.pki/nssdb", "-t", "C,,", "-n", "Flux Test Root CA", "-i", "/ca/root.pem"]
+        );
+        assert_eq!(nickname(&crate::fixtures::root_ca_cert()), "Flux Test Root CA");
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    #[test]
+    fn test_keychain_needs_macos() {
+        let output = OutputFormatter::with_writer(&Config::default().output, std::io::sink());
+        let err = install_keychain(&Config::default(), "laptop", None, false, None, &output).unwrap_err();
+        assert!(matches!(err, FluxError::InstallError(_)));
+    }
+}
@@ -1,9 +1,32 @@
 //! Interactive mode for user prompts
 
 use crate::crypto::SanEntry;
+use crate::duration::Days;
 use crate::error::{FluxError, Result};
 use crate::batch::CsrFile;
-use dialoguer::{Input, Confirm, Select, MultiSelect};
+use crate::inventory::Inventory;
+use dialoguer::{Completion, Input, Confirm, Select, MultiSelect};
+
+/// Tab completion of the last comma-separated SAN from the inventory
+struct SanCompletion<'a>(&'a Inventory);
+
+impl Completion for SanCompletion<'_> {
+    fn get(&self, input: &str) -> Option<String> {
+        let (head, last) = match input.rfind(',') {
+            Some(i) => input.split_at(i + 1),
+            None => ("", input),
+        };
+        let suggestion = self.0.suggest(last, 1).into_iter().next()?;
+        Some(format!("{}{}", head, suggestion))
+    }
+}
+
+/// Point out tab completion when the inventory has hosts
+fn print_inventory_hint(inventory: &Inventory) {
+    if !inventory.is_empty() {
+        println!("Tab completes hostnames and addresses from the inventory");
+    }
+}
 
 /// Prompt for certificate name
 pub fn prompt_cert_name() -> Result<String> {
@@ -25,12 +48,19 @@ pub fn prompt_cert_name() -> Result<String> {
 }
 
 /// Prompt for Subject Alternative Names
-pub fn prompt_sans() -> Result<Vec<SanEntry>> {
+///
+/// Starts out with what the inventory knows about `cert_name`.
+pub fn prompt_sans(cert_name: &str, inventory: &Inventory) -> Result<Vec<SanEntry>> {
     println!("\nEnter Subject Alternative Names (DNS and IP addresses)");
     println!("Example: DNS:service.fluxlab.systems,DNS:service.local,IP:10.0.2.100");
+    print_inventory_hint(inventory);
 
+    let completion = SanCompletion(inventory);
+    let known: Vec<String> = inventory.sans_for(cert_name).iter().map(ToString::to_string).collect();
     let sans_input: String = Input::new()
         .with_prompt("SANs")
+        .with_initial_text(known.join(","))
+        .completion_with(&completion)
         .validate_with(|input: &String| -> std::result::Result<(), String> {
             if input.trim().is_empty() {
                 return Err("Subject Alternative Names are required".to_string());
@@ -55,6 +85,15 @@ pub fn prompt_password_protection() -> Result<bool> {
         .map_err(|e| FluxError::InteractiveError(e.to_string()))
 }
 
+/// Prompt whether all keys in a batch share one password
+pub fn prompt_shared_password(count: usize) -> Result<bool> {
+    Confirm::new()
+        .with_prompt(format!("Use the same password for all {} private keys?", count))
+        .default(true)
+        .interact()
+        .map_err(|e| FluxError::InteractiveError(e.to_string()))
+}
+
 /// Prompt for processing mode (single or batch)
 pub fn prompt_processing_mode() -> Result<usize> {
     let modes = vec![
@@ -114,12 +153,15 @@ pub fn prompt_use_common_sans() -> Result<bool> {
 }
 
 /// Prompt for common SANs value
-pub fn prompt_common_sans() -> Result<Vec<SanEntry>> {
+pub fn prompt_common_sans(inventory: &Inventory) -> Result<Vec<SanEntry>> {
     println!("\nEnter common Subject Alternative Names:");
     println!("Example: DNS:*.fluxlab.systems,IP:10.0.2.100");
+    print_inventory_hint(inventory);
 
+    let completion = SanCompletion(inventory);
     let sans_input: String = Input::new()
         .with_prompt("Common SANs")
+        .completion_with(&completion)
         .validate_with(|input: &String| -> std::result::Result<(), String> {
             if input.trim().is_empty() {
                 return Ok(()); // Allow empty for no common SANs
@@ -148,29 +190,35 @@ pub fn prompt_confirm(message: &str) -> Result<bool> {
         .map_err(|e| FluxError::InteractiveError(e.to_string()))
 }
 
-/// Prompt for certificate validity days
-pub fn prompt_cert_days(default: u32) -> Result<u32> {
+/// Confirm revoking certificates (defaults to no)
+pub fn prompt_revoke(names: &[&str]) -> Result<bool> {
+    Confirm::new()
+        .with_prompt(format!("Revoke {} certificates removed from the manifest ({})?", names.len(), names.join(", ")))
+        .default(false)
+        .interact()
+        .map_err(|e| FluxError::InteractiveError(e.to_string()))
+}
+
+/// Prompt for certificate validity (days, or e.g. 13w, 1y)
+pub fn prompt_cert_days(default: Days) -> Result<Days> {
     let days: String = Input::new()
-        .with_prompt("Certificate validity in days")
+        .with_prompt("Certificate validity (e.g. 375, 13w, 1y)")
         .default(default.to_string())
         .validate_with(|input: &String| -> std::result::Result<(), &str> {
-            match input.parse::<u32>() {
-                Ok(d) if d > 0 && d <= 825 => Ok(()), // Max 825 days per CA/B Forum
-                Ok(_) => Err("Days must be between 1 and 825"),
-                Err(_) => Err("Please enter a valid number"),
+            match input.parse::<Days>() {
+                Ok(d) if d.get() > 0 && d.get() <= 825 => Ok(()), // Max 825 days per CA/B Forum
+                Ok(_) => Err("Validity must be between 1 and 825 days"),
+                Err(_) => Err("Please enter a number of days, or e.g. 13w, 1y"),
             }
         })
         .interact_text()
         .map_err(|e| FluxError::InteractiveError(e.to_string()))?;
 
-    days.parse::<u32>()
-        .map_err(|e| FluxError::InvalidConfigValue("cert_days".to_string(), e.to_string()))
+    days.parse()
 }
 
 #[cfg(test)]
 mod tests {
-    use super::*;
-
     // Interactive tests would require mocking user input
     // These are placeholder tests
 }
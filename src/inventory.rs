@@ -0,0 +1,679 @@
+//! Known hosts for SAN suggestions
+//!
+//! `[[inventory]]` sources list hostnames and addresses that already exist on
+//! the network: a hosts file, a Pi-hole's local DNS records and DHCP leases,
+//! the clients of a UniFi Network application, or NetBox IP addresses with a
+//! DNS name. The interactive SAN prompts and the web UI suggest entries from
+//! them, so names are picked instead of typed. A source that can't be reached
+//! is skipped with a warning.
+
+use crate::ca::chain_pem;
+use crate::config::Config;
+use crate::crypto::{certs_from_pem, SanEntry};
+use crate::error::{FluxError, Result};
+use crate::fsutil::read_to_string_bounded;
+use crate::notify::webhook::parse_url;
+use openssl::ssl::{SslConnector, SslMethod};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write as _;
+use std::io::{Read, Write};
+use std::net::{IpAddr, TcpStream};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Largest response read from an inventory API
+const MAX_RESPONSE: u64 = 16 * 1024 * 1024;
+
+/// Connect, read and write timeout of inventory requests
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Upper bound on pages followed per source
+const MAX_PAGES: usize = 100;
+
+/// A source of known hosts (`[[inventory]]`)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InventoryConfig {
+    #[serde(flatten)]
+    pub source: SourceKind,
+
+    /// Appended to names without a dot (`lab` turns `nas01` into `nas01.lab`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub domain: Option<String>,
+}
+
+/// Where an inventory source reads its hosts
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum SourceKind {
+    /// Hosts-file lines (`<address> <name>...`), e.g. /etc/hosts
+    File { path: PathBuf },
+    /// Local DNS records and DHCP leases of a Pi-hole (v6 API)
+    Pihole {
+        url: String,
+        /// Web interface or app password (none if the API is open)
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        password_file: Option<PathBuf>,
+    },
+    /// Clients of a UniFi Network application (integration API)
+    Unifi {
+        /// API base, e.g. `https://unifi.lab/proxy/network/integration`
+        url: String,
+        api_key_file: PathBuf,
+        /// Site name (default: all sites)
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        site: Option<String>,
+    },
+    /// NetBox IP addresses that have a DNS name
+    Netbox { url: String, token_file: PathBuf },
+}
+
+/// A named host and its addresses
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Host {
+    pub name: String,
+    pub addresses: Vec<IpAddr>,
+}
+
+/// Something that knows hosts on the network
+pub trait InventorySource {
+    /// Name used in warnings, e.g. `pihole https://pihole.lab`
+    fn name(&self) -> String;
+
+    /// Every host the source knows
+    fn hosts(&self) -> Result<Vec<Host>>;
+}
+
+/// The source configured by `entry`
+pub fn source(config: &Config, entry: &InventoryConfig) -> Box<dyn InventorySource> {
+    let domain = entry.domain.clone();
+    match &entry.source {
+        SourceKind::File { path } => Box::new(HostsFile { path: path.clone(), domain }),
+        SourceKind::Pihole { url, password_file } => Box::new(Pihole {
+            http: HttpClient::new(config, url),
+            password_file: password_file.clone(),
+            domain,
+        }),
+        SourceKind::Unifi { url, api_key_file, site } => Box::new(Unifi {
+            http: HttpClient::new(config, url),
+            api_key_file: api_key_file.clone(),
+            site: site.clone(),
+            domain,
+        }),
+        SourceKind::Netbox { url, token_file } => Box::new(Netbox {
+            http: HttpClient::new(config, url),
+            token_file: token_file.clone(),
+            domain,
+        }),
+    }
+}
+
+/// Hosts of every source, merged by name
+#[derive(Debug, Clone, Default)]
+pub struct Inventory {
+    hosts: BTreeMap<String, BTreeSet<IpAddr>>,
+}
+
+impl Inventory {
+    /// Query every configured source
+    ///
+    /// Sources that fail are left out and returned with their error.
+    pub fn load(config: &Config) -> (Self, Vec<(String, FluxError)>) {
+        let mut inventory = Self::default();
+        let mut errors = Vec::new();
+        for entry in &config.inventory {
+            let source = source(config, entry);
+            match source.hosts() {
+                Ok(hosts) => inventory.extend(hosts),
+                Err(e) => errors.push((source.name(), e)),
+            }
+        }
+        (inventory, errors)
+    }
+
+    /// Add hosts, merging addresses of names already known
+    pub fn extend(&mut self, hosts: impl IntoIterator<Item = Host>) {
+        for host in hosts {
+            self.hosts.entry(host.name).or_default().extend(host.addresses);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hosts.is_empty()
+    }
+
+    /// Known SANs of a certificate name: the matching hosts and their addresses
+    ///
+    /// A name without a dot also matches hosts with that first label.
+    pub fn sans_for(&self, name: &str) -> Vec<SanEntry> {
+        let name = name.trim().trim_end_matches('.').to_lowercase();
+        let matches = |host: &str| host == name || (!name.contains('.') && host.split('.').next() == Some(name.as_str()));
+
+        let mut names = Vec::new();
+        let mut addresses: BTreeSet<&IpAddr> = BTreeSet::new();
+        for (host, host_addresses) in self.hosts.iter().filter(|(host, _)| matches(host)) {
+            names.push(SanEntry::Dns(host.clone()));
+            addresses.extend(host_addresses);
+        }
+        names.extend(addresses.into_iter().map(|address| SanEntry::Ip(address.to_string())));
+        names
+    }
+
+    /// SANs (`DNS:…`, `IP:…`) matching what was typed so far, prefix matches first
+    ///
+    /// The addresses of a matching host are suggested along with its name.
+    pub fn suggest(&self, query: &str, limit: usize) -> Vec<String> {
+        let query = query.trim();
+        let query = ["DNS:", "IP:"]
+            .iter()
+            .find_map(|prefix| query.get(..prefix.len()).filter(|p| p.eq_ignore_ascii_case(prefix)).map(|_| &query[prefix.len()..]))
+            .unwrap_or(query)
+            .to_lowercase();
+        let rank = |candidate: &str| {
+            if candidate.starts_with(&query) {
+                Some(0)
+            } else if candidate.contains(&query) {
+                Some(1)
+            } else {
+                None
+            }
+        };
+
+        let mut matches = BTreeSet::new();
+        for (name, addresses) in &self.hosts {
+            let name_rank = rank(name);
+            if let Some(r) = name_rank {
+                matches.insert((r, format!("DNS:{}", name)));
+            }
+            for address in addresses {
+                let address = address.to_string();
+                if let Some(r) = [name_rank, rank(&address)].into_iter().flatten().min() {
+                    matches.insert((r, format!("IP:{}", address)));
+                }
+            }
+        }
+        matches.into_iter().map(|(_, san)| san).take(limit).collect()
+    }
+}
+
+/// Lower-cased host name with `domain` appended to single labels, if usable
+fn host_name(name: &str, domain: Option<&str>) -> Option<String> {
+    let name = name.trim().trim_end_matches('.').to_lowercase();
+    let valid = !name.is_empty()
+        && !name.starts_with(['-', '.'])
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | '_'));
+    if !valid || name == "localhost" {
+        return None;
+    }
+    match domain {
+        Some(domain) if !name.contains('.') => Some(format!("{}.{}", name, domain.trim_matches('.'))),
+        _ => Some(name),
+    }
+}
+
+/// Addresses worth putting in a certificate
+fn usable(address: &IpAddr) -> bool {
+    !(address.is_loopback() || address.is_unspecified() || address.is_multicast())
+}
+
+/// Hosts of hosts-file lines; comments, loopback entries and bad lines are skipped
+fn parse_hosts<'a>(lines: impl IntoIterator<Item = &'a str>, domain: Option<&str>) -> Vec<Host> {
+    let mut hosts = Vec::new();
+    for line in lines {
+        let line = line.split('#').next().unwrap_or_default();
+        let mut fields = line.split_whitespace();
+        let Some(address) = fields.next().and_then(|a| a.parse::<IpAddr>().ok()).filter(usable) else {
+            continue;
+        };
+        hosts.extend(fields.filter_map(|name| host_name(name, domain)).map(|name| Host { name, addresses: vec![address] }));
+    }
+    hosts
+}
+
+/// Read a secret (password, token) from a file
+fn read_secret(path: &Path) -> Result<String> {
+    Ok(read_to_string_bounded(path)?.trim().to_string())
+}
+
+/// Hosts file, e.g. /etc/hosts or a dnsmasq `addn-hosts` file
+struct HostsFile {
+    path: PathBuf,
+    domain: Option<String>,
+}
+
+impl InventorySource for HostsFile {
+    fn name(&self) -> String {
+        format!("file {}", self.path.display())
+    }
+
+    fn hosts(&self) -> Result<Vec<Host>> {
+        let text = read_to_string_bounded(&self.path)?;
+        Ok(parse_hosts(text.lines(), self.domain.as_deref()))
+    }
+}
+
+/// Pi-hole v6: `/api/config/dns/hosts` and `/api/dhcp/leases`
+struct Pihole {
+    http: HttpClient,
+    password_file: Option<PathBuf>,
+    domain: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct PiholeAuth {
+    session: PiholeSession,
+}
+
+#[derive(Deserialize)]
+struct PiholeSession {
+    #[serde(default)]
+    sid: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct PiholeHosts {
+    config: PiholeConfig,
+}
+
+#[derive(Deserialize)]
+struct PiholeConfig {
+    dns: PiholeDns,
+}
+
+#[derive(Deserialize)]
+struct PiholeDns {
+    #[serde(default)]
+    hosts: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct PiholeLeases {
+    #[serde(default)]
+    leases: Vec<PiholeLease>,
+}
+
+#[derive(Deserialize)]
+struct PiholeLease {
+    name: String,
+    ip: String,
+}
+
+impl InventorySource for Pihole {
+    fn name(&self) -> String {
+        format!("pihole {}", self.http.base)
+    }
+
+    fn hosts(&self) -> Result<Vec<Host>> {
+        // A session is only needed when the API has a password; it is closed again afterwards
+        let sid = match &self.password_file {
+            Some(path) => {
+                let body = serde_json::json!({ "password": read_secret(path)? }).to_string();
+                let auth: PiholeAuth = self.http.json("POST", "/api/auth", &[], Some(body.as_bytes()))?;
+                Some(auth.session.sid.ok_or_else(|| FluxError::InventoryError(format!("{}: login failed", self.http.base)))?)
+            }
+            None => None,
+        };
+        let headers: Vec<(&str, &str)> = sid.iter().map(|sid| ("X-FTL-SID", sid.as_str())).collect();
+
+        let result = self.fetch(&headers);
+        if sid.is_some() {
+            let _ = self.http.send("DELETE", "/api/auth", &headers, None);
+        }
+        result
+    }
+}
+
+impl Pihole {
+    /// Local DNS records and DHCP leases
+    fn fetch(&self, headers: &[(&str, &str)]) -> Result<Vec<Host>> {
+        let dns: PiholeHosts = self.http.json("GET", "/api/config/dns/hosts", headers, None)?;
+        let leases: PiholeLeases = self.http.json("GET", "/api/dhcp/leases", headers, None)?;
+
+        let domain = self.domain.as_deref();
+        let mut hosts = parse_hosts(dns.config.dns.hosts.iter().map(String::as_str), domain);
+        for lease in leases.leases {
+            if let (Some(name), Ok(address)) = (host_name(&lease.name, domain), lease.ip.parse()) {
+                hosts.push(Host { name, addresses: vec![address] });
+            }
+        }
+        Ok(hosts)
+    }
+}
+
+/// UniFi Network application integration API (`X-API-KEY`)
+struct Unifi {
+    http: HttpClient,
+    api_key_file: PathBuf,
+    site: Option<String>,
+    domain: Option<String>,
+}
+
+/// A page of a UniFi list
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", bound(deserialize = "T: Deserialize<'de>"))]
+struct UnifiPage<T> {
+    #[serde(default)]
+    data: Vec<T>,
+    #[serde(default)]
+    total_count: usize,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UnifiSite {
+    id: String,
+    #[serde(default)]
+    internal_reference: String,
+    #[serde(default)]
+    name: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UnifiClient {
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    ip_address: Option<String>,
+}
+
+impl Unifi {
+    /// Every item of a paginated list
+    fn list<T: DeserializeOwned>(&self, path: &str, api_key: &str) -> Result<Vec<T>> {
+        const PAGE: usize = 200;
+        let mut items = Vec::new();
+        for _ in 0..MAX_PAGES {
+            let url = format!("{}?offset={}&limit={}", path, items.len(), PAGE);
+            let page: UnifiPage<T> = self.http.json("GET", &url, &[("X-API-KEY", api_key)], None)?;
+            let done = page.data.len() < PAGE || items.len() + page.data.len() >= page.total_count;
+            items.extend(page.data);
+            if done {
+                break;
+            }
+        }
+        Ok(items)
+    }
+}
+
+impl InventorySource for Unifi {
+    fn name(&self) -> String {
+        format!("unifi {}", self.http.base)
+    }
+
+    fn hosts(&self) -> Result<Vec<Host>> {
+        let api_key = read_secret(&self.api_key_file)?;
+        let sites: Vec<UnifiSite> = self.list("/v1/sites", &api_key)?;
+        let sites: Vec<UnifiSite> = match &self.site {
+            Some(wanted) => sites
+                .into_iter()
+                .filter(|site| site.internal_reference.eq_ignore_ascii_case(wanted) || site.name.eq_ignore_ascii_case(wanted))
+                .collect(),
+            None => sites,
+        };
+        if let (Some(wanted), true) = (&self.site, sites.is_empty()) {
+            return Err(FluxError::InventoryError(format!("{}: no site '{}'", self.http.base, wanted)));
+        }
+
+        let mut hosts = Vec::new();
+        for site in sites {
+            let clients: Vec<UnifiClient> = self.list(&format!("/v1/sites/{}/clients", site.id), &api_key)?;
+            for client in clients {
+                let address = client.ip_address.and_then(|ip| ip.parse().ok());
+                if let (Some(name), Some(address)) = (host_name(&client.name, self.domain.as_deref()), address) {
+                    hosts.push(Host { name, addresses: vec![address] });
+                }
+            }
+        }
+        Ok(hosts)
+    }
+}
+
+/// NetBox `/api/ipam/ip-addresses/` (`Authorization: Token`)
+struct Netbox {
+    http: HttpClient,
+    token_file: PathBuf,
+    domain: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct NetboxPage {
+    #[serde(default)]
+    results: Vec<NetboxAddress>,
+    #[serde(default)]
+    next: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct NetboxAddress {
+    /// Address with prefix length, e.g. `10.0.0.5/24`
+    address: String,
+    #[serde(default)]
+    dns_name: String,
+}
+
+impl InventorySource for Netbox {
+    fn name(&self) -> String {
+        format!("netbox {}", self.http.base)
+    }
+
+    fn hosts(&self) -> Result<Vec<Host>> {
+        let authorization = format!("Token {}", read_secret(&self.token_file)?);
+        let headers = [("Authorization", authorization.as_str()), ("Accept", "application/json")];
+
+        let mut hosts = Vec::new();
+        let mut next = Some(format!("{}/api/ipam/ip-addresses/?dns_name__empty=false&limit=1000", self.http.base));
+        for _ in 0..MAX_PAGES {
+            let Some(url) = next.take() else { break };
+            let page: NetboxPage = self.http.json_at("GET", &url, &headers, None)?;
+            for entry in page.results {
+                let address = entry.address.split('/').next().and_then(|a| a.parse().ok()).filter(usable);
+                if let (Some(name), Some(address)) = (host_name(&entry.dns_name, self.domain.as_deref()), address) {
+                    hosts.push(Host { name, addresses: vec![address] });
+                }
+            }
+            next = page.next;
+        }
+        Ok(hosts)
+    }
+}
+
+/// Minimal JSON-over-HTTP client for the inventory APIs
+///
+/// Requests are HTTP/1.0, so responses are never chunked and end when the
+/// server closes the connection. HTTPS trusts the system roots and this CA,
+/// which often issued the appliance's own certificate.
+struct HttpClient {
+    /// URL without a trailing slash
+    base: String,
+    connector: Option<SslConnector>,
+}
+
+impl HttpClient {
+    fn new(config: &Config, url: &str) -> Self {
+        let connector = SslConnector::builder(SslMethod::tls_client()).ok().map(|mut builder| {
+            if let Ok(certs) = chain_pem(config).and_then(|pem| certs_from_pem(&pem)) {
+                for cert in certs {
+                    let _ = builder.cert_store_mut().add_cert(cert);
+                }
+            }
+            builder.build()
+        });
+        Self { base: url.trim_end_matches('/').to_string(), connector }
+    }
+
+    /// Request `path` under the base URL and parse the JSON response
+    fn json<T: DeserializeOwned>(&self, method: &str, path: &str, headers: &[(&str, &str)], body: Option<&[u8]>) -> Result<T> {
+        self.json_at(method, &format!("{}{}", self.base, path), headers, body)
+    }
+
+    /// Request an absolute URL and parse the JSON response
+    fn json_at<T: DeserializeOwned>(&self, method: &str, url: &str, headers: &[(&str, &str)], body: Option<&[u8]>) -> Result<T> {
+        let body = self.send_to(method, url, headers, body)?;
+        serde_json::from_slice(&body).map_err(|e| FluxError::InventoryError(format!("{}: invalid response: {}", url, e)))
+    }
+
+    fn send(&self, method: &str, path: &str, headers: &[(&str, &str)], body: Option<&[u8]>) -> Result<Vec<u8>> {
+        self.send_to(method, &format!("{}{}", self.base, path), headers, body)
+    }
+
+    /// Body of a 2xx response
+    fn send_to(&self, method: &str, url: &str, headers: &[(&str, &str)], body: Option<&[u8]>) -> Result<Vec<u8>> {
+        let target = parse_url(url, "inventory.url")?;
+        let failed = |why: String| FluxError::InventoryError(format!("{}: {}", target.authority, why));
+        let body = body.unwrap_or_default();
+
+        let mut head = format!(
+            "{} {} HTTP/1.0\r\nHost: {}\r\nUser-Agent: flux-ssl-mgr/{}\r\nContent-Length: {}\r\n",
+            method,
+            target.path,
+            target.authority,
+            env!("CARGO_PKG_VERSION"),
+            body.len()
+        );
+        if !body.is_empty() {
+            head.push_str("Content-Type: application/json\r\n");
+        }
+        for (name, value) in headers {
+            if value.contains(['\r', '\n']) {
+                return Err(failed(format!("invalid {} header", name)));
+            }
+            let _ = write!(head, "{}: {}\r\n", name, value);
+        }
+        head.push_str("\r\n");
+
+        let stream = TcpStream::connect((target.host, target.port)).map_err(|e| failed(e.to_string()))?;
+        stream.set_read_timeout(Some(TIMEOUT)).map_err(|e| failed(e.to_string()))?;
+        stream.set_write_timeout(Some(TIMEOUT)).map_err(|e| failed(e.to_string()))?;
+        let response = if target.tls {
+            let connector = self.connector.as_ref().ok_or_else(|| failed("TLS is unavailable".to_string()))?;
+            let mut stream = connector.connect(target.host, stream).map_err(|e| failed(format!("TLS: {}", e)))?;
+            exchange(&mut stream, head.as_bytes(), body)
+        } else {
+            let mut stream = stream;
+            exchange(&mut stream, head.as_bytes(), body)
+        }
+        .map_err(|e| failed(e.to_string()))?;
+
+        let split = response.windows(4).position(|w| w == b"\r\n\r\n").ok_or_else(|| failed("incomplete response".to_string()))?;
+        let status_line = String::from_utf8_lossy(&response[..split]).lines().next().unwrap_or_default().to_string();
+        match status_line.split_whitespace().nth(1).and_then(|s| s.parse::<u16>().ok()) {
+            Some(200..=299) => Ok(response[split + 4..].to_vec()),
+            _ => Err(failed(format!("{} {} answered '{}'", method, target.path, status_line))),
+        }
+    }
+}
+
+/// Write the request and read the whole response
+fn exchange<S: Read + Write>(stream: &mut S, head: &[u8], body: &[u8]) -> std::io::Result<Vec<u8>> {
+    stream.write_all(head)?;
+    stream.write_all(body)?;
+    stream.flush()?;
+
+    let mut response = Vec::new();
+    // TLS servers often close without a close_notify; keep what arrived
+    if let Err(e) = stream.take(MAX_RESPONSE + 1).read_to_end(&mut response) {
+        if response.is_empty() {
+            return Err(e);
+        }
+    }
+    if response.len() as u64 > MAX_RESPONSE {
+        return Err(std::io::Error::other(format!("response larger than {} bytes", MAX_RESPONSE)));
+    }
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufRead;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_hosts_and_suggestions() {
+        let hosts = parse_hosts(
+            [
+                "127.0.0.1 localhost",
+                "10.0.0.5  nas01.lab nas01   # storage",
+                "fd00::5   nas01.lab",
+                "10.0.0.20 printer",
+                "not-an-address router",
+                "10.0.0.9  bad!name",
+            ],
+            Some("lab"),
+        );
+        let mut inventory = Inventory::default();
+        inventory.extend(hosts);
+        inventory.extend([Host { name: "router.lab".to_string(), addresses: vec!["10.0.0.1".parse().unwrap()] }]);
+
+        assert_eq!(inventory.suggest("nas", 10), ["DNS:nas01.lab", "IP:10.0.0.5", "IP:fd00::5"]);
+        assert_eq!(inventory.suggest("ip:10.0.0", 10), ["IP:10.0.0.1", "IP:10.0.0.20", "IP:10.0.0.5"]);
+        // Prefix matches come before substring matches
+        assert_eq!(inventory.suggest("r", 10)[..3], ["DNS:router.lab", "IP:10.0.0.1", "DNS:printer.lab"]);
+        assert_eq!(inventory.suggest("ter", 10), ["DNS:printer.lab", "DNS:router.lab", "IP:10.0.0.1", "IP:10.0.0.20"]);
+        assert_eq!(inventory.suggest("", 2).len(), 2);
+
+        assert_eq!(inventory.sans_for("NAS01"), [
+            SanEntry::Dns("nas01.lab".to_string()),
+            SanEntry::Ip("10.0.0.5".to_string()),
+            SanEntry::Ip("fd00::5".to_string()),
+        ]);
+        assert!(inventory.sans_for("nas02.lab").is_empty());
+    }
+
+    #[test]
+    fn test_netbox_source() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let token_file = temp_dir.path().join("token");
+        std::fs::write(&token_file, "nb-token\n").unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let pages = [
+            serde_json::json!({
+                "results": [
+                    {"address": "10.0.0.5/24", "dns_name": "nas01.lab"},
+                    {"address": "10.0.0.6/24", "dns_name": ""},
+                ],
+                "next": format!("{}/api/ipam/ip-addresses/?offset=2", base),
+            }),
+            serde_json::json!({"results": [{"address": "fd00::7/64", "dns_name": "cam01"}], "next": null}),
+        ];
+        let server = std::thread::spawn(move || {
+            let mut requests = Vec::new();
+            for page in pages {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = std::io::BufReader::new(stream);
+                let mut head = String::new();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    head.push_str(&line);
+                    if line.trim_end().is_empty() {
+                        break;
+                    }
+                }
+                requests.push(head);
+                let body = page.to_string();
+                write!(reader.get_mut(), "HTTP/1.0 200 OK\r\nContent-Type: application/json\r\n\r\n{}", body).unwrap();
+            }
+            requests
+        });
+
+        let config = Config {
+            inventory: vec![InventoryConfig {
+                source: SourceKind::Netbox { url: base, token_file },
+                domain: Some("lab".to_string()),
+            }],
+            ..Config::default()
+        };
+        let (inventory, errors) = Inventory::load(&config);
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(inventory.suggest("", 10), ["DNS:cam01.lab", "DNS:nas01.lab", "IP:10.0.0.5", "IP:fd00::7"]);
+
+        let requests = server.join().unwrap();
+        assert!(requests[0].starts_with("GET /api/ipam/ip-addresses/?dns_name__empty=false&limit=1000 HTTP/1.0"));
+        assert!(requests[0].contains("Authorization: Token nb-token\r\n"));
+        assert!(requests[1].starts_with("GET /api/ipam/ip-addresses/?offset=2 "));
+    }
+}
@@ -0,0 +1,322 @@
+//! Publishing certificates to an LDAP / Active Directory directory
+//!
+//! With `[ldap]` configured, issued certificates are written to the
+//! `userCertificate` attribute of their entry (`entry_dn`), and the CA
+//! certificate and CRL to `cACertificate` and `certificateRevocationList` of
+//! `ca_dn`. Windows clients in a Samba AD domain pick up trust and
+//! certificates from there.
+//!
+//! Changes are LDIF piped to the system `ldapmodify`, in the same way
+//! deployments use `scp` and `kubectl`. With `auto_publish`, issuing and
+//! revoking update the directory as they happen; `flux-ssl-mgr ldap publish`
+//! does it by hand.
+
+use crate::config::{Config, LdapConfig};
+use crate::crypto::{certs_from_pem, load_cert};
+use crate::error::{FluxError, Result};
+use crate::output::OutputFormatter;
+use openssl::base64;
+use openssl::x509::{X509Crl, X509};
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// The `[ldap]` section, or an error saying it is missing
+pub fn settings(config: &Config) -> Result<&LdapConfig> {
+    config.ldap.as_ref()
+        .ok_or_else(|| FluxError::MissingConfig("[ldap] (directory publishing is not configured)".to_string()))
+}
+
+/// Entry DN for a certificate, from the `entry_dn` template
+///
+/// The name comes from the certificate's CN, which web clients choose, so it
+/// is escaped as an attribute value and can't add RDNs of its own.
+pub fn entry_dn(template: &str, cert_name: &str) -> String {
+    let host = cert_name.split('.').next().unwrap_or(cert_name);
+    template
+        .replace("{name}", &escape_dn_value(cert_name))
+        .replace("{host}", &escape_dn_value(host))
+}
+
+/// Escape a DN attribute value (RFC 4514, section 2.4)
+fn escape_dn_value(value: &str) -> String {
+    let last = value.chars().count().saturating_sub(1);
+    let mut escaped = String::with_capacity(value.len());
+    for (i, c) in value.chars().enumerate() {
+        match c {
+            ',' | '+' | '"' | '\\' | '<' | '>' | ';' | '=' => escaped.push('\\'),
+            '#' if i == 0 => escaped.push('\\'),
+            ' ' if i == 0 || i == last => escaped.push('\\'),
+            '\0' => {
+                escaped.push_str("\\00");
+                continue;
+            }
+            _ => {}
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Attribute name, with `;binary` where the directory wants it
+fn attribute(ldap: &LdapConfig, name: &str) -> String {
+    if ldap.binary {
+        format!("{};binary", name)
+    } else {
+        name.to_string()
+    }
+}
+
+/// `dn:` line, base64-encoded when it isn't a safe LDIF string
+fn dn_line(dn: &str) -> String {
+    let safe = !dn.starts_with([' ', ':', '<'])
+        && !dn.ends_with(' ')
+        && dn.bytes().all(|b| (0x20..0x7f).contains(&b));
+    if safe {
+        format!("dn: {}\n", dn)
+    } else {
+        format!("dn:: {}\n", base64::encode_block(dn.as_bytes()))
+    }
+}
+
+fn value_line(attribute: &str, der: &[u8]) -> String {
+    format!("{}:: {}\n", attribute, base64::encode_block(der))
+}
+
+/// LDIF adding `cert` to an entry (or replacing its certificates)
+pub fn publish_ldif(ldap: &LdapConfig, dn: &str, cert: &X509) -> Result<String> {
+    let attr = attribute(ldap, "userCertificate");
+    let op = if ldap.replace { "replace" } else { "add" };
+    Ok(format!(
+        "{}changetype: modify\n{}: {}\n{}-\n",
+        dn_line(dn),
+        op,
+        attr,
+        value_line(&attr, &cert.to_der()?)
+    ))
+}
+
+/// LDIF removing `cert` from an entry
+pub fn unpublish_ldif(ldap: &LdapConfig, dn: &str, cert: &X509) -> Result<String> {
+    let attr = attribute(ldap, "userCertificate");
+    Ok(format!(
+        "{}changetype: modify\ndelete: {}\n{}-\n",
+        dn_line(dn),
+        attr,
+        value_line(&attr, &cert.to_der()?)
+    ))
+}
+
+/// LDIF replacing the CA certificates and, if given, the CRL of the CA entry
+pub fn ca_ldif(ldap: &LdapConfig, dn: &str, ca_certs: &[X509], crl: Option<&X509Crl>) -> Result<String> {
+    let cert_attr = attribute(ldap, "cACertificate");
+    let mut ldif = format!("{}changetype: modify\nreplace: {}\n", dn_line(dn), cert_attr);
+    for cert in ca_certs {
+        ldif.push_str(&value_line(&cert_attr, &cert.to_der()?));
+    }
+    ldif.push_str("-\n");
+    if let Some(crl) = crl {
+        let crl_attr = attribute(ldap, "certificateRevocationList");
+        ldif.push_str(&format!("replace: {}\n{}-\n", crl_attr, value_line(&crl_attr, &crl.to_der()?)));
+    }
+    Ok(ldif)
+}
+
+/// `ldapmodify` reading LDIF from stdin
+pub fn ldapmodify_command(ldap: &LdapConfig) -> Command {
+    let mut cmd = Command::new("ldapmodify");
+    cmd.arg("-H").arg(&ldap.uri);
+    match &ldap.sasl_mech {
+        Some(mech) => {
+            cmd.args(["-Q", "-Y", mech]);
+        }
+        None => {
+            cmd.arg("-x");
+            if let Some(bind_dn) = &ldap.bind_dn {
+                cmd.arg("-D").arg(bind_dn);
+            }
+            if let Some(password_file) = &ldap.password_file {
+                cmd.arg("-y").arg(password_file);
+            }
+        }
+    }
+    cmd
+}
+
+/// Apply LDIF changes with `ldapmodify`
+pub fn apply(ldap: &LdapConfig, ldif: &str) -> Result<()> {
+    let mut child = ldapmodify_command(ldap)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| FluxError::LdapError(format!("failed to run ldapmodify: {}", e)))?;
+
+    // Report ldapmodify's own error rather than a broken pipe if it exits early
+    let written = child.stdin.take().map_or(Ok(()), |mut stdin| stdin.write_all(ldif.as_bytes()));
+    let out = child.wait_with_output()
+        .map_err(|e| FluxError::LdapError(format!("failed to run ldapmodify: {}", e)))?;
+    if !out.status.success() {
+        return Err(FluxError::LdapError(format!(
+            "ldapmodify exited with {}: {}",
+            out.status,
+            String::from_utf8_lossy(&out.stderr).trim()
+        )));
+    }
+    written?;
+    Ok(())
+}
+
+/// Publish a certificate to its entry, returning the entry DN
+pub fn publish_cert(config: &Config, cert_name: &str, cert: &X509) -> Result<String> {
+    let ldap = settings(config)?;
+    let dn = entry_dn(&ldap.entry_dn, cert_name);
+    apply(ldap, &publish_ldif(ldap, &dn, cert)?)?;
+    Ok(dn)
+}
+
+/// Remove a certificate from its entry, returning the entry DN
+pub fn unpublish_cert(config: &Config, cert_name: &str, cert: &X509) -> Result<String> {
+    let ldap = settings(config)?;
+    let dn = entry_dn(&ldap.entry_dn, cert_name);
+    apply(ldap, &unpublish_ldif(ldap, &dn, cert)?)?;
+    Ok(dn)
+}
+
+/// LDIF for the CA entry: the intermediate (and root, if present) and the
+/// configured CRL
+pub fn ca_changes(config: &Config) -> Result<(String, String)> {
+    let ldap = settings(config)?;
+    let dn = ldap.ca_dn.clone()
+        .ok_or_else(|| FluxError::MissingConfig("ldap.ca_dn".to_string()))?;
+
+    let mut certs = vec![load_cert(&config.ca_cert_path)?];
+    let root = config.root_ca_cert_path();
+    if root.exists() {
+        certs.push(load_cert(&root)?);
+    }
+    let crl = match &config.revocation.crl_path {
+        Some(path) => {
+            let data = std::fs::read(path)
+                .map_err(|e| FluxError::FileReadFailed(path.clone(), e.to_string()))?;
+            Some(X509Crl::from_pem(&data).or_else(|_| X509Crl::from_der(&data))?)
+        }
+        None => None,
+    };
+
+    let ldif = ca_ldif(ldap, &dn, &certs, crl.as_ref())?;
+    Ok((dn, ldif))
+}
+
+/// Publish the CA certificates and CRL, returning the CA entry DN
+pub fn publish_ca(config: &Config) -> Result<String> {
+    let (dn, ldif) = ca_changes(config)?;
+    apply(settings(config)?, &ldif)?;
+    Ok(dn)
+}
+
+/// Whether certificates are published as they are issued and revoked
+pub fn auto_publish(config: &Config) -> bool {
+    config.ldap.as_ref().is_some_and(|l| l.auto_publish)
+}
+
+/// Publish a newly issued certificate when `auto_publish` is on
+///
+/// Failures are warnings: the certificate is issued either way.
+pub fn on_issued(config: &Config, cert_name: &str, cert: &X509, output: &OutputFormatter) {
+    if !auto_publish(config) {
+        return;
+    }
+    match publish_cert(config, cert_name, cert) {
+        Ok(dn) => output.verbose(&format!("Published {} to {}", cert_name, dn)),
+        Err(e) => output.warning(&format!("Failed to publish {} to the directory: {}", cert_name, e)),
+    }
+}
+
+/// Remove a revoked certificate when `auto_publish` is on
+pub fn on_revoked(config: &Config, cert_name: &str, cert_pem: &[u8], output: &OutputFormatter) {
+    if !auto_publish(config) {
+        return;
+    }
+    let result = certs_from_pem(cert_pem)
+        .and_then(|certs| certs.into_iter().next().ok_or_else(|| FluxError::LdapError("no certificate".to_string())))
+        .and_then(|cert| unpublish_cert(config, cert_name, &cert));
+    match result {
+        Ok(dn) => output.verbose(&format!("Removed {} from {}", cert_name, dn)),
+        Err(e) => output.warning(&format!("Failed to remove {} from the directory: {}", cert_name, e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ca::CertSigner;
+    use crate::fixtures;
+
+    fn ldap() -> LdapConfig {
+        LdapConfig {
+            uri: "ldaps://dc1.lab.example".to_string(),
+            bind_dn: Some("CN=flux,CN=Users,DC=lab,DC=example".to_string()),
+            password_file: Some("/root/.ldap-pass".into()),
+            sasl_mech: None,
+            entry_dn: "CN={host},CN=Computers,DC=lab,DC=example".to_string(),
+            ca_dn: Some("CN=Flux,CN=Certification Authorities,CN=Public Key Services,CN=Services,CN=Configuration,DC=lab,DC=example".to_string()),
+            binary: false,
+            replace: false,
+            auto_publish: false,
+        }
+    }
+
+    #[test]
+    fn test_publish_ldif() {
+        let cert = fixtures::intermediate_ca().sign(&fixtures::leaf_csr("nas01.lab", &[]), 30).unwrap();
+        let dn = entry_dn(&ldap().entry_dn, "nas01.lab");
+        assert_eq!(dn, "CN=nas01,CN=Computers,DC=lab,DC=example");
+
+        let ldif = publish_ldif(&ldap(), &dn, &cert).unwrap();
+        let value = base64::encode_block(&cert.to_der().unwrap());
+        assert_eq!(ldif, format!("dn: {}\nchangetype: modify\nadd: userCertificate\nuserCertificate:: {}\n-\n", dn, value));
+
+        let openldap = LdapConfig { binary: true, replace: true, ..ldap() };
+        assert!(publish_ldif(&openldap, &dn, &cert).unwrap().contains("replace: userCertificate;binary\nuserCertificate;binary:: "));
+        assert!(unpublish_ldif(&ldap(), &dn, &cert).unwrap().contains("delete: userCertificate\n"));
+        assert!(dn_line("CN=Zoë,DC=lab").starts_with("dn:: "));
+    }
+
+    #[test]
+    fn test_entry_dn_escapes_the_name() {
+        let template = "CN={name},CN=Computers,DC=lab,DC=example";
+        assert_eq!(
+            entry_dn(template, "x,CN=Administrator,CN=Users,DC=lab"),
+            "CN=x\\,CN\\=Administrator\\,CN\\=Users\\,DC\\=lab,CN=Computers,DC=lab,DC=example"
+        );
+        assert_eq!(
+            entry_dn(&ldap().entry_dn, "x,CN=Administrator.lab"),
+            "CN=x\\,CN\\=Administrator,CN=Computers,DC=lab,DC=example"
+        );
+        assert_eq!(escape_dn_value("#a+b "), "\\#a\\+b\\ ");
+        assert_eq!(escape_dn_value(" a\"b\\<c>;"), "\\ a\\\"b\\\\\\<c\\>\\;");
+        assert_eq!(escape_dn_value("nas01.lab"), "nas01.lab");
+    }
+
+    #[test]
+    fn test_ca_ldif() {
+        let certs = [fixtures::intermediate_ca().cert().clone(), fixtures::root_ca_cert()];
+        let ldif = ca_ldif(&ldap(), "CN=Flux,DC=lab", &certs, None).unwrap();
+        assert_eq!(ldif.matches("cACertificate:: ").count(), 2);
+        assert!(ldif.starts_with("dn: CN=Flux,DC=lab\nchangetype: modify\nreplace: cACertificate\n"));
+        assert!(!ldif.contains("certificateRevocationList"));
+    }
+
+    #[test]
+    fn test_ldapmodify_command() {
+        let args = |ldap: &LdapConfig| -> Vec<String> {
+            ldapmodify_command(ldap).get_args().map(|a| a.to_string_lossy().into_owned()).collect()
+        };
+        assert_eq!(
+            args(&ldap()),
+            ["-H", "ldaps://dc1.lab.example", "-x", "-D", "CN=flux,CN=Users,DC=lab,DC=example", "-y", "/root/.ldap-pass"]
+        );
+        let kerberos = LdapConfig { sasl_mech: Some("GSSAPI".to_string()), ..ldap() };
+        assert_eq!(args(&kerberos), ["-H", "ldaps://dc1.lab.example", "-Q", "-Y", "GSSAPI"]);
+    }
+}
@@ -2,13 +2,51 @@
 //!
 //! A powerful, secure certificate management tool for homestead/homelab internal PKI environments.
 
+pub mod apikey;
 pub mod config;
 pub mod error;
+pub mod duration;
 pub mod crypto;
 pub mod ca;
 pub mod batch;
+pub mod check;
+#[cfg(feature = "daemon")]
+pub mod daemon;
+#[cfg(feature = "deploy")]
+pub mod deploy;
+pub mod doctor;
+pub mod enroll;
+pub mod fsutil;
+pub mod health;
+pub mod history;
+pub mod import;
+pub mod install;
+pub mod inventory;
+#[cfg(feature = "interactive")]
 pub mod interactive;
+pub mod k8s;
+pub mod ldap;
+pub mod logging;
 pub mod output;
+pub mod passwords;
+pub mod policy;
+pub mod remote;
+pub mod rollback;
+pub mod scan;
+pub mod smoke;
+pub mod store;
+pub mod telemetry;
+pub mod truststore;
+pub mod verify;
+pub mod manifest;
+pub mod metrics;
+pub mod notify;
+pub mod watch;
+pub mod window;
+pub mod workspace;
+
+#[cfg(test)]
+pub(crate) mod fixtures;
 
 #[cfg(feature = "web")]
 pub mod web;
@@ -16,4 +54,4 @@ pub mod web;
 pub use config::Config;
 pub use error::{FluxError, Result};
 pub use ca::IntermediateCA;
-pub use output::OutputFormatter;
+pub use output::{MessageKind, OutputFormatter, OutputSink};
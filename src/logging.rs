@@ -0,0 +1,91 @@
+//! Log verbosity shared by the CLI and the web service
+
+use tracing::Level;
+
+/// How much to log, from the `-q` / `-v` / `-vv` flags
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// Errors only (`-q`)
+    Quiet,
+    /// `RUST_LOG` if set, otherwise info
+    #[default]
+    Normal,
+    /// Debug logging (`-v`)
+    Debug,
+    /// Trace logging (`-vv`)
+    Trace,
+}
+
+impl Verbosity {
+    /// Verbosity from the number of `-v` flags and the quiet flag (quiet wins)
+    pub fn from_flags(verbose: u8, quiet: bool) -> Self {
+        match (quiet, verbose) {
+            (true, _) => Verbosity::Quiet,
+            (false, 0) => Verbosity::Normal,
+            (false, 1) => Verbosity::Debug,
+            (false, _) => Verbosity::Trace,
+        }
+    }
+
+    /// Filter directive for the tracing subscriber
+    ///
+    /// Explicit flags take precedence over `RUST_LOG`.
+    pub fn filter(self, rust_log: Option<&str>) -> String {
+        match self {
+            Verbosity::Quiet => "error".to_string(),
+            Verbosity::Normal => rust_log
+                .filter(|s| !s.trim().is_empty())
+                .unwrap_or("info")
+                .to_string(),
+            Verbosity::Debug => "debug".to_string(),
+            Verbosity::Trace => "trace".to_string(),
+        }
+    }
+
+    /// Level for per-request HTTP logs (visible from `-v` up)
+    pub fn http_level(self) -> Level {
+        if self >= Verbosity::Debug {
+            Level::INFO
+        } else {
+            Level::DEBUG
+        }
+    }
+
+    /// Whether to log request headers
+    pub fn http_headers(self) -> bool {
+        self == Verbosity::Trace
+    }
+}
+
+/// Install the global tracing subscriber
+#[cfg(feature = "cli")]
+pub fn init(verbosity: Verbosity) {
+    let rust_log = std::env::var("RUST_LOG").ok();
+
+    tracing_subscriber::fmt()
+        .with_env_filter(verbosity.filter(rust_log.as_deref()))
+        .init();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_flags() {
+        assert_eq!(Verbosity::from_flags(0, false), Verbosity::Normal);
+        assert_eq!(Verbosity::from_flags(1, false), Verbosity::Debug);
+        assert_eq!(Verbosity::from_flags(2, false), Verbosity::Trace);
+        assert_eq!(Verbosity::from_flags(5, false), Verbosity::Trace);
+        assert_eq!(Verbosity::from_flags(2, true), Verbosity::Quiet);
+    }
+
+    #[test]
+    fn test_filter_honors_rust_log() {
+        assert_eq!(Verbosity::Normal.filter(None), "info");
+        assert_eq!(Verbosity::Normal.filter(Some("flux_ssl_mgr=debug")), "flux_ssl_mgr=debug");
+        assert_eq!(Verbosity::Normal.filter(Some("")), "info");
+        assert_eq!(Verbosity::Quiet.filter(Some("trace")), "error");
+        assert_eq!(Verbosity::Trace.filter(Some("warn")), "trace");
+    }
+}
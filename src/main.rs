@@ -1,11 +1,26 @@
 //! Flux SSL Manager - CLI Entry Point
 
-use clap::{Parser, Subcommand};
-use flux_ssl_mgr::{Config, IntermediateCA, OutputFormatter, Result, FluxError};
-use flux_ssl_mgr::crypto::SanEntry;
-use flux_ssl_mgr::batch;
+use clap::{ArgAction, ArgMatches, Args, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use clap::error::ErrorKind;
+use flux_ssl_mgr::{Config, OutputFormatter, Result, FluxError};
+use flux_ssl_mgr::config::CommandKind;
+use flux_ssl_mgr::ca::load_signer;
+use flux_ssl_mgr::crypto::{SanEntry, load_cert};
+use flux_ssl_mgr::deploy;
+use flux_ssl_mgr::duration::{Days, Validity};
+use flux_ssl_mgr::batch::{self, PasswordMode};
+use flux_ssl_mgr::crypto::key::{prompt_password, prompt_password_with_confirmation};
+use flux_ssl_mgr::passwords::PasswordFile;
+use flux_ssl_mgr::inventory::Inventory;
+use flux_ssl_mgr::store::{update_store, CertStore, IssuedCert};
+use flux_ssl_mgr::telemetry::{Counters, Summary};
+use secrecy::{ExposeSecret, Secret};
 use flux_ssl_mgr::interactive;
+use flux_ssl_mgr::k8s;
+use flux_ssl_mgr::logging::{self, Verbosity};
+use flux_ssl_mgr::manifest::{self, Manifest};
 use std::path::PathBuf;
+use std::time::Instant;
 
 #[derive(Parser)]
 #[command(name = "flux-ssl-mgr")]
@@ -15,75 +30,252 @@ struct Cli {
     #[arg(short, long, global = true)]
     config: Option<PathBuf>,
 
-    /// Enable verbose output
-    #[arg(short, long, global = true)]
-    verbose: bool,
+    /// Verbose output (-v debug, -vv trace)
+    #[arg(short, long, global = true, action = ArgAction::Count)]
+    verbose: u8,
 
     /// Quiet mode (suppress non-error output)
     #[arg(short, long, global = true)]
     quiet: bool,
 
+    /// End with a one-line JSON summary (command, duration, counts, result) on stderr
+    #[arg(long, global = true)]
+    summary_json: bool,
+
+    /// Container mode: take config from FLUX_SSL_* variables, apply --manifest and exit
+    #[arg(long, conflicts_with = "config")]
+    oneshot: bool,
+
+    /// Manifest applied by --oneshot
+    #[arg(long, env = "FLUX_SSL_MANIFEST", default_value = "/etc/flux-ssl-mgr/manifest.yaml")]
+    manifest: PathBuf,
+
     #[command(subcommand)]
-    command: Commands,
+    command: Option<Commands>,
+}
+
+/// Extra output written next to the PEM files
+#[derive(Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    /// PEM/CRT certificate and PEM key only
+    #[default]
+    Pem,
+    /// Also a kubernetes.io/tls Secret manifest (<name>.secret.yaml)
+    K8sSecret,
+    /// Also key, certificate and intermediate in one file for HAProxy (<name>.combined.pem)
+    Combined,
+}
+
+/// Options for the batch subcommand
+#[derive(Args)]
+struct BatchArgs {
+    /// Directory containing CSR files
+    #[arg(short, long)]
+    dir: Option<PathBuf>,
+
+    /// Process all CSRs without prompting
+    #[arg(short, long)]
+    all: bool,
+
+    /// Filter CSRs by name pattern
+    #[arg(short, long)]
+    filter: Option<String>,
+
+    /// Common SANs for all certificates (comma-separated)
+    #[arg(short, long, value_delimiter = ',')]
+    sans: Option<Vec<String>>,
+
+    /// Password-protect all private keys
+    #[arg(short, long)]
+    password: bool,
+
+    /// Use one password for all private keys (with --password)
+    #[arg(long, requires = "password")]
+    same_password: bool,
+
+    /// Protect each private key with a generated random password
+    #[arg(long, conflicts_with = "password")]
+    generate_passwords: bool,
+
+    /// Write generated passwords to this encrypted file instead of printing them
+    /// (required with --quiet)
+    #[arg(long, requires = "generate_passwords")]
+    password_file: Option<PathBuf>,
+
+    /// Skip names whose existing certificate has more than DAYS days left
+    #[arg(long, value_name = "DAYS", num_args = 0..=1, default_missing_value = "30")]
+    skip_valid: Option<Days>,
+
+    /// Start of validity, RFC 3339 (default: now minus defaults.backdate_minutes)
+    #[arg(long, value_name = "TIME")]
+    not_before: Option<chrono::DateTime<chrono::Utc>>,
+
+    /// Show what would be issued without touching the CA key or filesystem
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Additional output format
+    #[arg(long, value_enum, default_value_t)]
+    output_format: OutputFormat,
+
+    /// Keep running and sign CSRs as they are dropped into the directory
+    #[arg(long, conflicts_with_all = ["all", "sans", "password", "generate_passwords", "skip_valid", "dry_run", "output_format"])]
+    watch: bool,
+}
+
+/// Options for the single subcommand
+#[derive(Args)]
+struct SingleArgs {
+    /// Certificate name
+    #[arg(short, long)]
+    name: Option<String>,
+
+    /// Subject Alternative Names (comma-separated)
+    /// Example: DNS:example.com,IP:192.168.1.1
+    #[arg(short, long, value_delimiter = ',')]
+    sans: Option<Vec<String>>,
+
+    /// Password-protect the private key
+    #[arg(short, long)]
+    password: bool,
+
+    /// Certificate validity (days, or e.g. 13w, 1y, 12h)
+    #[arg(short, long, visible_alias = "validity")]
+    days: Option<Validity>,
+
+    /// Start of validity, RFC 3339 (default: now minus defaults.backdate_minutes)
+    #[arg(long, value_name = "TIME")]
+    not_before: Option<chrono::DateTime<chrono::Utc>>,
+
+    /// RSA key size in bits
+    #[arg(short, long)]
+    key_size: Option<u32>,
+
+    /// Cover both the name and its wildcard (name plus *.name as DNS SANs)
+    #[arg(long, requires = "name")]
+    wildcard_pair: bool,
+
+    /// Show what would be issued without touching the CA key or filesystem
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Additional output format
+    #[arg(long, value_enum, default_value_t)]
+    output_format: OutputFormat,
 }
 
 #[derive(Subcommand)]
 enum Commands {
     /// Generate a single certificate
-    Single {
-        /// Certificate name
-        #[arg(short, long)]
-        name: Option<String>,
-
-        /// Subject Alternative Names (comma-separated)
-        /// Example: DNS:example.com,IP:192.168.1.1
-        #[arg(short, long, value_delimiter = ',')]
-        sans: Option<Vec<String>>,
+    Single(SingleArgs),
 
-        /// Password-protect the private key
-        #[arg(short, long)]
-        password: bool,
+    /// Batch process CSR files
+    Batch(BatchArgs),
 
-        /// Certificate validity in days
-        #[arg(short, long)]
-        days: Option<u32>,
+    /// Show passwords stored in an encrypted password file
+    Passwords {
+        /// Password file written by `batch --password-file`
+        file: PathBuf,
 
-        /// RSA key size in bits
+        /// Only show the password for this certificate
         #[arg(short, long)]
-        key_size: Option<u32>,
+        name: Option<String>,
     },
 
-    /// Batch process CSR files
-    Batch {
-        /// Directory containing CSR files
+    /// Reconcile issued certificates with a manifest file
+    Apply {
+        /// Manifest file (YAML)
         #[arg(short, long)]
-        dir: Option<PathBuf>,
+        file: PathBuf,
 
-        /// Process all CSRs without prompting
-        #[arg(short, long)]
-        all: bool,
+        /// Show what would change without issuing or revoking anything
+        #[arg(long)]
+        dry_run: bool,
 
-        /// Filter CSRs by name pattern
+        /// Revoke certificates removed from the manifest without asking
         #[arg(short, long)]
-        filter: Option<String>,
+        yes: bool,
+    },
 
-        /// Common SANs for all certificates (comma-separated)
-        #[arg(short, long, value_delimiter = ',')]
-        sans: Option<Vec<String>>,
+    /// Push an issued certificate to deploy targets (SSH, Kubernetes or Docker)
+    Deploy {
+        /// Certificate name
+        name: String,
+
+        /// Deploy target from the config (repeatable)
+        #[arg(short, long, required = true)]
+        target: Vec<String>,
+    },
+
+    /// Restore the previous certificate and key from the output directory backups
+    Rollback {
+        /// Certificate name
+        name: String,
 
-        /// Password-protect all private keys
+        /// Redeploy to this target (repeatable; default: the daemon's targets for the name)
         #[arg(short, long)]
-        password: bool,
+        target: Vec<String>,
     },
 
     /// Show certificate information
     Info {
-        /// Certificate file path
-        cert: PathBuf,
+        /// Certificate file path (PEM, DER or PKCS#7)
+        #[arg(required_unless_present = "remote")]
+        cert: Option<PathBuf>,
+
+        /// Inspect the chain served on host:port instead of a file (port defaults to 443)
+        #[arg(long, value_name = "HOST:PORT", conflicts_with = "cert")]
+        remote: Option<String>,
+
+        /// Name sent as SNI (default: the remote host)
+        #[arg(long, conflicts_with = "cert")]
+        sni: Option<String>,
+
+        /// Upgrade a plaintext session first: smtp or imap
+        #[arg(long, conflicts_with = "cert")]
+        starttls: Option<flux_ssl_mgr::remote::StartTls>,
+
+        /// Print the details as JSON, one object per certificate in an array
+        #[arg(long)]
+        json: bool,
 
         /// Show full certificate details
-        #[arg(short, long)]
-        verbose: bool,
+        // Shares its id with the global -v, so it must be a counter too
+        #[arg(short, long, action = ArgAction::Count)]
+        verbose: u8,
+    },
+
+    /// Verify a certificate against the CA chain and revocation data
+    Verify {
+        /// Certificate file path (PEM, DER or PKCS#7; extra certificates are used as intermediates)
+        cert: PathBuf,
+
+        /// Additional intermediate certificates
+        #[arg(long, value_name = "FILE")]
+        chain: Option<PathBuf>,
+    },
+
+    /// Inspect certificate signing requests
+    Csr {
+        #[command(subcommand)]
+        command: CsrCommand,
+    },
+
+    /// Inspect private key files
+    Key {
+        #[command(subcommand)]
+        command: KeyCommand,
+    },
+
+    /// Check whether a private key belongs to a certificate
+    Match {
+        /// Certificate file path (PEM, DER or PKCS#7; the first certificate is used)
+        #[arg(long)]
+        cert: PathBuf,
+
+        /// Private key file path (PEM; prompts for the passphrase if encrypted)
+        #[arg(long)]
+        key: PathBuf,
     },
 
     /// Configuration management
@@ -99,6 +291,154 @@ enum Commands {
         /// Output path for configuration file
         #[arg(short, long)]
         output: Option<PathBuf>,
+
+        /// Reset permissions on existing output and working directories
+        #[arg(long)]
+        fix_perms: bool,
+
+        /// Check paths, the CA key and certificate, permissions and the output directory
+        #[arg(long)]
+        doctor: bool,
+    },
+
+    /// Run the CA signing agent (unlocks the CA key once and serves signing over a unix socket)
+    #[cfg(unix)]
+    Agent {
+        /// Socket path (defaults to agent.socket_path from config)
+        #[arg(short, long)]
+        socket: Option<PathBuf>,
+    },
+
+    /// Check certificate expiry with Nagios/Icinga exit codes (0 OK, 1 WARNING, 2 CRITICAL, 3 UNKNOWN)
+    Check {
+        /// Certificate file to check
+        #[arg(required_unless_present = "all", conflicts_with = "all")]
+        cert: Option<PathBuf>,
+
+        /// Check every current certificate in the store
+        #[arg(long)]
+        all: bool,
+
+        /// WARNING below this many days (default: check.warning_days)
+        #[arg(long)]
+        warning: Option<Days>,
+
+        /// CRITICAL below this many days (default: check.critical_days)
+        #[arg(long)]
+        critical: Option<Days>,
+    },
+
+    /// Send expiry notifications (email, MQTT, webhooks) that are due
+    Notify {
+        /// Print the digest instead of sending it
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Show issued certificates and deployments waiting for a maintenance window
+    Status,
+
+    /// Add certificates issued with `openssl ca` to the certificate store
+    Import {
+        /// OpenSSL CA directory (holding index.txt, newcerts/ and certs/, or intermediate/)
+        #[arg(long, value_name = "DIR")]
+        openssl_ca: PathBuf,
+
+        /// Show what would be imported without changing the store
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Issue, verify and revoke a throwaway certificate to check the CA end to end
+    Smoke {
+        /// Also serve the certificate on a local TLS port and connect to it
+        #[arg(long)]
+        tls: bool,
+    },
+
+    /// Find the ports certificates are served on, or check those for drift
+    Scan {
+        /// Certificates to scan (default: every current certificate)
+        names: Vec<String>,
+
+        /// Only probe the recorded endpoints; fail if any presents an older certificate
+        #[arg(long)]
+        verify: bool,
+
+        /// Ports to try on each SAN host (default: scan.ports)
+        #[arg(long, value_delimiter = ',')]
+        ports: Vec<u16>,
+    },
+
+    /// Renew expiring certificates on a schedule and push them to their deploy targets
+    Daemon {
+        /// Run a single renewal pass and exit
+        #[arg(long)]
+        once: bool,
+    },
+
+    /// Export certificate expiry metrics for Prometheus
+    Metrics {
+        /// Listen address
+        #[arg(short, long, default_value = "127.0.0.1:9184")]
+        listen: String,
+
+        /// Print the metrics once and exit (for the node_exporter textfile collector)
+        #[arg(long)]
+        once: bool,
+    },
+
+    /// Certificate authority maintenance
+    Ca {
+        #[command(subcommand)]
+        command: CaCommand,
+    },
+
+    /// Install an issued certificate or the CA on this machine
+    Install {
+        /// Certificate name (for --keychain)
+        name: Option<String>,
+
+        /// Import the certificate and key into the login keychain (macOS)
+        #[arg(long, requires = "name")]
+        keychain: bool,
+
+        /// Keychain file to import into instead of the login keychain
+        #[arg(long, requires = "keychain")]
+        keychain_file: Option<PathBuf>,
+
+        /// Also trust the root CA (macOS asks for your password)
+        #[arg(long)]
+        trust_root: bool,
+
+        /// Trust the root CA in the NSS databases of Firefox and Chromium (Linux)
+        #[arg(long)]
+        nss: bool,
+
+        /// NSS database directory to use instead of searching $HOME (repeatable)
+        #[arg(long, requires = "nss")]
+        nss_db: Vec<PathBuf>,
+    },
+
+    /// Export the root and intermediate CA as a Java truststore
+    Truststore {
+        /// Output file [default: <output_dir>/truststore.p12, or .jks]
+        #[arg(short, long)]
+        out: Option<PathBuf>,
+
+        /// Write a JKS store instead of PKCS#12
+        #[arg(long)]
+        jks: bool,
+
+        /// Read the store password from this file instead of prompting
+        #[arg(long)]
+        password_file: Option<PathBuf>,
+    },
+
+    /// Publish certificates to LDAP / Active Directory
+    Ldap {
+        #[command(subcommand)]
+        command: LdapCommand,
     },
 
     /// Start web service (requires 'web' feature)
@@ -112,276 +452,1859 @@ enum Commands {
         #[arg(short, long, default_value = "8443")]
         port: u16,
     },
+
+    /// Manage web service API keys (requires 'web' feature)
+    #[cfg(feature = "web")]
+    Web {
+        #[command(subcommand)]
+        command: WebCommand,
+    },
+
+    /// Print a script enrolling a new machine with a one-time token (requires 'web' feature)
+    #[cfg(feature = "web")]
+    EnrollScript {
+        /// Certificate name, used as the machine's common name
+        name: String,
+
+        /// Additional SANs (comma-separated, e.g. IP:10.0.0.5)
+        #[arg(short, long, value_delimiter = ',')]
+        sans: Vec<String>,
+
+        /// URL of `flux-ssl-mgr serve` as the machine reaches it
+        #[arg(short, long)]
+        url: String,
+
+        /// Script type: sh or powershell
+        #[arg(long, default_value = "sh")]
+        shell: flux_ssl_mgr::enroll::ScriptShell,
+
+        /// How long the enrollment token stays valid
+        #[arg(long, default_value = "24h")]
+        ttl: flux_ssl_mgr::duration::Interval,
+
+        /// Write the script to this file (mode 0600) instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum CaCommand {
+    /// Re-certify the intermediate CA key with the root CA (also when it has expired)
+    RenewIntermediate {
+        /// Validity of the new certificate (days, or e.g. 1y; capped at the root's expiry)
+        #[arg(long, default_value = "5y")]
+        days: Days,
+
+        /// Root CA certificate [default: <working_dir>/certs/ca.cert.pem]
+        #[arg(long)]
+        root_cert: Option<PathBuf>,
+
+        /// Root CA private key [default: <working_dir>/private/ca.key.pem]
+        #[arg(long)]
+        root_key: Option<PathBuf>,
+
+        /// Path length of the new certificate [default: the current one's]
+        #[arg(long)]
+        pathlen: Option<u32>,
+
+        /// Replace the certificate without asking
+        #[arg(short, long)]
+        yes: bool,
+    },
+
+    /// Write an encrypted backup of the CA keys, certificates, database and config
+    Backup {
+        /// Backup file to write (mode 0600)
+        #[arg(short, long)]
+        out: PathBuf,
+
+        /// Read the passphrase from this file instead of prompting
+        #[arg(long)]
+        password_file: Option<PathBuf>,
+    },
+
+    /// Restore the CA from a backup made with `ca backup`
+    Restore {
+        /// Backup file
+        archive: PathBuf,
+
+        /// Read the passphrase from this file instead of prompting
+        #[arg(long)]
+        password_file: Option<PathBuf>,
+
+        /// Replace files of an existing CA
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Write the CA chain (intermediate, then root) for configuring clients
+    Chain {
+        /// Only the intermediate certificate
+        #[arg(long, conflicts_with = "root")]
+        intermediate: bool,
+
+        /// Only the root certificate
+        #[arg(long)]
+        root: bool,
+
+        /// DER instead of PEM (a single certificate: use with --intermediate or --root)
+        #[arg(long)]
+        der: bool,
+
+        /// Output file [default: stdout]
+        #[arg(short, long)]
+        out: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum CsrCommand {
+    /// Show what a CSR asks for: subject, SANs, key, signature and extensions
+    Info {
+        /// CSR file path
+        csr: PathBuf,
+
+        /// Print the details as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Sign a CSR with the intermediate CA (as a subordinate CA with --ca)
+    Sign {
+        /// CSR file path
+        csr: PathBuf,
+
+        /// Issue a subordinate CA certificate (CA:TRUE, keyCertSign/cRLSign)
+        #[arg(long)]
+        ca: bool,
+
+        /// Path length of the subordinate CA [default: sub_ca.pathlen]
+        #[arg(long, requires = "ca")]
+        pathlen: Option<u32>,
+
+        /// Permitted name constraint (domain or CIDR; repeatable, replaces [sub_ca])
+        #[arg(long, value_name = "SUBTREE", requires = "ca")]
+        permit: Vec<String>,
+
+        /// Excluded name constraint (domain or CIDR; repeatable, replaces [sub_ca])
+        #[arg(long, value_name = "SUBTREE", requires = "ca")]
+        exclude: Vec<String>,
+
+        /// Certificate validity (days, or e.g. 13w, 1y) [default: defaults.cert_days]
+        #[arg(short, long, visible_alias = "validity")]
+        days: Option<Validity>,
+
+        /// Write the certificate to this file instead of stdout
+        #[arg(short, long)]
+        out: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum KeyCommand {
+    /// Show a key's format, algorithm, size, encryption and SPKI fingerprint
+    Info {
+        /// Private key file path (PEM)
+        key: PathBuf,
+
+        /// Prompt for the passphrase of an encrypted key to show its size and fingerprint
+        #[arg(long)]
+        unlock: bool,
+
+        /// Print the details as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum LdapCommand {
+    /// Write certificates to their directory entries (all current ones if no names are given)
+    Publish {
+        /// Certificate names
+        names: Vec<String>,
+
+        /// Publish the CA certificates and CRL to ldap.ca_dn instead
+        #[arg(long, conflicts_with = "names")]
+        ca: bool,
+
+        /// Print the LDIF instead of applying it
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[cfg(feature = "web")]
+#[derive(Subcommand)]
+enum WebCommand {
+    /// API keys in the token file
+    Token {
+        #[command(subcommand)]
+        command: TokenCommand,
+    },
+}
+
+#[cfg(feature = "web")]
+#[derive(Subcommand)]
+enum TokenCommand {
+    /// Create a key and print its token (shown only this once)
+    Create {
+        /// Key name
+        name: String,
+
+        /// Allowed scopes: read, issue, revoke (comma-separated or repeated)
+        #[arg(short, long = "scope", required = true, value_delimiter = ',')]
+        scopes: Vec<flux_ssl_mgr::apikey::Scope>,
+    },
+
+    /// Create a one-time enrollment token for `/api/csr/upload` and print it
+    New {
+        /// Name the CSR must have; `*` matches one or more label characters (e.g. nas*.lab)
+        #[arg(short, long)]
+        name: String,
+
+        /// How long the token stays valid
+        #[arg(long, default_value = "24h")]
+        ttl: flux_ssl_mgr::duration::Interval,
+
+        /// SANs allowed besides DNS:<name> (comma-separated)
+        #[arg(short, long, value_delimiter = ',')]
+        sans: Vec<String>,
+    },
+
+    /// List keys from the config and the token file, and pending enrollments
+    List,
+
+    /// Delete a key, or the enrollment tokens for a name, from the token file
+    Revoke {
+        /// Key name or enrollment name
+        name: String,
+    },
+}
+
+/// What the `--summary-json` line reports on
+struct SummaryTarget {
+    command: String,
+    counters: Counters,
+}
+
+fn main() {
+    let started = Instant::now();
+    let mut summary = None;
+    let result = run(&mut summary);
+
+    let exit_code = match &result {
+        Ok(code) => *code,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            1
+        }
+    };
+    if let Some(SummaryTarget { command, counters }) = summary {
+        let error = result.err().map(|e| e.to_string());
+        eprintln!("{}", Summary::new(&command, started.elapsed(), &counters, exit_code, error).to_line());
+    }
+    std::process::exit(exit_code);
+}
+
+/// Subcommand path, e.g. `web token create`
+fn command_name(matches: &ArgMatches) -> String {
+    let mut names = Vec::new();
+    let mut current = matches;
+    while let Some((name, sub)) = current.subcommand() {
+        names.push(name);
+        current = sub;
+    }
+    if names.is_empty() { "oneshot".to_string() } else { names.join(" ") }
 }
 
-fn main() {
-    if let Err(e) = run() {
-        eprintln!("Error: {}", e);
-        std::process::exit(1);
-    }
+fn run(summary: &mut Option<SummaryTarget>) -> Result<i32> {
+    // Parse CLI arguments
+    let matches = Cli::command().get_matches();
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    let counters = Counters::default();
+    if cli.summary_json {
+        *summary = Some(SummaryTarget { command: command_name(&matches), counters: counters.clone() });
+    }
+    match (&cli.command, cli.oneshot) {
+        (None, false) => Cli::command()
+            .error(ErrorKind::MissingSubcommand, "a subcommand (or --oneshot) is required")
+            .exit(),
+        (Some(_), true) => Cli::command()
+            .error(ErrorKind::ArgumentConflict, "--oneshot cannot be combined with a subcommand")
+            .exit(),
+        (Some(Commands::Batch(args)), false) if cli.quiet && args.generate_passwords && args.password_file.is_none() => Cli::command()
+            .error(ErrorKind::MissingRequiredArgument, "--quiet --generate-passwords needs --password-file, or the generated passwords are never shown")
+            .exit(),
+        _ => {}
+    }
+
+    // Initialize tracing
+    let verbosity = Verbosity::from_flags(cli.verbose, cli.quiet);
+    logging::init(verbosity);
+
+    // Load configuration
+    let mut config = if cli.oneshot {
+        // Containers configure everything through the environment
+        Config::default().with_env_overrides(std::env::vars())?
+    } else if matches!(cli.command, Some(Commands::Config { doctor: true, .. }) | Some(Commands::Ca { command: CaCommand::Restore { .. } })) {
+        // Diagnose what validation would reject instead of failing on it, or
+        // restore the CA files it would miss
+        Config::resolve(cli.config.clone().or_else(Config::find_file).as_deref(), std::env::vars())?
+    } else if let Some(config_path) = &cli.config {
+        Config::load_with_env(Some(config_path), std::env::vars())?
+    } else {
+        Config::load()?
+    };
+
+    // Cap the size of certificate/CSR/key files we read
+    flux_ssl_mgr::fsutil::set_max_read_size(config.limits.max_file_size);
+
+    // Override output settings from CLI
+    if cli.verbose > 0 {
+        config.output.verbose = true;
+    }
+    if cli.quiet {
+        config.output.quiet = true;
+    }
+
+    // Create output formatter
+    let output = OutputFormatter::new(&config.output).with_counters(counters.clone());
+    if config.output.summary_json && summary.is_none() {
+        *summary = Some(SummaryTarget { command: command_name(&matches), counters });
+    }
+
+    let Some(command) = cli.command else {
+        return handle_apply(cli.manifest, false, false, config, output).map(|()| 0);
+    };
+
+    // Execute command
+    let result = match command {
+        Commands::Single(args) => {
+            handle_single(args, config, output)
+        }
+        Commands::Batch(args) => {
+            handle_batch(args, config, output)
+        }
+        Commands::Passwords { file, name } => {
+            handle_passwords(file, name, output)
+        }
+        Commands::Apply { file, dry_run, yes } => {
+            handle_apply(file, dry_run, yes, config, output)
+        }
+        Commands::Deploy { name, target } => {
+            handle_deploy(name, target, config, output)
+        }
+        Commands::Rollback { name, target } => {
+            handle_rollback(name, target, config, output)
+        }
+        Commands::Info { cert, remote, sni, starttls, json, verbose } => {
+            let source = match (cert, remote) {
+                (_, Some(address)) => InfoSource::Remote { address, sni, starttls },
+                (Some(path), None) => InfoSource::File(path),
+                (None, None) => unreachable!("clap requires a certificate or --remote"),
+            };
+            handle_info(source, json, verbose > 0, config, output)
+        }
+        Commands::Verify { cert, chain } => {
+            handle_verify(cert, chain, config, output)
+        }
+        Commands::Csr { command: CsrCommand::Info { csr, json } } => {
+            handle_csr_info(csr, json, output)
+        }
+        Commands::Csr { command: CsrCommand::Sign { csr, ca, pathlen, permit, exclude, days, out } } => {
+            let sub_ca = ca.then_some(SubCaOverrides { pathlen, permit, exclude });
+            handle_csr_sign(csr, sub_ca, days, out, config, output)
+        }
+        Commands::Key { command: KeyCommand::Info { key, unlock, json } } => {
+            handle_key_info(key, unlock, json, output)
+        }
+        Commands::Match { cert, key } => {
+            handle_match(cert, key, output)
+        }
+        Commands::Config { init, show, output: output_path, fix_perms, doctor } => {
+            handle_config(init, show, output_path, fix_perms, doctor, config, output)
+        }
+        #[cfg(unix)]
+        Commands::Agent { socket } => {
+            handle_agent(socket, config, output)
+        }
+        Commands::Check { cert, all: _, warning, critical } => {
+            return Ok(handle_check(cert, warning, critical, config));
+        }
+        Commands::Notify { dry_run } => {
+            handle_notify(dry_run, config, output)
+        }
+        Commands::Status => {
+            handle_status(config, output)
+        }
+        Commands::Import { openssl_ca, dry_run } => {
+            handle_import(openssl_ca, dry_run, config, output)
+        }
+        Commands::Smoke { tls } => {
+            let signer = load_signer(&config)?;
+            flux_ssl_mgr::smoke::run(&config, signer.as_ref(), tls, &output)?;
+            output.success("Smoke test passed");
+            Ok(())
+        }
+        Commands::Scan { names, verify, ports } => {
+            handle_scan(names, verify, ports, config, output)
+        }
+        Commands::Daemon { once } => {
+            handle_daemon(once, config)
+        }
+        Commands::Metrics { listen, once } => {
+            handle_metrics(listen, once, config, output)
+        }
+        Commands::Ca { command: CaCommand::RenewIntermediate { days, root_cert, root_key, pathlen, yes } } => {
+            handle_ca_renew(days, root_cert, root_key, pathlen, yes, config, output)
+        }
+        Commands::Ca { command: CaCommand::Chain { intermediate, root, der, out } } => {
+            handle_ca_chain(intermediate, root, der, out, config, output)
+        }
+        Commands::Ca { command: CaCommand::Backup { out, password_file } } => {
+            let config_file = cli.config.clone().or_else(Config::find_file);
+            handle_ca_backup(out, password_file, config_file, config, output)
+        }
+        Commands::Ca { command: CaCommand::Restore { archive, password_file, force } } => {
+            handle_ca_restore(archive, password_file, force, config, output)
+        }
+        Commands::Install { name, keychain, keychain_file, trust_root, nss, nss_db } => {
+            handle_install(InstallArgs { name, keychain, keychain_file, trust_root, nss, nss_db }, config, output)
+        }
+        Commands::Truststore { out, jks, password_file } => {
+            handle_truststore(out, jks, password_file, config, output)
+        }
+        Commands::Ldap { command: LdapCommand::Publish { names, ca, dry_run } } => {
+            handle_ldap_publish(names, ca, dry_run, config, output)
+        }
+        #[cfg(feature = "web")]
+        Commands::Serve { bind, port } => {
+            handle_serve(bind, port, verbosity, config)
+        }
+        #[cfg(feature = "web")]
+        Commands::Web { command: WebCommand::Token { command } } => {
+            handle_web_token(command, config, output)
+        }
+        #[cfg(feature = "web")]
+        Commands::EnrollScript { name, sans, url, shell, ttl, output: path } => {
+            handle_enroll_script(EnrollArgs { name, sans, url, shell, ttl, path }, config, output)
+        }
+    };
+    result.map(|()| 0)
+}
+
+/// Hosts of the `[[inventory]]` sources for SAN prompts; failing sources are warnings
+fn load_inventory(config: &Config, output: &OutputFormatter) -> Inventory {
+    if config.inventory.is_empty() {
+        return Inventory::default();
+    }
+    output.step("Loading host inventory...");
+    let (inventory, errors) = Inventory::load(config);
+    for (source, e) in errors {
+        output.warning(&format!("Inventory source {}: {}", source, e));
+    }
+    inventory
+}
+
+fn handle_single(args: SingleArgs, mut config: Config, output: OutputFormatter) -> Result<()> {
+    let SingleArgs { name, sans, password, days, not_before, key_size, wildcard_pair, dry_run, output_format } = args;
+    let command_defaults = config.command_defaults(CommandKind::Single).clone();
+    config = config.for_command(CommandKind::Single);
+    let k8s_secret = output_format == OutputFormat::K8sSecret;
+    if k8s_secret && password {
+        return Err(FluxError::InvalidConfigValue(
+            "output-format".to_string(),
+            "k8s-secret needs an unencrypted private key".to_string(),
+        ));
+    }
+    if output_format == OutputFormat::Combined {
+        config.defaults.combined_pem = true;
+    }
+
+    // Override config with CLI args if provided
+    if let Some(d) = days {
+        config.defaults.cert_days = d;
+    }
+    if let Some(k) = key_size {
+        config.defaults.key_size = k;
+    }
+    if not_before.is_some() {
+        config.defaults.not_before = not_before;
+    }
+
+    output.header("PKI Certificate Generation");
+
+    // Get certificate name (CLI or interactive)
+    let cert_name = if let Some(n) = name {
+        n
+    } else {
+        interactive::prompt_cert_name()?
+    };
+
+    // Get SANs (CLI or interactive)
+    let mut san_entries = if let Some(s) = sans {
+        let sans_str = s.join(",");
+        SanEntry::parse_multiple(&sans_str)?
+    } else if wildcard_pair {
+        Vec::new()
+    } else {
+        interactive::prompt_sans(&cert_name, &load_inventory(&config, &output))?
+    };
+
+    if wildcard_pair {
+        let mut pair = SanEntry::wildcard_pair(&cert_name)?;
+        san_entries.retain(|san| !pair.contains(san));
+        pair.append(&mut san_entries);
+        san_entries = pair;
+    }
+    san_entries.extend(command_defaults.san_entries()?);
+
+    // Get password protection preference (CLI, [defaults.single] or interactive)
+    let use_password = if password {
+        true
+    } else if k8s_secret || config.defaults.combined_pem {
+        false
+    } else if let Some(protect) = command_defaults.password_protect {
+        protect
+    } else if dry_run {
+        false
+    } else {
+        interactive::prompt_password_protection()?
+    };
+
+    if dry_run {
+        config.validate()?;
+        let ca_cert = load_cert(&config.ca_cert_path)?;
+        let plan = batch::plan_certificate(&cert_name, &san_entries, use_password, &config, &ca_cert)?;
+        print_plan(&plan, &output);
+        output.info("Dry run: nothing was written");
+        return Ok(());
+    }
+
+    let key_password = if use_password {
+        Some(prompt_password_with_confirmation(&format!("Enter password for {}", cert_name))?)
+    } else {
+        None
+    };
+
+    // Load CA (or connect to the signing agent)
+    let ca = load_signer(&config)?;
+
+    // Process certificate
+    batch::process_certificate(
+        &cert_name,
+        &san_entries,
+        key_password.as_ref(),
+        &config,
+        ca.as_ref(),
+        &output,
+    )?;
+
+    output.print_cert_summary(&cert_name, &config.output_dir);
+    if k8s_secret {
+        let path = k8s::write_tls_secret(&cert_name, &config)?;
+        output.success(&format!("Kubernetes secret written to {}", path.display()));
+    }
+    output.warning("Don't forget to update your service configuration with the new certificate!");
+
+    Ok(())
+}
+
+fn handle_batch(args: BatchArgs, mut config: Config, output: OutputFormatter) -> Result<()> {
+    let BatchArgs { dir, all, filter, sans, password, same_password, generate_passwords, password_file, skip_valid, not_before, dry_run, output_format, watch } = args;
+    let command_defaults = config.command_defaults(CommandKind::Batch).clone();
+    config = config.for_command(CommandKind::Batch);
+    if not_before.is_some() {
+        config.defaults.not_before = not_before;
+    }
+    if watch {
+        let dir = dir.unwrap_or_else(|| config.csr_input_dir.clone());
+        output.header("PKI CSR Watch");
+        output.info(&format!("Watching {} for new CSRs (Ctrl+C to stop)", dir.display()));
+        return flux_ssl_mgr::watch::watch(&dir, filter.as_deref(), &config, &output);
+    }
+    if output_format == OutputFormat::K8sSecret && (password || generate_passwords) {
+        return Err(FluxError::InvalidConfigValue(
+            "output-format".to_string(),
+            "k8s-secret needs unencrypted private keys".to_string(),
+        ));
+    }
+    if output_format == OutputFormat::Combined {
+        config.defaults.combined_pem = true;
+    }
+    // [defaults.batch] password_protect yields to formats that need plain keys
+    let password = password || (command_defaults.password_protect == Some(true)
+        && output_format != OutputFormat::K8sSecret
+        && !config.defaults.combined_pem);
+
+    output.header("PKI Batch Certificate Processing");
+
+    // Unlock (or create) the password file before issuing anything
+    let password_store = match &password_file {
+        Some(_) if dry_run => None,
+        Some(path) if path.exists() => {
+            let passphrase = prompt_password(&format!("Passphrase for {}", path.display()))?;
+            let file = PasswordFile::load(path, &passphrase)?;
+            Some((file, passphrase))
+        }
+        Some(path) => {
+            let passphrase = prompt_password_with_confirmation(&format!("New passphrase for {}", path.display()))?;
+            Some((PasswordFile::new(), passphrase))
+        }
+        None => None,
+    };
+
+    // Get CSR directory
+    let csr_dir = if let Some(d) = dir {
+        d
+    } else {
+        PathBuf::from(interactive::prompt_csr_directory(
+            config.csr_input_dir.to_str().unwrap_or("/home/fluxadmin/ssl")
+        )?)
+    };
+
+    // Find CSR files
+    let mut csr_files = batch::find_csr_files(&csr_dir)?;
+
+    // Apply filter if provided
+    if let Some(pattern) = filter {
+        csr_files = batch::filter_csr_files(csr_files, &pattern);
+        if csr_files.is_empty() {
+            return Err(FluxError::NoCsrFilesFound(csr_dir));
+        }
+    }
+
+    output.info(&format!("Found {} CSR files", csr_files.len()));
+
+    // Select CSRs to process
+    let selected_indices = if all {
+        (0..csr_files.len()).collect()
+    } else {
+        interactive::prompt_csr_selection(&csr_files)?
+    };
+
+    let mut selected: Vec<_> = selected_indices.iter().map(|&i| csr_files[i].clone()).collect();
+
+    // In dry runs, report what would be skipped instead of letting batch_process decide
+    if let (true, Some(min_days)) = (dry_run, skip_valid) {
+        selected.retain(|csr| match batch::existing_valid_cert(&csr.name, min_days, &config) {
+            Some(days) => {
+                output.info(&format!("Would skip {}: existing certificate valid for {} more days", csr.name, days));
+                false
+            }
+            None => true,
+        });
+    }
+
+    let selected_names: Vec<String> = selected.iter().map(|csr| csr.name.clone()).collect();
+
+    // Get common SANs ([defaults.batch] sans are always included)
+    let configured_sans = command_defaults.san_entries()?;
+    let common_sans = if let Some(s) = sans {
+        let sans_str = s.join(",");
+        let mut entries = SanEntry::parse_multiple(&sans_str)?;
+        entries.extend(configured_sans);
+        Some(entries)
+    } else if !configured_sans.is_empty() {
+        Some(configured_sans)
+    } else if interactive::prompt_use_common_sans()? {
+        Some(interactive::prompt_common_sans(&load_inventory(&config, &output))?)
+    } else {
+        None
+    };
+
+    if dry_run {
+        config.validate()?;
+        let ca_cert = load_cert(&config.ca_cert_path)?;
+        let common_sans = common_sans.unwrap_or_default();
+
+        let mut failed = 0;
+        for (name, plan) in batch::plan_batch(&selected, &common_sans, password || generate_passwords, &config, &ca_cert) {
+            match plan {
+                Ok(plan) => print_plan(&plan, &output),
+                Err(e) => {
+                    failed += 1;
+                    output.error(&format!("{}: {}", name, e));
+                }
+            }
+        }
+
+        output.info(&format!("Dry run: {} would be issued, {} invalid; nothing was written", selected.len() - failed, failed));
+        return Ok(());
+    }
+
+    // Decide how keys are protected; passwords are collected before processing starts
+    let password_mode = if generate_passwords {
+        PasswordMode::Generate
+    } else if !password {
+        PasswordMode::None
+    } else if same_password || (selected_names.len() > 1 && interactive::prompt_shared_password(selected_names.len())?) {
+        PasswordMode::Shared
+    } else {
+        PasswordMode::Prompt
+    };
+
+    // Process batch
+    let result = batch::batch_process(
+        selected_names,
+        common_sans,
+        password_mode,
+        skip_valid,
+        &config,
+        &output,
+    )?;
+
+    output.print_batch_summary(result.successful, result.skipped.len(), result.failed);
+
+    if output_format == OutputFormat::K8sSecret {
+        for name in &result.issued {
+            match k8s::write_tls_secret(name, &config) {
+                Ok(path) => output.success(&format!("Kubernetes secret written to {}", path.display())),
+                Err(e) => output.error(&format!("{}: {}", name, e)),
+            }
+        }
+    }
+
+    if !result.encrypted.is_empty() {
+        output.info(&format!("Password-protected keys: {}", result.encrypted.join(", ")));
+    }
+
+    if !result.generated_passwords.is_empty() {
+        match (password_store, &password_file) {
+            (Some((mut file, passphrase)), Some(path)) => {
+                for (name, password) in &result.generated_passwords {
+                    file.insert(name, password.clone());
+                }
+                if let Err(e) = file.save(path, &passphrase) {
+                    // The keys are already encrypted with these passwords
+                    output.error(&format!("Could not save key passwords to {}, printing them instead", path.display()));
+                    print_generated_passwords(&result.generated_passwords);
+                    return Err(e);
+                }
+                output.success(&format!("Key passwords saved to {}", path.display()));
+            }
+            _ => {
+                output.warning("Generated key passwords are shown only once. Store them securely!");
+                print_generated_passwords(&result.generated_passwords);
+            }
+        }
+    }
+
+    // Show errors if any
+    if !result.errors.is_empty() {
+        output.println("\nFailed certificates:");
+        for (name, error) in result.errors {
+            output.error(&format!("{}: {}", name, error));
+        }
+    }
+
+    Ok(())
+}
+
+/// Print generated key passwords to stdout, even with --quiet: they exist
+/// nowhere else
+fn print_generated_passwords(passwords: &[(String, Secret<String>)]) {
+    for (name, password) in passwords {
+        println!("  {}: {}", name, password.expose_secret());
+    }
+}
+
+/// Print what a dry run would issue
+fn print_plan(plan: &batch::IssuancePlan, output: &OutputFormatter) {
+    output.step(&format!("Would issue {}", plan.name));
+    output.println(&format!("  Subject:    {}", plan.subject));
+    output.println(&format!("  Issuer:     {}", plan.issuer));
+    if plan.sans.is_empty() {
+        output.println("  SANs:       (none)");
+    } else {
+        output.println(&format!("  SANs:       {}", plan.sans.join(", ")));
+    }
+    output.println(&format!(
+        "  Key:        RSA {} bits{}",
+        plan.key_size,
+        if plan.key_encrypted { ", password-protected" } else { "" }
+    ));
+    output.println(&format!(
+        "  Validity:   {} to {}",
+        plan.not_before.format("%Y-%m-%d %H:%M:%S UTC"),
+        plan.not_after.format("%Y-%m-%d %H:%M:%S UTC")
+    ));
+    output.println("  Files:");
+    for path in plan.paths.all() {
+        output.println(&format!("    {}", path.display()));
+    }
+    if plan.combined_pem {
+        output.println(&format!("    {}", plan.paths.output_combined.display()));
+    }
+}
+
+fn handle_apply(file: PathBuf, dry_run: bool, yes: bool, config: Config, output: OutputFormatter) -> Result<()> {
+    output.header("Applying Certificate Manifest");
+
+    let manifest = Manifest::from_file(&file)?;
+    output.info(&format!("Loaded {} certificates from {}", manifest.certificates.len(), file.display()));
+
+    let diff = manifest::diff(&manifest, &CertStore::open_default(&config)?)?;
+    diff.print(&output);
+
+    if diff.is_empty() {
+        output.success("Everything is up to date");
+        return Ok(());
+    }
+    if dry_run {
+        output.info("Dry run: nothing was changed");
+        return Ok(());
+    }
+
+    let revoke_removed = diff.removed.is_empty() || yes || confirm_revocation(&diff.removed)?;
+    flux_ssl_mgr::history::save_manifest(&config, &file)?;
+    let result = manifest::apply(&diff, &config, &output, revoke_removed)?;
+
+    output.print_apply_summary(result.issued, result.renewed, result.unchanged, result.revoked, result.failed);
+    if result.skipped_revocations > 0 {
+        output.warning(&format!(
+            "{} certificates removed from the manifest were not revoked (re-run with --yes to revoke them)",
+            result.skipped_revocations
+        ));
+    }
+
+    if !result.errors.is_empty() {
+        output.println("\nFailed certificates:");
+        for (name, error) in &result.errors {
+            output.error(&format!("{}: {}", name, error));
+        }
+        return Err(FluxError::ManifestError(format!("{} certificates failed", result.failed)));
+    }
+
+    Ok(())
+}
+
+/// Ask before revoking certificates removed from a manifest (never when not on a terminal)
+fn confirm_revocation(removed: &[IssuedCert]) -> Result<bool> {
+    use std::io::IsTerminal;
+
+    if !std::io::stdin().is_terminal() {
+        return Ok(false);
+    }
+
+    let names: Vec<&str> = removed.iter().map(|c| c.name.as_str()).collect();
+    interactive::prompt_revoke(&names)
+}
+
+fn handle_deploy(name: String, targets: Vec<String>, config: Config, output: OutputFormatter) -> Result<()> {
+    batch::validate_cert_name(&name)?;
+
+    // Check every target before pushing anywhere
+    for target in &targets {
+        deploy::target(&config, target)?;
+    }
+
+    for target in &targets {
+        deploy::deploy(&name, target, &config, &output)?;
+        output.count("deployed", 1);
+    }
+
+    Ok(())
+}
+
+fn handle_rollback(name: String, targets: Vec<String>, config: Config, output: OutputFormatter) -> Result<()> {
+    let targets = if targets.is_empty() { flux_ssl_mgr::daemon::deploy_targets(&config, &name)? } else { targets };
+    for target in &targets {
+        deploy::target(&config, target)?;
+    }
+
+    let record = flux_ssl_mgr::rollback::rollback(&config, &name)?;
+    output.success(&format!(
+        "Rolled back {} to serial {} (expires {})",
+        name, record.serial, record.not_after.format("%Y-%m-%d")
+    ));
+
+    for target in &targets {
+        deploy::deploy(&name, target, &config, &output)?;
+        output.count("deployed", 1);
+    }
+
+    Ok(())
+}
+
+fn handle_ca_chain(
+    intermediate: bool,
+    root: bool,
+    der: bool,
+    out: Option<PathBuf>,
+    config: Config,
+    output: OutputFormatter,
+) -> Result<()> {
+    use flux_ssl_mgr::ca::{ca_chain, ChainPart};
+    use std::io::Write;
+
+    let part = match (intermediate, root) {
+        (true, _) => ChainPart::Intermediate,
+        (_, true) => ChainPart::Root,
+        _ => ChainPart::Full,
+    };
+    let chain = ca_chain(&config, part)?;
+    let bytes = if der {
+        match chain.as_slice() {
+            [cert] => cert.to_der()?,
+            _ => {
+                return Err(FluxError::InvalidConfigValue(
+                    "der".to_string(),
+                    "DER holds a single certificate; add --intermediate or --root".to_string(),
+                ))
+            }
+        }
+    } else {
+        let mut pem = Vec::new();
+        for cert in &chain {
+            pem.extend(flux_ssl_mgr::crypto::cert_to_pem(cert)?);
+        }
+        pem
+    };
+
+    match out {
+        Some(path) => {
+            flux_ssl_mgr::fsutil::write_atomic(&path, &bytes, flux_ssl_mgr::fsutil::PUBLIC_FILE_MODE)?;
+            output.success(&format!("Wrote {} certificate(s) to {}", chain.len(), path.display()));
+        }
+        None => std::io::stdout().write_all(&bytes)?,
+    }
+    Ok(())
+}
+
+fn handle_ca_renew(
+    days: Days,
+    root_cert: Option<PathBuf>,
+    root_key: Option<PathBuf>,
+    pathlen: Option<u32>,
+    yes: bool,
+    config: Config,
+    output: OutputFormatter,
+) -> Result<()> {
+    use flux_ssl_mgr::ca::renew;
+    use std::io::IsTerminal;
+
+    let root_cert_path = root_cert.unwrap_or_else(|| config.root_ca_cert_path());
+    let root_key_path = root_key.unwrap_or_else(|| config.root_ca_key_path());
+    if !root_cert_path.exists() {
+        return Err(FluxError::CaCertNotFound(root_cert_path));
+    }
+
+    output.header("Certificate Authority");
+    print_ca_status(&renew::ca_status(&config)?, &output);
+
+    let current = load_cert(&config.ca_cert_path)?;
+    let root = load_cert(&root_cert_path)?;
+    let renewed = renew::recertify(&current, &root, &renew::load_root_key(&root_key_path)?, days.get(), pathlen)?;
+    let not_after = flux_ssl_mgr::crypto::cert::asn1_time_to_chrono(renewed.not_after())?;
+
+    output.println("");
+    output.info(&format!(
+        "New intermediate certificate: same key, valid until {}",
+        not_after.format("%Y-%m-%d")
+    ));
+    if not_after >= flux_ssl_mgr::crypto::cert::asn1_time_to_chrono(root.not_after())? {
+        output.warning("Validity was capped at the root CA's expiry");
+    }
+    if renewed.pathlen() != current.pathlen() {
+        let show = |pathlen: Option<u32>| pathlen.map_or_else(|| "unlimited".to_string(), |p| p.to_string());
+        output.info(&format!("Path length: {} (was {})", show(renewed.pathlen()), show(current.pathlen())));
+    }
+
+    let confirmed = yes || (std::io::stdin().is_terminal()
+        && interactive::prompt_confirm(&format!("Replace {}?", config.ca_cert_path.display()))?);
+    if !confirmed {
+        return Err(FluxError::UserCancelled);
+    }
+
+    let backup = renew::install(&config, &renewed)?;
+    output.success(&format!("Renewed the intermediate CA certificate (previous one kept as {})", backup.display()));
+    output.info("Certificates it issued stay valid; redeploy the CA chain wherever it is installed");
+    #[cfg(unix)]
+    if config.agent.socket_path.exists() {
+        output.warning("Restart the signing agent so it picks up the new certificate");
+    }
+
+    Ok(())
+}
+
+/// One line per CA certificate, with a pointer to the fix when expired
+fn print_ca_status(status: &[flux_ssl_mgr::ca::renew::CaCertStatus], output: &OutputFormatter) {
+    for ca in status {
+        let expires = ca.not_after.format("%Y-%m-%d %H:%M");
+        if ca.is_expired() {
+            output.error(&format!("{} CA {}: expired {} ({} days ago)", ca.role, ca.subject, expires, -ca.days_remaining()));
+        } else {
+            output.println(&format!("{} CA {}: expires {} ({} days)", ca.role, ca.subject, expires, ca.days_remaining()));
+        }
+    }
+    if status.first().is_some_and(|ca| ca.is_expired()) {
+        output.warning("Signing is disabled until the intermediate is renewed: flux-ssl-mgr ca renew-intermediate");
+    }
+}
+
+fn handle_passwords(file: PathBuf, name: Option<String>, output: OutputFormatter) -> Result<()> {
+    let passphrase = prompt_password(&format!("Passphrase for {}", file.display()))?;
+    let passwords = PasswordFile::load(&file, &passphrase)?;
+
+    if let Some(name) = name {
+        let password = passwords.get(&name)
+            .ok_or_else(|| FluxError::PasswordFileError(format!("no password stored for {}", name)))?;
+        output.println(password.expose_secret());
+        return Ok(());
+    }
+
+    for (name, password) in passwords.entries() {
+        output.println(&format!("{}: {}", name, password.expose_secret()));
+    }
+
+    Ok(())
+}
+
+/// What `info` inspects
+enum InfoSource {
+    File(PathBuf),
+    Remote {
+        address: String,
+        sni: Option<String>,
+        starttls: Option<flux_ssl_mgr::remote::StartTls>,
+    },
+}
+
+fn handle_info(source: InfoSource, json: bool, verbose: bool, config: Config, output: OutputFormatter) -> Result<()> {
+    use flux_ssl_mgr::crypto::cert::{load_cert, load_certs};
+
+    // PEM, DER or PKCS#7, or the chain a server presents; shown certificate by certificate
+    let (label, certs) = match source {
+        InfoSource::File(path) => (path.display().to_string(), load_certs(&path)?),
+        InfoSource::Remote { address, sni, starttls } => {
+            let chain = flux_ssl_mgr::remote::presented_chain(&address, sni.as_deref(), starttls, config.scan.timeout.get())?;
+            (flux_ssl_mgr::remote::with_default_port(&address), chain)
+        }
+    };
+    let issuer = load_cert(&config.ca_cert_path).ok();
+
+    if json {
+        // The structure /api/cert/info returns
+        let details = flux_ssl_mgr::crypto::details::describe_all(&certs, &config.revocation, issuer.as_ref())?;
+        println!("{}", serde_json::to_string_pretty(&details).expect("certificate details serialize"));
+        return Ok(());
+    }
+
+    for (index, cert) in certs.iter().enumerate() {
+        let title = if certs.len() > 1 {
+            format!("Certificate Information: {} ({} of {})", label, index + 1, certs.len())
+        } else {
+            format!("Certificate Information: {}", label)
+        };
+        output.header(&title);
+        show_cert_info(cert, issuer.as_ref(), verbose, &config, &output)?;
+    }
+
+    Ok(())
+}
+
+fn show_cert_info(cert: &openssl::x509::X509, issuer: Option<&openssl::x509::X509>, verbose: bool, config: &Config, output: &OutputFormatter) -> Result<()> {
+    use flux_ssl_mgr::crypto::cert::{get_cert_info, is_cert_expired, days_until_expiration};
+    use flux_ssl_mgr::crypto::{check_revocation, RevocationState};
+
+    let info = get_cert_info(cert)?;
+    output.println(&info);
+
+    // Check expiration
+    let expired = is_cert_expired(cert)?;
+    let days_left = days_until_expiration(cert)?;
+
+    if expired {
+        output.error(&format!("Certificate is EXPIRED (expired {} days ago)", -days_left));
+    } else if days_left < 30 {
+        output.warning(&format!("Certificate expires in {} days", days_left));
+    } else {
+        output.success(&format!("Certificate is valid ({} days remaining)", days_left));
+    }
+
+    // Check revocation status against local CRL / CA database
+    // A missing or unreadable CRL/index shouldn't hide the certificate itself
+    match check_revocation(cert, &config.revocation, issuer) {
+        Ok(None) => {}
+        Err(e) => output.warning(&format!("Revocation status: Unknown (failed to load revocation data: {})", e)),
+        Ok(Some(status)) => match status.state {
+            RevocationState::Good => output.success(&format!("Revocation status: Good ({})", status.detail())),
+            RevocationState::Unknown => output.warning(&format!("Revocation status: Unknown ({})", status.detail())),
+            RevocationState::Revoked => {
+                let mut msg = format!("Revocation status: REVOKED ({})", status.detail());
+                if let Some(at) = status.revoked_at {
+                    msg.push_str(&format!(" on {}", at.format("%Y-%m-%d %H:%M:%S UTC")));
+                }
+                if let Some(reason) = &status.reason {
+                    msg.push_str(&format!(", reason: {}", reason));
+                }
+                output.error(&msg);
+            }
+        },
+    }
+
+    if verbose {
+        // Show additional details
+        output.println("\nPublic Key Info:");
+        let pubkey = cert.public_key()?;
+        output.println("  Algorithm: RSA");
+        if let Ok(rsa) = pubkey.rsa() {
+            output.println(&format!("  Key Size: {} bits", rsa.size() * 8));
+        }
+    }
+
+    Ok(())
+}
+
+fn handle_csr_info(csr_path: PathBuf, json: bool, output: OutputFormatter) -> Result<()> {
+    use flux_ssl_mgr::crypto::{details::describe_csr, display_dns, format_name, load_csr};
+
+    let csr = load_csr(&csr_path)?;
+    let details = describe_csr(&csr)?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&details).expect("CSR details serialize"));
+        return Ok(());
+    }
+
+    output.header(&format!("CSR Information: {}", csr_path.display()));
+    output.println(&format!("Subject: {}", format_name(csr.subject_name())));
+    if details.subject_alternative_names.is_empty() {
+        output.println("Requested SANs: none");
+    } else {
+        let sans: Vec<String> = details.subject_alternative_names.iter()
+            .map(|san| san.strip_prefix("DNS:").map_or_else(|| san.clone(), |dns| format!("DNS:{}", display_dns(dns))))
+            .collect();
+        output.println(&format!("Requested SANs: {}", sans.join(", ")));
+    }
+    output.println(&format!("Public Key: {} {} bits", details.public_key.algorithm, details.public_key.size));
+    output.println(&format!("Signature Algorithm: {}", details.signature_algorithm));
+    if !details.extensions.is_empty() {
+        output.println("Requested Extensions:");
+        for ext in &details.extensions {
+            let critical = if ext.critical { " (critical)" } else { "" };
+            output.println(&format!("  {}{}: {}", ext.name, critical, ext.value));
+        }
+    }
+
+    // A request that wasn't signed by its own key may have been tampered with
+    if !details.signature_valid {
+        return Err(FluxError::CsrSignatureInvalid(csr_path));
+    }
+    output.success("Signature is valid");
+
+    Ok(())
+}
+
+fn handle_verify(cert_path: PathBuf, chain: Option<PathBuf>, config: Config, output: OutputFormatter) -> Result<()> {
+    use flux_ssl_mgr::crypto::{load_certs, RevocationState};
+
+    let mut certs = load_certs(&cert_path)?.into_iter();
+    let cert = certs.next().expect("load_certs returns at least one certificate");
+    let mut intermediates: Vec<_> = certs.collect();
+    if let Some(chain) = &chain {
+        intermediates.extend(load_certs(chain)?);
+    }
+
+    let verification = flux_ssl_mgr::verify::verify(&config, &cert, &intermediates)?;
+
+    output.header(&format!("Verifying {}", cert_path.display()));
+    for element in &verification.chain.chain {
+        let line = format!("[{}] {}", element.depth, element.subject);
+        match &element.error {
+            Some(error) => output.error(&format!("{}: {}", line, error)),
+            None if element.valid => output.success(&line),
+            None => output.error(&line),
+        }
+    }
+    if let Some(status) = &verification.revocation {
+        match status.state {
+            RevocationState::Good => output.success(&format!("Revocation status: Good ({})", status.detail())),
+            RevocationState::Unknown => output.warning(&format!("Revocation status: Unknown ({})", status.detail())),
+            RevocationState::Revoked => {}
+        }
+    }
+
+    if let Some(reason) = verification.failure() {
+        return Err(FluxError::VerificationFailed(reason));
+    }
+    output.success("Certificate is trusted by this CA");
+    Ok(())
+}
+
+/// `csr sign --ca` options that replace the `[sub_ca]` configuration
+struct SubCaOverrides {
+    pathlen: Option<u32>,
+    permit: Vec<String>,
+    exclude: Vec<String>,
+}
+
+fn handle_csr_sign(
+    csr_path: PathBuf,
+    sub_ca: Option<SubCaOverrides>,
+    days: Option<Validity>,
+    out: Option<PathBuf>,
+    config: Config,
+    output: OutputFormatter,
+) -> Result<()> {
+    use flux_ssl_mgr::ca::IntermediateCA;
+    use flux_ssl_mgr::crypto::{cert_to_pem, extract_certificate_info, load_csr, NameConstraints};
+    use std::io::Write;
+
+    let csr = load_csr(&csr_path)?;
+    let validity = days.unwrap_or(config.defaults.cert_days);
+
+    let (cert, extensions) = match sub_ca {
+        None => (load_signer(&config)?.sign_for(&csr, validity)?, None),
+        Some(overrides) => {
+            // The signing agent only issues leaf certificates
+            let ca = IntermediateCA::load(&config)?;
+            let mut extensions = ca.sub_ca().clone();
+            if let Some(pathlen) = overrides.pathlen {
+                extensions.pathlen = pathlen;
+            }
+            if !overrides.permit.is_empty() || !overrides.exclude.is_empty() {
+                extensions.name_constraints = NameConstraints::parse(&overrides.permit, &overrides.exclude)?;
+            }
+            (ca.sign_sub_ca(&csr, validity, &extensions)?, Some(extensions))
+        }
+    };
+
+    let pem = cert_to_pem(&cert)?;
+    let Some(path) = out else {
+        // Only the certificate goes to stdout, so it can be piped
+        std::io::stdout().write_all(&pem)?;
+        return Ok(());
+    };
+    flux_ssl_mgr::fsutil::write_atomic(&path, &pem, flux_ssl_mgr::fsutil::PUBLIC_FILE_MODE)?;
+
+    let info = extract_certificate_info(&cert)?;
+    output.success(&format!("Signed {} (serial {}), written to {}", info.subject, info.serial_number, path.display()));
+    if let Some(extensions) = extensions {
+        output.info(&format!("Subordinate CA with path length {}", extensions.pathlen));
+        if extensions.name_constraints.is_empty() {
+            output.warning("No name constraints: the subordinate CA can issue for any name");
+        } else {
+            output.info(&format!("Name constraints: {}", extensions.name_constraints));
+        }
+    }
+    Ok(())
+}
+
+fn handle_key_info(key_path: PathBuf, unlock: bool, json: bool, output: OutputFormatter) -> Result<()> {
+    use flux_ssl_mgr::crypto::key::inspect_key;
+    use flux_ssl_mgr::fsutil::read_bounded;
+    use secrecy::ExposeSecret;
+
+    let pem = zeroize::Zeroizing::new(read_bounded(&key_path)?);
+    let mut info = inspect_key(&pem, None)?;
+    if info.encrypted && unlock {
+        let password = prompt_password(&format!("Passphrase for {}", key_path.display()))?;
+        info = inspect_key(&pem, Some(password.expose_secret()))?;
+    }
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&info).expect("key details serialize"));
+        return Ok(());
+    }
+
+    output.header(&format!("Key Information: {}", key_path.display()));
+    output.println(&format!("Format: {}", info.format));
+    output.println(&format!("Encrypted: {}", if info.encrypted { "yes" } else { "no" }));
+    if let Some(algorithm) = &info.algorithm {
+        output.println(&format!("Algorithm: {}", algorithm));
+    }
+    if let Some(size) = info.size {
+        output.println(&format!("Size: {} bits", size));
+    }
+    match &info.spki_sha256 {
+        Some(fingerprint) => output.println(&format!("SPKI SHA-256: {}", fingerprint)),
+        None => output.info("Use --unlock to show the size and fingerprint of this encrypted key"),
+    }
+
+    Ok(())
+}
+
+fn handle_match(cert_path: PathBuf, key_path: PathBuf, output: OutputFormatter) -> Result<()> {
+    use flux_ssl_mgr::crypto::key::{inspect_key, matches_cert, parse_private_key, spki_sha256};
+    use flux_ssl_mgr::fsutil::read_bounded;
+
+    let cert = load_cert(&cert_path)?;
+    let pem = zeroize::Zeroizing::new(read_bounded(&key_path)?);
+    let key = if inspect_key(&pem, None)?.encrypted {
+        let password = prompt_password(&format!("Passphrase for {}", key_path.display()))?;
+        parse_private_key(&pem, Some(password.expose_secret()))?
+    } else {
+        parse_private_key(&pem, None)?
+    };
+
+    if matches_cert(&key, &cert)? {
+        output.success(&format!("{} matches {}", key_path.display(), cert_path.display()));
+        output.println(&format!("SPKI SHA-256: {}", spki_sha256(&key)?));
+        return Ok(());
+    }
+
+    let cert_key = cert.public_key()?;
+    output.println(&format!("Certificate SPKI SHA-256: {}", spki_sha256(&cert_key)?));
+    output.println(&format!("Key SPKI SHA-256:         {}", spki_sha256(&key)?));
+    Err(FluxError::KeyCertMismatch(key_path, cert_path))
+}
+
+fn handle_ca_backup(
+    out: PathBuf,
+    password_file: Option<PathBuf>,
+    config_file: Option<PathBuf>,
+    config: Config,
+    output: OutputFormatter,
+) -> Result<()> {
+    use flux_ssl_mgr::fsutil::read_to_string_bounded;
+
+    let passphrase = match &password_file {
+        Some(path) => secrecy::Secret::new(read_to_string_bounded(path)?.trim_end_matches(['\r', '\n']).to_string()),
+        None => prompt_password_with_confirmation("Backup passphrase")?,
+    };
+
+    let manifest = flux_ssl_mgr::ca::backup::backup(&config, config_file.as_deref(), &out, passphrase.expose_secret())?;
+    let size: u64 = manifest.files.iter().map(|f| f.size).sum();
+    output.success(&format!("Wrote {} ({} files, {} bytes before encryption)", out.display(), manifest.files.len(), size));
+    output.info(&format!("CA: {}", manifest.ca_subject));
+    output.warning("Keep the passphrase somewhere other than this machine; the backup can't be restored without it");
+    Ok(())
 }
 
-fn run() -> Result<()> {
-    // Parse CLI arguments
-    let cli = Cli::parse();
+fn handle_ca_restore(
+    archive: PathBuf,
+    password_file: Option<PathBuf>,
+    force: bool,
+    config: Config,
+    output: OutputFormatter,
+) -> Result<()> {
+    use flux_ssl_mgr::ca::backup::{self, Backup};
+    use flux_ssl_mgr::fsutil::read_to_string_bounded;
 
-    // Initialize tracing
-    let log_level = if cli.verbose {
-        "debug".to_string()
-    } else {
-        std::env::var("RUST_LOG").unwrap_or_else(|_| "info".to_string())
+    let data = std::fs::read(&archive).map_err(|e| FluxError::FileReadFailed(archive.clone(), e.to_string()))?;
+    let passphrase = match &password_file {
+        Some(path) => secrecy::Secret::new(read_to_string_bounded(path)?.trim_end_matches(['\r', '\n']).to_string()),
+        None => prompt_password("Backup passphrase")?,
     };
 
-    tracing_subscriber::fmt()
-        .with_env_filter(&log_level)
-        .init();
-
-    // Load configuration
-    let mut config = if let Some(config_path) = &cli.config {
-        Config::from_file(config_path)?
-    } else {
-        Config::load()?
+    let backup = Backup::open(&data, passphrase.expose_secret())?;
+    output.info(&format!(
+        "Backup of {} from {} ({} files, checksums verified)",
+        backup.manifest.ca_subject,
+        backup.manifest.created_at.format("%Y-%m-%d %H:%M UTC"),
+        backup.manifest.files.len()
+    ));
+
+    let key_password = || match &config.ca_key_password_file {
+        Some(path) => Ok(secrecy::Secret::new(read_to_string_bounded(path)?.trim_end_matches(['\r', '\n']).to_string())),
+        None => prompt_password("CA key password (to check it against the certificate)"),
     };
+    let restored = backup::restore(&backup, force, key_password)?;
 
-    // Override output settings from CLI
-    if cli.verbose {
-        config.output.verbose = true;
+    for path in &restored {
+        output.println(&format!("  {}", path.display()));
     }
-    if cli.quiet {
-        config.output.quiet = true;
+    output.success(&format!("Restored {} files; the CA key matches its certificate", restored.len()));
+    Ok(())
+}
+
+fn handle_truststore(
+    out: Option<PathBuf>,
+    jks: bool,
+    password_file: Option<PathBuf>,
+    config: Config,
+    output: OutputFormatter,
+) -> Result<()> {
+    use flux_ssl_mgr::fsutil::{read_to_string_bounded, write_atomic, PUBLIC_FILE_MODE};
+    use flux_ssl_mgr::truststore::{self, TruststoreFormat};
+
+    let format = if jks { TruststoreFormat::Jks } else { TruststoreFormat::Pkcs12 };
+    let entries = truststore::ca_entries(&config)?;
+    let password = match &password_file {
+        Some(path) => secrecy::Secret::new(read_to_string_bounded(path)?.trim_end_matches(['\r', '\n']).to_string()),
+        None => prompt_password_with_confirmation("Truststore password")?,
+    };
+
+    let store = truststore::build(&entries, format, password.expose_secret())?;
+    let out = out.unwrap_or_else(|| config.output_dir.join(format!("truststore.{}", format.extension())));
+    write_atomic(&out, &store, PUBLIC_FILE_MODE)?;
+
+    output.success(&format!("Wrote {}", out.display()));
+    for (alias, _) in &entries {
+        output.println(&format!("  trusted: {}", alias));
     }
+    Ok(())
+}
 
-    // Create output formatter
-    let output = OutputFormatter::new(&config.output);
+fn handle_config(
+    init: bool,
+    show: bool,
+    output_path: Option<PathBuf>,
+    fix_perms: bool,
+    doctor: bool,
+    config: Config,
+    output: OutputFormatter,
+) -> Result<()> {
+    if init {
+        let config_path = output_path
+            .or_else(Config::user_file)
+            .unwrap_or_else(|| PathBuf::from("flux-ssl-mgr.toml"));
 
-    // Execute command
-    match cli.command {
-        Commands::Single { name, sans, password, days, key_size } => {
-            handle_single(name, sans, password, days, key_size, config, output)
+        // Create parent directory if it doesn't exist
+        if let Some(parent) = config_path.parent() {
+            std::fs::create_dir_all(parent)?;
         }
-        Commands::Batch { dir, all, filter, sans, password } => {
-            handle_batch(dir, all, filter, sans, password, config, output)
+
+        // Create default config
+        let default_config = Config::default();
+        default_config.save(&config_path)?;
+
+        println!("Created default configuration at: {}", config_path.display());
+        println!("\nPlease edit this file to match your PKI setup.");
+        return Ok(());
+    }
+
+    if fix_perms {
+        let changed = flux_ssl_mgr::fsutil::fix_permissions(&config)?;
+        if changed.is_empty() {
+            output.success("Directory permissions already match the configuration");
         }
-        Commands::Info { cert, verbose } => {
-            handle_info(cert, verbose, output)
+        for (dir, mode) in changed {
+            output.success(&format!("Set {} to {:o}", dir.display(), mode));
         }
-        Commands::Config { init, show, output: output_path } => {
-            handle_config(init, show, output_path, config)
+        return Ok(());
+    }
+
+    if doctor {
+        use flux_ssl_mgr::doctor::{diagnose, Severity};
+
+        output.header("Configuration Check");
+        let findings = diagnose(&config);
+        for finding in &findings {
+            match finding.severity {
+                Severity::Ok => output.success(&finding.message),
+                Severity::Warning => output.warning(&finding.message),
+                Severity::Error => output.error(&finding.message),
+            }
+            if let Some(hint) = &finding.hint {
+                output.println(&format!("    → {}", hint));
+            }
         }
-        #[cfg(feature = "web")]
-        Commands::Serve { bind, port } => {
-            handle_serve(bind, port, config)
+        let problems = findings.iter().filter(|f| f.severity == Severity::Error).count();
+        if problems > 0 {
+            return Err(FluxError::ConfigProblems(problems));
         }
+        output.success("Ready to issue certificates");
+        return Ok(());
     }
-}
 
-fn handle_single(
-    name: Option<String>,
-    sans: Option<Vec<String>>,
-    password: bool,
-    days: Option<u32>,
-    key_size: Option<u32>,
-    mut config: Config,
-    output: OutputFormatter,
-) -> Result<()> {
-    // Override config with CLI args if provided
-    if let Some(d) = days {
-        config.defaults.cert_days = d;
-    }
-    if let Some(k) = key_size {
-        config.defaults.key_size = k;
+    if show {
+        println!("Current Configuration:");
+        println!("======================");
+        println!("{}", toml::to_string_pretty(&config).unwrap());
+        return Ok(());
     }
 
-    output.header("PKI Certificate Generation");
+    println!("Use --init to create a configuration file");
+    println!("Use --show to display current configuration");
+    println!("Use --fix-perms to reset directory permissions");
+    println!("Use --doctor to check the configuration before issuing");
 
-    // Get certificate name (CLI or interactive)
-    let cert_name = if let Some(n) = name {
-        n
-    } else {
-        interactive::prompt_cert_name()?
-    };
+    Ok(())
+}
 
-    // Get SANs (CLI or interactive)
-    let san_entries = if let Some(s) = sans {
-        let sans_str = s.join(",");
-        SanEntry::parse_multiple(&sans_str)?
-    } else {
-        interactive::prompt_sans()?
-    };
+#[cfg(unix)]
+fn handle_agent(socket: Option<PathBuf>, config: Config, output: OutputFormatter) -> Result<()> {
+    use flux_ssl_mgr::ca::agent::SigningAgent;
+    use flux_ssl_mgr::IntermediateCA;
 
-    // Get password protection preference (CLI or interactive)
-    let use_password = if password {
-        true
-    } else {
-        interactive::prompt_password_protection()?
-    };
+    let socket_path = socket.unwrap_or_else(|| config.agent.socket_path.clone());
 
-    // Load CA
-    let ca = IntermediateCA::load(&config)?;
+    output.header("CA Signing Agent");
 
-    // Process certificate
-    batch::process_certificate(
-        &cert_name,
-        &san_entries,
-        use_password,
-        &config,
-        &ca,
-        &output,
-    )?;
+    let ca = IntermediateCA::load_in_memory(&config)?;
+    let agent = SigningAgent::bind(&socket_path, ca)?;
 
-    output.print_cert_summary(&cert_name, &config.output_dir);
-    output.warning("Don't forget to update your service configuration with the new certificate!");
+    output.success(&format!("CA key unlocked, listening on {}", agent.socket_path().display()));
+    output.info("Press Ctrl+C to stop the agent");
 
-    Ok(())
+    agent.run()
 }
 
-fn handle_batch(
-    dir: Option<PathBuf>,
-    all: bool,
-    filter: Option<String>,
-    sans: Option<Vec<String>>,
-    password: bool,
-    config: Config,
-    output: OutputFormatter,
-) -> Result<()> {
-    output.header("PKI Batch Certificate Processing");
+/// Runs the monitoring check and returns the plugin exit code
+fn handle_check(cert: Option<PathBuf>, warning: Option<Days>, critical: Option<Days>, config: Config) -> i32 {
+    use flux_ssl_mgr::check::{self, CertCheck, Thresholds};
+
+    let now = chrono::Utc::now();
+    let result = Thresholds::new(
+        warning.unwrap_or(config.check.warning_days),
+        critical.unwrap_or(config.check.critical_days),
+    )
+    .and_then(|thresholds| {
+        let checks = match &cert {
+            Some(path) => {
+                let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+                vec![CertCheck::from_cert(&name, &load_cert(path)?, thresholds, now)?]
+            }
+            None => {
+                let mut checks = check::check_store(&CertStore::open_default(&config)?, thresholds, now);
+                // An expired intermediate breaks every certificate it issued
+                if let Ok(ca) = load_cert(&config.ca_cert_path) {
+                    checks.push(CertCheck::from_cert("intermediate CA", &ca, thresholds, now)?);
+                }
+                checks
+            }
+        };
+        Ok(check::summarize(&checks, thresholds, now))
+    });
+
+    // Plugin output goes to stdout whatever the output settings
+    let (status, line) = result.unwrap_or_else(|e| check::unknown(&e));
+    println!("{}", line);
+    status.exit_code()
+}
 
-    // Get CSR directory
-    let csr_dir = if let Some(d) = dir {
-        d
+fn handle_scan(names: Vec<String>, verify: bool, ports: Vec<u16>, config: Config, output: OutputFormatter) -> Result<()> {
+    use flux_ssl_mgr::scan::{self, Served};
+
+    let store = CertStore::open_default(&config)?;
+    let records: Vec<_> = if names.is_empty() {
+        store.current().into_iter().cloned().collect()
     } else {
-        PathBuf::from(interactive::prompt_csr_directory(
-            config.csr_input_dir.to_str().unwrap_or("/home/fluxadmin/ssl")
-        )?)
+        names.iter()
+            .map(|name| store.latest(name).cloned().ok_or_else(|| FluxError::StoreError(format!("{} is not in the certificate store", name))))
+            .collect::<Result<_>>()?
     };
+    let ports = if ports.is_empty() { config.scan.ports.clone() } else { ports };
+    let timeout = config.scan.timeout.get();
+
+    let mut drifted = Vec::new();
+    let mut found = Vec::new();
+    for record in &records {
+        if verify && record.endpoints.is_empty() {
+            output.verbose(&format!("{}: no endpoints recorded", record.name));
+            continue;
+        }
+        let findings = if verify {
+            scan::verify(&store, record, timeout)
+        } else {
+            scan::discover(&store, record, &ports, timeout)
+        };
+
+        for finding in &findings {
+            let at = format!("{} on {} ({})", record.name, finding.endpoint.address(), finding.endpoint);
+            match &finding.served {
+                Served::Current => output.success(&at),
+                Served::Outdated(serial) => {
+                    output.warning(&format!("{} presents older serial {}", at, serial));
+                    drifted.push(at);
+                }
+                Served::Other(serial) => {
+                    output.warning(&format!("{} presents serial {}, not one of this certificate's", at, serial));
+                    drifted.push(at);
+                }
+                Served::Unreachable(reason) => output.warning(&format!("{} unreachable: {}", at, reason)),
+            }
+        }
+        if findings.is_empty() {
+            output.info(&format!("{}: not found on any port", record.name));
+        }
+        if !verify {
+            found.push((record.name.clone(), scan::endpoints(record, &findings)));
+        }
+    }
 
-    // Find CSR files
-    let mut csr_files = batch::find_csr_files(&csr_dir)?;
+    if !found.is_empty() {
+        let count = found.iter().map(|(_, endpoints)| endpoints.len()).sum::<usize>();
+        update_store(&config, "Record scanned endpoints", |store| {
+            for (name, endpoints) in found {
+                store.set_endpoints(&name, endpoints);
+            }
+        })?;
+        output.info(&format!("Recorded {} endpoint(s)", count));
+    }
 
-    // Apply filter if provided
-    if let Some(pattern) = filter {
-        csr_files = batch::filter_csr_files(csr_files, &pattern);
-        if csr_files.is_empty() {
-            return Err(FluxError::NoCsrFilesFound(csr_dir));
+    if verify && !drifted.is_empty() {
+        return Err(FluxError::DeploymentDrift(format!("{} endpoint(s) present another certificate", drifted.len())));
+    }
+    Ok(())
+}
+
+fn handle_notify(dry_run: bool, config: Config, output: OutputFormatter) -> Result<()> {
+    use flux_ssl_mgr::notify;
+
+    let reports = notify::notify(&config, dry_run)?;
+    let mut failed = 0;
+    for report in &reports {
+        match (&report.digest, &report.error) {
+            (_, Some(error)) => {
+                failed += 1;
+                output.error(&format!("{}: {}", report.sink, error));
+            }
+            (None, None) => output.info(&format!("{}: nothing due", report.sink)),
+            (Some(digest), None) if dry_run => {
+                output.println(&format!("--- {}\nSubject: {}\n", report.sink, digest.subject));
+                output.println(&digest.body);
+            }
+            (Some(digest), None) => {
+                output.success(&format!("{}: sent {} certificates", report.sink, digest.notices.len()));
+                output.count("sent", 1);
+            }
         }
     }
+    output.count("failed", failed);
 
-    output.info(&format!("Found {} CSR files", csr_files.len()));
+    if failed > 0 {
+        return Err(FluxError::NotifyError(format!("{} of {} sinks failed", failed, reports.len())));
+    }
+    Ok(())
+}
 
-    // Select CSRs to process
-    let selected_indices = if all {
-        (0..csr_files.len()).collect()
-    } else {
-        interactive::prompt_csr_selection(&csr_files)?
-    };
+struct InstallArgs {
+    name: Option<String>,
+    keychain: bool,
+    keychain_file: Option<PathBuf>,
+    trust_root: bool,
+    nss: bool,
+    nss_db: Vec<PathBuf>,
+}
 
-    let selected_names: Vec<String> = selected_indices.iter()
-        .map(|&i| csr_files[i].name.clone())
-        .collect();
+fn handle_install(args: InstallArgs, config: Config, output: OutputFormatter) -> Result<()> {
+    use flux_ssl_mgr::crypto::is_key_encrypted;
+    use flux_ssl_mgr::install;
+    use secrecy::ExposeSecret;
 
-    // Get common SANs
-    let common_sans = if let Some(s) = sans {
-        let sans_str = s.join(",");
-        Some(SanEntry::parse_multiple(&sans_str)?)
-    } else if interactive::prompt_use_common_sans()? {
-        Some(interactive::prompt_common_sans()?)
-    } else {
-        None
-    };
+    let InstallArgs { name, keychain, keychain_file, trust_root, nss, nss_db } = args;
+    if !keychain && !nss {
+        return Err(FluxError::InstallError("choose where to install: --keychain or --nss".to_string()));
+    }
 
-    // Process batch
-    let result = batch::batch_process(
-        selected_names,
-        common_sans,
-        password,
-        &config,
-        &output,
-    )?;
+    if let (true, Some(name)) = (keychain, &name) {
+        batch::validate_cert_name(name)?;
+        let (_, key_path) = batch::IssuancePaths::current(&config, name);
+        let password = if key_path.exists() && is_key_encrypted(&key_path)? {
+            Some(prompt_password(&format!("Password for {}", key_path.display()))?)
+        } else {
+            None
+        };
+
+        install::install_keychain(
+            &config,
+            name,
+            keychain_file.as_deref(),
+            trust_root,
+            password.as_ref().map(|p| p.expose_secret().as_str()),
+            &output,
+        )?;
+    }
 
-    output.print_batch_summary(result.successful, result.failed);
+    if nss {
+        let count = install::install_nss(&config, &nss_db, &output)?;
+        output.success(&format!("Trusted the CA in {} NSS database(s); restart the browsers to pick it up", count));
+    }
+    Ok(())
+}
 
-    // Show errors if any
-    if !result.errors.is_empty() {
-        output.println("\nFailed certificates:");
-        for (name, error) in result.errors {
-            output.error(&format!("{}: {}", name, error));
+fn handle_ldap_publish(names: Vec<String>, ca: bool, dry_run: bool, config: Config, output: OutputFormatter) -> Result<()> {
+    use flux_ssl_mgr::ldap;
+
+    let settings = ldap::settings(&config)?;
+    if ca {
+        let (dn, ldif) = ldap::ca_changes(&config)?;
+        if dry_run {
+            output.println(&ldif);
+        } else {
+            ldap::apply(settings, &ldif)?;
+            output.success(&format!("Published the CA certificates to {}", dn));
         }
+        return Ok(());
+    }
+
+    let store = CertStore::open_default(&config)?;
+    let records: Vec<&IssuedCert> = if names.is_empty() {
+        store.current()
+    } else {
+        names.iter()
+            .map(|name| store.latest(name).ok_or_else(|| FluxError::LdapError(format!("{} is not in the certificate store", name))))
+            .collect::<Result<_>>()?
+    };
+    if records.is_empty() {
+        output.info("No certificates to publish");
+        return Ok(());
     }
 
+    let mut failed = 0;
+    for record in records {
+        let cert = load_cert(&record.cert_path)?;
+        let dn = ldap::entry_dn(&settings.entry_dn, &record.name);
+        if dry_run {
+            output.println(&ldap::publish_ldif(settings, &dn, &cert)?);
+            continue;
+        }
+        match ldap::apply(settings, &ldap::publish_ldif(settings, &dn, &cert)?) {
+            Ok(()) => {
+                output.success(&format!("Published {} to {}", record.name, dn));
+                output.count("published", 1);
+            }
+            Err(e) => {
+                output.error(&format!("{}: {}", record.name, e));
+                failed += 1;
+            }
+        }
+    }
+    output.count("failed", failed);
+    if failed > 0 {
+        return Err(FluxError::LdapError(format!("{} certificate(s) could not be published", failed)));
+    }
     Ok(())
 }
 
-fn handle_info(cert_path: PathBuf, verbose: bool, output: OutputFormatter) -> Result<()> {
-    use flux_ssl_mgr::crypto::cert::{load_cert, get_cert_info, is_cert_expired, days_until_expiration};
+fn handle_import(dir: PathBuf, dry_run: bool, config: Config, output: OutputFormatter) -> Result<()> {
+    let summary = flux_ssl_mgr::import::import_openssl_ca(&config, &dir, dry_run)?;
 
-    let cert = load_cert(&cert_path)?;
+    for record in &summary.imported {
+        let state = match &record.revoked_at {
+            Some(_) => format!("revoked ({})", record.revocation_reason.as_deref().unwrap_or("unspecified")),
+            None if record.is_expired() => "expired".to_string(),
+            None => format!("expires {}", record.not_after.format("%Y-%m-%d")),
+        };
+        output.info(&format!("{} (serial {}): {}", record.name, record.serial, state));
+    }
+    if summary.known > 0 {
+        output.info(&format!("{} certificate(s) already in the store", summary.known));
+    }
+    if summary.other_issuer > 0 {
+        output.info(&format!("Skipped {} certificate file(s) issued by another CA", summary.other_issuer));
+    }
+    if !summary.missing.is_empty() {
+        output.warning(&format!(
+            "No certificate file for serial(s) {} in index.txt; not imported",
+            summary.missing.join(", ")
+        ));
+    }
 
-    output.header(&format!("Certificate Information: {}", cert_path.display()));
+    let verb = if dry_run { "Would import" } else { "Imported" };
+    output.success(&format!("{} {} certificate(s) from {}", verb, summary.imported.len(), dir.display()));
+    Ok(())
+}
 
-    let info = get_cert_info(&cert)?;
-    output.println(&info);
+fn handle_status(config: Config, output: OutputFormatter) -> Result<()> {
+    let store = CertStore::open_default(&config)?;
+    let now = chrono::Local::now().naive_local();
 
-    // Check expiration
-    let expired = is_cert_expired(&cert)?;
-    let days_left = days_until_expiration(&cert)?;
+    output.header("Certificate Authority");
+    match flux_ssl_mgr::ca::renew::ca_status(&config) {
+        Ok(status) => print_ca_status(&status, &output),
+        Err(e) => output.warning(&format!("Could not read the CA certificate: {}", e)),
+    }
+    output.println("");
 
-    if expired {
-        output.error(&format!("Certificate is EXPIRED (expired {} days ago)", -days_left));
-    } else if days_left < 30 {
-        output.warning(&format!("Certificate expires in {} days", days_left));
-    } else {
-        output.success(&format!("Certificate is valid ({} days remaining)", days_left));
+    output.header("Certificates");
+    let certs = store.current();
+    if certs.is_empty() {
+        output.info("No certificates issued yet");
+    }
+    let width = certs.iter().map(|c| c.name.len()).max().unwrap_or(0).max(4);
+    if !certs.is_empty() {
+        output.println(&format!("{:width$}  {:<16}  {:>5}  {:<8}  {}", "NAME", "EXPIRES", "DAYS", "STATE", "SERVED ON", width = width));
+    }
+    for cert in &certs {
+        let days = cert.days_remaining();
+        let state = if cert.is_expired() {
+            "expired"
+        } else if days < i64::from(config.check.warning_days.get()) {
+            "expiring"
+        } else {
+            "ok"
+        };
+        let mut served: Vec<String> = cert.endpoints.iter().map(|e| e.to_string()).collect();
+        served.sort();
+        served.dedup();
+        output.println(format!(
+            "{:width$}  {:<16}  {:>5}  {:<8}  {}",
+            cert.name,
+            cert.not_after.format("%Y-%m-%d %H:%M"),
+            days,
+            state,
+            served.join(", "),
+            width = width
+        ).trim_end());
     }
 
-    if verbose {
-        // Show additional details
-        output.println("\nPublic Key Info:");
-        let pubkey = cert.public_key()?;
-        output.println(&format!("  Algorithm: RSA"));
-        if let Ok(rsa) = pubkey.rsa() {
-            output.println(&format!("  Key Size: {} bits", rsa.size() * 8));
+    let pending = store.pending_deploys();
+    if !pending.is_empty() {
+        output.println("");
+        output.header("Pending Deployments");
+        for queued in pending {
+            let next = config.deploy.get(&queued.target)
+                .and_then(|t| t.window.as_ref())
+                .and_then(|w| w.next_open(now))
+                .map_or_else(|| "next daemon pass".to_string(), |t| t.format("%Y-%m-%d %H:%M").to_string());
+            output.println(&format!(
+                "{} -> {}  (queued {}, window opens {})",
+                queued.name,
+                queued.target,
+                queued.queued_at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M"),
+                next
+            ));
         }
     }
 
     Ok(())
 }
 
-fn handle_config(init: bool, show: bool, output_path: Option<PathBuf>, config: Config) -> Result<()> {
-    if init {
-        let config_path = output_path.unwrap_or_else(|| {
-            PathBuf::from(std::env::var("HOME").unwrap_or_else(|_| ".".to_string()))
-                .join(".config/flux-ssl-mgr/config.toml")
-        });
+fn handle_daemon(once: bool, config: Config) -> Result<()> {
+    use flux_ssl_mgr::daemon;
+    use flux_ssl_mgr::output::MessageKind;
+    use std::sync::Arc;
 
-        // Create parent directory if it doesn't exist
-        if let Some(parent) = config_path.parent() {
-            std::fs::create_dir_all(parent)?;
+    // The daemon's output goes to the log
+    let output = OutputFormatter::with_sink(&config.output, Arc::new(|kind: MessageKind, msg: &str| match kind {
+        MessageKind::Error => tracing::error!("{}", msg),
+        MessageKind::Warning => tracing::warn!("{}", msg),
+        MessageKind::Verbose => tracing::debug!("{}", msg),
+        _ => tracing::info!("{}", msg),
+    }));
+
+    if once {
+        let report = daemon::renew_due(&config, &output)?;
+        report.log();
+        if !report.failed.is_empty() {
+            return Err(FluxError::RenewalError(format!("{} of {} certificates", report.failed.len(), report.renewed.len() + report.failed.len())));
         }
-
-        // Create default config
-        let default_config = Config::default();
-        default_config.save(&config_path)?;
-
-        println!("Created default configuration at: {}", config_path.display());
-        println!("\nPlease edit this file to match your PKI setup.");
         return Ok(());
     }
 
-    if show {
-        println!("Current Configuration:");
-        println!("======================");
-        println!("{}", toml::to_string_pretty(&config).unwrap());
+    tracing::info!(
+        "Renewal daemon started: every {}, renewing below {} days",
+        config.daemon.interval, config.daemon.renew_before
+    );
+    let runtime = tokio::runtime::Runtime::new()
+        .map_err(FluxError::IoError)?;
+    runtime.block_on(daemon::run(Arc::new(config), output))
+}
+
+fn handle_metrics(listen: String, once: bool, config: Config, output: OutputFormatter) -> Result<()> {
+    use flux_ssl_mgr::metrics;
+
+    if once {
+        print!("{}", metrics::render(&metrics::collect(&config)?, chrono::Utc::now()));
         return Ok(());
     }
 
-    println!("Use --init to create a configuration file");
-    println!("Use --show to display current configuration");
+    output.header("Metrics Exporter");
+    output.success(&format!("Serving http://{}/metrics", listen));
+    output.info("Press Ctrl+C to stop the exporter");
 
-    Ok(())
+    metrics::serve(&listen, &config)
 }
 
 #[cfg(feature = "web")]
-fn handle_serve(bind: String, port: u16, config: Config) -> Result<()> {
+fn handle_serve(bind: String, port: u16, verbosity: Verbosity, config: Config) -> Result<()> {
     use flux_ssl_mgr::web::{start_server, ServerConfig};
     use std::sync::Arc;
 
@@ -391,14 +2314,189 @@ fn handle_serve(bind: String, port: u16, config: Config) -> Result<()> {
     let server_config = ServerConfig {
         bind_address: bind,
         port,
+        verbosity,
     };
 
     // Create a tokio runtime
     let runtime = tokio::runtime::Runtime::new()
-        .map_err(|e| FluxError::IoError(e))?;
+        .map_err(FluxError::IoError)?;
 
     // Run the server
     runtime.block_on(async {
         start_server(Arc::new(config), server_config).await
     })
 }
+
+#[cfg(feature = "web")]
+struct EnrollArgs {
+    name: String,
+    sans: Vec<String>,
+    url: String,
+    shell: flux_ssl_mgr::enroll::ScriptShell,
+    ttl: flux_ssl_mgr::duration::Interval,
+    path: Option<PathBuf>,
+}
+
+#[cfg(feature = "web")]
+fn handle_enroll_script(args: EnrollArgs, config: Config, output: OutputFormatter) -> Result<()> {
+    use flux_ssl_mgr::apikey::TokenFile;
+    use flux_ssl_mgr::config::SanOrder;
+    use flux_ssl_mgr::crypto::normalize_sans;
+    use flux_ssl_mgr::enroll::EnrollScript;
+
+    let EnrollArgs { name, sans, url, shell, ttl, path } = args;
+    batch::validate_cert_name(&name)?;
+    if !(url.starts_with("http://") || url.starts_with("https://")) {
+        return Err(FluxError::InvalidConfigValue("url".to_string(), format!("{} (expected e.g. https://ca.lab:8443)", url)));
+    }
+    let mut requested = vec![SanEntry::Dns(name.clone())];
+    if !sans.is_empty() {
+        requested.extend(SanEntry::parse_multiple(&sans.join(","))?);
+    }
+    let sans = normalize_sans(&name, &requested, SanOrder::CnFirst);
+
+    let ttl = chrono::Duration::from_std(ttl.get())
+        .map_err(|e| FluxError::InvalidConfigValue("ttl".to_string(), e.to_string()))?;
+    let tokens_path = config.web_tokens_path();
+    let mut tokens = TokenFile::open(&tokens_path)?;
+    // DNS:<name> leads the list and is always allowed
+    let (token, enrollment) = tokens.create_enrollment(&name, &sans[1..], ttl)?;
+    tokens.save()?;
+    let expires_at = enrollment.expires_at;
+
+    let script = EnrollScript { name: name.clone(), sans, url, token, key_size: config.defaults.key_size, expires_at }
+        .render(shell);
+    let expiry = expires_at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M");
+    match path {
+        Some(path) => {
+            flux_ssl_mgr::fsutil::write_atomic(&path, script.as_bytes(), flux_ssl_mgr::fsutil::PRIVATE_FILE_MODE)?;
+            output.success(&format!("Wrote {} enrollment script for {} to {}", shell, name, path.display()));
+            output.info(&format!("Its token works once, until {}; keep the script secret until then", expiry));
+        }
+        None => {
+            // Status goes to stderr so the script can be piped or redirected
+            let status = OutputFormatter::with_writer(&config.output, std::io::stderr());
+            print!("{}", script);
+            status.info(&format!("Enrollment token for {} works once, until {}", name, expiry));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "web")]
+fn handle_web_token(command: TokenCommand, config: Config, output: OutputFormatter) -> Result<()> {
+    use flux_ssl_mgr::apikey::TokenFile;
+
+    let path = config.web_tokens_path();
+    let mut tokens = TokenFile::open(&path)?;
+
+    match command {
+        TokenCommand::Create { name, scopes } => {
+            if config.web.api_keys.iter().any(|k| k.name == name) {
+                return Err(FluxError::InvalidConfigValue(
+                    "web token".to_string(),
+                    format!("a key named {} is already defined in the config", name),
+                ));
+            }
+            let token = tokens.create(&name, scopes.into_iter().collect())?;
+            tokens.save()?;
+
+            output.success(&format!("Created API key {} in {}", name, path.display()));
+            output.warning("Store this token now; it can't be shown again:");
+            println!("{}", token);
+        }
+        TokenCommand::New { name, ttl, sans } => {
+            flux_ssl_mgr::apikey::validate_name_pattern(&name)?;
+            let sans = if sans.is_empty() { Vec::new() } else { SanEntry::parse_multiple(&sans.join(","))? };
+            let ttl = chrono::Duration::from_std(ttl.get())
+                .map_err(|e| FluxError::InvalidConfigValue("ttl".to_string(), e.to_string()))?;
+            // DNS:<name> is always allowed; `sans` lists only the extra ones
+            let (token, enrollment) = tokens.create_enrollment(&name, &sans, ttl)?;
+            tokens.save()?;
+
+            output.success(&format!(
+                "Created enrollment token for {}, valid once until {}",
+                name,
+                enrollment.expires_at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M")
+            ));
+            if config.web.allow_unauthenticated && !flux_ssl_mgr::web::auth::enabled(&config)? {
+                output.warning("No API keys exist and web.allow_unauthenticated is set, so the web API is open to requests without this token as well");
+            }
+            output.warning("Store this token now; it can't be shown again:");
+            println!("{}", token);
+        }
+        TokenCommand::List => {
+            let keys: Vec<(&str, &flux_ssl_mgr::apikey::ApiKey)> = config.web.api_keys.iter()
+                .map(|k| ("config", k))
+                .chain(tokens.keys().iter().map(|k| ("token file", k)))
+                .collect();
+            let now = chrono::Utc::now();
+            let enrollments: Vec<_> = tokens.enrollments().iter().filter(|e| !e.is_expired(now)).collect();
+            if !enrollments.is_empty() {
+                output.header("Enrollment tokens");
+                for enrollment in &enrollments {
+                    output.println(&format!(
+                        "  {}  until {}{}",
+                        enrollment.name,
+                        enrollment.expires_at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M"),
+                        if enrollment.sans.is_empty() { String::new() } else { format!("  (also {})", enrollment.sans.join(", ")) }
+                    ));
+                }
+                if !keys.is_empty() {
+                    output.header("API keys");
+                }
+            }
+            if keys.is_empty() {
+                output.info(if config.web.allow_unauthenticated {
+                    "No API keys: the web API is open to anyone who can reach it (web.allow_unauthenticated)"
+                } else {
+                    "No API keys: read requests are open; issue and revoke requests are refused"
+                });
+                return Ok(());
+            }
+
+            let width = keys.iter().map(|(_, k)| k.name.len()).max().unwrap_or(0).max(4);
+            output.println(&format!("{:width$}  {:<18}  {:<10}  {}", "NAME", "SCOPES", "SOURCE", "CREATED", width = width));
+            for (source, key) in keys {
+                let scopes: Vec<String> = key.scopes.iter().map(ToString::to_string).collect();
+                output.println(&format!(
+                    "{:width$}  {:<18}  {:<10}  {}",
+                    key.name,
+                    scopes.join(","),
+                    source,
+                    key.created_at.map_or_else(|| "-".to_string(), |t| t.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string()),
+                    width = width
+                ));
+            }
+        }
+        TokenCommand::Revoke { name } => {
+            let enrollments = tokens.remove_enrollments(&name);
+            if !tokens.remove(&name) && enrollments == 0 {
+                let hint = if config.web.api_keys.iter().any(|k| k.name == name) { " (it is defined in the config)" } else { "" };
+                return Err(FluxError::InvalidConfigValue(
+                    "web token".to_string(),
+                    format!("no key named {} in {}{}", name, path.display(), hint),
+                ));
+            }
+            tokens.save()?;
+            if enrollments > 0 {
+                output.success(&format!("Revoked {} enrollment token(s) for {}", enrollments, name));
+            } else {
+                output.success(&format!("Revoked API key {}", name));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cli_definition() {
+        Cli::command().debug_assert();
+    }
+}
@@ -0,0 +1,503 @@
+//! Declarative certificate manifests
+//!
+//! A manifest lists the certificates that should exist. Applying it compares
+//! each entry against the issued certificate store and issues whatever is
+//! missing, expiring, or out of date.
+//!
+//! ```yaml
+//! renew_before_days: 30
+//! certificates:
+//!   - name: nas
+//!     sans: ["DNS:nas.lab.internal", "IP:10.0.0.5"]
+//!     profile: server
+//!   - name: grafana
+//!     sans: ["DNS:grafana.lab.internal"]
+//!     validity_days: 90
+//!     deploy: [router]
+//! ```
+//!
+//! Entries with `deploy` targets are pushed to them right after issuance.
+//!
+//! The manifest is the desired state: names it has managed before but no
+//! longer lists are revoked, once the caller confirms.
+
+use crate::batch::{process_certificate, validate_cert_name};
+use crate::ca::{load_signer, OpensslDb};
+use crate::config::{Config, SanOrder};
+#[cfg(feature = "deploy")]
+use crate::deploy;
+use crate::crypto::{normalize_sans, SanEntry};
+use crate::duration::Days;
+use crate::error::{FluxError, Result};
+use crate::fsutil::read_to_string_bounded;
+use crate::ldap;
+use crate::output::OutputFormatter;
+use crate::notify::webhook::{self, Event, EventKind};
+use crate::store::{update_store, CertStore, IssuedCert};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Desired certificate state
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Renew certificates with fewer than this many days remaining
+    #[serde(default = "default_renew_before_days")]
+    pub renew_before_days: Days,
+
+    /// Certificates that should exist
+    #[serde(default)]
+    pub certificates: Vec<ManifestEntry>,
+}
+
+/// A single desired certificate
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// Certificate name
+    pub name: String,
+
+    /// Subject Alternative Names (e.g. `DNS:example.com`)
+    #[serde(default)]
+    pub sans: Vec<String>,
+
+    /// Config profile to issue with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+
+    /// Validity period in days (overrides the profile)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub validity_days: Option<Days>,
+
+    /// RSA key size in bits (overrides the profile)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_size: Option<u32>,
+
+    /// Deploy targets the certificate should be pushed to after issuance
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deploy: Vec<String>,
+}
+
+fn default_renew_before_days() -> Days { Days::new(30) }
+
+impl Manifest {
+    /// Load a manifest from a YAML (or JSON) file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let data = read_to_string_bounded(path.as_ref())?;
+
+        Self::parse(&data)
+    }
+
+    /// Parse and validate a manifest
+    pub fn parse(data: &str) -> Result<Self> {
+        let manifest: Manifest = serde_yaml::from_str(data)
+            .map_err(|e| FluxError::ManifestError(e.to_string()))?;
+
+        manifest.validate()?;
+        Ok(manifest)
+    }
+
+    /// Check names are valid and unique and SANs parse
+    pub fn validate(&self) -> Result<()> {
+        let mut seen = std::collections::HashSet::new();
+
+        for entry in &self.certificates {
+            validate_cert_name(&entry.name)?;
+
+            if !seen.insert(entry.name.as_str()) {
+                return Err(FluxError::ManifestError(format!("duplicate certificate name '{}'", entry.name)));
+            }
+
+            entry.san_entries()?;
+        }
+
+        Ok(())
+    }
+}
+
+impl ManifestEntry {
+    /// Parsed SAN entries
+    pub fn san_entries(&self) -> Result<Vec<SanEntry>> {
+        self.sans.iter()
+            .map(|s| SanEntry::parse(s.trim()))
+            .collect()
+    }
+
+    /// Configuration to issue this entry with (profile and overrides applied)
+    pub fn effective_config(&self, config: &Config) -> Result<Config> {
+        let mut config = match &self.profile {
+            Some(profile) => config.with_profile(profile)?,
+            None => config.clone(),
+        };
+
+        if let Some(days) = self.validity_days {
+            config.defaults.cert_days = days.into();
+        }
+        if let Some(key_size) = self.key_size {
+            config.defaults.key_size = key_size;
+        }
+
+        Ok(config)
+    }
+}
+
+/// What applying a manifest entry will do
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlanAction {
+    /// No certificate exists yet
+    Issue,
+    /// Existing certificate is expired or expiring soon
+    Renew,
+    /// Existing certificate no longer matches the manifest
+    Reissue,
+    /// Existing certificate is current
+    Unchanged,
+}
+
+impl std::fmt::Display for PlanAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            PlanAction::Issue => "issue",
+            PlanAction::Renew => "renew",
+            PlanAction::Reissue => "reissue",
+            PlanAction::Unchanged => "unchanged",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Planned action for a manifest entry
+#[derive(Debug, Clone)]
+pub struct PlannedCert {
+    pub entry: ManifestEntry,
+    pub action: PlanAction,
+    pub reason: String,
+}
+
+/// Difference between a manifest and the store
+#[derive(Debug, Clone)]
+pub struct Diff {
+    /// One entry per manifest certificate
+    pub changes: Vec<PlannedCert>,
+    /// Managed certificates the manifest no longer lists
+    pub removed: Vec<IssuedCert>,
+}
+
+impl Diff {
+    /// Whether applying would change nothing
+    pub fn is_empty(&self) -> bool {
+        self.removed.is_empty() && self.changes.iter().all(|p| p.action == PlanAction::Unchanged)
+    }
+
+    /// Print one line per certificate: `+` issue, `~` renew/reissue, `-` revoke, `=` unchanged
+    pub fn print(&self, output: &OutputFormatter) {
+        for p in &self.changes {
+            let marker = match p.action {
+                PlanAction::Issue => '+',
+                PlanAction::Renew | PlanAction::Reissue => '~',
+                PlanAction::Unchanged => '=',
+            };
+            output.println(&format!("  {} {}: {} ({})", marker, p.entry.name, p.action, p.reason));
+        }
+        for cert in &self.removed {
+            output.println(&format!("  - {}: revoke (removed from manifest, serial {})", cert.name, cert.serial));
+        }
+    }
+}
+
+/// Result of applying a manifest
+#[derive(Debug, Default)]
+pub struct ApplyResult {
+    pub issued: usize,
+    pub renewed: usize,
+    pub unchanged: usize,
+    pub revoked: usize,
+    /// Removed certificates left alone because revocation wasn't confirmed
+    pub skipped_revocations: usize,
+    pub failed: usize,
+    pub errors: Vec<(String, String)>,
+}
+
+/// Compare the manifest against the store
+pub fn plan(manifest: &Manifest, store: &CertStore) -> Result<Vec<PlannedCert>> {
+    manifest.certificates.iter()
+        .map(|entry| {
+            let (action, reason) = plan_entry(entry, store.latest(&entry.name), manifest.renew_before_days)?;
+            Ok(PlannedCert {
+                entry: entry.clone(),
+                action,
+                reason,
+            })
+        })
+        .collect()
+}
+
+/// Managed certificates that are still valid but no longer in the manifest
+pub fn removed(manifest: &Manifest, store: &CertStore) -> Vec<IssuedCert> {
+    store.managed().iter()
+        .filter(|name| !manifest.certificates.iter().any(|e| &e.name == *name))
+        .filter_map(|name| store.latest(name))
+        .filter(|cert| !cert.is_revoked())
+        .cloned()
+        .collect()
+}
+
+/// Compare the manifest against the store, including removals
+pub fn diff(manifest: &Manifest, store: &CertStore) -> Result<Diff> {
+    Ok(Diff {
+        changes: plan(manifest, store)?,
+        removed: removed(manifest, store),
+    })
+}
+
+fn plan_entry(entry: &ManifestEntry, existing: Option<&IssuedCert>, renew_before_days: Days) -> Result<(PlanAction, String)> {
+    let existing = match existing {
+        Some(e) if e.is_revoked() => return Ok((PlanAction::Issue, "revoked".to_string())),
+        Some(e) if e.cert_path.exists() => e,
+        Some(e) => return Ok((PlanAction::Issue, format!("certificate file {} is missing", e.cert_path.display()))),
+        None => return Ok((PlanAction::Issue, "not issued yet".to_string())),
+    };
+
+    let days_left = existing.days_remaining();
+    if existing.is_expired() {
+        return Ok((PlanAction::Renew, "expired".to_string()));
+    }
+    if days_left < renew_before_days.get() as i64 {
+        return Ok((PlanAction::Renew, format!("expires in {} days", days_left)));
+    }
+
+    // Compare normalized and sorted, so case, duplicates and order aren't drift
+    let existing_sans: Vec<SanEntry> = existing.sans.iter().filter_map(|s| SanEntry::parse(s).ok()).collect();
+    let desired = normalize_sans(&entry.name, &entry.san_entries()?, SanOrder::Sorted);
+    let actual = normalize_sans(&entry.name, &existing_sans, SanOrder::Sorted);
+    if desired != actual {
+        return Ok((PlanAction::Reissue, "SANs changed".to_string()));
+    }
+
+    Ok((PlanAction::Unchanged, format!("valid for {} more days", days_left)))
+}
+
+/// Apply a diff: issue or renew every entry that needs it, then revoke
+/// removed certificates if `revoke_removed` is set
+pub fn apply(diff: &Diff, config: &Config, output: &OutputFormatter, revoke_removed: bool) -> Result<ApplyResult> {
+    let mut result = ApplyResult::default();
+    let pending: Vec<&PlannedCert> = diff.changes.iter()
+        .filter(|p| p.action != PlanAction::Unchanged)
+        .collect();
+    result.unchanged = diff.changes.len() - pending.len();
+
+    if !pending.is_empty() {
+        // Catch typos in deploy targets before issuing anything
+        for p in &pending {
+            check_deploy_targets(&p.entry, config)?;
+        }
+
+        // Load CA (or connect to the signing agent) once
+        let ca = load_signer(config)?;
+
+        for p in pending {
+            let outcome = p.entry.effective_config(config)
+                .and_then(|cfg| {
+                    let sans = p.entry.san_entries()?;
+                    process_certificate(&p.entry.name, &sans, None, &cfg, ca.as_ref(), output)?;
+                    deploy_entry(&p.entry, &cfg, output)
+                });
+
+            match outcome {
+                Ok(()) => {
+                    match p.action {
+                        PlanAction::Issue => result.issued += 1,
+                        _ => result.renewed += 1,
+                    }
+                }
+                Err(e) => {
+                    result.failed += 1;
+                    result.errors.push((p.entry.name.clone(), e.to_string()));
+                }
+            }
+        }
+    }
+
+    if !revoke_removed {
+        result.skipped_revocations = diff.removed.len();
+    }
+
+    let message = format!(
+        "Apply manifest: {} issued, {} renewed, {} revoked",
+        result.issued, result.renewed, if revoke_removed { diff.removed.len() } else { 0 }
+    );
+    let revoked = update_store(config, &message, |store| {
+        for p in &diff.changes {
+            store.managed_mut().insert(p.entry.name.clone());
+        }
+
+        let mut revoked = Vec::new();
+        if revoke_removed {
+            for cert in &diff.removed {
+                let serials = store.revoke(&cert.name, "cessationOfOperation");
+                output.info(&format!("Revoked {} (serial {})", cert.name, serials.join(", ")));
+                store.managed_mut().remove(&cert.name);
+                revoked.extend(store.certificates().iter()
+                    .filter(|c| c.name == cert.name && serials.contains(&c.serial))
+                    .cloned());
+                result.revoked += 1;
+            }
+        }
+        revoked
+    })?;
+
+    if let Some(db) = OpensslDb::from_config(config).filter(|_| !revoked.is_empty()) {
+        let serials: Vec<String> = revoked.iter().map(|c| c.serial.clone()).collect();
+        if let Err(e) = db.revoke(&serials, "cessationOfOperation", chrono::Utc::now()) {
+            output.warning(&format!("Failed to mark revocations in {}: {}", db.index_path().display(), e));
+        }
+    }
+
+    for cert in &revoked {
+        webhook::emit(config, &Event::new(EventKind::Revoked, cert));
+        if ldap::auto_publish(config) {
+            match std::fs::read(&cert.cert_path) {
+                Ok(pem) => ldap::on_revoked(config, &cert.name, &pem, output),
+                Err(e) => output.warning(&format!("Failed to remove {} from the directory: {}", cert.name, e)),
+            }
+        }
+    }
+
+    Ok(result)
+}
+
+/// Make sure every deploy target of an entry exists
+#[cfg(feature = "deploy")]
+fn check_deploy_targets(entry: &ManifestEntry, config: &Config) -> Result<()> {
+    entry.deploy.iter().try_for_each(|target| deploy::target(config, target).map(|_| ()))
+}
+
+/// Push a freshly issued entry to its deploy targets (or queue it for their windows)
+#[cfg(feature = "deploy")]
+fn deploy_entry(entry: &ManifestEntry, config: &Config, output: &OutputFormatter) -> Result<()> {
+    entry.deploy.iter().try_for_each(|target| deploy::deploy_in_window(&entry.name, target, config, output).map(|_| ()))
+}
+
+#[cfg(not(feature = "deploy"))]
+fn check_deploy_targets(entry: &ManifestEntry, _config: &Config) -> Result<()> {
+    if entry.deploy.is_empty() {
+        Ok(())
+    } else {
+        Err(FluxError::DeployError(format!("{}: built without the deploy feature", entry.name)))
+    }
+}
+
+#[cfg(not(feature = "deploy"))]
+fn deploy_entry(_entry: &ManifestEntry, _config: &Config, _output: &OutputFormatter) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, Utc};
+
+    const MANIFEST: &str = r#"
+renew_before_days: 2w
+certificates:
+  - name: nas
+    sans: ["DNS:nas.lab", "IP:10.0.0.5"]
+  - name: grafana
+    sans: ["DNS:grafana.lab"]
+    validity_days: 90
+    deploy: [router]
+"#;
+
+    fn issued(name: &str, sans: &[&str], valid_days: i64, cert_path: &Path) -> IssuedCert {
+        let now = Utc::now();
+        IssuedCert {
+            name: name.to_string(),
+            serial: "01".to_string(),
+            subject: format!("CN={}", name),
+            sans: sans.iter().map(|s| s.to_string()).collect(),
+            not_before: now,
+            not_after: now + Duration::days(valid_days),
+            issued_at: now,
+            cert_path: cert_path.to_path_buf(),
+            key_path: None,
+            key_encrypted: false,
+            revoked_at: None,
+            revocation_reason: None,
+            endpoints: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_parse_manifest() {
+        let manifest = Manifest::parse(MANIFEST).unwrap();
+        assert_eq!(manifest.renew_before_days, Days::new(14));
+        assert_eq!(manifest.certificates.len(), 2);
+        assert_eq!(manifest.certificates[1].validity_days, Some(Days::new(90)));
+        assert_eq!(manifest.certificates[1].deploy, vec!["router"]);
+    }
+
+    #[test]
+    fn test_parse_manifest_rejects_duplicates_and_bad_sans() {
+        let dup = "certificates:\n  - name: a\n  - name: a\n";
+        assert!(matches!(Manifest::parse(dup), Err(FluxError::ManifestError(_))));
+
+        let bad_san = "certificates:\n  - name: a\n    sans: [\"FOO:bar\"]\n";
+        assert!(Manifest::parse(bad_san).is_err());
+    }
+
+    #[test]
+    fn test_plan() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let cert_path = temp_dir.path().join("nas.cert.pem");
+        std::fs::write(&cert_path, "placeholder").unwrap();
+
+        let manifest = Manifest::parse(MANIFEST).unwrap();
+        let mut store = CertStore::open(temp_dir.path().join("store.json")).unwrap();
+
+        // Nothing issued yet
+        let planned = plan(&manifest, &store).unwrap();
+        assert!(planned.iter().all(|p| p.action == PlanAction::Issue));
+
+        // Current certificate with matching SANs (in a different order)
+        store.record(issued("nas", &["IP:10.0.0.5", "DNS:nas.lab"], 300, &cert_path));
+        // Expiring certificate
+        store.record(issued("grafana", &["DNS:grafana.lab"], 7, &cert_path));
+        let planned = plan(&manifest, &store).unwrap();
+        assert_eq!(planned[0].action, PlanAction::Unchanged);
+        assert_eq!(planned[1].action, PlanAction::Renew);
+
+        // Case and duplicates aren't drift either
+        store.record(issued("nas", &["DNS:NAS.lab", "IP:10.0.0.5", "DNS:nas.lab"], 300, &cert_path));
+        assert_eq!(plan(&manifest, &store).unwrap()[0].action, PlanAction::Unchanged);
+
+        // SANs changed
+        store.record(issued("nas", &["DNS:nas.lab"], 300, &cert_path));
+        let planned = plan(&manifest, &store).unwrap();
+        assert_eq!(planned[0].action, PlanAction::Reissue);
+    }
+
+    #[test]
+    fn test_diff_revokes_removed_managed_certs() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let cert_path = temp_dir.path().join("nas.cert.pem");
+        std::fs::write(&cert_path, "placeholder").unwrap();
+
+        let manifest = Manifest::parse(MANIFEST).unwrap();
+        let mut store = CertStore::open(temp_dir.path().join("store.json")).unwrap();
+        store.record(issued("nas", &["DNS:nas.lab", "IP:10.0.0.5"], 300, &cert_path));
+        store.record(issued("grafana", &["DNS:grafana.lab"], 300, &cert_path));
+        store.record(issued("old", &["DNS:old.lab"], 300, &cert_path));
+        // Issued outside the manifest, so never revoked by it
+        store.record(issued("manual", &["DNS:manual.lab"], 300, &cert_path));
+        store.managed_mut().extend(["nas", "grafana", "old"].map(String::from));
+
+        let pending = diff(&manifest, &store).unwrap();
+        assert!(!pending.is_empty());
+        let removed: Vec<_> = pending.removed.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(removed, vec!["old"]);
+
+        // Once revoked, it is no longer a pending removal; re-adding it issues a new one
+        store.revoke("old", "cessationOfOperation");
+        assert!(diff(&manifest, &store).unwrap().is_empty());
+        let readded = Manifest::parse("certificates:\n  - name: old\n    sans: [\"DNS:old.lab\"]\n").unwrap();
+        assert_eq!(plan(&readded, &store).unwrap()[0].action, PlanAction::Issue);
+    }
+}
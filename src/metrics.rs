@@ -0,0 +1,257 @@
+//! Prometheus metrics for certificate expiry
+//!
+//! Metrics are rendered fresh on every scrape from the certificate store
+//! (latest issuance per name) plus any `<name>.cert.pem` in the output
+//! directory that the store doesn't know about:
+//!
+//! ```text
+//! flux_cert_not_after_timestamp{name="nas",source="store"} 1767225600
+//! flux_cert_expired{name="nas",source="store"} 0
+//! flux_cert_issuances_total{name="nas"} 3
+//! ```
+//!
+//! The exporter is a small blocking HTTP server serving `GET /metrics`.
+
+use crate::config::Config;
+use crate::crypto::cert::asn1_time_to_chrono;
+use crate::crypto::load_cert;
+use crate::error::{FluxError, Result};
+use crate::store::CertStore;
+use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+
+/// Suffix of certificate files in the output directory
+const CERT_SUFFIX: &str = ".cert.pem";
+
+/// Longest request head accepted from a scraper
+const MAX_REQUEST_SIZE: usize = 8 * 1024;
+
+/// Expiry of one certificate
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CertExpiry {
+    pub name: String,
+    /// `store` or `output`
+    pub source: &'static str,
+    pub not_after: DateTime<Utc>,
+}
+
+/// Current snapshot of everything exported
+#[derive(Debug, Default)]
+pub struct Snapshot {
+    pub certificates: Vec<CertExpiry>,
+    /// Issuances recorded in the store, by name
+    pub issuances: BTreeMap<String, usize>,
+    /// Output files that could not be read
+    pub scan_errors: usize,
+}
+
+/// Scan the store and the output directory
+pub fn collect(config: &Config) -> Result<Snapshot> {
+    let store = CertStore::open_default(config)?;
+    let mut snapshot = Snapshot::default();
+
+    for cert in store.certificates() {
+        *snapshot.issuances.entry(cert.name.clone()).or_default() += 1;
+    }
+    for cert in store.current() {
+        snapshot.certificates.push(CertExpiry {
+            name: cert.name.clone(),
+            source: "store",
+            not_after: cert.not_after,
+        });
+    }
+
+    let Ok(entries) = std::fs::read_dir(&config.output_dir) else {
+        return Ok(snapshot);
+    };
+    let mut untracked: Vec<(String, std::path::PathBuf)> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+            let name = file_name.strip_suffix(CERT_SUFFIX)?.to_string();
+            Some((name, entry.path()))
+        })
+        .filter(|(name, _)| !snapshot.issuances.contains_key(name))
+        .collect();
+    untracked.sort();
+
+    for (name, path) in untracked {
+        match load_cert(&path).and_then(|cert| asn1_time_to_chrono(cert.not_after())) {
+            Ok(not_after) => snapshot.certificates.push(CertExpiry { name, source: "output", not_after }),
+            Err(e) => {
+                tracing::debug!("Skipping {}: {}", path.display(), e);
+                snapshot.scan_errors += 1;
+            }
+        }
+    }
+
+    Ok(snapshot)
+}
+
+/// Render a snapshot in the Prometheus text format
+pub fn render(snapshot: &Snapshot, now: DateTime<Utc>) -> String {
+    let mut out = String::new();
+
+    header(&mut out, "flux_cert_not_after_timestamp", "gauge", "Certificate expiry time in Unix seconds");
+    for cert in &snapshot.certificates {
+        let _ = writeln!(out, "flux_cert_not_after_timestamp{} {}", cert_labels(cert), cert.not_after.timestamp());
+    }
+
+    header(&mut out, "flux_cert_expired", "gauge", "1 if the certificate has expired, 0 otherwise");
+    for cert in &snapshot.certificates {
+        let _ = writeln!(out, "flux_cert_expired{} {}", cert_labels(cert), u8::from(cert.not_after <= now));
+    }
+
+    header(&mut out, "flux_cert_issuances_total", "counter", "Certificates issued, from the certificate store");
+    for (name, count) in &snapshot.issuances {
+        let _ = writeln!(out, "flux_cert_issuances_total{{name=\"{}\"}} {}", escape(name), count);
+    }
+
+    header(&mut out, "flux_cert_scan_errors", "gauge", "Certificate files in the output directory that could not be read");
+    let _ = writeln!(out, "flux_cert_scan_errors {}", snapshot.scan_errors);
+
+    out
+}
+
+fn header(out: &mut String, metric: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", metric, help);
+    let _ = writeln!(out, "# TYPE {} {}", metric, kind);
+}
+
+fn cert_labels(cert: &CertExpiry) -> String {
+    format!("{{name=\"{}\",source=\"{}\"}}", escape(&cert.name), cert.source)
+}
+
+/// Escape a label value
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Serve `/metrics` on `listen` until the process is stopped
+pub fn serve(listen: &str, config: &Config) -> Result<()> {
+    let listener = TcpListener::bind(listen)
+        .map_err(|e| FluxError::IoError(std::io::Error::new(e.kind(), format!("{}: {}", listen, e))))?;
+    tracing::info!("Serving metrics on http://{}/metrics", listen);
+
+    for stream in listener.incoming() {
+        let result = stream
+            .map_err(FluxError::from)
+            .and_then(|stream| handle_connection(stream, config));
+        if let Err(e) = result {
+            tracing::warn!("Metrics request failed: {}", e);
+        }
+    }
+
+    Ok(())
+}
+
+fn handle_connection(mut stream: TcpStream, config: &Config) -> Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    stream.set_write_timeout(Some(Duration::from_secs(5)))?;
+
+    let path = read_request_path(&mut stream)?;
+    let (status, content_type, body) = match path.as_deref() {
+        Some("/metrics") => match collect(config) {
+            Ok(snapshot) => ("200 OK", "text/plain; version=0.0.4", render(&snapshot, Utc::now())),
+            Err(e) => ("500 Internal Server Error", "text/plain", format!("{}\n", e)),
+        },
+        Some("/") => ("200 OK", "text/html", "<a href=\"/metrics\">metrics</a>\n".to_string()),
+        Some(_) => ("404 Not Found", "text/plain", "not found\n".to_string()),
+        None => ("400 Bad Request", "text/plain", "bad request\n".to_string()),
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )?;
+    Ok(())
+}
+
+/// Path of a `GET` request, after reading (and discarding) its headers
+fn read_request_path(stream: &mut TcpStream) -> Result<Option<String>> {
+    let mut reader = BufReader::new(stream).take(MAX_REQUEST_SIZE as u64);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 0 && line.trim_end() != "" {
+        line.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    Ok(match (parts.next(), parts.next()) {
+        (Some("GET"), Some(target)) => Some(target.split('?').next().unwrap_or(target).to_string()),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::{record_issuance, IssuedCert};
+    use chrono::TimeZone;
+
+    fn issued(name: &str, not_after: DateTime<Utc>) -> IssuedCert {
+        IssuedCert {
+            name: name.to_string(),
+            serial: "01".to_string(),
+            subject: format!("CN={}", name),
+            sans: Vec::new(),
+            not_before: not_after - chrono::Duration::days(90),
+            not_after,
+            issued_at: not_after - chrono::Duration::days(90),
+            cert_path: format!("/tmp/{}.cert.pem", name).into(),
+            key_path: None,
+            key_encrypted: false,
+            revoked_at: None,
+            revocation_reason: None,
+            endpoints: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_collect_and_render() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let config = Config {
+            working_dir: temp_dir.path().join("pki"),
+            output_dir: temp_dir.path().join("out"),
+            ..Config::default()
+        };
+        let expired = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let valid = Utc.with_ymd_and_hms(2040, 1, 1, 0, 0, 0).unwrap();
+        record_issuance(&config, issued("nas", expired)).unwrap();
+        record_issuance(&config, issued("nas", valid)).unwrap();
+
+        // An untracked certificate in the output directory, plus an unreadable one
+        std::fs::create_dir_all(&config.output_dir).unwrap();
+        let ca = crate::fixtures::intermediate_ca();
+        std::fs::write(config.output_dir.join("legacy.cert.pem"), ca.cert().to_pem().unwrap()).unwrap();
+        std::fs::write(config.output_dir.join("broken.cert.pem"), "garbage").unwrap();
+        std::fs::write(config.output_dir.join("nas.key.pem"), "ignored").unwrap();
+
+        let snapshot = collect(&config).unwrap();
+        assert_eq!(snapshot.issuances.get("nas"), Some(&2));
+        assert_eq!(snapshot.scan_errors, 1);
+
+        let text = render(&snapshot, Utc.with_ymd_and_hms(2030, 1, 1, 0, 0, 0).unwrap());
+        assert!(text.contains(&format!("flux_cert_not_after_timestamp{{name=\"nas\",source=\"store\"}} {}", valid.timestamp())));
+        assert!(text.contains("flux_cert_expired{name=\"nas\",source=\"store\"} 0"));
+        assert!(text.contains("flux_cert_expired{name=\"legacy\",source=\"output\"} 0"));
+        assert!(text.contains("flux_cert_issuances_total{name=\"nas\"} 2"));
+        assert!(text.contains("# TYPE flux_cert_issuances_total counter"));
+        assert!(!text.contains("broken"));
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape(r#"a"b\c"#), r#"a\"b\\c"#);
+    }
+}
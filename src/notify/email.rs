@@ -0,0 +1,108 @@
+//! SMTP delivery of notification emails
+
+use super::{Digest, Sink};
+use crate::config::{EmailConfig, SinkOptions, SmtpSecurity};
+use crate::error::{FluxError, Result};
+use crate::fsutil::read_to_string_bounded;
+use lettre::message::{header::ContentType, Mailbox};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{Message, SmtpTransport, Transport};
+use zeroize::Zeroizing;
+
+/// Emails digests to the configured recipients
+pub struct EmailSink<'a>(pub &'a EmailConfig);
+
+impl Sink for EmailSink<'_> {
+    fn name(&self) -> String {
+        "email".to_string()
+    }
+
+    fn options(&self) -> &SinkOptions {
+        &self.0.options
+    }
+
+    fn send(&self, digest: &Digest) -> Result<()> {
+        send(self.0, &digest.subject, &digest.body)
+    }
+}
+
+/// Build a plain-text message to every configured recipient
+pub fn build_message(email: &EmailConfig, subject: &str, body: &str) -> Result<Message> {
+    let mailbox = |address: &str| {
+        address.parse::<Mailbox>()
+            .map_err(|e| FluxError::InvalidConfigValue("notify.email".to_string(), format!("'{}': {}", address, e)))
+    };
+
+    if email.to.is_empty() {
+        return Err(FluxError::InvalidConfigValue("notify.email.to".to_string(), "no recipients".to_string()));
+    }
+
+    let mut builder = Message::builder()
+        .from(mailbox(&email.from)?)
+        .subject(subject)
+        .header(ContentType::TEXT_PLAIN);
+    for to in &email.to {
+        builder = builder.to(mailbox(to)?);
+    }
+
+    builder.body(body.to_string()).map_err(|e| FluxError::NotifyError(e.to_string()))
+}
+
+/// Send a message through the configured SMTP server
+pub fn send(email: &EmailConfig, subject: &str, body: &str) -> Result<()> {
+    let message = build_message(email, subject, body)?;
+    let failed = |e: lettre::transport::smtp::Error| FluxError::NotifyError(format!("{}: {}", email.server, e));
+
+    let mut transport = match email.security {
+        SmtpSecurity::Starttls => SmtpTransport::starttls_relay(&email.server).map_err(failed)?,
+        SmtpSecurity::Tls => SmtpTransport::relay(&email.server).map_err(failed)?,
+        SmtpSecurity::None => SmtpTransport::builder_dangerous(&email.server).port(25),
+    };
+    if let Some(port) = email.port {
+        transport = transport.port(port);
+    }
+    if let Some(username) = &email.username {
+        let password = match &email.password_file {
+            Some(path) => Zeroizing::new(read_to_string_bounded(path)?),
+            None => return Err(FluxError::MissingConfig("notify.email.password_file".to_string())),
+        };
+        let password = password.trim_end_matches(['\r', '\n']).to_string();
+        transport = transport.credentials(Credentials::new(username.clone(), password));
+    }
+
+    transport.build().send(&message).map_err(failed)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn email_config() -> EmailConfig {
+        EmailConfig {
+            server: "smtp.lab".to_string(),
+            port: None,
+            security: SmtpSecurity::default(),
+            username: None,
+            password_file: None,
+            from: "PKI <pki@lab.internal>".to_string(),
+            to: vec!["admin@lab.internal".to_string(), "ops@lab.internal".to_string()],
+            options: SinkOptions::default(),
+        }
+    }
+
+    #[test]
+    fn test_build_message() {
+        let message = build_message(&email_config(), "[flux-ssl-mgr] nas expires in 5 days", "body\n").unwrap();
+        let text = String::from_utf8(message.formatted()).unwrap();
+        assert!(text.contains("From: PKI <pki@lab.internal>"), "{}", text);
+        assert!(text.contains("To: admin@lab.internal, ops@lab.internal"), "{}", text);
+        assert!(text.contains("Subject: [flux-ssl-mgr] nas expires in 5 days"), "{}", text);
+
+        let mut bad = email_config();
+        bad.to = vec!["not an address".to_string()];
+        assert!(build_message(&bad, "s", "b").is_err());
+        bad.to.clear();
+        assert!(build_message(&bad, "s", "b").is_err());
+    }
+}
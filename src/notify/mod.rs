@@ -0,0 +1,357 @@
+//! Expiry notifications
+//!
+//! Notifications go to sinks (`[notify.email]`, `[notify.mqtt]` and every
+//! `[[notify.webhook]]` subscribed to `expiring` events). Each uses
+//! the `[notify]` thresholds and schedule unless it sets its own:
+//!
+//! - `immediate`: each certificate is announced once per threshold it drops
+//!   below, and everything due in one run goes out as a single digest.
+//! - `daily HH:MM` / `weekly <day> HH:MM`: on the first run after the
+//!   scheduled time, a summary of every certificate below the largest threshold.
+//!
+//! What each sink has been sent is kept in `flux-notify.json` next to the store.
+
+#[cfg(feature = "email")]
+pub mod email;
+pub mod mqtt;
+pub mod schedule;
+pub mod webhook;
+
+use crate::config::{Config, SinkOptions};
+use crate::duration::Days;
+use crate::error::{FluxError, Result};
+use crate::store::{CertStore, IssuedCert};
+use chrono::{DateTime, Local, Utc};
+use schedule::Schedule;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::path::Path;
+
+/// Somewhere notifications are delivered
+pub trait Sink {
+    /// Name used in the notification state and reports
+    fn name(&self) -> String;
+
+    /// Thresholds and schedule overrides
+    fn options(&self) -> &SinkOptions;
+
+    /// Deliver a digest
+    fn send(&self, digest: &Digest) -> Result<()>;
+}
+
+/// A certificate below a notification threshold
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Notice {
+    pub name: String,
+    pub serial: String,
+    pub not_after: DateTime<Utc>,
+    /// Whole days until expiry (negative once expired)
+    pub days_remaining: i64,
+    /// Lowest threshold crossed
+    pub threshold: Days,
+}
+
+impl Notice {
+    /// Notice for a certificate below any of `thresholds`
+    fn crossed(cert: &IssuedCert, thresholds: &[Days], now: DateTime<Utc>) -> Option<Self> {
+        let days_remaining = (cert.not_after - now).num_days();
+        let threshold = thresholds.iter()
+            .copied()
+            .filter(|t| cert.not_after <= now || days_remaining < i64::from(t.get()))
+            .min()?;
+
+        Some(Notice {
+            name: cert.name.clone(),
+            serial: cert.serial.clone(),
+            not_after: cert.not_after,
+            days_remaining,
+            threshold,
+        })
+    }
+}
+
+/// Current certificates below a threshold not yet notified about, soonest first
+///
+/// `notified` maps serials to the lowest threshold already sent.
+pub fn due_notices(store: &CertStore, thresholds: &[Days], notified: &BTreeMap<String, Days>, now: DateTime<Utc>) -> Vec<Notice> {
+    let mut notices: Vec<Notice> = store.current()
+        .into_iter()
+        .filter_map(|cert| Notice::crossed(cert, thresholds, now))
+        .filter(|notice| notified.get(&notice.serial).is_none_or(|sent| *sent > notice.threshold))
+        .collect();
+
+    notices.sort_by_key(|n| n.not_after);
+    notices
+}
+
+/// Every current certificate below any threshold, soonest first
+pub fn summary_notices(store: &CertStore, thresholds: &[Days], now: DateTime<Utc>) -> Vec<Notice> {
+    due_notices(store, thresholds, &BTreeMap::new(), now)
+}
+
+/// One message covering every due notice
+#[derive(Debug, Clone, Serialize)]
+pub struct Digest {
+    pub notices: Vec<Notice>,
+    pub subject: String,
+    /// Plain-text body
+    pub body: String,
+}
+
+/// Build the digest message for a set of notices
+pub fn digest(notices: &[Notice], now: DateTime<Utc>) -> Digest {
+    let expired = notices.iter().filter(|n| n.not_after <= now).count();
+    let subject = match (notices.len(), expired) {
+        (1, 1) => format!("[flux-ssl-mgr] {} has expired", notices[0].name),
+        (1, _) => format!("[flux-ssl-mgr] {} expires in {} days", notices[0].name, notices[0].days_remaining),
+        (n, 0) => format!("[flux-ssl-mgr] {} certificates expiring soon", n),
+        (n, e) => format!("[flux-ssl-mgr] {} certificates expiring soon ({} expired)", n, e),
+    };
+
+    let width = notices.iter().map(|n| n.name.len()).max().unwrap_or(0);
+    let mut body = String::from("The following certificates need renewing:\n\n");
+    for notice in notices {
+        let when = if notice.not_after <= now {
+            "EXPIRED".to_string()
+        } else {
+            format!("expires in {} days", notice.days_remaining)
+        };
+        let _ = writeln!(
+            body,
+            "  {:width$}  {:<20} {}  serial {}",
+            notice.name,
+            when,
+            notice.not_after.format("%Y-%m-%d %H:%M UTC"),
+            notice.serial,
+            width = width
+        );
+    }
+    body.push_str("\nRenew with `flux-ssl-mgr apply` or `flux-ssl-mgr single --name <name>`.\n");
+
+    Digest { notices: notices.to_vec(), subject, body }
+}
+
+/// What a sink has been sent
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct SinkState {
+    /// Last scheduled summary run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_summary: Option<DateTime<Utc>>,
+
+    /// Lowest threshold announced, by serial (immediate mode)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    notified: BTreeMap<String, Days>,
+}
+
+/// Notification state of every sink
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct NotifyState {
+    #[serde(default)]
+    sinks: BTreeMap<String, SinkState>,
+}
+
+impl NotifyState {
+    fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let data = std::fs::read_to_string(path)
+            .map_err(|e| FluxError::FileReadFailed(path.to_path_buf(), e.to_string()))?;
+        serde_json::from_str(&data)
+            .map_err(|e| FluxError::NotifyError(format!("{}: {}", path.display(), e)))
+    }
+
+    fn save(&self, path: &Path) -> Result<()> {
+        let data = serde_json::to_string_pretty(self)
+            .map_err(|e| FluxError::NotifyError(e.to_string()))?;
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let tmp_path = path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, data)
+            .map_err(|e| FluxError::FileWriteFailed(tmp_path.clone(), e.to_string()))?;
+        std::fs::rename(&tmp_path, path)
+            .map_err(|e| FluxError::FileWriteFailed(path.to_path_buf(), e.to_string()))
+    }
+}
+
+/// Configured sinks
+pub fn sinks(config: &Config) -> Result<Vec<Box<dyn Sink + '_>>> {
+    let mut sinks: Vec<Box<dyn Sink + '_>> = Vec::new();
+
+    if let Some(email) = &config.notify.email {
+        #[cfg(feature = "email")]
+        sinks.push(Box::new(email::EmailSink(email)));
+
+        #[cfg(not(feature = "email"))]
+        {
+            let _ = email;
+            return Err(FluxError::NotifyError("notify.email is set but built without the email feature".to_string()));
+        }
+    }
+    if let Some(mqtt) = &config.notify.mqtt {
+        sinks.push(Box::new(mqtt::MqttSink(mqtt)));
+    }
+    for hook in config.notify.webhooks.iter().filter(|w| webhook::subscribed(w, webhook::EventKind::Expiring)) {
+        sinks.push(Box::new(webhook::WebhookSink(hook)));
+    }
+
+    if sinks.is_empty() {
+        return Err(FluxError::MissingConfig("notify.email, notify.mqtt or notify.webhook".to_string()));
+    }
+
+    // Sink names key the notification state
+    let mut names: Vec<String> = sinks.iter().map(|s| s.name()).collect();
+    names.sort();
+    if let Some(pair) = names.windows(2).find(|pair| pair[0] == pair[1]) {
+        return Err(FluxError::InvalidConfigValue("notify.webhook.name".to_string(), format!("'{}' is used twice", pair[0])));
+    }
+    Ok(sinks)
+}
+
+/// Outcome of a notification run for one sink
+#[derive(Debug)]
+pub struct SinkReport {
+    pub sink: String,
+    /// What was (or, in a dry run, would have been) sent
+    pub digest: Option<Digest>,
+    /// Delivery error
+    pub error: Option<String>,
+}
+
+/// Send whatever is due to every sink and remember what was sent
+///
+/// A failing sink doesn't stop the others. With `dry_run` nothing is sent
+/// or recorded.
+pub fn notify(config: &Config, dry_run: bool) -> Result<Vec<SinkReport>> {
+    let store = CertStore::open_default(config)?;
+    let state_path = config.notify_state_path();
+    let mut state = NotifyState::load(&state_path)?;
+    let now = Utc::now();
+    let local_now = now.with_timezone(&Local).naive_local();
+
+    let mut reports = Vec::new();
+    for sink in sinks(config)? {
+        let thresholds = sink.options().thresholds.as_deref().unwrap_or(&config.notify.thresholds);
+        let schedule = sink.options().schedule.unwrap_or(config.notify.schedule);
+        let sink_state = state.sinks.entry(sink.name()).or_default();
+
+        let last_summary = sink_state.last_summary.map(|t| t.with_timezone(&Local).naive_local());
+        let summary_due = schedule != Schedule::Immediate && schedule.is_due(last_summary, local_now);
+        let notices = match schedule {
+            Schedule::Immediate => due_notices(&store, thresholds, &sink_state.notified, now),
+            _ if summary_due => summary_notices(&store, thresholds, now),
+            _ => Vec::new(),
+        };
+
+        let mut report = SinkReport { sink: sink.name(), digest: None, error: None };
+        if !notices.is_empty() {
+            let mut message = digest(&notices, now);
+            if let Schedule::Daily(_) | Schedule::Weekly(..) = schedule {
+                message.subject = summary_subject(schedule, &notices);
+            }
+
+            if !dry_run {
+                match sink.send(&message) {
+                    Ok(()) => {
+                        for notice in &message.notices {
+                            sink_state.notified.insert(notice.serial.clone(), notice.threshold);
+                        }
+                    }
+                    Err(e) => report.error = Some(e.to_string()),
+                }
+            }
+            report.digest = Some(message);
+        }
+
+        // A failed summary is retried on the next run
+        if summary_due && !dry_run && report.error.is_none() {
+            sink_state.last_summary = Some(now);
+        }
+        reports.push(report);
+    }
+
+    if !dry_run {
+        // Forget certificates that were renewed or revoked since
+        let current: Vec<&str> = store.current().iter().map(|c| c.serial.as_str()).collect();
+        for sink_state in state.sinks.values_mut() {
+            sink_state.notified.retain(|serial, _| current.contains(&serial.as_str()));
+        }
+        state.save(&state_path)?;
+    }
+
+    Ok(reports)
+}
+
+fn summary_subject(schedule: Schedule, notices: &[Notice]) -> String {
+    let period = if matches!(schedule, Schedule::Weekly(..)) { "Weekly" } else { "Daily" };
+    format!("[flux-ssl-mgr] {} certificate summary: {} need renewing", period, notices.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::IssuedCert;
+    use chrono::{Duration, TimeZone};
+
+    fn now() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 10, 17, 12, 0, 0).unwrap()
+    }
+
+    fn issued(name: &str, days_left: i64) -> IssuedCert {
+        IssuedCert {
+            name: name.to_string(),
+            serial: name.to_string(),
+            subject: format!("CN={}", name),
+            sans: Vec::new(),
+            not_before: now() - Duration::days(300),
+            not_after: now() + Duration::days(days_left) + Duration::hours(1),
+            issued_at: now() - Duration::days(300),
+            cert_path: format!("/tmp/{}.cert.pem", name).into(),
+            key_path: None,
+            key_encrypted: false,
+            revoked_at: None,
+            revocation_reason: None,
+            endpoints: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_due_notices() {
+        let thresholds = [Days::new(30), Days::new(14), Days::new(7)];
+        let mut store = CertStore::default();
+        for (name, days_left) in [("fresh", 90), ("nas", 20), ("grafana", 20), ("router", 5), ("old", -2)] {
+            store.record(issued(name, days_left));
+        }
+        let notified = BTreeMap::from([
+            ("grafana".to_string(), Days::new(30)),
+            ("router".to_string(), Days::new(30)),
+            ("old".to_string(), Days::new(7)),
+        ]);
+
+        let notices = due_notices(&store, &thresholds, &notified, now());
+        let due: Vec<_> = notices.iter().map(|n| (n.name.as_str(), n.threshold.get())).collect();
+        assert_eq!(due, vec![("router", 7), ("nas", 30)]);
+
+        // A summary lists everything below a threshold, whatever was sent before
+        assert_eq!(summary_notices(&store, &thresholds, now()).len(), 4);
+    }
+
+    #[test]
+    fn test_digest() {
+        let mut store = CertStore::default();
+        store.record(issued("nas", 20));
+        store.record(issued("old", -2));
+        let notices = summary_notices(&store, &[Days::new(30)], now());
+
+        let message = digest(&notices, now());
+        assert_eq!(message.subject, "[flux-ssl-mgr] 2 certificates expiring soon (1 expired)");
+        assert!(message.body.contains("  old  EXPIRED"), "{}", message.body);
+        assert!(message.body.contains("  nas  expires in 20 days"), "{}", message.body);
+
+        assert_eq!(digest(&notices[1..], now()).subject, "[flux-ssl-mgr] nas expires in 20 days");
+    }
+}
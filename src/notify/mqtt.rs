@@ -0,0 +1,166 @@
+//! MQTT delivery of notification digests
+//!
+//! A minimal MQTT 3.1.1 publisher: connect, publish one QoS 0 message with
+//! the digest as JSON, disconnect. Plain TCP only, so point it at a broker
+//! on a trusted network (or a local bridge).
+
+use super::{Digest, Sink};
+use crate::config::{MqttConfig, SinkOptions};
+use crate::error::{FluxError, Result};
+use crate::fsutil::read_to_string_bounded;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+use zeroize::Zeroizing;
+
+const CONNECT: u8 = 0x10;
+const CONNACK: u8 = 0x20;
+const PUBLISH: u8 = 0x30;
+const DISCONNECT: u8 = 0xe0;
+
+/// Publishes digests to an MQTT topic
+pub struct MqttSink<'a>(pub &'a MqttConfig);
+
+impl Sink for MqttSink<'_> {
+    fn name(&self) -> String {
+        "mqtt".to_string()
+    }
+
+    fn options(&self) -> &SinkOptions {
+        &self.0.options
+    }
+
+    fn send(&self, digest: &Digest) -> Result<()> {
+        let payload = serde_json::to_vec(digest).map_err(|e| FluxError::NotifyError(e.to_string()))?;
+        publish(self.0, &payload)
+    }
+}
+
+/// Publish one message
+pub fn publish(config: &MqttConfig, payload: &[u8]) -> Result<()> {
+    let failed = |e: std::io::Error| FluxError::NotifyError(format!("MQTT broker {}: {}", config.broker, e));
+
+    let password = match &config.password_file {
+        Some(path) => Some(Zeroizing::new(read_to_string_bounded(path)?.trim_end_matches(['\r', '\n']).to_string())),
+        None => None,
+    };
+
+    let mut stream = TcpStream::connect(&config.broker).map_err(failed)?;
+    stream.set_read_timeout(Some(Duration::from_secs(10))).map_err(failed)?;
+    stream.set_write_timeout(Some(Duration::from_secs(10))).map_err(failed)?;
+
+    let connect = connect_packet(&config.client_id, config.username.as_deref(), password.as_deref().map(String::as_str));
+    stream.write_all(&connect).map_err(failed)?;
+
+    let mut connack = [0u8; 4];
+    stream.read_exact(&mut connack).map_err(failed)?;
+    if connack[0] != CONNACK || connack[3] != 0 {
+        return Err(FluxError::NotifyError(format!(
+            "MQTT broker {} refused the connection (return code {})",
+            config.broker, connack[3]
+        )));
+    }
+
+    stream.write_all(&publish_packet(&config.topic, payload, config.retain)).map_err(failed)?;
+    stream.write_all(&[DISCONNECT, 0]).map_err(failed)?;
+    Ok(())
+}
+
+fn connect_packet(client_id: &str, username: Option<&str>, password: Option<&str>) -> Vec<u8> {
+    let mut flags = 0x02; // clean session
+    if username.is_some() {
+        flags |= 0x80;
+    }
+    if password.is_some() {
+        flags |= 0x40;
+    }
+
+    let mut body = Vec::new();
+    put_str(&mut body, "MQTT");
+    body.extend_from_slice(&[4, flags, 0, 60]); // protocol level 4, keep-alive 60s
+    put_str(&mut body, client_id);
+    for field in [username, password].into_iter().flatten() {
+        put_str(&mut body, field);
+    }
+
+    packet(CONNECT, &body)
+}
+
+fn publish_packet(topic: &str, payload: &[u8], retain: bool) -> Vec<u8> {
+    let mut body = Vec::new();
+    put_str(&mut body, topic);
+    body.extend_from_slice(payload);
+
+    packet(PUBLISH | u8::from(retain), &body)
+}
+
+fn packet(header: u8, body: &[u8]) -> Vec<u8> {
+    let mut out = vec![header];
+    out.extend(remaining_length(body.len()));
+    out.extend_from_slice(body);
+    out
+}
+
+/// Variable-length "remaining length" encoding
+fn remaining_length(mut len: usize) -> Vec<u8> {
+    let mut out = Vec::new();
+    loop {
+        let mut byte = (len % 128) as u8;
+        len /= 128;
+        if len > 0 {
+            byte |= 0x80;
+        }
+        out.push(byte);
+        if len == 0 {
+            return out;
+        }
+    }
+}
+
+fn put_str(out: &mut Vec<u8>, s: &str) {
+    out.extend_from_slice(&(s.len() as u16).to_be_bytes());
+    out.extend_from_slice(s.as_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_remaining_length() {
+        assert_eq!(remaining_length(0), vec![0]);
+        assert_eq!(remaining_length(127), vec![0x7f]);
+        assert_eq!(remaining_length(128), vec![0x80, 0x01]);
+        assert_eq!(remaining_length(16_384), vec![0x80, 0x80, 0x01]);
+    }
+
+    #[test]
+    fn test_publish_to_broker() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let config = MqttConfig {
+            broker: listener.local_addr().unwrap().to_string(),
+            username: Some("pki".to_string()),
+            ..MqttConfig::default()
+        };
+
+        let broker = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut received = Vec::new();
+            let mut buf = [0u8; 512];
+            let n = stream.read(&mut buf).unwrap();
+            received.extend_from_slice(&buf[..n]);
+            stream.write_all(&[CONNACK, 2, 0, 0]).unwrap();
+            stream.read_to_end(&mut received).unwrap();
+            received
+        });
+
+        publish(&config, b"{}").unwrap();
+        let received = broker.join().unwrap();
+
+        let connect = connect_packet("flux-ssl-mgr", Some("pki"), None);
+        assert_eq!(&received[..connect.len()], &connect[..]);
+        let rest = &received[connect.len()..];
+        assert_eq!(rest, [publish_packet("flux-ssl-mgr/expiry", b"{}", false), vec![DISCONNECT, 0]].concat());
+    }
+}
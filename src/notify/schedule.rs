@@ -0,0 +1,131 @@
+//! Digest schedules
+//!
+//! `immediate` notifies as soon as a certificate crosses a threshold.
+//! `daily 08:00` and `weekly mon 08:00` send a summary of everything below
+//! the thresholds at that local time (on the first run at or after it).
+
+use crate::error::{FluxError, Result};
+use chrono::{Datelike, Duration, NaiveDateTime, NaiveTime, Weekday};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// When a sink sends notifications
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Schedule {
+    /// As soon as a certificate crosses a threshold
+    #[default]
+    Immediate,
+    /// A summary every day at this time
+    Daily(NaiveTime),
+    /// A summary every week on this day at this time
+    Weekly(Weekday, NaiveTime),
+}
+
+impl Schedule {
+    /// Most recent scheduled time at or before `now` (`None` for immediate)
+    pub fn last_slot(&self, now: NaiveDateTime) -> Option<NaiveDateTime> {
+        let (slot, period) = match *self {
+            Schedule::Immediate => return None,
+            Schedule::Daily(time) => (now.date().and_time(time), Duration::days(1)),
+            Schedule::Weekly(day, time) => {
+                let days_back = (now.weekday().num_days_from_monday() + 7 - day.num_days_from_monday()) % 7;
+                ((now.date() - Duration::days(i64::from(days_back))).and_time(time), Duration::days(7))
+            }
+        };
+
+        Some(if slot <= now { slot } else { slot - period })
+    }
+
+    /// Whether a summary is due, given when the last one was sent
+    pub fn is_due(&self, last_sent: Option<NaiveDateTime>, now: NaiveDateTime) -> bool {
+        match (self.last_slot(now), last_sent) {
+            (Some(slot), Some(last)) => last < slot,
+            _ => true,
+        }
+    }
+}
+
+impl FromStr for Schedule {
+    type Err = FluxError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || FluxError::InvalidConfigValue(
+            "notify.schedule".to_string(),
+            format!("'{}' (expected immediate, daily HH:MM or weekly <day> HH:MM)", s),
+        );
+        let time = |t: &str| NaiveTime::parse_from_str(t, "%H:%M").map_err(|_| invalid());
+
+        let parts: Vec<String> = s.split_whitespace().map(str::to_ascii_lowercase).collect();
+        match parts.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+            ["immediate"] => Ok(Schedule::Immediate),
+            ["daily", t] => Ok(Schedule::Daily(time(t)?)),
+            ["weekly", day, t] => Ok(Schedule::Weekly(day.parse().map_err(|_| invalid())?, time(t)?)),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Schedule::Immediate => write!(f, "immediate"),
+            Schedule::Daily(time) => write!(f, "daily {}", time.format("%H:%M")),
+            Schedule::Weekly(day, time) => write!(f, "weekly {} {}", day, time.format("%H:%M")),
+        }
+    }
+}
+
+impl TryFrom<String> for Schedule {
+    type Error = FluxError;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+impl From<Schedule> for String {
+    fn from(schedule: Schedule) -> Self {
+        schedule.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn at(day: u32, hour: u32, min: u32) -> NaiveDateTime {
+        // 2026-10-12 is a Monday
+        NaiveDate::from_ymd_opt(2026, 10, day).unwrap().and_hms_opt(hour, min, 0).unwrap()
+    }
+
+    #[test]
+    fn test_parse_schedule() {
+        assert_eq!("immediate".parse::<Schedule>().unwrap(), Schedule::Immediate);
+        let daily: Schedule = "daily 08:30".parse().unwrap();
+        assert_eq!(daily.to_string(), "daily 08:30");
+        let weekly: Schedule = "Weekly MONDAY 07:00".parse().unwrap();
+        assert_eq!(weekly.to_string(), "weekly Mon 07:00");
+        assert!("hourly".parse::<Schedule>().is_err());
+        assert!("daily 25:00".parse::<Schedule>().is_err());
+    }
+
+    #[test]
+    fn test_due() {
+        let daily: Schedule = "daily 08:00".parse().unwrap();
+        assert_eq!(daily.last_slot(at(14, 7, 0)), Some(at(13, 8, 0)));
+        assert!(daily.is_due(None, at(14, 7, 0)));
+        assert!(!daily.is_due(Some(at(13, 9, 0)), at(14, 7, 59)));
+        assert!(daily.is_due(Some(at(13, 9, 0)), at(14, 8, 0)));
+
+        let weekly: Schedule = "weekly mon 08:00".parse().unwrap();
+        assert_eq!(weekly.last_slot(at(12, 8, 0)), Some(at(12, 8, 0)));
+        assert_eq!(weekly.last_slot(at(12, 7, 0)), Some(at(5, 8, 0)));
+        assert_eq!(weekly.last_slot(at(17, 12, 0)), Some(at(12, 8, 0)));
+        assert!(!weekly.is_due(Some(at(12, 8, 5)), at(18, 23, 0)));
+
+        assert!(Schedule::Immediate.is_due(Some(at(17, 12, 0)), at(17, 12, 0)));
+    }
+}
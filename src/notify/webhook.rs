@@ -0,0 +1,370 @@
+//! Webhook delivery of certificate events
+//!
+//! Every `[[notify.webhook]]` receives JSON POSTs for the events it subscribes
+//! to: `issued`, `renewed` and `revoked` as they happen, and `expiring`
+//! digests from `flux-ssl-mgr notify`. With a `secret_file`, each request
+//! carries `X-Flux-Signature: sha256=<hex HMAC-SHA256 of the body>`.
+
+use super::{Digest, Sink};
+use crate::config::{Config, SinkOptions, WebhookConfig};
+use crate::crypto::CertificateInfo;
+use crate::error::{FluxError, Result};
+use crate::fsutil::read_to_string_bounded;
+use crate::store::IssuedCert;
+use chrono::{DateTime, Utc};
+use openssl::hash::MessageDigest;
+use openssl::pkey::PKey;
+use openssl::sign::Signer;
+use openssl::ssl::{SslConnector, SslMethod};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write as _;
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+use zeroize::Zeroizing;
+
+/// Header carrying the body signature
+pub const SIGNATURE_HEADER: &str = "X-Flux-Signature";
+
+/// Largest response read back from a webhook
+const MAX_RESPONSE: u64 = 64 * 1024;
+
+/// Limit on connecting, and on each read and write
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Kinds of events a webhook can subscribe to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EventKind {
+    Issued,
+    Renewed,
+    Revoked,
+    /// Expiry digests from `flux-ssl-mgr notify`
+    Expiring,
+}
+
+impl EventKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            EventKind::Issued => "issued",
+            EventKind::Renewed => "renewed",
+            EventKind::Revoked => "revoked",
+            EventKind::Expiring => "expiring",
+        }
+    }
+}
+
+/// A certificate lifecycle event
+#[derive(Debug, Clone, Serialize)]
+pub struct Event {
+    pub event: EventKind,
+    pub name: String,
+    pub serial: String,
+    pub subject: String,
+    pub sans: Vec<String>,
+    pub not_after: DateTime<Utc>,
+    /// Revocation reason
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl Event {
+    /// Event for a stored certificate record
+    pub fn new(event: EventKind, cert: &IssuedCert) -> Self {
+        Event {
+            event,
+            name: cert.name.clone(),
+            serial: cert.serial.clone(),
+            subject: cert.subject.clone(),
+            sans: cert.sans.clone(),
+            not_after: cert.not_after,
+            reason: cert.revocation_reason.clone(),
+            timestamp: Utc::now(),
+        }
+    }
+
+    /// Event for a certificate that isn't in the store (web issuance)
+    pub fn from_info(event: EventKind, name: &str, info: &CertificateInfo) -> Self {
+        Event {
+            event,
+            name: name.to_string(),
+            serial: info.serial_number.clone(),
+            subject: info.subject.clone(),
+            sans: info.sans.clone(),
+            not_after: info.not_after,
+            reason: None,
+            timestamp: Utc::now(),
+        }
+    }
+}
+
+/// Payload of an `expiring` delivery
+#[derive(Serialize)]
+struct Expiring<'a> {
+    event: EventKind,
+    timestamp: DateTime<Utc>,
+    #[serde(flatten)]
+    digest: &'a Digest,
+}
+
+/// Posts expiry digests to a webhook
+pub struct WebhookSink<'a>(pub &'a WebhookConfig);
+
+impl Sink for WebhookSink<'_> {
+    fn name(&self) -> String {
+        self.0.name.clone()
+    }
+
+    fn options(&self) -> &SinkOptions {
+        &self.0.options
+    }
+
+    fn send(&self, digest: &Digest) -> Result<()> {
+        let payload = Expiring { event: EventKind::Expiring, timestamp: Utc::now(), digest };
+        deliver(self.0, EventKind::Expiring, &payload)
+    }
+}
+
+/// Whether a webhook wants events of this kind (all kinds if it lists none)
+pub fn subscribed(webhook: &WebhookConfig, kind: EventKind) -> bool {
+    webhook.events.is_empty() || webhook.events.contains(&kind)
+}
+
+/// Send an event to every webhook subscribed to it
+///
+/// Failures are logged rather than returned: the certificate was issued or
+/// revoked either way.
+pub fn emit(config: &Config, event: &Event) {
+    for webhook in config.notify.webhooks.iter().filter(|w| subscribed(w, event.event)) {
+        if let Err(e) = deliver(webhook, event.event, event) {
+            tracing::warn!("Webhook {} failed for {} {}: {}", webhook.name, event.event.as_str(), event.name, e);
+        }
+    }
+}
+
+/// Serialize, sign and POST a payload
+fn deliver<T: Serialize>(webhook: &WebhookConfig, kind: EventKind, payload: &T) -> Result<()> {
+    let body = serde_json::to_vec(payload).map_err(|e| FluxError::NotifyError(e.to_string()))?;
+
+    let mut headers: Vec<(String, String)> = webhook.headers.iter()
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect();
+    headers.push(("X-Flux-Event".to_string(), kind.as_str().to_string()));
+    if let Some(path) = &webhook.secret_file {
+        let secret = Zeroizing::new(read_to_string_bounded(path)?);
+        let secret = secret.trim_end_matches(['\r', '\n']);
+        headers.push((SIGNATURE_HEADER.to_string(), format!("sha256={}", sign(secret.as_bytes(), &body)?)));
+    }
+
+    post(&webhook.url, &headers, &body)
+        .map_err(|e| FluxError::NotifyError(format!("webhook {}: {}", webhook.name, e)))
+}
+
+/// Hex HMAC-SHA256 of `body`
+pub fn sign(secret: &[u8], body: &[u8]) -> Result<String> {
+    let key = PKey::hmac(secret)?;
+    let mut signer = Signer::new(MessageDigest::sha256(), &key)?;
+    let mac = signer.sign_oneshot_to_vec(body)?;
+
+    Ok(mac.iter().fold(String::with_capacity(mac.len() * 2), |mut hex, b| {
+        let _ = write!(hex, "{:02x}", b);
+        hex
+    }))
+}
+
+/// Parts of an http(s) URL
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Target<'a> {
+    pub tls: bool,
+    /// `host[:port]`, as sent in the Host header
+    pub authority: &'a str,
+    pub host: &'a str,
+    pub port: u16,
+    /// Path and query
+    pub path: &'a str,
+}
+
+/// Split an http(s) URL; `key` names the setting in errors
+pub(crate) fn parse_url<'a>(url: &'a str, key: &str) -> Result<Target<'a>> {
+    let invalid = |why: &str| FluxError::InvalidConfigValue(key.to_string(), format!("'{}': {}", url, why));
+
+    let (tls, rest) = if let Some(rest) = url.strip_prefix("https://") {
+        (true, rest)
+    } else if let Some(rest) = url.strip_prefix("http://") {
+        (false, rest)
+    } else {
+        return Err(invalid("expected http:// or https://"));
+    };
+
+    let (authority, path) = match rest.find(['/', '?']) {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "/"),
+    };
+    if authority.is_empty() || authority.contains('@') {
+        return Err(invalid("expected a host (without user info)"));
+    }
+
+    // `[v6]:port` or `host:port`
+    let (host, port) = match authority.rfind(':') {
+        Some(i) if !authority[i..].contains(']') => {
+            let port = authority[i + 1..].parse().map_err(|_| invalid("bad port"))?;
+            (&authority[..i], port)
+        }
+        _ => (authority, if tls { 443 } else { 80 }),
+    };
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+
+    Ok(Target { tls, authority, host, port, path })
+}
+
+/// POST a JSON body, failing on anything but a 2xx response
+fn post(url: &str, headers: &[(String, String)], body: &[u8]) -> Result<()> {
+    let target = parse_url(url, "notify.webhook.url")?;
+    let io = |e: std::io::Error| FluxError::NotifyError(format!("{}: {}", target.authority, e));
+
+    let mut request = format!(
+        "POST {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: flux-ssl-mgr/{}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n",
+        if target.path.starts_with('/') { target.path.to_string() } else { format!("/{}", target.path) },
+        target.authority,
+        env!("CARGO_PKG_VERSION"),
+        body.len()
+    );
+    for (name, value) in headers {
+        if name.contains(['\r', '\n', ':']) || value.contains(['\r', '\n']) {
+            return Err(FluxError::InvalidConfigValue("notify.webhook.headers".to_string(), name.clone()));
+        }
+        let _ = write!(request, "{}: {}\r\n", name, value);
+    }
+    request.push_str("\r\n");
+
+    let stream = connect(target.host, target.port).map_err(io)?;
+    stream.set_read_timeout(Some(TIMEOUT)).map_err(io)?;
+    stream.set_write_timeout(Some(TIMEOUT)).map_err(io)?;
+
+    let response = if target.tls {
+        let connector = SslConnector::builder(SslMethod::tls_client())?.build();
+        let mut stream = connector.connect(target.host, stream)
+            .map_err(|e| FluxError::NotifyError(format!("{}: TLS: {}", target.authority, e)))?;
+        exchange(&mut stream, request.as_bytes(), body).map_err(io)?
+    } else {
+        let mut stream = stream;
+        exchange(&mut stream, request.as_bytes(), body).map_err(io)?
+    };
+
+    let status_line = response.lines().next().unwrap_or_default();
+    match status_line.split_whitespace().nth(1).and_then(|s| s.parse::<u16>().ok()) {
+        Some(200..=299) => Ok(()),
+        _ => Err(FluxError::NotifyError(format!("{} answered '{}'", target.authority, status_line))),
+    }
+}
+
+/// Connect to the first address of `host` that answers within [`TIMEOUT`]
+fn connect(host: &str, port: u16) -> std::io::Result<TcpStream> {
+    let mut last_err = None;
+    for addr in (host, port).to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, TIMEOUT) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_err = Some(e),
+        }
+    }
+    Err(last_err.unwrap_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "no address found")))
+}
+
+/// Write the request and read the (bounded) response
+pub(crate) fn exchange<S: Read + Write>(stream: &mut S, head: &[u8], body: &[u8]) -> std::io::Result<String> {
+    stream.write_all(head)?;
+    stream.write_all(body)?;
+    stream.flush()?;
+
+    let mut response = Vec::new();
+    // Servers that ignore `Connection: close` time out here after the headers
+    if let Err(e) = stream.take(MAX_RESPONSE).read_to_end(&mut response) {
+        if response.is_empty() {
+            return Err(e);
+        }
+    }
+    Ok(String::from_utf8_lossy(&response).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_parse_url() {
+        let t = parse_url("https://ntfy.sh/flux-certs", "url").unwrap();
+        assert_eq!(t, Target { tls: true, authority: "ntfy.sh", host: "ntfy.sh", port: 443, path: "/flux-certs" });
+
+        let t = parse_url("http://[fd00::2]:8123/api/webhook/certs?x=1", "url").unwrap();
+        assert_eq!((t.host, t.port, t.path), ("fd00::2", 8123, "/api/webhook/certs?x=1"));
+        assert_eq!(parse_url("http://ha.lab", "url").unwrap().path, "/");
+
+        assert!(parse_url("ftp://ha.lab/", "url").is_err());
+        assert!(parse_url("http://user:pw@ha.lab/", "url").is_err());
+        assert!(parse_url("http://ha.lab:http/", "url").is_err());
+    }
+
+    #[test]
+    fn test_sign() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            sign(b"Jefe", b"what do ya want for nothing?").unwrap(),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn test_deliver_signed_event() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let secret_file = temp_dir.path().join("secret");
+        std::fs::write(&secret_file, "s3cret\n").unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let webhook = WebhookConfig {
+            name: "ha".to_string(),
+            url: format!("http://{}/hook", listener.local_addr().unwrap()),
+            headers: BTreeMap::from([("Authorization".to_string(), "Bearer t".to_string())]),
+            secret_file: Some(secret_file),
+            events: vec![EventKind::Issued],
+            options: SinkOptions::default(),
+        };
+        assert!(subscribed(&webhook, EventKind::Issued));
+        assert!(!subscribed(&webhook, EventKind::Revoked));
+
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 4096];
+            while !request.ends_with(b"}") {
+                let n = stream.read(&mut buf).unwrap();
+                request.extend_from_slice(&buf[..n]);
+            }
+            stream.write_all(b"HTTP/1.1 204 No Content\r\n\r\n").unwrap();
+            String::from_utf8(request).unwrap()
+        });
+
+        let event = Event {
+            event: EventKind::Issued,
+            name: "nas".to_string(),
+            serial: "1A".to_string(),
+            subject: "CN=nas".to_string(),
+            sans: vec!["DNS:nas.lab".to_string()],
+            not_after: Utc::now(),
+            reason: None,
+            timestamp: Utc::now(),
+        };
+        deliver(&webhook, event.event, &event).unwrap();
+
+        let request = server.join().unwrap();
+        let (head, body) = request.split_once("\r\n\r\n").unwrap();
+        assert!(head.starts_with("POST /hook HTTP/1.1\r\n"), "{}", head);
+        assert!(head.contains("Authorization: Bearer t\r\n"));
+        assert!(head.contains("X-Flux-Event: issued\r\n"));
+        let signature = format!("{}: sha256={}", SIGNATURE_HEADER, sign(b"s3cret", body.as_bytes()).unwrap());
+        assert!(head.contains(&signature), "{}", head);
+        assert!(body.starts_with("{\"event\":\"issued\",\"name\":\"nas\""), "{}", body);
+    }
+}
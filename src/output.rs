@@ -1,105 +1,308 @@
 //! Output formatting module
+//!
+//! [`OutputFormatter`] decides what to show (quiet/verbose, progress bars) and
+//! hands each message to an [`OutputSink`]: the terminal by default, any
+//! `Write` implementation, or a callback for library consumers that want to
+//! capture progress programmatically.
+//!
+//! Colors and progress bars need the `interactive` feature; without it,
+//! terminal output is plain text and batches run without progress bars.
 
-use console::{Style, Term};
+#[cfg(feature = "interactive")]
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use std::io::{IsTerminal, Write};
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "interactive")]
+use std::time::Duration;
 use crate::config::OutputConfig;
+use crate::telemetry::Counters;
+
+/// Kind of message passed to an [`OutputSink`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageKind {
+    Success,
+    Error,
+    Warning,
+    Info,
+    Step,
+    Verbose,
+    Header,
+    /// Plain line
+    Plain,
+    /// Text without a trailing newline
+    Inline,
+}
+
+/// Destination for formatted output
+pub trait OutputSink: Send + Sync {
+    /// Handle one message
+    fn emit(&self, kind: MessageKind, msg: &str);
+
+    /// Whether the sink is an interactive terminal (enables progress bars)
+    fn is_terminal(&self) -> bool {
+        false
+    }
+}
+
+impl<F> OutputSink for F
+where
+    F: Fn(MessageKind, &str) + Send + Sync,
+{
+    fn emit(&self, kind: MessageKind, msg: &str) {
+        self(kind, msg)
+    }
+}
+
+/// Colors used when rendering for a terminal
+#[derive(Debug, Clone, Copy)]
+enum Color {
+    Green,
+    Yellow,
+    Red,
+    Blue,
+}
+
+/// Apply a color (plain text without the `interactive` feature)
+#[cfg(feature = "interactive")]
+fn paint(color: Color, text: &str) -> String {
+    use console::Style;
+
+    let style = match color {
+        Color::Green => Style::new().green().bold(),
+        Color::Yellow => Style::new().yellow().bold(),
+        Color::Red => Style::new().red().bold(),
+        Color::Blue => Style::new().blue(),
+    };
+    style.apply_to(text).to_string()
+}
+
+#[cfg(not(feature = "interactive"))]
+fn paint(_color: Color, text: &str) -> String {
+    text.to_string()
+}
+
+/// Render a message as text lines (without trailing newlines)
+fn render(kind: MessageKind, msg: &str, colored: bool) -> Vec<String> {
+    let color = |color: Color, text: &str| if colored { paint(color, text) } else { text.to_string() };
+    let mark = |symbol: &str, c: Color| format!("{} {}", color(c, symbol), msg);
+
+    match kind {
+        MessageKind::Success => vec![mark("✓", Color::Green)],
+        MessageKind::Error => vec![mark("✗", Color::Red)],
+        MessageKind::Warning => vec![mark("⚠", Color::Yellow)],
+        MessageKind::Info => vec![mark("ℹ", Color::Blue)],
+        MessageKind::Verbose => vec![color(Color::Blue, msg)],
+        MessageKind::Header => {
+            let separator = "=".repeat(msg.len());
+            vec![color(Color::Green, msg), color(Color::Green, &separator)]
+        }
+        MessageKind::Step | MessageKind::Plain | MessageKind::Inline => vec![msg.to_string()],
+    }
+}
+
+/// Sink writing to stdout
+struct TermSink {
+    colored: bool,
+}
+
+impl OutputSink for TermSink {
+    fn emit(&self, kind: MessageKind, msg: &str) {
+        let mut stdout = std::io::stdout().lock();
+        if kind == MessageKind::Inline {
+            let _ = write!(stdout, "{}", msg);
+        } else {
+            for line in render(kind, msg, self.colored) {
+                let _ = writeln!(stdout, "{}", line);
+            }
+        }
+        let _ = stdout.flush();
+    }
+
+    fn is_terminal(&self) -> bool {
+        std::io::stdout().is_terminal()
+    }
+}
+
+/// Sink writing uncolored text to any writer
+pub struct WriterSink<W: Write + Send> {
+    writer: Mutex<W>,
+}
+
+impl<W: Write + Send> WriterSink<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer: Mutex::new(writer) }
+    }
+}
+
+impl<W: Write + Send> OutputSink for WriterSink<W> {
+    fn emit(&self, kind: MessageKind, msg: &str) {
+        let mut writer = match self.writer.lock() {
+            Ok(w) => w,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        if kind == MessageKind::Inline {
+            let _ = write!(writer, "{}", msg);
+        } else {
+            for line in render(kind, msg, false) {
+                let _ = writeln!(writer, "{}", line);
+            }
+        }
+        let _ = writer.flush();
+    }
+}
 
 /// Output formatter with color support
+#[derive(Clone)]
 pub struct OutputFormatter {
-    term: Term,
-    colored: bool,
+    sink: Arc<dyn OutputSink>,
     verbose: bool,
     quiet: bool,
-    green: Style,
-    yellow: Style,
-    red: Style,
-    blue: Style,
+    /// Counts for the `--summary-json` line, shared between clones
+    counters: Counters,
+    /// Progress bar that step/info/success messages are routed to
+    #[cfg(feature = "interactive")]
+    bar: Option<ProgressBar>,
 }
 
 impl OutputFormatter {
-    /// Create a new output formatter
+    /// Create a new output formatter writing to stdout
     pub fn new(config: &OutputConfig) -> Self {
-        let green = Style::new().green().bold();
-        let yellow = Style::new().yellow().bold();
-        let red = Style::new().red().bold();
-        let blue = Style::new().blue();
+        let sink = TermSink { colored: config.colored };
+
+        Self::with_sink(config, Arc::new(sink))
+    }
+
+    /// Create an output formatter writing plain text to `writer`
+    pub fn with_writer<W: Write + Send + 'static>(config: &OutputConfig, writer: W) -> Self {
+        Self::with_sink(config, Arc::new(WriterSink::new(writer)))
+    }
 
+    /// Create an output formatter delivering messages to a custom sink
+    ///
+    /// Closures of type `Fn(MessageKind, &str)` implement [`OutputSink`].
+    pub fn with_sink(config: &OutputConfig, sink: Arc<dyn OutputSink>) -> Self {
         Self {
-            term: Term::stdout(),
-            colored: config.colored,
+            sink,
             verbose: config.verbose,
             quiet: config.quiet,
-            green,
-            yellow,
-            red,
-            blue,
+            counters: Counters::default(),
+            #[cfg(feature = "interactive")]
+            bar: None,
         }
     }
 
+    /// Formatter that shows step/info/success messages on a progress bar
+    ///
+    /// Warnings and errors are still printed, above the bars.
+    #[cfg(feature = "interactive")]
+    pub fn with_progress_bar(&self, bar: ProgressBar) -> Self {
+        Self {
+            bar: Some(bar),
+            ..self.clone()
+        }
+    }
+
+    /// Formatter adding to `counters` instead of its own
+    pub fn with_counters(self, counters: Counters) -> Self {
+        Self { counters, ..self }
+    }
+
+    /// Counters reported in the `--summary-json` line
+    pub fn counters(&self) -> &Counters {
+        &self.counters
+    }
+
+    /// Add `n` to the summary counter `name` (e.g. `deployed`)
+    pub fn count(&self, name: &'static str, n: usize) {
+        self.counters.add(name, n as u64);
+    }
+
+    /// Start a batch progress display
+    ///
+    /// Returns `None` in quiet mode or when output is not a terminal.
+    pub fn batch_progress(&self, total: usize) -> Option<BatchProgress> {
+        if self.quiet || !self.sink.is_terminal() {
+            return None;
+        }
+
+        BatchProgress::new(total)
+    }
+
+    /// Emit a message, keeping any active progress bar intact
+    fn emit(&self, kind: MessageKind, msg: &str) {
+        #[cfg(feature = "interactive")]
+        if let Some(bar) = &self.bar {
+            return bar.suspend(|| self.sink.emit(kind, msg));
+        }
+
+        self.sink.emit(kind, msg);
+    }
+
+    /// Show a transient message on the progress bar, if there is one
+    #[cfg(feature = "interactive")]
+    fn set_bar_message(&self, msg: &str) -> bool {
+        match &self.bar {
+            Some(bar) => {
+                bar.set_message(msg.to_string());
+                true
+            }
+            None => false,
+        }
+    }
+
+    #[cfg(not(feature = "interactive"))]
+    fn set_bar_message(&self, _msg: &str) -> bool {
+        false
+    }
+
     /// Print success message
     pub fn success(&self, msg: &str) {
-        if self.quiet {
+        if self.quiet || self.set_bar_message(msg) {
             return;
         }
 
-        if self.colored {
-            let _ = self.term.write_line(&format!("{} {}", self.green.apply_to("✓"), msg));
-        } else {
-            let _ = self.term.write_line(&format!("✓ {}", msg));
-        }
+        self.emit(MessageKind::Success, msg);
     }
 
     /// Print error message
     pub fn error(&self, msg: &str) {
-        if self.colored {
-            let _ = self.term.write_line(&format!("{} {}", self.red.apply_to("✗"), msg));
-        } else {
-            let _ = self.term.write_line(&format!("✗ {}", msg));
-        }
+        self.count("errors", 1);
+        self.emit(MessageKind::Error, msg);
     }
 
     /// Print warning message
     pub fn warning(&self, msg: &str) {
+        self.count("warnings", 1);
         if self.quiet {
             return;
         }
 
-        if self.colored {
-            let _ = self.term.write_line(&format!("{} {}", self.yellow.apply_to("⚠"), msg));
-        } else {
-            let _ = self.term.write_line(&format!("⚠ {}", msg));
-        }
+        self.emit(MessageKind::Warning, msg);
     }
 
     /// Print info message
     pub fn info(&self, msg: &str) {
-        if self.quiet {
+        if self.quiet || self.set_bar_message(msg) {
             return;
         }
 
-        if self.colored {
-            let _ = self.term.write_line(&format!("{} {}", self.blue.apply_to("ℹ"), msg));
-        } else {
-            let _ = self.term.write_line(&format!("ℹ {}", msg));
-        }
+        self.emit(MessageKind::Info, msg);
     }
 
     /// Print step message
     pub fn step(&self, msg: &str) {
-        if self.quiet {
+        if self.quiet || self.set_bar_message(msg) {
             return;
         }
 
-        let _ = self.term.write_line(msg);
+        self.emit(MessageKind::Step, msg);
     }
 
     /// Print verbose message (only if verbose mode is enabled)
     pub fn verbose(&self, msg: &str) {
         if self.verbose && !self.quiet {
-            if self.colored {
-                let _ = self.term.write_line(&self.blue.apply_to(msg).to_string());
-            } else {
-                let _ = self.term.write_line(msg);
-            }
+            self.emit(MessageKind::Verbose, msg);
         }
     }
 
@@ -109,32 +312,26 @@ impl OutputFormatter {
             return;
         }
 
-        let separator = "=".repeat(msg.len());
-        if self.colored {
-            let _ = self.term.write_line(&self.green.apply_to(msg).to_string());
-            let _ = self.term.write_line(&self.green.apply_to(&separator).to_string());
-        } else {
-            let _ = self.term.write_line(msg);
-            let _ = self.term.write_line(&separator);
-        }
+        self.emit(MessageKind::Header, msg);
     }
 
     /// Print raw message
     pub fn println(&self, msg: &str) {
         if !self.quiet {
-            let _ = self.term.write_line(msg);
+            self.emit(MessageKind::Plain, msg);
         }
     }
 
     /// Print without newline
     pub fn print(&self, msg: &str) {
         if !self.quiet {
-            let _ = self.term.write_str(msg);
+            self.emit(MessageKind::Inline, msg);
         }
     }
 
     /// Print certificate summary
     pub fn print_cert_summary(&self, cert_name: &str, output_dir: &std::path::Path) {
+        self.count("issued", 1);
         if self.quiet {
             return;
         }
@@ -148,7 +345,10 @@ impl OutputFormatter {
     }
 
     /// Print batch summary
-    pub fn print_batch_summary(&self, successful: usize, failed: usize) {
+    pub fn print_batch_summary(&self, successful: usize, skipped: usize, failed: usize) {
+        self.count("issued", successful);
+        self.count("skipped", skipped);
+        self.count("failed", failed);
         if self.quiet {
             return;
         }
@@ -157,6 +357,28 @@ impl OutputFormatter {
         self.header("Batch processing complete!");
         self.success(&format!("Processed: {} certificates", successful));
 
+        if skipped > 0 {
+            self.info(&format!("Skipped: {} still valid", skipped));
+        }
+
+        if failed > 0 {
+            self.error(&format!("Failed: {} certificates", failed));
+        }
+    }
+
+    /// Print manifest apply summary
+    pub fn print_apply_summary(&self, issued: usize, renewed: usize, unchanged: usize, revoked: usize, failed: usize) {
+        for (name, n) in [("issued", issued), ("renewed", renewed), ("unchanged", unchanged), ("revoked", revoked), ("failed", failed)] {
+            self.count(name, n);
+        }
+        if self.quiet {
+            return;
+        }
+
+        self.println("");
+        self.header("Manifest applied!");
+        self.success(&format!("Issued: {}, renewed: {}, revoked: {}, unchanged: {}", issued, renewed, revoked, unchanged));
+
         if failed > 0 {
             self.error(&format!("Failed: {} certificates", failed));
         }
@@ -165,15 +387,184 @@ impl OutputFormatter {
 
 impl Default for OutputFormatter {
     fn default() -> Self {
-        Self {
-            term: Term::stdout(),
-            colored: true,
-            verbose: false,
-            quiet: false,
-            green: Style::new().green().bold(),
-            yellow: Style::new().yellow().bold(),
-            red: Style::new().red().bold(),
-            blue: Style::new().blue(),
+        Self::new(&OutputConfig::default())
+    }
+}
+
+/// Progress display for batch processing
+///
+/// An overall bar plus one spinner per certificate in flight.
+#[cfg(feature = "interactive")]
+pub struct BatchProgress {
+    multi: MultiProgress,
+    overall: ProgressBar,
+}
+
+/// One certificate's spinner within a [`BatchProgress`]
+#[cfg(feature = "interactive")]
+pub struct CertProgress {
+    bar: ProgressBar,
+}
+
+#[cfg(feature = "interactive")]
+impl BatchProgress {
+    fn new(total: usize) -> Option<Self> {
+        let multi = MultiProgress::new();
+        let overall = multi.add(ProgressBar::new(total as u64));
+        overall.set_style(
+            ProgressStyle::with_template("[{elapsed_precise}] {bar:40.cyan/blue} {pos}/{len} {msg}")
+                .unwrap_or_else(|_| ProgressStyle::default_bar())
+                .progress_chars("##-"),
+        );
+
+        Some(Self { multi, overall })
+    }
+
+    /// Add a spinner for a certificate being processed
+    pub fn start(&self, cert_name: &str) -> CertProgress {
+        let bar = self.multi.insert_before(&self.overall, ProgressBar::new_spinner());
+        bar.set_style(
+            ProgressStyle::with_template("{spinner:.green} {prefix:.bold} {wide_msg}")
+                .unwrap_or_else(|_| ProgressStyle::default_spinner()),
+        );
+        bar.set_prefix(cert_name.to_string());
+        bar.enable_steady_tick(Duration::from_millis(100));
+        CertProgress { bar }
+    }
+
+    /// Remove a certificate's spinner and advance the overall bar
+    pub fn finish(&self, progress: CertProgress, cert_name: &str) {
+        progress.bar.finish_and_clear();
+        self.overall.set_message(cert_name.to_string());
+        self.overall.inc(1);
+    }
+
+    /// Clear the display
+    pub fn clear(self) {
+        self.overall.finish_and_clear();
+    }
+}
+
+#[cfg(feature = "interactive")]
+impl CertProgress {
+    /// Formatter routing `output`'s step/info/success messages to this spinner
+    pub fn output(&self, output: &OutputFormatter) -> OutputFormatter {
+        output.with_progress_bar(self.bar.clone())
+    }
+}
+
+/// Progress display for batch processing (never created without `interactive`)
+#[cfg(not(feature = "interactive"))]
+pub enum BatchProgress {}
+
+/// One certificate's progress (a no-op without `interactive`)
+#[cfg(not(feature = "interactive"))]
+pub struct CertProgress(());
+
+#[cfg(not(feature = "interactive"))]
+impl BatchProgress {
+    fn new(_total: usize) -> Option<Self> {
+        None
+    }
+
+    pub fn start(&self, _cert_name: &str) -> CertProgress {
+        match *self {}
+    }
+
+    pub fn finish(&self, _progress: CertProgress, _cert_name: &str) {
+        match *self {}
+    }
+
+    pub fn clear(self) {
+        match self {}
+    }
+}
+
+#[cfg(not(feature = "interactive"))]
+impl CertProgress {
+    pub fn output(&self, output: &OutputFormatter) -> OutputFormatter {
+        output.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_batch_progress_suppressed_when_quiet() {
+        let config = OutputConfig {
+            quiet: true,
+            ..OutputConfig::default()
+        };
+        assert!(OutputFormatter::new(&config).batch_progress(3).is_none());
+    }
+
+    /// Writer whose contents can be inspected after the formatter owns it
+    #[derive(Clone, Default)]
+    struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
         }
     }
+
+    #[test]
+    fn test_writer_output() {
+        let buf = SharedBuf::default();
+        let output = OutputFormatter::with_writer(&OutputConfig::default(), buf.clone());
+
+        output.header("Title");
+        output.success("done");
+        output.verbose("hidden");
+
+        let text = String::from_utf8(buf.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(text, "Title\n=====\n✓ done\n");
+    }
+
+    #[test]
+    fn test_callback_sink() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let captured = Arc::clone(&events);
+        let sink = move |kind: MessageKind, msg: &str| captured.lock().unwrap().push((kind, msg.to_string()));
+
+        let output = OutputFormatter::with_sink(&OutputConfig::default(), Arc::new(sink));
+        output.step("Generating private key...");
+        output.error("failed");
+
+        let events = events.lock().unwrap();
+        assert_eq!(events[0], (MessageKind::Step, "Generating private key...".to_string()));
+        assert_eq!(events[1], (MessageKind::Error, "failed".to_string()));
+        assert!(output.batch_progress(1).is_none());
+    }
+
+    #[test]
+    fn test_summary_counts_survive_quiet() {
+        let config = OutputConfig { quiet: true, ..OutputConfig::default() };
+        let output = OutputFormatter::with_writer(&config, std::io::sink());
+        output.clone().print_batch_summary(3, 1, 0);
+        output.warning("hidden");
+
+        let counts = output.counters().snapshot();
+        assert_eq!(counts["issued"], 3);
+        assert_eq!(counts["skipped"], 1);
+        assert_eq!(counts["warnings"], 1);
+    }
+
+    #[cfg(feature = "interactive")]
+    #[test]
+    fn test_progress_bar_captures_step_messages() {
+        let bar = ProgressBar::hidden();
+        let output = OutputFormatter::new(&OutputConfig::default()).with_progress_bar(bar.clone());
+
+        output.step("Generating private key...");
+        assert_eq!(bar.message(), "Generating private key...");
+        output.success("Private key generated");
+        assert_eq!(bar.message(), "Private key generated");
+    }
 }
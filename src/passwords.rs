@@ -0,0 +1,204 @@
+//! Encrypted password files
+//!
+//! Generated private key passwords can be written to a password file instead
+//! of being printed. The file is JSON; the password map inside is encrypted
+//! with AES-256-GCM under a key derived from a passphrase (PBKDF2-SHA256).
+
+use crate::error::{FluxError, Result};
+use crate::fsutil::{read_to_string_bounded, write_atomic, PRIVATE_FILE_MODE};
+use openssl::base64;
+use openssl::hash::MessageDigest;
+use openssl::pkcs5::pbkdf2_hmac;
+use openssl::rand::rand_bytes;
+use openssl::symm::{decrypt_aead, encrypt_aead, Cipher};
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use zeroize::Zeroize;
+
+const KDF_ITERATIONS: u32 = 600_000;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+
+/// Characters used for generated passwords (no look-alikes)
+const PASSWORD_ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz23456789";
+
+/// Length of generated key passwords
+pub const GENERATED_PASSWORD_LEN: usize = 24;
+
+/// Generate a random password
+pub fn generate_password(len: usize) -> Result<Secret<String>> {
+    let mut password = String::with_capacity(len);
+    let mut buf = [0u8; 64];
+
+    // Rejection sampling keeps the distribution uniform
+    let limit = 256 - (256 % PASSWORD_ALPHABET.len());
+    while password.len() < len {
+        rand_bytes(&mut buf)?;
+        for &b in buf.iter().filter(|&&b| (b as usize) < limit) {
+            if password.len() == len {
+                break;
+            }
+            password.push(PASSWORD_ALPHABET[b as usize % PASSWORD_ALPHABET.len()] as char);
+        }
+    }
+    buf.zeroize();
+
+    Ok(Secret::new(password))
+}
+
+/// On-disk format
+#[derive(Serialize, Deserialize)]
+struct EncryptedFile {
+    version: u32,
+    kdf: String,
+    iterations: u32,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+    tag: String,
+}
+
+/// Certificate name to key password map
+#[derive(Default)]
+pub struct PasswordFile {
+    entries: BTreeMap<String, Secret<String>>,
+}
+
+impl PasswordFile {
+    /// Create an empty password file
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load and decrypt a password file
+    pub fn load<P: AsRef<Path>>(path: P, passphrase: &Secret<String>) -> Result<Self> {
+        let data = read_to_string_bounded(path.as_ref())?;
+
+        let file: EncryptedFile = serde_json::from_str(&data)
+            .map_err(|e| FluxError::PasswordFileError(format!("invalid password file: {}", e)))?;
+
+        if file.version != 1 || file.kdf != "pbkdf2-sha256" {
+            return Err(FluxError::PasswordFileError("unsupported password file format".to_string()));
+        }
+
+        let decode = |s: &str| base64::decode_block(s)
+            .map_err(|_| FluxError::PasswordFileError("invalid encoding".to_string()));
+        let salt = decode(&file.salt)?;
+        let nonce = decode(&file.nonce)?;
+        let ciphertext = decode(&file.ciphertext)?;
+        let tag = decode(&file.tag)?;
+
+        let mut key = derive_key(passphrase, &salt, file.iterations)?;
+        let plaintext = decrypt_aead(Cipher::aes_256_gcm(), &key, Some(&nonce), &[], &ciphertext, &tag);
+        key.zeroize();
+        let mut plaintext = plaintext
+            .map_err(|_| FluxError::PasswordFileError("wrong passphrase or corrupted file".to_string()))?;
+
+        let map: std::result::Result<BTreeMap<String, String>, _> = serde_json::from_slice(&plaintext);
+        plaintext.zeroize();
+        let map = map.map_err(|e| FluxError::PasswordFileError(e.to_string()))?;
+
+        Ok(Self {
+            entries: map.into_iter().map(|(k, v)| (k, Secret::new(v))).collect(),
+        })
+    }
+
+    /// Encrypt and save the password file (mode 0600, replaced atomically)
+    pub fn save<P: AsRef<Path>>(&self, path: P, passphrase: &Secret<String>) -> Result<()> {
+        let map: BTreeMap<&str, &str> = self.entries.iter()
+            .map(|(k, v)| (k.as_str(), v.expose_secret().as_str()))
+            .collect();
+        let mut plaintext = serde_json::to_vec(&map)
+            .map_err(|e| FluxError::PasswordFileError(e.to_string()))?;
+
+        let mut salt = [0u8; SALT_LEN];
+        let mut nonce = [0u8; NONCE_LEN];
+        rand_bytes(&mut salt)?;
+        rand_bytes(&mut nonce)?;
+
+        let mut key = derive_key(passphrase, &salt, KDF_ITERATIONS)?;
+        let mut tag = [0u8; TAG_LEN];
+        let ciphertext = encrypt_aead(Cipher::aes_256_gcm(), &key, Some(&nonce), &[], &plaintext, &mut tag);
+        key.zeroize();
+        plaintext.zeroize();
+
+        let file = EncryptedFile {
+            version: 1,
+            kdf: "pbkdf2-sha256".to_string(),
+            iterations: KDF_ITERATIONS,
+            salt: base64::encode_block(&salt),
+            nonce: base64::encode_block(&nonce),
+            ciphertext: base64::encode_block(&ciphertext?),
+            tag: base64::encode_block(&tag),
+        };
+        let json = serde_json::to_string_pretty(&file)
+            .map_err(|e| FluxError::PasswordFileError(e.to_string()))?;
+
+        write_atomic(path.as_ref(), json.as_bytes(), PRIVATE_FILE_MODE)
+    }
+
+    /// Set the password for a certificate
+    pub fn insert(&mut self, name: &str, password: Secret<String>) {
+        self.entries.insert(name.to_string(), password);
+    }
+
+    /// Password for a certificate
+    pub fn get(&self, name: &str) -> Option<&Secret<String>> {
+        self.entries.get(name)
+    }
+
+    /// All entries, sorted by certificate name
+    pub fn entries(&self) -> impl Iterator<Item = (&str, &Secret<String>)> {
+        self.entries.iter().map(|(k, v)| (k.as_str(), v))
+    }
+}
+
+fn derive_key(passphrase: &Secret<String>, salt: &[u8], iterations: u32) -> Result<[u8; 32]> {
+    let mut key = [0u8; 32];
+    pbkdf2_hmac(passphrase.expose_secret().as_bytes(), salt, iterations as usize, MessageDigest::sha256(), &mut key)?;
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_password() {
+        let password = generate_password(GENERATED_PASSWORD_LEN).unwrap();
+        assert_eq!(password.expose_secret().len(), GENERATED_PASSWORD_LEN);
+        assert!(password.expose_secret().bytes().all(|b| PASSWORD_ALPHABET.contains(&b)));
+        assert_ne!(password.expose_secret(), generate_password(GENERATED_PASSWORD_LEN).unwrap().expose_secret());
+    }
+
+    #[test]
+    fn test_password_file_roundtrip() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("passwords.json");
+        let passphrase = Secret::new("correct horse".to_string());
+
+        let mut file = PasswordFile::new();
+        file.insert("web", Secret::new("s3cret".to_string()));
+        file.save(&path, &passphrase).unwrap();
+
+        assert!(!std::fs::read_to_string(&path).unwrap().contains("s3cret"));
+
+        let loaded = PasswordFile::load(&path, &passphrase).unwrap();
+        assert_eq!(loaded.get("web").unwrap().expose_secret(), "s3cret");
+
+        let wrong = Secret::new("battery staple".to_string());
+        assert!(matches!(PasswordFile::load(&path, &wrong), Err(FluxError::PasswordFileError(_))));
+
+        // A file left readable by others is replaced with a private one
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+            loaded.save(&path, &passphrase).unwrap();
+            assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+    }
+}
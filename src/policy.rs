@@ -0,0 +1,442 @@
+//! Signing policy: what a CSR must look like before the CA signs it
+//!
+//! The rules come from `[policy]` and are checked in the CA's signing path,
+//! so `batch`, `single`, the web service and the signing agent all enforce
+//! them. Every rule is evaluated, and a refusal lists each one that failed
+//! rather than stopping at the first:
+//!
+//! - `allowed_key_types`, `min_rsa_bits`, `min_ec_bits`: the CSR's key
+//! - `allowed_domains`: domains and CIDR ranges the CN and DNS/IP SANs must
+//!   fall in, and that bound the name constraints of subordinate CAs
+//! - `max_validity`: the longest validity that may be requested
+//! - `forbidden_extensions`: extensions a CSR may not ask for
+//! - `required_subject`: subject fields that must be present
+
+use crate::config::PolicyConfig;
+use crate::crypto::details::describe_csr;
+use crate::crypto::{get_csr_subject, to_ascii_dns, NameConstraints, SanEntry, Subtree};
+use crate::duration::Validity;
+use crate::error::{FluxError, Result};
+use openssl::asn1::Asn1Object;
+use openssl::nid::Nid;
+use openssl::pkey::Id;
+use openssl::x509::X509Req;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::net::IpAddr;
+
+/// Public key algorithm of a CSR
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyType {
+    Rsa,
+    Ec,
+    Ed25519,
+    Ed448,
+}
+
+impl KeyType {
+    fn from_id(id: Id) -> Option<Self> {
+        match id {
+            Id::RSA => Some(Self::Rsa),
+            Id::EC => Some(Self::Ec),
+            Id::ED25519 => Some(Self::Ed25519),
+            Id::ED448 => Some(Self::Ed448),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for KeyType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Rsa => "RSA",
+            Self::Ec => "EC",
+            Self::Ed25519 => "Ed25519",
+            Self::Ed448 => "Ed448",
+        })
+    }
+}
+
+/// One rule a CSR broke
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "web", derive(utoipa::ToSchema))]
+pub struct Violation {
+    /// The `[policy]` key of the rule, or the check (e.g. `signature`) for
+    /// rules that aren't configurable
+    pub rule: String,
+    pub message: String,
+}
+
+impl Violation {
+    pub fn new(rule: &str, message: impl Into<String>) -> Self {
+        Self { rule: rule.to_string(), message: message.into() }
+    }
+}
+
+/// Every rule a CSR broke, in the order they were checked
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Violations(Vec<Violation>);
+
+impl Violations {
+    pub fn iter(&self) -> std::slice::Iter<'_, Violation> {
+        self.0.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn into_vec(self) -> Vec<Violation> {
+        self.0
+    }
+}
+
+impl FromIterator<Violation> for Violations {
+    fn from_iter<I: IntoIterator<Item = Violation>>(iter: I) -> Self {
+        Self(iter.into_iter().collect())
+    }
+}
+
+impl From<Violation> for Violations {
+    fn from(violation: Violation) -> Self {
+        Self(vec![violation])
+    }
+}
+
+impl fmt::Display for Violations {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let messages: Vec<&str> = self.0.iter().map(|v| v.message.as_str()).collect();
+        match messages.as_slice() {
+            [message] => f.write_str(message),
+            messages => write!(f, "{} rules failed: {}", messages.len(), messages.join("; ")),
+        }
+    }
+}
+
+/// Check a CSR and the certificate asked for against the policy
+///
+/// `sans` are the SANs the certificate will carry (the CSR's, or their
+/// replacement) and `validity` the requested validity.
+pub fn evaluate(policy: &PolicyConfig, csr: &X509Req, sans: &[SanEntry], validity: Validity) -> Result<()> {
+    let mut violations = Vec::new();
+    key_violations(policy, csr, &mut violations)?;
+    name_violations(policy, get_csr_subject(csr).ok().as_deref(), sans, &mut violations)?;
+
+    if let Some(max) = policy.max_validity.filter(|max| validity > *max) {
+        violations.push(Violation::new(
+            "max_validity",
+            format!("validity of {} is longer than max_validity ({})", validity, max),
+        ));
+    }
+
+    let extensions = describe_csr(csr)?.extensions;
+    for ext in &extensions {
+        if policy.forbidden_extensions.iter().any(|name| same_object(name, &ext.oid)) {
+            violations.push(Violation::new(
+                "forbidden_extensions",
+                format!("the CSR asks for the forbidden {} extension", ext.name),
+            ));
+        }
+    }
+
+    for field in &policy.required_subject {
+        let present = subject_nid(field)
+            .is_some_and(|nid| csr.subject_name().entries_by_nid(nid).any(|entry| !entry.data().is_empty()));
+        if !present {
+            violations.push(Violation::new(
+                "required_subject",
+                format!("the subject has no {} (required_subject)", field),
+            ));
+        }
+    }
+
+    refuse_if_any(violations)
+}
+
+/// Check only the CSR's key against the key rules
+pub fn check_key(policy: &PolicyConfig, csr: &X509Req) -> Result<()> {
+    let mut violations = Vec::new();
+    key_violations(policy, csr, &mut violations)?;
+    refuse_if_any(violations)
+}
+
+fn refuse_if_any(violations: Vec<Violation>) -> Result<()> {
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(FluxError::PolicyViolation(Violations(violations)))
+    }
+}
+
+fn key_violations(policy: &PolicyConfig, csr: &X509Req, violations: &mut Vec<Violation>) -> Result<()> {
+    let key = csr.public_key()?;
+    let Some(key_type) = KeyType::from_id(key.id()) else {
+        violations.push(Violation::new("allowed_key_types", "the CSR's key type is not supported"));
+        return Ok(());
+    };
+    if !policy.allowed_key_types.is_empty() && !policy.allowed_key_types.contains(&key_type) {
+        violations.push(Violation::new(
+            "allowed_key_types",
+            format!("{} keys are not in allowed_key_types", key_type),
+        ));
+    }
+    let min = match key_type {
+        KeyType::Rsa => Some(("min_rsa_bits", policy.min_rsa_bits)),
+        KeyType::Ec => Some(("min_ec_bits", policy.min_ec_bits)),
+        KeyType::Ed25519 | KeyType::Ed448 => None,
+    };
+    if let Some((rule, min)) = min.filter(|(_, min)| key.bits() < *min) {
+        violations.push(Violation::new(
+            rule,
+            format!("{}-bit {} key is below {} ({})", key.bits(), key_type, rule, min),
+        ));
+    }
+    Ok(())
+}
+
+/// Check a certificate's CN and SANs against `allowed_domains`
+///
+/// For refusing before a key is generated; [`evaluate`] checks them again.
+pub fn check_names(policy: &PolicyConfig, common_name: Option<&str>, sans: &[SanEntry]) -> Result<()> {
+    let mut violations = Vec::new();
+    name_violations(policy, common_name, sans, &mut violations)?;
+    refuse_if_any(violations)
+}
+
+fn name_violations(
+    policy: &PolicyConfig,
+    common_name: Option<&str>,
+    sans: &[SanEntry],
+    violations: &mut Vec<Violation>,
+) -> Result<()> {
+    if policy.allowed_domains.is_empty() {
+        return Ok(());
+    }
+    let allowed = policy.allowed_domains.iter().map(|entry| allowed_subtree(entry)).collect::<Result<Vec<_>>>()?;
+    let outside = |name: &SanEntry| !allowed.iter().any(|subtree| subtree.contains(name));
+
+    // A CN that is also a SAN is reported once, as the SAN
+    if let Some(name) = common_name.and_then(host_name).filter(|name| !sans.contains(name) && outside(name)) {
+        let cn = match &name {
+            SanEntry::Dns(name) | SanEntry::Ip(name) | SanEntry::Email(name) => name,
+        };
+        violations.push(Violation::new("allowed_domains", format!("CN {} is outside allowed_domains", cn)));
+    }
+    for san in sans.iter().filter(|san| !matches!(san, SanEntry::Email(_))) {
+        if outside(san) {
+            violations.push(Violation::new("allowed_domains", format!("{} is outside allowed_domains", san)));
+        }
+    }
+    Ok(())
+}
+
+/// Check a subordinate CA's name constraints against `allowed_domains`
+///
+/// A sub-CA could otherwise issue for names the CA itself won't sign, so
+/// with `allowed_domains` set it needs permitted DNS subtrees, and every
+/// permitted subtree must fall within an allowed entry.
+pub fn check_sub_ca(policy: &PolicyConfig, constraints: &NameConstraints) -> Result<()> {
+    if policy.allowed_domains.is_empty() {
+        return Ok(());
+    }
+    let allowed = policy.allowed_domains.iter().map(|entry| allowed_subtree(entry)).collect::<Result<Vec<_>>>()?;
+
+    let mut violations = Vec::new();
+    if !constraints.permitted.iter().any(|subtree| matches!(subtree, Subtree::Dns(_))) {
+        violations.push(Violation::new(
+            "allowed_domains",
+            "a subordinate CA needs permitted DNS subtrees within allowed_domains",
+        ));
+    }
+    for subtree in &constraints.permitted {
+        if !allowed.iter().any(|base| subtree.within(base)) {
+            violations.push(Violation::new(
+                "allowed_domains",
+                format!("permitted subtree {} is outside allowed_domains", subtree),
+            ));
+        }
+    }
+    refuse_if_any(violations)
+}
+
+/// An `allowed_domains` entry: `*.lab.internal` (names below it),
+/// `lab.internal` (it and the names below it), or a CIDR range
+fn allowed_subtree(entry: &str) -> Result<Subtree> {
+    match entry.trim().strip_prefix("*.") {
+        Some(domain) => format!(".{}", domain).parse(),
+        None => entry.parse(),
+    }
+}
+
+/// A CN as the DNS name or address it names
+///
+/// `None` for CNs that aren't host names, such as `Home Lab CA`.
+fn host_name(common_name: &str) -> Option<SanEntry> {
+    if let Ok(addr) = common_name.parse::<IpAddr>() {
+        return Some(SanEntry::Ip(addr.to_string()));
+    }
+    let name = to_ascii_dns(common_name).ok()?.to_ascii_lowercase();
+    let is_host = !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | '_' | '*'));
+    is_host.then_some(SanEntry::Dns(name))
+}
+
+/// Whether an extension given by name or OID is the object with dotted OID `oid`
+fn same_object(name: &str, oid: &str) -> bool {
+    match (Asn1Object::from_str(name), Asn1Object::from_str(oid)) {
+        (Ok(a), Ok(b)) if a.nid() != Nid::UNDEF => a.nid() == b.nid(),
+        _ => name == oid,
+    }
+}
+
+/// NID of a subject field such as `O`, `OU` or `organizationName`
+fn subject_nid(field: &str) -> Option<Nid> {
+    Asn1Object::from_str(field).ok().map(|object| object.nid()).filter(|nid| *nid != Nid::UNDEF)
+}
+
+/// Check that the `[policy]` rules can be applied
+pub fn validate(policy: &PolicyConfig) -> Result<()> {
+    let invalid = |key: &str, message: String| FluxError::InvalidConfigValue(format!("policy.{}", key), message);
+
+    for entry in &policy.allowed_domains {
+        allowed_subtree(entry).map_err(|e| invalid("allowed_domains", e.to_string()))?;
+    }
+    for name in &policy.forbidden_extensions {
+        if Asn1Object::from_str(name).is_err() {
+            return Err(invalid("forbidden_extensions", format!("unknown extension '{}'", name)));
+        }
+    }
+    for field in &policy.required_subject {
+        if subject_nid(field).is_none() {
+            return Err(invalid("required_subject", format!("unknown subject field '{}'", field)));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::{create_csr, generate_rsa_key};
+    use crate::fixtures;
+
+    fn dns(name: &str) -> SanEntry {
+        SanEntry::Dns(name.to_string())
+    }
+
+    #[test]
+    fn test_sub_ca_within_allowed_domains() {
+        let policy = PolicyConfig {
+            allowed_domains: vec!["*.lab.internal".to_string(), "10.0.0.0/8".to_string()],
+            ..PolicyConfig::default()
+        };
+        let constraints = |permitted: &[&str]| {
+            NameConstraints::parse(&permitted.iter().map(ToString::to_string).collect::<Vec<_>>(), &[]).unwrap()
+        };
+
+        check_sub_ca(&policy, &constraints(&["guest.lab.internal", "10.20.0.0/16"])).unwrap();
+        check_sub_ca(&PolicyConfig::default(), &NameConstraints::default()).unwrap();
+
+        let Err(FluxError::PolicyViolation(violations)) = check_sub_ca(&policy, &NameConstraints::default()) else {
+            panic!("expected a policy violation");
+        };
+        assert!(violations.iter().next().unwrap().message.contains("needs permitted DNS subtrees"));
+
+        // The allowlist covers names below lab.internal, not lab.internal itself
+        let Err(FluxError::PolicyViolation(violations)) = check_sub_ca(&policy, &constraints(&["lab.internal", "192.168.0.0/16"])) else {
+            panic!("expected a policy violation");
+        };
+        assert_eq!(violations.len(), 2);
+    }
+
+    #[test]
+    fn test_default_policy() {
+        let csr = fixtures::leaf_csr("nas.lab", &[dns("nas.lab")]);
+        evaluate(&PolicyConfig::default(), &csr, &[dns("nas.lab")], Validity::days(3650)).unwrap();
+
+        let weak = create_csr("nas", &generate_rsa_key(1024, None).unwrap(), &[], None).unwrap();
+        let err = evaluate(&PolicyConfig::default(), &weak, &[], Validity::days(30)).unwrap_err();
+        assert_eq!(err.to_string(), "Policy violation: 1024-bit RSA key is below min_rsa_bits (2048)");
+    }
+
+    #[test]
+    fn test_every_rule_is_reported() {
+        let policy = PolicyConfig {
+            allowed_key_types: vec![KeyType::Ec],
+            allowed_domains: vec!["lab.internal".to_string(), "10.0.0.0/8".to_string()],
+            max_validity: Some(Validity::days(90)),
+            forbidden_extensions: vec!["basicConstraints".to_string()],
+            required_subject: vec!["CN".to_string(), "O".to_string()],
+            ..PolicyConfig::default()
+        };
+        validate(&policy).unwrap();
+
+        let sans = [dns("nas.lab.internal"), dns("example.com"), SanEntry::Ip("10.1.2.3".to_string()), SanEntry::Ip("192.168.1.5".to_string())];
+        let Err(FluxError::PolicyViolation(violations)) = evaluate(&policy, &fixtures::ca_csr("Guest CA"), &sans, Validity::days(365)) else {
+            panic!("expected a policy violation");
+        };
+        let rules: Vec<&str> = violations.iter().map(|v| v.rule.as_str()).collect();
+        assert_eq!(rules, [
+            "allowed_key_types",
+            "allowed_domains",
+            "allowed_domains",
+            "max_validity",
+            "forbidden_extensions",
+            "required_subject",
+        ]);
+        let messages: Vec<&str> = violations.iter().map(|v| v.message.as_str()).collect();
+        assert!(messages.contains(&"DNS:example.com is outside allowed_domains"), "{:?}", messages);
+        assert!(messages.contains(&"IP:192.168.1.5 is outside allowed_domains"), "{:?}", messages);
+        assert!(violations.to_string().starts_with("6 rules failed: "), "{}", violations);
+    }
+
+    #[test]
+    fn test_allowed_domains() {
+        let policy = PolicyConfig {
+            allowed_domains: vec!["*.lab.internal".to_string(), "10.0.0.0/8".to_string()],
+            ..PolicyConfig::default()
+        };
+        let check = |cn: &str, sans: &[SanEntry]| check_names(&policy, Some(cn), sans);
+
+        check("nas.lab.internal", &[dns("nas.lab.internal"), dns("*.nas.lab.internal")]).unwrap();
+        check("10.1.2.3", &[SanEntry::Ip("10.1.2.3".to_string())]).unwrap();
+        check("*.lab.internal", &[]).unwrap();
+        // Not a host name, so only its SANs count
+        check("Home Lab CA", &[]).unwrap();
+
+        // *. means below the domain, not the domain itself
+        let err = check("lab.internal", &[]).unwrap_err();
+        assert_eq!(err.to_string(), "Policy violation: CN lab.internal is outside allowed_domains");
+        let err = check("nas", &[dns("nas.lab.internal")]).unwrap_err();
+        assert_eq!(err.to_string(), "Policy violation: CN nas is outside allowed_domains");
+
+        let sans = [dns("www.example.com"), SanEntry::Ip("192.168.1.5".to_string())];
+        let Err(FluxError::PolicyViolation(violations)) = check("www.example.com", &sans) else {
+            panic!("expected a policy violation");
+        };
+        let messages: Vec<&str> = violations.iter().map(|v| v.message.as_str()).collect();
+        assert_eq!(messages, [
+            "DNS:www.example.com is outside allowed_domains",
+            "IP:192.168.1.5 is outside allowed_domains",
+        ]);
+    }
+
+    #[test]
+    fn test_validate() {
+        let policy = |f: fn(&mut PolicyConfig)| {
+            let mut policy = PolicyConfig::default();
+            f(&mut policy);
+            validate(&policy)
+        };
+        assert!(policy(|p| p.allowed_domains = vec!["*.lab.internal".to_string()]).is_ok());
+        assert!(policy(|p| p.allowed_domains = vec!["10.0.0.1/8".to_string()]).is_err());
+        assert!(policy(|p| p.forbidden_extensions = vec!["noSuchExtension".to_string()]).is_err());
+        assert!(policy(|p| p.forbidden_extensions = vec!["1.3.6.1.5.5.7.1.24".to_string()]).is_ok());
+        assert!(policy(|p| p.required_subject = vec!["XX".to_string()]).is_err());
+        assert!(policy(|p| p.required_subject = vec!["organizationName".to_string()]).is_ok());
+    }
+}
@@ -0,0 +1,197 @@
+//! Certificates presented by remote TLS endpoints (`info --remote`)
+//!
+//! Connects to `host:port`, optionally upgrading a plaintext SMTP or IMAP
+//! session with STARTTLS first, and returns the chain the server presents
+//! without verifying it, so expired or untrusted certificates can be
+//! inspected too.
+
+use crate::error::{FluxError, Result};
+use crate::health::{bracketed, connect, connector};
+use openssl::x509::X509;
+use std::fmt;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Plaintext protocol upgraded with STARTTLS before the handshake
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StartTls {
+    Smtp,
+    Imap,
+}
+
+impl fmt::Display for StartTls {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            StartTls::Smtp => "smtp",
+            StartTls::Imap => "imap",
+        })
+    }
+}
+
+impl FromStr for StartTls {
+    type Err = FluxError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "smtp" => Ok(StartTls::Smtp),
+            "imap" => Ok(StartTls::Imap),
+            _ => Err(FluxError::InvalidConfigValue("starttls".to_string(), format!("'{}' (expected smtp or imap)", s))),
+        }
+    }
+}
+
+/// `host:port` with 443 filled in when the port is missing
+pub fn with_default_port(address: &str) -> String {
+    let has_port = match address.strip_prefix('[') {
+        Some(rest) => rest.contains("]:"),
+        None => address.matches(':').count() == 1,
+    };
+    if has_port { address.to_string() } else { format!("{}:443", bracketed(address)) }
+}
+
+/// Chain presented on `address`, leaf first
+///
+/// `sni` is the name sent in the handshake (default: the host, unless it is
+/// an IP address).
+pub fn presented_chain(address: &str, sni: Option<&str>, starttls: Option<StartTls>, timeout: Duration) -> Result<Vec<X509>> {
+    let address = with_default_port(address);
+    let failed = |why: String| FluxError::RemoteInspectFailed(format!("{}: {}", address, why));
+
+    let mut stream = connect(&address, timeout).map_err(|e| match e {
+        FluxError::HealthCheckFailed(reason) => FluxError::RemoteInspectFailed(reason),
+        e => e,
+    })?;
+    if let Some(protocol) = starttls {
+        upgrade(&mut stream, protocol).map_err(|e| failed(format!("STARTTLS ({}): {}", protocol, e)))?;
+    }
+
+    let host = address.rsplit_once(':').map_or(address.as_str(), |(host, _)| host)
+        .trim_start_matches('[').trim_end_matches(']');
+    let server_name = sni.unwrap_or(host);
+    let tls = connector()?.configure()?
+        .verify_hostname(false)
+        .use_server_name_indication(sni.is_some() || host.parse::<std::net::IpAddr>().is_err())
+        .connect(server_name, stream)
+        .map_err(|e| failed(format!("TLS: {}", e)))?;
+
+    let chain: Vec<X509> = match tls.ssl().peer_cert_chain() {
+        Some(chain) => chain.iter().map(|cert| cert.to_owned()).collect(),
+        None => tls.ssl().peer_certificate().into_iter().collect(),
+    };
+    if chain.is_empty() {
+        return Err(failed("no certificate presented".to_string()));
+    }
+    Ok(chain)
+}
+
+/// Run the plaintext exchange that precedes the TLS handshake
+fn upgrade(stream: &mut TcpStream, protocol: StartTls) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    match protocol {
+        StartTls::Smtp => {
+            expect_reply(&mut reader, "220")?;
+            stream.write_all(b"EHLO flux-ssl-mgr\r\n")?;
+            expect_reply(&mut reader, "250")?;
+            stream.write_all(b"STARTTLS\r\n")?;
+            expect_reply(&mut reader, "220")
+        }
+        StartTls::Imap => {
+            expect_line(&mut reader, |line| line.starts_with("* OK"))?;
+            stream.write_all(b"a1 STARTTLS\r\n")?;
+            let line = expect_line(&mut reader, |line| line.starts_with("a1 "))?;
+            if line.starts_with("a1 OK") { Ok(()) } else { Err(refused(&line)) }
+        }
+    }
+}
+
+/// Read an SMTP reply, which may span several `code-` lines
+fn expect_reply(reader: &mut impl BufRead, code: &str) -> std::io::Result<()> {
+    loop {
+        let line = read_line(reader)?;
+        if !line.starts_with(code) {
+            return Err(refused(&line));
+        }
+        if line.as_bytes().get(3) != Some(&b'-') {
+            return Ok(());
+        }
+    }
+}
+
+/// Skip lines until one matches, returning it
+fn expect_line(reader: &mut impl BufRead, matches: impl Fn(&str) -> bool) -> std::io::Result<String> {
+    loop {
+        let line = read_line(reader)?;
+        if matches(&line) {
+            return Ok(line);
+        }
+    }
+}
+
+fn read_line(reader: &mut impl BufRead) -> std::io::Result<String> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "connection closed"));
+    }
+    Ok(line.trim_end().to_string())
+}
+
+fn refused(line: &str) -> std::io::Error {
+    std::io::Error::other(format!("server answered '{}'", line))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ca::CertSigner;
+    use crate::fixtures;
+    use openssl::ssl::{SslAcceptor, SslMethod};
+    use std::io::Read;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_default_port() {
+        assert_eq!(with_default_port("nas.lab"), "nas.lab:443");
+        assert_eq!(with_default_port("nas.lab:636"), "nas.lab:636");
+        assert_eq!(with_default_port("fd00::2"), "[fd00::2]:443");
+        assert_eq!(with_default_port("[fd00::2]:993"), "[fd00::2]:993");
+        assert_eq!("IMAP".parse::<StartTls>().unwrap(), StartTls::Imap);
+    }
+
+    #[test]
+    fn test_presented_chain_with_starttls() {
+        let ca = fixtures::intermediate_ca();
+        let leaf = ca.sign(&fixtures::leaf_csr("mail", &[]), 30).unwrap();
+
+        let mut acceptor = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls()).unwrap();
+        acceptor.set_private_key(&fixtures::leaf_key()).unwrap();
+        acceptor.set_certificate(&leaf).unwrap();
+        acceptor.add_extra_chain_cert(ca.cert().clone()).unwrap();
+        let acceptor = acceptor.build();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        let server = std::thread::spawn(move || {
+            for greeting in [None, Some("220 mail.lab ESMTP\r\n")] {
+                let (mut stream, _) = listener.accept().unwrap();
+                if let Some(greeting) = greeting {
+                    stream.write_all(greeting.as_bytes()).unwrap();
+                    let mut buf = [0u8; 256];
+                    let _ = stream.read(&mut buf).unwrap();
+                    stream.write_all(b"250-mail.lab\r\n250 STARTTLS\r\n").unwrap();
+                    let _ = stream.read(&mut buf).unwrap();
+                    stream.write_all(b"220 Ready to start TLS\r\n").unwrap();
+                }
+                let _ = acceptor.accept(stream);
+            }
+        });
+
+        let serials = |chain: Vec<X509>| chain.iter().map(|c| c.serial_number().to_bn().unwrap()).collect::<Vec<_>>();
+        let expected = serials(vec![leaf, ca.cert().clone()]);
+        let timeout = Duration::from_secs(5);
+        assert_eq!(serials(presented_chain(&address, Some("mail.lab"), None, timeout).unwrap()), expected);
+        assert_eq!(serials(presented_chain(&address, None, Some(StartTls::Smtp), timeout).unwrap()), expected);
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,206 @@
+//! Rolling back to an earlier certificate generation
+//!
+//! With `overwrite_policy = "backup"` (the default) every re-issue moves the
+//! replaced certificate and key to `output_dir/.bak/<timestamp>/`. A rollback
+//! puts the newest backed-up generation that is older than the current
+//! certificate (and neither expired nor revoked) back in place and records it
+//! as the current certificate. The files it replaces are backed up the same
+//! way, so running it again goes back one more generation.
+//!
+//! Other overwrite policies keep no earlier generations, so rollback refuses
+//! to run under them rather than finding nothing to restore.
+
+use crate::batch::{backup_root, new_backup_dir, validate_cert_name, IssuancePaths};
+use crate::config::{Config, OverwritePolicy};
+use crate::crypto::load_cert;
+use crate::error::{FluxError, Result};
+use crate::fsutil::backup_files;
+use crate::store::{update_store, CertStore, IssuedCert};
+use std::path::{Path, PathBuf};
+
+/// A certificate (and key) kept in a backup directory
+#[derive(Debug, Clone)]
+pub struct Generation {
+    pub dir: PathBuf,
+    /// The backed-up certificate, with paths inside `dir`
+    pub record: IssuedCert,
+}
+
+/// Backed-up generations of `name`, newest certificate first
+pub fn generations(config: &Config, name: &str) -> Result<Vec<Generation>> {
+    let root = backup_root(config);
+    if !root.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let paths = IssuancePaths::new(config, name);
+    let file_name = |path: &Path| path.file_name().map(PathBuf::from).unwrap_or_default();
+    let (cert_file, key_file) = (file_name(&paths.output_cert_pem), file_name(&paths.output_key));
+
+    let mut generations = Vec::new();
+    for entry in std::fs::read_dir(&root)? {
+        let dir = entry?.path();
+        let cert_path = dir.join(&cert_file);
+        // Backups of other names, or unreadable leftovers
+        let Ok(cert) = load_cert(&cert_path) else { continue };
+
+        let key_path = dir.join(&key_file);
+        let key_path = key_path.exists().then_some(key_path);
+        let record = IssuedCert::from_cert(name, &cert, &cert_path, key_path.as_deref())?;
+        generations.push(Generation { dir, record });
+    }
+
+    generations.sort_by_key(|g| std::cmp::Reverse(g.record.not_before));
+    Ok(generations)
+}
+
+/// The generation a rollback of `name` would restore
+pub fn previous(config: &Config, name: &str) -> Result<Generation> {
+    if config.overwrite_policy != OverwritePolicy::Backup {
+        return Err(FluxError::RollbackError(format!(
+            "rollback needs overwrite_policy = \"backup\", which keeps earlier generations in {} \
+             (it is \"{}\", so re-issues don't keep them)",
+            backup_root(config).display(),
+            format!("{:?}", config.overwrite_policy).to_lowercase()
+        )));
+    }
+
+    let store = CertStore::open_default(config)?;
+    let current = load_cert(IssuancePaths::new(config, name).output_cert_pem).ok()
+        .map(|cert| IssuedCert::from_cert(name, &cert, Path::new(""), None))
+        .transpose()?;
+    let revoked = |serial: &str| store.certificates().iter().any(|c| c.serial == serial && c.is_revoked());
+
+    generations(config, name)?
+        .into_iter()
+        .filter(|g| current.as_ref().is_none_or(|c| g.record.not_before < c.not_before && g.record.serial != c.serial))
+        .find(|g| !g.record.is_expired() && !revoked(&g.record.serial))
+        .ok_or_else(|| FluxError::RollbackError(format!(
+            "no earlier unexpired, unrevoked generation of {} in {}",
+            name,
+            backup_root(config).display()
+        )))
+}
+
+/// Restore the previous generation of `name`, returning its new store record
+pub fn rollback(config: &Config, name: &str) -> Result<IssuedCert> {
+    validate_cert_name(name)?;
+    let previous = previous(config, name)?;
+    let paths = IssuancePaths::new(config, name);
+
+    // Keep the current files, so the rollback can itself be rolled back
+    let existing: Vec<&Path> = paths.outputs().into_iter().filter(|p| p.exists()).collect();
+    if !existing.is_empty() {
+        backup_files(&existing, &new_backup_dir(config)?)?;
+    }
+
+    for output in paths.outputs() {
+        let Some(file_name) = output.file_name() else { continue };
+        let backup = previous.dir.join(file_name);
+        if backup.exists() {
+            std::fs::rename(&backup, output)
+                .map_err(|e| FluxError::FileWriteFailed(output.to_path_buf(), e.to_string()))?;
+        }
+    }
+    // Only succeeds once nothing else is kept there
+    let _ = std::fs::remove_dir(&previous.dir);
+
+    let cert = load_cert(&paths.output_cert_pem)?;
+    let key_path = paths.output_key.exists().then_some(paths.output_key.as_path());
+    let mut record = IssuedCert::from_cert(name, &cert, &paths.output_cert_pem, key_path)?;
+
+    let message = format!("Roll back {} to serial {}", name, record.serial);
+    update_store(config, &message, |store| {
+        record.key_encrypted = store.certificates().iter()
+            .any(|c| c.serial == record.serial && c.key_encrypted);
+        store.record(record.clone());
+    })?;
+
+    Ok(record)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ca::CertSigner;
+    use crate::crypto::save_cert_pem;
+    use crate::fixtures;
+    use crate::store::record_issuance;
+
+    fn issue(config: &Config, days: u32) -> IssuedCert {
+        let cert = fixtures::intermediate_ca().sign(&fixtures::leaf_csr("web", &[]), days).unwrap();
+        let paths = IssuancePaths::new(config, "web");
+        std::fs::create_dir_all(&config.output_dir).unwrap();
+        crate::batch::protect_existing_outputs(&paths, config).unwrap();
+        save_cert_pem(&cert, &paths.output_cert_pem).unwrap();
+        std::fs::write(&paths.output_key, format!("KEY {}", days)).unwrap();
+
+        let record = IssuedCert::from_cert("web", &cert, &paths.output_cert_pem, Some(&paths.output_key)).unwrap();
+        record_issuance(config, record.clone()).unwrap();
+        // Generations are ordered by notBefore, which has one-second resolution
+        std::thread::sleep(std::time::Duration::from_millis(1100));
+        record
+    }
+
+    #[test]
+    fn test_rollback_walks_back_generations() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let config = Config {
+            working_dir: temp_dir.path().join("pki"),
+            output_dir: temp_dir.path().join("out"),
+            ..Config::default()
+        };
+        assert!(matches!(rollback(&config, "web"), Err(FluxError::RollbackError(_))));
+
+        let first = issue(&config, 30);
+        let second = issue(&config, 60);
+        let third = issue(&config, 90);
+        assert_eq!(generations(&config, "web").unwrap().len(), 2);
+
+        let restored = rollback(&config, "web").unwrap();
+        assert_eq!(restored.serial, second.serial);
+        let paths = IssuancePaths::new(&config, "web");
+        assert_eq!(std::fs::read_to_string(&paths.output_key).unwrap(), "KEY 60");
+        assert_eq!(CertStore::open_default(&config).unwrap().latest("web").unwrap().serial, second.serial);
+
+        // The third generation is newer, so the next rollback skips it
+        assert_eq!(rollback(&config, "web").unwrap().serial, first.serial);
+        assert!(generations(&config, "web").unwrap().iter().any(|g| g.record.serial == third.serial));
+        assert!(rollback(&config, "web").is_err());
+    }
+
+    #[test]
+    fn test_previous_skips_revoked() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let config = Config {
+            working_dir: temp_dir.path().join("pki"),
+            output_dir: temp_dir.path().join("out"),
+            ..Config::default()
+        };
+        issue(&config, 30);
+        issue(&config, 60);
+        update_store(&config, "Revoke", |store| store.revoke("web", "keyCompromise")).unwrap();
+        issue(&config, 90);
+
+        assert_eq!(generations(&config, "web").unwrap().len(), 2);
+        assert!(previous(&config, "web").is_err());
+    }
+
+    #[test]
+    fn test_rollback_needs_backup_policy() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut config = Config {
+            working_dir: temp_dir.path().join("pki"),
+            output_dir: temp_dir.path().join("out"),
+            ..Config::default()
+        };
+        issue(&config, 30);
+        issue(&config, 60);
+
+        for policy in [OverwritePolicy::Overwrite, OverwritePolicy::Fail] {
+            config.overwrite_policy = policy;
+            let err = rollback(&config, "web").unwrap_err();
+            assert!(err.to_string().contains("overwrite_policy = \"backup\""), "{}", err);
+        }
+    }
+}
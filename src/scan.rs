@@ -0,0 +1,285 @@
+//! Finding where certificates are served
+//!
+//! `scan` connects to the hosts in a certificate's SANs on the usual TLS ports
+//! (`[scan] ports`) and records every endpoint presenting a certificate issued
+//! under that name, such as `https:443` or `ldaps:636`. `status` shows the
+//! endpoints, and `scan --verify` probes just those endpoints to catch drift:
+//! a service still presenting an older certificate after a renewal.
+
+use crate::duration::Interval;
+use crate::error::{FluxError, Result};
+use crate::health::{bracketed, connect, connector};
+use crate::store::{CertStore, IssuedCert};
+use chrono::{DateTime, Utc};
+use openssl::x509::X509;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fmt;
+use std::time::Duration;
+
+/// Where to look for certificates
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScanConfig {
+    /// Ports tried on every host
+    #[serde(default = "default_ports")]
+    pub ports: Vec<u16>,
+
+    /// Connect and handshake timeout per endpoint
+    #[serde(default = "default_timeout")]
+    pub timeout: Interval,
+}
+
+impl Default for ScanConfig {
+    fn default() -> Self {
+        Self { ports: default_ports(), timeout: default_timeout() }
+    }
+}
+
+fn default_ports() -> Vec<u16> { vec![443, 8443, 636, 465, 993, 995, 853, 8883] }
+fn default_timeout() -> Interval { Interval::from_secs(3) }
+
+/// Usual name of the TLS service on `port`
+pub fn protocol(port: u16) -> &'static str {
+    match port {
+        443 | 8443 => "https",
+        636 => "ldaps",
+        465 => "smtp",
+        993 => "imaps",
+        995 => "pop3s",
+        853 => "dot",
+        5061 => "sips",
+        6697 => "ircs",
+        8883 => "mqtts",
+        _ => "tls",
+    }
+}
+
+/// A host and port a certificate was found on
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Endpoint {
+    pub host: String,
+    pub port: u16,
+    /// Service on the port (`https`, `ldaps`, ...)
+    pub protocol: String,
+    /// Last scan that found a certificate of this name here
+    pub last_seen: DateTime<Utc>,
+}
+
+impl Endpoint {
+    /// `host:port`, with IPv6 literals bracketed
+    pub fn address(&self) -> String {
+        format!("{}:{}", bracketed(&self.host), self.port)
+    }
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.protocol, self.port)
+    }
+}
+
+/// What an endpoint presented
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Served {
+    /// The current certificate
+    Current,
+    /// An older certificate of the same name, by serial
+    Outdated(String),
+    /// A certificate that isn't one of this name's
+    Other(String),
+    /// Nothing: closed, filtered, or not TLS
+    Unreachable(String),
+}
+
+/// One probed endpoint
+#[derive(Debug, Clone)]
+pub struct Finding {
+    pub endpoint: Endpoint,
+    pub served: Served,
+}
+
+/// Hosts named in a certificate's SANs; wildcards can't be probed
+pub fn hosts(record: &IssuedCert) -> Vec<String> {
+    let mut hosts: Vec<String> = record.sans.iter()
+        .filter_map(|san| san.strip_prefix("DNS:").or_else(|| san.strip_prefix("IP:")))
+        .filter(|host| !host.contains('*'))
+        .map(str::to_string)
+        .collect();
+    hosts.dedup();
+    hosts
+}
+
+/// Probe the SAN hosts on `ports`, and the endpoints found before
+///
+/// Returns a finding per endpoint presenting one of the name's certificates,
+/// plus the recorded endpoints that couldn't be reached this time.
+pub fn discover(store: &CertStore, record: &IssuedCert, ports: &[u16], timeout: Duration) -> Vec<Finding> {
+    let mut targets: BTreeSet<(String, u16)> = hosts(record).into_iter()
+        .flat_map(|host| ports.iter().map(move |port| (host.clone(), *port)))
+        .collect();
+    targets.extend(record.endpoints.iter().map(|e| (e.host.clone(), e.port)));
+
+    probe_all(store, record, targets, timeout)
+        .into_iter()
+        .filter(|finding| match finding.served {
+            Served::Current | Served::Outdated(_) => true,
+            Served::Unreachable(_) => record.endpoints.iter().any(|e| e.host == finding.endpoint.host && e.port == finding.endpoint.port),
+            Served::Other(_) => false,
+        })
+        .collect()
+}
+
+/// Probe only the endpoints recorded for `record`
+pub fn verify(store: &CertStore, record: &IssuedCert, timeout: Duration) -> Vec<Finding> {
+    let targets = record.endpoints.iter().map(|e| (e.host.clone(), e.port)).collect();
+    probe_all(store, record, targets, timeout)
+}
+
+/// Endpoints to record after a scan: what was found, and what was unreachable
+///
+/// Unreachable endpoints keep their last sighting, so a host that was down
+/// during one scan isn't forgotten.
+pub fn endpoints(record: &IssuedCert, findings: &[Finding]) -> Vec<Endpoint> {
+    findings.iter()
+        .filter_map(|finding| match finding.served {
+            Served::Current | Served::Outdated(_) => Some(finding.endpoint.clone()),
+            Served::Unreachable(_) => record.endpoints.iter()
+                .find(|e| e.host == finding.endpoint.host && e.port == finding.endpoint.port)
+                .cloned(),
+            Served::Other(_) => None,
+        })
+        .collect()
+}
+
+/// Probe endpoints in parallel, in `targets` order
+fn probe_all(store: &CertStore, record: &IssuedCert, targets: BTreeSet<(String, u16)>, timeout: Duration) -> Vec<Finding> {
+    let serials: BTreeSet<String> = store.certificates().iter()
+        .filter(|c| c.name == record.name)
+        .map(|c| normalize_serial(&c.serial))
+        .collect();
+    let current = normalize_serial(&record.serial);
+    let now = Utc::now();
+
+    std::thread::scope(|scope| {
+        let probes: Vec<_> = targets.into_iter()
+            .map(|(host, port)| {
+                let (serials, current) = (&serials, &current);
+                scope.spawn(move || {
+                    let served = match presented(&host, port, timeout) {
+                        Err(e) => Served::Unreachable(reason(e)),
+                        Ok(cert) => {
+                            let serial = serial_of(&cert);
+                            if serial == *current {
+                                Served::Current
+                            } else if serials.contains(&serial) {
+                                Served::Outdated(serial)
+                            } else {
+                                Served::Other(serial)
+                            }
+                        }
+                    };
+                    let endpoint = Endpoint { protocol: protocol(port).to_string(), host, port, last_seen: now };
+                    Finding { endpoint, served }
+                })
+            })
+            .collect();
+        probes.into_iter().filter_map(|probe| probe.join().ok()).collect()
+    })
+}
+
+/// Leaf certificate presented on `host:port`, sending `host` as SNI
+fn presented(host: &str, port: u16, timeout: Duration) -> Result<X509> {
+    let address = format!("{}:{}", bracketed(host), port);
+    let stream = connect(&address, timeout)?;
+    let tls = connector()?.configure()?
+        .verify_hostname(false)
+        .use_server_name_indication(host.parse::<std::net::IpAddr>().is_err())
+        .connect(host, stream)
+        .map_err(|e| FluxError::HealthCheckFailed(format!("{}: TLS: {}", address, e)))?;
+    tls.ssl().peer_certificate()
+        .ok_or_else(|| FluxError::HealthCheckFailed(format!("{}: no certificate presented", address)))
+}
+
+fn reason(e: FluxError) -> String {
+    match e {
+        FluxError::HealthCheckFailed(reason) => reason,
+        e => e.to_string(),
+    }
+}
+
+fn serial_of(cert: &X509) -> String {
+    cert.serial_number().to_bn()
+        .and_then(|bn| bn.to_hex_str().map(|s| s.to_string()))
+        .unwrap_or_default()
+}
+
+fn normalize_serial(serial: &str) -> String {
+    serial.chars().filter(|c| *c != ':').collect::<String>().to_uppercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ca::CertSigner;
+    use crate::fixtures;
+    use openssl::ssl::{SslAcceptor, SslMethod};
+    use std::net::TcpListener;
+    use std::path::Path;
+
+    fn record(cert: &X509) -> IssuedCert {
+        let mut record = IssuedCert::from_cert("web", cert, Path::new("/tmp/web.cert.pem"), None).unwrap();
+        record.sans = vec!["IP:127.0.0.1".to_string(), "DNS:*.lab".to_string()];
+        record
+    }
+
+    #[test]
+    fn test_protocol_names() {
+        assert_eq!(protocol(443), "https");
+        assert_eq!(protocol(636), "ldaps");
+        assert_eq!(protocol(465), "smtp");
+        assert_eq!(protocol(9443), "tls");
+    }
+
+    #[test]
+    fn test_discover_and_verify() {
+        let ca = fixtures::intermediate_ca();
+        let old = ca.sign(&fixtures::leaf_csr("web", &[]), 30).unwrap();
+        let new = ca.sign(&fixtures::leaf_csr("web", &[]), 30).unwrap();
+
+        let mut acceptor = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls()).unwrap();
+        acceptor.set_private_key(&fixtures::leaf_key()).unwrap();
+        acceptor.set_certificate(&old).unwrap();
+        let acceptor = acceptor.build();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            for _ in 0..2 {
+                let (stream, _) = listener.accept().unwrap();
+                let _ = acceptor.accept(stream);
+            }
+        });
+
+        let mut store = CertStore::default();
+        store.record(record(&old));
+        let mut current = record(&new);
+        current.issued_at += chrono::Duration::seconds(1);
+        store.record(current.clone());
+        assert_eq!(hosts(&current), ["127.0.0.1"]);
+
+        // The renewed certificate hasn't been deployed yet
+        let findings = discover(&store, &current, &[port], Duration::from_secs(2));
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].served, Served::Outdated(serial_of(&old)));
+        current.endpoints = endpoints(&current, &findings);
+        assert_eq!(current.endpoints[0].to_string(), format!("tls:{}", port));
+
+        let findings = verify(&store, &current, Duration::from_secs(2));
+        assert_eq!(findings[0].served, Served::Outdated(serial_of(&old)));
+        server.join().unwrap();
+
+        // Down for now, but still recorded
+        let findings = verify(&store, &current, Duration::from_secs(2));
+        assert!(matches!(findings[0].served, Served::Unreachable(_)));
+        assert_eq!(endpoints(&current, &findings), current.endpoints);
+    }
+}
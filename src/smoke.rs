@@ -0,0 +1,170 @@
+//! End-to-end smoke test of the configured CA (`flux-ssl-mgr smoke`)
+//!
+//! Issues a one-day certificate for a throwaway name through the same signer
+//! as every other command (the agent if one is running), checks that it
+//! chains to the CA, optionally serves it on an ephemeral loopback TLS port
+//! and connects to it as a client would, then records, revokes and forgets it
+//! in the certificate store (and revokes it in the OpenSSL CA database, if
+//! `[openssl_ca]` is enabled). Nothing is written to the output directory.
+
+use crate::ca::{chain_pem, CertSigner, OpensslDb};
+use crate::config::Config;
+use crate::crypto::{self, backend::backend, verify_chain, SanEntry};
+use crate::error::{FluxError, Result};
+use crate::output::OutputFormatter;
+use crate::store::{update_store, IssuedCert};
+use openssl::pkey::{PKey, Private};
+use openssl::ssl::{SslAcceptor, SslConnector, SslMethod};
+use openssl::x509::verify::X509VerifyFlags;
+use openssl::x509::X509;
+use std::net::TcpListener;
+use std::path::Path;
+use std::time::Duration;
+
+/// Validity of the throwaway certificate
+const SMOKE_DAYS: u32 = 1;
+
+/// Throwaway certificate name; `.invalid` never resolves
+fn smoke_name() -> String {
+    let mut bytes = [0u8; 4];
+    let suffix = openssl::rand::rand_bytes(&mut bytes)
+        .map_or_else(|_| std::process::id().to_string(), |()| hex(&bytes));
+    format!("flux-smoke-{}.invalid", suffix)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Error for a failed `step`, unless it already names one
+fn failed(step: &str) -> impl FnOnce(FluxError) -> FluxError + '_ {
+    move |e| match e {
+        FluxError::SmokeTestFailed(..) => e,
+        e => FluxError::SmokeTestFailed(step.to_string(), e.to_string()),
+    }
+}
+
+/// Run the smoke test, reporting each step as it passes
+///
+/// With `tls`, the certificate is also served on `127.0.0.1` and a client
+/// trusting only the CA chain connects to it by name.
+pub fn run(config: &Config, signer: &dyn CertSigner, tls: bool, output: &OutputFormatter) -> Result<()> {
+    let name = smoke_name();
+
+    let (key, cert) = issue(config, signer, &name).map_err(failed("issue"))?;
+    let serial = crypto::extract_certificate_info(&cert).map_err(failed("issue"))?.serial_number;
+    output.success(&format!("Issued {} (serial {}, {} day)", name, serial, SMOKE_DAYS));
+
+    let chain = chain_pem(config).and_then(|pem| crypto::certs_from_pem(&pem)).map_err(failed("chain"))?;
+    let verification = verify_chain(&cert, &chain, &[]).map_err(failed("chain"))?;
+    if !verification.trusted {
+        let why = verification.error.unwrap_or_else(|| "not trusted".to_string());
+        return Err(FluxError::SmokeTestFailed("chain".to_string(), why));
+    }
+    output.success(&format!("Chains to {}", verification.chain.last().map_or("the CA", |c| c.subject.as_str())));
+
+    if tls {
+        let port = handshake(&name, &key, &cert, &chain).map_err(failed("tls"))?;
+        output.success(&format!("TLS handshake on 127.0.0.1:{} verified {}", port, name));
+    }
+
+    let revoked = update_store(config, &format!("Smoke test {}", name), |store| {
+        store.record(IssuedCert::from_cert(&name, &cert, Path::new(""), None)?);
+        let revoked = store.revoke(&name, "cessationOfOperation");
+        store.forget(&name);
+        Ok::<_, FluxError>(revoked)
+    })
+    .and_then(|r| r)
+    .map_err(failed("store"))?;
+    if revoked != [serial.clone()] {
+        return Err(FluxError::SmokeTestFailed("store".to_string(), format!("revoking {} returned {:?}", serial, revoked)));
+    }
+    output.success("Recorded, revoked and removed from the certificate store");
+
+    if let Some(db) = OpensslDb::from_config(config) {
+        db.revoke(&[serial], "cessationOfOperation", chrono::Utc::now()).map_err(failed("store"))?;
+        output.success(&format!("Revoked in {}", db.index_path().display()));
+    }
+
+    Ok(())
+}
+
+/// Throwaway key and certificate for `name` and `127.0.0.1`
+fn issue(config: &Config, signer: &dyn CertSigner, name: &str) -> Result<(PKey<Private>, X509)> {
+    let backend = backend(config.crypto_backend)?;
+    let key_pem = zeroize::Zeroizing::new(backend.generate_key(config.defaults.key_size)?);
+    let sans = [SanEntry::Dns(name.to_string()), SanEntry::Ip("127.0.0.1".to_string())];
+    let csr = crypto::csr_from_pem_bytes(&backend.create_csr(&key_pem, name, &sans)?)?;
+    let cert = signer.sign(&csr, SMOKE_DAYS)?;
+    let key = PKey::private_key_from_pem(&key_pem)?;
+    Ok((key, cert))
+}
+
+/// Serve `cert` on an ephemeral loopback port and connect to it
+///
+/// The client trusts only `chain` and checks the name, as a browser would.
+/// Returns the port used.
+fn handshake(name: &str, key: &PKey<Private>, cert: &X509, chain: &[X509]) -> Result<u16> {
+    let mut acceptor = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls())?;
+    acceptor.set_private_key(key)?;
+    acceptor.set_certificate(cert)?;
+    for ca in chain {
+        acceptor.add_extra_chain_cert(ca.clone())?;
+    }
+    let acceptor = acceptor.build();
+
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let port = listener.local_addr()?.port();
+    let server = std::thread::spawn(move || -> std::result::Result<(), String> {
+        let (stream, _) = listener.accept().map_err(|e| e.to_string())?;
+        stream.set_read_timeout(Some(Duration::from_secs(10))).map_err(|e| e.to_string())?;
+        acceptor.accept(stream).map(drop).map_err(|e| e.to_string())
+    });
+
+    let mut connector = SslConnector::builder(SslMethod::tls_client())?;
+    for ca in chain {
+        connector.cert_store_mut().add_cert(ca.clone())?;
+    }
+    // The intermediate may be the only anchor when the root isn't on this machine
+    connector.verify_param_mut().set_flags(X509VerifyFlags::PARTIAL_CHAIN)?;
+    let stream = crate::health::connect(&format!("127.0.0.1:{}", port), Duration::from_secs(10))?;
+    let client = connector.build().connect(name, stream)
+        .map_err(|e| FluxError::SmokeTestFailed("tls".to_string(), e.to_string()));
+    drop(client?);
+
+    server.join()
+        .map_err(|_| FluxError::SmokeTestFailed("tls".to_string(), "server thread panicked".to_string()))?
+        .map_err(|e| FluxError::SmokeTestFailed("tls".to_string(), format!("server: {}", e)))?;
+    Ok(port)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::OutputConfig;
+    use crate::crypto::save_cert_pem;
+    use crate::fixtures;
+    use crate::store::CertStore;
+
+    #[test]
+    fn test_smoke_passes_and_leaves_no_trace() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut config = Config {
+            working_dir: temp_dir.path().to_path_buf(),
+            ca_cert_path: temp_dir.path().join("ca.cert.pem"),
+            ..Config::default()
+        };
+        config.defaults.key_size = 2048;
+        let ca = fixtures::intermediate_ca();
+        save_cert_pem(ca.cert(), &config.ca_cert_path).unwrap();
+        let output = OutputFormatter::with_writer(&OutputConfig::default(), std::io::sink());
+
+        run(&config, &ca, true, &output).unwrap();
+        assert!(CertStore::open_default(&config).unwrap().certificates().is_empty());
+
+        // A CA certificate that doesn't match the signing key fails the chain step
+        save_cert_pem(&fixtures::root_ca_cert(), &config.ca_cert_path).unwrap();
+        let err = run(&config, &ca, false, &output).unwrap_err();
+        assert!(matches!(err, FluxError::SmokeTestFailed(ref step, _) if step == "chain"), "{}", err);
+    }
+}
@@ -0,0 +1,402 @@
+//! Issued certificate store
+//!
+//! Keeps a JSON record of every certificate issued by this tool so that
+//! later runs can tell what exists, what is expiring, and what changed.
+
+use crate::config::Config;
+use crate::crypto::extract_certificate_info;
+use crate::error::{FluxError, Result};
+use crate::history;
+use crate::scan::Endpoint;
+use chrono::{DateTime, Utc};
+use openssl::x509::X509;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Serializes read-modify-write cycles on the store within this process
+static STORE_LOCK: Mutex<()> = Mutex::new(());
+
+/// Record of an issued certificate
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IssuedCert {
+    /// Certificate name (file stem in the output directory)
+    pub name: String,
+    /// Serial number (hex)
+    pub serial: String,
+    /// Subject DN
+    pub subject: String,
+    /// Subject Alternative Names (e.g. `DNS:example.com`)
+    #[serde(default)]
+    pub sans: Vec<String>,
+    /// Not valid before timestamp
+    pub not_before: DateTime<Utc>,
+    /// Not valid after timestamp
+    pub not_after: DateTime<Utc>,
+    /// When the certificate was recorded
+    pub issued_at: DateTime<Utc>,
+    /// Path of the issued certificate
+    pub cert_path: PathBuf,
+    /// Path of the matching private key, if generated by this tool
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_path: Option<PathBuf>,
+    /// Whether the private key is password-protected
+    #[serde(default)]
+    pub key_encrypted: bool,
+    /// When the certificate was revoked
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revoked_at: Option<DateTime<Utc>>,
+    /// RFC 5280 revocation reason (e.g. `cessationOfOperation`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revocation_reason: Option<String>,
+    /// Where `scan` found the certificate served (e.g. `https:443`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub endpoints: Vec<Endpoint>,
+}
+
+impl IssuedCert {
+    /// Build a record from an issued certificate
+    pub fn from_cert(name: &str, cert: &X509, cert_path: &Path, key_path: Option<&Path>) -> Result<Self> {
+        let info = extract_certificate_info(cert)?;
+
+        Ok(Self {
+            name: name.to_string(),
+            serial: info.serial_number,
+            subject: info.subject,
+            sans: info.sans,
+            not_before: info.not_before,
+            not_after: info.not_after,
+            issued_at: Utc::now(),
+            cert_path: cert_path.to_path_buf(),
+            key_path: key_path.map(Path::to_path_buf),
+            key_encrypted: false,
+            revoked_at: None,
+            revocation_reason: None,
+            endpoints: Vec::new(),
+        })
+    }
+
+    /// Days until expiration (negative if already expired)
+    pub fn days_remaining(&self) -> i64 {
+        (self.not_after - Utc::now()).num_days()
+    }
+
+    /// Whether the certificate has expired
+    pub fn is_expired(&self) -> bool {
+        self.not_after < Utc::now()
+    }
+
+    /// Whether the certificate has been revoked
+    pub fn is_revoked(&self) -> bool {
+        self.revoked_at.is_some()
+    }
+}
+
+/// A deployment waiting for its target's maintenance window
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PendingDeploy {
+    pub name: String,
+    pub target: String,
+    pub queued_at: DateTime<Utc>,
+}
+
+/// JSON-backed store of issued certificates
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct CertStore {
+    #[serde(skip)]
+    path: PathBuf,
+
+    #[serde(default)]
+    certificates: Vec<IssuedCert>,
+
+    /// Names owned by a manifest, which `apply` revokes once they leave it
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    managed: BTreeSet<String>,
+
+    /// Deployments deferred to a maintenance window
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pending_deploys: Vec<PendingDeploy>,
+}
+
+impl CertStore {
+    /// Open the store at `path`, starting empty if it doesn't exist yet
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+
+        if !path.exists() {
+            return Ok(Self { path, ..Self::default() });
+        }
+
+        let data = std::fs::read_to_string(&path)
+            .map_err(|e| FluxError::FileReadFailed(path.clone(), e.to_string()))?;
+        let mut store: CertStore = serde_json::from_str(&data)
+            .map_err(|e| FluxError::StoreError(format!("{}: {}", path.display(), e)))?;
+        store.path = path;
+
+        Ok(store)
+    }
+
+    /// Open the store configured for this PKI
+    pub fn open_default(config: &Config) -> Result<Self> {
+        Self::open(config.store_path())
+    }
+
+    /// Write the store back to disk
+    pub fn save(&self) -> Result<()> {
+        let data = serde_json::to_string_pretty(self)
+            .map_err(|e| FluxError::StoreError(e.to_string()))?;
+
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        // Write to a sibling file and rename so readers never see a partial store
+        let tmp_path = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, data)
+            .map_err(|e| FluxError::FileWriteFailed(tmp_path.clone(), e.to_string()))?;
+        std::fs::rename(&tmp_path, &self.path)
+            .map_err(|e| FluxError::FileWriteFailed(self.path.clone(), e.to_string()))?;
+
+        Ok(())
+    }
+
+    /// Add an issuance record
+    ///
+    /// A renewal is served where its predecessor was, so it inherits the
+    /// predecessor's endpoints unless it has its own.
+    pub fn record(&mut self, mut cert: IssuedCert) {
+        if cert.endpoints.is_empty() {
+            if let Some(previous) = self.latest(&cert.name) {
+                cert.endpoints = previous.endpoints.clone();
+            }
+        }
+        self.certificates.push(cert);
+    }
+
+    /// Replace the endpoints of the latest record for `name`
+    pub fn set_endpoints(&mut self, name: &str, endpoints: Vec<Endpoint>) {
+        if let Some(latest) = self.certificates.iter_mut()
+            .filter(|c| c.name == name)
+            .max_by_key(|c| c.issued_at)
+        {
+            latest.endpoints = endpoints;
+        }
+    }
+
+    /// All records, in issuance order
+    pub fn certificates(&self) -> &[IssuedCert] {
+        &self.certificates
+    }
+
+    /// Most recent record for a certificate name
+    pub fn latest(&self, name: &str) -> Option<&IssuedCert> {
+        self.certificates.iter()
+            .filter(|c| c.name == name)
+            .max_by_key(|c| c.issued_at)
+    }
+
+    /// Most recent record for every certificate name, sorted by name
+    ///
+    /// Names whose latest certificate was revoked are left out.
+    pub fn current(&self) -> Vec<&IssuedCert> {
+        self.latest_each().into_iter().filter(|c| !c.is_revoked()).collect()
+    }
+
+    /// Most recent record for every certificate name, revoked or not, sorted by name
+    pub fn latest_each(&self) -> Vec<&IssuedCert> {
+        let mut names: Vec<&str> = self.certificates.iter().map(|c| c.name.as_str()).collect();
+        names.sort_unstable();
+        names.dedup();
+
+        names.into_iter().filter_map(|name| self.latest(name)).collect()
+    }
+
+    /// Revoke every unrevoked certificate issued under `name`
+    ///
+    /// Returns the serials that were revoked.
+    pub fn revoke(&mut self, name: &str, reason: &str) -> Vec<String> {
+        let now = Utc::now();
+
+        self.certificates.iter_mut()
+            .filter(|c| c.name == name && !c.is_revoked())
+            .map(|c| {
+                c.revoked_at = Some(now);
+                c.revocation_reason = Some(reason.to_string());
+                c.serial.clone()
+            })
+            .collect()
+    }
+
+    /// Drop every record for `name`, returning how many there were
+    pub fn forget(&mut self, name: &str) -> usize {
+        let before = self.certificates.len();
+        self.certificates.retain(|c| c.name != name);
+        before - self.certificates.len()
+    }
+
+    /// Names owned by a manifest
+    pub fn managed(&self) -> &BTreeSet<String> {
+        &self.managed
+    }
+
+    /// Mutable set of names owned by a manifest
+    pub fn managed_mut(&mut self) -> &mut BTreeSet<String> {
+        &mut self.managed
+    }
+
+    /// Deployments waiting for a maintenance window, oldest first
+    pub fn pending_deploys(&self) -> &[PendingDeploy] {
+        &self.pending_deploys
+    }
+
+    /// Queue a deployment (once per certificate and target)
+    pub fn queue_deploy(&mut self, name: &str, target: &str) {
+        if !self.pending_deploys.iter().any(|p| p.name == name && p.target == target) {
+            self.pending_deploys.push(PendingDeploy {
+                name: name.to_string(),
+                target: target.to_string(),
+                queued_at: Utc::now(),
+            });
+        }
+    }
+
+    /// Drop a queued deployment, returning whether it was queued
+    pub fn remove_deploy(&mut self, name: &str, target: &str) -> bool {
+        let before = self.pending_deploys.len();
+        self.pending_deploys.retain(|p| !(p.name == name && p.target == target));
+        self.pending_deploys.len() != before
+    }
+}
+
+/// Open the configured store, change it and save it, holding the store lock
+///
+/// With git history enabled the change is committed with `message`; a failed
+/// commit is logged but doesn't fail the update.
+pub fn update_store<T>(config: &Config, message: &str, f: impl FnOnce(&mut CertStore) -> T) -> Result<T> {
+    let _guard = STORE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+    let mut store = CertStore::open_default(config)?;
+    let value = f(&mut store);
+    store.save()?;
+
+    if let Err(e) = history::commit(config, message) {
+        tracing::warn!("Failed to commit certificate store history: {}", e);
+    }
+
+    Ok(value)
+}
+
+/// Record an issuance in the configured store
+///
+/// Returns whether it replaces an unrevoked certificate of the same name
+/// (a renewal).
+pub fn record_issuance(config: &Config, record: IssuedCert) -> Result<bool> {
+    let message = format!("Issue {} (serial {})", record.name, record.serial);
+    update_store(config, &message, |store| {
+        let renewal = store.latest(&record.name).is_some_and(|c| !c.is_revoked());
+        store.record(record);
+        renewal
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn record(name: &str, issued_days_ago: i64, valid_days: i64) -> IssuedCert {
+        let issued_at = Utc::now() - Duration::days(issued_days_ago);
+        IssuedCert {
+            name: name.to_string(),
+            serial: format!("{:X}", issued_days_ago),
+            subject: format!("CN={}", name),
+            sans: vec![format!("DNS:{}.lab", name)],
+            not_before: issued_at,
+            not_after: issued_at + Duration::days(valid_days),
+            issued_at,
+            cert_path: PathBuf::from(format!("/tmp/{}.cert.pem", name)),
+            key_path: None,
+            key_encrypted: false,
+            revoked_at: None,
+            revocation_reason: None,
+            endpoints: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_store_roundtrip_and_latest() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("store.json");
+
+        let mut store = CertStore::open(&path).unwrap();
+        assert!(store.certificates().is_empty());
+
+        store.record(record("nas", 400, 375));
+        store.record(record("nas", 10, 375));
+        store.record(record("router", 5, 90));
+        store.save().unwrap();
+
+        let store = CertStore::open(&path).unwrap();
+        assert_eq!(store.certificates().len(), 3);
+
+        let nas = store.latest("nas").unwrap();
+        assert_eq!(nas.serial, "A");
+        assert!(!nas.is_expired());
+        assert_eq!(nas.days_remaining(), 364);
+
+        let current: Vec<_> = store.current().iter().map(|c| c.name.clone()).collect();
+        assert_eq!(current, vec!["nas", "router"]);
+    }
+
+    #[test]
+    fn test_revoke() {
+        let mut store = CertStore::default();
+        store.record(record("nas", 400, 375));
+        store.record(record("nas", 10, 375));
+        store.record(record("router", 5, 90));
+
+        assert_eq!(store.revoke("nas", "cessationOfOperation"), vec!["190", "A"]);
+        assert!(store.revoke("nas", "cessationOfOperation").is_empty());
+
+        let nas = store.latest("nas").unwrap();
+        assert!(nas.is_revoked());
+        assert_eq!(nas.revocation_reason.as_deref(), Some("cessationOfOperation"));
+
+        let current: Vec<_> = store.current().iter().map(|c| c.name.clone()).collect();
+        assert_eq!(current, vec!["router"]);
+    }
+
+    #[test]
+    fn test_pending_deploys() {
+        let mut store = CertStore::default();
+        store.queue_deploy("nas", "nas-ssh");
+        store.queue_deploy("nas", "nas-ssh");
+        store.queue_deploy("grafana", "k8s");
+
+        let pending: Vec<_> = store.pending_deploys().iter().map(|p| (p.name.as_str(), p.target.as_str())).collect();
+        assert_eq!(pending, vec![("nas", "nas-ssh"), ("grafana", "k8s")]);
+
+        assert!(store.remove_deploy("nas", "nas-ssh"));
+        assert!(!store.remove_deploy("nas", "nas-ssh"));
+        assert_eq!(store.pending_deploys().len(), 1);
+    }
+
+    #[test]
+    fn test_renewal_inherits_endpoints() {
+        let endpoint = Endpoint {
+            host: "nas.lab".to_string(),
+            port: 636,
+            protocol: "ldaps".to_string(),
+            last_seen: Utc::now(),
+        };
+        let mut store = CertStore::default();
+        store.record(record("nas", 400, 375));
+        store.set_endpoints("nas", vec![endpoint.clone()]);
+        store.record(record("nas", 10, 375));
+        store.record(record("router", 5, 90));
+
+        assert_eq!(store.latest("nas").unwrap().endpoints, [endpoint]);
+        assert!(store.latest("router").unwrap().endpoints.is_empty());
+    }
+}
@@ -0,0 +1,99 @@
+//! Machine-readable command summary (`--summary-json`)
+//!
+//! With `--summary-json` or `output.summary_json`, every command ends with a
+//! single JSON object on stderr: the command, how long it took, what it
+//! counted (certificates issued, skipped, failed, warnings, ...) and whether it
+//! succeeded. Wrappers and audit pipelines read that line instead of parsing
+//! the human output, which may change between releases.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Counters shared by every clone of an [`OutputFormatter`](crate::OutputFormatter)
+#[derive(Debug, Clone, Default)]
+pub struct Counters(Arc<Mutex<BTreeMap<&'static str, u64>>>);
+
+impl Counters {
+    /// Add `n` to the counter `name`
+    pub fn add(&self, name: &'static str, n: u64) {
+        if let Ok(mut counts) = self.0.lock() {
+            *counts.entry(name).or_default() += n;
+        }
+    }
+
+    /// Current values, by name
+    pub fn snapshot(&self) -> BTreeMap<&'static str, u64> {
+        self.0.lock().map(|counts| counts.clone()).unwrap_or_default()
+    }
+}
+
+/// Outcome of a command
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    Success,
+    Failure,
+}
+
+/// The summary line of one command run
+#[derive(Debug, Clone, Serialize)]
+pub struct Summary {
+    pub command: String,
+    pub version: &'static str,
+    pub duration_ms: u64,
+    pub result: Outcome,
+    pub exit_code: i32,
+    pub counts: BTreeMap<&'static str, u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Summary {
+    /// Summary of a run that exited with `exit_code`, or failed with `error`
+    pub fn new(command: &str, duration: Duration, counters: &Counters, exit_code: i32, error: Option<String>) -> Self {
+        Self {
+            command: command.to_string(),
+            version: env!("CARGO_PKG_VERSION"),
+            duration_ms: u64::try_from(duration.as_millis()).unwrap_or(u64::MAX),
+            result: if exit_code == 0 && error.is_none() { Outcome::Success } else { Outcome::Failure },
+            exit_code,
+            counts: counters.snapshot(),
+            error,
+        }
+    }
+
+    /// The summary as one line of JSON
+    pub fn to_line(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary_line() {
+        let counters = Counters::default();
+        let shared = counters.clone();
+        shared.add("issued", 2);
+        shared.add("issued", 1);
+        counters.add("failed", 1);
+
+        let summary = Summary::new("batch", Duration::from_millis(1500), &counters, 1, Some("1 certificate failed".to_string()));
+        let line = summary.to_line();
+        assert!(!line.contains('\n'));
+        let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(value["command"], "batch");
+        assert_eq!(value["duration_ms"], 1500);
+        assert_eq!(value["result"], "failure");
+        assert_eq!(value["counts"], serde_json::json!({"failed": 1, "issued": 3}));
+        assert_eq!(value["error"], "1 certificate failed");
+
+        let ok = Summary::new("check", Duration::ZERO, &Counters::default(), 0, None);
+        assert_eq!(ok.result, Outcome::Success);
+        assert!(!ok.to_line().contains("error"));
+    }
+}
@@ -0,0 +1,188 @@
+//! Java truststores of the CA certificates (`flux-ssl-mgr truststore`)
+//!
+//! JVM applications such as Keycloak and Jenkins read trust anchors from a
+//! keystore rather than a PEM bundle. The store holds the root (when present)
+//! and the intermediate as trusted certificate entries.
+//!
+//! Java only treats PKCS#12 certificates carrying Oracle's trustedKeyUsage
+//! attribute as trusted, which the OpenSSL library API can't set, so PKCS#12
+//! stores are written with `openssl pkcs12 -jdktrust` (OpenSSL 3.2+). JKS
+//! stores are written directly.
+
+use crate::config::Config;
+use crate::crypto::load_cert;
+use crate::error::{FluxError, Result};
+use openssl::hash::{hash, MessageDigest};
+use openssl::nid::Nid;
+use openssl::x509::X509;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// Truststore file format
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TruststoreFormat {
+    Pkcs12,
+    Jks,
+}
+
+impl TruststoreFormat {
+    /// Conventional file extension
+    pub fn extension(self) -> &'static str {
+        match self {
+            TruststoreFormat::Pkcs12 => "p12",
+            TruststoreFormat::Jks => "jks",
+        }
+    }
+}
+
+/// The CA certificates to trust, root first, each with its alias
+///
+/// Aliases are the lowercased common names, as keytool would show them.
+pub fn ca_entries(config: &Config) -> Result<Vec<(String, X509)>> {
+    let mut certs = Vec::new();
+    let root = config.root_ca_cert_path();
+    if root.exists() {
+        certs.push(load_cert(&root)?);
+    }
+    certs.push(load_cert(&config.ca_cert_path)?);
+
+    let mut entries: Vec<(String, X509)> = Vec::new();
+    for (i, cert) in certs.into_iter().enumerate() {
+        let mut alias = cert.subject_name().entries_by_nid(Nid::COMMONNAME).next()
+            .and_then(|entry| entry.data().as_utf8().ok())
+            .map_or_else(|| format!("ca-{}", i + 1), |cn| cn.to_lowercase());
+        if entries.iter().any(|(existing, _)| *existing == alias) {
+            alias = format!("{}-{}", alias, i + 1);
+        }
+        entries.push((alias, cert));
+    }
+    Ok(entries)
+}
+
+/// Build a truststore of `entries` protected by `password`
+pub fn build(entries: &[(String, X509)], format: TruststoreFormat, password: &str) -> Result<Vec<u8>> {
+    match format {
+        TruststoreFormat::Pkcs12 => pkcs12(entries, password),
+        TruststoreFormat::Jks => jks(entries, password),
+    }
+}
+
+/// PKCS#12 truststore, via the openssl command
+fn pkcs12(entries: &[(String, X509)], password: &str) -> Result<Vec<u8>> {
+    let pem_file = tempfile::NamedTempFile::new()?;
+    for (_, cert) in entries {
+        pem_file.as_file().write_all(&cert.to_pem()?)?;
+    }
+
+    let mut child = pkcs12_command(entries, pem_file.path())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| FluxError::TruststoreError(format!("failed to run openssl: {}", e)))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        writeln!(stdin, "{}", password)?;
+    }
+    let out = child.wait_with_output()?;
+    if !out.status.success() {
+        return Err(FluxError::TruststoreError(format!(
+            "openssl exited with {} (-jdktrust needs OpenSSL 3.2 or newer): {}",
+            out.status,
+            String::from_utf8_lossy(&out.stderr).trim()
+        )));
+    }
+    Ok(out.stdout)
+}
+
+/// `openssl pkcs12 -export` of the certificates in `pem`, trusted for any
+/// purpose, reading the password from stdin
+fn pkcs12_command(entries: &[(String, X509)], pem: &Path) -> Command {
+    let mut cmd = Command::new("openssl");
+    cmd.args(["pkcs12", "-export", "-nokeys", "-jdktrust", "anyExtendedKeyUsage", "-passout", "stdin", "-in"])
+        .arg(pem);
+    for (alias, _) in entries {
+        cmd.args(["-caname", alias]);
+    }
+    cmd
+}
+
+/// JKS truststore: a trusted certificate entry per certificate, followed by
+/// the keyed SHA-1 digest Java checks on load
+fn jks(entries: &[(String, X509)], password: &str) -> Result<Vec<u8>> {
+    const MAGIC: u32 = 0xFEED_FEED;
+    const VERSION: u32 = 2;
+    const TRUSTED_CERT_ENTRY: u32 = 2;
+
+    let timestamp = chrono::Utc::now().timestamp_millis() as u64;
+    let mut data = Vec::new();
+    data.extend(MAGIC.to_be_bytes());
+    data.extend(VERSION.to_be_bytes());
+    data.extend((entries.len() as u32).to_be_bytes());
+    for (alias, cert) in entries {
+        let der = cert.to_der()?;
+        data.extend(TRUSTED_CERT_ENTRY.to_be_bytes());
+        write_utf(&mut data, alias)?;
+        data.extend(timestamp.to_be_bytes());
+        write_utf(&mut data, "X.509")?;
+        data.extend((der.len() as u32).to_be_bytes());
+        data.extend(der);
+    }
+
+    let mut keyed = Vec::new();
+    for unit in password.encode_utf16() {
+        keyed.extend(unit.to_be_bytes());
+    }
+    keyed.extend(b"Mighty Aphrodite");
+    keyed.extend(&data);
+    data.extend(hash(MessageDigest::sha1(), &keyed)?.as_ref());
+    Ok(data)
+}
+
+/// Java's `writeUTF`: a 16-bit length, then the string (aliases and type names
+/// here never contain NUL, the one character modified UTF-8 encodes differently)
+fn write_utf(out: &mut Vec<u8>, s: &str) -> Result<()> {
+    let len = u16::try_from(s.len())
+        .map_err(|_| FluxError::TruststoreError(format!("alias too long: {}", s)))?;
+    out.extend(len.to_be_bytes());
+    out.extend(s.as_bytes());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    #[test]
+    fn test_jks() {
+        let entries = vec![
+            ("flux root ca".to_string(), fixtures::root_ca_cert()),
+            ("flux intermediate ca".to_string(), fixtures::intermediate_ca().cert().to_owned()),
+        ];
+        let store = build(&entries, TruststoreFormat::Jks, "changeit").unwrap();
+
+        assert_eq!(store[..12], [0xFE, 0xED, 0xFE, 0xED, 0, 0, 0, 2, 0, 0, 0, 2]);
+        assert_eq!(&store[12..16], &[0, 0, 0, 2]);
+        assert_eq!(&store[16..18], &[0, 12]);
+        assert_eq!(&store[18..30], b"flux root ca");
+
+        // The trailing digest is keyed by the password
+        let (data, digest) = store.split_at(store.len() - 20);
+        let mut keyed: Vec<u8> = "changeit".encode_utf16().flat_map(|u| u.to_be_bytes()).collect();
+        keyed.extend(b"Mighty Aphrodite");
+        keyed.extend(data);
+        assert_eq!(digest, hash(MessageDigest::sha1(), &keyed).unwrap().as_ref());
+    }
+
+    #[test]
+    fn test_pkcs12_command() {
+        let entries = vec![("flux root ca".to_string(), fixtures::root_ca_cert())];
+        let cmd = pkcs12_command(&entries, Path::new("/tmp/ca.pem"));
+        let args: Vec<_> = cmd.get_args().map(|a| a.to_string_lossy().into_owned()).collect();
+        assert_eq!(args, [
+            "pkcs12", "-export", "-nokeys", "-jdktrust", "anyExtendedKeyUsage", "-passout", "stdin",
+            "-in", "/tmp/ca.pem", "-caname", "flux root ca",
+        ]);
+    }
+}
@@ -0,0 +1,107 @@
+//! Validating certificates against this CA (`flux-ssl-mgr verify`)
+//!
+//! The intermediate (`ca_cert_path`) and, when present, the root
+//! (`certs/ca.cert.pem`) form the trust store. Signatures and validity dates
+//! are checked along the chain, then revocation status from the configured
+//! CA database or CRL.
+
+use crate::config::Config;
+use crate::crypto::{check_revocation, load_cert, verify_chain, ChainVerification, RevocationState, RevocationStatus};
+use crate::error::Result;
+use openssl::x509::X509;
+
+/// Outcome of verifying one certificate
+#[derive(Debug, Clone)]
+pub struct Verification {
+    pub chain: ChainVerification,
+    /// Revocation status, when revocation data is configured
+    pub revocation: Option<RevocationStatus>,
+}
+
+impl Verification {
+    /// Why the certificate can't be trusted, if it can't
+    pub fn failure(&self) -> Option<String> {
+        if !self.chain.trusted {
+            let reason = self.chain.error.as_deref().unwrap_or("not trusted");
+            return Some(match self.chain.error_depth {
+                Some(0) | None => reason.to_string(),
+                Some(depth) => format!("{} (at depth {})", reason, depth),
+            });
+        }
+        match &self.revocation {
+            Some(status) if status.state == RevocationState::Revoked => {
+                let mut reason = format!("revoked ({})", status.source);
+                if let Some(why) = &status.reason {
+                    reason.push_str(&format!(": {}", why));
+                }
+                Some(reason)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Verify `cert` against the configured intermediate, and root if present
+///
+/// `intermediates` are extra untrusted certificates that may complete the
+/// chain, such as the rest of an uploaded bundle.
+pub fn against_ca(config: &Config, cert: &X509, intermediates: &[X509]) -> Result<ChainVerification> {
+    let intermediate = load_cert(&config.ca_cert_path)?;
+
+    let root_path = config.root_ca_cert_path();
+    let (trusted, mut untrusted) = match load_cert(&root_path) {
+        Ok(root) => (vec![root], vec![intermediate]),
+        Err(_) => {
+            tracing::debug!("Root CA not found at {:?}, verifying against intermediate only", root_path);
+            (vec![intermediate], vec![])
+        }
+    };
+    untrusted.extend(intermediates.iter().cloned());
+
+    verify_chain(cert, &trusted, &untrusted)
+}
+
+/// Verify the chain and revocation status of `cert`
+pub fn verify(config: &Config, cert: &X509, intermediates: &[X509]) -> Result<Verification> {
+    let chain = against_ca(config, cert, intermediates)?;
+    let issuer = load_cert(&config.ca_cert_path).ok();
+    let revocation = check_revocation(cert, &config.revocation, issuer.as_ref())?;
+    Ok(Verification { chain, revocation })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ca::CertSigner;
+    use crate::crypto::save_cert_pem;
+    use crate::fixtures;
+
+    #[test]
+    fn test_verify() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut config = Config {
+            working_dir: temp_dir.path().to_path_buf(),
+            ca_cert_path: temp_dir.path().join("ca.cert.pem"),
+            ..Config::default()
+        };
+        let ca = fixtures::intermediate_ca();
+        save_cert_pem(ca.cert(), &config.ca_cert_path).unwrap();
+        let leaf = ca.sign(&fixtures::leaf_csr("nas01.lab", &[]), 30).unwrap();
+
+        let verification = verify(&config, &leaf, &[]).unwrap();
+        assert!(verification.chain.trusted);
+        assert_eq!(verification.failure(), None);
+
+        // Signed by someone else entirely
+        let failure = verify(&config, &fixtures::root_ca_cert(), &[]).unwrap().failure().unwrap();
+        assert_eq!(failure, "self-signed certificate");
+
+        // Revoked in the CA database
+        let serial = leaf.serial_number().to_bn().unwrap().to_hex_str().unwrap().to_string();
+        let index = temp_dir.path().join("index.txt");
+        std::fs::write(&index, format!("R\t270101000000Z\t250301120000Z,keyCompromise\t{}\tunknown\t/CN=nas01.lab\n", serial)).unwrap();
+        config.revocation.index_path = Some(index);
+        let failure = verify(&config, &leaf, &[]).unwrap().failure().unwrap();
+        assert_eq!(failure, "revoked (index): keyCompromise");
+    }
+}
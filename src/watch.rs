@@ -0,0 +1,227 @@
+//! Watch mode for the CSR input directory
+//!
+//! `batch --watch` polls the directory rather than using inotify, which
+//! doesn't see files written by other NFS/SMB clients. A new `.csr` file is
+//! picked up once its size and modification time are unchanged between two
+//! polls, so half-copied files are left alone.
+//!
+//! Unlike a plain batch run, the CSR itself is signed: the device that sent
+//! it keeps its private key. The certificate goes to the output directory and
+//! the CSR moves to the archive directory, or to the failed directory next to
+//! a `.error` file saying why.
+
+use crate::batch::{protect_existing_outputs, validate_cert_name, CsrFile, IssuancePaths};
+use crate::ca::{load_signer, CertSigner};
+use crate::config::{Config, OverwritePolicy, PolicyConfig};
+use crate::crypto::{cert_to_pem, load_csr};
+use crate::error::{FluxError, Result};
+use crate::fsutil::{create_dir_with_mode, write_atomic, PUBLIC_FILE_MODE};
+use crate::ldap;
+use crate::notify::webhook::{self, Event, EventKind};
+use crate::output::OutputFormatter;
+use crate::policy::Violation;
+use crate::store::{record_issuance, IssuedCert};
+use crate::workspace::{NameLock, Workspace};
+use chrono::Utc;
+use openssl::x509::X509Req;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Tracks CSR files between polls
+pub struct Watcher {
+    dir: PathBuf,
+    /// Size and modification time at the last poll
+    seen: HashMap<PathBuf, (u64, SystemTime)>,
+}
+
+impl Watcher {
+    pub fn new(dir: &Path) -> Self {
+        Self { dir: dir.to_path_buf(), seen: HashMap::new() }
+    }
+
+    /// CSR files that haven't changed since the previous poll
+    pub fn poll(&mut self) -> Result<Vec<CsrFile>> {
+        let mut current = HashMap::new();
+        let mut ready = Vec::new();
+
+        for entry in std::fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|ext| ext != "csr") {
+                continue;
+            }
+            let Ok(meta) = std::fs::metadata(&path) else { continue };
+            if !meta.is_file() {
+                continue;
+            }
+
+            let stamp = (meta.len(), meta.modified()?);
+            if self.seen.get(&path) == Some(&stamp) {
+                let name = path.file_stem().and_then(|s| s.to_str()).unwrap_or("unknown").to_string();
+                ready.push(CsrFile { path: path.clone(), name });
+            }
+            current.insert(path, stamp);
+        }
+
+        self.seen = current;
+        ready.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(ready)
+    }
+
+    /// Forget a file (after it was moved away)
+    pub fn forget(&mut self, path: &Path) {
+        self.seen.remove(path);
+    }
+}
+
+/// Check a CSR against the signing policy
+///
+/// The name must be usable as a file name, the CSR must carry a valid
+/// self-signature, and its key must satisfy the `[policy]` key rules. The
+/// rest of the policy is checked when it is signed.
+pub fn check_csr(name: &str, csr: &X509Req, policy: &PolicyConfig) -> Result<()> {
+    validate_cert_name(name)?;
+
+    let key = csr.public_key()?;
+    if !csr.verify(&key)? {
+        return Err(FluxError::PolicyViolation(
+            Violation::new("signature", format!("{}: CSR signature does not verify", name)).into(),
+        ));
+    }
+
+    crate::policy::check_key(policy, csr)
+}
+
+/// Sign a CSR file and write the certificate to the output directory
+///
+/// Returns the issued certificate's store record.
+pub fn sign_csr_file(csr_file: &CsrFile, config: &Config, ca: &dyn CertSigner, output: &OutputFormatter) -> Result<IssuedCert> {
+    let csr = load_csr(&csr_file.path)?;
+    check_csr(&csr_file.name, &csr, &config.policy)?;
+
+    let paths = IssuancePaths::new(config, &csr_file.name);
+    if config.overwrite_policy == OverwritePolicy::Fail {
+        protect_existing_outputs(&paths, config)?;
+    }
+    create_dir_with_mode(&config.output_dir, config.permissions.output_dir)?;
+    if let Some(certs_dir) = paths.cert_pem.parent() {
+        create_dir_with_mode(certs_dir, config.permissions.output_dir)?;
+    }
+
+    let cert = ca.sign_for(&csr, config.defaults.cert_days)?;
+    let mut workspace = Workspace::new(config)?;
+    let cert_file = cert_to_pem(&cert)?;
+    for path in [&paths.cert_pem, &paths.cert_crt, &paths.output_cert_pem, &paths.output_cert_crt] {
+        let mode = if path.starts_with(&config.output_dir) { config.permissions.certificate } else { PUBLIC_FILE_MODE };
+        write_atomic(&workspace.stage(path), &cert_file, mode)?;
+    }
+
+    let _lock = NameLock::acquire(config, &csr_file.name)?;
+    if let Some(backup_dir) = protect_existing_outputs(&paths, config)? {
+        output.info(&format!("Backed up existing {} files to {}", csr_file.name, backup_dir.display()));
+    }
+    workspace.publish()?;
+
+    let record = IssuedCert::from_cert(&csr_file.name, &cert, &paths.output_cert_pem, None)?;
+    let mut event = Event::new(EventKind::Issued, &record);
+    match record_issuance(config, record.clone()) {
+        Ok(renewal) => {
+            if renewal {
+                event.event = EventKind::Renewed;
+            }
+            webhook::emit(config, &event);
+        }
+        Err(e) => output.warning(&format!("Failed to record {} in certificate store: {}", csr_file.name, e)),
+    }
+    ldap::on_issued(config, &csr_file.name, &cert, output);
+
+    Ok(record)
+}
+
+/// Move a CSR into `dir` under a timestamped name, returning the new path
+pub fn archive(path: &Path, dir: &Path) -> Result<PathBuf> {
+    std::fs::create_dir_all(dir)?;
+
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("unknown");
+    let dest = dir.join(format!("{}-{}.csr", stem, Utc::now().format("%Y%m%dT%H%M%S%.3fZ")));
+    std::fs::rename(path, &dest)
+        .map_err(|e| FluxError::FileWriteFailed(dest.clone(), e.to_string()))?;
+    Ok(dest)
+}
+
+/// Sign CSRs dropped into `dir` until the process is stopped
+pub fn watch(dir: &Path, filter: Option<&str>, config: &Config, output: &OutputFormatter) -> Result<()> {
+    let archive_dir = config.watch.archive_dir.clone().unwrap_or_else(|| dir.join("processed"));
+    let failed_dir = config.watch.failed_dir.clone().unwrap_or_else(|| dir.join("failed"));
+
+    // Load CA (or connect to the signing agent) once
+    let ca = load_signer(config)?;
+    let mut watcher = Watcher::new(dir);
+
+    loop {
+        for csr_file in watcher.poll()? {
+            if filter.is_some_and(|pattern| !csr_file.name.contains(pattern)) {
+                continue;
+            }
+
+            match sign_csr_file(&csr_file, config, ca.as_ref(), output) {
+                Ok(record) => {
+                    output.success(&format!("Signed {} (serial {}, expires {})", csr_file.name, record.serial, record.not_after.format("%Y-%m-%d")));
+                    archive(&csr_file.path, &archive_dir)?;
+                }
+                Err(e) => {
+                    output.error(&format!("Rejected {}: {}", csr_file.name, e));
+                    let moved = archive(&csr_file.path, &failed_dir)?;
+                    let note = moved.with_extension("csr.error");
+                    std::fs::write(&note, format!("{}\n", e))
+                        .map_err(|e| FluxError::FileWriteFailed(note.clone(), e.to_string()))?;
+                }
+            }
+            watcher.forget(&csr_file.path);
+        }
+
+        std::thread::sleep(config.watch.interval.get());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::{create_csr, generate_rsa_key};
+
+    #[test]
+    fn test_poll_waits_for_stable_files() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut watcher = Watcher::new(temp_dir.path());
+        let csr = temp_dir.path().join("nas.csr");
+
+        std::fs::write(&csr, "partial").unwrap();
+        std::fs::write(temp_dir.path().join("notes.txt"), "x").unwrap();
+        assert!(watcher.poll().unwrap().is_empty());
+
+        std::fs::write(&csr, "partial, now complete").unwrap();
+        assert!(watcher.poll().unwrap().is_empty());
+
+        let ready = watcher.poll().unwrap();
+        assert_eq!(ready.len(), 1);
+        assert_eq!(ready[0].name, "nas");
+
+        let moved = archive(&csr, &temp_dir.path().join("processed")).unwrap();
+        watcher.forget(&csr);
+        assert!(moved.starts_with(temp_dir.path().join("processed")));
+        assert!(watcher.poll().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_check_csr() {
+        let key = generate_rsa_key(2048, None).unwrap();
+        let csr = create_csr("nas", &key, &[], None).unwrap();
+        let policy = PolicyConfig::default();
+        assert!(check_csr("nas", &csr, &policy).is_ok());
+        assert!(check_csr("../nas", &csr, &policy).is_err());
+
+        let weak = generate_rsa_key(1024, None).unwrap();
+        let csr = create_csr("nas", &weak, &[], None).unwrap();
+        assert!(matches!(check_csr("nas", &csr, &policy), Err(FluxError::PolicyViolation(_))));
+    }
+}
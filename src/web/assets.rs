@@ -0,0 +1,83 @@
+//! Static assets compiled into the binary
+//!
+//! `/static/*` is served from the `static/` directory as it was at build
+//! time, like the HTML pages, so `serve` works from any working directory and
+//! needs nothing installed next to the binary.
+
+use axum::{
+    extract::Path,
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use include_dir::{include_dir, Dir};
+use openssl::sha::sha256;
+
+use super::crl::if_none_match;
+
+static STATIC_DIR: Dir<'_> = include_dir!("$CARGO_MANIFEST_DIR/static");
+
+/// Serve an embedded asset from `/static/*path`
+///
+/// Assets change only with the binary, so browsers revalidate them with the
+/// ETag instead of caching them for a fixed time.
+pub async fn serve_static(Path(path): Path<String>, headers: HeaderMap) -> Response {
+    let Some(file) = STATIC_DIR.get_file(&path) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let body = file.contents();
+    let etag = format!("\"{}\"", sha256(body)[..16].iter().map(|b| format!("{:02x}", b)).collect::<String>());
+
+    let mut response = if if_none_match(&headers, &etag) {
+        StatusCode::NOT_MODIFIED.into_response()
+    } else {
+        let mut response = body.into_response();
+        response.headers_mut().insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type(&path)));
+        response
+    };
+    if let Ok(etag) = HeaderValue::from_str(&etag) {
+        response.headers_mut().insert(header::ETAG, etag);
+    }
+    response.headers_mut().insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+    response
+}
+
+/// Content type by file extension
+fn content_type(path: &str) -> &'static str {
+    match path.rsplit_once('.').map(|(_, ext)| ext.to_ascii_lowercase()).as_deref() {
+        Some("css") => "text/css; charset=utf-8",
+        Some("js") => "text/javascript; charset=utf-8",
+        Some("html") => "text/html; charset=utf-8",
+        Some("json") => "application/json",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("ico") => "image/x-icon",
+        Some("woff2") => "font/woff2",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::Request, routing::get, Router};
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_embedded_assets() {
+        let app = Router::new().route("/static/*path", get(serve_static));
+        let get = |path: &str| Request::get(path).body(Body::empty()).unwrap();
+
+        let response = app.clone().oneshot(get("/static/css/styles.css")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "text/css; charset=utf-8");
+        let etag = response.headers()[header::ETAG].clone();
+
+        let mut request = get("/static/css/styles.css");
+        request.headers_mut().insert(header::IF_NONE_MATCH, etag);
+        assert_eq!(app.clone().oneshot(request).await.unwrap().status(), StatusCode::NOT_MODIFIED);
+
+        for missing in ["/static/css/missing.css", "/static/../Cargo.toml"] {
+            assert_eq!(app.clone().oneshot(get(missing)).await.unwrap().status(), StatusCode::NOT_FOUND);
+        }
+    }
+}
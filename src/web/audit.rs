@@ -0,0 +1,214 @@
+//! Audit trail of web API actions
+//!
+//! Each request to an audited endpoint appends one JSON line to the audit log
+//! (`[web] audit_log`, default `intermediate/flux-audit.jsonl`): when, from
+//! which address, with which API key or enrollment, the names asked for, the
+//! serials issued or served, and the response status. The file is only ever
+//! appended to, and is created with mode 0600.
+//!
+//! Handlers and the auth middleware add details with [`note_identity`],
+//! [`note_name`] and [`note_serial`]; outside an audited request these do
+//! nothing.
+
+use axum::{
+    extract::{ConnectInfo, OriginalUri, Request, State},
+    middleware::Next,
+    response::Response,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::io::Write;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::config::Config;
+use crate::error::{FluxError, Result};
+
+/// One audited request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub timestamp: DateTime<Utc>,
+    /// Address of the client, when the server knows it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client: Option<IpAddr>,
+    /// API key name, or `enrollment:<name>`; missing while the API is open
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identity: Option<String>,
+    pub method: String,
+    pub path: String,
+    /// Common names requested or inspected
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub names: Vec<String>,
+    /// Serials issued, served or inspected
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub serials: Vec<String>,
+    /// HTTP status of the response
+    pub status: u16,
+}
+
+/// Details gathered while a request is handled
+#[derive(Debug, Default)]
+struct Notes {
+    identity: Option<String>,
+    names: Vec<String>,
+    serials: Vec<String>,
+}
+
+tokio::task_local! {
+    static CURRENT: RefCell<Notes>;
+}
+
+fn with_notes(f: impl FnOnce(&mut Notes)) {
+    let _ = CURRENT.try_with(|notes| f(&mut notes.borrow_mut()));
+}
+
+/// Record who made the current request
+pub fn note_identity(identity: impl Into<String>) {
+    with_notes(|notes| notes.identity = Some(identity.into()));
+}
+
+/// Record a common name the current request asked for
+pub fn note_name(name: impl Into<String>) {
+    with_notes(|notes| notes.names.push(name.into()));
+}
+
+/// Record a serial the current request issued or served
+pub fn note_serial(serial: impl Into<String>) {
+    with_notes(|notes| notes.serials.push(serial.into()));
+}
+
+/// Append-only audit file
+pub struct AuditLog {
+    path: PathBuf,
+    // Keeps concurrent entries from interleaving
+    lock: Mutex<()>,
+}
+
+impl AuditLog {
+    pub fn new(config: &Config) -> Self {
+        Self::at(config.web_audit_path())
+    }
+
+    pub fn at(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into(), lock: Mutex::new(()) }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Append one entry as a JSON line
+    pub fn append(&self, entry: &AuditEntry) -> Result<()> {
+        let mut line = serde_json::to_string(entry)
+            .map_err(|e| FluxError::FileWriteFailed(self.path.clone(), e.to_string()))?;
+        line.push('\n');
+
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        let mut options = std::fs::OpenOptions::new();
+        options.append(true).create(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut f = options.open(&self.path)
+            .map_err(|e| FluxError::FileWriteFailed(self.path.clone(), e.to_string()))?;
+        f.write_all(line.as_bytes())
+            .map_err(|e| FluxError::FileWriteFailed(self.path.clone(), e.to_string()))
+    }
+}
+
+/// Middleware writing an [`AuditEntry`] for every request it wraps
+///
+/// Used with `axum::middleware::from_fn_with_state(log, record)`, outside the
+/// rate limiter and authentication so rejected requests are recorded too. A
+/// failure to write the entry is logged; the response goes out regardless.
+pub async fn record(State(log): State<Arc<AuditLog>>, request: Request, next: Next) -> Response {
+    let client = request.extensions().get::<ConnectInfo<SocketAddr>>().map(|info| info.0.ip());
+    let method = request.method().to_string();
+    let path = request.extensions().get::<OriginalUri>()
+        .map_or_else(|| request.uri().path().to_string(), |uri| uri.path().to_string());
+
+    let (response, notes) = CURRENT
+        .scope(RefCell::default(), async move {
+            let response = next.run(request).await;
+            (response, CURRENT.with(RefCell::take))
+        })
+        .await;
+
+    let entry = AuditEntry {
+        timestamp: Utc::now(),
+        client,
+        identity: notes.identity,
+        method,
+        path,
+        names: notes.names,
+        serials: notes.serials,
+        status: response.status().as_u16(),
+    };
+    let written = tokio::task::spawn_blocking(move || {
+        if let Err(e) = log.append(&entry) {
+            tracing::error!("Failed to write audit entry to {}: {}", log.path().display(), e);
+        }
+    });
+    if let Err(e) = written.await {
+        tracing::error!("Failed to write audit entry: {}", e);
+    }
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_notes_only_apply_inside_a_request() {
+        note_name("outside.lab");
+
+        let notes = CURRENT
+            .scope(RefCell::default(), async {
+                note_identity("ci");
+                note_name("nas01.lab");
+                note_serial("0A1B");
+                CURRENT.with(RefCell::take)
+            })
+            .await;
+        assert_eq!(notes.identity.as_deref(), Some("ci"));
+        assert_eq!(notes.names, vec!["nas01.lab"]);
+        assert_eq!(notes.serials, vec!["0A1B"]);
+    }
+
+    #[test]
+    fn test_append_writes_json_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = AuditLog::at(dir.path().join("audit.jsonl"));
+        for status in [200, 403] {
+            log.append(&AuditEntry {
+                timestamp: Utc::now(),
+                client: Some(IpAddr::from([192, 0, 2, 1])),
+                identity: None,
+                method: "POST".to_string(),
+                path: "/api/cert/generate".to_string(),
+                names: vec!["nas01.lab".to_string()],
+                serials: Vec::new(),
+                status,
+            })
+            .unwrap();
+        }
+
+        let text = std::fs::read_to_string(log.path()).unwrap();
+        let entries: Vec<AuditEntry> = text.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert_eq!(entries.iter().map(|e| e.status).collect::<Vec<_>>(), vec![200, 403]);
+        assert!(!text.contains("serials"));
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(log.path()).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
+}
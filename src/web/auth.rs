@@ -0,0 +1,207 @@
+//! API key authentication for `/api/*`
+//!
+//! Requests carry a key as `Authorization: Bearer <token>` or `X-API-Key`.
+//! Keys are re-read on every request, so keys added or removed with
+//! `flux-ssl-mgr web token` apply without a restart. While no key exists at
+//! all, the read routes stay open but issuing and revoking are refused, unless
+//! `web.allow_unauthenticated` is set (the server warns about it at startup).
+//!
+//! `/api/csr/upload` also takes a one-time enrollment token in place of a key.
+//! The middleware only checks that it is valid; the handler checks the CSR
+//! against it and uses it up.
+
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::Utc;
+use std::sync::Arc;
+
+use crate::apikey::{self, Enrollment, Scope, TokenFile};
+use crate::config::Config;
+
+use super::audit;
+use super::models::WebError;
+
+/// Header accepted besides `Authorization: Bearer`
+const API_KEY_HEADER: &str = "x-api-key";
+
+/// Middleware rejecting requests without a key that has `scope`
+///
+/// Used with `axum::middleware::from_fn_with_state((config, scope), require_scope)`.
+pub async fn require_scope(
+    State((config, scope)): State<(Arc<Config>, Scope)>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let allow_unauthenticated = config.web.allow_unauthenticated;
+    let keys = match tokio::task::spawn_blocking(move || apikey::all_keys(&config)).await {
+        Ok(Ok(keys)) => keys,
+        Ok(Err(e)) => return WebError::internal_error(format!("Failed to load API keys: {}", e)).into_response(),
+        Err(e) => return WebError::internal_error(e.to_string()).into_response(),
+    };
+    if keys.is_empty() {
+        if scope == Scope::Read || allow_unauthenticated {
+            return next.run(request).await;
+        }
+        return WebError::unauthorized(format!(
+            "No API keys exist, so {} requests are refused; create one with `flux-ssl-mgr web token create <name> --scope {}` \
+             (or an enrollment token with `flux-ssl-mgr web token new`)",
+            scope, scope
+        ))
+        .into_response();
+    }
+
+    let Some(token) = presented_token(request.headers()) else {
+        return WebError::unauthorized("An API key is required").into_response();
+    };
+    let Some(key) = apikey::authenticate(&keys, token) else {
+        return WebError::unauthorized("Invalid API key").into_response();
+    };
+    if !key.allows(scope) {
+        return WebError::forbidden(format!("API key '{}' lacks the {} scope", key.name, scope)).into_response();
+    }
+
+    tracing::debug!("Authenticated API key '{}' for {}", key.name, request.uri().path());
+    audit::note_identity(key.name.as_str());
+    next.run(request).await
+}
+
+/// A valid enrollment token presented with the request
+#[derive(Debug, Clone)]
+pub struct EnrollmentGrant {
+    pub token: String,
+    pub enrollment: Enrollment,
+}
+
+/// [`require_scope`], also accepting an enrollment token
+///
+/// A valid enrollment reaches the handler as an [`EnrollmentGrant`] extension.
+pub async fn require_scope_or_enrollment(
+    State((config, scope)): State<(Arc<Config>, Scope)>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let Some(token) = presented_token(request.headers()).filter(|t| apikey::is_enrollment_token(t)) else {
+        return require_scope(State((config, scope)), request, next).await;
+    };
+
+    let token = token.to_string();
+    let lookup = {
+        let token = token.clone();
+        move || -> crate::error::Result<Option<Enrollment>> {
+            Ok(TokenFile::open(config.web_tokens_path())?.enrollment(&token, Utc::now()).cloned())
+        }
+    };
+    let enrollment = match tokio::task::spawn_blocking(lookup).await {
+        Ok(Ok(Some(enrollment))) => enrollment,
+        Ok(Ok(None)) => return WebError::unauthorized("Invalid, expired or used enrollment token").into_response(),
+        Ok(Err(e)) => return WebError::internal_error(format!("Failed to load enrollment tokens: {}", e)).into_response(),
+        Err(e) => return WebError::internal_error(e.to_string()).into_response(),
+    };
+
+    tracing::debug!("Enrollment token for '{}' presented for {}", enrollment.name, request.uri().path());
+    audit::note_identity(format!("enrollment:{}", enrollment.name));
+    request.extensions_mut().insert(EnrollmentGrant { token, enrollment });
+    next.run(request).await
+}
+
+/// Token from `Authorization: Bearer` or `X-API-Key`
+pub(super) fn presented_token(headers: &HeaderMap) -> Option<&str> {
+    let bearer = headers.get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer ").or_else(|| v.strip_prefix("bearer ")));
+
+    bearer
+        .or_else(|| headers.get(API_KEY_HEADER).and_then(|v| v.to_str().ok()))
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+}
+
+/// Whether any API key is configured (the server warns when not)
+pub fn enabled(config: &Config) -> crate::error::Result<bool> {
+    Ok(!apikey::all_keys(config)?.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apikey::TokenFile;
+    use axum::{body::Body, http::StatusCode, routing::get, Router};
+    use std::collections::BTreeSet;
+    use tower::ServiceExt;
+
+    fn app(config: &Arc<Config>, scope: Scope) -> Router {
+        Router::new()
+            .route("/api/thing", get(|| async { "ok" }))
+            .route_layer(axum::middleware::from_fn_with_state((Arc::clone(config), scope), require_scope))
+    }
+
+    async fn status(app: Router, token: Option<&str>) -> StatusCode {
+        let mut request = Request::builder().uri("/api/thing");
+        if let Some(token) = token {
+            request = request.header(header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+        app.oneshot(request.body(Body::empty()).unwrap()).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn test_require_scope() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let config = Arc::new(Config {
+            working_dir: temp_dir.path().to_path_buf(),
+            ..Config::default()
+        });
+
+        // No keys yet: reads are open, issuing and revoking are not
+        assert_eq!(status(app(&config, Scope::Read), None).await, StatusCode::OK);
+        assert_eq!(status(app(&config, Scope::Issue), None).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(status(app(&config, Scope::Revoke), None).await, StatusCode::UNAUTHORIZED);
+
+        let mut tokens = TokenFile::open(config.web_tokens_path()).unwrap();
+        let reader = tokens.create("grafana", BTreeSet::from([Scope::Read])).unwrap();
+        tokens.save().unwrap();
+
+        assert_eq!(status(app(&config, Scope::Read), None).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(status(app(&config, Scope::Read), Some("flux_wrong")).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(status(app(&config, Scope::Read), Some(&reader)).await, StatusCode::OK);
+        assert_eq!(status(app(&config, Scope::Issue), Some(&reader)).await, StatusCode::FORBIDDEN);
+
+        // Revoking the last key doesn't open the API again
+        tokens.remove("grafana");
+        tokens.save().unwrap();
+        assert_eq!(status(app(&config, Scope::Issue), None).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(status(app(&config, Scope::Revoke), Some(&reader)).await, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_allow_unauthenticated() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut config = Config {
+            working_dir: temp_dir.path().to_path_buf(),
+            ..Config::default()
+        };
+        config.web.allow_unauthenticated = true;
+        let config = Arc::new(config);
+        assert_eq!(status(app(&config, Scope::Issue), None).await, StatusCode::OK);
+        assert_eq!(status(app(&config, Scope::Revoke), None).await, StatusCode::OK);
+
+        // Keys still apply once one exists
+        let mut tokens = TokenFile::open(config.web_tokens_path()).unwrap();
+        tokens.create("ci", BTreeSet::from([Scope::Issue])).unwrap();
+        tokens.save().unwrap();
+        assert_eq!(status(app(&config, Scope::Issue), None).await, StatusCode::UNAUTHORIZED);
+    }
+
+    #[test]
+    fn test_presented_token() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(presented_token(&headers), None);
+        headers.insert(API_KEY_HEADER, " flux_abc ".parse().unwrap());
+        assert_eq!(presented_token(&headers), Some("flux_abc"));
+        headers.insert(header::AUTHORIZATION, "Bearer flux_def".parse().unwrap());
+        assert_eq!(presented_token(&headers), Some("flux_def"));
+    }
+}
@@ -0,0 +1,169 @@
+//! ZIP bundles of an issued certificate (`format=zip` downloads)
+//!
+//! Files use certbot's names, which most install guides refer to:
+//! `cert.pem`, `privkey.pem`, `chain.pem` and `fullchain.pem`, plus a short
+//! `README.txt`, all in a `<name>/` folder. The folder name keeps only
+//! `[A-Za-z0-9._-]` of the certificate name, since web clients choose it.
+
+use chrono::{DateTime, Utc};
+use std::io::{Cursor, Write};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::error::Result;
+
+/// What goes into a bundle
+pub struct Bundle<'a> {
+    /// Certificate name, used (sanitized) for the folder
+    pub name: &'a str,
+    pub serial: &'a str,
+    pub not_after: DateTime<Utc>,
+    pub cert_pem: &'a [u8],
+    /// Private key as stored; `None` when the CA never had it
+    pub key_pem: Option<&'a [u8]>,
+    /// Whether `key_pem` is password protected
+    pub key_encrypted: bool,
+    /// CA certificates, intermediate first
+    pub chain_pem: &'a [u8],
+}
+
+impl Bundle<'_> {
+    /// The bundle as a ZIP archive
+    pub fn to_zip(&self) -> Result<Vec<u8>> {
+        let mut fullchain = self.cert_pem.to_vec();
+        fullchain.extend_from_slice(self.chain_pem);
+        let readme = self.readme();
+
+        let mut files: Vec<(&str, &[u8], u32)> = vec![
+            ("cert.pem", self.cert_pem, 0o644),
+            ("chain.pem", self.chain_pem, 0o644),
+            ("fullchain.pem", &fullchain, 0o644),
+        ];
+        if let Some(key) = self.key_pem {
+            files.push(("privkey.pem", key, 0o600));
+        }
+        files.push(("README.txt", readme.as_bytes(), 0o644));
+
+        let folder = self.folder();
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        for (file, contents, mode) in files {
+            let options = SimpleFileOptions::default()
+                .compression_method(CompressionMethod::Deflated)
+                .unix_permissions(mode);
+            zip.start_file(format!("{}/{}", folder, file), options).map_err(std::io::Error::other)?;
+            zip.write_all(contents)?;
+        }
+        Ok(zip.finish().map_err(std::io::Error::other)?.into_inner())
+    }
+
+    /// Folder name: the certificate name with anything but `[A-Za-z0-9._-]`
+    /// replaced by `_`, so it can't leave the folder or be invalid on Windows
+    fn folder(&self) -> String {
+        let folder: String = self.name.chars()
+            .map(|c| if c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-') { c } else { '_' })
+            .collect();
+        match folder.trim_start_matches('.') {
+            "" => "certificate".to_string(),
+            rest => format!("{}{}", "_".repeat(folder.len() - rest.len()), rest),
+        }
+    }
+
+    /// Install note shipped as README.txt
+    fn readme(&self) -> String {
+        let key_line = match (self.key_pem, self.key_encrypted) {
+            (None, _) => "  (no private key: it stayed on the machine that created the CSR)".to_string(),
+            (Some(_), true) => "  privkey.pem     private key, password protected; keep it secret".to_string(),
+            (Some(_), false) => "  privkey.pem     private key; keep it secret (chmod 600)".to_string(),
+        };
+        [
+            format!("Certificate {}", self.name),
+            format!("Serial {}, valid until {}", self.serial, self.not_after.format("%Y-%m-%d %H:%M UTC")),
+            String::new(),
+            "Files".to_string(),
+            "  cert.pem        the certificate".to_string(),
+            "  chain.pem       CA certificates (intermediate, then root)".to_string(),
+            "  fullchain.pem   certificate followed by the chain".to_string(),
+            key_line,
+            String::new(),
+            "Most servers want fullchain.pem and privkey.pem, for example".to_string(),
+            "  nginx:   ssl_certificate fullchain.pem; ssl_certificate_key privkey.pem;".to_string(),
+            "  Apache:  SSLCertificateFile fullchain.pem".to_string(),
+            "           SSLCertificateKeyFile privkey.pem".to_string(),
+            format!("  HAProxy: cat fullchain.pem privkey.pem > {}.pem", self.folder()),
+            String::new(),
+            "Clients need the root CA (the last certificate in chain.pem) in their".to_string(),
+            "trust store to accept the certificate.".to_string(),
+        ]
+        .join("\n")
+            + "\n"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use zip::ZipArchive;
+
+    #[test]
+    fn test_zip_bundle() {
+        let bundle = Bundle {
+            name: "nas01.lab",
+            serial: "1A2B",
+            not_after: DateTime::from_timestamp(1_800_000_000, 0).unwrap(),
+            cert_pem: b"CERT\n",
+            key_pem: Some(b"KEY\n"),
+            key_encrypted: false,
+            chain_pem: b"INTERMEDIATE\nROOT\n",
+        };
+        let mut archive = ZipArchive::new(Cursor::new(bundle.to_zip().unwrap())).unwrap();
+        let mut names: Vec<&str> = archive.file_names().collect();
+        names.sort_unstable();
+        assert_eq!(names, [
+            "nas01.lab/README.txt",
+            "nas01.lab/cert.pem",
+            "nas01.lab/chain.pem",
+            "nas01.lab/fullchain.pem",
+            "nas01.lab/privkey.pem",
+        ]);
+
+        let mut read = |name: &str| {
+            let mut file = archive.by_name(name).unwrap();
+            let mode = file.unix_mode().unwrap() & 0o777;
+            let mut text = String::new();
+            file.read_to_string(&mut text).unwrap();
+            (text, mode)
+        };
+        assert_eq!(read("nas01.lab/fullchain.pem").0, "CERT\nINTERMEDIATE\nROOT\n");
+        assert_eq!(read("nas01.lab/privkey.pem"), ("KEY\n".to_string(), 0o600));
+        assert!(read("nas01.lab/README.txt").0.contains("valid until 2027-01-15"));
+
+        let without_key = Bundle { key_pem: None, ..bundle };
+        let archive = ZipArchive::new(Cursor::new(without_key.to_zip().unwrap())).unwrap();
+        assert_eq!(archive.len(), 4);
+    }
+
+    #[test]
+    fn test_zip_bundle_with_hostile_name() {
+        let folders = |name: &str| -> Vec<String> {
+            let bundle = Bundle {
+                name,
+                serial: "1A2B",
+                not_after: DateTime::from_timestamp(1_800_000_000, 0).unwrap(),
+                cert_pem: b"CERT\n",
+                key_pem: None,
+                key_encrypted: false,
+                chain_pem: b"",
+            };
+            let archive = ZipArchive::new(Cursor::new(bundle.to_zip().unwrap())).unwrap();
+            let mut folders: Vec<String> = archive.file_names().map(|f| f.split('/').next().unwrap().to_string()).collect();
+            assert!(archive.file_names().all(|f| f.matches('/').count() == 1), "{:?}", folders);
+            folders.dedup();
+            folders
+        };
+        assert_eq!(folders("../../etc/cron.d"), ["___.._etc_cron.d"]);
+        assert_eq!(folders("*.lab"), ["_.lab"]);
+        assert_eq!(folders(".."), ["certificate"]);
+        assert_eq!(folders("C:\\Windows\\x"), ["C__Windows_x"]);
+    }
+}
@@ -0,0 +1,101 @@
+//! CORS policy from `[web.cors]`
+//!
+//! Dashboards hosted on another origin need the browser's permission to call
+//! `/api/*`. Preflight requests are answered before routing and
+//! authentication; the actual requests still need an API key. Tokens travel in
+//! headers, not cookies, so credentials are never allowed.
+
+use axum::http::{HeaderName, HeaderValue, Method};
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+
+use crate::config::CorsConfig;
+use crate::error::{FluxError, Result};
+
+/// The layer for `config`, or `None` when no origin is allowed
+pub fn layer(config: &CorsConfig) -> Result<Option<CorsLayer>> {
+    if config.allowed_origins.is_empty() {
+        return Ok(None);
+    }
+
+    let origins = if config.allowed_origins.iter().any(|o| o == "*") {
+        AllowOrigin::from(Any)
+    } else {
+        let origins = config.allowed_origins.iter()
+            .map(|origin| {
+                let origin = origin.trim_end_matches('/');
+                if !(origin.starts_with("http://") || origin.starts_with("https://")) {
+                    return Err(invalid("allowed_origins", origin, "expected e.g. https://dash.lab"));
+                }
+                HeaderValue::from_str(origin).map_err(|e| invalid("allowed_origins", origin, e))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        AllowOrigin::list(origins)
+    };
+
+    let methods = config.allowed_methods.iter()
+        .map(|m| Method::from_bytes(m.to_ascii_uppercase().as_bytes()).map_err(|e| invalid("allowed_methods", m, e)))
+        .collect::<Result<Vec<_>>>()?;
+    let headers = config.allowed_headers.iter()
+        .map(|h| HeaderName::from_bytes(h.as_bytes()).map_err(|e| invalid("allowed_headers", h, e)))
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(Some(
+        CorsLayer::new()
+            .allow_origin(origins)
+            .allow_methods(methods)
+            .allow_headers(headers),
+    ))
+}
+
+fn invalid(key: &str, value: &str, reason: impl std::fmt::Display) -> FluxError {
+    FluxError::InvalidConfigValue(format!("web.cors.{}", key), format!("{}: {}", value, reason))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, extract::Request, http::{header, StatusCode}, routing::post, Router};
+    use tower::ServiceExt;
+
+    fn cors(origins: &[&str]) -> CorsConfig {
+        CorsConfig {
+            allowed_origins: origins.iter().map(|o| o.to_string()).collect(),
+            ..CorsConfig::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_preflight() {
+        let app = Router::new()
+            .route("/api/cert/generate", post(|| async { "ok" }))
+            .layer(layer(&cors(&["https://dash.lab/"])).unwrap().unwrap());
+        let preflight = |origin: &str| {
+            Request::builder()
+                .method(Method::OPTIONS)
+                .uri("/api/cert/generate")
+                .header(header::ORIGIN, origin)
+                .header(header::ACCESS_CONTROL_REQUEST_METHOD, "POST")
+                .header(header::ACCESS_CONTROL_REQUEST_HEADERS, "authorization")
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let response = app.clone().oneshot(preflight("https://dash.lab")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN], "https://dash.lab");
+        assert!(response.headers()[header::ACCESS_CONTROL_ALLOW_HEADERS].to_str().unwrap().contains("authorization"));
+
+        let response = app.oneshot(preflight("https://evil.example")).await.unwrap();
+        assert!(response.headers().get(header::ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+    }
+
+    #[test]
+    fn test_layer_config() {
+        assert!(layer(&CorsConfig::default()).unwrap().is_none());
+        assert!(layer(&cors(&["*"])).unwrap().is_some());
+        assert!(matches!(layer(&cors(&["dash.lab"])), Err(FluxError::InvalidConfigValue(..))));
+
+        let bad_method = CorsConfig { allowed_methods: vec!["GE T".to_string()], ..cors(&["https://dash.lab"]) };
+        assert!(layer(&bad_method).is_err());
+    }
+}
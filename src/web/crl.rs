@@ -0,0 +1,191 @@
+//! CRL downloads at `/crl/intermediate.crl` (DER) and `/crl/intermediate.pem`
+//!
+//! Clients follow the CRL distribution point of issued certificates here, so
+//! these routes need no API key. The CRL is read from `revocation.crl_path`
+//! on every request, picking up a re-signed CRL without a restart. Responses
+//! may be cached until the CRL's nextUpdate.
+
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use openssl::sha::sha256;
+use openssl::x509::X509Crl;
+use std::sync::Arc;
+use tracing::warn;
+
+use crate::config::Config;
+use crate::crypto::cert::asn1_time_to_chrono;
+use crate::crypto::revocation::load_crl;
+use crate::error::Result;
+
+use super::models::WebError;
+
+/// Longest time a response may be cached, even when nextUpdate is further away
+const MAX_AGE_LIMIT: i64 = 24 * 60 * 60;
+
+/// Encoding of a CRL download
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CrlFormat {
+    Der,
+    Pem,
+}
+
+impl CrlFormat {
+    fn content_type(self) -> &'static str {
+        match self {
+            CrlFormat::Der => "application/pkix-crl",
+            CrlFormat::Pem => "application/x-pem-file",
+        }
+    }
+}
+
+pub async fn serve_der(State(config): State<Arc<Config>>, headers: HeaderMap) -> Response {
+    crl_response(&config, CrlFormat::Der, &headers, Utc::now())
+}
+
+pub async fn serve_pem(State(config): State<Arc<Config>>, headers: HeaderMap) -> Response {
+    crl_response(&config, CrlFormat::Pem, &headers, Utc::now())
+}
+
+/// The configured CRL in `format`, or 304 when the client's copy is current
+fn crl_response(config: &Config, format: CrlFormat, headers: &HeaderMap, now: DateTime<Utc>) -> Response {
+    let Some(path) = &config.revocation.crl_path else {
+        return WebError::not_found("No CRL is published (revocation.crl_path is not set)").into_response();
+    };
+    let crl = match load_crl(path) {
+        Ok(crl) => crl,
+        Err(e) => {
+            warn!("Failed to serve the CRL: {}", e);
+            return WebError::internal_error("The CRL could not be read").into_response();
+        }
+    };
+    let Encoded { body, last_update, next_update } = match encode(&crl, format) {
+        Ok(encoded) => encoded,
+        Err(e) => {
+            warn!("Failed to serve the CRL: {}", e);
+            return WebError::internal_error("The CRL could not be read").into_response();
+        }
+    };
+
+    // The strong validator covers the encoding too, so DER and PEM differ
+    let etag = format!("\"{}\"", sha256(&body)[..16].iter().map(|b| format!("{:02x}", b)).collect::<String>());
+
+    let mut response_headers = vec![
+        (header::ETAG, etag.clone()),
+        (header::LAST_MODIFIED, http_date(last_update)),
+        (header::CACHE_CONTROL, cache_control(next_update, now)),
+    ];
+    if let Some(next_update) = next_update {
+        response_headers.push((header::EXPIRES, http_date(next_update)));
+    }
+
+    let mut response = if if_none_match(headers, &etag) {
+        StatusCode::NOT_MODIFIED.into_response()
+    } else {
+        let mut response = body.into_response();
+        response.headers_mut().insert(header::CONTENT_TYPE, HeaderValue::from_static(format.content_type()));
+        response
+    };
+    for (name, value) in response_headers {
+        if let Ok(value) = HeaderValue::from_str(&value) {
+            response.headers_mut().insert(name, value);
+        }
+    }
+    response
+}
+
+/// Response body of a CRL with its validity
+struct Encoded {
+    body: Vec<u8>,
+    last_update: DateTime<Utc>,
+    next_update: Option<DateTime<Utc>>,
+}
+
+fn encode(crl: &X509Crl, format: CrlFormat) -> Result<Encoded> {
+    let body = match format {
+        CrlFormat::Der => crl.to_der()?,
+        CrlFormat::Pem => crl.to_pem()?,
+    };
+    Ok(Encoded {
+        body,
+        last_update: asn1_time_to_chrono(crl.last_update())?,
+        next_update: crl.next_update().map(asn1_time_to_chrono).transpose()?,
+    })
+}
+
+/// Cacheable until nextUpdate (at most a day); revalidate once it has passed
+fn cache_control(next_update: Option<DateTime<Utc>>, now: DateTime<Utc>) -> String {
+    match next_update.map(|next| (next - now).num_seconds()) {
+        Some(seconds) if seconds > 0 => format!("public, max-age={}", seconds.min(MAX_AGE_LIMIT)),
+        _ => "no-cache".to_string(),
+    }
+}
+
+pub(super) fn if_none_match(headers: &HeaderMap, etag: &str) -> bool {
+    headers.get_all(header::IF_NONE_MATCH).iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|tag| {
+            let tag = tag.trim();
+            tag == "*" || tag.trim_start_matches("W/") == etag
+        })
+}
+
+/// RFC 9110 IMF-fixdate
+fn http_date(time: DateTime<Utc>) -> String {
+    time.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::to_bytes;
+
+    const FIXTURE_CRL: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/intermediate.crl.pem");
+
+    fn config() -> Config {
+        let mut config = Config::default();
+        config.revocation.crl_path = Some(FIXTURE_CRL.into());
+        config
+    }
+
+    #[tokio::test]
+    async fn test_crl_downloads() {
+        let now = Utc::now();
+        let response = crl_response(&config(), CrlFormat::Der, &HeaderMap::new(), now);
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/pkix-crl");
+        assert_eq!(response.headers()[header::CACHE_CONTROL], "public, max-age=86400");
+        assert!(response.headers().contains_key(header::EXPIRES));
+        let etag = response.headers()[header::ETAG].clone();
+        let der = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        X509Crl::from_der(&der).unwrap();
+
+        let response = crl_response(&config(), CrlFormat::Pem, &HeaderMap::new(), now);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/x-pem-file");
+        assert_ne!(response.headers()[header::ETAG], etag);
+        let pem = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert!(pem.starts_with(b"-----BEGIN X509 CRL-----"));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::IF_NONE_MATCH, etag.clone());
+        let response = crl_response(&config(), CrlFormat::Der, &headers, now);
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers()[header::ETAG], etag);
+
+        let response = crl_response(&Config::default(), CrlFormat::Der, &HeaderMap::new(), now);
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn test_cache_control() {
+        let now = Utc::now();
+        assert_eq!(cache_control(Some(now + chrono::Duration::hours(1)), now), "public, max-age=3600");
+        assert_eq!(cache_control(Some(now - chrono::Duration::hours(1)), now), "no-cache");
+        assert_eq!(cache_control(None, now), "no-cache");
+        assert_eq!(http_date(DateTime::from_timestamp(0, 0).unwrap()), "Thu, 01 Jan 1970 00:00:00 GMT");
+    }
+}
@@ -1,17 +1,39 @@
 use axum::Json;
-use secrecy::{ExposeSecret, Secret};
+use secrecy::Secret;
 use std::sync::Arc;
 use tracing::{debug, info};
 
-use crate::ca::IntermediateCA;
-use crate::config::Config;
+use crate::ca::{ca_chain, CertSigner, ChainPart, IntermediateCA};
+use crate::config::{CommandKind, Config};
 use crate::crypto;
-
+use crate::duration::Validity;
+use crate::web::bundle::Bundle;
+use openssl::base64;
+use openssl::pkey::PKey;
+use zeroize::Zeroizing;
+
+use super::csr_handler::{issued, sign_error};
+use super::download_handler::pkcs12_bundle;
+use super::super::audit;
 use super::super::models::{
-    CertificateGenerateRequest, CertificateGenerateResponse, CertificateWithKey, WebError,
+    CertificateGenerateRequest, CertificateGenerateResponse, CertificateWithKey, ErrorResponse, WebError,
 };
 
 /// Handle manual certificate generation request
+#[utoipa::path(
+    post,
+    path = "/api/cert/generate",
+    tag = "certificates",
+    request_body = CertificateGenerateRequest,
+    responses(
+        (status = 200, description = "Certificate and private key", body = CertificateGenerateResponse),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 403, description = "API key lacks the issue scope", body = ErrorResponse),
+        (status = 429, description = "Rate limited", body = ErrorResponse),
+    ),
+    security(("api_key" = []))
+)]
 pub async fn handle_certificate_generate(
     config: Arc<Config>,
     Json(request): Json<CertificateGenerateRequest>,
@@ -21,35 +43,48 @@ pub async fn handle_certificate_generate(
         request.common_name
     );
 
+    audit::note_name(request.common_name.as_str());
+
     // Validate common name
     if request.common_name.is_empty() {
         return Err(WebError::invalid_input("Common name cannot be empty"));
     }
 
+    // Omitted settings come from [defaults.web], already folded into config.defaults
+    let web_defaults = config.command_defaults(CommandKind::Web);
+    let validity = request.validity_days.map_or(config.defaults.cert_days, Validity::days);
+    let key_size = request.key_size.unwrap_or(config.defaults.key_size);
+    let password_protect = request.password_protect.or(web_defaults.password_protect).unwrap_or(false);
+
     // Validate validity days
-    if request.validity_days == 0 || request.validity_days > 825 {
+    if validity.as_secs() == 0 || validity > Validity::days(825) {
         return Err(WebError::invalid_input("Validity days must be between 1 and 825"));
     }
 
     // Validate key size
-    if request.key_size != 2048 && request.key_size != 4096 {
+    if key_size != 2048 && key_size != 4096 {
         return Err(WebError::invalid_input("Key size must be 2048 or 4096"));
     }
 
     // Validate password requirement
-    if request.password_protect && request.key_password.is_none() {
+    if password_protect && request.key_password.is_none() {
         return Err(WebError::invalid_input(
             "Password required when password_protect is true",
         ));
     }
 
+    let backend = crypto::backend(config.crypto_backend)
+        .map_err(|e| WebError::internal_error(e.to_string()))?;
+
     // Generate private key
-    debug!("Generating RSA private key (size: {})", request.key_size);
-    let private_key = crypto::generate_rsa_key(request.key_size, None)
-        .map_err(|e| WebError::key_generation_failed(format!("Failed to generate key: {}", e)))?;
+    debug!("Generating RSA private key (size: {}, backend: {})", key_size, backend.name());
+    let plain_key_pem = Zeroizing::new(backend.generate_key(key_size)
+        .map_err(|e| WebError::key_generation_failed(format!("Failed to generate key: {}", e)))?);
+    let private_key = PKey::private_key_from_pem(&plain_key_pem)
+        .map_err(|e| WebError::key_generation_failed(format!("Failed to load key: {}", e)))?;
 
     // Convert key to PEM (optionally encrypted)
-    let key_pem = if request.password_protect {
+    let key_pem = if password_protect {
         let password = request.key_password.as_ref().unwrap();
         let secret = Secret::new(password.clone());
 
@@ -64,16 +99,19 @@ pub async fn handle_certificate_generate(
     debug!("Private key generated successfully");
 
     // Parse SANs
-    let sans: Vec<crypto::SanEntry> = request
+    let mut sans: Vec<crypto::SanEntry> = request
         .sans
         .iter()
         .map(|s| crypto::SanEntry::parse(s))
         .collect::<Result<Vec<_>, _>>()
         .map_err(|e| WebError::invalid_input(format!("Invalid SAN format: {}", e)))?;
+    sans.extend(web_defaults.san_entries()?);
+    let sans = crypto::normalize_sans(&request.common_name, &sans, config.defaults.san_order);
 
     // Create CSR
     debug!("Creating CSR with CN={}", request.common_name);
-    let csr = crypto::create_csr(&request.common_name, &private_key, &sans, Some(&request.common_name))
+    let csr = backend.create_csr(&plain_key_pem, &request.common_name, &sans)
+        .and_then(|pem| crypto::csr_from_pem_bytes(&pem))
         .map_err(|e| WebError::signing_failed(format!("Failed to create CSR: {}", e)))?;
 
     debug!("CSR created successfully");
@@ -85,21 +123,58 @@ pub async fn handle_certificate_generate(
     debug!("CA loaded successfully");
 
     // Sign certificate
-    let cert = crypto::sign_csr(&csr, ca.cert(), ca.key(), request.validity_days)
-        .map_err(|e| WebError::signing_failed(format!("Failed to sign certificate: {}", e)))?;
+    let cert = ca.sign_for(&csr, validity).map_err(sign_error)?;
 
     info!("Certificate signed successfully");
 
     // Extract certificate information
     let cert_info = crypto::extract_certificate_info(&cert)
         .map_err(|e| WebError::internal_error(format!("Failed to extract cert info: {}", e)))?;
+    audit::note_serial(cert_info.serial_number.as_str());
+
+    issued(&config, Some(request.common_name.clone()), cert.clone()).await;
 
     // Convert certificate to PEM
     let cert_pem = crypto::cert_to_pem(&cert)
         .map_err(|e| WebError::internal_error(format!("Failed to convert cert to PEM: {}", e)))?;
 
     // Load CA chain (intermediate + root CA)
-    let ca_chain = build_ca_chain(&config, &ca).ok();
+    let ca_chain = ca_chain(&config, ChainPart::Full)
+        .and_then(|certs| certs.iter().map(crypto::cert_to_pem).collect::<crate::Result<Vec<_>>>())
+        .map(|pems| String::from_utf8_lossy(&pems.concat()).into_owned())
+        .map_err(|e| debug!("CA chain unavailable: {}", e))
+        .ok();
+
+    let (pkcs12, pkcs12_password) = if request.pkcs12 {
+        let ca_certs = match &ca_chain {
+            Some(pem) => crypto::certs_from_pem(pem.as_bytes())
+                .map_err(|e| WebError::internal_error(format!("Failed to read the CA chain: {}", e)))?,
+            None => Vec::new(),
+        };
+        let password = request.pkcs12_password.as_deref()
+            .or(request.key_password.as_deref().filter(|_| password_protect));
+        let (der, generated) = pkcs12_bundle(&request.common_name, &cert, &private_key, &ca_certs, password)?;
+        (Some(base64::encode_block(&der)), generated)
+    } else {
+        (None, None)
+    };
+
+    let zip = if request.zip {
+        let bundle = Bundle {
+            name: &request.common_name,
+            serial: &cert_info.serial_number,
+            not_after: cert_info.not_after,
+            cert_pem: &cert_pem,
+            key_pem: Some(&key_pem),
+            key_encrypted: password_protect,
+            chain_pem: ca_chain.as_deref().unwrap_or_default().as_bytes(),
+        };
+        let zip = bundle.to_zip()
+            .map_err(|e| WebError::internal_error(format!("Failed to build ZIP bundle: {}", e)))?;
+        Some(base64::encode_block(&zip))
+    } else {
+        None
+    };
 
     let response = CertificateGenerateResponse {
         success: true,
@@ -113,42 +188,11 @@ pub async fn handle_certificate_generate(
             not_after: cert_info.not_after,
             sans: cert_info.sans,
             download_url: None, // API returns PEM data directly; clients can save locally
+            pkcs12,
+            pkcs12_password,
+            zip,
         },
     };
 
     Ok(Json(response))
 }
-
-/// Build CA certificate chain (intermediate + root)
-fn build_ca_chain(config: &Config, ca: &IntermediateCA) -> std::result::Result<String, WebError> {
-    let mut chain = String::new();
-
-    // Add intermediate CA certificate
-    let intermediate_pem = crypto::cert_to_pem(ca.cert())
-        .map_err(|e| WebError::internal_error(format!("Failed to convert intermediate cert: {}", e)))?;
-    chain.push_str(&String::from_utf8_lossy(&intermediate_pem));
-
-    // Try to load root CA certificate
-    // Standard PKI structure places root CA at /root/ca/certs/ca.cert.pem
-    let root_ca_path = config.working_dir.join("certs").join("ca.cert.pem");
-
-    if root_ca_path.exists() {
-        debug!("Loading root CA from {:?}", root_ca_path);
-        match crypto::load_cert(&root_ca_path) {
-            Ok(root_cert) => {
-                let root_pem = crypto::cert_to_pem(&root_cert)
-                    .map_err(|e| WebError::internal_error(format!("Failed to convert root cert: {}", e)))?;
-                chain.push_str(&String::from_utf8_lossy(&root_pem));
-                debug!("Root CA added to chain");
-            }
-            Err(e) => {
-                debug!("Failed to load root CA: {}", e);
-                // Continue without root CA
-            }
-        }
-    } else {
-        debug!("Root CA not found at {:?}, chain will only contain intermediate", root_ca_path);
-    }
-
-    Ok(chain)
-}
@@ -0,0 +1,192 @@
+//! Signing many CSRs in one request (`POST /api/csr/upload-batch`)
+
+use axum::{extract::Multipart, Json};
+use openssl::x509::{X509, X509Req};
+use std::io::{Cursor, Read};
+use std::sync::Arc;
+use tracing::{debug, info, warn};
+use zip::ZipArchive;
+
+use crate::ca::{CertSigner, IntermediateCA};
+use crate::config::Config;
+use crate::crypto;
+use crate::duration::Validity;
+
+use super::csr_handler::{certificate_info, merged_sans, notify_issued, parse_additional_sans, split_sans};
+use super::upload::{form_error, read_field_limited, read_text_field};
+
+use super::super::audit;
+use super::super::models::{
+    CertificateInfo, CsrBatchItem, CsrBatchUploadForm, CsrBatchUploadResponse, ErrorResponse, WebError,
+};
+
+/// Most CSRs signed in one batch upload
+pub const MAX_BATCH_CSRS: usize = 100;
+
+/// Leading bytes of a ZIP archive
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+
+/// An uploaded CSR, or why it couldn't be read
+struct Upload {
+    file: String,
+    data: Result<Vec<u8>, String>,
+}
+
+/// Sign several CSRs with the same SANs and validity
+///
+/// Every CSR is signed on its own: one that fails to parse or sign is
+/// reported in its result without affecting the others.
+#[utoipa::path(
+    post,
+    path = "/api/csr/upload-batch",
+    tag = "certificates",
+    request_body(content = CsrBatchUploadForm, content_type = "multipart/form-data"),
+    responses(
+        (status = 200, description = "Result per CSR", body = CsrBatchUploadResponse),
+        (status = 400, description = "No CSRs, too many CSRs, or an invalid form or archive", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 403, description = "API key lacks the issue scope", body = ErrorResponse),
+        (status = 413, description = "Upload too large", body = ErrorResponse),
+        (status = 429, description = "Rate limited", body = ErrorResponse),
+    ),
+    security(("api_key" = []))
+)]
+pub async fn handle_csr_batch_upload(
+    config: Arc<Config>,
+    mut multipart: Multipart,
+) -> Result<Json<CsrBatchUploadResponse>, WebError> {
+    info!("Processing batch CSR upload request");
+
+    let limit = config.web_upload_limit();
+    let mut uploads = Vec::new();
+    let mut sans = Vec::new();
+    let mut validity = config.defaults.cert_days;
+
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| form_error(e, "Failed to parse form data"))?
+    {
+        let name = field.name().unwrap_or("").to_string();
+        debug!("Processing field: {}", name);
+
+        match name.as_str() {
+            "csr_files" | "csr_file" => {
+                let file = field
+                    .file_name()
+                    .map(str::to_string)
+                    .unwrap_or_else(|| format!("csr-{}", uploads.len() + 1));
+                let data = read_field_limited(field, limit, "CSR file").await?;
+                if data.starts_with(ZIP_MAGIC) {
+                    uploads.extend(unzip_csrs(&file, &data, limit)?);
+                } else {
+                    uploads.push(Upload { file, data: Ok(data) });
+                }
+
+                if uploads.len() > MAX_BATCH_CSRS {
+                    return Err(WebError::invalid_input(format!(
+                        "At most {} CSRs can be signed in one upload",
+                        MAX_BATCH_CSRS
+                    )));
+                }
+            }
+            "sans" => {
+                sans = split_sans(&read_text_field(field, "SANs").await?);
+            }
+            "validity_days" => {
+                let text = read_text_field(field, "validity_days").await?;
+                validity = text.parse().unwrap_or(config.defaults.cert_days);
+            }
+            _ => {
+                debug!("Ignoring unknown field: {}", name);
+            }
+        }
+    }
+
+    if uploads.is_empty() {
+        return Err(WebError::bad_request("No CSR files provided"));
+    }
+    let additional_sans = parse_additional_sans(&sans)?;
+
+    let ca = IntermediateCA::load(&config)
+        .map_err(|e| WebError::ca_error(format!("Failed to load CA: {}", e)))?;
+
+    let mut response = CsrBatchUploadResponse { successful: 0, failed: 0, results: Vec::with_capacity(uploads.len()) };
+    for Upload { file, data } in uploads {
+        let item = match sign_upload(&config, &ca, data, &additional_sans, validity) {
+            Ok((csr, cert, certificate)) => {
+                if let Ok(common_name) = crypto::get_csr_subject(&csr) {
+                    audit::note_name(common_name);
+                }
+                audit::note_serial(certificate.serial.as_str());
+                notify_issued(&config, &csr, cert).await;
+                response.successful += 1;
+                CsrBatchItem { file, success: true, certificate: Some(certificate), error: None }
+            }
+            Err(error) => {
+                warn!("Failed to sign {}: {}", file, error);
+                response.failed += 1;
+                CsrBatchItem { file, success: false, certificate: None, error: Some(error) }
+            }
+        };
+        response.results.push(item);
+    }
+
+    info!("Batch upload: {} signed, {} failed", response.successful, response.failed);
+    Ok(Json(response))
+}
+
+/// Parse and sign one uploaded CSR
+fn sign_upload(
+    config: &Config,
+    ca: &IntermediateCA,
+    data: Result<Vec<u8>, String>,
+    additional_sans: &[crypto::SanEntry],
+    validity: Validity,
+) -> Result<(X509Req, X509, CertificateInfo), String> {
+    let data = data?;
+    let csr = crypto::csr_from_pem_bytes(&data).map_err(|e| format!("Failed to parse CSR: {}", e))?;
+    let merged = merged_sans(config, &csr, additional_sans).map_err(|e| e.response.error.message)?;
+
+    let signed = match &merged {
+        Some(sans) => ca.sign_with_sans(&csr, sans, validity),
+        None => ca.sign_for(&csr, validity),
+    };
+    let cert = signed.map_err(|e| format!("Failed to sign certificate: {}", e))?;
+    let certificate = certificate_info(&cert).map_err(|e| e.response.error.message)?;
+    Ok((csr, cert, certificate))
+}
+
+/// The `.csr` and `.pem` members of a ZIP archive
+///
+/// Stops after one more member than a batch may hold.
+fn unzip_csrs(archive_name: &str, data: &[u8], limit: usize) -> Result<Vec<Upload>, WebError> {
+    let invalid = |e: zip::result::ZipError| WebError::bad_request(format!("{} is not a valid ZIP archive: {}", archive_name, e));
+    let mut archive = ZipArchive::new(Cursor::new(data)).map_err(invalid)?;
+
+    let mut uploads = Vec::new();
+    for index in 0..archive.len() {
+        let mut entry = archive.by_index(index).map_err(invalid)?;
+        let path = entry.name().to_string();
+        let base = path.rsplit('/').next().unwrap_or_default().to_lowercase();
+        let wanted = base.ends_with(".csr") || base.ends_with(".pem");
+        if entry.is_dir() || !wanted || base.starts_with('.') || path.starts_with("__MACOSX/") {
+            continue;
+        }
+
+        // Sizes in the archive can lie, so count what is actually extracted
+        let mut contents = Vec::new();
+        let data = match (&mut entry).take(limit as u64 + 1).read_to_end(&mut contents) {
+            Err(e) => Err(format!("Failed to extract: {}", e)),
+            Ok(_) if contents.len() > limit => Err(format!("Exceeds the {} byte limit", limit)),
+            Ok(_) => Ok(contents),
+        };
+        uploads.push(Upload { file: format!("{}/{}", archive_name, path), data });
+
+        if uploads.len() > MAX_BATCH_CSRS {
+            break;
+        }
+    }
+
+    Ok(uploads)
+}
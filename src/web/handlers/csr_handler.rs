@@ -1,71 +1,88 @@
 use axum::{extract::Multipart, Json};
+use openssl::x509::{X509, X509Req};
+use std::path::Path;
 use std::sync::Arc;
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
 
-use crate::ca::IntermediateCA;
-use crate::config::Config;
+use crate::apikey;
+use crate::ca::{CertSigner, IntermediateCA};
+use crate::config::{CommandKind, Config};
 use crate::crypto;
+use crate::fsutil::{write_atomic, PUBLIC_FILE_MODE};
+use crate::ldap;
+use crate::notify::webhook::{self, Event, EventKind};
+use crate::store::{record_issuance, IssuedCert};
 
+use super::upload::{form_error, read_field_limited, read_text_field};
+use super::super::auth::EnrollmentGrant;
+
+use super::super::audit;
 use super::super::models::{
-    CertificateInfo, CsrUploadMetadata, CsrUploadResponse, WebError,
+    CertificateInfo, CsrSignCaForm, CsrUploadForm, CsrUploadResponse, ErrorResponse, WebError,
 };
 
 /// Handle CSR upload and signing
+///
+/// With an enrollment token (`grant`) the CSR's common name and SANs must be
+/// within the enrollment, which is used up once the certificate is signed.
+#[utoipa::path(
+    post,
+    path = "/api/csr/upload",
+    tag = "certificates",
+    request_body(content = CsrUploadForm, content_type = "multipart/form-data"),
+    responses(
+        (status = 200, description = "Signed certificate", body = CsrUploadResponse),
+        (status = 400, description = "Invalid CSR or form", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid API key or enrollment token", body = ErrorResponse),
+        (status = 403, description = "API key lacks the issue scope, or the CSR is outside the enrollment", body = ErrorResponse),
+        (status = 413, description = "CSR file too large", body = ErrorResponse),
+        (status = 429, description = "Rate limited", body = ErrorResponse),
+    ),
+    security(("api_key" = []), ("enrollment_token" = []))
+)]
 pub async fn handle_csr_upload(
     config: Arc<Config>,
+    grant: Option<EnrollmentGrant>,
     mut multipart: Multipart,
 ) -> Result<Json<CsrUploadResponse>, WebError> {
     info!("Processing CSR upload request");
 
     let mut csr_data: Option<Vec<u8>> = None;
-    let mut metadata = CsrUploadMetadata {
-        sans: Vec::new(),
-        validity_days: config.defaults.cert_days,
-    };
+    let mut sans = Vec::new();
+    let mut validity = config.defaults.cert_days;
 
     // Parse multipart form data
     while let Some(field) = multipart
         .next_field()
         .await
-        .map_err(|e| WebError::bad_request(format!("Failed to parse form data: {}", e)))?
+        .map_err(|e| form_error(e, "Failed to parse form data"))?
     {
         let name = field.name().unwrap_or("").to_string();
         debug!("Processing field: {}", name);
 
         match name.as_str() {
             "csr_file" => {
-                let data = field
-                    .bytes()
-                    .await
-                    .map_err(|e| WebError::bad_request(format!("Failed to read CSR file: {}", e)))?;
+                let data = read_field_limited(
+                    field,
+                    config.web_upload_limit(),
+                    "CSR file",
+                )
+                .await?;
 
                 if data.is_empty() {
                     return Err(WebError::invalid_csr("CSR file is empty"));
                 }
 
-                // Check file size (5MB limit)
-                if data.len() > 5 * 1024 * 1024 {
-                    return Err(WebError::file_too_large("CSR file exceeds 5MB limit"));
-                }
-
-                csr_data = Some(data.to_vec());
+                csr_data = Some(data);
             }
             "sans" => {
-                let text = field
-                    .text()
-                    .await
-                    .map_err(|e| WebError::bad_request(format!("Failed to read SANs: {}", e)))?;
-
-                // Parse comma-separated SANs
-                metadata.sans = text
-                    .split(',')
-                    .map(|s| s.trim().to_string())
-                    .filter(|s| !s.is_empty())
-                    .collect();
+                let text = read_text_field(field, "SANs").await?;
+
+                sans = split_sans(&text);
             }
             "validity_days" => {
-                let text = field.text().await.unwrap_or_default();
-                metadata.validity_days = text.parse().unwrap_or(config.defaults.cert_days);
+                let text = read_text_field(field, "validity_days").await?;
+                validity = text.parse().unwrap_or(config.defaults.cert_days);
             }
             _ => {
                 debug!("Ignoring unknown field: {}", name);
@@ -80,14 +97,16 @@ pub async fn handle_csr_upload(
         .map_err(|e| WebError::invalid_csr(format!("Failed to parse CSR: {}", e)))?;
 
     debug!("CSR parsed successfully");
+    if let Ok(common_name) = crypto::get_csr_subject(&csr) {
+        audit::note_name(common_name);
+    }
 
-    // Parse additional SANs (currently not used in sign_csr, but could be extended)
-    let _additional_sans: Vec<crypto::SanEntry> = metadata
-        .sans
-        .iter()
-        .map(|s| crypto::SanEntry::parse(s))
-        .collect::<Result<Vec<_>, _>>()
-        .map_err(|e| WebError::invalid_input(format!("Invalid SAN format: {}", e)))?;
+    let additional_sans = parse_additional_sans(&sans)?;
+    let merged_sans = merged_sans(&config, &csr, &additional_sans)?;
+
+    if let Some(grant) = &grant {
+        check_enrollment(&config, &csr, merged_sans.as_deref(), grant)?;
+    }
 
     // Load CA
     let ca = IntermediateCA::load(&config)
@@ -96,31 +115,268 @@ pub async fn handle_csr_upload(
     debug!("CA loaded successfully");
 
     // Sign certificate
-    let cert = crypto::sign_csr(&csr, ca.cert(), ca.key(), metadata.validity_days)
-        .map_err(|e| WebError::signing_failed(format!("Failed to sign certificate: {}", e)))?;
+    let sign = || {
+        let signed = match &merged_sans {
+            Some(sans) => ca.sign_with_sans(&csr, sans, validity),
+            None => ca.sign_for(&csr, validity),
+        };
+        signed.map_err(sign_error)
+    };
+    let cert = match grant {
+        Some(grant) => redeem_enrollment(&config, &grant, sign)?,
+        None => sign()?,
+    };
 
     info!("Certificate signed successfully");
 
-    // Extract certificate information
-    let cert_info = crypto::extract_certificate_info(&cert)
-        .map_err(|e| WebError::internal_error(format!("Failed to extract cert info: {}", e)))?;
+    let certificate = certificate_info(&cert)?;
+    audit::note_serial(certificate.serial.as_str());
+    notify_issued(&config, &csr, cert).await;
+
+    Ok(Json(CsrUploadResponse { success: true, certificate }))
+}
+
+/// Sign a CSR as a subordinate CA
+///
+/// Only with `policy.allow_web_sub_ca`. The path length and name constraints
+/// default to `[sub_ca]`; `pathlen`, `permitted` and `excluded` may only
+/// narrow them.
+#[utoipa::path(
+    post,
+    path = "/api/csr/sign-ca",
+    tag = "certificates",
+    request_body(content = CsrSignCaForm, content_type = "multipart/form-data"),
+    responses(
+        (status = 200, description = "Signed subordinate CA certificate", body = CsrUploadResponse),
+        (status = 400, description = "Invalid CSR, form or name constraint", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 403, description = "Sub-CA signing is disabled, the API key lacks the issue scope, the request widens [sub_ca], or the CA can't sign this path length", body = ErrorResponse),
+        (status = 413, description = "CSR file too large", body = ErrorResponse),
+        (status = 429, description = "Rate limited", body = ErrorResponse),
+    ),
+    security(("api_key" = []))
+)]
+pub async fn handle_csr_sign_ca(
+    config: Arc<Config>,
+    mut multipart: Multipart,
+) -> Result<Json<CsrUploadResponse>, WebError> {
+    if !config.policy.allow_web_sub_ca {
+        return Err(WebError::forbidden(
+            "Signing subordinate CAs over the web is disabled (policy.allow_web_sub_ca)",
+        ));
+    }
+    info!("Processing sub-CA signing request");
+
+    let mut csr_data: Option<Vec<u8>> = None;
+    let mut validity = config.defaults.cert_days;
+    let mut pathlen = None;
+    let mut permitted = Vec::new();
+    let mut excluded = Vec::new();
+
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| form_error(e, "Failed to parse form data"))?
+    {
+        let name = field.name().unwrap_or("").to_string();
+        debug!("Processing field: {}", name);
+
+        match name.as_str() {
+            "csr_file" => {
+                let data = read_field_limited(field, config.web_upload_limit(), "CSR file").await?;
+                if data.is_empty() {
+                    return Err(WebError::invalid_csr("CSR file is empty"));
+                }
+                csr_data = Some(data);
+            }
+            "validity_days" => {
+                let text = read_text_field(field, "validity_days").await?;
+                validity = text.parse().unwrap_or(config.defaults.cert_days);
+            }
+            "pathlen" => {
+                let text = read_text_field(field, "pathlen").await?;
+                pathlen = Some(text.trim().parse::<u32>()
+                    .map_err(|_| WebError::invalid_input(format!("Invalid pathlen '{}'", text.trim())))?);
+            }
+            "permitted" => permitted = split_sans(&read_text_field(field, "permitted").await?),
+            "excluded" => excluded = split_sans(&read_text_field(field, "excluded").await?),
+            _ => {
+                debug!("Ignoring unknown field: {}", name);
+            }
+        }
+    }
 
-    // Convert to PEM
-    let pem = crypto::cert_to_pem(&cert)
+    let csr_data = csr_data.ok_or_else(|| WebError::bad_request("No CSR file provided"))?;
+    let csr = crypto::csr_from_pem_bytes(&csr_data)
+        .map_err(|e| WebError::invalid_csr(format!("Failed to parse CSR: {}", e)))?;
+    if let Ok(common_name) = crypto::get_csr_subject(&csr) {
+        audit::note_name(common_name);
+    }
+
+    let ca = IntermediateCA::load(&config)
+        .map_err(|e| WebError::ca_error(format!("Failed to load CA: {}", e)))?;
+    // Requests may only narrow [sub_ca]
+    let requested = crypto::NameConstraints::parse(&permitted, &excluded)
+        .map_err(|e| WebError::invalid_input(e.to_string()))?;
+    let sub_ca = crypto::narrow_sub_ca(ca.sub_ca(), pathlen, &requested).map_err(WebError::from)?;
+
+    let cert = ca.sign_sub_ca(&csr, validity, &sub_ca).map_err(sign_error)?;
+    info!("Subordinate CA certificate signed successfully");
+
+    let certificate = certificate_info(&cert)?;
+    audit::note_serial(certificate.serial.as_str());
+    notify_issued(&config, &csr, cert).await;
+
+    Ok(Json(CsrUploadResponse { success: true, certificate }))
+}
+
+/// Policy refusals are reported as bad input or forbidden, anything else as
+/// a signing failure
+pub(super) fn sign_error(err: crate::error::FluxError) -> WebError {
+    use crate::error::FluxError;
+
+    match err {
+        FluxError::InvalidSanFormat(_) | FluxError::WildcardNotAllowed(_) | FluxError::PolicyViolation(_) => {
+            WebError::from(err)
+        }
+        err => WebError::signing_failed(format!("Failed to sign certificate: {}", err)),
+    }
+}
+
+/// Comma-separated SANs of the `sans` form field
+pub(super) fn split_sans(text: &str) -> Vec<String> {
+    text.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect()
+}
+
+pub(super) fn parse_additional_sans(sans: &[String]) -> Result<Vec<crypto::SanEntry>, WebError> {
+    sans.iter()
+        .map(|s| crypto::SanEntry::parse(s))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| WebError::invalid_input(format!("Invalid SAN format: {}", e)))
+}
+
+/// The CSR's SANs plus `additional` and `[defaults.web]` SANs, normalized
+///
+/// `None` when that is exactly what the CSR already requests, so it is signed as-is.
+pub(super) fn merged_sans(
+    config: &Config,
+    csr: &X509Req,
+    additional: &[crypto::SanEntry],
+) -> Result<Option<Vec<crypto::SanEntry>>, WebError> {
+    let common_name = crypto::get_csr_subject(csr).unwrap_or_default();
+    let csr_sans = crypto::csr_sans(csr)
+        .map_err(|e| WebError::invalid_csr(format!("Failed to read CSR SANs: {}", e)))?;
+    let mut additional = additional.to_vec();
+    additional.extend(config.command_defaults(CommandKind::Web).san_entries()?);
+    let merged = crypto::merge_sans(&common_name, &csr_sans, &additional, config.defaults.san_order);
+    Ok((merged != csr_sans).then_some(merged))
+}
+
+/// Response form of a signed certificate
+pub(super) fn certificate_info(cert: &X509) -> Result<CertificateInfo, WebError> {
+    let cert_info = crypto::extract_certificate_info(cert)
+        .map_err(|e| WebError::internal_error(format!("Failed to extract cert info: {}", e)))?;
+    let pem = crypto::cert_to_pem(cert)
         .map_err(|e| WebError::internal_error(format!("Failed to convert to PEM: {}", e)))?;
 
-    let response = CsrUploadResponse {
-        success: true,
-        certificate: CertificateInfo {
-            pem: String::from_utf8_lossy(&pem).to_string(),
-            subject: cert_info.subject,
-            issuer: cert_info.issuer,
-            serial: cert_info.serial_number,
-            not_before: cert_info.not_before,
-            not_after: cert_info.not_after,
-            sans: cert_info.sans,
-        },
+    Ok(CertificateInfo {
+        pem: String::from_utf8_lossy(&pem).to_string(),
+        subject: cert_info.subject,
+        issuer: cert_info.issuer,
+        serial: cert_info.serial_number,
+        not_before: cert_info.not_before,
+        not_after: cert_info.not_after,
+        sans: cert_info.sans,
+    })
+}
+
+/// Record a signed certificate, then send the webhook and publish to the
+/// directory in the background
+///
+/// All of them block on the disk or the network, so they stay off the
+/// runtime. The record is awaited so the certificate is listed as soon as
+/// the response is out.
+pub(super) async fn notify_issued(config: &Arc<Config>, csr: &X509Req, cert: X509) {
+    let name = crypto::get_csr_subject(csr).ok();
+    issued(config, name, cert).await
+}
+
+/// [`notify_issued`] for a certificate named `name` (its subject if `None`)
+pub(super) async fn issued(config: &Arc<Config>, name: Option<String>, cert: X509) {
+    let Ok(cert_info) = crypto::extract_certificate_info(&cert) else { return };
+    let name = name.unwrap_or_else(|| cert_info.subject.clone());
+    let mut event = Event::from_info(EventKind::Issued, &name, &cert_info);
+
+    let (record_config, record_name, record_cert) = (Arc::clone(config), name.clone(), cert.clone());
+    match tokio::task::spawn_blocking(move || record_issued(&record_config, &record_name, &record_cert)).await {
+        Ok(Ok(true)) => event.event = EventKind::Renewed,
+        Ok(Ok(false)) => {}
+        Ok(Err(e)) => warn!("Failed to record {} in certificate store: {}", name, e),
+        Err(e) => warn!("Failed to record {} in certificate store: {}", name, e),
+    }
+
+    let emit_config = Arc::clone(config);
+    tokio::task::spawn_blocking(move || {
+        webhook::emit(&emit_config, &event);
+        if ldap::auto_publish(&emit_config) {
+            match ldap::publish_cert(&emit_config, &name, &cert) {
+                Ok(dn) => info!("Published {} to {}", name, dn),
+                Err(e) => warn!("Failed to publish {} to the directory: {}", name, e),
+            }
+        }
+    });
+}
+
+/// Keep a copy of a web-issued certificate and add it to the certificate store
+///
+/// Returns whether it renews an unrevoked certificate of the same name.
+fn record_issued(config: &Config, name: &str, cert: &X509) -> crate::Result<bool> {
+    let record = IssuedCert::from_cert(name, cert, Path::new(""), None)?;
+    let dir = config.web_certs_dir();
+    std::fs::create_dir_all(&dir)?;
+    let cert_path = dir.join(format!("{}.cert.pem", record.serial));
+    write_atomic(&cert_path, &crypto::cert_to_pem(cert)?, PUBLIC_FILE_MODE)?;
+
+    record_issuance(config, IssuedCert { cert_path, ..record })
+}
+
+/// Check the CSR against an enrollment
+fn check_enrollment(
+    config: &Config,
+    csr: &X509Req,
+    merged_sans: Option<&[crypto::SanEntry]>,
+    grant: &EnrollmentGrant,
+) -> Result<(), WebError> {
+    let common_name = crypto::get_csr_subject(csr)
+        .map_err(|e| WebError::invalid_csr(format!("Failed to read CSR subject: {}", e)))?;
+    let mut sans = match merged_sans {
+        Some(sans) => sans.to_vec(),
+        None => crypto::csr_sans(csr)
+            .map_err(|e| WebError::invalid_csr(format!("Failed to read CSR SANs: {}", e)))?,
     };
+    // [defaults.web] SANs go on every certificate; the token needn't list them
+    let configured = config.command_defaults(CommandKind::Web).san_entries()?;
+    sans.retain(|san| !configured.contains(san));
+    if !grant.enrollment.permits(&common_name, &sans) {
+        return Err(WebError::forbidden(format!(
+            "The enrollment token for '{}' doesn't allow a certificate for '{}' with these SANs",
+            grant.enrollment.name, common_name
+        )));
+    }
+    Ok(())
+}
 
-    Ok(Json(response))
+/// Sign with `sign` and use the enrollment up, if it is still unused
+fn redeem_enrollment(
+    config: &Config,
+    grant: &EnrollmentGrant,
+    sign: impl FnOnce() -> Result<X509, WebError>,
+) -> Result<X509, WebError> {
+    match apikey::redeem_enrollment(config, &grant.token, |_| sign())? {
+        Some(cert) => {
+            info!("Enrollment token for '{}' used", grant.enrollment.name);
+            Ok(cert)
+        }
+        None => Err(WebError::unauthorized("Enrollment token was already used")),
+    }
 }
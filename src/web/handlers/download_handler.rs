@@ -0,0 +1,194 @@
+use axum::extract::{rejection::QueryRejection, Path, Query};
+use axum::http::{header, HeaderMap, HeaderValue};
+use axum::response::{IntoResponse, Response};
+use openssl::pkey::{PKey, Private};
+use openssl::x509::X509;
+use secrecy::ExposeSecret;
+use std::sync::Arc;
+use tracing::info;
+
+use crate::ca::chain_pem;
+use crate::config::Config;
+use crate::crypto;
+use crate::passwords::{generate_password, GENERATED_PASSWORD_LEN};
+use crate::fsutil::read_bounded;
+use crate::store::{CertStore, IssuedCert};
+use crate::web::bundle::Bundle;
+use zeroize::Zeroizing;
+
+use super::super::audit;
+use super::super::models::{CertDownloadQuery, DownloadFormat, ErrorResponse, WebError};
+
+/// Request header with the PKCS#12 password; also the response header
+/// carrying a generated one
+pub const PKCS12_PASSWORD_HEADER: &str = "x-pkcs12-password";
+
+/// Request header unlocking a password-protected private key
+pub const KEY_PASSWORD_HEADER: &str = "x-key-password";
+
+/// Download an issued certificate by serial number
+///
+/// `pkcs12` and `zip` bundle the private key, so the route needs the issue scope.
+/// Without an `X-PKCS12-Password` header the bundle gets a generated password,
+/// returned in the same response header.
+#[utoipa::path(
+    get,
+    path = "/api/certs/{serial}/download",
+    tag = "certificates",
+    params(
+        ("serial" = String, Path, description = "Serial number in hex (case and colons are ignored)"),
+        CertDownloadQuery,
+        ("X-PKCS12-Password" = Option<String>, Header, description = "Password of the PKCS#12 bundle"),
+        ("X-Key-Password" = Option<String>, Header, description = "Password of a protected private key"),
+    ),
+    responses(
+        (status = 200, description = "Certificate file", content(
+            ("application/x-pem-file"), ("application/pkix-cert"), ("application/x-pkcs12"), ("application/zip"),
+        )),
+        (status = 400, description = "Invalid query or missing key password", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 403, description = "API key lacks the issue scope", body = ErrorResponse),
+        (status = 404, description = "Unknown serial, or the CA doesn't hold the private key", body = ErrorResponse),
+    ),
+    security(("api_key" = []))
+)]
+pub async fn handle_certificate_download(
+    config: Arc<Config>,
+    Path(serial): Path<String>,
+    query: Result<Query<CertDownloadQuery>, QueryRejection>,
+    headers: HeaderMap,
+) -> Result<Response, WebError> {
+    let Query(query) = query.map_err(|e| WebError::bad_request(format!("Invalid query: {}", e)))?;
+
+    let store = CertStore::open_default(&config)
+        .map_err(|e| WebError::internal_error(format!("Failed to read certificate store: {}", e)))?;
+    let record = find_serial(&store, &serial)
+        .ok_or_else(|| WebError::not_found(format!("No certificate with serial {}", serial)))?;
+    audit::note_name(record.name.as_str());
+    audit::note_serial(record.serial.as_str());
+    let cert = crypto::load_cert(&record.cert_path)
+        .map_err(|e| WebError::internal_error(format!("Failed to read {}: {}", record.cert_path.display(), e)))?;
+    let ca_certs = chain_pem(&config)
+        .and_then(|pem| crypto::certs_from_pem(&pem))
+        .map_err(|e| WebError::ca_error(format!("Failed to read the CA chain: {}", e)))?;
+
+    let mut generated = None;
+    let (body, content_type, extension) = match query.format {
+        DownloadFormat::Pem => {
+            let mut pem = crypto::cert_to_pem(&cert).map_err(|e| WebError::internal_error(e.to_string()))?;
+            for ca in &ca_certs {
+                pem.extend(crypto::cert_to_pem(ca).map_err(|e| WebError::internal_error(e.to_string()))?);
+            }
+            (pem, "application/x-pem-file", "pem")
+        }
+        DownloadFormat::Der => {
+            (cert.to_der().map_err(|e| WebError::internal_error(e.to_string()))?, "application/pkix-cert", "crt")
+        }
+        DownloadFormat::Pkcs12 => {
+            let key = load_key(record, header_value(&headers, KEY_PASSWORD_HEADER))?;
+            let (der, password) = pkcs12_bundle(
+                &record.name,
+                &cert,
+                &key,
+                &ca_certs,
+                header_value(&headers, PKCS12_PASSWORD_HEADER),
+            )?;
+            generated = password;
+            (der, "application/x-pkcs12", "p12")
+        }
+        DownloadFormat::Zip => (zip_bundle(&config, record, &cert)?, "application/zip", "zip"),
+    };
+
+    info!("Serving {} ({}) as {}", record.name, record.serial, extension);
+    let mut response = body.into_response();
+    let response_headers = response.headers_mut();
+    response_headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+    response_headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    // Certificate names are limited to alphanumerics, '-', '_' and '.'
+    if let Ok(value) = HeaderValue::from_str(&format!("attachment; filename=\"{}.{}\"", record.name, extension)) {
+        response_headers.insert(header::CONTENT_DISPOSITION, value);
+    }
+    if let Some(password) = generated.and_then(|p| HeaderValue::from_str(&p).ok()) {
+        response_headers.insert(PKCS12_PASSWORD_HEADER, password);
+    }
+    Ok(response)
+}
+
+/// PKCS#12 bundle of a certificate, its key and CA chain
+///
+/// Returns the generated password too when `password` is `None`.
+pub(super) fn pkcs12_bundle(
+    name: &str,
+    cert: &X509,
+    key: &PKey<Private>,
+    ca_certs: &[X509],
+    password: Option<&str>,
+) -> Result<(Vec<u8>, Option<String>), WebError> {
+    let generated = match password {
+        Some("") => return Err(WebError::invalid_input("The PKCS#12 password cannot be empty")),
+        Some(_) => None,
+        None => Some(
+            generate_password(GENERATED_PASSWORD_LEN)
+                .map_err(|e| WebError::internal_error(e.to_string()))?
+                .expose_secret()
+                .clone(),
+        ),
+    };
+    let der = crypto::to_pkcs12(name, cert, key, ca_certs, password.or(generated.as_deref()).unwrap_or_default())
+        .map_err(|e| WebError::internal_error(format!("Failed to build PKCS#12 bundle: {}", e)))?;
+    Ok((der, generated))
+}
+
+/// ZIP bundle of a stored certificate, with its key as stored on disk
+fn zip_bundle(config: &Config, record: &IssuedCert, cert: &X509) -> Result<Vec<u8>, WebError> {
+    let key_pem = record.key_path.as_ref().filter(|path| path.exists())
+        .map(|path| read_bounded(path).map(Zeroizing::new))
+        .transpose()
+        .map_err(|e| WebError::internal_error(format!("Failed to read the private key: {}", e)))?;
+    let cert_pem = crypto::cert_to_pem(cert).map_err(|e| WebError::internal_error(e.to_string()))?;
+    let chain = chain_pem(config).map_err(|e| WebError::ca_error(format!("Failed to read the CA chain: {}", e)))?;
+
+    Bundle {
+        name: &record.name,
+        serial: &record.serial,
+        not_after: record.not_after,
+        cert_pem: &cert_pem,
+        key_pem: key_pem.as_deref().map(Vec::as_slice),
+        key_encrypted: record.key_encrypted,
+        chain_pem: &chain,
+    }
+    .to_zip()
+    .map_err(|e| WebError::internal_error(format!("Failed to build ZIP bundle: {}", e)))
+}
+
+/// Latest store record with `serial`
+fn find_serial<'a>(store: &'a CertStore, serial: &str) -> Option<&'a IssuedCert> {
+    let wanted = normalize_serial(serial);
+    store.certificates().iter().rev().find(|cert| normalize_serial(&cert.serial) == wanted)
+}
+
+/// Upper-case hex without colons or leading zeros
+fn normalize_serial(serial: &str) -> String {
+    let hex: String = serial.chars().filter(|c| *c != ':').collect::<String>().to_uppercase();
+    hex.trim_start_matches('0').to_string()
+}
+
+fn load_key(record: &IssuedCert, password: Option<&str>) -> Result<PKey<Private>, WebError> {
+    let key_path = record.key_path.as_ref().ok_or_else(|| {
+        WebError::not_found(format!("The private key of {} is not held by this CA (it was signed from a CSR)", record.name))
+    })?;
+    let encrypted = crypto::is_key_encrypted(key_path)
+        .map_err(|e| WebError::internal_error(format!("Failed to read the private key: {}", e)))?;
+    if encrypted && password.is_none() {
+        return Err(WebError::bad_request(format!(
+            "The private key of {} is password protected; send its password in X-Key-Password",
+            record.name
+        )));
+    }
+    crypto::load_private_key(key_path, password)
+        .map_err(|e| WebError::bad_request(format!("Failed to unlock the private key: {}", e)))
+}
+
+fn header_value<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name).and_then(|v| v.to_str().ok())
+}
@@ -1,56 +1,68 @@
 use axum::{extract::Multipart, Json};
-use chrono::{DateTime, Utc};
-use openssl::hash::MessageDigest;
 use openssl::x509::X509;
-use std::collections::HashMap;
+use std::sync::Arc;
 use tracing::{debug, info};
 
+use crate::config::Config;
 use crate::crypto;
 
+use super::upload::{form_error, read_field_limited, read_text_field};
+
+use super::super::audit;
 use super::super::models::{
-    CertificateInfoResponse, DetailedCertificateInfo, ExtensionInfo, FingerprintInfo,
-    PublicKeyInfo, ValidityInfo, WebError,
+    CertInfoForm, CertificateInfoResponse, ChainVerificationInfo, ErrorResponse, WebError,
 };
 
 /// Handle certificate information request
+#[utoipa::path(
+    post,
+    path = "/api/cert/info",
+    tag = "certificates",
+    request_body(content = CertInfoForm, content_type = "multipart/form-data"),
+    responses(
+        (status = 200, description = "Certificate details", body = CertificateInfoResponse),
+        (status = 400, description = "Invalid certificate or form", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 403, description = "API key lacks the read scope", body = ErrorResponse),
+        (status = 413, description = "Certificate file too large", body = ErrorResponse),
+    ),
+    security(("api_key" = []))
+)]
 pub async fn handle_certificate_info(
+    config: Arc<Config>,
     mut multipart: Multipart,
 ) -> Result<Json<CertificateInfoResponse>, WebError> {
     info!("Processing certificate info request");
 
-    let mut cert_data: Option<Vec<u8>> = None;
+    let mut certs: Vec<X509> = Vec::new();
     let mut verify_chain = false;
+    let upload_limit = config.web_upload_limit();
 
     // Parse multipart form data
     while let Some(field) = multipart
         .next_field()
         .await
-        .map_err(|e| WebError::bad_request(format!("Failed to parse form data: {}", e)))?
+        .map_err(|e| form_error(e, "Failed to parse form data"))?
     {
         let name = field.name().unwrap_or("").to_string();
         debug!("Processing field: {}", name);
 
         match name.as_str() {
             "cert_file" => {
-                let data = field.bytes().await.map_err(|e| {
-                    WebError::bad_request(format!("Failed to read certificate file: {}", e))
-                })?;
+                let data = read_field_limited(field, upload_limit, "Certificate file").await?;
 
                 if data.is_empty() {
                     return Err(WebError::invalid_certificate("Certificate file is empty"));
                 }
 
-                // Check file size (5MB limit)
-                if data.len() > 5 * 1024 * 1024 {
-                    return Err(WebError::file_too_large(
-                        "Certificate file exceeds 5MB limit",
-                    ));
-                }
-
-                cert_data = Some(data.to_vec());
+                // A single upload may contain a whole chain; fields may also repeat
+                let parsed = crypto::certs_from_bytes(&data).map_err(|e| {
+                    WebError::invalid_certificate(format!("Failed to parse certificate: {}", e))
+                })?;
+                certs.extend(parsed);
             }
             "verify_chain" => {
-                let text = field.text().await.unwrap_or_default();
+                let text = read_text_field(field, "verify_chain").await?;
                 verify_chain = text.parse().unwrap_or(false);
             }
             _ => {
@@ -59,208 +71,59 @@ pub async fn handle_certificate_info(
         }
     }
 
-    let cert_data =
-        cert_data.ok_or_else(|| WebError::bad_request("No certificate file provided"))?;
-
-    // Parse certificate
-    let cert = X509::from_pem(&cert_data).map_err(|e| {
-        WebError::invalid_certificate(format!("Failed to parse certificate: {}", e))
-    })?;
-
-    debug!("Certificate parsed successfully");
-
-    // Extract certificate information
-    let cert_info = crypto::extract_certificate_info(&cert).map_err(|e| {
-        WebError::internal_error(format!("Failed to extract certificate info: {}", e))
-    })?;
-
-    // Calculate fingerprints
-    let sha1_digest = cert
-        .digest(MessageDigest::sha1())
-        .map_err(|e| WebError::internal_error(format!("Failed to calculate SHA1: {}", e)))?;
-
-    let sha256_digest = cert
-        .digest(MessageDigest::sha256())
-        .map_err(|e| WebError::internal_error(format!("Failed to calculate SHA256: {}", e)))?;
-
-    let sha1 = sha1_digest
-        .as_ref()
-        .iter()
-        .map(|b| format!("{:02X}", b))
-        .collect::<Vec<_>>()
-        .join(":");
-
-    let sha256 = sha256_digest
-        .as_ref()
-        .iter()
-        .map(|b| format!("{:02X}", b))
-        .collect::<Vec<_>>()
-        .join(":");
-
-    // Extract issuer and subject into HashMaps
-    let issuer = parse_x509_name(cert.issuer_name());
-    let subject = parse_x509_name(cert.subject_name());
-
-    // Calculate validity info
-    let now = chrono::Utc::now();
-    let not_before = cert_info.not_before;
-    let not_after = cert_info.not_after;
-    let days_remaining = (not_after - now).num_days();
-    let is_expired = now > not_after;
-    let is_expiring_soon = days_remaining < 30 && !is_expired;
-
-    // Get public key info
-    let public_key_info = extract_public_key_info(&cert)?;
-
-    // Extract extensions
-    let extensions = extract_extensions(&cert);
-
-    // Convert to PEM
-    let pem = cert
-        .to_pem()
-        .map_err(|e| WebError::internal_error(format!("Failed to convert to PEM: {}", e)))?;
-
-    let response = CertificateInfoResponse {
-        success: true,
-        certificate: DetailedCertificateInfo {
-            version: cert.version() + 1, // OpenSSL uses 0-based versioning
-            serial_number: cert_info.serial_number.clone(),
-            signature_algorithm: cert_info.signature_algorithm.clone(),
-            issuer,
-            validity: ValidityInfo {
-                not_before,
-                not_after,
-                days_remaining,
-                is_expired,
-                is_expiring_soon,
-            },
-            subject,
-            subject_alternative_names: cert_info.sans.clone(),
-            public_key: public_key_info,
-            extensions,
-            fingerprints: FingerprintInfo { sha1, sha256 },
-            pem: String::from_utf8_lossy(&pem).to_string(),
-        },
-    };
-
-    info!("Certificate info extracted successfully");
-
-    Ok(Json(response))
-}
-
-/// Parse X509Name into HashMap
-fn parse_x509_name(name: &openssl::x509::X509NameRef) -> HashMap<String, String> {
-    let mut map = HashMap::new();
-
-    for entry in name.entries() {
-        if let Ok(data) = entry.data().as_utf8() {
-            let key = entry.object().nid().short_name().unwrap_or("UNKNOWN");
-            map.insert(key.to_string(), data.to_string());
-        }
+    if certs.is_empty() {
+        return Err(WebError::bad_request("No certificate file provided"));
     }
 
-    map
-}
-
-/// Extract public key information
-fn extract_public_key_info(cert: &X509) -> Result<PublicKeyInfo, WebError> {
-    let public_key = cert
-        .public_key()
-        .map_err(|e| WebError::internal_error(format!("Failed to get public key: {}", e)))?;
+    debug!("Parsed {} certificate(s)", certs.len());
 
-    let algorithm = if public_key.rsa().is_ok() {
-        "RSA"
-    } else if public_key.ec_key().is_ok() {
-        "ECDSA"
-    } else {
-        "UNKNOWN"
-    };
-
-    let size = public_key.bits();
-
-    let exponent = if let Ok(rsa) = public_key.rsa() {
-        rsa.e().to_dec_str().ok().and_then(|s| s.parse().ok())
+    // CA certificate verifies the CRL signature; only needed if revocation data is configured
+    let issuer = if config.revocation.is_configured() {
+        crypto::load_cert(&config.ca_cert_path).ok()
     } else {
         None
     };
 
-    Ok(PublicKeyInfo {
-        algorithm: algorithm.to_string(),
-        size,
-        exponent,
-    })
-}
-
-/// Extract certificate extensions
-fn extract_extensions(cert: &X509) -> Vec<ExtensionInfo> {
-    let mut extensions = Vec::new();
-
-    // Extract Subject Alternative Names
-    if let Some(san_ext) = cert.subject_alt_names() {
-        let mut sans = Vec::new();
-        for san in san_ext {
-            if let Some(dns) = san.dnsname() {
-                sans.push(format!("DNS:{}", dns));
-            }
-            if let Some(ip) = san.ipaddress() {
-                let ip_str = ip.iter()
-                    .map(|b| b.to_string())
-                    .collect::<Vec<_>>()
-                    .join(".");
-                sans.push(format!("IP:{}", ip_str));
-            }
-            if let Some(email) = san.email() {
-                sans.push(format!("EMAIL:{}", email));
-            }
+    let mut details = crypto::details::describe_all(&certs, &config.revocation, issuer.as_ref())
+        .map_err(|e| WebError::internal_error(format!("Failed to extract certificate info: {}", e)))?;
+    for (index, detail) in details.iter_mut().enumerate() {
+        if let Some(cn) = detail.subject.get("CN") {
+            audit::note_name(cn.as_str());
         }
-
-        if !sans.is_empty() {
-            extensions.push(ExtensionInfo {
-                oid: "2.5.29.17".to_string(),
-                name: "Subject Alternative Name".to_string(),
-                critical: false,
-                value: sans.join(", "),
-            });
+        audit::note_serial(detail.serial_number.as_str());
+
+        if verify_chain {
+            // Other uploaded certificates may serve as intermediates
+            let others: Vec<X509> = certs.iter()
+                .enumerate()
+                .filter(|(i, _)| *i != index)
+                .map(|(_, c)| c.clone())
+                .collect();
+            detail.chain_verification = Some(verify_against_ca(&config, &certs[index], &others)?);
         }
     }
 
-    // Extract Authority Key Identifier
-    if let Some(aki) = cert.authority_key_id() {
-        let keyid = aki.as_slice()
-            .iter()
-            .map(|b| format!("{:02X}", b))
-            .collect::<Vec<_>>()
-            .join(":");
+    let response = CertificateInfoResponse {
+        success: true,
+        certificate: details[0].clone(),
+        certificates: if details.len() > 1 { details } else { Vec::new() },
+    };
 
-        extensions.push(ExtensionInfo {
-            oid: "2.5.29.35".to_string(),
-            name: "Authority Key Identifier".to_string(),
-            critical: false,
-            value: format!("keyid:{}", keyid),
-        });
-    }
+    info!("Certificate info extracted successfully");
 
-    // Extract Subject Key Identifier
-    if let Some(ski) = cert.subject_key_id() {
-        let keyid = ski.as_slice()
-            .iter()
-            .map(|b| format!("{:02X}", b))
-            .collect::<Vec<_>>()
-            .join(":");
+    Ok(Json(response))
+}
 
-        extensions.push(ExtensionInfo {
-            oid: "2.5.29.14".to_string(),
-            name: "Subject Key Identifier".to_string(),
-            critical: false,
-            value: keyid,
-        });
-    }
+/// Verify a certificate against the configured intermediate (and root, if present)
+fn verify_against_ca(
+    config: &Config,
+    cert: &X509,
+    extra_intermediates: &[X509],
+) -> Result<ChainVerificationInfo, WebError> {
+    let result = crate::verify::against_ca(config, cert, extra_intermediates)
+        .map_err(|e| WebError::ca_error(format!("Failed to verify chain: {}", e)))?;
 
-    // Note: OpenSSL version in use doesn't expose direct methods for
-    // Basic Constraints, Key Usage, Extended Key Usage extraction.
-    // These would require parsing the extension stack directly which is
-    // version-dependent. The above extensions cover the most critical
-    // certificate information for web service use.
+    debug!("Chain verification result: trusted={}", result.trusted);
 
-    extensions
+    Ok(result.into())
 }
@@ -0,0 +1,79 @@
+use axum::extract::{rejection::QueryRejection, Query};
+use axum::Json;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tracing::warn;
+
+use crate::config::Config;
+use crate::inventory::Inventory;
+
+use super::super::models::{ErrorResponse, InventorySuggestQuery, InventorySuggestResponse, WebError};
+
+/// How long the inventory is reused before the sources are queried again
+pub const INVENTORY_CACHE_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// Most suggestions returned at once
+pub const MAX_SUGGESTIONS: usize = 100;
+
+/// Inventory shared between requests, reloaded after [`INVENTORY_CACHE_TTL`]
+#[derive(Default)]
+pub struct InventoryCache(Mutex<Option<(Instant, Arc<Inventory>)>>);
+
+impl InventoryCache {
+    /// The cached inventory, loading it first if it is missing or stale
+    ///
+    /// Concurrent requests wait for one load instead of each querying the sources.
+    async fn get(&self, config: &Arc<Config>) -> Result<Arc<Inventory>, WebError> {
+        let mut cached = self.0.lock().await;
+        if let Some((loaded, inventory)) = cached.as_ref() {
+            if loaded.elapsed() < INVENTORY_CACHE_TTL {
+                return Ok(Arc::clone(inventory));
+            }
+        }
+
+        let load_config = Arc::clone(config);
+        let (inventory, errors) = tokio::task::spawn_blocking(move || Inventory::load(&load_config))
+            .await
+            .map_err(|e| WebError::internal_error(e.to_string()))?;
+        for (source, e) in errors {
+            warn!("Inventory source {}: {}", source, e);
+        }
+
+        let inventory = Arc::new(inventory);
+        *cached = Some((Instant::now(), Arc::clone(&inventory)));
+        Ok(inventory)
+    }
+}
+
+/// Suggest SANs from the configured host inventory
+///
+/// Matches hostnames and addresses containing `q`, prefix matches first; the
+/// addresses of a matching host come along with its name.
+#[utoipa::path(
+    get,
+    path = "/api/inventory/suggest",
+    tag = "certificates",
+    params(InventorySuggestQuery),
+    responses(
+        (status = 200, description = "Suggested SANs", body = InventorySuggestResponse),
+        (status = 400, description = "Invalid query", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 403, description = "API key lacks the read scope", body = ErrorResponse),
+    ),
+    security(("api_key" = []))
+)]
+pub async fn handle_inventory_suggest(
+    config: Arc<Config>,
+    cache: Arc<InventoryCache>,
+    query: Result<Query<InventorySuggestQuery>, QueryRejection>,
+) -> Result<Json<InventorySuggestResponse>, WebError> {
+    let Query(query) = query.map_err(|e| WebError::bad_request(format!("Invalid query: {}", e)))?;
+    if config.inventory.is_empty() {
+        return Ok(Json(InventorySuggestResponse { suggestions: Vec::new() }));
+    }
+
+    let inventory = cache.get(&config).await?;
+    let suggestions = inventory.suggest(&query.q, query.limit.clamp(1, MAX_SUGGESTIONS));
+    Ok(Json(InventorySuggestResponse { suggestions }))
+}
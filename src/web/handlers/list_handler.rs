@@ -0,0 +1,119 @@
+use axum::extract::{rejection::QueryRejection, Query};
+use axum::Json;
+use chrono::{DateTime, Utc};
+use std::sync::Arc;
+use tracing::info;
+
+use crate::config::Config;
+use crate::store::{CertStore, IssuedCert};
+
+use super::super::models::{
+    CertListQuery, CertListResponse, CertStatus, CertSummary, ErrorResponse, WebError, MAX_PER_PAGE,
+};
+
+/// List issued certificates from the certificate store
+///
+/// One entry per certificate name (its latest issuance), sorted by name.
+#[utoipa::path(
+    get,
+    path = "/api/certs",
+    tag = "certificates",
+    params(CertListQuery),
+    responses(
+        (status = 200, description = "Matching certificates", body = CertListResponse),
+        (status = 400, description = "Invalid query", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 403, description = "API key lacks the read scope", body = ErrorResponse),
+    ),
+    security(("api_key" = []))
+)]
+pub async fn handle_certificate_list(
+    config: Arc<Config>,
+    query: Result<Query<CertListQuery>, QueryRejection>,
+) -> Result<Json<CertListResponse>, WebError> {
+    let Query(query) = query.map_err(|e| WebError::bad_request(format!("Invalid query: {}", e)))?;
+    if query.page == 0 || query.per_page == 0 || query.per_page > MAX_PER_PAGE {
+        return Err(WebError::bad_request(format!(
+            "page must be at least 1 and per_page between 1 and {}",
+            MAX_PER_PAGE
+        )));
+    }
+
+    let store = CertStore::open_default(&config)
+        .map_err(|e| WebError::internal_error(format!("Failed to read certificate store: {}", e)))?;
+    let now = Utc::now();
+    let warning_days = i64::from(config.check.warning_days.get());
+
+    let matching: Vec<CertSummary> = store.latest_each().into_iter()
+        .filter(|cert| matches(&query, cert, now))
+        .map(|cert| summary(cert, now, warning_days))
+        .collect();
+    let total = matching.len();
+    let certificates = matching.into_iter()
+        .skip((query.page - 1).saturating_mul(query.per_page))
+        .take(query.per_page)
+        .collect();
+
+    info!("Listed {} of {} certificates", total.min(query.per_page), total);
+    Ok(Json(CertListResponse {
+        success: true,
+        certificates,
+        total,
+        page: query.page,
+        per_page: query.per_page,
+    }))
+}
+
+/// Whether a store record passes the query's filters
+fn matches(query: &CertListQuery, cert: &IssuedCert, now: DateTime<Utc>) -> bool {
+    let contains = |haystack: &str, needle: &str| haystack.to_lowercase().contains(&needle.to_lowercase());
+
+    if let Some(cn) = &query.cn {
+        if !contains(&cert.name, cn) && !contains(&cert.subject, cn) {
+            return false;
+        }
+    }
+    if let Some(san) = &query.san {
+        if !cert.sans.iter().any(|s| contains(s, san)) {
+            return false;
+        }
+    }
+    if let Some(revoked) = query.revoked {
+        if cert.is_revoked() != revoked {
+            return false;
+        }
+    }
+    if let Some(days) = query.expiring_within {
+        if cert.is_revoked() || cert.not_after > now + days.as_duration() {
+            return false;
+        }
+    }
+    true
+}
+
+fn summary(cert: &IssuedCert, now: DateTime<Utc>, warning_days: i64) -> CertSummary {
+    let days_remaining = (cert.not_after - now).num_days();
+    let status = if cert.is_revoked() {
+        CertStatus::Revoked
+    } else if cert.not_after < now {
+        CertStatus::Expired
+    } else if days_remaining < warning_days {
+        CertStatus::Expiring
+    } else {
+        CertStatus::Valid
+    };
+
+    CertSummary {
+        name: cert.name.clone(),
+        serial: cert.serial.clone(),
+        subject: cert.subject.clone(),
+        sans: cert.sans.clone(),
+        not_before: cert.not_before,
+        not_after: cert.not_after,
+        issued_at: cert.issued_at,
+        days_remaining,
+        status,
+        revoked_at: cert.revoked_at,
+        revocation_reason: cert.revocation_reason.clone(),
+    }
+}
@@ -1,10 +1,19 @@
 pub mod cert_handler;
+pub mod csr_batch_handler;
 pub mod csr_handler;
+pub mod download_handler;
 pub mod info_handler;
+pub mod inventory_handler;
+pub mod list_handler;
+pub mod upload;
 
 #[cfg(test)]
 mod tests;
 
 pub use cert_handler::*;
+pub use csr_batch_handler::*;
 pub use csr_handler::*;
+pub use download_handler::*;
 pub use info_handler::*;
+pub use inventory_handler::*;
+pub use list_handler::*;
@@ -1,12 +1,9 @@
 //! Tests for web service handlers
 
 #[cfg(test)]
+#[allow(clippy::module_inception)]
 mod tests {
-    use super::super::*;
-    use crate::config::Config;
     use crate::web::models::{CertificateGenerateRequest, WebError};
-    use axum::Json;
-    use std::sync::Arc;
 
     #[tokio::test]
     async fn test_certificate_generate_request_validation() {
@@ -14,15 +11,18 @@ mod tests {
         let valid_request = CertificateGenerateRequest {
             common_name: "example.com".to_string(),
             sans: vec!["DNS:www.example.com".to_string()],
-            validity_days: 365,
-            key_size: 4096,
-            password_protect: false,
+            validity_days: Some(365),
+            key_size: Some(4096),
+            password_protect: Some(false),
             key_password: None,
+            pkcs12: false,
+            pkcs12_password: None,
+            zip: false,
         };
 
         assert_eq!(valid_request.common_name, "example.com");
-        assert_eq!(valid_request.key_size, 4096);
-        assert_eq!(valid_request.validity_days, 365);
+        assert_eq!(valid_request.key_size, Some(4096));
+        assert_eq!(valid_request.validity_days, Some(365));
     }
 
     #[tokio::test]
@@ -31,14 +31,17 @@ mod tests {
         let invalid_request = CertificateGenerateRequest {
             common_name: "example.com".to_string(),
             sans: vec![],
-            validity_days: 365,
-            key_size: 1024, // Invalid
-            password_protect: false,
+            validity_days: Some(365),
+            key_size: Some(1024), // Invalid
+            password_protect: Some(false),
             key_password: None,
+            pkcs12: false,
+            pkcs12_password: None,
+            zip: false,
         };
 
         // The handler should reject this
-        assert_eq!(invalid_request.key_size, 1024);
+        assert_eq!(invalid_request.key_size, Some(1024));
     }
 
     #[tokio::test]
@@ -46,13 +49,16 @@ mod tests {
         let invalid_request = CertificateGenerateRequest {
             common_name: "example.com".to_string(),
             sans: vec![],
-            validity_days: 1000, // Too long (max 825)
-            key_size: 4096,
-            password_protect: false,
+            validity_days: Some(1000), // Too long (max 825)
+            key_size: Some(4096),
+            password_protect: Some(false),
             key_password: None,
+            pkcs12: false,
+            pkcs12_password: None,
+            zip: false,
         };
 
-        assert!(invalid_request.validity_days > 825);
+        assert!(invalid_request.validity_days > Some(825));
     }
 
     #[tokio::test]
@@ -61,26 +67,32 @@ mod tests {
         let invalid = CertificateGenerateRequest {
             common_name: "example.com".to_string(),
             sans: vec![],
-            validity_days: 365,
-            key_size: 4096,
-            password_protect: true,
+            validity_days: Some(365),
+            key_size: Some(4096),
+            password_protect: Some(true),
             key_password: None, // Missing password
+            pkcs12: false,
+            pkcs12_password: None,
+            zip: false,
         };
 
-        assert!(invalid.password_protect);
+        assert_eq!(invalid.password_protect, Some(true));
         assert!(invalid.key_password.is_none());
 
         // Should be valid: password provided
         let valid = CertificateGenerateRequest {
             common_name: "example.com".to_string(),
             sans: vec![],
-            validity_days: 365,
-            key_size: 4096,
-            password_protect: true,
+            validity_days: Some(365),
+            key_size: Some(4096),
+            password_protect: Some(true),
             key_password: Some("secure_password".to_string()),
+            pkcs12: false,
+            pkcs12_password: None,
+            zip: false,
         };
 
-        assert!(valid.password_protect);
+        assert_eq!(valid.password_protect, Some(true));
         assert!(valid.key_password.is_some());
     }
 
@@ -93,10 +105,13 @@ mod tests {
                 "DNS:mail.example.com".to_string(),
                 "IP:192.168.1.100".to_string(),
             ],
-            validity_days: 365,
-            key_size: 4096,
-            password_protect: false,
+            validity_days: Some(365),
+            key_size: Some(4096),
+            password_protect: Some(false),
             key_password: None,
+            pkcs12: false,
+            pkcs12_password: None,
+            zip: false,
         };
 
         assert_eq!(request.sans.len(), 3);
@@ -136,9 +151,758 @@ mod tests {
         let invalid_low = 0;
         let invalid_high = 826;
 
-        assert!(min_days >= 1 && min_days <= 825);
-        assert!(max_days >= 1 && max_days <= 825);
+        assert!((1..=825).contains(&min_days));
+        assert!((1..=825).contains(&max_days));
         assert!(invalid_low < 1);
         assert!(invalid_high > 825);
     }
+
+    #[tokio::test]
+    async fn test_oversized_upload_rejected() {
+        use axum::body::Body;
+        use axum::http::{Request, StatusCode};
+        use std::sync::Arc;
+        use tower::ServiceExt;
+
+        let mut config = crate::config::Config::default();
+        config.limits.max_upload_size = 1024;
+        let app = crate::web::routes::create_router(Arc::new(config));
+
+        let boundary = "flux-test-boundary";
+        let body = format!(
+            "--{b}\r\nContent-Disposition: form-data; name=\"cert_file\"; filename=\"big.pem\"\r\n\r\n{data}\r\n--{b}--\r\n",
+            b = boundary,
+            data = "A".repeat(4096),
+        );
+        let request = Request::post("/api/cert/info")
+            .header("content-type", format!("multipart/form-data; boundary={}", boundary))
+            .body(Body::from(body))
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[tokio::test]
+    async fn test_body_limit_is_file_too_large() {
+        use axum::body::{to_bytes, Body};
+        use axum::http::{Request, StatusCode};
+        use std::sync::Arc;
+        use tower::ServiceExt;
+
+        let mut config = crate::config::Config::default();
+        config.web.max_body_size = Some(2048);
+        config.web.allow_unauthenticated = true;
+        assert_eq!(config.web_upload_limit(), 5 * 1024 * 1024);
+        let app = crate::web::routes::create_router(Arc::new(config));
+
+        let boundary = "flux-test-boundary";
+        let multipart = format!(
+            "--{b}\r\nContent-Disposition: form-data; name=\"cert_file\"; filename=\"big.pem\"\r\n\r\n{data}\r\n--{b}--\r\n",
+            b = boundary,
+            data = "A".repeat(4096),
+        );
+        let json = format!("{{\"common_name\": \"{}\"}}", "a".repeat(4096));
+        let requests = [
+            Request::post("/api/cert/info")
+                .header("content-type", format!("multipart/form-data; boundary={}", boundary))
+                .body(Body::from(multipart))
+                .unwrap(),
+            Request::post("/api/cert/generate")
+                .header("content-type", "application/json")
+                .body(Body::from(json))
+                .unwrap(),
+        ];
+
+        // Multipart and JSON bodies over the limit fail the same way
+        for request in requests {
+            let response = app.clone().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+            let body: serde_json::Value = serde_json::from_slice(&to_bytes(response.into_body(), usize::MAX).await.unwrap()).unwrap();
+            assert_eq!(body["error"]["code"], "FILE_TOO_LARGE");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_info_verifies_chain() {
+        use crate::ca::CertSigner;
+        use crate::crypto::{cert_to_pem, save_cert_pem};
+        use crate::fixtures;
+        use axum::body::{to_bytes, Body};
+        use axum::http::{Request, StatusCode};
+        use std::sync::Arc;
+        use tower::ServiceExt;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let config = crate::config::Config {
+            working_dir: temp_dir.path().to_path_buf(),
+            ca_cert_path: temp_dir.path().join("ca.cert.pem"),
+            ..Default::default()
+        };
+        let ca = fixtures::intermediate_ca();
+        save_cert_pem(ca.cert(), &config.ca_cert_path).unwrap();
+        let leaf = ca.sign(&fixtures::leaf_csr("nas01.lab", &[]), 30).unwrap();
+        let app = crate::web::routes::create_router(Arc::new(config));
+
+        let boundary = "flux-test-boundary";
+        let info = |cert: &openssl::x509::X509| {
+            let body = format!(
+                "--{b}\r\nContent-Disposition: form-data; name=\"cert_file\"; filename=\"cert.pem\"\r\n\r\n{pem}\r\n\
+                 --{b}\r\nContent-Disposition: form-data; name=\"verify_chain\"\r\n\r\ntrue\r\n--{b}--\r\n",
+                b = boundary,
+                pem = String::from_utf8(cert_to_pem(cert).unwrap()).unwrap(),
+            );
+            Request::post("/api/cert/info")
+                .header("content-type", format!("multipart/form-data; boundary={}", boundary))
+                .body(Body::from(body))
+                .unwrap()
+        };
+
+        let response = app.clone().oneshot(info(&leaf)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(&to_bytes(response.into_body(), usize::MAX).await.unwrap()).unwrap();
+        let chain = &body["certificate"]["chain_verification"];
+        assert_eq!(chain["trusted"], true);
+        assert_eq!(chain["depth"], 2);
+        assert!(chain.get("error_depth").is_none());
+
+        // The root didn't issue anything this CA trusts
+        let response = app.oneshot(info(&fixtures::root_ca_cert())).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&to_bytes(response.into_body(), usize::MAX).await.unwrap()).unwrap();
+        let chain = &body["certificate"]["chain_verification"];
+        assert_eq!(chain["trusted"], false);
+        assert_eq!(chain["error_depth"], 0);
+        assert!(chain["error"].is_string());
+    }
+
+    #[tokio::test]
+    async fn test_csr_upload_with_enrollment_token() {
+        use crate::apikey::{Scope, TokenFile};
+        use crate::crypto::{cert_from_pem, key_to_pem, save_cert_pem, SanEntry};
+        use crate::fixtures;
+        use axum::body::{to_bytes, Body};
+        use axum::http::{Request, StatusCode};
+        use std::collections::BTreeSet;
+        use std::sync::Arc;
+        use tower::ServiceExt;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let config = crate::config::Config {
+            working_dir: temp_dir.path().to_path_buf(),
+            ca_cert_path: temp_dir.path().join("ca.cert.pem"),
+            ca_key_path: temp_dir.path().join("ca.key.pem"),
+            ..crate::config::Config::default()
+        };
+        let ca = fixtures::intermediate_ca();
+        save_cert_pem(ca.cert(), &config.ca_cert_path).unwrap();
+        std::fs::write(&config.ca_key_path, key_to_pem(&fixtures::intermediate_key()).unwrap()).unwrap();
+
+        let mut tokens = TokenFile::open(config.web_tokens_path()).unwrap();
+        let admin = tokens.create("admin", BTreeSet::from([Scope::Issue])).unwrap();
+        let ip = SanEntry::Ip("10.0.0.5".to_string());
+        let (token, _) = tokens.create_enrollment("nas01.lab", std::slice::from_ref(&ip), chrono::Duration::hours(1)).unwrap();
+        let (other, _) = tokens.create_enrollment("nas02.lab", &[], chrono::Duration::hours(1)).unwrap();
+        tokens.save().unwrap();
+        let audit_path = config.web_audit_path();
+        let config = Arc::new(config);
+        let app = crate::web::routes::create_router(Arc::clone(&config));
+
+        let upload_csr = |csr: openssl::x509::X509Req, token: &str| {
+            let pem = String::from_utf8(csr.to_pem().unwrap()).unwrap();
+            let boundary = "flux-test-boundary";
+            let body = format!(
+                "--{b}\r\nContent-Disposition: form-data; name=\"csr_file\"; filename=\"a.csr\"\r\n\r\n{pem}\r\n--{b}--\r\n",
+                b = boundary,
+            );
+            Request::post("/api/csr/upload")
+                .header("content-type", format!("multipart/form-data; boundary={}", boundary))
+                .header("authorization", format!("Bearer {}", token))
+                .body(Body::from(body))
+                .unwrap()
+        };
+        let upload = |name: &str, sans: &[SanEntry], token: &str| upload_csr(fixtures::leaf_csr(name, sans), token);
+        let sans = [SanEntry::Dns("nas01.lab".to_string()), ip];
+
+        // Outside the enrollment: rejected, and the token stays usable
+        let response = app.clone().oneshot(upload("router.lab", &[], &token)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        // Within the enrollment but refused at signing (a 1024-bit key): still usable
+        let weak_key = crate::crypto::generate_rsa_key(1024, None).unwrap();
+        let weak = crate::crypto::create_csr("nas01.lab", &weak_key, &sans, None).unwrap();
+        let response = app.clone().oneshot(upload_csr(weak, &token)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let response = app.clone().oneshot(upload("nas01.lab", &sans, &token)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(&to_bytes(response.into_body(), usize::MAX).await.unwrap()).unwrap();
+        let cert = cert_from_pem(body["certificate"]["pem"].as_str().unwrap().as_bytes()).unwrap();
+        assert_eq!(cert.subject_alt_names().unwrap().len(), 2);
+        let serial = body["certificate"]["serial"].as_str().unwrap().to_string();
+
+        // Single use; other enrollments and API keys aren't affected
+        let response = app.clone().oneshot(upload("nas01.lab", &sans, &token)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = app.clone().oneshot(upload("nas02.lab", &[], &other)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = app.oneshot(upload("router.lab", &[], &admin)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // Every attempt is in the audit log, with who made it
+        let entries: Vec<crate::web::audit::AuditEntry> = std::fs::read_to_string(audit_path).unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let summary: Vec<(u16, Option<&str>)> = entries.iter().map(|e| (e.status, e.identity.as_deref())).collect();
+        assert_eq!(summary, [
+            (403, Some("enrollment:nas01.lab")),
+            (403, Some("enrollment:nas01.lab")),
+            (200, Some("enrollment:nas01.lab")),
+            (401, None),
+            (200, Some("enrollment:nas02.lab")),
+            (200, Some("admin")),
+        ]);
+        assert_eq!(entries[0].names, ["router.lab"]);
+        assert!(entries[0].serials.is_empty());
+        assert_eq!(entries[2].names, ["nas01.lab"]);
+        assert_eq!(entries[2].serials, std::slice::from_ref(&serial));
+        assert_eq!(entries[2].path, "/api/csr/upload");
+
+        // Signed certificates are in the store, with a copy of each
+        let store = crate::store::CertStore::open_default(&config).unwrap();
+        let recorded: Vec<&str> = store.certificates().iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(recorded, ["nas01.lab", "nas02.lab", "router.lab"]);
+        assert_eq!(store.certificates()[0].serial, serial);
+        assert!(store.certificates()[0].cert_path.starts_with(config.web_certs_dir()));
+        assert!(store.certificates()[0].cert_path.exists());
+    }
+
+    #[tokio::test]
+    async fn test_certificate_list() {
+        use crate::store::{update_store, IssuedCert};
+        use axum::body::{to_bytes, Body};
+        use axum::http::{Request, StatusCode};
+        use chrono::{Duration, Utc};
+        use std::sync::Arc;
+        use tower::ServiceExt;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let config = crate::config::Config {
+            working_dir: temp_dir.path().to_path_buf(),
+            ..crate::config::Config::default()
+        };
+        let issued = |name: &str, sans: &[&str], days: i64| IssuedCert {
+            name: name.to_string(),
+            serial: "01".to_string(),
+            subject: format!("CN={}", name),
+            sans: sans.iter().map(|s| s.to_string()).collect(),
+            not_before: Utc::now() - Duration::days(1),
+            not_after: Utc::now() + Duration::days(days),
+            issued_at: Utc::now(),
+            cert_path: format!("/tmp/{}.cert.pem", name).into(),
+            key_path: None,
+            key_encrypted: false,
+            revoked_at: None,
+            revocation_reason: None,
+            endpoints: Vec::new(),
+        };
+        update_store(&config, "test", |store| {
+            store.record(issued("nas01.lab", &["DNS:nas01.lab", "IP:10.0.0.5"], 300));
+            store.record(issued("nas02.lab", &["DNS:nas02.lab"], 10));
+            store.record(issued("router.lab", &["DNS:router.lab"], 300));
+            store.revoke("router.lab", "superseded");
+        })
+        .unwrap();
+        let app = crate::web::routes::create_router(Arc::new(config));
+
+        let list = |query: &str| {
+            let app = app.clone();
+            let request = Request::get(format!("/api/certs{}", query)).body(Body::empty()).unwrap();
+            async move {
+                let response = app.oneshot(request).await.unwrap();
+                let status = response.status();
+                let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+                (status, serde_json::from_slice::<serde_json::Value>(&body).unwrap())
+            }
+        };
+        let names = |body: &serde_json::Value| -> Vec<String> {
+            body["certificates"].as_array().unwrap().iter().map(|c| c["name"].as_str().unwrap().to_string()).collect()
+        };
+
+        let (status, body) = list("").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(names(&body), ["nas01.lab", "nas02.lab", "router.lab"]);
+        assert_eq!(body["certificates"][1]["status"], "expiring");
+        assert_eq!(body["certificates"][2]["status"], "revoked");
+
+        assert_eq!(names(&list("?cn=NAS").await.1), ["nas01.lab", "nas02.lab"]);
+        assert_eq!(names(&list("?san=10.0.0.5").await.1), ["nas01.lab"]);
+        assert_eq!(names(&list("?expiring_within=2w").await.1), ["nas02.lab"]);
+        assert_eq!(names(&list("?revoked=false").await.1), ["nas01.lab", "nas02.lab"]);
+        assert_eq!(names(&list("?revoked=true").await.1), ["router.lab"]);
+
+        let (_, page) = list("?per_page=2&page=2").await;
+        assert_eq!(names(&page), ["router.lab"]);
+        assert_eq!(page["total"], 3);
+
+        assert_eq!(list("?per_page=0").await.0, StatusCode::BAD_REQUEST);
+        assert_eq!(list("?expiring_within=soon").await.0, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_bundle_downloads() {
+        use crate::ca::CertSigner;
+        use crate::crypto::{key_to_pem, save_cert_pem, SanEntry};
+        use crate::fixtures;
+        use crate::store::{update_store, IssuedCert};
+        use axum::body::{to_bytes, Body};
+        use axum::http::{Request, StatusCode};
+        use openssl::pkcs12::Pkcs12;
+        use std::sync::Arc;
+        use tower::ServiceExt;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut config = crate::config::Config {
+            working_dir: temp_dir.path().to_path_buf(),
+            ca_cert_path: temp_dir.path().join("ca.cert.pem"),
+            ca_key_path: temp_dir.path().join("ca.key.pem"),
+            ..crate::config::Config::default()
+        };
+        config.web.allow_unauthenticated = true;
+        let ca = fixtures::intermediate_ca();
+        save_cert_pem(ca.cert(), &config.ca_cert_path).unwrap();
+        std::fs::write(&config.ca_key_path, key_to_pem(&fixtures::intermediate_key()).unwrap()).unwrap();
+
+        let cert = ca.sign(&fixtures::leaf_csr("nas01.lab", &[SanEntry::Dns("nas01.lab".to_string())]), 30).unwrap();
+        let (cert_path, key_path) = (temp_dir.path().join("nas01.cert.pem"), temp_dir.path().join("nas01.key.pem"));
+        save_cert_pem(&cert, &cert_path).unwrap();
+        std::fs::write(&key_path, key_to_pem(&fixtures::leaf_key()).unwrap()).unwrap();
+        let record = IssuedCert::from_cert("nas01.lab", &cert, &cert_path, Some(&key_path)).unwrap();
+        let serial = record.serial.clone();
+        update_store(&config, "test", |store| store.record(record)).unwrap();
+        let app = crate::web::routes::create_router(Arc::new(config));
+
+        // Generated along with the certificate, protected with pkcs12_password
+        let request = Request::post("/api/cert/generate")
+            .header("content-type", "application/json")
+            .body(Body::from(r#"{"common_name": "cam01.lab", "key_size": 2048, "pkcs12": true, "pkcs12_password": "camera", "zip": true}"#))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(&to_bytes(response.into_body(), usize::MAX).await.unwrap()).unwrap();
+        assert!(body["certificate"]["pkcs12_password"].is_null());
+        let der = openssl::base64::decode_block(body["certificate"]["pkcs12"].as_str().unwrap()).unwrap();
+        let parsed = Pkcs12::from_der(&der).unwrap().parse2("camera").unwrap();
+        assert_eq!(parsed.ca.unwrap().len(), 1);
+        let zip = openssl::base64::decode_block(body["certificate"]["zip"].as_str().unwrap()).unwrap();
+        let archive = zip::ZipArchive::new(std::io::Cursor::new(zip)).unwrap();
+        assert!(archive.file_names().any(|name| name == "cam01.lab/privkey.pem"));
+        let generated_serial = body["certificate"]["serial"].as_str().unwrap().to_string();
+
+        let download = |path: String, password: Option<&str>| {
+            let mut request = Request::get(path);
+            if let Some(password) = password {
+                request = request.header("x-pkcs12-password", password);
+            }
+            app.clone().oneshot(request.body(Body::empty()).unwrap())
+        };
+
+        // Stored certificate by serial, with a generated password
+        let response = download(format!("/api/certs/{}/download?format=pkcs12", serial.to_lowercase()), None).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["content-type"], "application/x-pkcs12");
+        assert_eq!(response.headers()["content-disposition"], "attachment; filename=\"nas01.lab.p12\"");
+        let password = response.headers()["x-pkcs12-password"].to_str().unwrap().to_string();
+        let der = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let parsed = Pkcs12::from_der(&der).unwrap().parse2(&password).unwrap();
+        assert_eq!(parsed.cert.unwrap().to_der().unwrap(), cert.to_der().unwrap());
+        assert!(parsed.pkey.unwrap().public_eq(&fixtures::leaf_key()));
+
+        let response = download(format!("/api/certs/{}/download?format=pkcs12", serial), Some("nas")).await.unwrap();
+        assert!(!response.headers().contains_key("x-pkcs12-password"));
+        Pkcs12::from_der(&to_bytes(response.into_body(), usize::MAX).await.unwrap()).unwrap().parse2("nas").unwrap();
+
+        let response = download(format!("/api/certs/{}/download", serial), None).await.unwrap();
+        assert_eq!(response.headers()["content-type"], "application/x-pem-file");
+
+        let response = download(format!("/api/certs/{}/download?format=zip", serial), None).await.unwrap();
+        assert_eq!(response.headers()["content-disposition"], "attachment; filename=\"nas01.lab.zip\"");
+        let zip = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let mut archive = zip::ZipArchive::new(std::io::Cursor::new(zip.to_vec())).unwrap();
+        let mut key = String::new();
+        std::io::Read::read_to_string(&mut archive.by_name("nas01.lab/privkey.pem").unwrap(), &mut key).unwrap();
+        assert_eq!(key.as_bytes(), key_to_pem(&fixtures::leaf_key()).unwrap().as_slice());
+        assert_eq!(download("/api/certs/ABCDEF/download".to_string(), None).await.unwrap().status(), StatusCode::NOT_FOUND);
+
+        // The generated certificate is listed and downloadable too, without its key
+        let response = app.clone().oneshot(Request::get("/api/certs").body(Body::empty()).unwrap()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&to_bytes(response.into_body(), usize::MAX).await.unwrap()).unwrap();
+        let listed: Vec<&str> = body["certificates"].as_array().unwrap().iter().map(|c| c["name"].as_str().unwrap()).collect();
+        assert_eq!(listed, ["cam01.lab", "nas01.lab"]);
+        let response = download(format!("/api/certs/{}/download", generated_serial), None).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(download(format!("/api/certs/{}/download?format=pkcs12", generated_serial), None).await.unwrap().status(), StatusCode::NOT_FOUND);
+        assert_eq!(
+            download(format!("/api/certs/{}/download?format=jks", serial), None).await.unwrap().status(),
+            StatusCode::BAD_REQUEST
+        );
+    }
+
+    #[tokio::test]
+    async fn test_csr_batch_upload() {
+        use crate::crypto::{key_to_pem, save_cert_pem, SanEntry};
+        use crate::fixtures;
+        use axum::body::{to_bytes, Body};
+        use axum::http::{Request, StatusCode};
+        use std::io::Write;
+        use std::sync::Arc;
+        use tower::ServiceExt;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut config = crate::config::Config {
+            working_dir: temp_dir.path().to_path_buf(),
+            ca_cert_path: temp_dir.path().join("ca.cert.pem"),
+            ca_key_path: temp_dir.path().join("ca.key.pem"),
+            ..crate::config::Config::default()
+        };
+        config.web.allow_unauthenticated = true;
+        let ca = fixtures::intermediate_ca();
+        save_cert_pem(ca.cert(), &config.ca_cert_path).unwrap();
+        std::fs::write(&config.ca_key_path, key_to_pem(&fixtures::intermediate_key()).unwrap()).unwrap();
+        let app = crate::web::routes::create_router(Arc::new(config));
+
+        let csr = |name: &str| fixtures::leaf_csr(name, &[SanEntry::Dns(name.to_string())]).to_pem().unwrap();
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        for name in ["cam01.lab", "cam02.lab"] {
+            zip.start_file(format!("cameras/{}.csr", name), zip::write::SimpleFileOptions::default()).unwrap();
+            zip.write_all(&csr(name)).unwrap();
+        }
+        zip.start_file("cameras/notes.txt", zip::write::SimpleFileOptions::default()).unwrap();
+        let zip = zip.finish().unwrap().into_inner();
+
+        let boundary = "flux-test-boundary";
+        let mut body = Vec::new();
+        for (file, data) in [("nas01.csr", csr("nas01.lab")), ("broken.csr", b"not a csr".to_vec()), ("cameras.zip", zip)] {
+            write!(body, "--{}\r\nContent-Disposition: form-data; name=\"csr_files\"; filename=\"{}\"\r\n\r\n", boundary, file).unwrap();
+            body.extend_from_slice(&data);
+            body.extend_from_slice(b"\r\n");
+        }
+        write!(body, "--{}\r\nContent-Disposition: form-data; name=\"sans\"\r\n\r\nIP:10.0.0.5\r\n--{}--\r\n", boundary, boundary).unwrap();
+        let request = Request::post("/api/csr/upload-batch")
+            .header("content-type", format!("multipart/form-data; boundary={}", boundary))
+            .body(Body::from(body))
+            .unwrap();
+
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(&to_bytes(response.into_body(), usize::MAX).await.unwrap()).unwrap();
+        assert_eq!((body["successful"].as_u64(), body["failed"].as_u64()), (Some(3), Some(1)));
+
+        let results = body["results"].as_array().unwrap();
+        let files: Vec<&str> = results.iter().map(|r| r["file"].as_str().unwrap()).collect();
+        assert_eq!(files, ["nas01.csr", "broken.csr", "cameras.zip/cameras/cam01.lab.csr", "cameras.zip/cameras/cam02.lab.csr"]);
+        assert!(results[1]["error"].as_str().unwrap().contains("Failed to parse CSR"));
+        // The shared SANs are added to each certificate
+        assert!(results[3]["certificate"]["sans"].as_array().unwrap().iter().any(|san| san.as_str().unwrap().contains("10.0.0.5")));
+
+        // Every signed certificate is in the listing
+        let response = app.oneshot(Request::get("/api/certs").body(Body::empty()).unwrap()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&to_bytes(response.into_body(), usize::MAX).await.unwrap()).unwrap();
+        let listed: Vec<&str> = body["certificates"].as_array().unwrap().iter().map(|c| c["name"].as_str().unwrap()).collect();
+        assert_eq!(listed, ["cam01.lab", "cam02.lab", "nas01.lab"]);
+    }
+
+    #[tokio::test]
+    async fn test_inventory_suggest() {
+        use crate::inventory::{InventoryConfig, SourceKind};
+        use axum::body::{to_bytes, Body};
+        use axum::http::{Request, StatusCode};
+        use std::sync::Arc;
+        use tower::ServiceExt;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let hosts = temp_dir.path().join("hosts");
+        std::fs::write(&hosts, "10.0.0.5 nas01\n10.0.0.6 nas02\n10.0.0.1 router\n").unwrap();
+        let config = crate::config::Config {
+            working_dir: temp_dir.path().to_path_buf(),
+            inventory: vec![InventoryConfig { source: SourceKind::File { path: hosts.clone() }, domain: Some("lab".to_string()) }],
+            ..crate::config::Config::default()
+        };
+        let app = crate::web::routes::create_router(Arc::new(config));
+
+        let suggest = |query: &str| {
+            let request = Request::get(format!("/api/inventory/suggest?{}", query)).body(Body::empty()).unwrap();
+            let app = app.clone();
+            async move {
+                let response = app.oneshot(request).await.unwrap();
+                let status = response.status();
+                let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+                (status, serde_json::from_slice::<serde_json::Value>(&body).unwrap_or_default())
+            }
+        };
+
+        let (status, body) = suggest("q=DNS:nas&limit=3").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["suggestions"], serde_json::json!(["DNS:nas01.lab", "DNS:nas02.lab", "IP:10.0.0.5"]));
+
+        // Served from the cache until it expires
+        std::fs::remove_file(&hosts).unwrap();
+        let (_, body) = suggest("q=router").await;
+        assert_eq!(body["suggestions"], serde_json::json!(["DNS:router.lab", "IP:10.0.0.1"]));
+
+        assert_eq!(suggest("limit=many").await.0, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_wildcards_refused_by_policy() {
+        use crate::crypto::{key_to_pem, save_cert_pem};
+        use crate::fixtures;
+        use axum::body::{to_bytes, Body};
+        use axum::http::{Request, StatusCode};
+        use std::sync::Arc;
+        use tower::ServiceExt;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut config = crate::config::Config {
+            working_dir: temp_dir.path().to_path_buf(),
+            ca_cert_path: temp_dir.path().join("ca.cert.pem"),
+            ca_key_path: temp_dir.path().join("ca.key.pem"),
+            ..crate::config::Config::default()
+        };
+        config.web.allow_unauthenticated = true;
+        config.policy.allow_wildcards = false;
+        save_cert_pem(fixtures::intermediate_ca().cert(), &config.ca_cert_path).unwrap();
+        std::fs::write(&config.ca_key_path, key_to_pem(&fixtures::intermediate_key()).unwrap()).unwrap();
+        let app = crate::web::routes::create_router(Arc::new(config));
+
+        let request = Request::post("/api/cert/generate")
+            .header("content-type", "application/json")
+            .body(Body::from(r#"{"common_name": "nas01.lab", "sans": ["DNS:*.nas01.lab"], "key_size": 2048}"#))
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = serde_json::from_slice(&to_bytes(response.into_body(), usize::MAX).await.unwrap()).unwrap();
+        assert_eq!(body["error"]["code"], "INVALID_INPUT");
+        assert!(body["error"]["message"].as_str().unwrap().contains("*.nas01.lab"));
+    }
+
+    #[tokio::test]
+    async fn test_domains_outside_allowlist_refused() {
+        use crate::crypto::{key_to_pem, save_cert_pem};
+        use crate::fixtures;
+        use axum::body::{to_bytes, Body};
+        use axum::http::{Request, StatusCode};
+        use std::sync::Arc;
+        use tower::ServiceExt;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut config = crate::config::Config {
+            working_dir: temp_dir.path().to_path_buf(),
+            ca_cert_path: temp_dir.path().join("ca.cert.pem"),
+            ca_key_path: temp_dir.path().join("ca.key.pem"),
+            ..crate::config::Config::default()
+        };
+        config.web.allow_unauthenticated = true;
+        config.policy.allowed_domains = vec!["*.lab.internal".to_string(), "10.0.0.0/8".to_string()];
+        save_cert_pem(fixtures::intermediate_ca().cert(), &config.ca_cert_path).unwrap();
+        std::fs::write(&config.ca_key_path, key_to_pem(&fixtures::intermediate_key()).unwrap()).unwrap();
+        let app = crate::web::routes::create_router(Arc::new(config));
+
+        let generate = |body: &'static str| Request::post("/api/cert/generate")
+            .header("content-type", "application/json")
+            .body(Body::from(body))
+            .unwrap();
+        let response = app.clone()
+            .oneshot(generate(r#"{"common_name": "nas.lab.internal", "sans": ["IP:10.0.0.5"], "key_size": 2048}"#))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = app
+            .oneshot(generate(r#"{"common_name": "www.example.com", "sans": ["IP:203.0.113.5"], "key_size": 2048}"#))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let body: serde_json::Value = serde_json::from_slice(&to_bytes(response.into_body(), usize::MAX).await.unwrap()).unwrap();
+        let violations = body["error"]["violations"].as_array().unwrap();
+        assert_eq!(violations.len(), 2, "{}", body);
+        assert!(violations.iter().all(|v| v["rule"] == "allowed_domains"), "{}", body);
+    }
+
+    #[tokio::test]
+    async fn test_web_command_defaults() {
+        use crate::crypto::{key_to_pem, save_cert_pem};
+        use crate::duration::Validity;
+        use crate::fixtures;
+        use axum::body::{to_bytes, Body};
+        use axum::http::{Request, StatusCode};
+        use std::sync::Arc;
+        use tower::ServiceExt;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut config = crate::config::Config {
+            working_dir: temp_dir.path().to_path_buf(),
+            ca_cert_path: temp_dir.path().join("ca.cert.pem"),
+            ca_key_path: temp_dir.path().join("ca.key.pem"),
+            ..crate::config::Config::default()
+        };
+        config.web.allow_unauthenticated = true;
+        config.defaults.web.cert_days = Some(Validity::days(30));
+        config.defaults.web.key_size = Some(2048);
+        config.defaults.web.sans = vec!["DNS:monitor.lab".to_string()];
+        save_cert_pem(fixtures::intermediate_ca().cert(), &config.ca_cert_path).unwrap();
+        std::fs::write(&config.ca_key_path, key_to_pem(&fixtures::intermediate_key()).unwrap()).unwrap();
+        let app = crate::web::routes::create_router(Arc::new(config));
+
+        let request = Request::post("/api/cert/generate")
+            .header("content-type", "application/json")
+            .body(Body::from(r#"{"common_name": "nas01.lab"}"#))
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(&to_bytes(response.into_body(), usize::MAX).await.unwrap()).unwrap();
+        let cert = crate::crypto::cert_from_pem(body["certificate"]["pem"].as_str().unwrap().as_bytes()).unwrap();
+        assert_eq!(cert.public_key().unwrap().bits(), 2048);
+        assert_eq!(crate::crypto::days_until_expiration(&cert).unwrap(), 29);
+        let sans: Vec<&str> = body["certificate"]["sans"].as_array().unwrap().iter().map(|s| s.as_str().unwrap()).collect();
+        assert!(sans.contains(&"DNS:monitor.lab"), "{:?}", sans);
+    }
+
+    #[tokio::test]
+    async fn test_csr_sign_ca() {
+        use crate::apikey::{Scope, TokenFile};
+        use crate::crypto::{cert_from_pem, key_to_pem, save_cert_pem};
+        use crate::fixtures;
+        use axum::body::{to_bytes, Body};
+        use axum::http::{Request, StatusCode};
+        use std::collections::BTreeSet;
+        use std::sync::Arc;
+        use tower::ServiceExt;
+
+        // The fixture intermediate has pathlen:0, so sign with the root
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut config = crate::config::Config {
+            working_dir: temp_dir.path().to_path_buf(),
+            ca_cert_path: temp_dir.path().join("ca.cert.pem"),
+            ca_key_path: temp_dir.path().join("ca.key.pem"),
+            ..crate::config::Config::default()
+        };
+        save_cert_pem(&fixtures::root_ca_cert(), &config.ca_cert_path).unwrap();
+        std::fs::write(&config.ca_key_path, key_to_pem(&fixtures::root_key()).unwrap()).unwrap();
+        let mut tokens = TokenFile::open(config.web_tokens_path()).unwrap();
+        let token = tokens.create("admin", BTreeSet::from([Scope::Issue])).unwrap();
+        tokens.save().unwrap();
+
+        let request = |path: &str, fields: &[(&str, &str)]| {
+            let pem = String::from_utf8(fixtures::ca_csr("Guest CA").to_pem().unwrap()).unwrap();
+            let boundary = "flux-test-boundary";
+            let mut body = format!(
+                "--{b}\r\nContent-Disposition: form-data; name=\"csr_file\"; filename=\"a.csr\"\r\n\r\n{pem}\r\n",
+                b = boundary,
+            );
+            for (name, value) in fields {
+                body.push_str(&format!("--{b}\r\nContent-Disposition: form-data; name=\"{name}\"\r\n\r\n{value}\r\n", b = boundary));
+            }
+            body.push_str(&format!("--{}--\r\n", boundary));
+            Request::post(path)
+                .header("content-type", format!("multipart/form-data; boundary={}", boundary))
+                .header("authorization", format!("Bearer {}", token))
+                .body(Body::from(body))
+                .unwrap()
+        };
+
+        // Off unless the policy allows it
+        let app = crate::web::routes::create_router(Arc::new(config.clone()));
+        let response = app.oneshot(request("/api/csr/sign-ca", &[])).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        config.policy.allow_web_sub_ca = true;
+        let app = crate::web::routes::create_router(Arc::new(config));
+        let fields = [("pathlen", "0"), ("permitted", "guest.lab, 10.20.0.0/16")];
+        let response = app.clone().oneshot(request("/api/csr/sign-ca", &fields)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(&to_bytes(response.into_body(), usize::MAX).await.unwrap()).unwrap();
+        let cert = cert_from_pem(body["certificate"]["pem"].as_str().unwrap().as_bytes()).unwrap();
+        assert_eq!(cert.pathlen(), Some(0));
+        let text = String::from_utf8(cert.to_text().unwrap()).unwrap();
+        assert!(text.contains("DNS:guest.lab"), "{}", text);
+
+        let response = app.clone().oneshot(request("/api/csr/sign-ca", &[("permitted", "*.guest.lab")])).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        // The leaf endpoint won't turn a CA CSR into a CA, and says which rule refused it
+        let response = app.oneshot(request("/api/csr/upload", &[])).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let body: serde_json::Value = serde_json::from_slice(&to_bytes(response.into_body(), usize::MAX).await.unwrap()).unwrap();
+        assert_eq!(body["error"]["violations"][0]["rule"], "sub_ca");
+    }
+
+    #[tokio::test]
+    async fn test_csr_sign_ca_only_narrows() {
+        use crate::apikey::{Scope, TokenFile};
+        use crate::crypto::{cert_from_pem, key_to_pem, save_cert_pem};
+        use crate::fixtures;
+        use axum::body::{to_bytes, Body};
+        use axum::http::{Request, StatusCode};
+        use std::collections::BTreeSet;
+        use std::sync::Arc;
+        use tower::ServiceExt;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut config = crate::config::Config {
+            working_dir: temp_dir.path().to_path_buf(),
+            ca_cert_path: temp_dir.path().join("ca.cert.pem"),
+            ca_key_path: temp_dir.path().join("ca.key.pem"),
+            ..crate::config::Config::default()
+        };
+        config.policy.allow_web_sub_ca = true;
+        config.sub_ca.pathlen = 0;
+        config.sub_ca.permitted_dns = vec!["guest.lab".to_string()];
+        config.sub_ca.excluded_dns = vec!["admin.guest.lab".to_string()];
+        save_cert_pem(&fixtures::root_ca_cert(), &config.ca_cert_path).unwrap();
+        std::fs::write(&config.ca_key_path, key_to_pem(&fixtures::root_key()).unwrap()).unwrap();
+        let mut tokens = TokenFile::open(config.web_tokens_path()).unwrap();
+        let token = tokens.create("admin", BTreeSet::from([Scope::Issue])).unwrap();
+        tokens.save().unwrap();
+
+        let request = |fields: &[(&str, &str)]| {
+            let pem = String::from_utf8(fixtures::ca_csr("Guest CA").to_pem().unwrap()).unwrap();
+            let boundary = "flux-test-boundary";
+            let mut body = format!(
+                "--{b}\r\nContent-Disposition: form-data; name=\"csr_file\"; filename=\"a.csr\"\r\n\r\n{pem}\r\n",
+                b = boundary,
+            );
+            for (name, value) in fields {
+                body.push_str(&format!("--{b}\r\nContent-Disposition: form-data; name=\"{name}\"\r\n\r\n{value}\r\n", b = boundary));
+            }
+            body.push_str(&format!("--{}--\r\n", boundary));
+            Request::post("/api/csr/sign-ca")
+                .header("content-type", format!("multipart/form-data; boundary={}", boundary))
+                .header("authorization", format!("Bearer {}", token))
+                .body(Body::from(body))
+                .unwrap()
+        };
+        let app = crate::web::routes::create_router(Arc::new(config));
+
+        // Exclusions add to [sub_ca]; the configured subtrees stay
+        let response = app.clone().oneshot(request(&[("excluded", "iot.guest.lab")])).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(&to_bytes(response.into_body(), usize::MAX).await.unwrap()).unwrap();
+        let cert = cert_from_pem(body["certificate"]["pem"].as_str().unwrap().as_bytes()).unwrap();
+        let text = String::from_utf8(cert.to_text().unwrap()).unwrap();
+        for name in ["DNS:guest.lab", "DNS:admin.guest.lab", "DNS:iot.guest.lab"] {
+            assert!(text.contains(name), "{} missing from {}", name, text);
+        }
+
+        // A longer path length or a wider subtree is refused
+        let response = app.clone().oneshot(request(&[("pathlen", "1")])).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let body: serde_json::Value = serde_json::from_slice(&to_bytes(response.into_body(), usize::MAX).await.unwrap()).unwrap();
+        assert_eq!(body["error"]["violations"][0]["rule"], "pathlen");
+
+        let response = app.oneshot(request(&[("permitted", "lab")])).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let body: serde_json::Value = serde_json::from_slice(&to_bytes(response.into_body(), usize::MAX).await.unwrap()).unwrap();
+        assert_eq!(body["error"]["violations"][0]["rule"], "name_constraints");
+    }
 }
@@ -0,0 +1,52 @@
+//! Bounded reading of multipart upload fields
+
+use axum::extract::multipart::{Field, MultipartError};
+use axum::http::StatusCode;
+
+use super::super::models::WebError;
+
+/// Limit for small text fields (SANs, flags, numbers)
+pub const MAX_TEXT_FIELD_SIZE: usize = 64 * 1024;
+
+/// Error for a multipart stream that couldn't be read
+///
+/// Running into the request body limit is `FILE_TOO_LARGE`, like an oversized
+/// field; anything else is a malformed form.
+pub fn form_error(e: MultipartError, context: &str) -> WebError {
+    if e.status() == StatusCode::PAYLOAD_TOO_LARGE {
+        WebError::file_too_large(format!("{}: {}", context, e.body_text()))
+    } else {
+        WebError::bad_request(format!("{}: {}", context, e))
+    }
+}
+
+/// Read a file field chunk by chunk, failing as soon as it exceeds `limit`
+pub async fn read_field_limited(
+    mut field: Field<'_>,
+    limit: usize,
+    what: &str,
+) -> Result<Vec<u8>, WebError> {
+    let mut data = Vec::new();
+
+    while let Some(chunk) = field
+        .chunk()
+        .await
+        .map_err(|e| form_error(e, &format!("Failed to read {}", what)))?
+    {
+        if data.len() + chunk.len() > limit {
+            return Err(WebError::file_too_large(format!(
+                "{} exceeds the {} byte limit",
+                what, limit
+            )));
+        }
+        data.extend_from_slice(&chunk);
+    }
+
+    Ok(data)
+}
+
+/// Read a small UTF-8 text field
+pub async fn read_text_field(field: Field<'_>, what: &str) -> Result<String, WebError> {
+    let data = read_field_limited(field, MAX_TEXT_FIELD_SIZE, what).await?;
+    String::from_utf8(data).map_err(|_| WebError::bad_request(format!("{} is not valid UTF-8", what)))
+}
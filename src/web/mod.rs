@@ -4,9 +4,24 @@
 //! - CSR upload and signing
 //! - Manual certificate generation
 //! - Certificate information display
+//! - PKCS#12 and ZIP bundle downloads
+//! - CRL downloads for the CRL distribution point
+//!
+//! `/api/*` requires an API key with the right scope once any key exists, and
+//! the signing endpoints are rate limited per client address and per key.
+//! Other origins may call the API as allowed by `[web.cors]`. Signing,
+//! download and info requests are recorded in an append-only audit log.
 
+pub mod assets;
+pub mod audit;
+pub mod auth;
+pub mod bundle;
+pub mod cors;
+pub mod crl;
 pub mod handlers;
 pub mod models;
+pub mod openapi;
+pub mod ratelimit;
 pub mod routes;
 pub mod server;
 
@@ -1,16 +1,26 @@
 use axum::{
-    http::StatusCode,
+    http::{header, HeaderValue, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 use std::fmt;
+use crate::policy::{Violation, Violations};
 
 /// API error codes
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub enum ErrorCode {
     #[serde(rename = "BAD_REQUEST")]
     BadRequest,
+    #[serde(rename = "UNAUTHORIZED")]
+    Unauthorized,
+    #[serde(rename = "FORBIDDEN")]
+    Forbidden,
+    #[serde(rename = "NOT_FOUND")]
+    NotFound,
+    #[serde(rename = "RATE_LIMITED")]
+    RateLimited,
     #[serde(rename = "INVALID_CSR")]
     InvalidCsr,
     #[serde(rename = "INVALID_INPUT")]
@@ -35,6 +45,10 @@ impl fmt::Display for ErrorCode {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             ErrorCode::BadRequest => write!(f, "BAD_REQUEST"),
+            ErrorCode::Unauthorized => write!(f, "UNAUTHORIZED"),
+            ErrorCode::Forbidden => write!(f, "FORBIDDEN"),
+            ErrorCode::NotFound => write!(f, "NOT_FOUND"),
+            ErrorCode::RateLimited => write!(f, "RATE_LIMITED"),
             ErrorCode::InvalidCsr => write!(f, "INVALID_CSR"),
             ErrorCode::InvalidInput => write!(f, "INVALID_INPUT"),
             ErrorCode::InvalidCertificate => write!(f, "INVALID_CERTIFICATE"),
@@ -49,16 +63,19 @@ impl fmt::Display for ErrorCode {
 }
 
 /// Error detail structure
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct ErrorDetail {
     pub code: ErrorCode,
     pub message: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub details: Option<String>,
+    /// Every signing policy rule the request broke
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub violations: Vec<Violation>,
 }
 
 /// API error response
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct ErrorResponse {
     pub success: bool,
     pub error: ErrorDetail,
@@ -72,6 +89,7 @@ impl ErrorResponse {
                 code,
                 message: message.into(),
                 details: None,
+                violations: Vec::new(),
             },
         }
     }
@@ -87,6 +105,7 @@ impl ErrorResponse {
                 code,
                 message: message.into(),
                 details: Some(details.into()),
+                violations: Vec::new(),
             },
         }
     }
@@ -123,6 +142,29 @@ impl WebError {
         Self::new(StatusCode::BAD_REQUEST, ErrorCode::BadRequest, message)
     }
 
+    pub fn unauthorized(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::UNAUTHORIZED, ErrorCode::Unauthorized, message)
+    }
+
+    pub fn forbidden(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::FORBIDDEN, ErrorCode::Forbidden, message)
+    }
+
+    /// 403 listing every policy rule the CSR broke
+    pub fn policy_violation(violations: Violations) -> Self {
+        let mut error = Self::forbidden(format!("Policy violation: {}", violations));
+        error.response.error.violations = violations.into_vec();
+        error
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::NOT_FOUND, ErrorCode::NotFound, message)
+    }
+
+    pub fn rate_limited(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::TOO_MANY_REQUESTS, ErrorCode::RateLimited, message)
+    }
+
     pub fn invalid_csr(message: impl Into<String>) -> Self {
         Self::new(StatusCode::BAD_REQUEST, ErrorCode::InvalidCsr, message)
     }
@@ -195,7 +237,12 @@ impl WebError {
 
 impl IntoResponse for WebError {
     fn into_response(self) -> Response {
-        (self.status, Json(self.response)).into_response()
+        let unauthorized = self.status == StatusCode::UNAUTHORIZED;
+        let mut response = (self.status, Json(self.response)).into_response();
+        if unauthorized {
+            response.headers_mut().insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+        }
+        response
     }
 }
 
@@ -220,7 +267,10 @@ impl From<crate::error::FluxError> for WebError {
             FluxError::CaKeyNotFound(_) | FluxError::CaCertNotFound(_) => {
                 WebError::ca_error(err.to_string())
             }
-            FluxError::InvalidSanFormat(_) => WebError::invalid_input(err.to_string()),
+            FluxError::InvalidSanFormat(_) | FluxError::WildcardNotAllowed(_) => {
+                WebError::invalid_input(err.to_string())
+            }
+            FluxError::PolicyViolation(violations) => WebError::policy_violation(violations),
             FluxError::OpenSslError(_) => WebError::signing_failed(err.to_string()),
             FluxError::ConfigError(_) => WebError::internal_error(err.to_string()),
             FluxError::IoError(_) => WebError::internal_error(err.to_string()),
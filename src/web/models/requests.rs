@@ -1,8 +1,10 @@
+use crate::duration::{deserialize_days, deserialize_optional_days, Days};
 use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
 use validator::Validate;
 
 /// Request to generate a certificate from manual input
-#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+#[derive(Debug, Clone, Serialize, Deserialize, Validate, ToSchema)]
 pub struct CertificateGenerateRequest {
     /// Common Name for the certificate
     #[validate(length(min = 1, max = 64))]
@@ -12,33 +14,41 @@ pub struct CertificateGenerateRequest {
     #[serde(default)]
     pub sans: Vec<String>,
 
-    /// Validity period in days
+    /// Validity period in days (default: `[defaults.web]`, then `[defaults]`)
     #[validate(range(min = 1, max = 825))]
-    #[serde(default = "default_validity_days")]
-    pub validity_days: u32,
+    #[serde(default, deserialize_with = "deserialize_optional_days")]
+    pub validity_days: Option<u32>,
 
-    /// RSA key size in bits
+    /// RSA key size in bits (default: `[defaults.web]`, then `[defaults]`)
     #[validate(custom(function = "validate_key_size"))]
-    #[serde(default = "default_key_size")]
-    pub key_size: u32,
+    #[serde(default)]
+    pub key_size: Option<u32>,
 
-    /// Whether to password-protect the private key
+    /// Whether to password-protect the private key (default: `[defaults.web]`, else no)
     #[serde(default)]
-    pub password_protect: bool,
+    pub password_protect: Option<bool>,
 
     /// Password for the private key (if password_protect is true)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub key_password: Option<String>,
+
+    /// Also return the certificate, key and CA chain as a PKCS#12 bundle
+    #[serde(default)]
+    pub pkcs12: bool,
+
+    /// Password of the PKCS#12 bundle (default: key_password, else a generated one)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pkcs12_password: Option<String>,
+
+    /// Also return a ZIP of the certificate, key, chain and install notes
+    #[serde(default)]
+    pub zip: bool,
 }
 
 fn default_validity_days() -> u32 {
     375
 }
 
-fn default_key_size() -> u32 {
-    4096
-}
-
 fn validate_key_size(key_size: u32) -> Result<(), validator::ValidationError> {
     if key_size == 2048 || key_size == 4096 {
         Ok(())
@@ -48,25 +58,167 @@ fn validate_key_size(key_size: u32) -> Result<(), validator::ValidationError> {
 }
 
 /// Request metadata for CSR upload (from form data)
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct CsrUploadMetadata {
     /// Additional SANs to add to the certificate
     #[serde(default)]
     pub sans: Vec<String>,
 
     /// Validity period in days
-    #[serde(default = "default_validity_days")]
+    #[serde(default = "default_validity_days", deserialize_with = "deserialize_days")]
     pub validity_days: u32,
 }
 
+/// Multipart form of `POST /api/csr/upload` (for the OpenAPI document)
+#[derive(ToSchema)]
+pub struct CsrUploadForm {
+    /// PEM-encoded certificate signing request
+    #[schema(value_type = String, format = Binary)]
+    pub csr_file: Vec<u8>,
+
+    /// Additional SANs, comma-separated (e.g. `DNS:nas.lab,IP:10.0.0.5`)
+    pub sans: Option<String>,
+
+    /// Validity in days, or e.g. `1y`
+    pub validity_days: Option<String>,
+}
+
+/// Multipart form of `POST /api/csr/sign-ca` (for the OpenAPI document)
+#[derive(ToSchema)]
+pub struct CsrSignCaForm {
+    /// PEM-encoded certificate signing request of the subordinate CA
+    #[schema(value_type = String, format = Binary)]
+    pub csr_file: Vec<u8>,
+
+    /// Validity in days, or e.g. `1y`
+    pub validity_days: Option<String>,
+
+    /// basicConstraints path length, at most `sub_ca.pathlen` (the default)
+    pub pathlen: Option<u32>,
+
+    /// Permitted subtrees, comma-separated (e.g. `guest.lab,10.20.0.0/16`);
+    /// each must fall within a `[sub_ca]` permitted subtree
+    pub permitted: Option<String>,
+
+    /// Excluded subtrees, comma-separated, added to the `[sub_ca]` exclusions
+    pub excluded: Option<String>,
+}
+
+/// Multipart form of `POST /api/csr/upload-batch` (for the OpenAPI document)
+#[derive(ToSchema)]
+pub struct CsrBatchUploadForm {
+    /// PEM-encoded CSRs, one per `csr_files` part; a ZIP archive of `.csr`
+    /// and `.pem` files is unpacked
+    #[schema(value_type = Vec<String>, format = Binary)]
+    pub csr_files: Vec<Vec<u8>>,
+
+    /// Additional SANs for every certificate, comma-separated
+    pub sans: Option<String>,
+
+    /// Validity in days, or e.g. `1y`
+    pub validity_days: Option<String>,
+}
+
+/// Multipart form of `POST /api/cert/info` (for the OpenAPI document)
+#[derive(ToSchema)]
+pub struct CertInfoForm {
+    /// PEM or DER certificate; a PEM file may hold a whole chain
+    #[schema(value_type = String, format = Binary)]
+    pub cert_file: Vec<u8>,
+
+    /// `true` to verify the chain against the configured CA
+    pub verify_chain: Option<bool>,
+}
+
 /// Request metadata for certificate info (from form data)
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct CertInfoMetadata {
     /// Whether to verify the certificate chain
     #[serde(default)]
     pub verify_chain: bool,
 }
 
+/// Query of `GET /api/certs`
+#[derive(Debug, Clone, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CertListQuery {
+    /// Substring of the certificate name or subject (case-insensitive)
+    pub cn: Option<String>,
+
+    /// Substring of any SAN, e.g. `10.0.0.5` or `DNS:nas` (case-insensitive)
+    pub san: Option<String>,
+
+    /// Only certificates expiring within this many days (or e.g. `2w`), expired ones included
+    #[param(value_type = Option<String>)]
+    pub expiring_within: Option<Days>,
+
+    /// `true` for revoked certificates only, `false` to leave them out
+    pub revoked: Option<bool>,
+
+    /// Page number, starting at 1
+    #[serde(default = "default_page")]
+    #[param(default = 1, minimum = 1)]
+    pub page: usize,
+
+    /// Certificates per page
+    #[serde(default = "default_per_page")]
+    #[param(default = 50, minimum = 1, maximum = 500)]
+    pub per_page: usize,
+}
+
+fn default_page() -> usize {
+    1
+}
+
+fn default_per_page() -> usize {
+    50
+}
+
+/// Query of `GET /api/inventory/suggest`
+#[derive(Debug, Clone, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct InventorySuggestQuery {
+    /// What was typed so far: part of a hostname or address, optionally with `DNS:` or `IP:`
+    #[serde(default)]
+    pub q: String,
+
+    /// Most suggestions to return
+    #[serde(default = "default_suggestion_limit")]
+    #[param(default = 20, minimum = 1, maximum = 100)]
+    pub limit: usize,
+}
+
+fn default_suggestion_limit() -> usize {
+    20
+}
+
+/// Largest `per_page` of `GET /api/certs`
+pub const MAX_PER_PAGE: usize = 500;
+
+/// Query of `GET /api/certs/{serial}/download`
+#[derive(Debug, Clone, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CertDownloadQuery {
+    /// File format
+    #[serde(default)]
+    pub format: DownloadFormat,
+}
+
+/// Format of a certificate download
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum DownloadFormat {
+    /// Certificate and CA chain, PEM
+    #[default]
+    Pem,
+    /// Certificate only, DER
+    Der,
+    /// Certificate, private key and CA chain, password-protected PKCS#12
+    Pkcs12,
+    /// Certificate, private key (if the CA has it), chain and install notes, ZIP
+    Zip,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -76,10 +228,13 @@ mod tests {
         let req = CertificateGenerateRequest {
             common_name: "example.com".to_string(),
             sans: vec!["DNS:www.example.com".to_string()],
-            validity_days: 375,
-            key_size: 4096,
-            password_protect: false,
+            validity_days: Some(375),
+            key_size: Some(4096),
+            password_protect: None,
             key_password: None,
+            pkcs12: false,
+            pkcs12_password: None,
+            zip: false,
         };
 
         assert!(req.validate().is_ok());
@@ -90,10 +245,13 @@ mod tests {
         let req = CertificateGenerateRequest {
             common_name: "example.com".to_string(),
             sans: vec![],
-            validity_days: 375,
-            key_size: 1024, // Invalid
-            password_protect: false,
+            validity_days: Some(375),
+            key_size: Some(1024), // Invalid
+            password_protect: None,
             key_password: None,
+            pkcs12: false,
+            pkcs12_password: None,
+            zip: false,
         };
 
         assert!(req.validate().is_err());
@@ -104,10 +262,13 @@ mod tests {
         let req = CertificateGenerateRequest {
             common_name: "example.com".to_string(),
             sans: vec![],
-            validity_days: 1000, // Too long
-            key_size: 4096,
-            password_protect: false,
+            validity_days: Some(1000), // Too long
+            key_size: Some(4096),
+            password_protect: None,
             key_password: None,
+            pkcs12: false,
+            pkcs12_password: None,
+            zip: false,
         };
 
         assert!(req.validate().is_err());
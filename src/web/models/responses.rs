@@ -1,9 +1,14 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use utoipa::ToSchema;
+
+pub use crate::crypto::details::{
+    ChainElementInfo, ChainRelationInfo, ChainVerificationInfo, DetailedCertificateInfo, ExtensionInfo,
+    FingerprintInfo, PublicKeyInfo, RevocationInfo, ValidityInfo,
+};
 
 /// Certificate information in API responses
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct CertificateInfo {
     /// Certificate in PEM format
     pub pem: String,
@@ -29,7 +34,7 @@ pub struct CertificateInfo {
 }
 
 /// Certificate information with private key (for generation)
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct CertificateWithKey {
     /// Certificate in PEM format
     pub pem: String,
@@ -60,105 +65,115 @@ pub struct CertificateWithKey {
     /// Download URL for certificate bundle
     #[serde(skip_serializing_if = "Option::is_none")]
     pub download_url: Option<String>,
-}
-
-/// Detailed certificate information
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct DetailedCertificateInfo {
-    /// X.509 version
-    pub version: i32,
-
-    /// Serial number (hex)
-    pub serial_number: String,
-
-    /// Signature algorithm
-    pub signature_algorithm: String,
-
-    /// Issuer DN components
-    pub issuer: HashMap<String, String>,
-
-    /// Validity information
-    pub validity: ValidityInfo,
-
-    /// Subject DN components
-    pub subject: HashMap<String, String>,
-
-    /// Subject Alternative Names
-    #[serde(skip_serializing_if = "Vec::is_empty", default)]
-    pub subject_alternative_names: Vec<String>,
-
-    /// Public key information
-    pub public_key: PublicKeyInfo,
-
-    /// Certificate extensions
-    #[serde(skip_serializing_if = "Vec::is_empty", default)]
-    pub extensions: Vec<ExtensionInfo>,
-
-    /// Fingerprints
-    pub fingerprints: FingerprintInfo,
-
-    /// Certificate in PEM format
-    pub pem: String,
-}
-
-/// Validity period information
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ValidityInfo {
-    pub not_before: DateTime<Utc>,
-    pub not_after: DateTime<Utc>,
-    pub days_remaining: i64,
-    pub is_expired: bool,
-    pub is_expiring_soon: bool,
-}
 
-/// Public key information
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct PublicKeyInfo {
-    pub algorithm: String,
-    pub size: u32,
+    /// PKCS#12 bundle of the certificate, key and CA chain (base64), if requested
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub exponent: Option<u64>,
-}
+    pub pkcs12: Option<String>,
 
-/// Extension information
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ExtensionInfo {
-    pub oid: String,
-    pub name: String,
-    pub critical: bool,
-    pub value: String,
-}
+    /// Password of the PKCS#12 bundle, when it was generated
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pkcs12_password: Option<String>,
 
-/// Fingerprint information
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct FingerprintInfo {
-    pub sha1: String,
-    pub sha256: String,
+    /// ZIP of the certificate, key, chain and install notes (base64), if requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub zip: Option<String>,
 }
 
 /// Generic success response for CSR upload
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct CsrUploadResponse {
     pub success: bool,
     pub certificate: CertificateInfo,
 }
 
+/// Outcome of one CSR of a batch upload
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct CsrBatchItem {
+    /// Uploaded file name (`archive.zip/name.csr` for archive members)
+    pub file: String,
+    pub success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub certificate: Option<CertificateInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Response of `POST /api/csr/upload-batch`, one result per CSR in upload order
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct CsrBatchUploadResponse {
+    pub successful: usize,
+    pub failed: usize,
+    pub results: Vec<CsrBatchItem>,
+}
+
 /// Generic success response for certificate generation
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct CertificateGenerateResponse {
     pub success: bool,
     pub certificate: CertificateWithKey,
 }
 
 /// Generic success response for certificate info
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct CertificateInfoResponse {
     pub success: bool,
+    /// First (usually leaf) certificate of the upload
     pub certificate: DetailedCertificateInfo,
+    /// All uploaded certificates, when more than one was provided
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub certificates: Vec<DetailedCertificateInfo>,
+}
+
+/// State of an issued certificate
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum CertStatus {
+    Valid,
+    /// Expires within `check.warning_days`
+    Expiring,
+    Expired,
+    Revoked,
+}
+
+/// One issued certificate in `GET /api/certs`
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct CertSummary {
+    pub name: String,
+    pub serial: String,
+    pub subject: String,
+    pub sans: Vec<String>,
+    pub not_before: DateTime<Utc>,
+    pub not_after: DateTime<Utc>,
+    pub issued_at: DateTime<Utc>,
+    /// Days until expiry (negative once expired)
+    pub days_remaining: i64,
+    pub status: CertStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revoked_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revocation_reason: Option<String>,
+}
+
+/// A page of issued certificates
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct CertListResponse {
+    pub success: bool,
+    pub certificates: Vec<CertSummary>,
+    /// Matching certificates across all pages
+    pub total: usize,
+    pub page: usize,
+    pub per_page: usize,
+}
+
+/// SANs suggested from the host inventory
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct InventorySuggestResponse {
+    /// `DNS:…` and `IP:…` entries, best matches first
+    pub suggestions: Vec<String>,
 }
 
 /// Health check response
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct HealthResponse {
     pub status: String,
     pub version: String,
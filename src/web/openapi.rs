@@ -0,0 +1,96 @@
+//! OpenAPI 3 document of the web API
+//!
+//! Built from the models and handler annotations and served at
+//! `/api/openapi.json`, like `/api/health` without an API key. With the
+//! `swagger-ui` feature, Swagger UI at `/api/docs` renders it.
+
+use utoipa::openapi::security::{Http, HttpAuthScheme, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+
+use super::handlers;
+use super::models::*;
+use super::routes;
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Flux SSL Manager",
+        description = "Certificate signing and inspection for the Flux SSL Manager CA",
+    ),
+    paths(
+        routes::health_check,
+        handlers::handle_csr_upload,
+        handlers::handle_csr_batch_upload,
+        handlers::handle_csr_sign_ca,
+        handlers::handle_certificate_generate,
+        handlers::handle_certificate_info,
+        handlers::handle_certificate_list,
+        handlers::handle_certificate_download,
+        handlers::handle_inventory_suggest,
+    ),
+    components(schemas(
+        CsrUploadForm,
+        CsrBatchUploadForm,
+        CsrSignCaForm,
+        CertInfoForm,
+        CertificateGenerateRequest,
+        CsrUploadResponse,
+        CsrBatchUploadResponse,
+        CertificateGenerateResponse,
+        CertificateInfoResponse,
+        CertListResponse,
+        CertSummary,
+        CertStatus,
+        DownloadFormat,
+        InventorySuggestResponse,
+        HealthResponse,
+        ErrorResponse,
+        ErrorCode,
+        crate::policy::Violation,
+    )),
+    modifiers(&SecuritySchemes),
+    tags((name = "certificates", description = "Signing, generating and inspecting certificates")),
+)]
+pub struct ApiDoc;
+
+/// Bearer schemes for API keys and enrollment tokens
+struct SecuritySchemes;
+
+impl Modify for SecuritySchemes {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        let bearer = |description: &str| {
+            let mut scheme = Http::new(HttpAuthScheme::Bearer);
+            scheme.description = Some(description.to_string());
+            SecurityScheme::Http(scheme)
+        };
+        components.add_security_scheme(
+            "api_key",
+            bearer("API key from `flux-ssl-mgr web token create` (also accepted as X-API-Key)"),
+        );
+        components.add_security_scheme(
+            "enrollment_token",
+            bearer("One-time enrollment token from `web token new` or `enroll-script`"),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_document() {
+        let doc = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        assert!(doc["openapi"].as_str().unwrap().starts_with("3."));
+        assert_eq!(doc["info"]["version"], env!("CARGO_PKG_VERSION"));
+
+        let paths = doc["paths"].as_object().unwrap();
+        for path in ["/api/health", "/api/csr/upload", "/api/csr/upload-batch", "/api/csr/sign-ca", "/api/cert/generate", "/api/cert/info", "/api/certs", "/api/certs/{serial}/download", "/api/inventory/suggest"] {
+            assert!(paths.contains_key(path), "{} missing", path);
+        }
+        assert!(doc["paths"]["/api/csr/upload"]["post"]["requestBody"]["content"]["multipart/form-data"].is_object());
+        assert!(doc["components"]["schemas"]["DetailedCertificateInfo"].is_object());
+        assert!(doc["components"]["securitySchemes"]["api_key"].is_object());
+    }
+}
@@ -0,0 +1,213 @@
+//! Rate limiting for the signing endpoints
+//!
+//! Key generation and signing are expensive, so `/api/csr/upload` and
+//! `/api/cert/generate` allow each client address and each presented API key a
+//! token bucket of `[web.rate_limit] burst` requests, refilled at the configured
+//! per-minute rate. Rejected requests get 429 with `Retry-After`.
+//!
+//! The client address is the TCP peer: behind a reverse proxy every request
+//! shares the proxy's address, so rely on the per-key limit there.
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{header, HeaderValue},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::config::RateLimitConfig;
+
+use super::auth::presented_token;
+use super::models::WebError;
+
+/// Number of tracked clients above which idle buckets are dropped
+const PRUNE_THRESHOLD: usize = 10_000;
+
+/// Who a bucket belongs to
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Client {
+    Address(IpAddr),
+    Token(String),
+}
+
+/// Refill rate and capacity of one kind of bucket
+#[derive(Debug, Clone, Copy)]
+struct Quota {
+    per_second: f64,
+    burst: f64,
+}
+
+impl Quota {
+    fn new(per_minute: u32, burst: u32) -> Option<Self> {
+        (per_minute > 0).then(|| Self {
+            per_second: f64::from(per_minute) / 60.0,
+            burst: f64::from(burst.max(1)),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    /// Tokens available at `now`
+    fn available(&self, quota: Quota, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        (self.tokens + elapsed * quota.per_second).min(quota.burst)
+    }
+}
+
+/// Token buckets per client address and per API key
+#[derive(Debug)]
+pub struct RateLimiter {
+    per_ip: Option<Quota>,
+    per_token: Option<Quota>,
+    buckets: Mutex<HashMap<Client, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: &RateLimitConfig) -> Self {
+        Self {
+            per_ip: Quota::new(config.per_ip, config.burst),
+            per_token: Quota::new(config.per_token, config.burst),
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Whether any limit is on
+    pub fn enabled(&self) -> bool {
+        self.per_ip.is_some() || self.per_token.is_some()
+    }
+
+    /// Take one request from every bucket that applies, or the time until all
+    /// of them have one to spare
+    ///
+    /// Nothing is taken when any bucket is empty, so a request rejected for its
+    /// key doesn't count against its address.
+    fn acquire(&self, address: Option<IpAddr>, token: Option<&str>, now: Instant) -> Result<(), Duration> {
+        let clients: Vec<(Client, Quota)> = [
+            address.zip(self.per_ip).map(|(ip, quota)| (Client::Address(ip), quota)),
+            token.zip(self.per_token).map(|(t, quota)| (Client::Token(t.to_string()), quota)),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        let available: Vec<f64> = clients.iter()
+            .map(|(client, quota)| buckets.get(client).map_or(quota.burst, |b| b.available(*quota, now)))
+            .collect();
+
+        let wait = clients.iter().zip(&available)
+            .filter(|(_, tokens)| **tokens < 1.0)
+            .map(|((_, quota), tokens)| (1.0 - tokens) / quota.per_second)
+            .fold(None, |max: Option<f64>, secs| Some(max.map_or(secs, |m| m.max(secs))));
+        if let Some(secs) = wait {
+            return Err(Duration::from_secs_f64(secs));
+        }
+
+        if buckets.len() >= PRUNE_THRESHOLD {
+            let (per_ip, per_token) = (self.per_ip, self.per_token);
+            buckets.retain(|client, bucket| {
+                let quota = match client {
+                    Client::Address(_) => per_ip,
+                    Client::Token(_) => per_token,
+                };
+                quota.is_some_and(|q| bucket.available(q, now) < q.burst)
+            });
+        }
+        for ((client, _), tokens) in clients.into_iter().zip(available) {
+            buckets.insert(client, Bucket { tokens: tokens - 1.0, updated: now });
+        }
+        Ok(())
+    }
+}
+
+/// Middleware answering 429 once a client is over its limit
+///
+/// Used with `axum::middleware::from_fn_with_state(limiter, limit)`.
+pub async fn limit(State(limiter): State<Arc<RateLimiter>>, request: Request, next: Next) -> Response {
+    let address = request.extensions().get::<ConnectInfo<SocketAddr>>().map(|info| info.0.ip());
+    let token = presented_token(request.headers());
+
+    if let Err(wait) = limiter.acquire(address, token, Instant::now()) {
+        let retry_after = wait.as_secs_f64().ceil().max(1.0) as u64;
+        tracing::warn!(
+            "Rate limited {} {}",
+            address.map_or_else(|| "unknown client".to_string(), |ip| ip.to_string()),
+            request.uri().path()
+        );
+        let mut response = WebError::rate_limited(format!("Too many requests; retry in {} seconds", retry_after))
+            .into_response();
+        response.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+        return response;
+    }
+
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::StatusCode, routing::post, Router};
+    use tower::ServiceExt;
+
+    fn limiter(per_ip: u32, per_token: u32, burst: u32) -> RateLimiter {
+        RateLimiter::new(&RateLimitConfig { per_ip, per_token, burst })
+    }
+
+    #[test]
+    fn test_bucket_refills() {
+        let limiter = limiter(60, 0, 2);
+        let ip: IpAddr = "192.0.2.1".parse().unwrap();
+        let start = Instant::now();
+
+        assert!(limiter.acquire(Some(ip), None, start).is_ok());
+        assert!(limiter.acquire(Some(ip), None, start).is_ok());
+        let wait = limiter.acquire(Some(ip), None, start).unwrap_err();
+        assert!((wait.as_secs_f64() - 1.0).abs() < 0.01);
+
+        // Another address has its own bucket; one per second comes back
+        assert!(limiter.acquire(Some("192.0.2.2".parse().unwrap()), None, start).is_ok());
+        assert!(limiter.acquire(Some(ip), None, start + Duration::from_secs(1)).is_ok());
+        assert!(limiter.acquire(Some(ip), None, start + Duration::from_secs(1)).is_err());
+    }
+
+    #[test]
+    fn test_token_limit_spans_addresses() {
+        let limiter = limiter(0, 1, 1);
+        let now = Instant::now();
+        assert!(limiter.per_ip.is_none() && limiter.enabled());
+
+        assert!(limiter.acquire(Some("192.0.2.1".parse().unwrap()), Some("flux_a"), now).is_ok());
+        assert!(limiter.acquire(Some("192.0.2.2".parse().unwrap()), Some("flux_a"), now).is_err());
+        assert!(limiter.acquire(Some("192.0.2.2".parse().unwrap()), Some("flux_b"), now).is_ok());
+        assert!(limiter.acquire(None, None, now).is_ok());
+        assert!(!self::limiter(0, 0, 5).enabled());
+    }
+
+    #[tokio::test]
+    async fn test_limit_answers_429() {
+        let limiter = Arc::new(limiter(1, 0, 1));
+        let app = Router::new()
+            .route("/api/cert/generate", post(|| async { "ok" }))
+            .route_layer(axum::middleware::from_fn_with_state(limiter, limit));
+        let request = || {
+            let mut request = Request::builder().method("POST").uri("/api/cert/generate").body(Body::empty()).unwrap();
+            request.extensions_mut().insert(ConnectInfo(SocketAddr::from(([192, 0, 2, 1], 40000))));
+            request
+        };
+
+        assert_eq!(app.clone().oneshot(request()).await.unwrap().status(), StatusCode::OK);
+        let response = app.oneshot(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "60");
+    }
+}
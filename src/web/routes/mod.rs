@@ -1,15 +1,25 @@
 use axum::{
+    extract::{DefaultBodyLimit, State},
+    http::{header, StatusCode},
+    middleware,
     routing::{get, post},
-    Router, Json,
-    response::Html,
+    Extension, Router, Json,
+    response::{Html, IntoResponse, Response},
 };
 use std::sync::Arc;
-use tower_http::services::ServeDir;
 
-use crate::config::Config;
+use crate::apikey::Scope;
+use crate::config::{CommandKind, Config};
 
+use super::audit::{self, AuditLog};
+use super::assets;
+use super::auth;
+use super::crl;
+use super::ratelimit::{self, RateLimiter};
 use super::handlers;
-use super::models::HealthResponse;
+use super::models::{HealthResponse, WebError};
+use super::openapi::ApiDoc;
+use utoipa::OpenApi;
 
 // Simple HTML page handlers
 async fn serve_index() -> Html<&'static str> {
@@ -29,42 +39,160 @@ async fn serve_cert_info() -> Html<&'static str> {
 }
 
 /// Health check endpoint
-async fn health_check() -> Json<HealthResponse> {
+#[utoipa::path(
+    get,
+    path = "/api/health",
+    tag = "service",
+    responses((status = 200, description = "Service is up", body = HealthResponse))
+)]
+pub(crate) async fn health_check() -> Json<HealthResponse> {
     Json(HealthResponse {
         status: "healthy".to_string(),
         version: env!("CARGO_PKG_VERSION").to_string(),
     })
 }
 
+/// OpenAPI document of the API
+async fn openapi_json() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+/// Turn axum's plain-text body limit rejections into `FILE_TOO_LARGE`
+///
+/// Extractors such as `Json` reject an oversized body before the handler
+/// runs; handler errors are already JSON and pass through unchanged.
+async fn body_too_large(State(limit): State<usize>, response: Response) -> Response {
+    let is_json = response.headers().get(header::CONTENT_TYPE)
+        .is_some_and(|value| value.as_bytes().starts_with(b"application/json"));
+    if response.status() != StatusCode::PAYLOAD_TOO_LARGE || is_json {
+        return response;
+    }
+    WebError::file_too_large(format!("Request body exceeds the {} byte limit", limit)).into_response()
+}
+
 /// Create the main application router
 pub fn create_router(config: Arc<Config>) -> Router {
-    // API routes
+    let config = Arc::new(config.for_command(CommandKind::Web));
+    let body_limit = config.web_body_limit();
+
+    let scoped = |scope: Scope| middleware::from_fn_with_state((Arc::clone(&config), scope), auth::require_scope);
+    // One limiter for the signing endpoints; it runs before authentication
+    let limiter = Arc::new(RateLimiter::new(&config.web.rate_limit));
+    let limited = || middleware::from_fn_with_state(Arc::clone(&limiter), ratelimit::limit);
+    let inventory = Arc::new(handlers::InventoryCache::default());
+    // Outermost, so requests turned away by the limiter or auth are recorded too
+    let audit_log = Arc::new(AuditLog::new(&config));
+    let audited = || middleware::from_fn_with_state(Arc::clone(&audit_log), audit::record);
+
+    // API routes; health stays open for load balancers and monitoring
     let api_routes = Router::new()
         .route("/health", get(health_check))
+        .route("/openapi.json", get(openapi_json))
         .route(
             "/csr/upload",
             post({
                 let config = Arc::clone(&config);
-                move |multipart| handlers::handle_csr_upload(Arc::clone(&config), multipart)
-            }),
+                move |grant: Option<Extension<auth::EnrollmentGrant>>, multipart| {
+                    handlers::handle_csr_upload(Arc::clone(&config), grant.map(|Extension(grant)| grant), multipart)
+                }
+            })
+            .route_layer(middleware::from_fn_with_state(
+                (Arc::clone(&config), Scope::Issue),
+                auth::require_scope_or_enrollment,
+            ))
+            .route_layer(limited())
+            .route_layer(audited()),
+        )
+        .route(
+            "/csr/upload-batch",
+            post({
+                let config = Arc::clone(&config);
+                move |multipart| handlers::handle_csr_batch_upload(Arc::clone(&config), multipart)
+            })
+            .route_layer(scoped(Scope::Issue))
+            .route_layer(limited())
+            .route_layer(audited()),
+        )
+        .route(
+            "/csr/sign-ca",
+            post({
+                let config = Arc::clone(&config);
+                move |multipart| handlers::handle_csr_sign_ca(Arc::clone(&config), multipart)
+            })
+            .route_layer(scoped(Scope::Issue))
+            .route_layer(limited())
+            .route_layer(audited()),
         )
         .route(
             "/cert/generate",
             post({
                 let config = Arc::clone(&config);
                 move |request| handlers::handle_certificate_generate(Arc::clone(&config), request)
-            }),
+            })
+            .route_layer(scoped(Scope::Issue))
+            .route_layer(limited())
+            .route_layer(audited()),
         )
-        .route("/cert/info", post(handlers::handle_certificate_info));
+        .route(
+            "/certs",
+            get({
+                let config = Arc::clone(&config);
+                move |query| handlers::handle_certificate_list(Arc::clone(&config), query)
+            })
+            .route_layer(scoped(Scope::Read))
+            .route_layer(audited()),
+        )
+        .route(
+            "/certs/:serial/download",
+            get({
+                let config = Arc::clone(&config);
+                move |serial, query, headers| {
+                    handlers::handle_certificate_download(Arc::clone(&config), serial, query, headers)
+                }
+            })
+            // PKCS#12 downloads carry the private key
+            .route_layer(scoped(Scope::Issue))
+            .route_layer(audited()),
+        )
+        .route(
+            "/inventory/suggest",
+            get({
+                let config = Arc::clone(&config);
+                move |query| handlers::handle_inventory_suggest(Arc::clone(&config), Arc::clone(&inventory), query)
+            })
+            // Not audited: the UI asks on every keystroke
+            .route_layer(scoped(Scope::Read)),
+        )
+        .route(
+            "/cert/info",
+            post({
+                let config = Arc::clone(&config);
+                move |multipart| handlers::handle_certificate_info(Arc::clone(&config), multipart)
+            })
+            .route_layer(scoped(Scope::Read))
+            .route_layer(audited()),
+        )
+        .layer(DefaultBodyLimit::max(body_limit))
+        .layer(middleware::map_response_with_state(body_limit, body_too_large));
 
     // Main router with API prefix
-    Router::new()
+    let router = Router::new()
         .nest("/api", api_routes)
-        // Serve static files from the static directory
-        .nest_service("/static", ServeDir::new("static"))
+        // CRL distribution point, open like /api/health
+        .route("/crl/intermediate.crl", get(crl::serve_der).with_state(Arc::clone(&config)))
+        .route("/crl/intermediate.pem", get(crl::serve_pem).with_state(Arc::clone(&config)))
+        // Static files, compiled into the binary
+        .route("/static/*path", get(assets::serve_static))
         // Web UI routes (HTML pages)
         .route("/", get(serve_index))
         .route("/csr-upload", get(serve_csr_upload))
         .route("/cert-generate", get(serve_cert_generate))
-        .route("/cert-info", get(serve_cert_info))
+        .route("/cert-info", get(serve_cert_info));
+
+    #[cfg(feature = "swagger-ui")]
+    let router = router.merge(
+        utoipa_swagger_ui::SwaggerUi::new("/api/docs").config(utoipa_swagger_ui::Config::from("/api/openapi.json")),
+    );
+
+    router
 }
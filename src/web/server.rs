@@ -2,19 +2,25 @@ use axum::Router;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use tokio::net::TcpListener;
-use tower_http::trace::TraceLayer;
+use axum::http::header::{HeaderName, AUTHORIZATION, COOKIE};
+use tower_http::sensitive_headers::{SetSensitiveRequestHeadersLayer, SetSensitiveResponseHeadersLayer};
+use tower_http::trace::{DefaultMakeSpan, DefaultOnRequest, DefaultOnResponse, TraceLayer};
 use tracing::{info, warn};
 
 use crate::config::Config;
 use crate::error::FluxError;
+use crate::logging::Verbosity;
 
-use super::routes;
+use super::ratelimit::RateLimiter;
+use super::{auth, cors, routes};
 
 /// Web server configuration
 #[derive(Debug, Clone)]
 pub struct ServerConfig {
     pub bind_address: String,
     pub port: u16,
+    /// Log verbosity for per-request tracing
+    pub verbosity: Verbosity,
 }
 
 impl Default for ServerConfig {
@@ -22,6 +28,7 @@ impl Default for ServerConfig {
         Self {
             bind_address: "127.0.0.1".to_string(),
             port: 8443,
+            verbosity: Verbosity::default(),
         }
     }
 }
@@ -33,8 +40,27 @@ pub async fn start_server(
 ) -> Result<(), FluxError> {
     info!("Starting Flux SSL Manager web service");
 
+    if !auth::enabled(&config)? {
+        if config.web.allow_unauthenticated {
+            warn!("No API keys configured and web.allow_unauthenticated is set: anyone who can reach the server can issue and revoke certificates");
+        } else {
+            warn!("No API keys configured: issue and revoke requests are refused until one is created with `flux-ssl-mgr web token create`");
+        }
+    }
+    if !RateLimiter::new(&config.web.rate_limit).enabled() {
+        warn!("Rate limits on the signing endpoints are off ([web.rate_limit])");
+    }
+
+    let cors = cors::layer(&config.web.cors)?;
+    if cors.is_some() {
+        info!("CORS enabled for {}", config.web.cors.allowed_origins.join(", "));
+    }
+
     // Create the router
-    let app = create_app(config);
+    let mut app = create_app(config, server_config.verbosity);
+    if let Some(cors) = cors {
+        app = app.layer(cors);
+    }
 
     // Bind address
     let addr = format!("{}:{}", server_config.bind_address, server_config.port);
@@ -48,28 +74,54 @@ pub async fn start_server(
     // Create TCP listener
     let listener = TcpListener::bind(socket_addr)
         .await
-        .map_err(|e| FluxError::IoError(e))?;
+        .map_err(FluxError::IoError)?;
 
-    // Start server
-    axum::serve(listener, app)
+    // Start server; the peer address is used for per-client rate limits
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
         .await
-        .map_err(|e| FluxError::IoError(std::io::Error::new(std::io::ErrorKind::Other, e)))?;
+        .map_err(|e| FluxError::IoError(std::io::Error::other(e)))?;
 
     Ok(())
 }
 
+/// Request headers carrying credentials: API keys, enrollment tokens and
+/// key/PKCS#12 passwords
+fn sensitive_request_headers() -> [HeaderName; 5] {
+    [
+        AUTHORIZATION,
+        COOKIE,
+        HeaderName::from_static("x-api-key"),
+        HeaderName::from_static("x-key-password"),
+        HeaderName::from_static("x-pkcs12-password"),
+    ]
+}
+
 /// Create the application with all middleware
-fn create_app(config: Arc<Config>) -> Router {
-    routes::create_router(config)
-        // Add tracing/logging middleware
-        .layer(TraceLayer::new_for_http())
+fn create_app(config: Arc<Config>, verbosity: Verbosity) -> Router {
+    with_tracing(routes::create_router(config), verbosity)
+}
+
+/// Add tracing/logging middleware, following the CLI verbosity
+///
+/// Credential headers are marked sensitive first, so `-vv` logs them as
+/// `Sensitive` rather than in plaintext.
+fn with_tracing(router: Router, verbosity: Verbosity) -> Router {
+    let level = verbosity.http_level();
+
+    router
+        .layer(
+            TraceLayer::new_for_http()
+                .make_span_with(DefaultMakeSpan::new().level(level).include_headers(verbosity.http_headers()))
+                .on_request(DefaultOnRequest::new().level(level))
+                .on_response(DefaultOnResponse::new().level(level)),
+        )
+        .layer(SetSensitiveResponseHeadersLayer::new([HeaderName::from_static("x-pkcs12-password")]))
+        .layer(SetSensitiveRequestHeadersLayer::new(sensitive_request_headers()))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::config::Config;
-    use std::path::PathBuf;
 
     #[test]
     fn test_server_config_default() {
@@ -77,4 +129,53 @@ mod tests {
         assert_eq!(config.bind_address, "127.0.0.1");
         assert_eq!(config.port, 8443);
     }
+
+    #[tokio::test]
+    async fn test_credentials_stay_out_of_request_logs() {
+        use axum::body::Body;
+        use axum::http::Request;
+        use axum::routing::get;
+        use std::sync::Mutex;
+        use tower::ServiceExt;
+
+        #[derive(Clone, Default)]
+        struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+        impl std::io::Write for Buffer {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::TRACE)
+            .with_ansi(false)
+            .with_writer(move || writer.clone())
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let app = with_tracing(Router::new().route("/", get(|| async { "ok" })), Verbosity::Trace);
+        let request = Request::get("/")
+            .header("authorization", "Bearer secret-bearer")
+            .header("cookie", "session=secret-cookie")
+            .header("x-api-key", "secret-api-key")
+            .header("x-key-password", "secret-key-password")
+            .header("x-pkcs12-password", "secret-pkcs12-password")
+            .header("user-agent", "flux-test")
+            .body(Body::empty())
+            .unwrap();
+        app.oneshot(request).await.unwrap();
+
+        let logged = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        assert!(logged.contains("flux-test"), "headers not logged: {}", logged);
+        assert!(logged.contains("Sensitive"));
+        assert!(!logged.contains("secret-"), "credential logged: {}", logged);
+    }
 }
@@ -0,0 +1,200 @@
+//! Maintenance windows
+//!
+//! A window is a cron expression (`minute hour day-of-month month day-of-week`,
+//! local time) matching every minute that is inside the window, so
+//! `* 2-4 * * sat,sun` is open from 02:00 to 04:59 on weekends. Fields take
+//! `*`, numbers, `a-b` ranges, `/n` steps and comma lists; months and weekdays
+//! also take names. As in cron, when both day fields are restricted a day
+//! matching either one counts.
+
+use crate::error::{FluxError, Result};
+use chrono::{Datelike, Duration, NaiveDateTime, NaiveTime, Timelike};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+const MONTHS: [&str; 12] = ["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+const WEEKDAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// How far ahead to look for the next opening (covers Feb 29 windows)
+const SEARCH_DAYS: i64 = 5 * 366;
+
+/// Minutes during which deployments may run
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct MaintenanceWindow {
+    expr: String,
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    /// Bit 0 is Sunday
+    weekdays: u64,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl MaintenanceWindow {
+    /// Whether `at` is inside the window
+    pub fn contains(&self, at: NaiveDateTime) -> bool {
+        bit(self.minutes, at.minute()) && bit(self.hours, at.hour()) && self.day_matches(at)
+    }
+
+    /// Start of the first window minute at or after `after`
+    pub fn next_open(&self, after: NaiveDateTime) -> Option<NaiveDateTime> {
+        let after = after.with_second(0)?.with_nanosecond(0)?;
+
+        (0..SEARCH_DAYS)
+            .map(|offset| after.date() + Duration::days(offset))
+            .filter(|date| self.day_matches(date.and_time(NaiveTime::MIN)))
+            .find_map(|date| {
+                (0..24).filter(|h| bit(self.hours, *h))
+                    .flat_map(|h| (0..60).filter(|m| bit(self.minutes, *m)).map(move |m| (h, m)))
+                    .filter_map(|(h, m)| date.and_hms_opt(h, m, 0))
+                    .find(|candidate| *candidate >= after)
+            })
+    }
+
+    fn day_matches(&self, at: NaiveDateTime) -> bool {
+        if !bit(self.months, at.month()) {
+            return false;
+        }
+
+        let day = bit(self.days, at.day());
+        let weekday = bit(self.weekdays, at.weekday().num_days_from_sunday());
+        match (self.any_day, self.any_weekday) {
+            (false, false) => day || weekday,
+            _ => day && weekday,
+        }
+    }
+}
+
+fn bit(set: u64, n: u32) -> bool {
+    set & (1 << n) != 0
+}
+
+/// Parse one cron field into a bit set over `min..=max`
+fn parse_field(field: &str, min: u32, max: u32, names: &[&str]) -> Option<u64> {
+    let value = |s: &str| -> Option<u32> {
+        let s = s.to_ascii_lowercase();
+        match names.iter().position(|n| *n == s) {
+            Some(i) => Some(i as u32 + min),
+            None => s.parse().ok(),
+        }
+    };
+
+    let mut set = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().ok().filter(|s| *s > 0)?),
+            None => (part, 1),
+        };
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((a, b)) => (value(a)?, value(b)?),
+                // `5/15` means 5, 20, 35, 50
+                None if part.contains('/') => (value(range)?, max),
+                None => (value(range)?, value(range)?),
+            },
+        };
+        if start < min || end > max || start > end {
+            return None;
+        }
+        set |= (start..=end).step_by(step as usize).fold(0, |acc, n| acc | 1 << n);
+    }
+    Some(set)
+}
+
+impl FromStr for MaintenanceWindow {
+    type Err = FluxError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = |why: &str| FluxError::InvalidConfigValue("window".to_string(), format!("'{}': {}", s, why));
+
+        let fields: Vec<&str> = s.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(invalid("expected 5 fields: minute hour day-of-month month day-of-week"));
+        };
+
+        let mut weekdays = parse_field(weekday, 0, 7, &WEEKDAYS).ok_or_else(|| invalid("bad day-of-week"))?;
+        // 7 is Sunday too
+        if bit(weekdays, 7) {
+            weekdays = (weekdays & !(1 << 7)) | 1;
+        }
+
+        Ok(MaintenanceWindow {
+            expr: fields.join(" "),
+            minutes: parse_field(minute, 0, 59, &[]).ok_or_else(|| invalid("bad minute"))?,
+            hours: parse_field(hour, 0, 23, &[]).ok_or_else(|| invalid("bad hour"))?,
+            days: parse_field(day, 1, 31, &[]).ok_or_else(|| invalid("bad day-of-month"))?,
+            months: parse_field(month, 1, 12, &MONTHS).ok_or_else(|| invalid("bad month"))?,
+            weekdays,
+            any_day: day == "*",
+            any_weekday: weekday == "*",
+        })
+    }
+}
+
+impl fmt::Display for MaintenanceWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.expr)
+    }
+}
+
+impl TryFrom<String> for MaintenanceWindow {
+    type Error = FluxError;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+impl From<MaintenanceWindow> for String {
+    fn from(window: MaintenanceWindow) -> Self {
+        window.expr
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn at(day: u32, hour: u32, min: u32) -> NaiveDateTime {
+        // 2026-10-17 is a Saturday
+        NaiveDate::from_ymd_opt(2026, 10, day).unwrap().and_hms_opt(hour, min, 30).unwrap()
+    }
+
+    #[test]
+    fn test_contains() {
+        let weekend: MaintenanceWindow = "* 2-4 * * sat,sun".parse().unwrap();
+        assert!(weekend.contains(at(17, 2, 0)));
+        assert!(weekend.contains(at(18, 4, 59)));
+        assert!(!weekend.contains(at(18, 5, 0)));
+        assert!(!weekend.contains(at(19, 3, 0)));
+
+        let quarter: MaintenanceWindow = "*/15 22 * * 7".parse().unwrap();
+        assert!(quarter.contains(at(18, 22, 45)));
+        assert!(!quarter.contains(at(18, 22, 46)));
+
+        // Either day field matches when both are restricted
+        let either: MaintenanceWindow = "* * 1 * mon".parse().unwrap();
+        assert!(either.contains(at(1, 12, 0)));
+        assert!(either.contains(at(19, 12, 0)));
+        assert!(!either.contains(at(20, 12, 0)));
+
+        for bad in ["* * * *", "60 * * * *", "* 5-2 * * *", "* * * * funday", "*/0 * * * *"] {
+            assert!(bad.parse::<MaintenanceWindow>().is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_next_open() {
+        let nightly: MaintenanceWindow = "0-29 3 * * mon-fri".parse().unwrap();
+        assert_eq!(nightly.next_open(at(17, 12, 0)), Some(at(19, 3, 0).with_second(0).unwrap()));
+        assert_eq!(nightly.next_open(at(19, 3, 10)), Some(at(19, 3, 10).with_second(0).unwrap()));
+        assert_eq!(nightly.next_open(at(19, 3, 30)), Some(at(20, 3, 0).with_second(0).unwrap()));
+        assert_eq!(nightly.to_string(), "0-29 3 * * mon-fri");
+    }
+}
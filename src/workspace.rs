@@ -0,0 +1,165 @@
+//! Per-operation staging workspaces
+//!
+//! Issuing writes the key, CSR and certificates into a private directory
+//! under `<working_dir>/intermediate/.work/` first. Only when everything has
+//! been written are the files moved into the shared layout (`private/`,
+//! `csr/`, `certs/` and the output directory), while holding a lock for the
+//! certificate name. Concurrent runs therefore never see half-written files or
+//! mix files from two issuances of the same name, and a failed run leaves the
+//! shared layout untouched.
+
+use crate::config::Config;
+use crate::error::{FluxError, Result};
+use crate::fsutil::{create_dir_with_mode, sync_dir, PRIVATE_DIR_MODE};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tempfile::TempDir;
+
+/// Workspaces left behind by killed runs are removed after this long
+const STALE_WORKSPACE_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Directory holding the workspaces and name locks
+pub fn work_root(config: &Config) -> PathBuf {
+    config.working_dir.join("intermediate").join(".work")
+}
+
+/// A private directory for one operation's files
+pub struct Workspace {
+    dir: TempDir,
+    staged: Vec<(PathBuf, PathBuf)>,
+}
+
+impl Workspace {
+    /// Create a workspace next to the shared layout (same filesystem, so
+    /// publishing is a rename)
+    pub fn new(config: &Config) -> Result<Self> {
+        let root = work_root(config);
+        create_dir_with_mode(&root, PRIVATE_DIR_MODE)?;
+        remove_stale(&root);
+
+        let dir = tempfile::Builder::new().prefix("op-").tempdir_in(&root)?;
+        Ok(Self { dir, staged: Vec::new() })
+    }
+
+    /// Where to write the file that will be published to `dest`
+    pub fn stage(&mut self, dest: &Path) -> PathBuf {
+        let file_name = dest.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        let staged = self.dir.path().join(format!("{}-{}", self.staged.len(), file_name));
+        self.staged.push((staged.clone(), dest.to_path_buf()));
+        staged
+    }
+
+    /// Move every staged file into place, in the order they were staged
+    ///
+    /// Hold the [`NameLock`] of the certificate while publishing.
+    pub fn publish(self) -> Result<()> {
+        for (staged, dest) in &self.staged {
+            move_into_place(staged, dest)?;
+        }
+        let mut dirs: Vec<&Path> = self.staged.iter().filter_map(|(_, dest)| dest.parent()).collect();
+        dirs.dedup();
+        for dir in dirs {
+            sync_dir(dir);
+        }
+        Ok(())
+    }
+}
+
+/// Rename `from` to `to`, copying through a temporary file next to `to` when
+/// they are on different filesystems (e.g. an output directory on another mount)
+fn move_into_place(from: &Path, to: &Path) -> Result<()> {
+    match std::fs::rename(from, to) {
+        Err(e) if e.kind() == std::io::ErrorKind::CrossesDevices => {
+            let dir = to.parent().unwrap_or(Path::new("."));
+            let tmp = tempfile::Builder::new().prefix(".flux-").tempfile_in(dir)?;
+            std::fs::copy(from, tmp.path())?;
+            tmp.as_file().sync_all()?;
+            tmp.persist(to).map_err(|e| FluxError::FileWriteFailed(to.to_path_buf(), e.error.to_string()))?;
+            std::fs::remove_file(from)?;
+            Ok(())
+        }
+        Err(e) => Err(FluxError::FileWriteFailed(to.to_path_buf(), e.to_string())),
+        Ok(()) => Ok(()),
+    }
+}
+
+/// Remove workspaces of runs that were killed before cleaning up
+fn remove_stale(root: &Path) {
+    let Ok(entries) = std::fs::read_dir(root) else { return };
+    let cutoff = SystemTime::now() - STALE_WORKSPACE_AGE;
+    for entry in entries.flatten() {
+        let stale = entry.file_name().to_string_lossy().starts_with("op-")
+            && entry.metadata().and_then(|m| m.modified()).is_ok_and(|modified| modified < cutoff);
+        if stale {
+            let _ = std::fs::remove_dir_all(entry.path());
+        }
+    }
+}
+
+/// Exclusive lock on a certificate name, across processes
+///
+/// Released when dropped (or when the process exits).
+pub struct NameLock(#[allow(dead_code)] File);
+
+impl NameLock {
+    /// Wait for and take the lock of `name`
+    pub fn acquire(config: &Config, name: &str) -> Result<Self> {
+        let root = work_root(config);
+        create_dir_with_mode(&root, PRIVATE_DIR_MODE)?;
+        let path = root.join(format!("{}.lock", name));
+        let file = File::options().create(true).truncate(false).write(true).open(&path)
+            .map_err(|e| FluxError::FileWriteFailed(path.clone(), e.to_string()))?;
+        file.lock().map_err(|e| FluxError::FileWriteFailed(path, e.to_string()))?;
+        Ok(Self(file))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(dir: &Path) -> Config {
+        Config { working_dir: dir.to_path_buf(), ..Config::default() }
+    }
+
+    #[test]
+    fn test_publish_moves_staged_files() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let config = config(temp_dir.path());
+        let dest = temp_dir.path().join("out");
+        std::fs::create_dir(&dest).unwrap();
+        std::fs::write(dest.join("web.crt"), "old").unwrap();
+
+        let mut workspace = Workspace::new(&config).unwrap();
+        std::fs::write(workspace.stage(&dest.join("web.key.pem")), "key").unwrap();
+        std::fs::write(workspace.stage(&dest.join("web.crt")), "new").unwrap();
+        assert!(!dest.join("web.key.pem").exists());
+
+        workspace.publish().unwrap();
+        assert_eq!(std::fs::read_to_string(dest.join("web.key.pem")).unwrap(), "key");
+        assert_eq!(std::fs::read_to_string(dest.join("web.crt")).unwrap(), "new");
+        // Nothing is left in the work directory
+        assert_eq!(std::fs::read_dir(work_root(&config)).unwrap().count(), 0);
+
+        // Dropped without publishing: the shared files stay as they were
+        let mut workspace = Workspace::new(&config).unwrap();
+        std::fs::write(workspace.stage(&dest.join("web.crt")), "half").unwrap();
+        drop(workspace);
+        assert_eq!(std::fs::read_to_string(dest.join("web.crt")).unwrap(), "new");
+    }
+
+    #[test]
+    fn test_name_lock_is_exclusive() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let config = config(temp_dir.path());
+
+        let lock = NameLock::acquire(&config, "web").unwrap();
+        let other = File::options().write(true).open(work_root(&config).join("web.lock")).unwrap();
+        assert!(other.try_lock().is_err());
+        NameLock::acquire(&config, "db").unwrap();
+
+        drop(lock);
+        other.try_lock().unwrap();
+    }
+}
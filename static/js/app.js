@@ -1,12 +1,29 @@
 // Flux SSL Manager - Frontend JavaScript
 
-console.log('Flux SSL Manager web interface loaded');
+// localStorage entry holding the API key
+const API_KEY_STORAGE = 'fluxApiKey';
 
-// Placeholder for future functionality
-document.addEventListener('DOMContentLoaded', function() {
-    console.log('DOM fully loaded');
+// fetch() for /api/* that sends the stored API key.
+// On 401 it asks for a key, stores it and retries once.
+async function apiFetch(url, options = {}) {
+    const send = () => {
+        const headers = new Headers(options.headers || {});
+        const key = localStorage.getItem(API_KEY_STORAGE);
+        if (key) {
+            headers.set('Authorization', 'Bearer ' + key);
+        }
+        return fetch(url, { ...options, headers });
+    };
 
-    // Future: Add event listeners for form submissions
-    // Future: Add certificate upload handling
-    // Future: Add certificate download functionality
-});
+    const response = await send();
+    if (response.status !== 401) {
+        return response;
+    }
+
+    const key = window.prompt('This server requires an API key (flux-ssl-mgr web token create):');
+    if (!key) {
+        return response;
+    }
+    localStorage.setItem(API_KEY_STORAGE, key.trim());
+    return send();
+}
@@ -12,6 +12,9 @@ document.addEventListener('DOMContentLoaded', function() {
     const addSanBtn = document.getElementById('add-san');
     const sanList = document.getElementById('san-list');
     const sansHidden = document.getElementById('sans');
+    const pkcs12Checkbox = document.getElementById('pkcs12');
+    const pkcs12PasswordGroup = document.getElementById('pkcs12-password-group');
+    const pkcs12PasswordInput = document.getElementById('pkcs12-password');
 
     let sans = [];
 
@@ -27,6 +30,13 @@ document.addEventListener('DOMContentLoaded', function() {
         }
     });
 
+    pkcs12Checkbox.addEventListener('change', function() {
+        pkcs12PasswordGroup.style.display = this.checked ? 'block' : 'none';
+        if (!this.checked) {
+            pkcs12PasswordInput.value = '';
+        }
+    });
+
     // SAN management
     function addSAN() {
         const value = sanInput.value.trim();
@@ -51,6 +61,47 @@ document.addEventListener('DOMContentLoaded', function() {
 
     addSanBtn.addEventListener('click', addSAN);
 
+    // Suggestions from the server's host inventory ([[inventory]] sources).
+    // Needs a key with the read scope; without one the field stays a plain input.
+    const sanSuggestions = document.getElementById('san-suggestions');
+    let suggestTimer = null;
+    let suggestionsAvailable = true;
+
+    async function loadSuggestions(query) {
+        const headers = {};
+        const key = localStorage.getItem(API_KEY_STORAGE);
+        if (key) {
+            headers['Authorization'] = 'Bearer ' + key;
+        }
+        try {
+            const response = await fetch('/api/inventory/suggest?q=' + encodeURIComponent(query), { headers });
+            if (!response.ok) {
+                suggestionsAvailable = response.status !== 401 && response.status !== 403;
+                return;
+            }
+            const data = await response.json();
+            sanSuggestions.replaceChildren(...data.suggestions
+                .filter(san => !sans.includes(san))
+                .map(san => new Option(san, san)));
+        } catch (error) {
+            // Suggestions are optional
+        }
+    }
+
+    sanInput.addEventListener('input', function() {
+        clearTimeout(suggestTimer);
+        if (suggestionsAvailable) {
+            suggestTimer = setTimeout(() => loadSuggestions(sanInput.value.trim()), 200);
+        }
+    });
+
+    // Start with what the inventory knows about the common name
+    document.getElementById('common-name').addEventListener('change', function() {
+        if (suggestionsAvailable && this.value.trim()) {
+            loadSuggestions(this.value.trim());
+        }
+    });
+
     sanInput.addEventListener('keypress', function(e) {
         if (e.key === 'Enter') {
             e.preventDefault();
@@ -89,13 +140,21 @@ document.addEventListener('DOMContentLoaded', function() {
             sans: sans,
             validity_days: parseInt(document.getElementById('validity-days').value),
             key_size: parseInt(document.getElementById('key-size').value),
-            password_protect: passwordProtectCheckbox.checked
+            password_protect: passwordProtectCheckbox.checked,
+            zip: true
         };
 
         if (formData.password_protect) {
             formData.key_password = passwordInput.value;
         }
 
+        if (pkcs12Checkbox.checked) {
+            formData.pkcs12 = true;
+            if (pkcs12PasswordInput.value) {
+                formData.pkcs12_password = pkcs12PasswordInput.value;
+            }
+        }
+
         // Validate
         if (!formData.common_name) {
             alert('Common Name is required');
@@ -110,7 +169,7 @@ document.addEventListener('DOMContentLoaded', function() {
         submitBtn.disabled = true;
 
         try {
-            const response = await fetch('/api/cert/generate', {
+            const response = await apiFetch('/api/cert/generate', {
                 method: 'POST',
                 headers: {
                     'Content-Type': 'application/json'
@@ -155,6 +214,14 @@ document.addEventListener('DOMContentLoaded', function() {
             document.getElementById('chain-section').style.display = 'block';
             document.getElementById('download-chain').style.display = 'inline-block';
         }
+
+        zipBundle = certificate.zip || null;
+        document.getElementById('download-zip').style.display = zipBundle ? 'inline-block' : 'none';
+        pkcs12Bundle = certificate.pkcs12 || null;
+        pkcs12Name = certificate.subject.replace(/^.*CN=/, '').replace(/[^A-Za-z0-9._-]/g, '_') || 'certificate';
+        document.getElementById('download-pkcs12').style.display = pkcs12Bundle ? 'inline-block' : 'none';
+        document.getElementById('pkcs12-generated-password').textContent = certificate.pkcs12_password || '';
+        document.getElementById('pkcs12-password-note').style.display = certificate.pkcs12_password ? 'block' : 'none';
     }
 
     function showError(message) {
@@ -180,8 +247,24 @@ document.addEventListener('DOMContentLoaded', function() {
         downloadFile(document.getElementById('chain-pem').value, 'ca-chain.pem');
     });
 
-    function downloadFile(content, filename) {
-        const blob = new Blob([content], { type: 'application/x-pem-file' });
+    let pkcs12Bundle = null;
+    let pkcs12Name = 'certificate';
+    let zipBundle = null;
+
+    function base64ToBytes(data) {
+        return Uint8Array.from(atob(data), c => c.charCodeAt(0));
+    }
+
+    document.getElementById('download-pkcs12').addEventListener('click', function() {
+        downloadFile(base64ToBytes(pkcs12Bundle), pkcs12Name + '.p12', 'application/x-pkcs12');
+    });
+
+    document.getElementById('download-zip').addEventListener('click', function() {
+        downloadFile(base64ToBytes(zipBundle), pkcs12Name + '.zip', 'application/zip');
+    });
+
+    function downloadFile(content, filename, type = 'application/x-pem-file') {
+        const blob = new Blob([content], { type: type });
         const url = URL.createObjectURL(blob);
         const a = document.createElement('a');
         a.href = url;
@@ -229,6 +312,7 @@ document.addEventListener('DOMContentLoaded', function() {
         sansHidden.value = '[]';
         passwordGroup.style.display = 'none';
         document.getElementById('chain-section').style.display = 'none';
+        pkcs12PasswordGroup.style.display = 'none';
     });
 
     // Try again
@@ -11,7 +11,7 @@ document.addEventListener('DOMContentLoaded', function() {
     const resultContainer = document.getElementById('result-container');
     const errorContainer = document.getElementById('error-container');
 
-    let selectedFile = null;
+    let selectedFiles = [];
 
     // File drag and drop
     ['dragenter', 'dragover', 'dragleave', 'drop'].forEach(eventName => {
@@ -51,18 +51,19 @@ document.addEventListener('DOMContentLoaded', function() {
     fileInput.addEventListener('change', handleFileSelect);
 
     function handleFileSelect(e) {
-        const file = e.target.files[0];
-        if (file) {
-            selectedFile = file;
-            displayFileInfo(file);
+        const files = Array.from(e.target.files);
+        if (files.length > 0) {
+            selectedFiles = files;
+            displayFileInfo(files);
         }
     }
 
-    function displayFileInfo(file) {
+    function displayFileInfo(files) {
         uploadPlaceholder.style.display = 'none';
         fileInfo.style.display = 'block';
-        fileInfo.querySelector('.file-name').textContent = file.name;
-        fileInfo.querySelector('.file-size').textContent = formatFileSize(file.size);
+        fileInfo.querySelector('.file-name').textContent = files.map(f => f.name).join(', ');
+        const totalSize = files.reduce((sum, f) => sum + f.size, 0);
+        fileInfo.querySelector('.file-size').textContent = formatFileSize(totalSize);
     }
 
     function formatFileSize(bytes) {
@@ -76,7 +77,7 @@ document.addEventListener('DOMContentLoaded', function() {
     // Remove file
     removeFileBtn.addEventListener('click', function() {
         fileInput.value = '';
-        selectedFile = null;
+        selectedFiles = [];
         uploadPlaceholder.style.display = 'block';
         fileInfo.style.display = 'none';
     });
@@ -85,7 +86,7 @@ document.addEventListener('DOMContentLoaded', function() {
     form.addEventListener('submit', async function(e) {
         e.preventDefault();
 
-        if (!selectedFile) {
+        if (selectedFiles.length === 0) {
             showError('Please select a certificate file');
             return;
         }
@@ -98,10 +99,11 @@ document.addEventListener('DOMContentLoaded', function() {
         submitBtn.disabled = true;
 
         const formData = new FormData();
-        formData.append('cert_file', selectedFile);
+        selectedFiles.forEach(file => formData.append('cert_file', file));
+        formData.append('verify_chain', document.getElementById('verify-chain').checked);
 
         try {
-            const response = await fetch('/api/cert/info', {
+            const response = await apiFetch('/api/cert/info', {
                 method: 'POST',
                 body: formData
             });
@@ -109,7 +111,7 @@ document.addEventListener('DOMContentLoaded', function() {
             const data = await response.json();
 
             if (response.ok && data.success) {
-                showResult(data.certificate);
+                showResults(data);
             } else {
                 showError(data.error || 'Failed to parse certificate');
             }
@@ -122,6 +124,28 @@ document.addEventListener('DOMContentLoaded', function() {
         }
     });
 
+    function showResults(data) {
+        const certs = data.certificates && data.certificates.length > 0
+            ? data.certificates
+            : [data.certificate];
+        const selector = document.getElementById('cert-selector');
+        const selectorGroup = document.getElementById('cert-selector-group');
+
+        selector.innerHTML = '';
+        certs.forEach((cert, index) => {
+            const option = document.createElement('option');
+            option.value = index;
+            const cn = cert.subject.CN || Object.values(cert.subject)[0] || 'certificate';
+            const role = cert.chain_relation ? ` (${cert.chain_relation.role})` : '';
+            option.textContent = `#${index} ${cn}${role}`;
+            selector.appendChild(option);
+        });
+        selector.onchange = () => showResult(certs[selector.value]);
+        selectorGroup.style.display = certs.length > 1 ? 'block' : 'none';
+
+        showResult(certs[0]);
+    }
+
     function showResult(cert) {
         form.style.display = 'none';
         errorContainer.style.display = 'none';
@@ -183,6 +207,33 @@ document.addEventListener('DOMContentLoaded', function() {
             validityStatus.innerHTML = '<span style="color: var(--success-color)">Valid</span>';
         }
 
+        // Revocation status (only present when the server has revocation data configured)
+        const revocationDd = document.getElementById('cert-revocation');
+        if (cert.revocation) {
+            const rev = cert.revocation;
+            if (rev.status === 'revoked') {
+                revocationDd.innerHTML = '<span style="color: var(--danger-color)">Revoked</span>';
+                let details = '';
+                if (rev.revoked_at) {
+                    details += ' on ' + new Date(rev.revoked_at).toLocaleString();
+                }
+                if (rev.reason) {
+                    details += ' (' + rev.reason + ')';
+                }
+                revocationDd.appendChild(document.createTextNode(details));
+            } else if (rev.status === 'good') {
+                revocationDd.innerHTML = '<span style="color: var(--success-color)">Good</span>';
+            } else {
+                revocationDd.innerHTML = '<span style="color: var(--warning-color)">Unknown</span>';
+            }
+            if (rev.note) {
+                revocationDd.appendChild(document.createTextNode(' (' + rev.note + ')'));
+            }
+            revocationDd.parentElement.style.display = 'block';
+        } else {
+            revocationDd.parentElement.style.display = 'none';
+        }
+
         // Public Key
         document.getElementById('key-algorithm').textContent = cert.public_key.algorithm;
         document.getElementById('key-size').textContent = cert.public_key.size + ' bits';
@@ -192,6 +243,48 @@ document.addEventListener('DOMContentLoaded', function() {
         document.getElementById('fingerprint-sha1').textContent = cert.fingerprints.sha1;
         document.getElementById('fingerprint-sha256').textContent = cert.fingerprints.sha256;
 
+        // Relationship to other uploaded certificates
+        const relationDd = document.getElementById('cert-relation');
+        if (cert.chain_relation) {
+            const rel = cert.chain_relation;
+            let text = rel.role;
+            if (rel.self_signed) {
+                text += ', self-signed';
+            } else if (rel.issuer_index !== undefined && rel.issuer_index !== null) {
+                text += `, issued by #${rel.issuer_index}` + (rel.signature_valid ? '' : ' (signature INVALID)');
+            } else {
+                text += ', issuer not in upload';
+            }
+            relationDd.textContent = text;
+            relationDd.parentElement.style.display = 'block';
+        } else {
+            relationDd.parentElement.style.display = 'none';
+        }
+
+        // Chain verification
+        const chainSection = document.getElementById('chain-section');
+        if (cert.chain_verification) {
+            chainSection.style.display = 'block';
+            const chain = cert.chain_verification;
+            const trustedDd = document.getElementById('chain-trusted');
+            if (chain.trusted) {
+                trustedDd.innerHTML = '<span style="color: var(--success-color)">Yes</span>';
+            } else {
+                trustedDd.innerHTML = '<span style="color: var(--danger-color)">No</span>';
+                const where = chain.error_depth !== undefined ? ' at depth ' + chain.error_depth : '';
+                trustedDd.appendChild(document.createTextNode(' (' + chain.error + where + ')'));
+            }
+            const chainList = document.getElementById('chain-elements');
+            chainList.innerHTML = '';
+            chain.chain.forEach(element => {
+                const li = document.createElement('li');
+                li.textContent = `[${element.depth}] ${element.subject} ${element.valid ? '✓' : '✗ ' + (element.error || '')}`;
+                chainList.appendChild(li);
+            });
+        } else {
+            chainSection.style.display = 'none';
+        }
+
         // Subject Alternative Names
         if (cert.subject_alternative_names && cert.subject_alternative_names.length > 0) {
             const sansSection = document.getElementById('sans-section');
@@ -203,6 +296,8 @@ document.addEventListener('DOMContentLoaded', function() {
                 li.textContent = san;
                 sansList.appendChild(li);
             });
+        } else {
+            document.getElementById('sans-section').style.display = 'none';
         }
 
         // Extensions
@@ -221,6 +316,8 @@ document.addEventListener('DOMContentLoaded', function() {
                 `;
                 extensionsDiv.appendChild(extDiv);
             });
+        } else {
+            document.getElementById('extensions-section').style.display = 'none';
         }
 
         // PEM
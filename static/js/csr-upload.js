@@ -10,8 +10,9 @@ document.addEventListener('DOMContentLoaded', function() {
     const submitBtn = document.getElementById('submit-btn');
     const resultContainer = document.getElementById('result-container');
     const errorContainer = document.getElementById('error-container');
+    const batchResultContainer = document.getElementById('batch-result-container');
 
-    let selectedFile = null;
+    let selectedFiles = [];
 
     // File drag and drop
     ['dragenter', 'dragover', 'dragleave', 'drop'].forEach(eventName => {
@@ -51,18 +52,26 @@ document.addEventListener('DOMContentLoaded', function() {
     fileInput.addEventListener('change', handleFileSelect);
 
     function handleFileSelect(e) {
-        const file = e.target.files[0];
-        if (file) {
-            selectedFile = file;
-            displayFileInfo(file);
+        const files = Array.from(e.target.files);
+        if (files.length > 0) {
+            selectedFiles = files;
+            displayFileInfo(files);
         }
     }
 
-    function displayFileInfo(file) {
+    function displayFileInfo(files) {
         uploadPlaceholder.style.display = 'none';
         fileInfo.style.display = 'block';
-        fileInfo.querySelector('.file-name').textContent = file.name;
-        fileInfo.querySelector('.file-size').textContent = formatFileSize(file.size);
+        const totalSize = files.reduce((sum, file) => sum + file.size, 0);
+        fileInfo.querySelector('.file-name').textContent = files.length === 1
+            ? files[0].name
+            : files.length + ' files';
+        fileInfo.querySelector('.file-size').textContent = formatFileSize(totalSize);
+    }
+
+    // Several files or an archive go to the batch endpoint
+    function isBatch(files) {
+        return files.length > 1 || files[0].name.toLowerCase().endsWith('.zip');
     }
 
     function formatFileSize(bytes) {
@@ -76,7 +85,7 @@ document.addEventListener('DOMContentLoaded', function() {
     // Remove file
     removeFileBtn.addEventListener('click', function() {
         fileInput.value = '';
-        selectedFile = null;
+        selectedFiles = [];
         uploadPlaceholder.style.display = 'block';
         fileInfo.style.display = 'none';
     });
@@ -85,7 +94,7 @@ document.addEventListener('DOMContentLoaded', function() {
     form.addEventListener('submit', async function(e) {
         e.preventDefault();
 
-        if (!selectedFile) {
+        if (selectedFiles.length === 0) {
             showError('Please select a CSR file');
             return;
         }
@@ -99,22 +108,25 @@ document.addEventListener('DOMContentLoaded', function() {
         spinner.style.display = 'inline-block';
         submitBtn.disabled = true;
 
+        const batch = isBatch(selectedFiles);
         const formData = new FormData();
-        formData.append('csr_file', selectedFile);
+        selectedFiles.forEach(file => formData.append(batch ? 'csr_files' : 'csr_file', file));
         formData.append('validity_days', validityDays);
 
         try {
-            const response = await fetch('/api/csr/upload', {
+            const response = await apiFetch(batch ? '/api/csr/upload-batch' : '/api/csr/upload', {
                 method: 'POST',
                 body: formData
             });
 
             const data = await response.json();
 
-            if (response.ok && data.success) {
+            if (response.ok && batch) {
+                showBatchResult(data);
+            } else if (response.ok && data.success) {
                 showResult(data.certificate);
             } else {
-                showError(data.error || 'Failed to sign certificate');
+                showError(errorMessage(data) || 'Failed to sign certificate');
             }
         } catch (error) {
             showError('Network error: ' + error.message);
@@ -140,26 +152,63 @@ document.addEventListener('DOMContentLoaded', function() {
         document.getElementById('cert-pem').value = certificate.pem;
     }
 
+    function showBatchResult(data) {
+        form.style.display = 'none';
+        errorContainer.style.display = 'none';
+        batchResultContainer.style.display = 'block';
+
+        document.getElementById('batch-summary').textContent =
+            '✓ ' + data.successful + ' signed, ' + data.failed + ' failed';
+
+        const tbody = document.getElementById('batch-results');
+        tbody.replaceChildren();
+        data.results.forEach(result => {
+            const row = tbody.insertRow();
+            row.className = result.success ? '' : 'failed';
+            row.insertCell().textContent = result.file;
+            row.insertCell().textContent = result.success ? result.certificate.subject : '';
+            row.insertCell().textContent = result.success ? result.certificate.serial : result.error;
+            const actions = row.insertCell();
+            if (result.success) {
+                const button = document.createElement('button');
+                button.type = 'button';
+                button.className = 'btn';
+                button.textContent = 'Download';
+                const name = result.file.split('/').pop().replace(/\.(csr|pem|txt)$/i, '') + '.crt.pem';
+                button.addEventListener('click', () => downloadPem(result.certificate.pem, name));
+                actions.appendChild(button);
+            }
+        });
+    }
+
+    function errorMessage(data) {
+        return data.error && data.error.message ? data.error.message : data.error;
+    }
+
     function showError(message) {
         form.style.display = 'none';
         resultContainer.style.display = 'none';
+        batchResultContainer.style.display = 'none';
         errorContainer.style.display = 'block';
         document.getElementById('error-message').textContent = message;
     }
 
     // Download certificate
     document.getElementById('download-cert').addEventListener('click', function() {
-        const pem = document.getElementById('cert-pem').value;
+        downloadPem(document.getElementById('cert-pem').value, 'certificate.pem');
+    });
+
+    function downloadPem(pem, filename) {
         const blob = new Blob([pem], { type: 'application/x-pem-file' });
         const url = URL.createObjectURL(blob);
         const a = document.createElement('a');
         a.href = url;
-        a.download = 'certificate.pem';
+        a.download = filename;
         document.body.appendChild(a);
         a.click();
         document.body.removeChild(a);
         URL.revokeObjectURL(url);
-    });
+    }
 
     // Copy to clipboard
     document.getElementById('copy-cert').addEventListener('click', async function() {
@@ -184,6 +233,13 @@ document.addEventListener('DOMContentLoaded', function() {
         removeFileBtn.click();
     });
 
+    document.getElementById('batch-sign-another').addEventListener('click', function() {
+        form.style.display = 'block';
+        batchResultContainer.style.display = 'none';
+        form.reset();
+        removeFileBtn.click();
+    });
+
     // Try again
     document.getElementById('try-again').addEventListener('click', function() {
         form.style.display = 'block';
@@ -0,0 +1,87 @@
+//! Tests that run the `flux-ssl-mgr` binary
+
+#[cfg(feature = "web")]
+mod web_token_tests {
+    use assert_cmd::cargo::cargo_bin_cmd;
+    use std::path::Path;
+    use tempfile::TempDir;
+
+    /// A working directory with a config that passes validation
+    fn workspace() -> TempDir {
+        let dir = TempDir::new().unwrap();
+        let intermediate = dir.path().join("intermediate");
+        std::fs::create_dir_all(intermediate.join("private")).unwrap();
+        std::fs::create_dir_all(intermediate.join("certs")).unwrap();
+        std::fs::copy(
+            "tests/fixtures/keys/intermediate-ca.key.pem",
+            intermediate.join("private/intermediate.key.pem"),
+        )
+        .unwrap();
+        std::fs::write(intermediate.join("certs/intermediate.cert.pem"), "").unwrap();
+
+        let config = format!(
+            "working_dir = {:?}\n\
+             output_dir = {:?}\n\
+             csr_input_dir = {:?}\n\
+             ca_key_path = {:?}\n\
+             ca_cert_path = {:?}\n",
+            dir.path(),
+            dir.path().join("out"),
+            dir.path().join("csr"),
+            intermediate.join("private/intermediate.key.pem"),
+            intermediate.join("certs/intermediate.cert.pem"),
+        );
+        std::fs::write(dir.path().join("config.toml"), config).unwrap();
+        dir
+    }
+
+    fn enrollment_sans(dir: &Path) -> Vec<String> {
+        let data = std::fs::read_to_string(dir.join("intermediate/flux-web-tokens.json")).unwrap();
+        let tokens: serde_json::Value = serde_json::from_str(&data).unwrap();
+        let sans = &tokens["enrollments"][0]["sans"];
+        match sans.as_array() {
+            Some(sans) => sans.iter().map(|s| s.as_str().unwrap().to_string()).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_web_token_new_without_sans() {
+        let dir = workspace();
+        cargo_bin_cmd!("flux-ssl-mgr")
+            .arg("--config")
+            .arg(dir.path().join("config.toml"))
+            .args(["web", "token", "new", "--name", "nas01.lab"])
+            .assert()
+            .success();
+
+        assert!(enrollment_sans(dir.path()).is_empty());
+    }
+
+    #[test]
+    fn test_web_token_new_keeps_every_san() {
+        let dir = workspace();
+        cargo_bin_cmd!("flux-ssl-mgr")
+            .arg("--config")
+            .arg(dir.path().join("config.toml"))
+            .args(["web", "token", "new", "--name", "nas01.lab", "--sans", "DNS:nas.lab,IP:10.0.0.5"])
+            .assert()
+            .success();
+
+        assert_eq!(enrollment_sans(dir.path()), ["DNS:nas.lab", "IP:10.0.0.5"]);
+    }
+}
+
+mod batch_tests {
+    use assert_cmd::cargo::cargo_bin_cmd;
+    use predicates::str::contains;
+
+    #[test]
+    fn test_quiet_generated_passwords_need_a_password_file() {
+        cargo_bin_cmd!("flux-ssl-mgr")
+            .args(["-q", "batch", "--all", "--generate-passwords"])
+            .assert()
+            .code(2)
+            .stderr(contains("--password-file"));
+    }
+}
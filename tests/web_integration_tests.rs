@@ -4,6 +4,8 @@
 //! requires a running CA infrastructure and would be performed manually or
 //! in a dedicated test environment.
 
+#![allow(clippy::assertions_on_constants)]
+
 #[cfg(feature = "web")]
 mod web_tests {
     /// Test health check endpoint